@@ -12,6 +12,10 @@ contract VerifiableDBAvs is Ownable, Pausable, ReentrancyGuard {
     // Version of the contract
     string public constant VERSION = "1.0.0";
     
+    // Merkle domain separators - aligned with core module
+    bytes public constant MERKLE_NODE_DOMAIN = "VERIFIABLEDB_MERKLE_NODE";
+    bytes public constant MERKLE_ROOT_DOMAIN = "VERIFIABLEDB_MERKLE_ROOT";
+    
     // Challenge parameters
     uint256 public challengePeriod = 7 days;
     uint256 public baseChallengeBond = 0.1 ether;
@@ -430,6 +434,40 @@ contract VerifiableDBAvs is Ownable, Pausable, ReentrancyGuard {
         emit DeterministicFunctionVerified(_functionName, _transactionId, _isValid);
     }
     
+    /// @notice Verify a Merkle proof exported by the core module's `to_abi_bytes`
+    /// @dev Hashes nodes like the core module's SHA-256 trees: each input is
+    /// framed with the domain, its length, the element count and 4-byte lengths
+    /// @param _proof abi.encode(bytes32[] siblings, uint256 index, bytes32 leaf),
+    /// where bit i of index is set when the ith sibling is a left child
+    /// @param _stateRoot State root the proof should lead to
+    /// @return Whether the proof leads to the state root
+    function verifyMerkleProof(bytes calldata _proof, bytes32 _stateRoot) public pure returns (bool) {
+        (bytes32[] memory siblings, uint256 index, bytes32 leaf) = abi.decode(_proof, (bytes32[], uint256, bytes32));
+        if (siblings.length < 256 && index >> siblings.length != 0) {
+            return false;
+        }
+        
+        bytes32 node = leaf;
+        for (uint256 i = 0; i < siblings.length; i++) {
+            bytes32 left = node;
+            bytes32 right = siblings[i];
+            if ((index >> i) & 1 == 1) {
+                (left, right) = (right, left);
+            }
+            node = sha256(abi.encodePacked(
+                MERKLE_NODE_DOMAIN,
+                uint8(MERKLE_NODE_DOMAIN.length),
+                uint8(2),
+                uint32(32),
+                left,
+                uint32(32),
+                right
+            ));
+        }
+        
+        return sha256(abi.encodePacked(MERKLE_ROOT_DOMAIN, uint8(MERKLE_ROOT_DOMAIN.length), node)) == _stateRoot;
+    }
+    
     /// @notice Update challenge parameters
     /// @param _challengePeriod New challenge period in seconds
     /// @param _baseChallengeBond New base challenge bond in wei
@@ -251,4 +251,36 @@ contract VerifiableDBAvsTest is Test {
         uint256 challengerBalanceAfter = challenger.balance;
         assertEq(challengerBalanceAfter, challengerBalanceBefore + challenge.bond / 2, "Challenger should get half bond back");
     }
+    
+    function test_VerifyMerkleProof() public {
+        // AbiMerkleProof { siblings: [0x11.., 0x22..], index: 2, leaf: 0xaa.. }.to_abi_bytes()
+        bytes memory proof = bytes.concat(
+            hex"0000000000000000000000000000000000000000000000000000000000000060",
+            hex"0000000000000000000000000000000000000000000000000000000000000002",
+            hex"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            hex"0000000000000000000000000000000000000000000000000000000000000002",
+            hex"1111111111111111111111111111111111111111111111111111111111111111",
+            hex"2222222222222222222222222222222222222222222222222222222222222222"
+        );
+        bytes32 leaf = hex"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+        // AbiMerkleProof::calculate_root for the same proof
+        bytes32 stateRoot = hex"c0e5e3ad7b76a45cc13618783862de51a536c16bc4348e50b70efcf092695382";
+        
+        assertTrue(avs.verifyMerkleProof(proof, stateRoot), "Proof should verify against its root");
+        assertFalse(avs.verifyMerkleProof(proof, postStateRoot), "Proof should not verify against another root");
+        
+        // Flipping a sibling's direction changes the root
+        bytes memory flipped = abi.encode(_siblings(), uint256(3), leaf);
+        assertFalse(avs.verifyMerkleProof(flipped, stateRoot), "Flipped proof should not verify");
+        
+        // Index bits beyond the siblings are rejected
+        bytes memory outOfRange = abi.encode(_siblings(), uint256(6), leaf);
+        assertFalse(avs.verifyMerkleProof(outOfRange, stateRoot), "Out-of-range index should not verify");
+    }
+    
+    function _siblings() internal pure returns (bytes32[] memory siblings) {
+        siblings = new bytes32[](2);
+        siblings[0] = hex"1111111111111111111111111111111111111111111111111111111111111111";
+        siblings[1] = hex"2222222222222222222222222222222222222222222222222222222222222222";
+    }
 } 
\ No newline at end of file
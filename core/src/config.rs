@@ -7,24 +7,8 @@ use std::time::Duration;
 use serde::{Serialize, Deserialize};
 use crate::utils::resource::ResourceLimits;
 
-/// Hash algorithm to use
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum HashAlgorithm {
-    /// SHA-256
-    Sha256,
-    
-    /// Blake2s
-    Blake2s,
-    
-    /// Keccak-256
-    Keccak256,
-}
-
-impl Default for HashAlgorithm {
-    fn default() -> Self {
-        HashAlgorithm::Sha256
-    }
-}
+/// Hash algorithm to use, shared with the Merkle trees and their proofs
+pub use crate::crypto::HashAlgorithm;
 
 /// Merkle tree configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -256,6 +240,23 @@ mod tests {
         assert!(!config.debug_mode);
     }
     
+    #[test]
+    fn test_hash_algorithm_selection() {
+        let mut config = CoreConfig::default();
+        config.merkle.hash_algorithm = HashAlgorithm::Blake3;
+        
+        let json = serde_json::to_string(&config).unwrap();
+        let deserialized: CoreConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.merkle.hash_algorithm, HashAlgorithm::Blake3);
+        
+        // The selected algorithm hashes like the tree built with it
+        let mut hasher = deserialized.merkle.hash_algorithm.hasher();
+        assert_eq!(
+            hasher.hash_with_domain("TEST", b"data"),
+            crate::crypto::secure_hash_with(HashAlgorithm::Blake3, "TEST", b"data")
+        );
+    }
+    
     #[test]
     fn test_development_config() {
         let config = CoreConfig::development();
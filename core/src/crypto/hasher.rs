@@ -21,13 +21,23 @@ pub trait SecureHasher: Debug + Send + Sync + 'static {
     /// Finalize the hash and return the result
     fn finalize(&mut self) -> [u8; 32];
     
-    /// Hash data with domain separation
-    fn hash_with_domain(&mut self, domain: &str, data: &[u8]) -> [u8; 32] {
+    /// Start a streaming hash with domain separation
+    ///
+    /// Feeds the same domain prefix as [`hash_with_domain`](Self::hash_with_domain),
+    /// so calling `update` with the data in chunks and then `finalize` gives
+    /// the same hash as hashing the whole data at once. The domain is given
+    /// up front because it prefixes the data.
+    fn begin_with_domain(&mut self, domain: &str) {
         // Add domain prefix for domain separation
         self.update(domain.as_bytes());
         
         // Add domain length as a single byte for additional protection
         self.update(&[domain.len() as u8]);
+    }
+    
+    /// Hash data with domain separation
+    fn hash_with_domain(&mut self, domain: &str, data: &[u8]) -> [u8; 32] {
+        self.begin_with_domain(domain);
         
         // Add the actual data
         self.update(data);
@@ -38,11 +48,7 @@ pub trait SecureHasher: Debug + Send + Sync + 'static {
     
     /// Hash multiple data elements with domain separation
     fn hash_multiple_with_domain(&mut self, domain: &str, data: &[&[u8]]) -> [u8; 32] {
-        // Add domain prefix for domain separation
-        self.update(domain.as_bytes());
-        
-        // Add domain length as a single byte for additional protection
-        self.update(&[domain.len() as u8]);
+        self.begin_with_domain(domain);
         
         // Add number of elements as a protection against concatenation attacks
         self.update(&[data.len() as u8]);
@@ -77,6 +83,16 @@ impl Sha256Hasher {
             inner: Sha256::new(),
         }
     }
+    
+    /// Create a SHA-256 hasher for streaming data under `domain`
+    ///
+    /// Update it with the data in chunks; `finalize` then gives the same hash
+    /// as `crypto::secure_hash(domain, data)` without buffering the data.
+    pub fn with_domain(domain: &str) -> Self {
+        let mut hasher = Self::new();
+        hasher.begin_with_domain(domain);
+        hasher
+    }
 }
 
 impl SecureHasher for Sha256Hasher {
@@ -172,9 +188,46 @@ impl SecureHasher for KeccakHasher {
     }
 }
 
+/// BLAKE3 implementation of SecureHasher
+///
+/// Considerably faster than SHA-256 on large inputs, which makes it the
+/// better choice for trees over large state captures.
+#[derive(Debug, Clone)]
+pub struct Blake3Hasher {
+    inner: blake3::Hasher,
+}
+
+impl Blake3Hasher {
+    /// Create a new BLAKE3 hasher
+    pub fn new() -> Self {
+        Blake3Hasher {
+            inner: blake3::Hasher::new(),
+        }
+    }
+}
+
+impl SecureHasher for Blake3Hasher {
+    fn new_instance() -> Box<dyn SecureHasher> {
+        Box::new(Self::new())
+    }
+    
+    fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+    
+    fn finalize(&mut self) -> [u8; 32] {
+        *self.inner.finalize().as_bytes()
+    }
+    
+    fn clone_box(&self) -> Box<dyn SecureHasher> {
+        Box::new(self.clone())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::HashAlgorithm;
     
     fn test_hasher_implementation<H: SecureHasher>(hasher: H) {
         let mut h1 = H::new_instance();
@@ -228,4 +281,36 @@ mod tests {
     fn test_keccak_hasher() {
         test_hasher_implementation(KeccakHasher::new());
     }
+    
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| i.to_be_bytes()).collect();
+        
+        // Streaming in chunks gives the same hash as secure_hash
+        let mut hasher = Sha256Hasher::with_domain("TEST");
+        for chunk in data.chunks(4096) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finalize(), crate::crypto::secure_hash("TEST", &data));
+        
+        // And likewise for every algorithm
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake2s, HashAlgorithm::Keccak256, HashAlgorithm::Blake3] {
+            let mut hasher = algorithm.hasher();
+            hasher.begin_with_domain("TEST");
+            for chunk in data.chunks(1000) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finalize(), crate::crypto::secure_hash_with(algorithm, "TEST", &data));
+        }
+    }
+    
+    #[test]
+    fn test_blake3_hasher() {
+        test_hasher_implementation(Blake3Hasher::new());
+        
+        // Same framing as SHA-256, but a different digest
+        let data = b"test data";
+        let blake3 = Blake3Hasher::new().hash_with_domain("TEST", data);
+        assert_ne!(blake3, Sha256Hasher::new().hash_with_domain("TEST", data));
+    }
 } 
\ No newline at end of file
@@ -4,12 +4,65 @@
 //! for use in the verification system.
 
 mod hasher;
+mod signing;
 
 pub use hasher::SecureHasher;
 pub use hasher::Sha256Hasher;
+pub use hasher::Blake3Hasher;
+pub use signing::{NodeKeypair, Signature, VerifyingKey, STATE_COMMIT_DOMAIN};
 
 use sha2::{Sha256, Digest};
+use hmac::{Hmac, Mac};
 use constant_time_eq::constant_time_eq;
+use serde::{Serialize, Deserialize};
+use hasher::{Blake2sHasher, KeccakHasher};
+
+/// Hash algorithm behind a [`SecureHasher`]
+///
+/// Recorded with hashes that must be recomputed later, such as Merkle
+/// proofs, so verification uses the same algorithm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    /// SHA-256, used by [`secure_hash`] and on-chain verifiers
+    #[default]
+    Sha256,
+    
+    /// BLAKE2s-256
+    Blake2s,
+    
+    /// Keccak-256
+    Keccak256,
+    
+    /// BLAKE3
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Create a fresh hasher for the algorithm
+    pub fn hasher(self) -> Box<dyn SecureHasher> {
+        match self {
+            HashAlgorithm::Sha256 => Sha256Hasher::new_instance(),
+            HashAlgorithm::Blake2s => Blake2sHasher::new_instance(),
+            HashAlgorithm::Keccak256 => KeccakHasher::new_instance(),
+            HashAlgorithm::Blake3 => Blake3Hasher::new_instance(),
+        }
+    }
+}
+
+/// Create a domain-separated secure hash with the given algorithm
+///
+/// Frames the input exactly like [`secure_hash`], so `HashAlgorithm::Sha256`
+/// gives the same hash.
+pub fn secure_hash_with(algorithm: HashAlgorithm, domain: &str, data: &[u8]) -> [u8; 32] {
+    algorithm.hasher().hash_with_domain(domain, data)
+}
+
+/// Create a domain-separated secure hash of multiple inputs with the given algorithm
+///
+/// Frames the inputs exactly like [`secure_hash_multiple`].
+pub fn secure_hash_multiple_with(algorithm: HashAlgorithm, domain: &str, data: &[&[u8]]) -> [u8; 32] {
+    algorithm.hasher().hash_multiple_with_domain(domain, data)
+}
 
 /// Create a domain-separated secure hash using SHA-256
 ///
@@ -79,6 +132,57 @@ pub fn secure_hash_multiple(domain: &str, data: &[&[u8]]) -> [u8; 32] {
     output
 }
 
+/// Create a domain-separated keyed tag using HMAC-SHA256
+///
+/// The message is framed like [`secure_hash`], so a tag for one domain
+/// can't be replayed under another. Only holders of `key` can produce a
+/// valid tag; check tags with [`verify_keyed`].
+///
+/// # Arguments
+///
+/// * `key` - Secret key, of any length
+/// * `domain` - Domain prefix (e.g., "STATE_COMMITMENT")
+/// * `data` - Data to authenticate
+///
+/// # Returns
+///
+/// A 32-byte tag with domain separation
+pub fn secure_hash_keyed(key: &[u8], domain: &str, data: &[u8]) -> [u8; 32] {
+    keyed_mac(key, domain, data).finalize().into_bytes().into()
+}
+
+/// Verify a keyed tag in constant time
+///
+/// Recomputes the tag with [`secure_hash_keyed`] and compares every byte
+/// regardless of where the first difference is, so the time taken doesn't
+/// reveal how much of a forged tag was right.
+///
+/// # Arguments
+///
+/// * `key` - Secret key the tag was created with
+/// * `domain` - Domain prefix the tag was created with
+/// * `data` - Data the tag authenticates
+/// * `tag` - Tag to verify
+///
+/// # Returns
+///
+/// True if the tag is valid for the data, false otherwise
+pub fn verify_keyed(key: &[u8], domain: &str, data: &[u8], tag: &[u8; 32]) -> bool {
+    verify_hash(&secure_hash_keyed(key, domain, data), tag)
+}
+
+/// HMAC-SHA256 over the domain-framed message
+fn keyed_mac(key: &[u8], domain: &str, data: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .expect("HMAC accepts keys of any length");
+    
+    // Same framing as secure_hash
+    mac.update(domain.as_bytes());
+    mac.update(&[domain.len() as u8]);
+    mac.update(data);
+    mac
+}
+
 /// Verify a hash in constant time to prevent timing attacks
 ///
 /// # Arguments
@@ -90,6 +194,23 @@ pub fn secure_hash_multiple(domain: &str, data: &[&[u8]]) -> [u8; 32] {
 ///
 /// True if the hashes match, false otherwise
 pub fn verify_hash(expected: &[u8; 32], actual: &[u8; 32]) -> bool {
+    verify_bytes(expected, actual)
+}
+
+/// Compare byte strings of any length in constant time
+///
+/// Strings of different lengths compare unequal straight away, since
+/// lengths aren't secret; equal-length strings are compared in full.
+///
+/// # Arguments
+///
+/// * `expected` - Expected bytes
+/// * `actual` - Actual bytes to verify
+///
+/// # Returns
+///
+/// True if the bytes match, false otherwise
+pub fn verify_bytes(expected: &[u8], actual: &[u8]) -> bool {
     constant_time_eq(expected, actual)
 }
 
@@ -115,6 +236,44 @@ mod tests {
         assert_ne!(hash, hash4);
     }
     
+    #[test]
+    fn test_secure_hash_keyed() {
+        let data = b"state root";
+        let tag = secure_hash_keyed(b"operator key", "TEST", data);
+        
+        // Same inputs should produce the same tag
+        assert_eq!(tag, secure_hash_keyed(b"operator key", "TEST", data));
+        
+        // Different keys, domains or data should produce different tags
+        assert_ne!(tag, secure_hash_keyed(b"challenger key", "TEST", data));
+        assert_ne!(tag, secure_hash_keyed(b"operator key", "DIFFERENT", data));
+        assert_ne!(tag, secure_hash_keyed(b"operator key", "TEST", b"forged root"));
+        
+        // A keyed tag is not the unkeyed hash, even under an empty key
+        assert_ne!(tag, secure_hash("TEST", data));
+        assert_ne!(secure_hash_keyed(b"", "TEST", data), secure_hash("TEST", data));
+    }
+    
+    #[test]
+    fn test_verify_keyed() {
+        let key = b"operator key";
+        let data = b"state root";
+        let tag = secure_hash_keyed(key, "TEST", data);
+        assert!(verify_keyed(key, "TEST", data, &tag));
+        
+        // Wrong key, domain or data is rejected
+        assert!(!verify_keyed(b"challenger key", "TEST", data, &tag));
+        assert!(!verify_keyed(key, "DIFFERENT", data, &tag));
+        assert!(!verify_keyed(key, "TEST", b"forged root", &tag));
+        
+        // A difference in any byte is rejected, not just early ones
+        for i in 0..tag.len() {
+            let mut forged = tag;
+            forged[i] ^= 1;
+            assert!(!verify_keyed(key, "TEST", data, &forged));
+        }
+    }
+    
     #[test]
     fn test_secure_hash_multiple() {
         let data1 = b"data1";
@@ -142,6 +301,25 @@ mod tests {
         assert_ne!(hash, hash5);
     }
     
+    #[test]
+    fn test_secure_hash_with_algorithm() {
+        let data = b"test data";
+        
+        // SHA-256 matches the default functions
+        assert_eq!(secure_hash_with(HashAlgorithm::Sha256, "TEST", data), secure_hash("TEST", data));
+        assert_eq!(
+            secure_hash_multiple_with(HashAlgorithm::Sha256, "TEST", &[data, data]),
+            secure_hash_multiple("TEST", &[data, data])
+        );
+        
+        // Each algorithm keeps domain separation
+        for algorithm in [HashAlgorithm::Blake2s, HashAlgorithm::Keccak256, HashAlgorithm::Blake3] {
+            let hash = secure_hash_with(algorithm, "TEST", data);
+            assert_ne!(hash, secure_hash("TEST", data));
+            assert_ne!(hash, secure_hash_with(algorithm, "DIFFERENT", data));
+        }
+    }
+    
     #[test]
     fn test_verify_hash() {
         let data = b"test data";
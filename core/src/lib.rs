@@ -45,6 +45,10 @@ pub mod error {
         #[error("Schema validation error: {0}")]
         SchemaValidationError(String),
 
+        /// Resource limit exceeded
+        #[error("Resource limit exceeded: {0}")]
+        ResourceLimitExceeded(String),
+
         /// General error
         #[error("General error: {0}")]
         GeneralError(String),
@@ -5,9 +5,11 @@
 
 mod tree;
 mod proof;
+mod sparse;
 
 pub use tree::{SecureMerkleTree, TreeNode, NodeType};
-pub use proof::{SecureMerkleProof, ProofItem, ProofDirection};
+pub use proof::{SecureMerkleProof, ProofItem, ProofDirection, ProofMetadata, ConsistencyProof, AbiMerkleProof};
+pub use sparse::{SparseMerkleTree, SparseMerkleProof, SPARSE_TREE_DEPTH};
 
 /// Domain constants for Merkle tree operations
 pub mod domains {
@@ -25,6 +27,9 @@ pub mod domains {
     
     /// Domain for proof items
     pub const PROOF_ITEM: &str = "VERIFIABLEDB_MERKLE_PROOF";
+    
+    /// Domain for deriving sparse tree keys from identifiers
+    pub const SPARSE_KEY: &str = "VERIFIABLEDB_MERKLE_SPARSE_KEY";
 }
 
 #[cfg(test)]
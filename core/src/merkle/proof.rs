@@ -2,11 +2,19 @@
 //!
 //! This module provides a proof structure for verifying inclusion in a Merkle tree.
 
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
 use std::fmt::{Debug, Formatter, Result as FmtResult};
 use serde::{Serialize, Deserialize};
 
-use crate::crypto;
+use crate::crypto::{self, HashAlgorithm};
+use crate::error::CoreError;
+use crate::Result;
 use super::domains;
+use super::tree::TreeNode;
+
+/// Size of one ABI word
+const ABI_WORD: usize = 32;
 
 /// Direction of a proof item (left or right)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -39,6 +47,37 @@ impl Debug for ProofItem {
     }
 }
 
+/// How a proof's hashes were computed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofMetadata {
+    /// Hash algorithm of the tree the proof was generated from
+    #[serde(default)]
+    pub algorithm: HashAlgorithm,
+    
+    /// Salt mixed into the tree's leaf hashes (all zeros for unsalted trees)
+    #[serde(default)]
+    pub salt: [u8; 32],
+}
+
+impl ProofMetadata {
+    /// Whether leaf hashes are salted
+    pub fn is_salted(&self) -> bool {
+        self.salt != [0; 32]
+    }
+    
+    /// Hash a leaf's data with the algorithm and salt
+    ///
+    /// The zero salt hashes the data alone, so unsalted trees keep the leaf
+    /// hashes they had before salting was supported.
+    pub fn leaf_hash(&self, data: &[u8]) -> [u8; 32] {
+        if self.is_salted() {
+            crypto::secure_hash_multiple_with(self.algorithm, domains::LEAF_NODE, &[&self.salt, data])
+        } else {
+            crypto::secure_hash_with(self.algorithm, domains::LEAF_NODE, data)
+        }
+    }
+}
+
 /// A proof of inclusion in a Merkle tree
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SecureMerkleProof {
@@ -49,15 +88,34 @@ pub struct SecureMerkleProof {
     pub position: usize,
     
     /// The proof items (siblings along the path from leaf to root)
+    ///
+    /// In a batch proof, the siblings no proven leaf's path provides, level
+    /// by level from the leaves up and in node order within a level.
     pub items: Vec<ProofItem>,
+    
+    /// Leaf positions a batch proof covers, ascending (empty for single-leaf proofs)
+    ///
+    /// Always serialized, since bincode can't tell a skipped field is missing.
+    #[serde(default)]
+    pub positions: Vec<usize>,
+    
+    /// Height of the tree the proof was generated from
+    #[serde(default)]
+    pub height: usize,
+    
+    /// How the proof's hashes were computed (SHA-256 for proofs without it)
+    #[serde(default)]
+    pub metadata: ProofMetadata,
 }
 
 impl Debug for SecureMerkleProof {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         write!(
             f,
-            "SecureMerkleProof {{ position: {}, items: {:?} }}",
+            "SecureMerkleProof {{ position: {}, positions: {:?}, algorithm: {:?}, items: {:?} }}",
             self.position,
+            self.positions,
+            self.metadata.algorithm,
             self.items
         )
     }
@@ -66,50 +124,96 @@ impl Debug for SecureMerkleProof {
 impl SecureMerkleProof {
     /// Create a new proof
     pub fn new(leaf_data: Vec<u8>, position: usize, items: Vec<ProofItem>) -> Self {
+        let height = items.len();
         SecureMerkleProof {
             leaf_data,
             position,
             items,
+            positions: Vec::new(),
+            height,
+            metadata: ProofMetadata::default(),
+        }
+    }
+    
+    /// Create a batch proof for the leaves at `positions`
+    ///
+    /// `items` are the siblings the leaves' paths don't provide, in the order
+    /// [`calculate_batch_root`](Self::calculate_batch_root) consumes them.
+    pub fn new_batch(positions: Vec<usize>, height: usize, items: Vec<ProofItem>) -> Self {
+        SecureMerkleProof {
+            leaf_data: Vec::new(),
+            position: positions.first().copied().unwrap_or_default(),
+            items,
+            positions,
+            height,
+            metadata: ProofMetadata::default(),
         }
     }
     
+    /// Whether this proof covers several leaves
+    pub fn is_batch(&self) -> bool {
+        !self.positions.is_empty()
+    }
+    
+    /// Number of siblings on a leaf's path to the root
+    ///
+    /// A single-leaf proof has exactly this many items; a batch proof has at
+    /// most this many per proven leaf.
+    pub fn expected_path_len(&self) -> usize {
+        self.height
+    }
+    
+    /// Range of leaf counts the proof's tree can have
+    ///
+    /// At least one more than the highest proven position, and at most the
+    /// capacity of a tree of the proof's height. Returns `None` if no tree of
+    /// that height holds the proven positions.
+    pub fn tree_size_bounds(&self) -> Option<RangeInclusive<usize>> {
+        let capacity = u32::try_from(self.height).ok().and_then(|height| 1usize.checked_shl(height))?;
+        let last = self.positions.last().copied().unwrap_or(self.position);
+        (last < capacity).then(|| last + 1..=capacity)
+    }
+    
     /// Verify the proof against a given root hash
+    ///
+    /// Rejects truncated or padded paths, whose length doesn't match the
+    /// proof's height, but otherwise trusts the proof's own metadata, height
+    /// and position. Use [`verify_with_metadata`](Self::verify_with_metadata)
+    /// for proofs from an untrusted source.
     pub fn verify(&self, root_hash: &[u8; 32]) -> bool {
-        // Generate the expected leaf hash
-        let leaf_hash = crypto::secure_hash(domains::LEAF_NODE, &self.leaf_data);
-        
-        // Calculate the root hash from the proof
-        let mut current_hash = leaf_hash;
-        
-        for item in &self.items {
-            match item.direction {
-                ProofDirection::Left => {
-                    // Current is the right child, sibling is the left child
-                    current_hash = crypto::secure_hash_multiple(
-                        domains::INTERNAL_NODE,
-                        &[&item.hash, &current_hash]
-                    );
-                }
-                ProofDirection::Right => {
-                    // Current is the left child, sibling is the right child
-                    current_hash = crypto::secure_hash_multiple(
-                        domains::INTERNAL_NODE,
-                        &[&current_hash, &item.hash]
-                    );
-                }
-            }
+        self.items.len() == self.expected_path_len()
+            && self.tree_size_bounds().is_some()
+            && self.calculate_root() == *root_hash
+    }
+    
+    /// Verify the proof of `data` at `position` against a given root hash,
+    /// hashing as `expected` says rather than as the proof claims
+    ///
+    /// Rejects proofs whose metadata differs from `expected`, whose height
+    /// doesn't match the number of proof items, or whose sibling directions
+    /// don't follow from `position`. Otherwise a proof could pick the salt
+    /// or algorithm under which an unrelated leaf hashes to the root, or
+    /// prove a leaf at a position other than the one the caller asked about.
+    pub fn verify_with_metadata(&self, data: &[u8], position: usize, root_hash: &[u8; 32], expected: &ProofMetadata) -> bool {
+        if self.is_batch()
+            || self.metadata != *expected
+            || self.leaf_data != data
+            || self.position != position
+        {
+            return false;
         }
         
-        // Apply final root domain separation
-        let calculated_root = crypto::secure_hash(domains::ROOT_NODE, &current_hash);
-        
-        // Compare with the provided root hash
-        calculated_root == *root_hash
+        // A node is a right child, with its sibling on the left, when its
+        // position bit is set; verify() first bounds the path by the height
+        self.verify(root_hash)
+            && self.items.iter()
+                .enumerate()
+                .all(|(level, item)| (item.direction == ProofDirection::Left) == (position >> level & 1 == 1))
     }
     
     /// Get the leaf hash (with domain separation)
     pub fn leaf_hash(&self) -> [u8; 32] {
-        crypto::secure_hash(domains::LEAF_NODE, &self.leaf_data)
+        self.metadata.leaf_hash(&self.leaf_data)
     }
     
     /// Calculate the root hash from the proof
@@ -120,14 +224,14 @@ impl SecureMerkleProof {
             match item.direction {
                 ProofDirection::Left => {
                     // Current is the right child, sibling is the left child
-                    current_hash = crypto::secure_hash_multiple(
+                    current_hash = self.hash_multiple(
                         domains::INTERNAL_NODE,
                         &[&item.hash, &current_hash]
                     );
                 }
                 ProofDirection::Right => {
                     // Current is the left child, sibling is the right child
-                    current_hash = crypto::secure_hash_multiple(
+                    current_hash = self.hash_multiple(
                         domains::INTERNAL_NODE,
                         &[&current_hash, &item.hash]
                     );
@@ -136,7 +240,75 @@ impl SecureMerkleProof {
         }
         
         // Apply final root domain separation
-        crypto::secure_hash(domains::ROOT_NODE, &current_hash)
+        self.hash(domains::ROOT_NODE, &current_hash)
+    }
+    
+    /// Calculate the root hash from a batch proof and the data of its leaves
+    ///
+    /// `datas` are the leaves' data in the order of `positions`. Returns
+    /// `None` if the proof isn't a well-formed batch proof for that many
+    /// leaves, including when it has sibling hashes left over.
+    pub fn calculate_batch_root(&self, datas: &[&[u8]]) -> Option<[u8; 32]> {
+        if self.positions.is_empty() || datas.len() != self.positions.len() || self.height >= usize::BITS as usize {
+            return None;
+        }
+        let first_leaf = 1usize << self.height;
+        if !self.positions.windows(2).all(|pair| pair[0] < pair[1]) || self.positions.last()? >= &first_leaf {
+            return None;
+        }
+        
+        // Known node hashes by tree index, where node i has children 2i and 2i + 1
+        let mut known: BTreeMap<usize, [u8; 32]> = self.positions.iter()
+            .zip(datas)
+            .map(|(position, data)| (first_leaf + position, self.metadata.leaf_hash(data)))
+            .collect();
+        let mut items = self.items.iter();
+        
+        for _ in 0..self.height {
+            let mut parents = BTreeMap::new();
+            for (&index, hash) in &known {
+                // A right child whose left sibling is known was combined with it already
+                if index % 2 == 1 && known.contains_key(&(index - 1)) {
+                    continue;
+                }
+                let parent_hash = match known.get(&(index ^ 1)) {
+                    Some(sibling) => self.hash_multiple(domains::INTERNAL_NODE, &[hash, sibling]),
+                    None => {
+                        let item = items.next()?;
+                        match (index % 2, item.direction) {
+                            (0, ProofDirection::Right) => self.hash_multiple(domains::INTERNAL_NODE, &[hash, &item.hash]),
+                            (1, ProofDirection::Left) => self.hash_multiple(domains::INTERNAL_NODE, &[&item.hash, hash]),
+                            _ => return None,
+                        }
+                    }
+                };
+                parents.insert(index / 2, parent_hash);
+            }
+            known = parents;
+        }
+        
+        if items.next().is_some() {
+            return None;
+        }
+        let root = known.get(&1)?;
+        Some(self.hash(domains::ROOT_NODE, root))
+    }
+    
+    /// Verify a batch proof for the data of its leaves against a given root hash
+    ///
+    /// `datas` are the leaves' data in the order of `positions`.
+    pub fn verify_batch(&self, datas: &[&[u8]], root_hash: &[u8; 32]) -> bool {
+        self.calculate_batch_root(datas) == Some(*root_hash)
+    }
+    
+    /// Hash with the proof's algorithm
+    fn hash(&self, domain: &str, data: &[u8]) -> [u8; 32] {
+        crypto::secure_hash_with(self.metadata.algorithm, domain, data)
+    }
+    
+    /// Hash multiple inputs with the proof's algorithm
+    fn hash_multiple(&self, domain: &str, data: &[&[u8]]) -> [u8; 32] {
+        crypto::secure_hash_multiple_with(self.metadata.algorithm, domain, data)
     }
     
     /// Get the number of proof items
@@ -148,6 +320,252 @@ impl SecureMerkleProof {
     pub fn is_empty(&self) -> bool {
         self.items.is_empty()
     }
+    
+    /// Convert to the layout consumed by on-chain verifiers
+    ///
+    /// The leaf is replaced by its hash, and sibling directions are packed
+    /// into the index: bit `i` is set when the `i`th sibling is a left child.
+    /// On-chain verifiers hash with SHA-256, so only proofs from SHA-256
+    /// trees verify there.
+    pub fn to_abi(&self) -> AbiMerkleProof {
+        let index = self.items.iter()
+            .enumerate()
+            .filter(|(_, item)| item.direction == ProofDirection::Left)
+            .fold(0u64, |index, (level, _)| index | (1 << level));
+        
+        AbiMerkleProof {
+            siblings: self.items.iter().map(|item| item.hash).collect(),
+            index,
+            leaf: self.leaf_hash(),
+        }
+    }
+    
+    /// ABI-encode the proof as `(bytes32[] siblings, uint256 index, bytes32 leaf)`
+    pub fn to_abi_bytes(&self) -> Vec<u8> {
+        self.to_abi().to_abi_bytes()
+    }
+}
+
+/// A proof that a tree only appended leaves since an earlier version
+///
+/// Follows the RFC 6962 construction: the proof holds the complete subtrees
+/// covering the old leaves, which rebuild the old root, and the nodes that
+/// extend those same subtrees to the new root. A rewritten historical leaf
+/// changes one of the subtrees, so both roots can't match.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsistencyProof {
+    /// Hashes of the complete subtrees covering the old leaves, largest first
+    pub subtrees: Vec<[u8; 32]>,
+    
+    /// Non-empty nodes the new tree adds beside the subtrees, level by level
+    /// from the leaves up and in node order within a level
+    pub items: Vec<[u8; 32]>,
+    
+    /// Height of the new tree
+    pub height: usize,
+    
+    /// Hash algorithm of the tree
+    #[serde(default)]
+    pub algorithm: HashAlgorithm,
+}
+
+impl ConsistencyProof {
+    /// Verify that the tree with `new_root` and `new_size` leaves extends
+    /// the one with `old_root` and `old_size` leaves
+    ///
+    /// The old tree may have been shorter than the new one, since trees grow
+    /// as leaves are appended, but not taller. Roots commit to which nodes
+    /// are empty rather than to a leaf count, so `new_size` only bounds where
+    /// the appended leaves may be.
+    pub fn verify(&self, old_root: &[u8; 32], new_root: &[u8; 32], old_size: usize, new_size: usize) -> bool {
+        if old_size == 0 || old_size > new_size || self.height >= usize::BITS as usize {
+            return false;
+        }
+        
+        // The old tree has nothing beside the subtrees but empty nodes
+        let min_height = old_size.next_power_of_two().trailing_zeros() as usize;
+        let old_matches = (min_height..=self.height)
+            .any(|height| self.calculate_root(old_size, height, old_size, |_| None) == Some(*old_root));
+        if !old_matches {
+            return false;
+        }
+        
+        let mut items = self.items.iter();
+        let calculated_root = self.calculate_root(old_size, self.height, new_size, |_| items.next().copied());
+        calculated_root == Some(*new_root) && items.next().is_none()
+    }
+    
+    /// Start position and level of the complete subtrees covering `old_size` leaves, largest first
+    pub(super) fn subtree_spans(old_size: usize) -> Vec<(usize, usize)> {
+        let mut start = 0;
+        (0..usize::BITS as usize).rev()
+            .filter(|level| old_size & (1 << level) != 0)
+            .map(|level| {
+                let span = (start, level);
+                start += 1 << level;
+                span
+            })
+            .collect()
+    }
+    
+    /// Calculate the root of a tree of `height` and `size` leaves from the subtrees
+    ///
+    /// Nodes at or beyond `size` are empty; any other node the subtrees don't
+    /// provide comes from `sibling`, which is called in the order of `items`.
+    pub(super) fn calculate_root(
+        &self,
+        old_size: usize,
+        height: usize,
+        size: usize,
+        mut sibling: impl FnMut(usize) -> Option<[u8; 32]>,
+    ) -> Option<[u8; 32]> {
+        let spans = Self::subtree_spans(old_size);
+        if spans.len() != self.subtrees.len() || height >= usize::BITS as usize || size > 1 << height || old_size > size {
+            return None;
+        }
+        let first_leaf = 1usize << height;
+        
+        // Known node hashes by tree index, where node i has children 2i and 2i + 1
+        let mut known: BTreeMap<usize, [u8; 32]> = BTreeMap::new();
+        for level in 0..=height {
+            for ((start, _), hash) in spans.iter().zip(&self.subtrees).filter(|((_, span_level), _)| *span_level == level) {
+                known.insert((first_leaf + start) >> level, *hash);
+            }
+            if level == height {
+                break;
+            }
+            
+            let mut parents = BTreeMap::new();
+            for (&index, hash) in &known {
+                // A right child whose left sibling is known was combined with it already
+                if index % 2 == 1 && known.contains_key(&(index - 1)) {
+                    continue;
+                }
+                let sibling_index = index ^ 1;
+                let sibling_hash = match known.get(&sibling_index) {
+                    Some(sibling_hash) => *sibling_hash,
+                    None if (sibling_index << level) - first_leaf >= size => {
+                        TreeNode::new_empty_with(self.algorithm, level, sibling_index).hash
+                    }
+                    None => sibling(sibling_index)?,
+                };
+                let children: [&[u8]; 2] = if index % 2 == 0 {
+                    [hash, &sibling_hash]
+                } else {
+                    [&sibling_hash, hash]
+                };
+                parents.insert(index / 2, crypto::secure_hash_multiple_with(self.algorithm, domains::INTERNAL_NODE, &children));
+            }
+            known = parents;
+        }
+        
+        let root = known.get(&1)?;
+        Some(crypto::secure_hash_with(self.algorithm, domains::ROOT_NODE, root))
+    }
+}
+
+/// A Merkle proof as on-chain verifiers consume it
+///
+/// Encodes as `abi.encode(bytes32[] siblings, uint256 index, bytes32 leaf)`,
+/// the layout `VerifiableDBAvs.verifyMerkleProof` decodes. The contract
+/// hashes nodes the same way as [`SecureMerkleProof`] on SHA-256 trees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbiMerkleProof {
+    /// Sibling hashes from the leaf level up
+    pub siblings: Vec<[u8; 32]>,
+    
+    /// Bit `i` is set when the `i`th sibling is a left child
+    pub index: u64,
+    
+    /// Domain-separated hash of the leaf
+    pub leaf: [u8; 32],
+}
+
+impl AbiMerkleProof {
+    /// ABI-encode the proof as `(bytes32[] siblings, uint256 index, bytes32 leaf)`
+    pub fn to_abi_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(ABI_WORD * (4 + self.siblings.len()));
+        // Head: offset of the dynamic array, then the static fields
+        bytes.extend_from_slice(&abi_word(3 * ABI_WORD as u64));
+        bytes.extend_from_slice(&abi_word(self.index));
+        bytes.extend_from_slice(&self.leaf);
+        // Tail: array length, then its elements
+        bytes.extend_from_slice(&abi_word(self.siblings.len() as u64));
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling);
+        }
+        bytes
+    }
+    
+    /// Decode a proof encoded by [`to_abi_bytes`](Self::to_abi_bytes)
+    pub fn from_abi_bytes(bytes: &[u8]) -> Result<Self> {
+        let word = |offset: usize| -> Result<[u8; 32]> {
+            offset.checked_add(ABI_WORD)
+                .and_then(|end| bytes.get(offset..end))
+                .map(|slice| slice.try_into().unwrap())
+                .ok_or_else(|| CoreError::MerkleError(format!("ABI proof truncated at byte {}", offset)))
+        };
+        
+        // Offsets and lengths come from the input, so positions derived from
+        // them are checked rather than left to overflow
+        let offset = usize::try_from(read_abi_uint(&word(0)?, "siblings offset")?)
+            .map_err(|_| CoreError::MerkleError("ABI proof siblings offset overflows".to_string()))?;
+        let index = read_abi_uint(&word(ABI_WORD)?, "index")?;
+        let leaf = word(2 * ABI_WORD)?;
+        if !offset.is_multiple_of(ABI_WORD) || offset < 3 * ABI_WORD || offset > bytes.len() {
+            return Err(CoreError::MerkleError(format!("Invalid siblings offset {}", offset)));
+        }
+        
+        let count = usize::try_from(read_abi_uint(&word(offset)?, "siblings length")?)
+            .map_err(|_| CoreError::MerkleError("ABI proof siblings length overflows".to_string()))?;
+        let siblings = (0..count)
+            .map(|i| {
+                let position = i.checked_add(1)
+                    .and_then(|words| words.checked_mul(ABI_WORD))
+                    .and_then(|skip| skip.checked_add(offset))
+                    .ok_or_else(|| CoreError::MerkleError(format!("ABI proof sibling {} position overflows", i)))?;
+                word(position)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if count < u64::BITS as usize && index >> count != 0 {
+            return Err(CoreError::MerkleError(format!("Index {} has bits beyond {} siblings", index, count)));
+        }
+        
+        Ok(AbiMerkleProof { siblings, index, leaf })
+    }
+    
+    /// Calculate the root hash from the proof
+    pub fn calculate_root(&self) -> [u8; 32] {
+        let mut current_hash = self.leaf;
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            current_hash = if self.index >> level & 1 == 1 {
+                crypto::secure_hash_multiple(domains::INTERNAL_NODE, &[sibling, &current_hash])
+            } else {
+                crypto::secure_hash_multiple(domains::INTERNAL_NODE, &[&current_hash, sibling])
+            };
+        }
+        crypto::secure_hash(domains::ROOT_NODE, &current_hash)
+    }
+    
+    /// Verify the proof against a given root hash
+    pub fn verify(&self, root_hash: &[u8; 32]) -> bool {
+        self.calculate_root() == *root_hash
+    }
+}
+
+/// Big-endian ABI word holding `value`
+fn abi_word(value: u64) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[ABI_WORD - 8..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// Read an ABI `uint256` word that must fit in 64 bits
+fn read_abi_uint(word: &[u8; 32], field: &str) -> Result<u64> {
+    if word[..ABI_WORD - 8].iter().any(|b| *b != 0) {
+        return Err(CoreError::MerkleError(format!("ABI proof {} exceeds 64 bits", field)));
+    }
+    Ok(u64::from_be_bytes(word[ABI_WORD - 8..].try_into().unwrap()))
 }
 
 #[cfg(test)]
@@ -269,4 +687,157 @@ mod tests {
         // Verify the calculated root matches the expected root
         assert_eq!(calculated_root, expected_root);
     }
+    
+    #[test]
+    fn test_verify_with_metadata() {
+        let leaves: Vec<Vec<u8>> = (0..6)
+            .map(|i| format!("test data {}", i).into_bytes())
+            .collect();
+        let leaf_refs: Vec<&[u8]> = leaves.iter().map(Vec::as_slice).collect();
+        let mut tree = crate::merkle::SecureMerkleTree::with_salt(leaves.len(), [3; 32]);
+        tree.add_leaves(&leaf_refs);
+        let root = tree.root_hash();
+        let expected = ProofMetadata { algorithm: HashAlgorithm::Sha256, salt: [3; 32] };
+        
+        let proof = tree.generate_proof(5);
+        assert!(proof.verify_with_metadata(&leaves[5], 5, &root, &expected));
+        
+        // The caller's expectations bind the data, position and metadata
+        assert!(!proof.verify_with_metadata(&leaves[4], 5, &root, &expected));
+        assert!(!proof.verify_with_metadata(&leaves[5], 4, &root, &expected));
+        assert!(!proof.verify_with_metadata(&leaves[5], 5, &root, &ProofMetadata::default()));
+        
+        // Altering any field of the proof makes it fail
+        let tampered: [fn(&mut SecureMerkleProof); 9] = [
+            |proof| proof.metadata.algorithm = HashAlgorithm::Blake3,
+            |proof| proof.metadata.salt = [4; 32],
+            |proof| proof.height += 1,
+            |proof| proof.height -= 1,
+            |proof| proof.position = 4,
+            |proof| proof.leaf_data = b"tampered data".to_vec(),
+            |proof| proof.items[0].direction = ProofDirection::Right,
+            |proof| proof.items[1].hash[0] ^= 1,
+            |proof| proof.positions = vec![5],
+        ];
+        for tamper in tampered {
+            let mut altered = proof.clone();
+            tamper(&mut altered);
+            assert!(!altered.verify_with_metadata(&leaves[5], 5, &root, &expected));
+        }
+        
+        // A proof that picks its own salt verifies on its own terms, but not on the caller's
+        let mut unsalted = crate::merkle::SecureMerkleTree::new(leaves.len());
+        unsalted.add_leaves(&leaf_refs);
+        let foreign = unsalted.generate_proof(5);
+        assert!(foreign.verify(&unsalted.root_hash()));
+        assert!(!foreign.verify_with_metadata(&leaves[5], 5, &unsalted.root_hash(), &expected));
+    }
+    
+    #[test]
+    fn test_path_length_bounds() {
+        let leaves: Vec<Vec<u8>> = (0..5)
+            .map(|i| format!("test data {}", i).into_bytes())
+            .collect();
+        let tree = crate::merkle::SecureMerkleTree::from_leaves(&leaves);
+        let root = tree.root_hash();
+        
+        let proof = tree.generate_proof(3);
+        assert_eq!(proof.expected_path_len(), 3);
+        assert_eq!(proof.tree_size_bounds(), Some(4..=8));
+        assert!(proof.verify(&root));
+        let batch = tree.generate_batch_proof(&[1, 4]).unwrap();
+        assert_eq!(batch.tree_size_bounds(), Some(5..=8));
+        
+        // A truncated path is rejected, even when its root is what the caller expects
+        let mut truncated = proof.clone();
+        truncated.items.pop();
+        assert!(!truncated.verify(&truncated.calculate_root()));
+        
+        // So is a padded one
+        let mut padded = proof.clone();
+        padded.items.push(padded.items[0].clone());
+        assert!(!padded.verify(&padded.calculate_root()));
+        
+        // Matching the height to the altered path doesn't help against the real root
+        truncated.height -= 1;
+        padded.height += 1;
+        assert!(!truncated.verify(&root));
+        assert!(!padded.verify(&root));
+        
+        // Nor does a position the height can't hold, or a height no tree can have
+        let mut outside = proof.clone();
+        outside.position = 8;
+        assert_eq!(outside.tree_size_bounds(), None);
+        assert!(!outside.verify(&outside.calculate_root()));
+        let mut towering = SecureMerkleProof::new(b"a".to_vec(), 0, Vec::new());
+        towering.height = usize::BITS as usize;
+        assert_eq!(towering.tree_size_bounds(), None);
+    }
+    
+    #[test]
+    fn test_abi_encoding() {
+        // abi.encode([bytes32(0x11..), bytes32(0x22..)], uint256(2), bytes32(0xaa..))
+        let expected = concat!(
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "1111111111111111111111111111111111111111111111111111111111111111",
+            "2222222222222222222222222222222222222222222222222222222222222222",
+        );
+        let abi_proof = AbiMerkleProof {
+            siblings: vec![[0x11; 32], [0x22; 32]],
+            index: 2,
+            leaf: [0xaa; 32],
+        };
+        assert_eq!(hex::encode(abi_proof.to_abi_bytes()), expected);
+        // The root the contract's test_VerifyMerkleProof checks this vector against
+        assert_eq!(
+            hex::encode(abi_proof.calculate_root()),
+            "c0e5e3ad7b76a45cc13618783862de51a536c16bc4348e50b70efcf092695382"
+        );
+        assert_eq!(AbiMerkleProof::from_abi_bytes(&hex::decode(expected).unwrap()).unwrap(), abi_proof);
+        
+        // Directions pack into the index, and the encoded proof checks against the same root
+        let items = vec![
+            ProofItem { hash: [0x11; 32], direction: ProofDirection::Right },
+            ProofItem { hash: [0x22; 32], direction: ProofDirection::Left },
+        ];
+        let proof = SecureMerkleProof::new(b"a".to_vec(), 2, items);
+        let decoded = AbiMerkleProof::from_abi_bytes(&proof.to_abi_bytes()).unwrap();
+        assert_eq!(decoded.index, 2);
+        assert_eq!(decoded.leaf, proof.leaf_hash());
+        assert!(decoded.verify(&proof.calculate_root()));
+        
+        // Truncated input and out-of-range indexes are rejected
+        let bytes = proof.to_abi_bytes();
+        assert!(AbiMerkleProof::from_abi_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut bad_index = bytes.clone();
+        bad_index[63] = 4;
+        assert!(AbiMerkleProof::from_abi_bytes(&bad_index).is_err());
+    }
+    
+    #[test]
+    fn test_abi_decoding_rejects_huge_offsets_and_lengths() {
+        let bytes = AbiMerkleProof {
+            siblings: vec![[0x11; 32], [0x22; 32]],
+            index: 2,
+            leaf: [0xaa; 32],
+        }.to_abi_bytes();
+        
+        // An offset that would wrap around to the start of the proof
+        let mut huge_offset = bytes.clone();
+        huge_offset[24..32].copy_from_slice(&0xFFFF_FFFF_FFFF_FFE0u64.to_be_bytes());
+        assert!(AbiMerkleProof::from_abi_bytes(&huge_offset).is_err());
+        
+        // An offset past the end of the proof
+        let mut past_end = bytes.clone();
+        past_end[24..32].copy_from_slice(&(bytes.len() as u64 + 32).to_be_bytes());
+        assert!(AbiMerkleProof::from_abi_bytes(&past_end).is_err());
+        
+        // A sibling count whose positions would overflow
+        let mut huge_count = bytes.clone();
+        huge_count[120..128].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(AbiMerkleProof::from_abi_bytes(&huge_count).is_err());
+    }
 } 
\ No newline at end of file
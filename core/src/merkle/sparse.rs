@@ -0,0 +1,327 @@
+//! Sparse Merkle tree keyed by row id
+//!
+//! Every possible key has a fixed leaf position given by the hash of the key,
+//! so inserting or deleting one key only rehashes that key's path, and a
+//! row's proof is addressed by its key rather than by an index that shifts
+//! as other rows come and go. Empty subtrees hash to precomputed per-height
+//! defaults and are never stored.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use serde::{Serialize, Deserialize};
+
+use crate::crypto;
+use super::domains;
+
+/// Depth of the tree: one level per bit of a 32-byte key
+pub const SPARSE_TREE_DEPTH: usize = 256;
+
+/// Hash of the empty subtree at each height (0 = leaf)
+fn default_hashes() -> Vec<[u8; 32]> {
+    let mut defaults = Vec::with_capacity(SPARSE_TREE_DEPTH + 1);
+    defaults.push(crypto::secure_hash(domains::EMPTY_NODE, &[]));
+    for height in 1..=SPARSE_TREE_DEPTH {
+        let child = defaults[height - 1];
+        defaults.push(hash_internal(&child, &child));
+    }
+    defaults
+}
+
+fn hash_internal(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    crypto::secure_hash_multiple(domains::INTERNAL_NODE, &[left, right])
+}
+
+fn hash_leaf(key: &[u8; 32], data: &[u8]) -> [u8; 32] {
+    crypto::secure_hash_multiple(domains::LEAF_NODE, &[key, data])
+}
+
+/// Bit of `key` selecting the child at `depth` (0 = root's children)
+fn bit_at(key: &[u8; 32], depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+/// `key` with every bit from `depth` onwards cleared, identifying the node at `depth`
+fn prefix(key: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut prefix = [0u8; 32];
+    let full_bytes = depth / 8;
+    prefix[..full_bytes].copy_from_slice(&key[..full_bytes]);
+    if !depth.is_multiple_of(8) {
+        prefix[full_bytes] = key[full_bytes] & !(0xFF >> (depth % 8));
+    }
+    prefix
+}
+
+/// Sparse Merkle tree over 256-bit keys
+#[derive(Clone)]
+pub struct SparseMerkleTree {
+    /// Non-default nodes, keyed by (depth, path prefix)
+    nodes: HashMap<(usize, [u8; 32]), [u8; 32]>,
+
+    /// Hash of the empty subtree at each height
+    defaults: Vec<[u8; 32]>,
+
+    /// Number of non-empty leaves
+    len: usize,
+}
+
+impl Debug for SparseMerkleTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "SparseMerkleTree {{ root_hash: {}, leaves: {} }}",
+            hex::encode(&self.root_hash()[0..4]), // Show first 4 bytes of hash
+            self.len
+        )
+    }
+}
+
+impl Default for SparseMerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SparseMerkleTree {
+    /// Create a new empty sparse Merkle tree
+    pub fn new() -> Self {
+        SparseMerkleTree {
+            nodes: HashMap::new(),
+            defaults: default_hashes(),
+            len: 0,
+        }
+    }
+
+    /// Derive the leaf key for an identifier such as a row id
+    pub fn key_for(id: &[u8]) -> [u8; 32] {
+        crypto::secure_hash(domains::SPARSE_KEY, id)
+    }
+
+    /// Get the root hash of the tree
+    pub fn root_hash(&self) -> [u8; 32] {
+        self.node(0, &[0u8; 32])
+    }
+
+    /// Get the number of non-empty leaves
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the tree has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Check if a key has a leaf
+    pub fn contains(&self, key: &[u8; 32]) -> bool {
+        self.nodes.contains_key(&(SPARSE_TREE_DEPTH, *key))
+    }
+
+    /// Set the leaf data for a key, rehashing only that key's path
+    pub fn update(&mut self, key: &[u8; 32], data: &[u8]) {
+        if !self.contains(key) {
+            self.len += 1;
+        }
+        self.set_path(key, Some(hash_leaf(key, data)));
+    }
+
+    /// Remove the leaf for a key, returning whether it was present
+    pub fn remove(&mut self, key: &[u8; 32]) -> bool {
+        if !self.contains(key) {
+            return false;
+        }
+        self.len -= 1;
+        self.set_path(key, None);
+        true
+    }
+
+    /// Generate a proof for a key
+    ///
+    /// Proves inclusion of the key's current leaf, or non-inclusion if the
+    /// key has no leaf. Default siblings are omitted from the proof.
+    pub fn generate_proof(&self, key: &[u8; 32]) -> SparseMerkleProof {
+        let mut bitmap = [0u8; 32];
+        let mut siblings = Vec::new();
+
+        for depth in (1..=SPARSE_TREE_DEPTH).rev() {
+            let mut sibling = prefix(key, depth);
+            sibling[(depth - 1) / 8] ^= 0x80 >> ((depth - 1) % 8);
+            if let Some(hash) = self.nodes.get(&(depth, sibling)) {
+                bitmap[(depth - 1) / 8] |= 0x80 >> ((depth - 1) % 8);
+                siblings.push(*hash);
+            }
+        }
+
+        SparseMerkleProof {
+            key: *key,
+            bitmap,
+            siblings,
+        }
+    }
+
+    /// Verify a proof against this tree's root
+    pub fn verify_proof(&self, proof: &SparseMerkleProof, data: Option<&[u8]>) -> bool {
+        proof.verify(&self.root_hash(), data)
+    }
+
+    /// Hash of the node at `depth` on the path `prefix`
+    fn node(&self, depth: usize, prefix: &[u8; 32]) -> [u8; 32] {
+        self.nodes
+            .get(&(depth, *prefix))
+            .copied()
+            .unwrap_or(self.defaults[SPARSE_TREE_DEPTH - depth])
+    }
+
+    /// Store a leaf hash (or clear it) and rehash the path up to the root
+    fn set_path(&mut self, key: &[u8; 32], leaf: Option<[u8; 32]>) {
+        match leaf {
+            Some(hash) => self.nodes.insert((SPARSE_TREE_DEPTH, *key), hash),
+            None => self.nodes.remove(&(SPARSE_TREE_DEPTH, *key)),
+        };
+
+        for depth in (0..SPARSE_TREE_DEPTH).rev() {
+            let parent = prefix(key, depth);
+            let mut right = parent;
+            right[depth / 8] |= 0x80 >> (depth % 8);
+            let left_hash = self.node(depth + 1, &parent);
+            let right_hash = self.node(depth + 1, &right);
+
+            let hash = hash_internal(&left_hash, &right_hash);
+            if hash == self.defaults[SPARSE_TREE_DEPTH - depth] {
+                self.nodes.remove(&(depth, parent));
+            } else {
+                self.nodes.insert((depth, parent), hash);
+            }
+        }
+    }
+}
+
+/// Proof of (non-)inclusion for a key in a sparse Merkle tree
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SparseMerkleProof {
+    /// Key the proof is for
+    pub key: [u8; 32],
+
+    /// Bit `d` is set when the sibling at depth `d + 1` is non-default
+    pub bitmap: [u8; 32],
+
+    /// Non-default sibling hashes, from the leaf level up
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl Debug for SparseMerkleProof {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "SparseMerkleProof {{ key: {}, siblings: {} }}",
+            hex::encode(&self.key[0..4]), // Show first 4 bytes of key
+            self.siblings.len()
+        )
+    }
+}
+
+impl SparseMerkleProof {
+    /// Verify the proof against a root hash
+    ///
+    /// `data` is the leaf data for an inclusion proof, or `None` to prove
+    /// that the key has no leaf.
+    pub fn verify(&self, root_hash: &[u8; 32], data: Option<&[u8]>) -> bool {
+        self.calculate_root(data) == Some(*root_hash)
+    }
+
+    /// Recompute the root implied by the proof, or `None` if it is malformed
+    pub fn calculate_root(&self, data: Option<&[u8]>) -> Option<[u8; 32]> {
+        let defaults = default_hashes();
+        let mut current = match data {
+            Some(data) => hash_leaf(&self.key, data),
+            None => defaults[0],
+        };
+
+        let mut siblings = self.siblings.iter();
+        for depth in (1..=SPARSE_TREE_DEPTH).rev() {
+            let bit = 0x80 >> ((depth - 1) % 8);
+            let sibling = if self.bitmap[(depth - 1) / 8] & bit != 0 {
+                *siblings.next()?
+            } else {
+                defaults[SPARSE_TREE_DEPTH - depth]
+            };
+
+            current = if bit_at(&self.key, depth - 1) {
+                hash_internal(&sibling, &current)
+            } else {
+                hash_internal(&current, &sibling)
+            };
+        }
+
+        if siblings.next().is_some() {
+            return None;
+        }
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_tree_proofs() {
+        let mut tree = SparseMerkleTree::new();
+        let empty_root = tree.root_hash();
+
+        let key_a = SparseMerkleTree::key_for(b"a");
+        let key_b = SparseMerkleTree::key_for(b"b");
+        tree.update(&key_a, b"row a");
+        tree.update(&key_b, b"row b");
+        assert_eq!(tree.len(), 2);
+
+        let proof = tree.generate_proof(&key_a);
+        assert!(tree.verify_proof(&proof, Some(b"row a")));
+        assert!(!tree.verify_proof(&proof, Some(b"tampered")));
+        assert!(!tree.verify_proof(&proof, None));
+
+        // Non-inclusion of an absent key
+        let key_c = SparseMerkleTree::key_for(b"c");
+        let absent = tree.generate_proof(&key_c);
+        assert!(tree.verify_proof(&absent, None));
+
+        // Removing every key returns to the empty root
+        assert!(tree.remove(&key_a));
+        assert!(tree.remove(&key_b));
+        assert!(!tree.remove(&key_b));
+        assert_eq!(tree.root_hash(), empty_root);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_sparse_tree_update_then_reprove() {
+        let mut tree = SparseMerkleTree::new();
+        let key_a = SparseMerkleTree::key_for(b"a");
+        let key_b = SparseMerkleTree::key_for(b"b");
+        tree.update(&key_a, b"row a");
+        tree.update(&key_b, b"row b");
+        let old_root = tree.root_hash();
+        let old_proof = tree.generate_proof(&key_a);
+
+        // Updating a key keeps its position and count but changes the root
+        tree.update(&key_a, b"row a v2");
+        assert_eq!(tree.len(), 2);
+        assert_ne!(tree.root_hash(), old_root);
+
+        // The old proof still holds for the old root only
+        assert!(old_proof.verify(&old_root, Some(b"row a")));
+        assert!(!tree.verify_proof(&old_proof, Some(b"row a")));
+
+        // A fresh proof verifies the new data, not the old
+        let new_proof = tree.generate_proof(&key_a);
+        assert!(tree.verify_proof(&new_proof, Some(b"row a v2")));
+        assert!(!tree.verify_proof(&new_proof, Some(b"row a")));
+
+        // The untouched key reproves against the new root
+        let proof_b = tree.generate_proof(&key_b);
+        assert!(tree.verify_proof(&proof_b, Some(b"row b")));
+
+        // Restoring the old data restores the old root
+        tree.update(&key_a, b"row a");
+        assert_eq!(tree.root_hash(), old_root);
+    }
+}
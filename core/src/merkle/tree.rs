@@ -3,13 +3,17 @@
 //! This module provides a cryptographically secure Merkle tree implementation
 //! with domain separation for different tree nodes.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::{Debug, Formatter, Result as FmtResult};
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use rand::rngs::OsRng;
+use rand::RngCore;
 
-use crate::crypto::{self};
+use crate::crypto::{self, HashAlgorithm};
+use crate::error::CoreError;
+use crate::Result;
 use super::domains;
-use super::proof::{SecureMerkleProof, ProofItem, ProofDirection};
+use super::proof::{SecureMerkleProof, ProofItem, ProofDirection, ProofMetadata, ConsistencyProof};
 
 /// Type of node in the Merkle tree
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -60,7 +64,12 @@ impl Debug for TreeNode {
 impl TreeNode {
     /// Create a new leaf node
     pub fn new_leaf(data: &[u8], index: usize) -> Self {
-        let hash = crypto::secure_hash(domains::LEAF_NODE, data);
+        Self::new_leaf_with(&ProofMetadata::default(), data, index)
+    }
+    
+    /// Create a new leaf node hashed with the given algorithm and salt
+    pub fn new_leaf_with(metadata: &ProofMetadata, data: &[u8], index: usize) -> Self {
+        let hash = metadata.leaf_hash(data);
         
         TreeNode {
             node_type: NodeType::Leaf,
@@ -73,8 +82,14 @@ impl TreeNode {
     
     /// Create a new internal node
     pub fn new_internal(left: &TreeNode, right: &TreeNode, index: usize, height: usize) -> Self {
+        Self::new_internal_with(HashAlgorithm::Sha256, left, right, index, height)
+    }
+    
+    /// Create a new internal node hashed with the given algorithm
+    pub fn new_internal_with(algorithm: HashAlgorithm, left: &TreeNode, right: &TreeNode, index: usize, height: usize) -> Self {
         // Hash the concatenation of the left and right child hashes with domain separation
-        let hash = crypto::secure_hash_multiple(
+        let hash = crypto::secure_hash_multiple_with(
+            algorithm,
             domains::INTERNAL_NODE,
             &[&left.hash, &right.hash]
         );
@@ -90,11 +105,17 @@ impl TreeNode {
     
     /// Create a new empty node
     pub fn new_empty(height: usize, index: usize) -> Self {
+        Self::new_empty_with(HashAlgorithm::Sha256, height, index)
+    }
+    
+    /// Create a new empty node hashed with the given algorithm
+    pub fn new_empty_with(algorithm: HashAlgorithm, height: usize, index: usize) -> Self {
         // Use height in the empty node hash for different default values at different heights
         let height_bytes = height.to_be_bytes();
         let index_bytes = index.to_be_bytes();
         
-        let hash = crypto::secure_hash_multiple(
+        let hash = crypto::secure_hash_multiple_with(
+            algorithm,
             domains::EMPTY_NODE,
             &[&height_bytes, &index_bytes]
         );
@@ -125,7 +146,11 @@ impl TreeNode {
 }
 
 /// A cryptographically secure Merkle tree with domain separation
-#[derive(Clone, Serialize, Deserialize)]
+///
+/// Serializes as its leaves, hashing parameters and root rather than its
+/// nodes. Deserializing rebuilds the nodes and fails if the rebuilt root
+/// doesn't match the stored one.
+#[derive(Clone)]
 pub struct SecureMerkleTree {
     /// Nodes of the tree
     nodes: HashMap<usize, TreeNode>,
@@ -141,24 +166,103 @@ pub struct SecureMerkleTree {
     
     /// Maximum number of leaves
     max_leaves: usize,
+    
+    /// Hash algorithm for every node of the tree
+    algorithm: HashAlgorithm,
+    
+    /// Salt mixed into leaf hashes (all zeros for unsalted trees)
+    salt: [u8; 32],
+}
+
+/// Serialized form of a [`SecureMerkleTree`]
+#[derive(Serialize, Deserialize)]
+struct TreeSnapshot {
+    /// Leaf data in position order
+    leaves: Vec<Vec<u8>>,
+    
+    /// Height of the tree
+    height: usize,
+    
+    /// Hash algorithm of the tree
+    algorithm: HashAlgorithm,
+    
+    /// Salt of the tree's leaf hashes
+    salt: [u8; 32],
+    
+    /// Root hash the rebuilt tree must have
+    root_hash: [u8; 32],
+}
+
+impl Serialize for SecureMerkleTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        TreeSnapshot {
+            leaves: self.leaves(),
+            height: self.height,
+            algorithm: self.algorithm,
+            salt: self.salt,
+            root_hash: self.root_hash(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecureMerkleTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let snapshot = TreeSnapshot::deserialize(deserializer)?;
+        Self::from_snapshot(snapshot).map_err(serde::de::Error::custom)
+    }
 }
 
 impl Debug for SecureMerkleTree {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         write!(
             f,
-            "SecureMerkleTree {{ root_hash: {}, height: {}, num_leaves: {}, max_leaves: {} }}",
+            "SecureMerkleTree {{ root_hash: {}, height: {}, num_leaves: {}, max_leaves: {}, algorithm: {:?} }}",
             hex::encode(&self.root_hash[0..4]), // Show first 4 bytes of hash
             self.height,
             self.num_leaves,
-            self.max_leaves
+            self.max_leaves,
+            self.algorithm
         )
     }
 }
 
 impl SecureMerkleTree {
     /// Create a new empty Merkle tree with a specified capacity
+    ///
+    /// The tree is unsalted, so anyone can precompute the leaf hashes of
+    /// guessable data. Meant for tests and for roots that must match existing
+    /// unsalted ones; use [`new_random`](Self::new_random) otherwise.
     pub fn new(capacity: usize) -> Self {
+        Self::with_algorithm(capacity, HashAlgorithm::Sha256)
+    }
+    
+    /// Create a new empty Merkle tree with a salt drawn from the OS CSPRNG
+    pub fn new_random(capacity: usize) -> Self {
+        let mut salt = [0u8; 32];
+        OsRng.fill_bytes(&mut salt);
+        Self::with_salt(capacity, salt)
+    }
+    
+    /// Create a new empty Merkle tree salting its leaf hashes with `salt`
+    ///
+    /// Proofs generated from the tree carry the salt so verifiers can
+    /// reproduce the leaf hashes. The zero salt gives an unsalted tree.
+    pub fn with_salt(capacity: usize, salt: [u8; 32]) -> Self {
+        Self::build(capacity, HashAlgorithm::Sha256, salt)
+    }
+    
+    /// Create a new empty Merkle tree hashing its nodes with `algorithm`
+    ///
+    /// Domain separation is the same for every algorithm. Proofs generated
+    /// from the tree record the algorithm, so they verify without knowing
+    /// which tree they came from.
+    pub fn with_algorithm(capacity: usize, algorithm: HashAlgorithm) -> Self {
+        Self::build(capacity, algorithm, [0; 32])
+    }
+    
+    /// Create a new empty Merkle tree with the given algorithm and salt
+    fn build(capacity: usize, algorithm: HashAlgorithm, salt: [u8; 32]) -> Self {
         // Calculate the minimum height needed for the capacity
         let height = (capacity as f64).log2().ceil() as usize;
         let max_leaves = 2_usize.pow(height as u32);
@@ -170,10 +274,12 @@ impl SecureMerkleTree {
             height,
             num_leaves: 0,
             max_leaves,
+            algorithm,
+            salt,
         };
         
         // Create the empty root node
-        let root_node = TreeNode::new_empty(height, 1);
+        let root_node = TreeNode::new_empty_with(algorithm, height, 1);
         tree.nodes.insert(1, root_node);
         
         tree
@@ -181,12 +287,16 @@ impl SecureMerkleTree {
     
     /// Create a new Merkle tree from a list of data items
     pub fn from_leaves(leaves: &[Vec<u8>]) -> Self {
+        Self::from_leaves_with_algorithm(leaves, HashAlgorithm::Sha256)
+    }
+    
+    /// Create a new Merkle tree from a list of data items, hashing with `algorithm`
+    pub fn from_leaves_with_algorithm(leaves: &[Vec<u8>], algorithm: HashAlgorithm) -> Self {
         let capacity = leaves.len();
-        let mut tree = Self::new(capacity);
+        let mut tree = Self::with_algorithm(capacity, algorithm);
         
-        for (i, leaf_data) in leaves.iter().enumerate() {
-            tree.update_leaf(i, leaf_data);
-        }
+        let leaves: Vec<&[u8]> = leaves.iter().map(Vec::as_slice).collect();
+        tree.add_leaves(&leaves);
         
         tree
     }
@@ -202,7 +312,7 @@ impl SecureMerkleTree {
         let root_node = self.nodes.get(&1).unwrap();
         
         // Apply domain separation to the root hash for additional security
-        crypto::secure_hash(domains::ROOT_NODE, &root_node.hash)
+        crypto::secure_hash_with(self.algorithm, domains::ROOT_NODE, &root_node.hash)
     }
     
     /// Get the number of leaves in the tree
@@ -220,6 +330,24 @@ impl SecureMerkleTree {
         self.height
     }
     
+    /// Get the hash algorithm of the tree
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+    
+    /// Get the salt of the tree's leaf hashes
+    pub fn salt(&self) -> [u8; 32] {
+        self.salt
+    }
+    
+    /// How the tree hashes, as recorded in its proofs
+    fn metadata(&self) -> ProofMetadata {
+        ProofMetadata {
+            algorithm: self.algorithm,
+            salt: self.salt,
+        }
+    }
+    
     /// Check if the tree is empty
     pub fn is_empty(&self) -> bool {
         self.num_leaves == 0
@@ -272,7 +400,7 @@ impl SecureMerkleTree {
         let leaf_index = self.leaf_index(position);
         
         // Create the new leaf node
-        let leaf_node = TreeNode::new_leaf(data, leaf_index);
+        let leaf_node = TreeNode::new_leaf_with(&self.metadata(), data, leaf_index);
         
         // Insert the leaf into the tree
         self.nodes.insert(leaf_index, leaf_node);
@@ -286,6 +414,155 @@ impl SecureMerkleTree {
         self.update_path(leaf_index);
     }
     
+    /// Append a leaf and recompute its path to the root
+    ///
+    /// Only the nodes on the new leaf's path are rehashed; the rest of the
+    /// tree is kept. A full tree is first rebuilt with the smallest height
+    /// holding the new leaf, so appending leaves one at a time gives the same
+    /// root as [`add_leaves`](Self::add_leaves) and [`from_leaves`](Self::from_leaves).
+    /// Returns the position of the new leaf.
+    pub fn add_leaf(&mut self, data: &[u8]) -> usize {
+        let position = self.num_leaves;
+        if position == self.max_leaves {
+            self.grow(position + 1);
+        }
+        self.update_leaf(position, data);
+        position
+    }
+    
+    /// Append leaves, computing each affected internal node once
+    ///
+    /// The ancestors of the new leaves are recomputed level by level rather
+    /// than path by path, so appending `n` leaves costs `O(n)` hashes instead
+    /// of `O(n log n)`. The tree grows like [`add_leaf`](Self::add_leaf) if
+    /// the leaves don't fit.
+    pub fn add_leaves(&mut self, data: &[&[u8]]) {
+        if data.is_empty() {
+            return;
+        }
+        let total = self.num_leaves + data.len();
+        if total > self.max_leaves {
+            self.grow(total);
+        }
+        
+        let first = self.num_leaves;
+        let metadata = self.metadata();
+        for (i, leaf_data) in data.iter().enumerate() {
+            let leaf_index = self.leaf_index(first + i);
+            self.nodes.insert(leaf_index, TreeNode::new_leaf_with(&metadata, leaf_data, leaf_index));
+        }
+        self.num_leaves = total;
+        
+        // The new leaves are contiguous, and so are their ancestors at each level
+        let mut start = self.leaf_index(first);
+        let mut end = self.leaf_index(total - 1);
+        while start > 1 {
+            start = Self::parent_index(start);
+            end = Self::parent_index(end);
+            for parent_index in start..=end {
+                let left = self.node_or_empty(Self::left_child_index(parent_index));
+                let right = self.node_or_empty(Self::right_child_index(parent_index));
+                let height = self.height - (parent_index as f64).log2().floor() as usize;
+                self.nodes.insert(parent_index, TreeNode::new_internal_with(self.algorithm, &left, &right, parent_index, height));
+            }
+        }
+    }
+    
+    /// Rebuild the tree with the smallest height holding `capacity` leaves
+    ///
+    /// Empty nodes hash their height and index, so every node changes when
+    /// the height does.
+    fn grow(&mut self, capacity: usize) {
+        let leaves = self.leaves();
+        *self = Self::build(capacity, self.algorithm, self.salt);
+        let leaves: Vec<&[u8]> = leaves.iter().map(Vec::as_slice).collect();
+        self.add_leaves(&leaves);
+    }
+    
+    /// Get the data of every leaf in position order
+    fn leaves(&self) -> Vec<Vec<u8>> {
+        (0..self.num_leaves)
+            .map(|position| {
+                self.nodes.get(&self.leaf_index(position))
+                    .and_then(|leaf| leaf.data.clone())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+    
+    /// Serialize the tree with bincode
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self)
+            .map_err(|e| CoreError::SerializationError(format!("Failed to serialize Merkle tree: {}", e)))
+    }
+    
+    /// Deserialize a tree serialized with [`to_bytes`](Self::to_bytes)
+    ///
+    /// Rebuilds the tree from its leaves and returns a `MerkleError` if the
+    /// rebuilt root doesn't match the stored one, which catches corrupted
+    /// leaves as well as a stored root from different hashing parameters.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let snapshot: TreeSnapshot = bincode::deserialize(bytes)
+            .map_err(|e| CoreError::SerializationError(format!("Failed to deserialize Merkle tree: {}", e)))?;
+        Self::from_snapshot(snapshot)
+    }
+    
+    /// Rebuild a tree from its serialized form, checking its root
+    fn from_snapshot(snapshot: TreeSnapshot) -> Result<Self> {
+        if snapshot.height >= usize::BITS as usize || snapshot.leaves.len() > 1 << snapshot.height {
+            return Err(CoreError::MerkleError(format!(
+                "{} leaves don't fit a tree of height {}",
+                snapshot.leaves.len(),
+                snapshot.height
+            )));
+        }
+        
+        let mut tree = Self::build(1 << snapshot.height, snapshot.algorithm, snapshot.salt);
+        let leaves: Vec<&[u8]> = snapshot.leaves.iter().map(Vec::as_slice).collect();
+        tree.add_leaves(&leaves);
+        
+        let root_hash = tree.root_hash();
+        if root_hash != snapshot.root_hash {
+            return Err(CoreError::MerkleError(format!(
+                "Rebuilt root {} doesn't match stored root {}",
+                hex::encode(root_hash),
+                hex::encode(snapshot.root_hash)
+            )));
+        }
+        
+        Ok(tree)
+    }
+    
+    /// Remove the last leaf and recompute the path to the root
+    ///
+    /// Ancestors left with no leaves beneath them are dropped rather than
+    /// rehashed, so the tree matches one of the same height built from the
+    /// remaining leaves.
+    pub fn remove_leaf(&mut self, position: usize) {
+        if self.num_leaves == 0 || position != self.num_leaves - 1 {
+            panic!("Only the last leaf can be removed");
+        }
+        
+        let mut current_index = self.leaf_index(position);
+        self.nodes.remove(&current_index);
+        self.num_leaves -= 1;
+        
+        // Walk up until a sibling still holds leaves, then rehash from there
+        while current_index > 1 {
+            let sibling_index = Self::sibling_index(current_index);
+            if self.nodes.contains_key(&sibling_index) {
+                self.update_path(sibling_index);
+                return;
+            }
+            
+            current_index = Self::parent_index(current_index);
+            self.nodes.remove(&current_index);
+        }
+        
+        // No leaves left, restore the empty root
+        self.nodes.insert(1, TreeNode::new_empty_with(self.algorithm, self.height, 1));
+    }
+    
     /// Update the path from a leaf to the root
     fn update_path(&mut self, start_index: usize) {
         let mut current_index = start_index;
@@ -296,10 +573,7 @@ impl SecureMerkleTree {
             let sibling_index = Self::sibling_index(current_index);
             
             // Get the sibling node, creating an empty one if it doesn't exist
-            let sibling = self.nodes.get(&sibling_index).cloned().unwrap_or_else(|| {
-                let height = self.height - (sibling_index as f64).log2().floor() as usize;
-                TreeNode::new_empty(height, sibling_index)
-            });
+            let sibling = self.node_or_empty(sibling_index);
             
             // Get the current node
             let current = self.nodes.get(&current_index).unwrap().clone();
@@ -312,7 +586,7 @@ impl SecureMerkleTree {
                 (sibling, current)
             };
             
-            let parent = TreeNode::new_internal(&left, &right, parent_index, height);
+            let parent = TreeNode::new_internal_with(self.algorithm, &left, &right, parent_index, height);
             
             // Insert the parent into the tree
             self.nodes.insert(parent_index, parent.clone());
@@ -337,15 +611,13 @@ impl SecureMerkleTree {
             let sibling_index = Self::sibling_index(current_index);
             
             // Get the sibling node
-            let sibling = self.nodes.get(&sibling_index).cloned().unwrap_or_else(|| {
-                let height = self.height - (sibling_index as f64).log2().floor() as usize;
-                TreeNode::new_empty(height, sibling_index)
-            });
+            let sibling = self.node_or_empty(sibling_index);
             
+            // The direction is the sibling's side: a left child has a right sibling
             let direction = if current_index % 2 == 0 {
-                ProofDirection::Left
-            } else {
                 ProofDirection::Right
+            } else {
+                ProofDirection::Left
             };
             
             // Add the sibling to the proof
@@ -367,7 +639,92 @@ impl SecureMerkleTree {
             leaf_data,
             position,
             items: proof_items,
+            positions: Vec::new(),
+            height: self.height,
+            metadata: self.metadata(),
+        }
+    }
+    
+    /// Generate one proof for several leaves
+    ///
+    /// Siblings shared by the leaves' paths appear once, and nodes the
+    /// verifier can compute from the proven leaves are left out, so the proof
+    /// is smaller than the separate proofs combined. Positions are sorted and
+    /// deduplicated; verify the proof with
+    /// [`SecureMerkleProof::verify_batch`] over the leaves' data in ascending
+    /// position order. Returns `None` if no positions are given or any is
+    /// out of bounds.
+    pub fn generate_batch_proof(&self, leaf_indices: &[usize]) -> Option<SecureMerkleProof> {
+        if leaf_indices.is_empty() || leaf_indices.iter().any(|position| *position >= self.num_leaves) {
+            return None;
+        }
+        let positions: Vec<usize> = leaf_indices.iter().copied().collect::<BTreeSet<_>>().into_iter().collect();
+        
+        // Walk up level by level, emitting the siblings that aren't known nodes
+        let mut known: BTreeSet<usize> = positions.iter().map(|position| self.leaf_index(*position)).collect();
+        let mut items = Vec::new();
+        for _ in 0..self.height {
+            for &index in &known {
+                let sibling_index = Self::sibling_index(index);
+                if known.contains(&sibling_index) {
+                    continue;
+                }
+                let direction = if index % 2 == 0 {
+                    ProofDirection::Right
+                } else {
+                    ProofDirection::Left
+                };
+                items.push(ProofItem {
+                    hash: self.node_or_empty(sibling_index).hash,
+                    direction,
+                });
+            }
+            known = known.iter().map(|index| Self::parent_index(*index)).collect();
         }
+        
+        let mut proof = SecureMerkleProof::new_batch(positions, self.height, items);
+        proof.metadata = self.metadata();
+        Some(proof)
+    }
+    
+    /// Generate a proof that the tree extends its first `old_size` leaves
+    ///
+    /// Verify it with [`ConsistencyProof::verify`] against the root the tree
+    /// had at `old_size` leaves. Returns `None` if `old_size` is zero or
+    /// larger than the tree.
+    pub fn consistency_proof(&self, old_size: usize) -> Option<ConsistencyProof> {
+        if old_size == 0 || old_size > self.num_leaves {
+            return None;
+        }
+        let subtrees = ConsistencyProof::subtree_spans(old_size).into_iter()
+            .map(|(start, level)| self.nodes.get(&(self.leaf_index(start) >> level)).map(|node| node.hash))
+            .collect::<Option<Vec<_>>>()?;
+        
+        let mut proof = ConsistencyProof {
+            subtrees,
+            items: Vec::new(),
+            height: self.height,
+            algorithm: self.algorithm,
+        };
+        
+        // Record the nodes the verifier will ask for, in the order it asks
+        let mut items = Vec::new();
+        proof.calculate_root(old_size, self.height, self.num_leaves, |index| {
+            let hash = self.node_or_empty(index).hash;
+            items.push(hash);
+            Some(hash)
+        })?;
+        proof.items = items;
+        
+        Some(proof)
+    }
+    
+    /// Get a node, or the empty node standing in for it if it doesn't exist
+    fn node_or_empty(&self, index: usize) -> TreeNode {
+        self.nodes.get(&index).cloned().unwrap_or_else(|| {
+            let height = self.height - (index as f64).log2().floor() as usize;
+            TreeNode::new_empty_with(self.algorithm, height, index)
+        })
     }
     
     /// Verify a proof against the root hash
@@ -487,6 +844,54 @@ mod tests {
         assert_eq!(leaf.data.as_ref().unwrap(), &data2);
     }
     
+    #[test]
+    fn test_bulk_insertion_matches_single_insertion() {
+        let leaves: Vec<Vec<u8>> = (0..100_000u32)
+            .map(|i| i.to_be_bytes().to_vec())
+            .collect();
+        let leaf_refs: Vec<&[u8]> = leaves.iter().map(Vec::as_slice).collect();
+        
+        let mut bulk = SecureMerkleTree::new(0);
+        bulk.add_leaves(&leaf_refs);
+        
+        let mut single = SecureMerkleTree::new(0);
+        for (i, leaf) in leaf_refs.iter().enumerate() {
+            assert_eq!(single.add_leaf(leaf), i);
+        }
+        
+        assert_eq!(bulk.num_leaves(), 100_000);
+        assert_eq!(bulk.height(), 17);
+        assert_eq!(bulk.root_hash(), single.root_hash());
+        assert_eq!(bulk.root_hash(), SecureMerkleTree::from_leaves(&leaves).root_hash());
+        
+        // Appending to a bulk-built tree only rehashes the new path
+        bulk.add_leaves(&leaf_refs[..10]);
+        for leaf in &leaf_refs[..10] {
+            single.add_leaf(leaf);
+        }
+        assert_eq!(bulk.root_hash(), single.root_hash());
+        let proof = bulk.generate_proof(100_005);
+        assert_eq!(proof.leaf_data, leaves[5]);
+        assert_eq!(proof.calculate_root(), bulk.root_hash());
+    }
+    
+    #[test]
+    fn test_remove_leaf() {
+        let leaves: Vec<Vec<u8>> = (0..7)
+            .map(|i| format!("test data {}", i).into_bytes())
+            .collect();
+        
+        // Removing trailing leaves matches a tree built without them, as long
+        // as the height doesn't change
+        let mut tree = SecureMerkleTree::from_leaves(&leaves);
+        for remaining in (5..7).rev() {
+            tree.remove_leaf(remaining);
+            let expected = SecureMerkleTree::from_leaves(&leaves[..remaining]);
+            assert_eq!(tree.num_leaves(), remaining);
+            assert_eq!(tree.root_hash(), expected.root_hash());
+        }
+    }
+    
     #[test]
     fn test_proof_generation_and_verification() {
         let mut tree = SecureMerkleTree::new(10);
@@ -514,6 +919,254 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_batch_proof() {
+        let leaves: Vec<Vec<u8>> = (0..7)
+            .map(|i| format!("test data {}", i).into_bytes())
+            .collect();
+        let tree = SecureMerkleTree::from_leaves(&leaves);
+        let root = tree.root_hash();
+        
+        // Positions are sorted and deduplicated, and the proof verifies over their data
+        let proof = tree.generate_batch_proof(&[5, 0, 2, 3, 2]).unwrap();
+        assert_eq!(proof.positions, vec![0, 2, 3, 5]);
+        let datas: Vec<&[u8]> = proof.positions.iter().map(|p| leaves[*p].as_slice()).collect();
+        assert!(proof.verify_batch(&datas, &root));
+        
+        // Shared siblings appear once, so the batch is smaller than separate proofs
+        let separate: usize = proof.positions.iter().map(|p| tree.generate_proof(*p).len()).sum();
+        assert!(proof.len() < separate);
+        let hashes: BTreeSet<[u8; 32]> = proof.items.iter().map(|item| item.hash).collect();
+        assert_eq!(hashes.len(), proof.len());
+        
+        // Altering any leaf datum breaks the proof
+        for i in 0..datas.len() {
+            let mut altered = datas.clone();
+            altered[i] = b"tampered data";
+            assert!(!proof.verify_batch(&altered, &root));
+        }
+        
+        // So do missing leaf data, out-of-order data and leftover siblings
+        assert!(!proof.verify_batch(&datas[1..], &root));
+        let mut reordered = datas.clone();
+        reordered.swap(0, 1);
+        assert!(!proof.verify_batch(&reordered, &root));
+        let mut padded = proof.clone();
+        padded.items.push(padded.items[0].clone());
+        assert!(!padded.verify_batch(&datas, &root));
+        
+        // Every leaf needs no siblings at all; a lone leaf matches its single proof
+        let all = tree.generate_batch_proof(&(0..7).collect::<Vec<_>>()).unwrap();
+        let all_datas: Vec<&[u8]> = leaves.iter().map(|leaf| leaf.as_slice()).collect();
+        assert!(all.verify_batch(&all_datas, &root));
+        assert_eq!(all.len(), 1); // The empty eighth leaf
+        let lone = tree.generate_batch_proof(&[6]).unwrap();
+        assert_eq!(lone.len(), tree.generate_proof(6).len());
+        assert!(lone.verify_batch(&[&leaves[6]], &root));
+        
+        assert!(tree.generate_batch_proof(&[]).is_none());
+        assert!(tree.generate_batch_proof(&[1, 7]).is_none());
+    }
+    
+    #[test]
+    fn test_blake3_tree() {
+        let leaves: Vec<Vec<u8>> = (0..5)
+            .map(|i| format!("test data {}", i).into_bytes())
+            .collect();
+        let tree = SecureMerkleTree::from_leaves_with_algorithm(&leaves, HashAlgorithm::Blake3);
+        let root = tree.root_hash();
+        assert_eq!(tree.algorithm(), HashAlgorithm::Blake3);
+        assert_ne!(root, SecureMerkleTree::from_leaves(&leaves).root_hash());
+        
+        // Appending keeps the algorithm, including when the tree grows
+        let mut appended = SecureMerkleTree::with_algorithm(1, HashAlgorithm::Blake3);
+        for leaf in &leaves {
+            appended.add_leaf(leaf);
+        }
+        assert_eq!(appended.root_hash(), root);
+        
+        // Proofs record the algorithm and verify with it
+        let proof = tree.generate_proof(3);
+        assert_eq!(proof.metadata.algorithm, HashAlgorithm::Blake3);
+        assert!(proof.verify(&root));
+        let mut sha256 = proof.clone();
+        sha256.metadata.algorithm = HashAlgorithm::Sha256;
+        assert!(!sha256.verify(&root));
+        
+        let batch = tree.generate_batch_proof(&[1, 4]).unwrap();
+        assert_eq!(batch.metadata.algorithm, HashAlgorithm::Blake3);
+        assert!(batch.verify_batch(&[&leaves[1], &leaves[4]], &root));
+        
+        // The algorithm survives serialization
+        let json = serde_json::to_string(&proof).unwrap();
+        let decoded: SecureMerkleProof = serde_json::from_str(&json).unwrap();
+        assert!(decoded.verify(&root));
+    }
+    
+    #[test]
+    fn test_salted_trees() {
+        let leaves: Vec<Vec<u8>> = (0..5)
+            .map(|i| format!("test data {}", i).into_bytes())
+            .collect();
+        let leaf_refs: Vec<&[u8]> = leaves.iter().map(Vec::as_slice).collect();
+        let salted = |salt: [u8; 32]| {
+            let mut tree = SecureMerkleTree::with_salt(leaves.len(), salt);
+            tree.add_leaves(&leaf_refs);
+            tree
+        };
+        
+        // Different salts give different roots for identical data
+        let unsalted = SecureMerkleTree::from_leaves(&leaves);
+        let tree = salted([1; 32]);
+        assert_ne!(tree.root_hash(), salted([2; 32]).root_hash());
+        assert_ne!(tree.root_hash(), unsalted.root_hash());
+        assert_eq!(tree.root_hash(), salted([1; 32]).root_hash());
+        
+        // The zero salt is the unsalted tree
+        assert_eq!(salted([0; 32]).root_hash(), unsalted.root_hash());
+        
+        // Random salts differ between trees
+        let mut first = SecureMerkleTree::new_random(leaves.len());
+        let mut second = SecureMerkleTree::new_random(leaves.len());
+        assert_ne!(first.salt(), second.salt());
+        first.add_leaves(&leaf_refs);
+        second.add_leaves(&leaf_refs);
+        assert_ne!(first.root_hash(), second.root_hash());
+        
+        // Proofs carry the salt, and verify only with it
+        let proof = tree.generate_proof(2);
+        assert_eq!(proof.metadata.salt, [1; 32]);
+        assert!(proof.verify(&tree.root_hash()));
+        let mut unsalted_proof = proof.clone();
+        unsalted_proof.metadata.salt = [0; 32];
+        assert!(!unsalted_proof.verify(&tree.root_hash()));
+        
+        let batch = tree.generate_batch_proof(&[0, 3]).unwrap();
+        assert!(batch.verify_batch(&[&leaves[0], &leaves[3]], &tree.root_hash()));
+        
+        // Growing the tree keeps the salt
+        let mut grown = SecureMerkleTree::with_salt(1, [1; 32]);
+        for leaf in &leaves {
+            grown.add_leaf(leaf);
+        }
+        assert_eq!(grown.root_hash(), tree.root_hash());
+    }
+    
+    #[test]
+    fn test_consistency_proof() {
+        let leaves: Vec<Vec<u8>> = (0..13)
+            .map(|i| format!("test data {}", i).into_bytes())
+            .collect();
+        
+        // Appending leaves one at a time, through growth from height 0 to 4
+        let mut tree = SecureMerkleTree::new(1);
+        let mut roots = Vec::new();
+        for leaf in &leaves {
+            tree.add_leaf(leaf);
+            roots.push(tree.root_hash());
+        }
+        let new_root = tree.root_hash();
+        
+        for old_size in 1..=leaves.len() {
+            let proof = tree.consistency_proof(old_size).unwrap();
+            assert!(proof.verify(&roots[old_size - 1], &new_root, old_size, leaves.len()));
+            
+            // Roots of older trees built with more capacity verify too
+            let roomy = SecureMerkleTree::from_leaves(&leaves[..old_size]);
+            assert!(proof.verify(&roomy.root_hash(), &new_root, old_size, leaves.len()));
+            
+            // The wrong old root or old size doesn't, nor a size the new tree can't hold
+            if old_size > 1 {
+                assert!(!proof.verify(&roots[old_size - 2], &new_root, old_size, leaves.len()));
+                assert!(!proof.verify(&roots[old_size - 1], &new_root, old_size - 1, leaves.len()));
+            }
+            assert!(!proof.verify(&roots[old_size - 1], &new_root, old_size, tree.max_leaves() + 1));
+        }
+        
+        assert!(tree.consistency_proof(0).is_none());
+        assert!(tree.consistency_proof(leaves.len() + 1).is_none());
+    }
+    
+    #[test]
+    fn test_consistency_proof_detects_rewrite() {
+        let leaves: Vec<Vec<u8>> = (0..10)
+            .map(|i| format!("test data {}", i).into_bytes())
+            .collect();
+        let old_root = SecureMerkleTree::from_leaves(&leaves[..6]).root_hash();
+        
+        // A historical leaf is mutated before new leaves are appended
+        let mut rewritten = SecureMerkleTree::from_leaves(&leaves[..6]);
+        rewritten.update_leaf(2, b"tampered data");
+        for leaf in &leaves[6..] {
+            rewritten.add_leaf(leaf);
+        }
+        let proof = rewritten.consistency_proof(6).unwrap();
+        assert!(!proof.verify(&old_root, &rewritten.root_hash(), 6, leaves.len()));
+        
+        // Swapping in the honest subtrees doesn't help against the new root
+        let honest = SecureMerkleTree::from_leaves(&leaves).consistency_proof(6).unwrap();
+        let mut forged = proof.clone();
+        forged.subtrees = honest.subtrees;
+        assert!(!forged.verify(&old_root, &rewritten.root_hash(), 6, leaves.len()));
+    }
+    
+    #[test]
+    fn test_serialization_round_trip() {
+        let leaves: Vec<Vec<u8>> = (0..5)
+            .map(|i| format!("test data {}", i).into_bytes())
+            .collect();
+        let leaf_refs: Vec<&[u8]> = leaves.iter().map(Vec::as_slice).collect();
+        let mut tree = SecureMerkleTree::with_salt(16, [7; 32]);
+        tree.add_leaves(&leaf_refs);
+        
+        let restored = SecureMerkleTree::from_bytes(&tree.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored.root_hash(), tree.root_hash());
+        assert_eq!(restored.height(), tree.height());
+        assert_eq!(restored.num_leaves(), tree.num_leaves());
+        assert_eq!(restored.salt(), tree.salt());
+        assert_eq!(restored.generate_proof(3).leaf_data, leaves[3]);
+        
+        // Other serde formats round-trip too, including empty and Blake3 trees
+        for tree in [SecureMerkleTree::new(0), SecureMerkleTree::from_leaves_with_algorithm(&leaves, HashAlgorithm::Blake3)] {
+            let json = serde_json::to_string(&tree).unwrap();
+            let restored: SecureMerkleTree = serde_json::from_str(&json).unwrap();
+            assert_eq!(restored.root_hash(), tree.root_hash());
+            assert_eq!(restored.algorithm(), tree.algorithm());
+        }
+    }
+    
+    #[test]
+    fn test_corrupted_serialization_rejected() {
+        let leaves: Vec<Vec<u8>> = (0..5)
+            .map(|i| format!("test data {}", i).into_bytes())
+            .collect();
+        let bytes = SecureMerkleTree::from_leaves(&leaves).to_bytes().unwrap();
+        
+        // Flip a byte of leaf data; the length prefixes are untouched
+        let offset = bytes.windows(11).position(|window| window == b"test data 2").unwrap();
+        let mut corrupted = bytes.clone();
+        corrupted[offset + 10] = b'9';
+        assert!(matches!(
+            SecureMerkleTree::from_bytes(&corrupted),
+            Err(CoreError::MerkleError(_))
+        ));
+        
+        // Flip a byte of the stored root
+        let mut corrupted = bytes.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        assert!(matches!(
+            SecureMerkleTree::from_bytes(&corrupted),
+            Err(CoreError::MerkleError(_))
+        ));
+        
+        // Truncated bytes don't decode at all
+        assert!(matches!(
+            SecureMerkleTree::from_bytes(&bytes[..bytes.len() / 2]),
+            Err(CoreError::SerializationError(_))
+        ));
+    }
+    
     #[test]
     fn test_tampered_proof() {
         let mut tree = SecureMerkleTree::new(10);
@@ -9,7 +9,7 @@ mod transaction;
 mod block;
 mod challenge;
 
-pub use table::{TableState, ColumnType, ColumnDefinition, TableSchema};
+pub use table::{TableState, MerkleTreeMode, ColumnType, ColumnDefinition, TableSchema, TriggerDefinition, TriggerTiming, TriggerEvent, PartitionStrategy, PartitionDefinition, PartitionScheme};
 pub use row::{Row, ValueType, Value};
 pub use transaction::{TransactionRecord, TransactionType, Operation, OperationType};
 pub use block::{BlockState, BlockHeader, BlockMetadata};
@@ -8,6 +8,7 @@ use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
 use crate::crypto;
+use crate::utils;
 use super::domains;
 
 /// Type of value in a row
@@ -40,6 +41,12 @@ pub enum ValueType {
     /// JSON data
     Json,
     
+    /// Array of values
+    Array,
+    
+    /// Composite (row type) value
+    Composite,
+    
     /// Null value
     Null,
 }
@@ -56,6 +63,10 @@ pub enum Value {
     Uuid(Uuid),
     Timestamp(i64),
     Json(String),
+    /// Array elements in order; multidimensional arrays nest
+    Array(Vec<Value>),
+    /// Composite fields as (name, value) pairs
+    Composite(Vec<(String, Value)>),
     Null,
 }
 
@@ -89,6 +100,8 @@ impl Debug for Value {
                     write!(f, "Json({})", v)
                 }
             }
+            Value::Array(v) => write!(f, "Array({:?})", v),
+            Value::Composite(v) => write!(f, "Composite({:?})", v),
             Value::Null => write!(f, "Null"),
         }
     }
@@ -107,11 +120,19 @@ impl Value {
             Value::Uuid(_) => ValueType::Uuid,
             Value::Timestamp(_) => ValueType::Timestamp,
             Value::Json(_) => ValueType::Json,
+            Value::Array(_) => ValueType::Array,
+            Value::Composite(_) => ValueType::Composite,
             Value::Null => ValueType::Null,
         }
     }
     
     /// Serialize the value to bytes
+    ///
+    /// Arrays and composites use a canonical encoding: elements keep their
+    /// order, composite fields are sorted by name, and every nested value is
+    /// tagged and length-prefixed so NULL elements stay distinguishable.
+    /// Valid JSON is written canonically, so documents that differ only in
+    /// key order, whitespace or number form give the same bytes.
     pub fn to_bytes(&self) -> Vec<u8> {
         match self {
             Value::Integer(v) => v.to_be_bytes().to_vec(),
@@ -122,10 +143,84 @@ impl Value {
             Value::Boolean(v) => vec![if *v { 1 } else { 0 }],
             Value::Uuid(v) => v.as_bytes().to_vec(),
             Value::Timestamp(v) => v.to_be_bytes().to_vec(),
-            Value::Json(v) => v.as_bytes().to_vec(),
+            Value::Json(v) => match serde_json::from_str::<serde_json::Value>(v) {
+                Ok(json) => utils::canonical_json_bytes(&json),
+                Err(_) => v.as_bytes().to_vec(),
+            },
+            Value::Array(elements) => {
+                let mut bytes = (elements.len() as u32).to_be_bytes().to_vec();
+                for element in elements {
+                    element.write_nested(&mut bytes);
+                }
+                bytes
+            }
+            Value::Composite(fields) => {
+                let mut bytes = (fields.len() as u32).to_be_bytes().to_vec();
+                for (name, value) in sorted_fields(fields) {
+                    bytes.extend_from_slice(&(name.len() as u32).to_be_bytes());
+                    bytes.extend_from_slice(name.as_bytes());
+                    value.write_nested(&mut bytes);
+                }
+                bytes
+            }
             Value::Null => vec![],
         }
     }
+    
+    /// Compare two values under the same canonicalization used for hashing
+    ///
+    /// Arrays are compared element-wise in order and composites field-wise by
+    /// name, regardless of field order. NULL equals NULL (also as an element),
+    /// and floats and JSON compare by their bytes, as they are hashed.
+    pub fn canonical_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.canonical_eq(b))
+            }
+            (Value::Composite(a), Value::Composite(b)) => {
+                a.len() == b.len()
+                    && sorted_fields(a)
+                        .iter()
+                        .zip(sorted_fields(b).iter())
+                        .all(|((a_name, a_value), (b_name, b_value))| {
+                            a_name == b_name && a_value.canonical_eq(b_value)
+                        })
+            }
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Json(_), Value::Json(_)) => self.to_bytes() == other.to_bytes(),
+            _ => self == other,
+        }
+    }
+    
+    /// Append the tagged, length-prefixed encoding of a nested value
+    fn write_nested(&self, bytes: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            Value::Null => 0,
+            Value::Integer(_) => 1,
+            Value::BigInt(_) => 2,
+            Value::Float(_) => 3,
+            Value::Text(_) => 4,
+            Value::Binary(_) => 5,
+            Value::Boolean(_) => 6,
+            Value::Uuid(_) => 7,
+            Value::Timestamp(_) => 8,
+            Value::Json(_) => 9,
+            Value::Array(_) => 10,
+            Value::Composite(_) => 11,
+        };
+        bytes.push(tag);
+        
+        let value_bytes = self.to_bytes();
+        bytes.extend_from_slice(&(value_bytes.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&value_bytes);
+    }
+}
+
+/// Composite fields sorted by name for deterministic ordering
+fn sorted_fields(fields: &[(String, Value)]) -> Vec<&(String, Value)> {
+    let mut sorted: Vec<&(String, Value)> = fields.iter().collect();
+    sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+    sorted
 }
 
 /// A row in a database table
@@ -287,6 +382,8 @@ mod tests {
             Value::Uuid(Uuid::new_v4()),
             Value::Timestamp(1609459200000), // 2021-01-01 00:00:00 UTC
             Value::Json(r#"{"key":"value"}"#.to_string()),
+            Value::Array(vec![Value::Integer(1), Value::Null]),
+            Value::Composite(vec![("x".to_string(), Value::Integer(1))]),
             Value::Null,
         ];
         
@@ -310,8 +407,83 @@ mod tests {
                 Value::Uuid(_) => ValueType::Uuid,
                 Value::Timestamp(_) => ValueType::Timestamp,
                 Value::Json(_) => ValueType::Json,
+                Value::Array(_) => ValueType::Array,
+                Value::Composite(_) => ValueType::Composite,
                 Value::Null => ValueType::Null,
             });
         }
     }
+    
+    #[test]
+    fn test_json_values_hash_canonically() {
+        let json_row = |json: &str| {
+            Row::new("1".to_string(), "events".to_string(), HashMap::from([
+                ("payload".to_string(), Value::Json(json.to_string())),
+            ]))
+        };
+        
+        // Reordered keys, whitespace and 1.0 vs 1 hash identically
+        let row = json_row(r#"{"amount":1,"tags":["a","b"]}"#);
+        assert_eq!(row.hash(), json_row(r#"{ "tags": ["a", "b"], "amount": 1.0 }"#).hash());
+        assert!(Value::Json(r#"{"b":1,"a":2}"#.to_string()).canonical_eq(&Value::Json(r#"{"a":2,"b":1.0}"#.to_string())));
+        
+        // Different values or element order don't
+        assert_ne!(row.hash(), json_row(r#"{"amount":1.5,"tags":["a","b"]}"#).hash());
+        assert_ne!(row.hash(), json_row(r#"{"amount":1,"tags":["b","a"]}"#).hash());
+        
+        // Text that isn't JSON still hashes as written
+        assert_eq!(Value::Json("not json".to_string()).to_bytes(), b"not json".to_vec());
+    }
+    
+    #[test]
+    fn test_array_equality_is_order_sensitive() {
+        let a = Value::Array(vec![Value::Integer(1), Value::Integer(2)]);
+        let b = Value::Array(vec![Value::Integer(2), Value::Integer(1)]);
+        
+        assert!(!a.canonical_eq(&b));
+        assert_ne!(a.to_bytes(), b.to_bytes());
+        assert!(a.canonical_eq(&a.clone()));
+        
+        // NULL elements are kept apart from missing and empty elements
+        let with_null = Value::Array(vec![Value::Null, Value::Text("a".to_string())]);
+        let without_null = Value::Array(vec![Value::Text("a".to_string())]);
+        let with_empty = Value::Array(vec![Value::Text(String::new()), Value::Text("a".to_string())]);
+        
+        assert!(!with_null.canonical_eq(&without_null));
+        assert!(!with_null.canonical_eq(&with_empty));
+        assert_ne!(with_null.to_bytes(), without_null.to_bytes());
+        assert_ne!(with_null.to_bytes(), with_empty.to_bytes());
+        assert!(with_null.canonical_eq(&with_null.clone()));
+    }
+    
+    #[test]
+    fn test_composite_equality_ignores_field_order() {
+        let a = Value::Composite(vec![
+            ("street".to_string(), Value::Text("Main St".to_string())),
+            ("zip".to_string(), Value::Null),
+            ("tags".to_string(), Value::Array(vec![Value::Text("home".to_string())])),
+        ]);
+        let b = Value::Composite(vec![
+            ("tags".to_string(), Value::Array(vec![Value::Text("home".to_string())])),
+            ("zip".to_string(), Value::Null),
+            ("street".to_string(), Value::Text("Main St".to_string())),
+        ]);
+        
+        assert!(a.canonical_eq(&b));
+        assert_eq!(a.to_bytes(), b.to_bytes());
+        
+        // Rows holding the reordered composites hash identically
+        let row_a = Row::new("1".to_string(), "addresses".to_string(), HashMap::from([("address".to_string(), a.clone())]));
+        let row_b = Row::new("1".to_string(), "addresses".to_string(), HashMap::from([("address".to_string(), b)]));
+        assert_eq!(row_a.hash(), row_b.hash());
+        
+        // A differing field value still differs
+        let c = Value::Composite(vec![
+            ("street".to_string(), Value::Text("Main St".to_string())),
+            ("zip".to_string(), Value::Text("12345".to_string())),
+            ("tags".to_string(), Value::Array(vec![Value::Text("home".to_string())])),
+        ]);
+        assert!(!a.canonical_eq(&c));
+        assert_ne!(a.to_bytes(), c.to_bytes());
+    }
 } 
\ No newline at end of file
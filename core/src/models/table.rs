@@ -8,7 +8,7 @@ use std::fmt::{Debug, Formatter, Result as FmtResult};
 use serde::{Serialize, Deserialize};
 
 use crate::crypto;
-use crate::merkle::SecureMerkleTree;
+use crate::merkle::{SecureMerkleTree, SparseMerkleTree, SparseMerkleProof};
 use super::domains;
 use super::row::Row;
 
@@ -71,6 +71,201 @@ pub struct ColumnDefinition {
     pub default_value: Option<String>,
 }
 
+/// When a trigger fires relative to the statement that fires it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriggerTiming {
+    /// Before the row/statement is applied
+    Before,
+    
+    /// After the row/statement is applied
+    After,
+    
+    /// Instead of the operation (views only)
+    InsteadOf,
+}
+
+impl TriggerTiming {
+    /// SQL keyword for the timing
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            TriggerTiming::Before => "BEFORE",
+            TriggerTiming::After => "AFTER",
+            TriggerTiming::InsteadOf => "INSTEAD OF",
+        }
+    }
+}
+
+/// DML event that fires a trigger
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriggerEvent {
+    /// INSERT
+    Insert,
+    
+    /// UPDATE
+    Update,
+    
+    /// DELETE
+    Delete,
+    
+    /// TRUNCATE
+    Truncate,
+}
+
+impl TriggerEvent {
+    /// SQL keyword for the event
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            TriggerEvent::Insert => "INSERT",
+            TriggerEvent::Update => "UPDATE",
+            TriggerEvent::Delete => "DELETE",
+            TriggerEvent::Truncate => "TRUNCATE",
+        }
+    }
+}
+
+/// Definition of a trigger installed on a table
+///
+/// Triggers are part of the captured schema because their side effects are
+/// part of the captured state; a replay without them cannot reproduce it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TriggerDefinition {
+    /// Name of the trigger
+    pub name: String,
+    
+    /// When the trigger fires
+    pub timing: TriggerTiming,
+    
+    /// Events that fire the trigger
+    pub events: Vec<TriggerEvent>,
+    
+    /// Whether the trigger fires once per row (otherwise once per statement)
+    pub for_each_row: bool,
+    
+    /// Name of the trigger function
+    pub function_name: String,
+    
+    /// Full `CREATE FUNCTION` statement of the trigger function
+    pub function_definition: String,
+}
+
+impl TriggerDefinition {
+    /// Decode a trigger from its `pg_trigger.tgtype` bitmask
+    pub fn from_pg_type(
+        name: String,
+        tgtype: i16,
+        function_name: String,
+        function_definition: String,
+    ) -> Self {
+        let timing = if tgtype & (1 << 6) != 0 {
+            TriggerTiming::InsteadOf
+        } else if tgtype & (1 << 1) != 0 {
+            TriggerTiming::Before
+        } else {
+            TriggerTiming::After
+        };
+        
+        let mut events = Vec::new();
+        for (bit, event) in [
+            (2, TriggerEvent::Insert),
+            (3, TriggerEvent::Delete),
+            (4, TriggerEvent::Update),
+            (5, TriggerEvent::Truncate),
+        ] {
+            if tgtype & (1 << bit) != 0 {
+                events.push(event);
+            }
+        }
+        
+        Self {
+            name,
+            timing,
+            events,
+            for_each_row: tgtype & 1 != 0,
+            function_name,
+            function_definition,
+        }
+    }
+    
+    /// Check if the trigger fires on an event
+    pub fn fires_on(&self, event: TriggerEvent) -> bool {
+        self.events.contains(&event)
+    }
+    
+    /// Build the `CREATE TRIGGER` statement for a table
+    pub fn create_statement(&self, qualified_table: &str) -> String {
+        let events: Vec<&str> = self.events.iter().map(|e| e.as_sql()).collect();
+        format!(
+            "CREATE TRIGGER {} {} {} ON {} FOR EACH {} EXECUTE FUNCTION {}()",
+            self.name,
+            self.timing.as_sql(),
+            events.join(" OR "),
+            qualified_table,
+            if self.for_each_row { "ROW" } else { "STATEMENT" },
+            self.function_name,
+        )
+    }
+}
+
+/// How rows of a partitioned table are routed to partitions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PartitionStrategy {
+    /// `PARTITION BY RANGE`
+    Range,
+    
+    /// `PARTITION BY LIST`
+    List,
+    
+    /// `PARTITION BY HASH`
+    Hash,
+}
+
+impl PartitionStrategy {
+    /// Decode a strategy from `pg_partitioned_table.partstrat`
+    pub fn from_pg_code(code: &str) -> Option<Self> {
+        match code {
+            "r" => Some(PartitionStrategy::Range),
+            "l" => Some(PartitionStrategy::List),
+            "h" => Some(PartitionStrategy::Hash),
+            _ => None,
+        }
+    }
+}
+
+/// A partition of a partitioned table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionDefinition {
+    /// Name of the partition's table
+    pub name: String,
+    
+    /// Partition bound as reported by PostgreSQL, e.g.
+    /// `FOR VALUES FROM ('2024-01-01') TO ('2025-01-01')`
+    pub bound: String,
+}
+
+/// Partitioning of a partitioned (parent) table
+///
+/// The parent is verified as one logical table: its rows are the union of
+/// its partitions' rows and it has a single root. The partition layout is
+/// part of the schema hash, so moving a boundary changes the schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionScheme {
+    /// Partitioning strategy
+    pub strategy: PartitionStrategy,
+    
+    /// Partition key as reported by `pg_get_partkeydef`, e.g. `RANGE (created_at)`
+    pub key: String,
+    
+    /// Partitions, ordered by name
+    pub partitions: Vec<PartitionDefinition>,
+}
+
+impl PartitionScheme {
+    /// Check if a table is one of the partitions
+    pub fn has_partition(&self, name: &str) -> bool {
+        self.partitions.iter().any(|p| p.name == name)
+    }
+}
+
 /// Schema of a database table
 #[derive(Clone, Serialize, Deserialize)]
 pub struct TableSchema {
@@ -89,6 +284,14 @@ pub struct TableSchema {
     /// Foreign key constraints
     pub foreign_keys: Vec<(Vec<String>, String, Vec<String>)>,
     
+    /// Triggers installed on the table
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<TriggerDefinition>,
+    
+    /// Partitioning, if this is a partitioned table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partitioning: Option<PartitionScheme>,
+    
     /// Hash of the schema
     #[serde(skip_serializing_if = "Option::is_none")]
     pub hash: Option<[u8; 32]>,
@@ -102,6 +305,8 @@ impl Debug for TableSchema {
             .field("primary_keys", &self.primary_keys)
             .field("unique_constraints", &self.unique_constraints)
             .field("foreign_keys", &self.foreign_keys)
+            .field("triggers", &self.triggers)
+            .field("partitioning", &self.partitioning)
             .finish()
     }
 }
@@ -121,6 +326,8 @@ impl TableSchema {
             primary_keys,
             unique_constraints,
             foreign_keys,
+            triggers: Vec::new(),
+            partitioning: None,
             hash: None,
         };
         
@@ -130,6 +337,21 @@ impl TableSchema {
         schema
     }
     
+    /// Attach trigger definitions to the schema
+    pub fn with_triggers(mut self, triggers: Vec<TriggerDefinition>) -> Self {
+        self.triggers = triggers;
+        self.hash = Some(self.calculate_hash());
+        self
+    }
+    
+    /// Mark the table as partitioned with the given layout
+    pub fn with_partitioning(mut self, mut partitioning: PartitionScheme) -> Self {
+        partitioning.partitions.sort_by(|a, b| a.name.cmp(&b.name));
+        self.partitioning = Some(partitioning);
+        self.hash = Some(self.calculate_hash());
+        self
+    }
+    
     /// Calculate the hash of the schema with domain separation
     pub fn calculate_hash(&self) -> [u8; 32] {
         // Create a temporary copy without the hash field for serialization
@@ -139,6 +361,8 @@ impl TableSchema {
             primary_keys: self.primary_keys.clone(),
             unique_constraints: self.unique_constraints.clone(),
             foreign_keys: self.foreign_keys.clone(),
+            triggers: self.triggers.clone(),
+            partitioning: self.partitioning.clone(),
             hash: None,
         };
         
@@ -167,6 +391,11 @@ impl TableSchema {
             .collect()
     }
     
+    /// Get the triggers that fire on an event
+    pub fn triggers_for(&self, event: TriggerEvent) -> impl Iterator<Item = &TriggerDefinition> {
+        self.triggers.iter().filter(move |t| t.fires_on(event))
+    }
+    
     /// Verify the hash of the schema
     pub fn verify_hash(&self) -> bool {
         match self.hash {
@@ -176,6 +405,18 @@ impl TableSchema {
     }
 }
 
+/// Kind of Merkle tree committing to a table's rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MerkleTreeMode {
+    /// Leaves are row hashes in sorted row id order; proofs are addressed by index
+    #[default]
+    Indexed,
+    
+    /// Leaves sit at the hash of their row id; proofs are addressed by key and
+    /// stay valid when other rows are inserted or deleted
+    Sparse,
+}
+
 /// State of a database table
 #[derive(Clone, Serialize, Deserialize)]
 pub struct TableState {
@@ -194,6 +435,22 @@ pub struct TableState {
     
     /// Number of rows in the table
     pub row_count: usize,
+    
+    /// Row IDs in Merkle leaf order (sorted), kept in sync with `merkle_tree`
+    #[serde(skip)]
+    leaf_ids: Vec<String>,
+    
+    /// Partition holding each row, keyed by row ID (partitioned tables only)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub row_partitions: HashMap<String, String>,
+    
+    /// Kind of Merkle tree the root hash is computed with
+    #[serde(default)]
+    pub tree_mode: MerkleTreeMode,
+    
+    /// Sparse Merkle tree of the rows, used instead of `merkle_tree` in sparse mode
+    #[serde(skip)]
+    sparse_tree: Option<SparseMerkleTree>,
 }
 
 impl Debug for TableState {
@@ -215,16 +472,53 @@ impl TableState {
             merkle_tree: None,
             root_hash: None,
             row_count: 0,
+            leaf_ids: Vec::new(),
+            row_partitions: HashMap::new(),
+            tree_mode: MerkleTreeMode::Indexed,
+            sparse_tree: None,
         }
     }
     
+    /// Compute the root hash with a sparse Merkle tree keyed by row id
+    ///
+    /// Inserting, updating or deleting a row only rehashes that row's path,
+    /// and proofs from `generate_sparse_proof` remain valid for rows that
+    /// were not touched. The root differs from the indexed tree's root.
+    pub fn with_sparse_tree(mut self) -> Self {
+        self.tree_mode = MerkleTreeMode::Sparse;
+        self.rebuild_merkle_tree();
+        self
+    }
+    
     /// Get a row by ID
     pub fn get_row(&self, id: &str) -> Option<&Row> {
         self.rows.get(id)
     }
     
+    /// Partition holding a row, for partitioned tables
+    pub fn partition_of(&self, id: &str) -> Option<&str> {
+        self.row_partitions.get(id).map(String::as_str)
+    }
+    
+    /// Record which partition a row came from and attribute it to this table
+    ///
+    /// Rows read from a partition carry the partition's name; they are hashed
+    /// under the parent's name so the root doesn't depend on whether rows were
+    /// read through the parent or from each partition.
+    fn route_partition(&mut self, mut row: Row) -> Row {
+        let is_partition = self.schema.partitioning.as_ref()
+            .is_some_and(|p| p.has_partition(&row.table_name));
+        if is_partition {
+            let partition = std::mem::replace(&mut row.table_name, self.schema.name.clone());
+            self.row_partitions.insert(row.id.clone(), partition);
+            row.hash = Some(row.calculate_hash());
+        }
+        row
+    }
+    
     /// Insert a row
     pub fn insert_row(&mut self, row: Row) {
+        let row = self.route_partition(row);
         let id = row.id.clone();
         self.rows.insert(id, row);
         self.row_count = self.rows.len();
@@ -233,8 +527,22 @@ impl TableState {
         self.rebuild_merkle_tree();
     }
     
+    /// Insert many rows, rebuilding the Merkle tree once
+    ///
+    /// Produces the same state as calling `insert_row` for each row, without
+    /// rehashing the whole tree after every insert.
+    pub fn insert_rows(&mut self, rows: impl IntoIterator<Item = Row>) {
+        for row in rows {
+            let row = self.route_partition(row);
+            self.rows.insert(row.id.clone(), row);
+        }
+        self.row_count = self.rows.len();
+        self.rebuild_merkle_tree();
+    }
+    
     /// Update a row
     pub fn update_row(&mut self, row: Row) {
+        let row = self.route_partition(row);
         let id = row.id.clone();
         self.rows.insert(id, row);
         
@@ -245,6 +553,7 @@ impl TableState {
     /// Delete a row
     pub fn delete_row(&mut self, id: &str) -> Option<Row> {
         let row = self.rows.remove(id);
+        self.row_partitions.remove(id);
         if row.is_some() {
             self.row_count = self.rows.len();
             
@@ -256,6 +565,11 @@ impl TableState {
     
     /// Rebuild the Merkle tree for the table
     pub fn rebuild_merkle_tree(&mut self) {
+        if self.tree_mode == MerkleTreeMode::Sparse {
+            self.rebuild_sparse_tree();
+            return;
+        }
+        
         // Collect row hashes in a deterministic order (by ID)
         let mut row_ids: Vec<String> = self.rows.keys().cloned().collect();
         row_ids.sort();
@@ -276,6 +590,7 @@ impl TableState {
         if row_hashes.is_empty() {
             self.merkle_tree = None;
             self.root_hash = None;
+            self.leaf_ids = Vec::new();
             return;
         }
         
@@ -287,10 +602,163 @@ impl TableState {
         
         self.merkle_tree = Some(tree);
         self.root_hash = Some(root_hash);
+        self.leaf_ids = row_ids;
+    }
+    
+    /// Insert or replace a row, updating only the affected Merkle leaves
+    ///
+    /// Produces the same root as `rebuild_merkle_tree`. Replacing a row touches
+    /// one leaf; a new row shifts the leaves sorted after it, so appends are
+    /// cheapest. Falls back to a full rebuild when the tree height changes.
+    pub fn upsert_row_incremental(&mut self, row: Row) {
+        let row = self.route_partition(row);
+        if self.tree_mode == MerkleTreeMode::Sparse {
+            let id = row.id.clone();
+            self.rows.insert(id.clone(), row);
+            self.row_count = self.rows.len();
+            self.update_sparse_leaf(&id);
+            return;
+        }
+        
+        let in_sync = self.merkle_tree.is_some() && self.leaf_ids.len() == self.rows.len();
+        let id = row.id.clone();
+        let leaf_hash = row.hash();
+        self.rows.insert(id.clone(), row);
+        self.row_count = self.rows.len();
+        
+        if !in_sync {
+            self.rebuild_merkle_tree();
+            return;
+        }
+        
+        let position = match self.leaf_ids.binary_search(&id) {
+            Ok(position) => {
+                if let Some(tree) = self.merkle_tree.as_mut() {
+                    tree.update_leaf(position, &leaf_hash);
+                    self.root_hash = Some(tree.root_hash());
+                }
+                return;
+            }
+            Err(position) => position,
+        };
+        
+        self.leaf_ids.insert(position, id);
+        self.update_leaves_from(position);
+    }
+    
+    /// Delete a row, updating only the affected Merkle leaves
+    ///
+    /// Produces the same root as `rebuild_merkle_tree`; the leaves sorted after
+    /// the row shift down by one.
+    pub fn delete_row_incremental(&mut self, id: &str) -> Option<Row> {
+        self.row_partitions.remove(id);
+        if self.tree_mode == MerkleTreeMode::Sparse {
+            let row = self.rows.remove(id)?;
+            self.row_count = self.rows.len();
+            self.update_sparse_leaf(id);
+            return Some(row);
+        }
+        
+        let in_sync = self.merkle_tree.is_some() && self.leaf_ids.len() == self.rows.len();
+        let row = self.rows.remove(id)?;
+        self.row_count = self.rows.len();
+        
+        let position = match self.leaf_ids.binary_search_by(|leaf_id| leaf_id.as_str().cmp(id)) {
+            Ok(position) if in_sync => position,
+            _ => {
+                self.rebuild_merkle_tree();
+                return Some(row);
+            }
+        };
+        
+        self.leaf_ids.remove(position);
+        if self.leaf_ids.is_empty() {
+            self.rebuild_merkle_tree();
+            return Some(row);
+        }
+        
+        self.update_leaves_from(position);
+        Some(row)
+    }
+    
+    /// Rehash the leaves from `position` onwards after `leaf_ids` shifted
+    fn update_leaves_from(&mut self, position: usize) {
+        let height = tree_height(self.leaf_ids.len());
+        let tree = match self.merkle_tree.as_mut() {
+            Some(tree) if tree.height() == height => tree,
+            _ => {
+                self.rebuild_merkle_tree();
+                return;
+            }
+        };
+        
+        for (offset, id) in self.leaf_ids[position..].iter().enumerate() {
+            tree.update_leaf(position + offset, &self.rows[id].hash());
+        }
+        
+        // Drop the leaf left over from a delete
+        while tree.num_leaves() > self.leaf_ids.len() {
+            tree.remove_leaf(tree.num_leaves() - 1);
+        }
+        
+        self.root_hash = Some(tree.root_hash());
+    }
+    
+    /// Rebuild the sparse Merkle tree from all rows
+    fn rebuild_sparse_tree(&mut self) {
+        let mut tree = SparseMerkleTree::new();
+        for (id, row) in &self.rows {
+            tree.update(&SparseMerkleTree::key_for(id.as_bytes()), &row.hash());
+        }
+        
+        self.root_hash = if tree.is_empty() { None } else { Some(tree.root_hash()) };
+        self.sparse_tree = Some(tree);
+        self.merkle_tree = None;
+        self.leaf_ids = Vec::new();
+    }
+    
+    /// Set or clear the sparse leaf of one row to match `rows`
+    fn update_sparse_leaf(&mut self, id: &str) {
+        let tree = match self.sparse_tree.as_mut() {
+            Some(tree) => tree,
+            None => {
+                self.rebuild_sparse_tree();
+                return;
+            }
+        };
+        
+        let key = SparseMerkleTree::key_for(id.as_bytes());
+        match self.rows.get(id) {
+            Some(row) => tree.update(&key, &row.hash()),
+            None => {
+                tree.remove(&key);
+            }
+        }
+        
+        self.root_hash = if tree.is_empty() { None } else { Some(tree.root_hash()) };
+    }
+    
+    /// Generate a key-addressed proof for a row in sparse mode
+    ///
+    /// The proof verifies against `root_hash` with the row's hash as leaf
+    /// data. Returns `None` if the row does not exist or the table is not in
+    /// sparse mode.
+    pub fn generate_sparse_proof(&self, id: &str) -> Option<(Row, SparseMerkleProof)> {
+        let row = self.get_row(id)?;
+        let tree = self.sparse_tree.as_ref()?;
+        
+        let proof = tree.generate_proof(&SparseMerkleTree::key_for(id.as_bytes()));
+        Some((row.clone(), proof))
     }
     
     /// Generate a Merkle proof for a row
     pub fn generate_proof(&self, id: &str) -> Option<(Row, Vec<u8>)> {
+        if self.tree_mode == MerkleTreeMode::Sparse {
+            let (row, proof) = self.generate_sparse_proof(id)?;
+            let proof_bytes = bincode::serialize(&proof).ok()?;
+            return Some((row, proof_bytes));
+        }
+        
         // Get the row
         let row = self.get_row(id)?;
         
@@ -358,6 +826,11 @@ impl TableState {
     }
 }
 
+/// Height of the Merkle tree built for `leaves` rows
+fn tree_height(leaves: usize) -> usize {
+    (leaves as f64).log2().ceil() as usize
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -438,6 +911,33 @@ mod tests {
         assert_ne!(schema.hash.unwrap(), modified_schema.hash.unwrap());
     }
     
+    #[test]
+    fn test_trigger_definition() {
+        // ROW | AFTER | INSERT | UPDATE
+        let trigger = TriggerDefinition::from_pg_type(
+            "audit_users".to_string(),
+            1 | (1 << 2) | (1 << 4),
+            "log_user_change".to_string(),
+            "CREATE FUNCTION log_user_change() ...".to_string(),
+        );
+        assert_eq!(trigger.timing, TriggerTiming::After);
+        assert!(trigger.for_each_row);
+        assert!(trigger.fires_on(TriggerEvent::Insert));
+        assert!(trigger.fires_on(TriggerEvent::Update));
+        assert!(!trigger.fires_on(TriggerEvent::Delete));
+        assert_eq!(
+            trigger.create_statement("verification.users"),
+            "CREATE TRIGGER audit_users AFTER INSERT OR UPDATE ON verification.users FOR EACH ROW EXECUTE FUNCTION log_user_change()"
+        );
+
+        // Triggers are part of the schema hash
+        let schema = create_test_schema();
+        let with_trigger = schema.clone().with_triggers(vec![trigger]);
+        assert!(with_trigger.verify_hash());
+        assert_ne!(schema.hash, with_trigger.hash);
+        assert_eq!(with_trigger.triggers_for(TriggerEvent::Insert).count(), 1);
+    }
+
     #[test]
     fn test_table_state_operations() {
         let schema = create_test_schema();
@@ -493,6 +993,136 @@ mod tests {
         let proof = table_state.generate_proof("1");
         assert!(proof.is_some());
     }
+
+    #[test]
+    fn test_incremental_updates_match_rebuild() {
+        let mut table_state = TableState::new(create_test_schema());
+        table_state.rebuild_merkle_tree();
+
+        let assert_matches_rebuild = |state: &TableState| {
+            let mut rebuilt = state.clone();
+            rebuilt.rebuild_merkle_tree();
+            assert_eq!(state.root_hash, rebuilt.root_hash);
+        };
+
+        // Inserts out of order shift leaves and cross tree height boundaries
+        for id in [5, 1, 9, 3, 7, 2, 8, 4, 6] {
+            table_state.upsert_row_incremental(create_test_row(id, "user", &format!("user{}@example.com", id)));
+            assert_matches_rebuild(&table_state);
+        }
+
+        // Replacing a row only touches its own leaf
+        table_state.upsert_row_incremental(create_test_row(4, "renamed", "user4@example.com"));
+        assert_matches_rebuild(&table_state);
+
+        // Deletes from the middle, the end and down to empty
+        for id in ["3", "9", "1", "5", "2", "8", "4", "6", "7"] {
+            assert!(table_state.delete_row_incremental(id).is_some());
+            assert_matches_rebuild(&table_state);
+        }
+        assert!(table_state.root_hash.is_none());
+        assert!(table_state.delete_row_incremental("1").is_none());
+    }
+
+    #[test]
+    fn test_bulk_insert_matches_row_by_row() {
+        let rows: Vec<Row> = (1..=300)
+            .map(|id| create_test_row(id, "user", &format!("user{}@example.com", id)))
+            .collect();
+        
+        let mut one_by_one = TableState::new(create_test_schema());
+        for row in rows.iter().cloned() {
+            one_by_one.insert_row(row);
+        }
+        
+        let mut bulk = TableState::new(create_test_schema());
+        bulk.insert_rows(rows.into_iter().rev());
+        assert_eq!(bulk.row_count, 300);
+        assert_eq!(bulk.root_hash, one_by_one.root_hash);
+        
+        // A later batch adds to, and overwrites, existing rows
+        bulk.insert_rows([create_test_row(7, "renamed", "user7@example.com"), create_test_row(301, "new", "new@example.com")]);
+        one_by_one.insert_row(create_test_row(7, "renamed", "user7@example.com"));
+        one_by_one.insert_row(create_test_row(301, "new", "new@example.com"));
+        assert_eq!(bulk.row_count, 301);
+        assert_eq!(bulk.root_hash, one_by_one.root_hash);
+    }
+    
+    #[test]
+    fn test_sparse_proof_survives_inserts() {
+        let mut table_state = TableState::new(create_test_schema()).with_sparse_tree();
+        for id in [2, 4, 6] {
+            table_state.upsert_row_incremental(create_test_row(id, "user", &format!("user{}@example.com", id)));
+        }
+        
+        let (row, proof) = table_state.generate_sparse_proof("4").unwrap();
+        let root_before = table_state.root_hash.unwrap();
+        assert!(proof.verify(&root_before, Some(&row.hash())));
+        
+        // A row sorted before "4" would shift its index; here row 4's leaf
+        // stays at the same key and proves against the new root
+        table_state.upsert_row_incremental(create_test_row(3, "new", "user3@example.com"));
+        let root_after = table_state.root_hash.unwrap();
+        assert_ne!(root_before, root_after);
+        assert!(proof.verify(&root_before, Some(&row.hash())));
+        
+        let (_, fresh_proof) = table_state.generate_sparse_proof("4").unwrap();
+        assert_eq!(proof.key, fresh_proof.key);
+        assert!(fresh_proof.verify(&root_after, Some(&row.hash())));
+        
+        // Incremental updates match a full rebuild
+        let mut rebuilt = table_state.clone();
+        rebuilt.rebuild_merkle_tree();
+        assert_eq!(rebuilt.root_hash, table_state.root_hash);
+        
+        // Deleting the new row restores the original root, so the first proof verifies again
+        assert!(table_state.delete_row_incremental("3").is_some());
+        assert_eq!(table_state.root_hash, Some(root_before));
+        assert!(proof.verify(&table_state.root_hash.unwrap(), Some(&row.hash())));
+    }
+    
+    #[test]
+    fn test_range_partitioned_table_is_one_logical_table() {
+        let partitioning = PartitionScheme {
+            strategy: PartitionStrategy::from_pg_code("r").unwrap(),
+            key: "RANGE (id)".to_string(),
+            partitions: vec![
+                PartitionDefinition { name: "users_high".to_string(), bound: "FOR VALUES FROM (100) TO (MAXVALUE)".to_string() },
+                PartitionDefinition { name: "users_low".to_string(), bound: "FOR VALUES FROM (MINVALUE) TO (100)".to_string() },
+            ],
+        };
+        let schema = create_test_schema().with_partitioning(partitioning);
+        assert!(schema.verify_hash());
+        assert_ne!(schema.hash, create_test_schema().hash);
+        
+        // Rows captured from each partition
+        let mut from_partitions = TableState::new(schema.clone());
+        for (id, partition) in [(1, "users_low"), (150, "users_high"), (42, "users_low")] {
+            let mut row = create_test_row(id, "user", &format!("user{}@example.com", id));
+            row.table_name = partition.to_string();
+            from_partitions.insert_row(row);
+        }
+        assert_eq!(from_partitions.row_count, 3);
+        assert_eq!(from_partitions.partition_of("150"), Some("users_high"));
+        assert_eq!(from_partitions.partition_of("42"), Some("users_low"));
+        
+        // The same rows read through the parent give the same logical root
+        let mut through_parent = TableState::new(schema);
+        for id in [1, 150, 42] {
+            through_parent.insert_row(create_test_row(id, "user", &format!("user{}@example.com", id)));
+        }
+        assert_eq!(from_partitions.root_hash, through_parent.root_hash);
+        
+        // A row's proof is addressed by its ID alone and verifies against the logical root
+        let (row, proof_bytes) = from_partitions.generate_proof("150").unwrap();
+        assert_eq!(row.table_name, "users");
+        let proof: crate::merkle::SecureMerkleProof = bincode::deserialize(&proof_bytes).unwrap();
+        assert_eq!(proof.leaf_data, row.hash().to_vec());
+        assert!(proof.verify(&from_partitions.root_hash.unwrap()));
+        
+        from_partitions.delete_row("150");
+        assert_eq!(from_partitions.partition_of("150"), None);
+    }
     
     #[test]
     fn test_table_state_hash() {
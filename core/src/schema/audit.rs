@@ -0,0 +1,300 @@
+//! Schema migration audit log
+//!
+//! This module provides a tamper-evident log of applied schema migrations.
+//! Each entry references the checksum of the schema it migrated from and the
+//! hash of the previous entry, so the full schema history can be verified
+//! the same way as the block history.
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+use crate::crypto;
+use crate::error::CoreError;
+use crate::Result;
+use super::{SchemaVersion, SchemaMigration};
+
+/// Domain for hashing audit log entries
+const SCHEMA_AUDIT_DOMAIN: &str = "VERIFIABLEDB_SCHEMA_AUDIT";
+
+/// An applied schema migration in the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaAuditEntry {
+    /// Position of the entry in the log, starting at 0
+    pub sequence: u64,
+
+    /// Schema version produced by the migration
+    pub version: u32,
+
+    /// Identifier of the applied migration
+    pub migration_id: Uuid,
+
+    /// Name of the applied migration
+    pub migration_name: String,
+
+    /// SQL statements of the migration, in execution order
+    pub statements: Vec<String>,
+
+    /// Time the migration was recorded
+    pub recorded_at: DateTime<Utc>,
+
+    /// Checksum of the schema before the migration
+    pub previous_checksum: [u8; 32],
+
+    /// Checksum of the schema after the migration
+    pub checksum: [u8; 32],
+
+    /// Hash of the previous entry (zero for the first entry)
+    pub previous_entry_hash: [u8; 32],
+
+    /// Hash of this entry
+    pub entry_hash: [u8; 32],
+}
+
+impl SchemaAuditEntry {
+    /// Calculate the hash of the entry
+    pub fn calculate_hash(&self) -> [u8; 32] {
+        let statements = serde_json::to_string(&self.statements).unwrap_or_default();
+        crypto::secure_hash_multiple(
+            SCHEMA_AUDIT_DOMAIN,
+            &[
+                &self.previous_entry_hash,
+                &self.previous_checksum,
+                &self.checksum,
+                &self.sequence.to_be_bytes(),
+                &self.version.to_be_bytes(),
+                self.migration_id.as_bytes(),
+                &self.recorded_at.timestamp_millis().to_be_bytes(),
+                self.migration_name.as_bytes(),
+                statements.as_bytes(),
+            ],
+        )
+    }
+
+    /// Verify the hash of the entry
+    pub fn verify_hash(&self) -> bool {
+        self.calculate_hash() == self.entry_hash
+    }
+}
+
+/// Chained audit log of applied schema migrations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaAuditLog {
+    /// Version of the schema the log starts from
+    pub base_version: u32,
+
+    /// Checksum of the schema the log starts from
+    pub base_checksum: [u8; 32],
+
+    /// Recorded migrations, oldest first
+    pub entries: Vec<SchemaAuditEntry>,
+}
+
+impl SchemaAuditLog {
+    /// Create an empty audit log starting from a schema version
+    pub fn new(base: &SchemaVersion) -> Self {
+        SchemaAuditLog {
+            base_version: base.version,
+            base_checksum: base.checksum,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Checksum of the latest schema in the log
+    pub fn head_checksum(&self) -> [u8; 32] {
+        self.entries.last().map_or(self.base_checksum, |entry| entry.checksum)
+    }
+
+    /// Version of the latest schema in the log
+    pub fn head_version(&self) -> u32 {
+        self.entries.last().map_or(self.base_version, |entry| entry.version)
+    }
+
+    /// Hash of the latest entry (zero while the log is empty)
+    pub fn head_hash(&self) -> [u8; 32] {
+        self.entries.last().map_or([0; 32], |entry| entry.entry_hash)
+    }
+
+    /// Record a migration from `previous` to `next`
+    ///
+    /// `previous` must be the latest schema in the log and `next` the version
+    /// created from it by a migration.
+    pub fn record(&mut self, previous: &SchemaVersion, next: &SchemaVersion) -> Result<&SchemaAuditEntry> {
+        if !previous.verify_checksum() || previous.checksum != self.head_checksum() {
+            return Err(CoreError::SchemaValidationError(format!(
+                "Schema version {} is not the head of the audit log", previous.version
+            )));
+        }
+        if !next.verify_checksum() {
+            return Err(CoreError::SchemaValidationError(format!(
+                "Schema version {} has an invalid checksum", next.version
+            )));
+        }
+        if next.version != previous.version + 1 {
+            return Err(CoreError::SchemaValidationError(format!(
+                "Schema version {} does not follow version {}", next.version, previous.version
+            )));
+        }
+        let migration = next.migration.as_ref().ok_or_else(|| CoreError::SchemaValidationError(format!(
+            "Schema version {} was not created by a migration", next.version
+        )))?;
+
+        let mut entry = SchemaAuditEntry {
+            sequence: self.entries.len() as u64,
+            version: next.version,
+            migration_id: migration.id,
+            migration_name: migration.name.clone(),
+            statements: migration.get_sql(),
+            recorded_at: Utc::now(),
+            previous_checksum: previous.checksum,
+            checksum: next.checksum,
+            previous_entry_hash: self.head_hash(),
+            entry_hash: [0; 32],
+        };
+        entry.entry_hash = entry.calculate_hash();
+
+        self.entries.push(entry);
+        Ok(self.entries.last().expect("entry was just recorded"))
+    }
+
+    /// Apply a migration to the latest schema and record it
+    pub fn apply_migration(
+        &mut self,
+        current: &SchemaVersion,
+        migration: SchemaMigration,
+        created_by: String,
+        description: String,
+    ) -> Result<SchemaVersion> {
+        let next = SchemaVersion::create_from_migration(current, migration, created_by, description);
+        self.record(current, &next)?;
+        Ok(next)
+    }
+
+    /// Verify that every entry is intact and linked to the one before it
+    pub fn verify(&self) -> Result<()> {
+        let mut previous_checksum = self.base_checksum;
+        let mut previous_version = self.base_version;
+        let mut previous_entry_hash = [0; 32];
+
+        for (sequence, entry) in self.entries.iter().enumerate() {
+            if entry.sequence != sequence as u64 || !entry.verify_hash() {
+                return Err(CoreError::SchemaValidationError(format!(
+                    "Schema audit entry {} has been tampered with", sequence
+                )));
+            }
+            if entry.previous_entry_hash != previous_entry_hash
+                || entry.previous_checksum != previous_checksum
+                || entry.version != previous_version + 1
+            {
+                return Err(CoreError::SchemaValidationError(format!(
+                    "Schema audit entry {} does not follow the previous schema", sequence
+                )));
+            }
+
+            previous_checksum = entry.checksum;
+            previous_version = entry.version;
+            previous_entry_hash = entry.entry_hash;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::models::{ColumnDefinition, ColumnType};
+    use crate::schema::{DdlStatement, MigrationDirection, MigrationOperation, ColumnDefinitionDdl};
+    use crate::schema::TableDefinition;
+
+    fn column(name: &str, primary_key: bool) -> ColumnDefinition {
+        ColumnDefinition {
+            name: name.to_string(),
+            column_type: ColumnType::Integer,
+            nullable: !primary_key,
+            primary_key,
+            unique: primary_key,
+            default_value: None,
+        }
+    }
+
+    fn migration(name: &str, statement: DdlStatement) -> SchemaMigration {
+        SchemaMigration::new(
+            name.to_string(),
+            MigrationDirection::Up,
+            vec![MigrationOperation { order: 1, statement }],
+        )
+    }
+
+    #[test]
+    fn test_migrations_produce_chained_audit_log() {
+        let initial = SchemaVersion::create_initial(
+            "test_user".to_string(),
+            "Initial schema".to_string(),
+            HashMap::new(),
+        );
+        let mut log = SchemaAuditLog::new(&initial);
+
+        let create_users = DdlStatement::create_table(
+            TableDefinition {
+                name: "users".to_string(),
+                columns: vec![column("id", true)],
+                primary_keys: vec!["id".to_string()],
+                unique_constraints: Vec::new(),
+                foreign_keys: Vec::new(),
+            },
+            "CREATE TABLE users (id INT PRIMARY KEY)".to_string(),
+        );
+        let v2 = log.apply_migration(
+            &initial,
+            migration("create_users", create_users),
+            "test_user".to_string(),
+            "Create users".to_string(),
+        ).unwrap();
+
+        let add_age = DdlStatement::alter_table(
+            "users".to_string(),
+            vec![ColumnDefinitionDdl::AddColumn(column("age", false))],
+            "ALTER TABLE users ADD COLUMN age INT".to_string(),
+        );
+        let v3 = log.apply_migration(
+            &v2,
+            migration("add_age", add_age),
+            "test_user".to_string(),
+            "Add age".to_string(),
+        ).unwrap();
+
+        // Each entry links the schema checksums and the previous entry
+        assert_eq!(log.entries.len(), 2);
+        assert_eq!(log.entries[0].previous_checksum, initial.checksum);
+        assert_eq!(log.entries[0].checksum, v2.checksum);
+        assert_eq!(log.entries[0].previous_entry_hash, [0; 32]);
+        assert_eq!(log.entries[1].previous_checksum, v2.checksum);
+        assert_eq!(log.entries[1].checksum, v3.checksum);
+        assert_eq!(log.entries[1].previous_entry_hash, log.entries[0].entry_hash);
+        assert_eq!(log.entries[1].statements, vec!["ALTER TABLE users ADD COLUMN age INT".to_string()]);
+        assert_eq!(log.head_checksum(), v3.checksum);
+        assert_eq!(log.head_version(), 3);
+        assert!(log.verify().is_ok());
+
+        // Migrating from a schema other than the head is rejected
+        let drop_users = DdlStatement::drop_table("users".to_string(), "DROP TABLE users".to_string());
+        assert!(log.apply_migration(
+            &v2,
+            migration("drop_users", drop_users),
+            "test_user".to_string(),
+            "Drop users".to_string(),
+        ).is_err());
+        assert_eq!(log.entries.len(), 2);
+
+        // Rewriting a recorded statement breaks the chain
+        let mut tampered = log.clone();
+        tampered.entries[0].statements = vec!["CREATE TABLE users (id BIGINT PRIMARY KEY)".to_string()];
+        assert!(tampered.verify().is_err());
+
+        // So does recomputing the tampered entry's hash without relinking the next one
+        tampered.entries[0].entry_hash = tampered.entries[0].calculate_hash();
+        assert!(tampered.verify().is_err());
+    }
+}
@@ -1,15 +1,17 @@
 //! Database schema validation
 //!
-//! This module provides utilities for validating database schemas
-//! and tracking schema migrations.
+//! This module provides utilities for validating database schemas,
+//! tracking schema migrations and auditing their history.
 
 mod validator;
 mod migration;
 mod ddl;
+mod audit;
 
 pub use validator::{SchemaValidator, ValidationResult, ValidationError};
 pub use migration::{SchemaMigration, MigrationDirection, MigrationOperation};
-pub use ddl::{DdlStatement, DdlOperation, ColumnDefinitionDdl};
+pub use ddl::{DdlStatement, DdlOperation, ColumnDefinitionDdl, TableDefinition};
+pub use audit::{SchemaAuditLog, SchemaAuditEntry};
 
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
@@ -209,7 +211,7 @@ impl SchemaVersion {
                             new_table.primary_keys.clone(),
                             new_table.unique_constraints.clone(),
                             new_table.foreign_keys.clone(),
-                        );
+                        ).with_triggers(new_table.triggers.clone());
                         new_tables.insert(new_name.clone(), new_table_schema);
                     }
                 }
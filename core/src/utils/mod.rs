@@ -6,13 +6,14 @@ pub mod resource;
 pub mod string;
 pub mod timer;
 
-pub use resource::ResourceLimiter;
+pub use resource::{ResourceGuard, ResourceLimiter};
 pub use string::StringUtils;
-pub use timer::Timer;
+pub use timer::{LatencyStats, Timer};
 
 use std::time::{Duration, Instant};
 use uuid::Uuid;
 use log::info;
+use rand::Rng;
 
 /// Generate a UUID v4
 pub fn generate_uuid() -> Uuid {
@@ -39,30 +40,120 @@ where
     result
 }
 
+/// Randomization of retry delays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Jitter {
+    /// Exact exponential delays
+    #[default]
+    None,
+    
+    /// Uniform between zero and the exponential delay
+    Full,
+    
+    /// Uniform between the initial backoff and three times the previous delay
+    Decorrelated,
+}
+
+/// Retry schedule for [`retry_with_backoff_config`]
+///
+/// Jitter spreads out the retries of many clients that failed together, so
+/// they don't hit a recovering backend in lockstep.
+#[derive(Debug, Clone)]
+pub struct BackoffConfig {
+    /// Retries after the first attempt
+    pub max_retries: usize,
+    
+    /// Delay before the first retry, doubled for each further retry
+    pub initial_backoff: Duration,
+    
+    /// Upper bound on any delay
+    pub max_backoff: Duration,
+    
+    /// Randomization applied to the delays
+    pub jitter: Jitter,
+}
+
+impl BackoffConfig {
+    /// Create a schedule of uncapped, unjittered exponential delays
+    pub fn new(max_retries: usize, initial_backoff: Duration) -> Self {
+        BackoffConfig {
+            max_retries,
+            initial_backoff,
+            max_backoff: Duration::MAX,
+            jitter: Jitter::None,
+        }
+    }
+    
+    /// Cap every delay at `max_backoff`
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+    
+    /// Randomize the delays
+    pub fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+    
+    /// Delay before retry number `retry` (0 for the first retry), given the previous delay
+    pub fn next_delay(&self, retry: usize, previous: Option<Duration>) -> Duration {
+        let exponential = u32::try_from(retry)
+            .ok()
+            .and_then(|retry| 2u32.checked_pow(retry))
+            .and_then(|factor| self.initial_backoff.checked_mul(factor))
+            .unwrap_or(if self.initial_backoff.is_zero() { Duration::ZERO } else { Duration::MAX })
+            .min(self.max_backoff);
+        
+        match self.jitter {
+            Jitter::None => exponential,
+            Jitter::Full => rand::thread_rng().gen_range(Duration::ZERO..=exponential),
+            Jitter::Decorrelated => {
+                let low = self.initial_backoff.min(self.max_backoff);
+                let high = previous.unwrap_or(low).saturating_mul(3).min(self.max_backoff).max(low);
+                rand::thread_rng().gen_range(low..=high)
+            }
+        }
+    }
+}
+
 /// Retry a fallible operation with exponential backoff
 pub async fn retry_with_backoff<F, Fut, T, E>(
     operation: F,
     max_retries: usize,
     initial_backoff: Duration,
 ) -> Result<T, E>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+{
+    retry_with_backoff_config(operation, &BackoffConfig::new(max_retries, initial_backoff)).await
+}
+
+/// Retry a fallible operation on the given backoff schedule
+pub async fn retry_with_backoff_config<F, Fut, T, E>(
+    operation: F,
+    config: &BackoffConfig,
+) -> Result<T, E>
 where
     F: Fn() -> Fut,
     Fut: std::future::Future<Output = Result<T, E>>,
 {
     let mut retries = 0;
-    let mut backoff = initial_backoff;
+    let mut previous = None;
     
     loop {
         match operation().await {
             Ok(value) => return Ok(value),
             Err(err) => {
-                if retries >= max_retries {
+                if retries >= config.max_retries {
                     return Err(err);
                 }
                 
+                let delay = config.next_delay(retries, previous);
+                previous = Some(delay);
                 retries += 1;
-                tokio::time::sleep(backoff).await;
-                backoff *= 2;
+                tokio::time::sleep(delay).await;
             }
         }
     }
@@ -78,6 +169,88 @@ pub fn hex_to_bytes(hex: &str) -> Result<Vec<u8>, hex::FromHexError> {
     hex::decode(hex)
 }
 
+/// Serialize a JSON value canonically
+///
+/// Object keys are sorted, there is no whitespace, and numbers are written
+/// in one form per value: integral numbers as plain integers (so `1.0` and
+/// `1` are the same), others in shortest round-trip form. Semantically
+/// equal values therefore give the same bytes on every node, which
+/// `Value::to_string` doesn't promise.
+pub fn canonical_json_bytes(value: &serde_json::Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_canonical_json(value, &mut bytes);
+    bytes
+}
+
+/// Append the canonical serialization of a JSON value
+fn write_canonical_json(value: &serde_json::Value, bytes: &mut Vec<u8>) {
+    match value {
+        serde_json::Value::Null => bytes.extend_from_slice(b"null"),
+        serde_json::Value::Bool(true) => bytes.extend_from_slice(b"true"),
+        serde_json::Value::Bool(false) => bytes.extend_from_slice(b"false"),
+        serde_json::Value::Number(number) => bytes.extend_from_slice(canonical_json_number(number).as_bytes()),
+        serde_json::Value::String(string) => write_json_string(string, bytes),
+        serde_json::Value::Array(elements) => {
+            bytes.push(b'[');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    bytes.push(b',');
+                }
+                write_canonical_json(element, bytes);
+            }
+            bytes.push(b']');
+        }
+        serde_json::Value::Object(fields) => {
+            let mut sorted: Vec<(&String, &serde_json::Value)> = fields.iter().collect();
+            sorted.sort_by_key(|(key, _)| *key);
+            
+            bytes.push(b'{');
+            for (i, (key, value)) in sorted.into_iter().enumerate() {
+                if i > 0 {
+                    bytes.push(b',');
+                }
+                write_json_string(key, bytes);
+                bytes.push(b':');
+                write_canonical_json(value, bytes);
+            }
+            bytes.push(b'}');
+        }
+    }
+}
+
+/// Append a JSON string literal with serde_json's escaping
+fn write_json_string(string: &str, bytes: &mut Vec<u8>) {
+    let literal = serde_json::to_string(string).expect("strings always serialize");
+    bytes.extend_from_slice(literal.as_bytes());
+}
+
+/// Write a JSON number in its canonical form
+fn canonical_json_number(number: &serde_json::Number) -> String {
+    if let Some(integer) = number.as_i64() {
+        return integer.to_string();
+    }
+    if let Some(integer) = number.as_u64() {
+        return integer.to_string();
+    }
+    
+    // Integral floats in i64 range are written as integers, including -0.0
+    match number.as_f64() {
+        Some(float) if float.fract() == 0.0 && float.abs() < i64::MAX as f64 => (float as i64).to_string(),
+        _ => number.to_string(),
+    }
+}
+
+/// Compare the bytes two hex strings encode in constant time
+///
+/// Use this rather than `==` on decoded hashes such as state roots, so the
+/// time taken doesn't reveal how much of a forged value was right. Hex case
+/// doesn't matter.
+pub fn hex_eq_constant_time(a_hex: &str, b_hex: &str) -> Result<bool, hex::FromHexError> {
+    let a = hex_to_bytes(a_hex)?;
+    let b = hex_to_bytes(b_hex)?;
+    Ok(crate::crypto::verify_bytes(&a, &b))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -188,6 +361,66 @@ mod tests {
         assert_eq!(counter3.load(Ordering::SeqCst), 4); // Initial attempt + 3 retries
     }
     
+    #[test]
+    fn test_backoff_delays_stay_in_bounds() {
+        let initial = Duration::from_millis(10);
+        let cap = Duration::from_millis(100);
+        
+        // Without jitter, delays double up to the cap
+        let exact = BackoffConfig::new(6, initial).with_max_backoff(cap);
+        let delays: Vec<u64> = (0..6).map(|retry| exact.next_delay(retry, None).as_millis() as u64).collect();
+        assert_eq!(delays, vec![10, 20, 40, 80, 100, 100]);
+        
+        // Full jitter stays between zero and the exponential delay
+        let full = exact.clone().with_jitter(Jitter::Full);
+        for _ in 0..100 {
+            for retry in 0..6 {
+                let bound = exact.next_delay(retry, None);
+                assert!(full.next_delay(retry, None) <= bound);
+            }
+        }
+        
+        // Decorrelated jitter stays between the initial delay and three times the previous one
+        let decorrelated = exact.clone().with_jitter(Jitter::Decorrelated);
+        for _ in 0..100 {
+            let mut previous = None;
+            for retry in 0..6 {
+                let delay = decorrelated.next_delay(retry, previous);
+                assert!(delay >= initial && delay <= cap);
+                assert!(delay <= previous.unwrap_or(initial) * 3);
+                previous = Some(delay);
+            }
+        }
+        
+        // Huge retry counts saturate instead of overflowing
+        assert_eq!(exact.next_delay(usize::MAX, None), cap);
+        assert_eq!(BackoffConfig::new(100, initial).next_delay(99, None), Duration::MAX);
+    }
+    
+    #[tokio::test]
+    async fn test_jittered_retries_respect_max_retries() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        
+        for jitter in [Jitter::Full, Jitter::Decorrelated] {
+            let attempts = AtomicU32::new(0);
+            let config = BackoffConfig::new(3, Duration::from_millis(1))
+                .with_max_backoff(Duration::from_millis(4))
+                .with_jitter(jitter);
+            
+            let result: Result<(), &'static str> = retry_with_backoff_config(
+                || {
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    async { Err("always fails") }
+                },
+                &config,
+            )
+            .await;
+            
+            assert_eq!(result, Err("always fails"));
+            assert_eq!(attempts.load(Ordering::SeqCst), 4); // Initial attempt + 3 retries
+        }
+    }
+    
     #[test]
     fn test_hex_conversion() {
         let bytes = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0];
@@ -201,4 +434,46 @@ mod tests {
         // Test invalid hex
         assert!(hex_to_bytes("invalid").is_err());
     }
+    
+    #[test]
+    fn test_canonical_json_bytes() {
+        let canonical = |json: &str| canonical_json_bytes(&serde_json::from_str(json).unwrap());
+        
+        // Key order and whitespace don't matter, at any depth
+        assert_eq!(
+            canonical(r#"{"b": 2, "a": {"y": [1, 2], "x": null}}"#),
+            br#"{"a":{"x":null,"y":[1,2]},"b":2}"#.to_vec()
+        );
+        assert_eq!(canonical(r#"{"a":1,"b":2}"#), canonical(r#"{ "b":2, "a":1 }"#));
+        
+        // Integral numbers have one form, other numbers keep their value
+        assert_eq!(canonical("1.0"), canonical("1"));
+        assert_eq!(canonical("-0.0"), canonical("0"));
+        assert_eq!(canonical("1e3"), canonical("1000"));
+        assert_eq!(canonical("1.5"), b"1.5".to_vec());
+        assert_ne!(canonical("1.5"), canonical("1"));
+        
+        // Array order and string contents are kept
+        assert_ne!(canonical("[1, 2]"), canonical("[2, 1]"));
+        assert_eq!(canonical(r#""a\"b\u00e9""#), "\"a\\\"bé\"".as_bytes().to_vec());
+        assert_ne!(canonical(r#""1""#), canonical("1"));
+    }
+    
+    #[test]
+    fn test_hex_eq_constant_time() {
+        let root = bytes_to_hex(&crate::crypto::secure_hash("TEST", b"state"));
+        let other = bytes_to_hex(&crate::crypto::secure_hash("TEST", b"other state"));
+        
+        // Equal bytes match regardless of hex case
+        assert_eq!(hex_eq_constant_time(&root, &root), Ok(true));
+        assert_eq!(hex_eq_constant_time(&root, &root.to_uppercase()), Ok(true));
+        
+        // Different bytes or lengths don't
+        assert_eq!(hex_eq_constant_time(&root, &other), Ok(false));
+        assert_eq!(hex_eq_constant_time(&root, &root[..62]), Ok(false));
+        
+        // Invalid hex on either side is an error
+        assert!(hex_eq_constant_time("invalid", &root).is_err());
+        assert!(hex_eq_constant_time(&root, "abc").is_err());
+    }
 } 
\ No newline at end of file
@@ -9,6 +9,7 @@ use std::time::{Duration, Instant};
 use tokio::sync::Semaphore;
 use std::future::Future;
 use tokio::time::timeout;
+use crate::error::CoreError;
 
 /// Resource limit configuration
 #[derive(Debug, Clone)]
@@ -141,6 +142,35 @@ impl ResourceLimiter {
         }
     }
     
+    /// Reserve bytes from the memory budget until the returned guard is dropped
+    ///
+    /// Unlike `allocate_memory`, a failed reservation never touches the
+    /// counter, so concurrent callers can't see a transient overshoot.
+    pub fn try_reserve(&self, bytes: u64) -> Result<ResourceGuard, CoreError> {
+        if !self.active {
+            return Ok(ResourceGuard {
+                memory_usage: self.memory_usage.clone(),
+                bytes: 0,
+            });
+        }
+        
+        let limit = self.limits.memory_limit;
+        self.memory_usage
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |current| {
+                current.checked_add(bytes).filter(|&usage| usage <= limit)
+            })
+            .map_err(|current| {
+                CoreError::ResourceLimitExceeded(
+                    ResourceError::MemoryLimitExceeded(current.saturating_add(bytes), limit).to_string(),
+                )
+            })?;
+        
+        Ok(ResourceGuard {
+            memory_usage: self.memory_usage.clone(),
+            bytes,
+        })
+    }
+    
     /// Free memory
     pub fn free_memory(&self, bytes: u64) {
         if !self.active {
@@ -230,6 +260,32 @@ impl ResourceLimiter {
     }
 }
 
+/// Reservation against a limiter's memory budget, released on drop
+#[derive(Debug)]
+pub struct ResourceGuard {
+    /// Shared memory usage counter of the limiter
+    memory_usage: Arc<AtomicU64>,
+    
+    /// Reserved bytes
+    bytes: u64,
+}
+
+impl ResourceGuard {
+    /// Get the number of reserved bytes
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+impl Drop for ResourceGuard {
+    fn drop(&mut self) {
+        // Saturate in case the limiter was reset while the guard was alive
+        let _ = self.memory_usage.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |current| {
+            Some(current.saturating_sub(self.bytes))
+        });
+    }
+}
+
 /// Memory tracking wrapper for data structures
 pub struct MemoryTracked<T: Clone> {
     /// The wrapped value
@@ -399,6 +455,61 @@ mod tests {
         assert_eq!(limiter.get_memory_usage(), 0);
     }
     
+    #[test]
+    fn test_try_reserve() {
+        let limits = ResourceLimits {
+            memory_limit: 1000,
+            ..ResourceLimits::default()
+        };
+        let limiter = ResourceLimiter::new(limits);
+        
+        let guard = limiter.try_reserve(600).unwrap();
+        assert_eq!(guard.bytes(), 600);
+        assert_eq!(limiter.get_memory_usage(), 600);
+        
+        // Over budget, and the failed reservation leaves the counter untouched
+        assert!(matches!(limiter.try_reserve(500), Err(CoreError::ResourceLimitExceeded(_))));
+        assert_eq!(limiter.get_memory_usage(), 600);
+        
+        drop(guard);
+        assert_eq!(limiter.get_memory_usage(), 0);
+        assert!(limiter.try_reserve(1000).is_ok());
+        assert!(limiter.try_reserve(u64::MAX).is_err());
+    }
+    
+    #[test]
+    fn test_try_reserve_concurrent() {
+        const THREADS: u64 = 8;
+        const ROUNDS: usize = 1000;
+        
+        let limits = ResourceLimits {
+            memory_limit: 100 * (THREADS / 2), // Room for half the threads at once
+            ..ResourceLimits::default()
+        };
+        let limiter = Arc::new(ResourceLimiter::new(limits));
+        
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let limiter = limiter.clone();
+                std::thread::spawn(move || {
+                    let mut granted = 0;
+                    for _ in 0..ROUNDS {
+                        if let Ok(guard) = limiter.try_reserve(100) {
+                            assert!(limiter.get_memory_usage() <= 100 * (THREADS / 2));
+                            granted += 1;
+                            drop(guard);
+                        }
+                    }
+                    granted
+                })
+            })
+            .collect();
+        
+        let granted: usize = handles.into_iter().map(|handle| handle.join().unwrap()).sum();
+        assert!(granted > 0);
+        assert_eq!(limiter.get_memory_usage(), 0);
+    }
+    
     #[tokio::test]
     async fn test_with_time_limit() {
         let mut limits = ResourceLimits::default();
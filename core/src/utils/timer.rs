@@ -2,9 +2,85 @@
 //!
 //! This module provides utilities for measuring execution time and managing timeouts.
 
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use log::{debug, info, warn};
+use rand::Rng;
+
+/// Maximum number of durations kept per accumulator for percentile estimates
+const RESERVOIR_SIZE: usize = 1024;
+
+/// Latency summary of a named accumulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Number of recorded durations
+    pub count: u64,
+    
+    /// Shortest recorded duration
+    pub min: Duration,
+    
+    /// Longest recorded duration
+    pub max: Duration,
+    
+    /// Median, estimated from the reservoir sample
+    pub p50: Duration,
+    
+    /// 95th percentile, estimated from the reservoir sample
+    pub p95: Duration,
+}
+
+/// Bounded uniform sample of recorded durations
+#[derive(Debug, Default)]
+struct Reservoir {
+    count: u64,
+    min: Duration,
+    max: Duration,
+    sample: Vec<Duration>,
+}
+
+impl Reservoir {
+    fn record(&mut self, duration: Duration) {
+        if self.count == 0 {
+            self.min = duration;
+            self.max = duration;
+        } else {
+            self.min = self.min.min(duration);
+            self.max = self.max.max(duration);
+        }
+        self.count += 1;
+        
+        // Algorithm R: the n-th duration replaces a random slot with probability size/n
+        if self.sample.len() < RESERVOIR_SIZE {
+            self.sample.push(duration);
+        } else {
+            let slot = rand::thread_rng().gen_range(0..self.count);
+            if let Some(entry) = usize::try_from(slot).ok().and_then(|slot| self.sample.get_mut(slot)) {
+                *entry = duration;
+            }
+        }
+    }
+    
+    fn stats(&self) -> LatencyStats {
+        let mut sorted = self.sample.clone();
+        sorted.sort_unstable();
+        
+        // Nearest-rank percentile
+        let percentile = |p: usize| {
+            let rank = (p * sorted.len()).div_ceil(100).max(1);
+            sorted[rank - 1]
+        };
+        
+        LatencyStats {
+            count: self.count,
+            min: self.min,
+            max: self.max,
+            p50: percentile(50),
+            p95: percentile(95),
+        }
+    }
+}
 
 /// Timer for measuring execution time
 #[derive(Debug, Clone)]
@@ -23,6 +99,9 @@ pub struct Timer {
     
     /// Whether to log automatically on drop
     log_on_drop: bool,
+    
+    /// Named latency accumulators, shared between clones
+    accumulators: Arc<Mutex<HashMap<String, Reservoir>>>,
 }
 
 impl Timer {
@@ -34,9 +113,17 @@ impl Timer {
             warning_threshold: None,
             error_threshold: None,
             log_on_drop: true,
+            accumulators: Arc::new(Mutex::new(HashMap::new())),
         }
     }
     
+    /// Create a timer with the given name that shares this timer's accumulators
+    pub fn child(&self, name: impl Into<String>) -> Self {
+        let mut timer = Timer::new(name).without_auto_log();
+        timer.accumulators = self.accumulators.clone();
+        timer
+    }
+    
     /// Set a warning threshold for the timer
     pub fn with_warning_threshold(mut self, threshold: Duration) -> Self {
         self.warning_threshold = Some(threshold);
@@ -148,6 +235,28 @@ impl Timer {
         self.reset();
         elapsed
     }
+    
+    /// Add a duration to the named accumulator
+    pub fn record(&self, name: impl Into<String>, duration: Duration) {
+        let mut accumulators = self.accumulators.lock().unwrap_or_else(|e| e.into_inner());
+        accumulators.entry(name.into()).or_default().record(duration);
+    }
+    
+    /// Add the elapsed time to the accumulator named after this timer
+    pub fn record_elapsed(&self) -> Duration {
+        let elapsed = self.elapsed();
+        self.record(self.name.clone(), elapsed);
+        elapsed
+    }
+    
+    /// Get latency statistics for every accumulator
+    pub fn snapshot(&self) -> HashMap<String, LatencyStats> {
+        let accumulators = self.accumulators.lock().unwrap_or_else(|e| e.into_inner());
+        accumulators
+            .iter()
+            .map(|(name, reservoir)| (name.clone(), reservoir.stats()))
+            .collect()
+    }
 }
 
 impl Display for Timer {
@@ -392,6 +501,42 @@ mod tests {
         assert!(timer.elapsed() < Duration::from_millis(10));
     }
     
+    #[test]
+    fn test_timer_latency_stats() {
+        let timer = Timer::new("test_timer").without_auto_log();
+        
+        // Fits in the reservoir, so percentiles are exact
+        for ms in 1..=1000 {
+            timer.record("exact", Duration::from_millis(ms));
+        }
+        
+        // Overflows the reservoir, so percentiles are sampled
+        let child = timer.child("child");
+        for ms in 1..=10_000 {
+            child.record("sampled", Duration::from_millis(ms));
+        }
+        
+        let snapshot = timer.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        
+        let exact = snapshot["exact"];
+        assert_eq!(exact.count, 1000);
+        assert_eq!(exact.min, Duration::from_millis(1));
+        assert_eq!(exact.max, Duration::from_millis(1000));
+        assert_eq!(exact.p50, Duration::from_millis(500));
+        assert_eq!(exact.p95, Duration::from_millis(950));
+        
+        let sampled = snapshot["sampled"];
+        assert_eq!(sampled.count, 10_000);
+        assert_eq!(sampled.min, Duration::from_millis(1));
+        assert_eq!(sampled.max, Duration::from_millis(10_000));
+        assert!(sampled.p50 >= Duration::from_millis(4000) && sampled.p50 <= Duration::from_millis(6000));
+        assert!(sampled.p95 >= Duration::from_millis(9000) && sampled.p95 <= Duration::from_millis(10_000));
+        
+        child.record_elapsed();
+        assert_eq!(timer.snapshot()["child"].count, 1);
+    }
+    
     #[tokio::test]
     async fn test_async_timer() {
         let timer = AsyncTimer::new("test_async_timer").without_auto_log();
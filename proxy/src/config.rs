@@ -9,6 +9,8 @@ use crate::interception::rewrite::RewriterConfig;
 use crate::interception::execution::ExecutorConfig;
 use crate::interception::verification::VerificationConfig;
 use crate::security::RateLimiterConfig;
+use crate::security::NetworkAccessConfig;
+use crate::server::pool::BackendPoolConfig;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -43,6 +45,12 @@ pub struct ProxyConfig {
     /// Rate limiter configuration
     pub rate_limiter_config: RateLimiterConfig,
     
+    /// Client networks the proxy accepts connections from
+    pub network_access_config: NetworkAccessConfig,
+    
+    /// Backend connection pool configuration
+    pub pool_config: BackendPoolConfig,
+    
     /// Maximum query length in bytes
     pub max_query_length: usize,
     
@@ -58,6 +66,10 @@ pub struct ProxyConfig {
     /// Maximum number of connections
     pub max_connections: usize,
     
+    /// Seconds open transactions may take to finish when the server stops,
+    /// before their sessions are closed forcibly
+    pub shutdown_timeout: u64,
+    
     /// Whether to log queries
     pub log_queries: bool,
     
@@ -120,11 +132,14 @@ impl Default for ProxyConfig {
             executor_config: ExecutorConfig::default(),
             verification_config: VerificationConfig::default(),
             rate_limiter_config: RateLimiterConfig::default(),
+            network_access_config: NetworkAccessConfig::default(),
+            pool_config: BackendPoolConfig::default(),
             max_query_length: 8192,
             transaction_boundary_protection: true,
             rate_limit: 0,
             connection_timeout: 30,
             max_connections: 100,
+            shutdown_timeout: 30,
             log_queries: true,
             log_level: "info".to_string(),
             log_file: None,
@@ -174,6 +189,7 @@ impl ProxyConfig {
                 rate_limit: 1000, // 1000 requests per minute
                 allow_list: vec!["127.0.0.1".parse().unwrap()], // Allow localhost
                 block_list: vec![],
+                ..Default::default()
             },
             ..Default::default()
         }
@@ -14,16 +14,178 @@
 
 use crate::error::{ProxyError, Result};
 use log::{debug, warn, info};
-use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::ops::ControlFlow;
+use std::sync::{Arc, Mutex};
 use sqlparser::ast::{
-    self, Statement, TableWithJoins, TableFactor, ObjectName, Value, GroupByExpr,
-    SetExpr, Query, Select, Expr, Join, TableAlias, 
-    Cte, JoinOperator, JoinConstraint, OrderByExpr, Offset, Fetch, LockType
+    self, visit_expressions, Visit, Visitor, Statement, TableWithJoins, TableFactor, ObjectName, Value, GroupByExpr,
+    SetExpr, Query, Select, Expr, Ident, Join, TableAlias, 
+    Cte, JoinOperator, JoinConstraint, OrderByExpr, Offset, Fetch, LockType, LockClause, NonBlock,
+    SelectItem, SetOperator, SetQuantifier, FunctionArg, FunctionArgExpr, OnInsert, OnConflict, OnConflictAction, FromTable,
+    CopySource
 };
 use sqlparser::dialect::PostgreSqlDialect;
 use sqlparser::parser::{Parser, ParserError};
-use crate::interception::rewrite::NON_DETERMINISTIC_FUNCTIONS;
+use crate::interception::catalog::{FunctionCatalog, FunctionVolatility};
+use crate::interception::copy::copy_from_stdin;
+use crate::interception::rewrite::{NON_DETERMINISTIC_FUNCTIONS, ColumnDefaults, find_non_deterministic_defaults};
+use crate::verification::environment::ReplayStatement;
+use crate::verification::state::LARGE_OBJECT_TABLE;
+use verifiable_db_core::models::{TableSchema, TriggerDefinition, TriggerEvent};
+
+/// Large-object functions that change the contents of `pg_largeobject`
+pub const LARGE_OBJECT_WRITE_FUNCTIONS: &[&str] = &[
+    "lo_create",
+    "lo_creat",
+    "lo_from_bytea",
+    "lo_import",
+    "lo_put",
+    "lowrite",
+    "lo_truncate",
+    "lo_truncate64",
+    "lo_unlink",
+];
+
+/// Large-object functions that touch the database server's filesystem, which replay can't reproduce
+pub const LARGE_OBJECT_FILE_FUNCTIONS: &[&str] = &["lo_import", "lo_export"];
+
+/// Functions returning connection- or server-specific state, which differs on replay
+///
+/// Their results can't be replaced with deterministic values, so queries
+/// calling them are unverifiable.
+pub const SESSION_STATE_FUNCTIONS: &[&str] = &[
+    "pg_backend_pid",
+    "pg_blocking_pids",
+    "inet_client_addr",
+    "inet_client_port",
+    "inet_server_addr",
+    "inet_server_port",
+    "current_setting",
+    "version",
+    "pg_postmaster_start_time",
+    "pg_conf_load_time",
+    "pg_current_logfile",
+    "pg_current_wal_lsn",
+    "pg_current_wal_insert_lsn",
+    "pg_current_xact_id",
+    "pg_current_xact_id_if_assigned",
+    "txid_current_if_assigned",
+    "pg_current_snapshot",
+    "txid_current_snapshot",
+    "pg_listening_channels",
+    "pg_notification_queue_usage",
+    "pg_my_temp_schema",
+];
+
+/// Aggregates whose result depends on input row order unless they contain an ORDER BY
+pub const ORDER_SENSITIVE_AGGREGATES: &[&str] = &[
+    "array_agg",
+    "string_agg",
+    "json_agg",
+    "jsonb_agg",
+    "json_object_agg",
+    "jsonb_object_agg",
+    "xmlagg",
+];
+
+/// Aggregate functions besides the [`ORDER_SENSITIVE_AGGREGATES`], weighted in complexity scoring
+pub const AGGREGATE_FUNCTIONS: &[&str] = &[
+    "count",
+    "sum",
+    "avg",
+    "min",
+    "max",
+    "bool_and",
+    "bool_or",
+    "every",
+    "bit_and",
+    "bit_or",
+    "stddev",
+    "stddev_pop",
+    "stddev_samp",
+    "variance",
+    "var_pop",
+    "var_samp",
+    "percentile_cont",
+    "percentile_disc",
+    "mode",
+];
+
+/// Output column names of a set operation, taken from its leftmost input
+///
+/// Returns `None` if any column can't be named (e.g. a wildcard).
+fn set_output_columns(body: &SetExpr) -> Option<Vec<String>> {
+    match body {
+        SetExpr::SetOperation { left, .. } => set_output_columns(left),
+        SetExpr::Query(query) => set_output_columns(&query.body),
+        SetExpr::Select(select) => select.projection.iter()
+            .map(|item| match item {
+                SelectItem::UnnamedExpr(expr) => Some(expr_column_name(expr)),
+                SelectItem::ExprWithAlias { alias, .. } => Some(alias.value.to_lowercase()),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Name an expression the way it appears as an output column
+fn expr_column_name(expr: &Expr) -> String {
+    match expr {
+        Expr::Identifier(ident) => ident.value.to_lowercase(),
+        Expr::CompoundIdentifier(idents) => idents.last()
+            .map(|ident| ident.value.to_lowercase())
+            .unwrap_or_default(),
+        other => other.to_string().to_lowercase(),
+    }
+}
+
+/// Output column an ORDER BY expression sorts on, resolving positional references
+fn order_by_key(expr: &Expr, columns: &[String]) -> String {
+    if let Expr::Value(Value::Number(position, _)) = expr {
+        if let Some(column) = position.parse::<usize>().ok()
+            .and_then(|p| p.checked_sub(1))
+            .and_then(|i| columns.get(i))
+        {
+            return column.clone();
+        }
+    }
+    expr_column_name(expr)
+}
+
+/// Collect the inputs of a tree of `UNION ALL`s, returning false if any other set operation is involved
+fn collect_union_all_inputs<'a>(body: &'a SetExpr, inputs: &mut Vec<&'a SetExpr>) -> bool {
+    match body {
+        SetExpr::SetOperation { op: SetOperator::Union, set_quantifier: SetQuantifier::All, left, right } => {
+            collect_union_all_inputs(left, inputs) && collect_union_all_inputs(right, inputs)
+        }
+        SetExpr::SetOperation { .. } => false,
+        other => {
+            inputs.push(other);
+            true
+        }
+    }
+}
+
+/// Schemas holding server metadata rather than user data
+pub const SYSTEM_CATALOG_SCHEMAS: &[&str] = &[
+    "pg_catalog",
+    "information_schema",
+    "pg_toast",
+];
+
+/// Maximum depth of nested views expanded during analysis
+pub const MAX_VIEW_DEPTH: usize = 16;
+
+/// Maximum depth of nested subqueries, CTEs and joins tables are extracted from
+pub const MAX_QUERY_DEPTH: usize = 64;
+
+/// Catalog query listing user view definitions for [`QueryAnalyzer::register_view`]
+///
+/// Materialized views hold stored rows and are not listed; they are read like tables.
+pub const VIEW_DEFINITIONS_QUERY: &str = "SELECT schemaname, viewname, definition FROM pg_catalog.pg_views \
+     WHERE schemaname NOT IN ('pg_catalog', 'information_schema')";
 
 /// Type of SQL query
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -40,6 +202,12 @@ pub enum QueryType {
     /// DELETE query
     Delete,
     
+    /// TRUNCATE query
+    Truncate,
+    
+    /// MERGE query
+    Merge,
+    
     /// CREATE TABLE query
     CreateTable,
     
@@ -67,6 +235,15 @@ pub enum QueryType {
     /// SAVEPOINT query
     Savepoint,
     
+    /// PREPARE TRANSACTION query, with the transaction's global identifier
+    PrepareTransaction(String),
+    
+    /// COMMIT PREPARED query, with the prepared transaction's global identifier
+    CommitPrepared(String),
+    
+    /// ROLLBACK PREPARED query, with the prepared transaction's global identifier
+    RollbackPrepared(String),
+    
     /// EXPLAIN query
     Explain,
     
@@ -79,6 +256,15 @@ pub enum QueryType {
     /// COPY query
     Copy,
     
+    /// DECLARE ... CURSOR query, with the cursor's name
+    DeclareCursor(String),
+    
+    /// FETCH query, with the name of the cursor fetched from
+    Fetch(String),
+    
+    /// MOVE query, with the name of the cursor moved
+    Move(String),
+    
     /// Other query type
     Other(String),
 }
@@ -91,6 +277,8 @@ impl QueryType {
             QueryType::Insert => "INSERT",
             QueryType::Update => "UPDATE",
             QueryType::Delete => "DELETE",
+            QueryType::Truncate => "TRUNCATE",
+            QueryType::Merge => "MERGE",
             QueryType::CreateTable => "CREATE TABLE",
             QueryType::AlterTable => "ALTER TABLE",
             QueryType::DropTable => "DROP TABLE",
@@ -100,10 +288,16 @@ impl QueryType {
             QueryType::Commit => "COMMIT",
             QueryType::Rollback => "ROLLBACK",
             QueryType::Savepoint => "SAVEPOINT",
+            QueryType::PrepareTransaction(_) => "PREPARE TRANSACTION",
+            QueryType::CommitPrepared(_) => "COMMIT PREPARED",
+            QueryType::RollbackPrepared(_) => "ROLLBACK PREPARED",
             QueryType::Explain => "EXPLAIN",
             QueryType::Set => "SET",
             QueryType::Show => "SHOW",
             QueryType::Copy => "COPY",
+            QueryType::DeclareCursor(_) => "DECLARE CURSOR",
+            QueryType::Fetch(_) => "FETCH",
+            QueryType::Move(_) => "MOVE",
             QueryType::Other(_) => "OTHER",
         }
     }
@@ -114,6 +308,8 @@ impl QueryType {
             QueryType::Insert | 
             QueryType::Update | 
             QueryType::Delete |
+            QueryType::Truncate |
+            QueryType::Merge |
             QueryType::Copy
         )
     }
@@ -135,15 +331,54 @@ impl QueryType {
             QueryType::BeginTransaction | 
             QueryType::Commit | 
             QueryType::Rollback |
-            QueryType::Savepoint
+            QueryType::Savepoint |
+            QueryType::PrepareTransaction(_) |
+            QueryType::CommitPrepared(_) |
+            QueryType::RollbackPrepared(_)
         )
     }
     
+    /// Global identifier of the prepared transaction a two-phase commit statement refers to
+    pub fn prepared_transaction_id(&self) -> Option<&str> {
+        match self {
+            QueryType::PrepareTransaction(gid) |
+            QueryType::CommitPrepared(gid) |
+            QueryType::RollbackPrepared(gid) => Some(gid),
+            _ => None,
+        }
+    }
+    
+    /// Name of the cursor a DECLARE, FETCH or MOVE statement refers to
+    pub fn cursor_name(&self) -> Option<&str> {
+        match self {
+            QueryType::DeclareCursor(name) |
+            QueryType::Fetch(name) |
+            QueryType::Move(name) => Some(name),
+            _ => None,
+        }
+    }
+    
+    /// Get the trigger events this query type may fire
+    ///
+    /// MERGE may fire any row event, depending on which of its clauses match.
+    pub fn trigger_events(&self) -> Vec<TriggerEvent> {
+        match self {
+            QueryType::Insert | QueryType::Copy => vec![TriggerEvent::Insert],
+            QueryType::Update => vec![TriggerEvent::Update],
+            QueryType::Delete => vec![TriggerEvent::Delete],
+            QueryType::Truncate => vec![TriggerEvent::Truncate],
+            QueryType::Merge => vec![TriggerEvent::Insert, TriggerEvent::Update, TriggerEvent::Delete],
+            _ => Vec::new(),
+        }
+    }
+    
     /// Check if the query type is read-only
     pub fn is_read_only(&self) -> bool {
         match self {
             QueryType::Select => true,
             QueryType::Explain => true,
+            // Cursors can only be declared over queries returning rows
+            QueryType::DeclareCursor(_) | QueryType::Fetch(_) | QueryType::Move(_) => true,
             // All other query types are considered to modify data
             _ => false,
         }
@@ -175,10 +410,13 @@ pub struct TableAccess {
     /// Access type (read, write, read-write)
     pub access_type: AccessType,
     
-    /// Columns accessed (if known)
+    /// Columns accessed (if known), lowercase and sorted; [`ALL_COLUMNS`] stands for every column
     pub columns: Option<Vec<String>>,
 }
 
+/// Sentinel in [`TableAccess::columns`] for a query accessing every column, as `SELECT *` does
+pub const ALL_COLUMNS: &str = "*";
+
 /// Non-deterministic operation detected in query
 #[derive(Debug, Clone)]
 pub struct NonDeterministicOperation {
@@ -222,6 +460,12 @@ pub struct QueryMetadata {
     /// Whether this query can be verified
     pub verifiable: bool,
     
+    /// Whether this query only reads system catalogs
+    ///
+    /// Metadata queries are forwarded normally but are excluded from state
+    /// capture and proofs, since server metadata isn't part of the verified state.
+    pub metadata_query: bool,
+    
     /// Whether this query should be cached
     pub cacheable: bool,
     
@@ -235,7 +479,46 @@ pub struct QueryMetadata {
 impl QueryMetadata {
     /// Check if the query modifies data
     pub fn modifies_data(&self) -> bool {
-        self.query_type.is_dml() || self.query_type.is_ddl()
+        self.query_type.is_dml() || self.query_type.is_ddl() || self.writes_large_objects()
+    }
+    
+    /// Check if the query calls functions that change large objects
+    pub fn writes_large_objects(&self) -> bool {
+        self.extra.contains_key("large_object_functions")
+    }
+    
+    /// Check if the query is an `INSERT ... ON CONFLICT`
+    pub fn is_upsert(&self) -> bool {
+        self.extra.contains_key("upsert")
+    }
+    
+    /// Check if the query locks the rows it reads (`FOR UPDATE`, `FOR SHARE`, ...)
+    pub fn takes_row_locks(&self) -> bool {
+        self.extra.contains_key("row_locks")
+    }
+    
+    /// Number of `$N` parameters a prepared statement takes
+    pub fn param_count(&self) -> usize {
+        self.extra
+            .get("param_count")
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0)
+    }
+    
+    /// Attach the values bound to a prepared statement's parameters
+    ///
+    /// The values must cover every placeholder of the statement, in placeholder order.
+    pub fn bind_params(&self, params: Vec<verifiable_db_core::models::Value>) -> Result<ReplayStatement> {
+        let expected = self.param_count();
+        if params.len() != expected {
+            return Err(ProxyError::Protocol(format!(
+                "Statement takes {} parameter(s) but {} were bound",
+                expected,
+                params.len()
+            )));
+        }
+        
+        Ok(ReplayStatement::new(self.query.clone(), params))
     }
     
     /// Check if the query requires special handling
@@ -276,6 +559,45 @@ impl QueryMetadata {
         self.query.hash(&mut hasher);
         format!("{:016x}", hasher.finish())
     }
+    
+    /// Get a fingerprint that ignores whitespace, keyword case and a
+    /// trailing semicolon, so equivalent spellings of a query share it
+    ///
+    /// Quoted identifiers and string literals are kept verbatim.
+    pub fn get_normalized_fingerprint(&self) -> String {
+        let normalized = normalize_query_text(&self.query);
+        let digest = Sha256::digest(normalized.as_bytes());
+        hex::encode(&digest[..8])
+    }
+}
+
+/// Collapse whitespace and lowercase everything outside quotes
+fn normalize_query_text(query: &str) -> String {
+    let mut normalized = String::with_capacity(query.len());
+    let mut quote = None;
+    let mut pending_space = false;
+    for c in query.trim().trim_end_matches(';').trim_end().chars() {
+        match quote {
+            Some(q) => {
+                normalized.push(c);
+                if c == q {
+                    quote = None;
+                }
+            }
+            None if c.is_whitespace() => pending_space = true,
+            None => {
+                if pending_space && !normalized.is_empty() {
+                    normalized.push(' ');
+                }
+                pending_space = false;
+                if c == '\'' || c == '"' {
+                    quote = Some(c);
+                }
+                normalized.extend(c.to_lowercase());
+            }
+        }
+    }
+    normalized
 }
 
 /// Query analyzer for SQL queries
@@ -284,9 +606,42 @@ pub struct QueryAnalyzer {
     /// Cache of parsed queries for faster re-analysis
     query_cache: HashMap<String, QueryMetadata>,
     
+    /// Cached queries from least to most recently used, for eviction
+    cache_order: VecDeque<String>,
+    
     /// Patterns for detecting non-deterministic functions
     non_deterministic_patterns: Vec<String>,
     
+    /// Aggregates that are non-deterministic without an internal ORDER BY
+    order_sensitive_aggregates: Vec<String>,
+    
+    /// Triggers installed on each table, keyed by table name
+    table_triggers: HashMap<String, Vec<TriggerDefinition>>,
+    
+    /// Column defaults of each table, keyed by lowercase table name
+    column_defaults: HashMap<String, ColumnDefaults>,
+    
+    /// Definitions of regular views, keyed by lowercase (optionally schema-qualified) name
+    view_definitions: HashMap<String, String>,
+    
+    /// Analysis of each declared cursor, keyed by cursor name
+    cursors: HashMap<String, QueryMetadata>,
+    
+    /// Whether view references are expanded to their definitions
+    expand_views: bool,
+    
+    /// Whether set operations must be totally ordered to be deterministic
+    enforce_set_operation_order: bool,
+    
+    /// Whether calls to session state functions make queries non-deterministic
+    detect_session_state_functions: bool,
+    
+    /// Catalog consulted for functions missing from the static list, if any
+    function_catalog: Option<Arc<dyn FunctionCatalog>>,
+    
+    /// Volatility reported by the function catalog, keyed by function name
+    function_volatility: Mutex<HashMap<String, Option<FunctionVolatility>>>,
+    
     /// Maximum cache size
     max_cache_size: usize,
     
@@ -314,22 +669,148 @@ impl QueryAnalyzer {
         
         Self {
             query_cache: HashMap::new(),
+            cache_order: VecDeque::new(),
             non_deterministic_patterns,
+            order_sensitive_aggregates: ORDER_SENSITIVE_AGGREGATES.iter().map(|a| a.to_string()).collect(),
+            table_triggers: HashMap::new(),
+            column_defaults: HashMap::new(),
+            view_definitions: HashMap::new(),
+            cursors: HashMap::new(),
+            expand_views: true,
+            enforce_set_operation_order: true,
+            detect_session_state_functions: true,
+            function_catalog: None,
+            function_volatility: Mutex::new(HashMap::new()),
             max_cache_size: 1000, // Cache up to 1000 queries
             enforce_determinism: true,
         }
     }
     
+    /// Replace the set of aggregates treated as order-sensitive
+    ///
+    /// Aggregates not in this set (e.g. `count`, `sum`, `max`) are always
+    /// considered deterministic.
+    pub fn set_order_sensitive_aggregates(&mut self, aggregates: Vec<String>) {
+        self.order_sensitive_aggregates = aggregates.into_iter().map(|a| a.to_lowercase()).collect();
+        self.clear_cache();
+    }
+    
+    /// Treat an aggregate as deterministic even without an internal ORDER BY
+    pub fn allow_aggregate(&mut self, aggregate: &str) {
+        let aggregate = aggregate.to_lowercase();
+        self.order_sensitive_aggregates.retain(|a| *a != aggregate);
+        self.clear_cache();
+    }
+    
+    /// Record the triggers installed on a table
+    ///
+    /// DML on the table inherits the determinism of every trigger it fires.
+    pub fn register_triggers(&mut self, table_name: &str, triggers: Vec<TriggerDefinition>) {
+        self.table_triggers.insert(table_name.to_string(), triggers);
+        self.clear_cache();
+    }
+    
+    /// Record the column defaults of a captured table schema
+    ///
+    /// INSERTs that leave a column to a non-deterministic default (e.g.
+    /// `DEFAULT now()`) are flagged even though their text looks deterministic.
+    pub fn register_column_defaults(&mut self, schema: &TableSchema) {
+        let defaults = schema.columns.iter()
+            .map(|column| (column.name.clone(), column.default_value.clone()))
+            .collect();
+        self.column_defaults.insert(schema.name.to_lowercase(), defaults);
+        self.clear_cache();
+    }
+    
+    /// Record the definition of a regular (non-materialized) view
+    ///
+    /// `name` may be schema-qualified; `definition` is the view's SELECT, as
+    /// returned by [`VIEW_DEFINITIONS_QUERY`]. Queries reading the view are
+    /// analyzed against the tables and expressions of its definition.
+    pub fn register_view(&mut self, name: &str, definition: &str) {
+        self.view_definitions.insert(name.to_lowercase(), definition.to_string());
+        self.clear_cache();
+    }
+    
+    /// Enable or disable expanding view references to their definitions
+    ///
+    /// When disabled, views are treated like tables and non-deterministic
+    /// expressions in their definitions go undetected.
+    pub fn set_expand_views(&mut self, enabled: bool) {
+        self.expand_views = enabled;
+        self.clear_cache();
+    }
+    
+    /// Enable or disable requiring a total order on set operation results
+    ///
+    /// When enabled, `UNION`/`INTERSECT`/`EXCEPT` need a final ORDER BY over
+    /// every output column, and `UNION ALL` without one needs every input
+    /// ordered.
+    pub fn set_enforce_set_operation_order(&mut self, enabled: bool) {
+        self.enforce_set_operation_order = enabled;
+        self.clear_cache();
+    }
+    
+    /// Enable or disable flagging calls to [`SESSION_STATE_FUNCTIONS`]
+    ///
+    /// When enabled, queries calling them (e.g. `pg_backend_pid()`) are
+    /// non-deterministic and unverifiable, since replay returns its own
+    /// connection's and server's values.
+    pub fn set_detect_session_state_functions(&mut self, enabled: bool) {
+        self.detect_session_state_functions = enabled;
+        self.clear_cache();
+    }
+    
+    /// Look up functions missing from the static list in `catalog`
+    ///
+    /// User-defined functions the catalog reports as volatile make queries
+    /// non-deterministic. Without a catalog only the static list applies.
+    pub fn set_function_catalog(&mut self, catalog: Option<Arc<dyn FunctionCatalog>>) {
+        self.function_catalog = catalog;
+        self.function_volatility.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.clear_cache();
+    }
+    
+    /// Parse `query` into statements, preparing it the way [`Self::analyze`] does
+    pub fn parse_statements(query: &str) -> Result<Vec<Statement>> {
+        let table_samples = find_table_samples(query);
+        let parsed_query = strip_table_samples(query, &table_samples);
+        let parsed_query = strip_delete_only(&parsed_query);
+        let parsed_query = terminate_copy_from_stdin(&parsed_query);
+        Parser::parse_sql(&PostgreSqlDialect {}, &parsed_query)
+            .map_err(|e| ProxyError::Analysis(format!("Failed to parse query: {}", e)))
+    }
+    
     /// Analyze a SQL query and extract metadata
     pub fn analyze(&mut self, query: &str) -> Result<QueryMetadata> {
+        // Cursor statements depend on the cursors declared so far, so they bypass the cache
+        if let Some(statement) = parse_cursor_statement(query) {
+            return self.analyze_cursor_statement(query, statement);
+        }
+        
         // Check cache first
-        if let Some(metadata) = self.query_cache.get(query) {
-            return Ok(metadata.clone());
+        if let Some(metadata) = self.query_cache.get(query).cloned() {
+            self.touch_cache_entry(query);
+            return Ok(metadata);
+        }
+        
+        // sqlparser doesn't know the two-phase commit statements
+        if two_phase_commit_type(query).is_some() {
+            let metadata = self.create_basic_metadata(query)?;
+            self.add_to_cache(query.to_string(), metadata.clone());
+            return Ok(metadata);
         }
         
+        // sqlparser doesn't parse TABLESAMPLE or DELETE FROM ONLY, so parse the
+        // query without them; sampling clauses are checked separately below
+        let table_samples = find_table_samples(query);
+        let parsed_query = strip_table_samples(query, &table_samples);
+        let parsed_query = strip_delete_only(&parsed_query);
+        let parsed_query = terminate_copy_from_stdin(&parsed_query);
+        
         // Parse the query
         let dialect = PostgreSqlDialect {};
-        let statements = match Parser::parse_sql(&dialect, query) {
+        let statements = match Parser::parse_sql(&dialect, &parsed_query) {
             Ok(statements) => statements,
             Err(err) => {
                 debug!("Failed to parse query: {}", err);
@@ -355,22 +836,183 @@ impl QueryAnalyzer {
         // Extract query type
         let query_type = self.extract_query_type(statement);
         
-        // Extract tables accessed
-        let tables = self.extract_tables(statement, &query_type);
+        // Extract tables accessed, reading through views to the tables they're defined over
+        let mut view_operations = Vec::new();
+        let mut expanded_views = Vec::new();
+        let mut tables = self.extract_tables(statement, &query_type);
+        if self.expand_views && !self.view_definitions.is_empty() {
+            tables = self.expand_view_references(tables, 0, &mut view_operations, &mut expanded_views);
+        }
+        
+        // Keep system catalogs out of dependency tracking
+        let (catalog_tables, mut tables): (Vec<TableAccess>, Vec<TableAccess>) = tables
+            .into_iter()
+            .partition(is_system_catalog);
+        
+        let mut extra = HashMap::new();
+        if !expanded_views.is_empty() {
+            extra.insert("views".to_string(), expanded_views.join(","));
+        }
+        if !catalog_tables.is_empty() {
+            let names: Vec<String> = catalog_tables.iter()
+                .map(|t| match &t.schema_name {
+                    Some(schema) => format!("{}.{}", schema, t.table_name),
+                    None => t.table_name.clone(),
+                })
+                .collect();
+            extra.insert("system_catalogs".to_string(), names.join(","));
+        }
+        
+        // Recursive CTEs iterate until they stop producing rows
+        let recursive_ctes = find_recursive_ctes(statement);
+        if !recursive_ctes.is_empty() {
+            extra.insert("recursive_ctes".to_string(), recursive_ctes.join(","));
+        }
+        
+        // An upsert's effect depends on the rows it conflicts with, so replay
+        // must resolve the conflicts against the same state
+        if let Some(action) = conflict_action(statement) {
+            extra.insert("upsert".to_string(), "true".to_string());
+            extra.insert("conflict_action".to_string(), action.to_string());
+        }
+        
+        // Large-object functions write pg_largeobject without naming it, even from a SELECT
+        let large_object_writes = find_listed_calls(statement, LARGE_OBJECT_WRITE_FUNCTIONS);
+        if !large_object_writes.is_empty() {
+            extra.insert("large_object_functions".to_string(), large_object_writes.join(","));
+            tables.push(TableAccess {
+                table_name: LARGE_OBJECT_TABLE.to_string(),
+                schema_name: None,
+                access_type: AccessType::Write,
+                columns: None,
+            });
+        }
+        
+        // Row locks make the query wait for, and block, concurrent writers of
+        // the locked rows, so it's ordered against them like a write
+        let locks = row_locks(statement);
+        if !locks.is_empty() {
+            let lock_types: Vec<&str> = locks.iter().map(|lock| lock_type_name(&lock.lock_type)).collect();
+            extra.insert("row_locks".to_string(), lock_types.join(","));
+        }
+        
+        // Reads of nothing but system catalogs are metadata queries: not verified,
+        // so determinism analysis doesn't apply
+        if query_type == QueryType::Select && !catalog_tables.is_empty() && tables.is_empty() {
+            let metadata = QueryMetadata {
+                query: query.to_string(),
+                query_type,
+                tables,
+                is_deterministic: true,
+                non_deterministic_operations: Vec::new(),
+                complexity_score: self.calculate_complexity(query, statement),
+                special_handling: false,
+                verifiable: false,
+                metadata_query: true,
+                cacheable: false, // Server metadata changes independently of user data
+                extra,
+                non_deterministic_reason: None,
+            };
+            
+            self.add_to_cache(query.to_string(), metadata.clone());
+            return Ok(metadata);
+        }
         
         // Collect non-deterministic operations and determine determinism
         let mut non_deterministic_operations = Vec::new();
         
         // Check for non-deterministic functions
-        for function in NON_DETERMINISTIC_FUNCTIONS {
-            if query.to_lowercase().contains(&function.to_lowercase()) {
-                non_deterministic_operations.push(NonDeterministicOperation {
-                    operation_type: "Function".to_string(),
-                    description: format!("Non-deterministic function: {}", function),
-                    can_fix_automatically: true,
-                    suggested_fix: Some(format!("Replace with deterministic version")),
-                });
-            }
+        for function in find_non_deterministic_calls(statement) {
+            non_deterministic_operations.push(NonDeterministicOperation {
+                operation_type: "Function".to_string(),
+                description: format!("Non-deterministic function: {}", function),
+                can_fix_automatically: true,
+                suggested_fix: Some(format!("Replace with deterministic version")),
+            });
+        }
+        
+        // Check for user-defined functions the catalog reports as volatile
+        for function in self.find_volatile_calls(statement) {
+            non_deterministic_operations.push(NonDeterministicOperation {
+                operation_type: "Function".to_string(),
+                description: format!("Volatile function: {}", function),
+                can_fix_automatically: false,
+                suggested_fix: Some(format!("Pass the result of {}() as a literal or parameter", function)),
+            });
+        }
+        
+        // Check for order-sensitive aggregates without an internal ORDER BY
+        for aggregate in self.find_unordered_aggregate_calls(statement) {
+            non_deterministic_operations.push(NonDeterministicOperation {
+                operation_type: "Aggregate".to_string(),
+                description: format!("Order-sensitive aggregate without ORDER BY: {}", aggregate),
+                can_fix_automatically: false,
+                suggested_fix: Some(format!("Add an ORDER BY inside {}(...)", aggregate)),
+            });
+        }
+        
+        // Check for table samples drawn without a seed
+        for sample in table_samples.iter().filter(|sample| sample.seed.is_none()) {
+            non_deterministic_operations.push(NonDeterministicOperation {
+                operation_type: "TableSample".to_string(),
+                description: format!("TABLESAMPLE {} without REPEATABLE seed", sample.method),
+                can_fix_automatically: false,
+                suggested_fix: Some("Add REPEATABLE (seed) to the TABLESAMPLE clause".to_string()),
+            });
+        }
+        
+        // Check for large-object functions reading or writing server files
+        for function in find_listed_calls(statement, LARGE_OBJECT_FILE_FUNCTIONS) {
+            non_deterministic_operations.push(NonDeterministicOperation {
+                operation_type: "LargeObject".to_string(),
+                description: format!("Large-object function accesses the server filesystem: {}", function),
+                can_fix_automatically: false,
+                suggested_fix: Some("Transfer the content with lo_from_bytea or lo_get instead".to_string()),
+            });
+        }
+        
+        // Check for functions returning connection- or server-specific state
+        for function in self.find_session_state_function_calls(statement) {
+            non_deterministic_operations.push(NonDeterministicOperation {
+                operation_type: "SessionState".to_string(),
+                description: format!("Function returns connection- or server-specific state: {}", function),
+                can_fix_automatically: false,
+                suggested_fix: Some(format!("Pass the value of {}() as a literal or parameter", function)),
+            });
+        }
+        
+        // Check for triggers with non-deterministic functions
+        non_deterministic_operations.extend(self.find_non_deterministic_triggers(&query_type, &tables));
+        
+        // Check for INSERTs leaving columns to non-deterministic defaults
+        for default in find_non_deterministic_defaults(statement, &self.column_defaults) {
+            non_deterministic_operations.push(NonDeterministicOperation {
+                operation_type: "Default".to_string(),
+                description: format!(
+                    "Column {}.{} defaults to non-deterministic function: {}",
+                    default.table_name, default.column, default.function
+                ),
+                can_fix_automatically: default.fixable,
+                suggested_fix: Some(format!("Supply a value for {}", default.column)),
+            });
+        }
+        
+        // Check for row locks that skip rows other transactions have locked
+        if locks.iter().any(|lock| lock.nonblock == Some(NonBlock::SkipLocked)) {
+            non_deterministic_operations.push(NonDeterministicOperation {
+                operation_type: "RowLock".to_string(),
+                description: "SKIP LOCKED returns rows depending on concurrent transactions".to_string(),
+                can_fix_automatically: false,
+                suggested_fix: Some("Remove SKIP LOCKED".to_string()),
+            });
+        }
+        
+        // Check for views whose definitions are non-deterministic
+        non_deterministic_operations.extend(view_operations);
+        
+        // Check for set operations whose result order is unspecified
+        if self.enforce_set_operation_order {
+            non_deterministic_operations.extend(self.find_unordered_set_operations(statement));
         }
         
         // Check for unordered queries
@@ -417,10 +1059,11 @@ impl QueryAnalyzer {
         let special_handling = self.needs_special_handling(statement, &query_type);
         
         // Determine if the query is verifiable
-        let verifiable = self.is_verifiable(&query_type, &non_deterministic_operations);
+        let writes_large_objects = !large_object_writes.is_empty();
+        let verifiable = self.is_verifiable(&query_type, writes_large_objects, &non_deterministic_operations);
         
         // Determine if the query is cacheable
-        let cacheable = self.is_cacheable(&query_type, is_deterministic);
+        let cacheable = !writes_large_objects && locks.is_empty() && self.is_cacheable(&query_type, is_deterministic);
         
         // Create metadata
         let metadata = QueryMetadata {
@@ -432,8 +1075,9 @@ impl QueryAnalyzer {
             complexity_score,
             special_handling,
             verifiable,
+            metadata_query: false,
             cacheable,
-            extra: HashMap::new(),
+            extra,
             non_deterministic_reason,
         };
         
@@ -443,11 +1087,99 @@ impl QueryAnalyzer {
         Ok(metadata)
     }
     
+    /// Analyze a statement from a Parse message, which may contain `$N` placeholders
+    ///
+    /// Placeholders stand for values bound later, so they never make a
+    /// statement non-deterministic. Where sqlparser doesn't accept a
+    /// placeholder, the statement is analyzed with NULL in its place. The
+    /// number of parameters is recorded for [`QueryMetadata::bind_params`].
+    pub fn analyze_prepared(&mut self, query: &str) -> Result<QueryMetadata> {
+        let placeholders = find_placeholders(query);
+        
+        let table_samples = find_table_samples(query);
+        let parsed_query = strip_table_samples(query, &table_samples);
+        let parses = Parser::parse_sql(&PostgreSqlDialect {}, &strip_delete_only(&parsed_query)).is_ok();
+        
+        let mut metadata = if parses || placeholders.is_empty() {
+            self.analyze(query)?
+        } else {
+            let mut metadata = self.analyze(&replace_placeholders(query, &placeholders, "NULL"))?;
+            metadata.query = query.to_string();
+            metadata
+        };
+        
+        let param_count = placeholders.iter().map(|(_, index)| *index).max().unwrap_or(0);
+        metadata.extra.insert("prepared".to_string(), "true".to_string());
+        metadata.extra.insert("param_count".to_string(), param_count.to_string());
+        
+        Ok(metadata)
+    }
+    
+    /// Analyze a DECLARE, FETCH or MOVE statement
+    ///
+    /// A declared cursor is analyzed as its query and registered under its
+    /// name; FETCH and MOVE take the analysis of the cursor they refer to, so
+    /// fetched rows are tracked against the tables the cursor reads. A cursor
+    /// pages through its query's result, so the pages only match on replay
+    /// if the query is deterministic as written: the rewriter doesn't reach
+    /// into cursor queries, and none of their non-deterministic operations
+    /// can be fixed automatically.
+    fn analyze_cursor_statement(&mut self, query: &str, statement: CursorStatement) -> Result<QueryMetadata> {
+        let (query_type, declared) = match statement {
+            CursorStatement::Declare { name, query: cursor_query } => {
+                let mut metadata = self.analyze(&cursor_query)?;
+                for op in &mut metadata.non_deterministic_operations {
+                    op.can_fix_automatically = false;
+                }
+                (QueryType::DeclareCursor(name), Some(metadata))
+            }
+            CursorStatement::Fetch(name) => {
+                let declared = self.cursors.get(&name).cloned();
+                (QueryType::Fetch(name), declared)
+            }
+            CursorStatement::Move(name) => {
+                let declared = self.cursors.get(&name).cloned();
+                (QueryType::Move(name), declared)
+            }
+        };
+        let name = query_type.cursor_name().unwrap_or_default().to_string();
+        
+        let mut metadata = match declared {
+            Some(declared) => declared,
+            None => {
+                // The cursor was declared before the analyzer saw it, so its query is unknown
+                let mut metadata = self.create_basic_metadata(query)?;
+                metadata.is_deterministic = false;
+                metadata.non_deterministic_operations.push(NonDeterministicOperation {
+                    operation_type: "Cursor".to_string(),
+                    description: format!("Query of cursor {} is unknown", name),
+                    can_fix_automatically: false,
+                    suggested_fix: Some(format!("Declare {} through the proxy", name)),
+                });
+                metadata
+            }
+        };
+        metadata.query = query.to_string();
+        metadata.query_type = query_type;
+        metadata.non_deterministic_reason = metadata.non_deterministic_operations.first()
+            .map(|op| op.description.clone());
+        metadata.verifiable = metadata.is_deterministic && !metadata.metadata_query;
+        metadata.cacheable = false; // Each FETCH continues from the cursor's position
+        metadata.extra.insert("cursor".to_string(), name.clone());
+        
+        if let QueryType::DeclareCursor(_) = metadata.query_type {
+            self.cursors.insert(name, metadata.clone());
+        }
+        Ok(metadata)
+    }
+    
     /// Create basic metadata for unparseable queries based on keyword matching
     fn create_basic_metadata(&self, query: &str) -> Result<QueryMetadata> {
         let lowercase_query = query.to_lowercase();
         
-        let query_type = if lowercase_query.starts_with("select") {
+        let query_type = if let Some(query_type) = two_phase_commit_type(query) {
+            query_type
+        } else if lowercase_query.starts_with("select") {
             QueryType::Select
         } else if lowercase_query.starts_with("insert") {
             QueryType::Insert
@@ -455,6 +1187,10 @@ impl QueryAnalyzer {
             QueryType::Update
         } else if lowercase_query.starts_with("delete") {
             QueryType::Delete
+        } else if lowercase_query.starts_with("truncate") {
+            QueryType::Truncate
+        } else if lowercase_query.starts_with("merge") {
+            QueryType::Merge
         } else if lowercase_query.starts_with("create table") {
             QueryType::CreateTable
         } else if lowercase_query.starts_with("alter table") {
@@ -495,6 +1231,7 @@ impl QueryAnalyzer {
             complexity_score: 1, // Default complexity for unparseable queries
             special_handling: false,
             verifiable: query_type_clone.is_dml() || query_type_clone.is_ddl(),
+            metadata_query: false,
             cacheable: false, // Unparseable queries are not cacheable
             extra: HashMap::new(),
             non_deterministic_reason: None,
@@ -515,6 +1252,8 @@ impl QueryAnalyzer {
             Statement::Insert { .. } => QueryType::Insert,
             Statement::Update { .. } => QueryType::Update,
             Statement::Delete { .. } => QueryType::Delete,
+            Statement::Truncate { .. } => QueryType::Truncate,
+            Statement::Merge { .. } => QueryType::Merge,
             Statement::CreateTable { .. } => QueryType::CreateTable,
             Statement::AlterTable { .. } => QueryType::AlterTable,
             Statement::Drop { object_type, .. } => {
@@ -543,31 +1282,34 @@ impl QueryAnalyzer {
         
         match statement {
             Statement::Query(query) => {
-                self.extract_tables_from_query(query, &mut tables, AccessType::Read);
+                self.extract_tables_from_query(query, &mut tables, AccessType::Read, 0);
+                self.apply_row_locks(query, &mut tables);
             }
-            Statement::Insert { table_name, source, .. } => {
-                // Add destination table with write access
+            Statement::Insert { table_name, source, on, .. } => {
+                // Add destination table with write access; ON CONFLICT also
+                // reads the existing rows to find the conflicts
+                let access_type = if on.is_some() { AccessType::ReadWrite } else { AccessType::Write };
                 tables.push(TableAccess {
                     table_name: self.object_name_to_string(table_name),
                     schema_name: self.extract_schema_name(table_name),
-                    access_type: AccessType::Write,
+                    access_type,
                     columns: None,
                 });
                 
                 // Add tables from the source query with read access
                 if let Some(query) = source {
-                    self.extract_tables_from_query(query, &mut tables, AccessType::Read);
+                    self.extract_tables_from_query(query, &mut tables, AccessType::Read, 0);
                 }
             }
             Statement::Update { table, from, .. } => {
                 // Extract tables from the main table being updated
                 if let sqlparser::ast::TableWithJoins { relation, joins } = table {
                     // The main table being updated gets ReadWrite access
-                    self.extract_tables_from_table_factor(relation, &mut tables, AccessType::ReadWrite);
+                    self.extract_tables_from_table_factor(relation, &mut tables, AccessType::ReadWrite, 0);
                     
                     // Process joins if any with read access
                     for join in joins {
-                        self.extract_tables_from_table_factor(&join.relation, &mut tables, AccessType::Read);
+                        self.extract_tables_from_table_factor(&join.relation, &mut tables, AccessType::Read, 0);
                     }
                 }
                 
@@ -581,37 +1323,52 @@ impl QueryAnalyzer {
                 }
             }
             Statement::Delete { from, using, .. } => {
-                // In a DELETE statement, 'from' could be of various types
-                // For now, we'll implement a simplified approach
-                
-                // This is a simplified implementation that will at least compile
-                // When we know exactly what type 'from' is in this version of sqlparser,
-                // this can be updated with the correct implementation
-                debug!("Processing DELETE statement with from clause");
-                
-                // Add a generic table access with write permission
-                tables.push(TableAccess {
-                    table_name: "table_from_delete".to_string(),
-                    schema_name: None,
-                    access_type: AccessType::Write,
-                    columns: None,
-                });
+                // Add the tables rows are deleted from with write access
+                for target in delete_targets(from) {
+                    self.extract_tables_from_table_with_joins(target, &mut tables, AccessType::Write, 0);
+                }
                 
                 // Add tables from the USING clause with read access
                 if let Some(using_tables) = using {
                     for using_twj in using_tables {
                         // Handle each TableWithJoins in the USING clause
                         if let sqlparser::ast::TableWithJoins { relation, joins } = using_twj {
-                            self.extract_tables_from_table_factor(&relation, &mut tables, AccessType::Read);
+                            self.extract_tables_from_table_factor(&relation, &mut tables, AccessType::Read, 0);
                             
                             // Process joins if any
                             for join in joins {
-                                self.extract_tables_from_table_factor(&join.relation, &mut tables, AccessType::Read);
+                                self.extract_tables_from_table_factor(&join.relation, &mut tables, AccessType::Read, 0);
                             }
                         }
                     }
                 }
             }
+            Statement::Truncate { table_name, .. } => {
+                // TRUNCATE removes every row without reading any
+                tables.push(TableAccess {
+                    table_name: self.object_name_to_string(table_name),
+                    schema_name: self.extract_schema_name(table_name),
+                    access_type: AccessType::Write,
+                    columns: None,
+                });
+            }
+            Statement::Copy { source: CopySource::Table { table_name, .. }, to, .. } => {
+                // COPY FROM loads rows into the table; COPY TO reads them out
+                tables.push(TableAccess {
+                    table_name: self.object_name_to_string(table_name),
+                    schema_name: self.extract_schema_name(table_name),
+                    access_type: if *to { AccessType::Read } else { AccessType::Write },
+                    columns: None,
+                });
+            }
+            Statement::Copy { source: CopySource::Query(query), .. } => {
+                self.extract_tables_from_query(query, &mut tables, AccessType::Read, 0);
+            }
+            Statement::Merge { table, source, .. } => {
+                // The target's rows are matched against the source before being changed
+                self.extract_tables_from_table_factor(table, &mut tables, AccessType::ReadWrite, 0);
+                self.extract_tables_from_table_factor(source, &mut tables, AccessType::Read, 0);
+            }
             Statement::CreateTable { name, .. } => {
                 tables.push(TableAccess {
                     table_name: self.object_name_to_string(name),
@@ -651,18 +1408,105 @@ impl QueryAnalyzer {
             }
         }
         
+        self.extract_columns(statement, &mut tables);
         tables
     }
     
     /// Extract tables from a query
-    fn extract_tables_from_query(&self, query: &Query, tables: &mut Vec<TableAccess>, access_type: AccessType) {
+    ///
+    /// References to the query's CTEs, including a recursive CTE's reference
+    /// to itself, aren't tables and are left out. Queries nested deeper than
+    /// [`MAX_QUERY_DEPTH`] are skipped.
+    fn extract_tables_from_query(&self, query: &Query, tables: &mut Vec<TableAccess>, access_type: AccessType, depth: usize) {
+        if depth > MAX_QUERY_DEPTH {
+            warn!("Query nested deeper than {} levels, not extracting its tables", MAX_QUERY_DEPTH);
+            return;
+        }
+        
+        let cte_names: Vec<String> = query.with.iter()
+            .flat_map(|with| &with.cte_tables)
+            .map(|cte| cte.alias.name.value.to_lowercase())
+            .collect();
+        let mut body_tables = Vec::new();
+        
         // Extract from the body of the query
-        match query.body.as_ref() {
+        self.extract_tables_from_set_expr(&query.body, &mut body_tables, access_type.clone(), depth);
+        merge_tables(tables, body_tables, &cte_names);
+        
+        // Extract from the CTE queries; without RECURSIVE, a CTE only sees the ones before it
+        if let Some(with) = &query.with {
+            for (index, cte) in with.cte_tables.iter().enumerate() {
+                let mut cte_tables = Vec::new();
+                self.extract_tables_from_query(&cte.query, &mut cte_tables, access_type.clone(), depth + 1);
+                let visible = if with.recursive { &cte_names[..] } else { &cte_names[..index] };
+                merge_tables(tables, cte_tables, visible);
+            }
+        }
+    }
+    
+    /// Upgrade the tables a query's row-locking clauses lock to ReadWrite
+    ///
+    /// `FOR UPDATE` and `FOR SHARE` don't change any rows, but they serialize
+    /// the query with concurrent transactions writing the locked rows: those
+    /// writes wait for the locking transaction, or it waits for them. The
+    /// order of the transactions on the primary, and so the state a replay
+    /// must reproduce, depends on these locks, so locked tables are tracked
+    /// like written ones. A clause without `OF` locks the rows of every table
+    /// in the query's FROM clause; `OF` names the tables, by alias if aliased.
+    fn apply_row_locks(&self, query: &Query, tables: &mut [TableAccess]) {
+        let select = match query.body.as_ref() {
+            SetExpr::Select(select) if !query.locks.is_empty() => select,
+            _ => return,
+        };
+        
+        let relations = select.from.iter()
+            .flat_map(|table_with_joins| {
+                std::iter::once(&table_with_joins.relation)
+                    .chain(table_with_joins.joins.iter().map(|join| &join.relation))
+            })
+            .filter_map(|relation| match relation {
+                TableFactor::Table { name, alias, .. } => Some((name, alias.as_ref())),
+                _ => None,
+            });
+        
+        for (name, alias) in relations {
+            let reference = match alias {
+                Some(alias) => alias.name.value.to_lowercase(),
+                None => self.object_name_to_string(name).to_lowercase(),
+            };
+            let locked = query.locks.iter().any(|lock| match &lock.of {
+                Some(of) => self.object_name_to_string(of).to_lowercase() == reference,
+                None => true,
+            });
+            if !locked {
+                continue;
+            }
+            
+            let table_name = self.object_name_to_string(name);
+            let schema_name = self.extract_schema_name(name);
+            for table in tables.iter_mut().filter(|t| t.table_name == table_name && t.schema_name == schema_name) {
+                table.access_type = AccessType::ReadWrite;
+            }
+        }
+    }
+    
+    /// Extract tables from a query body
+    fn extract_tables_from_set_expr(&self, body: &SetExpr, tables: &mut Vec<TableAccess>, access_type: AccessType, depth: usize) {
+        if depth > MAX_QUERY_DEPTH {
+            warn!("Set operations nested deeper than {} levels, not extracting their tables", MAX_QUERY_DEPTH);
+            return;
+        }
+        
+        match body {
             SetExpr::Select(select) => {
-                self.extract_tables_from_select(select, tables, access_type.clone());
+                self.extract_tables_from_select(select, tables, access_type.clone(), depth);
             }
             SetExpr::Query(subquery) => {
-                self.extract_tables_from_query(subquery, tables, access_type.clone());
+                self.extract_tables_from_query(subquery, tables, access_type.clone(), depth + 1);
+            }
+            SetExpr::SetOperation { left, right, .. } => {
+                self.extract_tables_from_set_expr(left, tables, access_type.clone(), depth + 1);
+                self.extract_tables_from_set_expr(right, tables, access_type.clone(), depth + 1);
             }
             SetExpr::Values(_) => {
                 // VALUES clause doesn't reference tables directly
@@ -682,7 +1526,7 @@ impl QueryAnalyzer {
                 
                 // Handle the source part of the insert (could be a query)
                 if let Some(source) = &insert.source {
-                    self.extract_tables_from_query(source, tables, AccessType::Read);
+                    self.extract_tables_from_query(source, tables, AccessType::Read, depth + 1);
                 }
             }
             SetExpr::Update(update) => {
@@ -701,7 +1545,7 @@ impl QueryAnalyzer {
                 // Handle any FROM clause in the UPDATE
                 if let Some(from) = &update.from {
                     for table_with_joins in from {
-                        self.extract_tables_from_table_with_joins(table_with_joins, tables, AccessType::Read);
+                        self.extract_tables_from_table_with_joins(table_with_joins, tables, AccessType::Read, depth);
                     }
                 }
             }
@@ -721,7 +1565,7 @@ impl QueryAnalyzer {
                 // Handle any USING clause in the DELETE
                 if let Some(using) = &delete.using {
                     for table_with_joins in using {
-                        self.extract_tables_from_table_with_joins(table_with_joins, tables, AccessType::Read);
+                        self.extract_tables_from_table_with_joins(table_with_joins, tables, AccessType::Read, depth);
                     }
                 }
             }
@@ -729,44 +1573,34 @@ impl QueryAnalyzer {
                 // Other types not handled specifically
             }
         }
-        
-        // Extract from using clause if present
-        if let Some(with) = &query.with {
-            for cte in &with.cte_tables {
-                // Process the CTE query
-                self.extract_tables_from_query(&cte.query, tables, access_type.clone());
-            }
-        }
-        
-        // Handle joins in the FROM clause of a SELECT
-        if let SetExpr::Select(select) = query.body.as_ref() {
-            for from_item in &select.from {
-                self.extract_tables_from_table_with_joins(from_item, tables, access_type.clone());
-            }
-        }
     }
     
     /// Extract tables from a SELECT statement
-    fn extract_tables_from_select(&self, select: &Select, tables: &mut Vec<TableAccess>, access_type: AccessType) {
+    fn extract_tables_from_select(&self, select: &Select, tables: &mut Vec<TableAccess>, access_type: AccessType, depth: usize) {
         for table_with_joins in &select.from {
             // Clone access_type before passing it
-            self.extract_tables_from_table_with_joins(table_with_joins, tables, access_type.clone());
+            self.extract_tables_from_table_with_joins(table_with_joins, tables, access_type.clone(), depth);
         }
     }
     
     /// Extract tables from a FROM clause with joins
-    fn extract_tables_from_table_with_joins(&self, table_with_joins: &TableWithJoins, tables: &mut Vec<TableAccess>, access_type: AccessType) {
+    fn extract_tables_from_table_with_joins(&self, table_with_joins: &TableWithJoins, tables: &mut Vec<TableAccess>, access_type: AccessType, depth: usize) {
         // Clone access_type before passing it
-        self.extract_tables_from_table_factor(&table_with_joins.relation, tables, access_type.clone());
+        self.extract_tables_from_table_factor(&table_with_joins.relation, tables, access_type.clone(), depth);
         
         for join in &table_with_joins.joins {
             // Clone access_type before passing it
-            self.extract_tables_from_table_factor(&join.relation, tables, access_type.clone());
+            self.extract_tables_from_table_factor(&join.relation, tables, access_type.clone(), depth);
         }
     }
     
     /// Extract tables from a table factor (table, subquery, etc.)
-    fn extract_tables_from_table_factor(&self, table_factor: &TableFactor, tables: &mut Vec<TableAccess>, access_type: AccessType) {
+    fn extract_tables_from_table_factor(&self, table_factor: &TableFactor, tables: &mut Vec<TableAccess>, access_type: AccessType, depth: usize) {
+        if depth > MAX_QUERY_DEPTH {
+            warn!("Joins nested deeper than {} levels, not extracting their tables", MAX_QUERY_DEPTH);
+            return;
+        }
+        
         match table_factor {
             TableFactor::Table { name, .. } => {
                 let table_name = self.object_name_to_string(name);
@@ -787,11 +1621,11 @@ impl QueryAnalyzer {
                 }
             }
             TableFactor::Derived { subquery, .. } => {
-                self.extract_tables_from_query(subquery, tables, access_type);
+                self.extract_tables_from_query(subquery, tables, access_type, depth + 1);
             }
             // Update the NestedJoin pattern to match the structure expected by the sqlparser library
             TableFactor::NestedJoin { table_with_joins, .. } => {
-                self.extract_tables_from_table_with_joins(table_with_joins, tables, access_type);
+                self.extract_tables_from_table_with_joins(table_with_joins, tables, access_type, depth + 1);
             }
             _ => {
                 // Other table factors don't access tables or we can't determine
@@ -799,17 +1633,249 @@ impl QueryAnalyzer {
         }
     }
     
-    /// Convert an object name to a string
-    fn object_name_to_string(&self, name: &ObjectName) -> String {
-        if name.0.is_empty() {
-            "".to_string()
-        } else {
-            name.0.last().unwrap().value.clone()
-        }
-    }
-    
-    /// Extract schema name from an object name
-    fn extract_schema_name(&self, name: &ObjectName) -> Option<String> {
+    /// Fill in the columns each of `tables` is accessed through
+    ///
+    /// Columns come from SELECT projections, WHERE, JOIN, GROUP BY, HAVING
+    /// and ORDER BY expressions, INSERT column lists and UPDATE assignments;
+    /// a DELETE accesses every column of its target.
+    /// A qualified column goes to the table or alias qualifying it; an
+    /// unqualified one to every table in its scope, since which of them has
+    /// the column isn't known here. Tables outside the SELECT, INSERT,
+    /// UPDATE and DELETE statements handled here keep `columns: None`.
+    fn extract_columns(&self, statement: &Statement, tables: &mut [TableAccess]) {
+        let mut columns = ColumnsByTable::new();
+        
+        match statement {
+            Statement::Query(query) => {
+                self.extract_columns_from_query(query, &mut columns);
+            }
+            Statement::Insert { table_name, columns: insert_columns, source, on, .. } => {
+                // Without a column list, the INSERT fills every column
+                let key = (self.object_name_to_string(table_name), self.extract_schema_name(table_name));
+                let target = columns.entry(key.clone()).or_default();
+                if insert_columns.is_empty() {
+                    target.insert(ALL_COLUMNS.to_string());
+                } else {
+                    target.extend(insert_columns.iter().map(|column| column.value.to_lowercase()));
+                }
+                
+                // DO UPDATE assigns columns of the conflicting row, reading
+                // it and the proposed row through EXCLUDED
+                if let Some(OnInsert::OnConflict(OnConflict { action: OnConflictAction::DoUpdate(update), .. })) = on {
+                    target.extend(update.assignments.iter()
+                        .filter_map(|assignment| assignment.id.last())
+                        .map(|column| column.value.to_lowercase()));
+                    let scope = [ScopeTable { qualifier: key.0.to_lowercase(), key }];
+                    let mut expressions: Vec<&Expr> = update.assignments.iter().map(|assignment| &assignment.value).collect();
+                    expressions.extend(&update.selection);
+                    record_column_references(&scope, &expressions, &[], &HashSet::new(), &mut columns);
+                }
+                
+                if let Some(query) = source {
+                    self.extract_columns_from_query(query, &mut columns);
+                }
+            }
+            Statement::Update { table, assignments, from, selection, .. } => {
+                let mut scope = Vec::new();
+                let mut expressions = Vec::new();
+                let mut using_columns = Vec::new();
+                self.bind_table_with_joins(table, &mut scope, &mut expressions, &mut using_columns, &mut columns);
+                if let Some(from) = from {
+                    self.bind_table_with_joins(from, &mut scope, &mut expressions, &mut using_columns, &mut columns);
+                }
+                
+                // Assigned columns belong to the updated table
+                if let TableFactor::Table { name, .. } = &table.relation {
+                    let target = columns
+                        .entry((self.object_name_to_string(name), self.extract_schema_name(name)))
+                        .or_default();
+                    target.extend(assignments.iter()
+                        .filter_map(|assignment| assignment.id.last())
+                        .map(|column| column.value.to_lowercase()));
+                }
+                expressions.extend(assignments.iter().map(|assignment| &assignment.value));
+                expressions.extend(selection);
+                
+                record_column_references(&scope, &expressions, &using_columns, &HashSet::new(), &mut columns);
+            }
+            Statement::Delete { from, using, selection, .. } => {
+                let mut scope = Vec::new();
+                let mut expressions = Vec::new();
+                let mut using_columns = Vec::new();
+                for table_with_joins in delete_targets(from).iter().chain(using.iter().flatten()) {
+                    self.bind_table_with_joins(table_with_joins, &mut scope, &mut expressions, &mut using_columns, &mut columns);
+                }
+                expressions.extend(selection);
+                record_column_references(&scope, &expressions, &using_columns, &HashSet::new(), &mut columns);
+                
+                // Deleting a row removes every one of its columns
+                for target in delete_targets(from) {
+                    if let TableFactor::Table { name, .. } = &target.relation {
+                        columns
+                            .entry((self.object_name_to_string(name), self.extract_schema_name(name)))
+                            .or_default()
+                            .insert(ALL_COLUMNS.to_string());
+                    }
+                }
+            }
+            _ => return,
+        }
+        
+        for table in tables.iter_mut() {
+            if let Some(referenced) = columns.get(&(table.table_name.clone(), table.schema_name.clone())) {
+                table.columns = Some(if referenced.contains(ALL_COLUMNS) {
+                    vec![ALL_COLUMNS.to_string()]
+                } else {
+                    referenced.iter().cloned().collect()
+                });
+            }
+        }
+    }
+    
+    /// Record the columns a query and its CTEs and subqueries in FROM access
+    fn extract_columns_from_query(&self, query: &Query, columns: &mut ColumnsByTable) {
+        if let Some(with) = &query.with {
+            for cte in &with.cte_tables {
+                self.extract_columns_from_query(&cte.query, columns);
+            }
+        }
+        self.extract_columns_from_set_expr(&query.body, &query.order_by, columns);
+    }
+    
+    /// Record the columns a query body accesses, with the ORDER BY applying to it
+    fn extract_columns_from_set_expr(&self, body: &SetExpr, order_by: &[OrderByExpr], columns: &mut ColumnsByTable) {
+        match body {
+            SetExpr::Select(select) => {
+                self.extract_columns_from_select(select, order_by, columns);
+            }
+            SetExpr::Query(query) => {
+                self.extract_columns_from_query(query, columns);
+            }
+            SetExpr::SetOperation { left, right, .. } => {
+                // An ORDER BY on a set operation sorts its output columns
+                self.extract_columns_from_set_expr(left, &[], columns);
+                self.extract_columns_from_set_expr(right, &[], columns);
+            }
+            _ => {
+                // Other bodies don't reference table columns
+            }
+        }
+    }
+    
+    /// Record the columns a SELECT accesses
+    fn extract_columns_from_select(&self, select: &Select, order_by: &[OrderByExpr], columns: &mut ColumnsByTable) {
+        let mut scope = Vec::new();
+        let mut expressions = Vec::new();
+        let mut using_columns = Vec::new();
+        for table_with_joins in &select.from {
+            self.bind_table_with_joins(table_with_joins, &mut scope, &mut expressions, &mut using_columns, columns);
+        }
+        
+        let mut aliases = HashSet::new();
+        for item in &select.projection {
+            match item {
+                SelectItem::UnnamedExpr(expr) => expressions.push(expr),
+                SelectItem::ExprWithAlias { expr, alias } => {
+                    expressions.push(expr);
+                    aliases.insert(alias.value.to_lowercase());
+                }
+                SelectItem::QualifiedWildcard(name, _) => {
+                    let qualifier = self.object_name_to_string(name).to_lowercase();
+                    for table in scope.iter().filter(|table| table.qualifier == qualifier) {
+                        columns.entry(table.key.clone()).or_default().insert(ALL_COLUMNS.to_string());
+                    }
+                }
+                SelectItem::Wildcard(_) => {
+                    for table in &scope {
+                        columns.entry(table.key.clone()).or_default().insert(ALL_COLUMNS.to_string());
+                    }
+                }
+            }
+        }
+        expressions.extend(&select.selection);
+        if let GroupByExpr::Expressions(group_by) = &select.group_by {
+            expressions.extend(group_by);
+        }
+        expressions.extend(&select.having);
+        record_column_references(&scope, &expressions, &using_columns, &HashSet::new(), columns);
+        
+        // ORDER BY can also name output columns by their alias
+        let order_by: Vec<&Expr> = order_by.iter().map(|order| &order.expr).collect();
+        record_column_references(&scope, &order_by, &[], &aliases, columns);
+    }
+    
+    /// Bring the tables of a FROM item into scope, collecting its join conditions
+    fn bind_table_with_joins<'a>(
+        &self,
+        table_with_joins: &'a TableWithJoins,
+        scope: &mut Vec<ScopeTable>,
+        expressions: &mut Vec<&'a Expr>,
+        using_columns: &mut Vec<&'a Ident>,
+        columns: &mut ColumnsByTable,
+    ) {
+        self.bind_table_factor(&table_with_joins.relation, scope, expressions, using_columns, columns);
+        
+        for join in &table_with_joins.joins {
+            self.bind_table_factor(&join.relation, scope, expressions, using_columns, columns);
+            
+            let constraint = match &join.join_operator {
+                JoinOperator::Inner(constraint)
+                | JoinOperator::LeftOuter(constraint)
+                | JoinOperator::RightOuter(constraint)
+                | JoinOperator::FullOuter(constraint) => Some(constraint),
+                _ => None,
+            };
+            match constraint {
+                Some(JoinConstraint::On(expr)) => expressions.push(expr),
+                Some(JoinConstraint::Using(idents)) => using_columns.extend(idents),
+                _ => {}
+            }
+        }
+    }
+    
+    /// Bring a table into scope, or record the columns a derived table accesses
+    fn bind_table_factor<'a>(
+        &self,
+        table_factor: &'a TableFactor,
+        scope: &mut Vec<ScopeTable>,
+        expressions: &mut Vec<&'a Expr>,
+        using_columns: &mut Vec<&'a Ident>,
+        columns: &mut ColumnsByTable,
+    ) {
+        match table_factor {
+            TableFactor::Table { name, alias, .. } => {
+                let key = (self.object_name_to_string(name), self.extract_schema_name(name));
+                columns.entry(key.clone()).or_default();
+                let qualifier = match alias {
+                    Some(alias) => alias.name.value.to_lowercase(),
+                    None => key.0.to_lowercase(),
+                };
+                scope.push(ScopeTable { qualifier, key });
+            }
+            TableFactor::Derived { subquery, .. } => {
+                // The subquery's columns are its own; the outer query reads its output
+                self.extract_columns_from_query(subquery, columns);
+            }
+            TableFactor::NestedJoin { table_with_joins, .. } => {
+                self.bind_table_with_joins(table_with_joins, scope, expressions, using_columns, columns);
+            }
+            _ => {
+                // Other table factors don't access tables or we can't determine
+            }
+        }
+    }
+    
+    /// Convert an object name to a string
+    fn object_name_to_string(&self, name: &ObjectName) -> String {
+        if name.0.is_empty() {
+            "".to_string()
+        } else {
+            name.0.last().unwrap().value.clone()
+        }
+    }
+    
+    /// Extract schema name from an object name
+    fn extract_schema_name(&self, name: &ObjectName) -> Option<String> {
         if name.0.len() > 1 {
             Some(name.0[name.0.len() - 2].value.clone())
         } else {
@@ -840,7 +1906,7 @@ impl QueryAnalyzer {
                         }
                         
                         // Add complexity for GROUP BY
-                        if let Some(group_by) = &select.group_by {
+                        if let GroupByExpr::Expressions(group_by) = &select.group_by {
                             // Check if group_by has any elements
                             if !group_by.is_empty() {
                                 complexity += 10;
@@ -853,9 +1919,7 @@ impl QueryAnalyzer {
                         }
                         
                         // Add complexity for ORDER BY
-                        if let Some(order_by) = &query.order_by {
-                            complexity += order_by.len() as u32 * 5;
-                        }
+                        complexity += query.order_by.len() as u32 * 5;
                         
                         // Add complexity for LIMIT and OFFSET
                         if query.limit.is_some() {
@@ -924,7 +1988,30 @@ impl QueryAnalyzer {
     /// Calculate complexity score for an expression
     fn calculate_expr_complexity(&self, expr: &Expr) -> u32 {
         match expr {
-            Expr::Function(_) => 5,  // Functions add complexity
+            Expr::Function(function) => {
+                let name = function.name.0.last()
+                    .map(|ident| ident.value.to_lowercase())
+                    .unwrap_or_default();
+                let is_aggregate = AGGREGATE_FUNCTIONS.contains(&name.as_str())
+                    || ORDER_SENSITIVE_AGGREGATES.contains(&name.as_str());
+                let function_complexity = if function.over.is_some() {
+                    30 // Window functions sort and scan each partition
+                } else if is_aggregate && function.distinct {
+                    20 // DISTINCT aggregates deduplicate their input
+                } else if is_aggregate {
+                    10 // Aggregates consume every input row
+                } else {
+                    5 // Functions add complexity
+                };
+                let argument_complexity: u32 = function.args.iter()
+                    .map(|arg| match arg {
+                        FunctionArg::Named { arg: FunctionArgExpr::Expr(expr), .. }
+                        | FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => self.calculate_expr_complexity(expr),
+                        _ => 0,
+                    })
+                    .sum();
+                function_complexity + argument_complexity
+            }
             Expr::Case { .. } => 15, // CASE expressions are quite complex
             Expr::Exists(_) => 10,   // EXISTS subqueries add complexity
             Expr::Subquery(_) => 15, // Subqueries add significant complexity 
@@ -952,9 +2039,9 @@ impl QueryAnalyzer {
     }
     
     /// Check if a query is verifiable
-    fn is_verifiable(&self, query_type: &QueryType, non_deterministic_ops: &[NonDeterministicOperation]) -> bool {
-        // Only DML and DDL queries are verifiable
-        if !query_type.is_dml() && !query_type.is_ddl() {
+    fn is_verifiable(&self, query_type: &QueryType, writes_large_objects: bool, non_deterministic_ops: &[NonDeterministicOperation]) -> bool {
+        // Only DML and DDL queries, or queries writing large objects, are verifiable
+        if !query_type.is_dml() && !query_type.is_ddl() && !writes_large_objects {
             return false;
         }
         
@@ -983,33 +2070,360 @@ impl QueryAnalyzer {
     /// Clear the query cache
     pub fn clear_cache(&mut self) {
         self.query_cache.clear();
+        self.cache_order.clear();
     }
     
-    /// Add a query to the cache
+    /// Add a query to the cache, evicting the least recently used entries when full
     fn add_to_cache(&mut self, query: String, metadata: QueryMetadata) {
-        // If cache is full, remove oldest entries
-        if self.query_cache.len() >= self.max_cache_size {
-            // Simple approach: just clear half the cache
-            let to_remove: Vec<String> = self.query_cache.keys()
-                .take(self.max_cache_size / 2)
-                .cloned()
-                .collect();
-            
-            for key in to_remove {
-                self.query_cache.remove(&key);
+        if self.query_cache.contains_key(&query) {
+            self.touch_cache_entry(&query);
+            self.query_cache.insert(query, metadata);
+            return;
+        }
+        
+        while self.query_cache.len() >= self.max_cache_size.max(1) {
+            match self.cache_order.pop_front() {
+                Some(oldest) => {
+                    self.query_cache.remove(&oldest);
+                }
+                None => break,
             }
         }
         
+        self.cache_order.push_back(query.clone());
         self.query_cache.insert(query, metadata);
     }
+    
+    /// Mark a cached query as the most recently used
+    fn touch_cache_entry(&mut self, query: &str) {
+        if let Some(position) = self.cache_order.iter().position(|q| q == query) {
+            if let Some(entry) = self.cache_order.remove(position) {
+                self.cache_order.push_back(entry);
+            }
+        }
+    }
 
+    /// Order-sensitive aggregates `statement` calls without an ORDER BY of their own
+    ///
+    /// Walks the statement's function calls, so a column or string that merely
+    /// contains an aggregate's name doesn't match. A call counts as ordered if
+    /// it has an `ORDER BY` in its argument list.
+    fn find_unordered_aggregate_calls(&self, statement: &Statement) -> Vec<String> {
+        let mut found: Vec<String> = Vec::new();
+        let _ = visit_expressions(statement, |expr| {
+            let unordered = match expr {
+                Expr::Function(function) if function.order_by.is_empty() => Some(function_name(function)),
+                Expr::ArrayAgg(array_agg) if array_agg.order_by.is_none() => Some("array_agg".to_string()),
+                _ => None,
+            };
+            if let Some(name) = unordered {
+                if self.order_sensitive_aggregates.contains(&name) && !found.contains(&name) {
+                    found.push(name);
+                }
+            }
+            ControlFlow::<()>::Continue(())
+        });
+        found
+    }
+    
+    /// [`Self::find_unordered_aggregate_calls`] for every statement in `query`
+    ///
+    /// Queries that don't parse are matched on their text instead.
+    fn find_unordered_aggregates(&self, query: &str) -> Vec<String> {
+        let parsed_query = strip_table_samples(query, &find_table_samples(query));
+        let parsed_query = strip_delete_only(&parsed_query);
+        let statements = match Parser::parse_sql(&PostgreSqlDialect {}, &parsed_query) {
+            Ok(statements) => statements,
+            Err(_) => return self.find_unordered_aggregates_in_text(query),
+        };
+        
+        let mut found: Vec<String> = Vec::new();
+        for aggregate in statements.iter().flat_map(|statement| self.find_unordered_aggregate_calls(statement)) {
+            if !found.contains(&aggregate) {
+                found.push(aggregate);
+            }
+        }
+        found
+    }
+    
+    /// Order-sensitive aggregates called in `query`'s text without an ORDER BY in their arguments
+    fn find_unordered_aggregates_in_text(&self, query: &str) -> Vec<String> {
+        let lowercase_query = query.to_lowercase();
+        let mut found = Vec::new();
+        
+        for aggregate in &self.order_sensitive_aggregates {
+            let mut search_from = 0;
+            
+            while let Some(pos) = lowercase_query[search_from..].find(aggregate.as_str()) {
+                let name_start = search_from + pos;
+                let name_end = name_start + aggregate.len();
+                search_from = name_end;
+                
+                // Require a word boundary so e.g. `my_array_agg(` doesn't match
+                let preceded_by_identifier = lowercase_query[..name_start].chars().next_back()
+                    .is_some_and(|c| c.is_alphanumeric() || c == '_');
+                let rest = lowercase_query[name_end..].trim_start();
+                if preceded_by_identifier || !rest.starts_with('(') {
+                    continue;
+                }
+                
+                // Find the matching closing parenthesis
+                let args_start = lowercase_query.len() - rest.len() + 1;
+                let mut depth = 1;
+                let mut args_end = lowercase_query.len();
+                for (i, c) in lowercase_query[args_start..].char_indices() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                args_end = args_start + i;
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                
+                let args = lowercase_query[args_start..args_end].split_whitespace().collect::<Vec<_>>().join(" ");
+                if !args.contains("order by") && !found.contains(aggregate) {
+                    found.push(aggregate.clone());
+                }
+            }
+        }
+        
+        found
+    }
+    
+    /// Find triggers fired by a DML statement whose functions are non-deterministic
+    ///
+    /// The replay can't rewrite function bodies, so these can never be fixed
+    /// automatically and make the statement unverifiable.
+    fn find_non_deterministic_triggers(&self, query_type: &QueryType, tables: &[TableAccess]) -> Vec<NonDeterministicOperation> {
+        let events = query_type.trigger_events();
+        if events.is_empty() {
+            return Vec::new();
+        }
+        
+        let mut operations = Vec::new();
+        for table in tables.iter().filter(|t| matches!(t.access_type, AccessType::Write | AccessType::ReadWrite)) {
+            let triggers = match self.table_triggers.get(&table.table_name) {
+                Some(triggers) => triggers,
+                None => continue,
+            };
+            
+            for trigger in triggers.iter().filter(|t| events.iter().any(|event| t.fires_on(*event))) {
+                let body = trigger.function_definition.to_lowercase();
+                if let Some(function) = NON_DETERMINISTIC_FUNCTIONS.iter().find(|f| body.contains(&f.to_lowercase())) {
+                    operations.push(NonDeterministicOperation {
+                        operation_type: "Trigger".to_string(),
+                        description: format!(
+                            "Trigger {} on {} calls non-deterministic function: {}",
+                            trigger.name, table.table_name, function
+                        ),
+                        can_fix_automatically: false,
+                        suggested_fix: Some(format!("Make {}() deterministic", trigger.function_name)),
+                    });
+                }
+            }
+        }
+        
+        operations
+    }
+    
+    /// Replace references to registered views with the tables their definitions read
+    ///
+    /// Nested views are expanded up to [`MAX_VIEW_DEPTH`] levels. Non-deterministic
+    /// expressions in a definition can't be rewritten by the replay, so they are
+    /// reported as operations that can't be fixed automatically.
+    fn expand_view_references(
+        &self,
+        tables: Vec<TableAccess>,
+        depth: usize,
+        operations: &mut Vec<NonDeterministicOperation>,
+        expanded: &mut Vec<String>,
+    ) -> Vec<TableAccess> {
+        let mut result = Vec::with_capacity(tables.len());
+        
+        for table in tables {
+            let (view_name, definition) = match self.find_view(&table) {
+                Some(view) => view,
+                None => {
+                    result.push(table);
+                    continue;
+                }
+            };
+            
+            if depth >= MAX_VIEW_DEPTH {
+                warn!("View {} nested deeper than {} levels, treating it as a table", view_name, MAX_VIEW_DEPTH);
+                operations.push(NonDeterministicOperation {
+                    operation_type: "View".to_string(),
+                    description: format!("View {} is nested too deeply to analyze", view_name),
+                    can_fix_automatically: false,
+                    suggested_fix: None,
+                });
+                result.push(table);
+                continue;
+            }
+            
+            let view_samples = find_table_samples(definition);
+            let statement = match Parser::parse_sql(&PostgreSqlDialect {}, &strip_table_samples(definition, &view_samples)) {
+                Ok(mut statements) if !statements.is_empty() => statements.remove(0),
+                _ => {
+                    // Without the definition we can't vouch for the view's determinism
+                    warn!("Failed to parse definition of view {}", view_name);
+                    operations.push(NonDeterministicOperation {
+                        operation_type: "View".to_string(),
+                        description: format!("Definition of view {} could not be analyzed", view_name),
+                        can_fix_automatically: false,
+                        suggested_fix: None,
+                    });
+                    result.push(table);
+                    continue;
+                }
+            };
+            
+            if !expanded.iter().any(|name| name == view_name) {
+                expanded.push(view_name.to_string());
+            }
+            
+            let lowercase_definition = definition.to_lowercase();
+            if let Some(function) = NON_DETERMINISTIC_FUNCTIONS.iter().find(|f| lowercase_definition.contains(&f.to_lowercase())) {
+                operations.push(NonDeterministicOperation {
+                    operation_type: "View".to_string(),
+                    description: format!("View {} calls non-deterministic function: {}", view_name, function),
+                    can_fix_automatically: false,
+                    suggested_fix: Some(format!("Remove {} from the definition of {}", function, view_name)),
+                });
+            }
+            for aggregate in self.find_unordered_aggregates(definition) {
+                operations.push(NonDeterministicOperation {
+                    operation_type: "View".to_string(),
+                    description: format!("View {} uses order-sensitive aggregate without ORDER BY: {}", view_name, aggregate),
+                    can_fix_automatically: false,
+                    suggested_fix: Some(format!("Add an ORDER BY inside {}(...) in {}", aggregate, view_name)),
+                });
+            }
+            for sample in view_samples.iter().filter(|sample| sample.seed.is_none()) {
+                operations.push(NonDeterministicOperation {
+                    operation_type: "View".to_string(),
+                    description: format!("View {} uses TABLESAMPLE {} without REPEATABLE seed", view_name, sample.method),
+                    can_fix_automatically: false,
+                    suggested_fix: Some(format!("Add REPEATABLE (seed) to the TABLESAMPLE clause in {}", view_name)),
+                });
+            }
+            
+            // The underlying tables are accessed the same way the view is
+            let underlying = self
+                .extract_tables(&statement, &QueryType::Select)
+                .into_iter()
+                .map(|t| TableAccess { access_type: table.access_type.clone(), ..t })
+                .collect();
+            result.extend(self.expand_view_references(underlying, depth + 1, operations, expanded));
+        }
+        
+        result
+    }
+    
+    /// Look up the registered view a table reference resolves to
+    ///
+    /// Unqualified names resolve to `public`, the default search path.
+    fn find_view(&self, table: &TableAccess) -> Option<(&str, &str)> {
+        let name = table.table_name.to_lowercase();
+        let candidates = match &table.schema_name {
+            Some(schema) => vec![format!("{}.{}", schema.to_lowercase(), name), name],
+            None => vec![name.clone(), format!("public.{}", name)],
+        };
+        
+        candidates.iter()
+            .find_map(|key| self.view_definitions.get_key_value(key))
+            .map(|(key, definition)| (key.as_str(), definition.as_str()))
+    }
+    
+    /// Find set operations whose combined result has no total order
+    ///
+    /// The final ORDER BY of a set operation applies to the whole result, so
+    /// it must name every output column for the order to be total. Without
+    /// one, `UNION ALL` returns its inputs back to back and is ordered only if
+    /// every input is; the deduplicating forms have no defined order at all.
+    fn find_unordered_set_operations(&self, statement: &Statement) -> Vec<NonDeterministicOperation> {
+        let query = match statement {
+            Statement::Query(query) => query,
+            _ => return Vec::new(),
+        };
+        let (op, set_quantifier) = match query.body.as_ref() {
+            SetExpr::SetOperation { op, set_quantifier, .. } => (op, set_quantifier),
+            _ => return Vec::new(),
+        };
+        let operation = match set_quantifier {
+            SetQuantifier::All => format!("{} ALL", op),
+            _ => op.to_string(),
+        };
+        
+        if !query.order_by.is_empty() {
+            // Unknown output columns (e.g. SELECT *) can't be checked
+            let columns = match set_output_columns(&query.body) {
+                Some(columns) => columns,
+                None => return Vec::new(),
+            };
+            let keys: HashSet<String> = query.order_by.iter()
+                .map(|order| order_by_key(&order.expr, &columns))
+                .collect();
+            let missing: Vec<&String> = columns.iter().filter(|c| !keys.contains(*c)).collect();
+            if missing.is_empty() {
+                return Vec::new();
+            }
+            return vec![NonDeterministicOperation {
+                operation_type: "SetOperation".to_string(),
+                description: format!("{} ordered by a subset of its output columns", operation),
+                can_fix_automatically: false,
+                suggested_fix: Some(format!(
+                    "Add {} to the ORDER BY",
+                    missing.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", ")
+                )),
+            }];
+        }
+        
+        let mut inputs = Vec::new();
+        if collect_union_all_inputs(&query.body, &mut inputs)
+            && inputs.iter().all(|input| matches!(input, SetExpr::Query(q) if !q.order_by.is_empty()))
+        {
+            return Vec::new();
+        }
+        
+        vec![NonDeterministicOperation {
+            operation_type: "SetOperation".to_string(),
+            description: format!("{} without ORDER BY on the combined result", operation),
+            can_fix_automatically: false,
+            suggested_fix: Some(format!("Add an ORDER BY over every output column after the last {} input", op)),
+        }]
+    }
+    
     /// Check if a query is deterministic
     pub fn is_deterministic(&self, query: &str) -> bool {
         // Check for non-deterministic functions
-        for function in NON_DETERMINISTIC_FUNCTIONS {
-            if query.to_lowercase().contains(&function.to_lowercase()) {
-                return false;
-            }
+        if !find_non_deterministic_functions(query).is_empty() {
+            return false;
+        }
+        
+        // Check for volatile user-defined functions
+        if !self.find_volatile_functions(query).is_empty() {
+            return false;
+        }
+        
+        // Check for order-sensitive aggregates
+        if !self.find_unordered_aggregates(query).is_empty() {
+            return false;
+        }
+        
+        // Check for unseeded table samples
+        if find_table_samples(query).iter().any(|sample| sample.seed.is_none()) {
+            return false;
+        }
+        
+        // Check for session state functions
+        if !self.find_session_state_calls(query).is_empty() {
+            return false;
         }
         
         // Check for unordered queries
@@ -1026,17 +2440,6 @@ impl QueryAnalyzer {
             return false;
         }
         
-        // Check for explicit RANDOM() calls
-        if query.to_lowercase().contains("random(") {
-            return false;
-        }
-        
-        // Check for UUID generation
-        if query.to_lowercase().contains("uuid_generate") || 
-           query.to_lowercase().contains("gen_random_uuid") {
-            return false;
-        }
-        
         // By default, assume the query is deterministic
         true
     }
@@ -1044,10 +2447,28 @@ impl QueryAnalyzer {
     /// Get the non-deterministic reason for a query
     pub fn get_non_deterministic_reason(&self, query: &str) -> Option<String> {
         // Check for non-deterministic functions
-        for function in NON_DETERMINISTIC_FUNCTIONS {
-            if query.to_lowercase().contains(&function.to_lowercase()) {
-                return Some(format!("Contains non-deterministic function: {}", function));
-            }
+        if let Some(function) = find_non_deterministic_functions(query).first() {
+            return Some(format!("Contains non-deterministic function: {}", function));
+        }
+        
+        // Check for volatile user-defined functions
+        if let Some(function) = self.find_volatile_functions(query).first() {
+            return Some(format!("Contains volatile function: {}", function));
+        }
+        
+        // Check for order-sensitive aggregates
+        if let Some(aggregate) = self.find_unordered_aggregates(query).first() {
+            return Some(format!("Order-sensitive aggregate without ORDER BY: {}", aggregate));
+        }
+        
+        // Check for unseeded table samples
+        if let Some(sample) = find_table_samples(query).into_iter().find(|sample| sample.seed.is_none()) {
+            return Some(format!("TABLESAMPLE {} without REPEATABLE seed", sample.method));
+        }
+        
+        // Check for session state functions
+        if let Some(function) = self.find_session_state_calls(query).first() {
+            return Some(format!("Contains session state function: {}", function));
         }
         
         // Check for unordered queries
@@ -1066,58 +2487,732 @@ impl QueryAnalyzer {
         // If we get here, the query is deterministic
         None
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
     
-    #[test]
-    fn test_analyze_select_query() {
-        let mut analyzer = QueryAnalyzer::new();
-        let query = "SELECT * FROM users WHERE id = 1";
-        let metadata = analyzer.analyze(query).unwrap();
+    /// The [`SESSION_STATE_FUNCTIONS`] `statement` calls, in list order, if their detection is enabled
+    fn find_session_state_function_calls(&self, statement: &Statement) -> Vec<&'static str> {
+        if !self.detect_session_state_functions {
+            return Vec::new();
+        }
         
-        assert_eq!(metadata.query_type, QueryType::Select);
-        assert_eq!(metadata.tables.len(), 1);
-        assert_eq!(metadata.tables[0].table_name, "users");
+        find_listed_calls(statement, SESSION_STATE_FUNCTIONS)
     }
     
-    #[test]
-    fn test_analyze_insert_query() {
-        let mut analyzer = QueryAnalyzer::new();
-        let query = "INSERT INTO users (id, name) VALUES (1, 'John')";
-        let metadata = analyzer.analyze(query).unwrap();
-        
-        assert_eq!(metadata.query_type, QueryType::Insert);
-        assert_eq!(metadata.tables.len(), 1);
-        assert_eq!(metadata.tables[0].table_name, "users");
-        assert_eq!(metadata.tables[0].access_type, AccessType::Write);
+    /// The [`SESSION_STATE_FUNCTIONS`] any statement in `query` calls, if their detection is enabled
+    ///
+    /// Queries that don't parse are matched on their text instead.
+    fn find_session_state_calls(&self, query: &str) -> Vec<&'static str> {
+        if !self.detect_session_state_functions {
+            return Vec::new();
+        }
+        find_function_calls(query, SESSION_STATE_FUNCTIONS)
     }
     
-    #[test]
-    fn test_analyze_update_query() {
-        let mut analyzer = QueryAnalyzer::new();
-        let query = "UPDATE users SET name = 'Jane' WHERE id = 1";
-        let metadata = analyzer.analyze(query).unwrap();
+    /// Functions missing from the static list that the function catalog
+    /// reports as volatile, in call order
+    ///
+    /// Nothing is reported without a catalog, or while it can't be reached.
+    fn find_volatile_calls(&self, statement: &Statement) -> Vec<String> {
+        let catalog = match &self.function_catalog {
+            Some(catalog) => catalog,
+            None => return Vec::new(),
+        };
         
-        assert_eq!(metadata.query_type, QueryType::Update);
-        assert_eq!(metadata.tables.len(), 1);
-        assert_eq!(metadata.tables[0].table_name, "users");
-        assert_eq!(metadata.tables[0].access_type, AccessType::ReadWrite);
+        let mut volatile = Vec::new();
+        for name in called_functions(statement) {
+            let listed = NON_DETERMINISTIC_FUNCTIONS.iter()
+                .any(|function| function.split('(').next() == Some(name.as_str()));
+            if listed || volatile.contains(&name) {
+                continue;
+            }
+            if self.function_volatility(catalog.as_ref(), &name) == Some(FunctionVolatility::Volatile) {
+                volatile.push(name);
+            }
+        }
+        volatile
     }
     
-    #[test]
-    fn test_analyze_delete_query() {
-        let mut analyzer = QueryAnalyzer::new();
-        let query = "DELETE FROM users WHERE id = 1";
-        let metadata = analyzer.analyze(query).unwrap();
+    /// [`Self::find_volatile_calls`] for every statement in `query`
+    fn find_volatile_functions(&self, query: &str) -> Vec<String> {
+        if self.function_catalog.is_none() {
+            return Vec::new();
+        }
         
-        assert_eq!(metadata.query_type, QueryType::Delete);
-        assert_eq!(metadata.tables.len(), 1);
-        assert_eq!(metadata.tables[0].table_name, "users");
-        assert_eq!(metadata.tables[0].access_type, AccessType::Write);
-    }
+        let parsed_query = strip_table_samples(query, &find_table_samples(query));
+        let parsed_query = strip_delete_only(&parsed_query);
+        let statements = match Parser::parse_sql(&PostgreSqlDialect {}, &parsed_query) {
+            Ok(statements) => statements,
+            Err(_) => return Vec::new(),
+        };
+        
+        let mut volatile: Vec<String> = Vec::new();
+        for name in statements.iter().flat_map(|statement| self.find_volatile_calls(statement)) {
+            if !volatile.contains(&name) {
+                volatile.push(name);
+            }
+        }
+        volatile
+    }
+    
+    /// Volatility of the function `name`, looked up once per name
+    ///
+    /// Failed lookups aren't cached, so the catalog is retried once it's back.
+    fn function_volatility(&self, catalog: &dyn FunctionCatalog, name: &str) -> Option<FunctionVolatility> {
+        let mut cache = self.function_volatility.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(volatility) = cache.get(name) {
+            return *volatility;
+        }
+        
+        match catalog.volatility(name) {
+            Ok(volatility) => {
+                cache.insert(name.to_string(), volatility);
+                volatility
+            }
+            Err(e) => {
+                warn!("Function catalog lookup failed, using the static function list: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// The functions from `functions` that `statement` calls, in list order
+fn find_listed_calls(statement: &Statement, functions: &[&'static str]) -> Vec<&'static str> {
+    let calls = called_functions(statement);
+    functions.iter()
+        .copied()
+        .filter(|function| calls.iter().any(|call| call == function))
+        .collect()
+}
+
+/// The functions from `functions` any statement in `query` calls, in list order
+///
+/// Queries that don't parse are matched on their text instead.
+fn find_function_calls(query: &str, functions: &[&'static str]) -> Vec<&'static str> {
+    let parsed_query = strip_table_samples(query, &find_table_samples(query));
+    let parsed_query = strip_delete_only(&parsed_query);
+    match Parser::parse_sql(&PostgreSqlDialect {}, &parsed_query) {
+        Ok(statements) => {
+            let calls: Vec<Vec<&'static str>> = statements.iter()
+                .map(|statement| find_listed_calls(statement, functions))
+                .collect();
+            functions.iter()
+                .copied()
+                .filter(|function| calls.iter().any(|found| found.contains(function)))
+                .collect()
+        }
+        Err(_) => find_function_calls_in_text(query, functions),
+    }
+}
+
+/// The functions from `functions` that `query`'s text calls, in list order
+///
+/// A call is the function name followed by `(`, not preceded by an
+/// identifier character, so `lo_creat` doesn't match `lo_create(`.
+fn find_function_calls_in_text(query: &str, functions: &[&'static str]) -> Vec<&'static str> {
+    let query = query.to_lowercase();
+    functions.iter()
+        .copied()
+        .filter(|function| {
+            query.match_indices(function).any(|(start, _)| {
+                let preceded_by_identifier = query[..start].chars().next_back()
+                    .is_some_and(|c| c.is_alphanumeric() || c == '_');
+                let rest = query[start + function.len()..].trim_start();
+                !preceded_by_identifier && rest.starts_with('(')
+            })
+        })
+        .collect()
+}
+
+/// The [`NON_DETERMINISTIC_FUNCTIONS`] `statement` calls, in list order
+///
+/// Walks the statement's expressions for function calls, so identifiers or
+/// strings that merely contain a function's name don't match and spacing
+/// or case doesn't hide a call. Entries with an argument, like
+/// `lo_create(0)`, only match calls whose first argument is that literal.
+fn find_non_deterministic_calls(statement: &Statement) -> Vec<&'static str> {
+    let mut calls = Vec::new();
+    let _ = visit_expressions(statement, |expr| {
+        if let Expr::Function(function) = expr {
+            let first_argument = function.args.first().map(|arg| arg.to_string());
+            calls.push((function_name(function), first_argument.unwrap_or_default()));
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    
+    NON_DETERMINISTIC_FUNCTIONS.iter()
+        .copied()
+        .filter(|function| {
+            let (name, argument) = match function.split_once('(') {
+                Some((name, rest)) => (name, rest.trim_end_matches([')', ','])),
+                None => (*function, ""),
+            };
+            calls.iter().any(|(call, first_argument)| {
+                call == name && (argument.is_empty() || first_argument == argument)
+            })
+        })
+        .collect()
+}
+
+/// Names of the functions `statement` calls, in call order
+fn called_functions(statement: &Statement) -> Vec<String> {
+    let mut names = Vec::new();
+    let _ = visit_expressions(statement, |expr| {
+        if let Expr::Function(function) = expr {
+            names.push(function_name(function));
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    names
+}
+
+/// Unqualified name of a called function
+///
+/// Quoted names are case-sensitive, so "NOW"() isn't now().
+fn function_name(function: &ast::Function) -> String {
+    function.name.0.last()
+        .map(|ident| match ident.quote_style {
+            Some(_) => ident.value.clone(),
+            None => ident.value.to_lowercase(),
+        })
+        .unwrap_or_default()
+}
+
+/// The [`NON_DETERMINISTIC_FUNCTIONS`] any statement in `query` calls, in list order
+///
+/// Queries that don't parse are matched on their text instead.
+fn find_non_deterministic_functions(query: &str) -> Vec<&'static str> {
+    let parsed_query = strip_table_samples(query, &find_table_samples(query));
+    let parsed_query = strip_delete_only(&parsed_query);
+    match Parser::parse_sql(&PostgreSqlDialect {}, &parsed_query) {
+        Ok(statements) => {
+            let calls: Vec<Vec<&'static str>> = statements.iter().map(find_non_deterministic_calls).collect();
+            NON_DETERMINISTIC_FUNCTIONS.iter()
+                .copied()
+                .filter(|function| calls.iter().any(|found| found.contains(function)))
+                .collect()
+        }
+        Err(_) => {
+            let query = query.to_lowercase();
+            NON_DETERMINISTIC_FUNCTIONS.iter()
+                .copied()
+                .filter(|function| query.contains(&function.to_lowercase()))
+                .collect()
+        }
+    }
+}
+
+/// The row-locking clauses of a query statement
+fn row_locks(statement: &Statement) -> &[LockClause] {
+    match statement {
+        Statement::Query(query) => &query.locks,
+        _ => &[],
+    }
+}
+
+/// Name of a row lock type as recorded in [`QueryMetadata::extra`]
+fn lock_type_name(lock_type: &LockType) -> &'static str {
+    match lock_type {
+        LockType::Update => "update",
+        LockType::Share => "share",
+    }
+}
+
+/// The `ON CONFLICT` action of an INSERT, `do_nothing` or `do_update`
+fn conflict_action(statement: &Statement) -> Option<&'static str> {
+    match statement {
+        Statement::Insert { on: Some(OnInsert::OnConflict(on_conflict)), .. } => match on_conflict.action {
+            OnConflictAction::DoNothing => Some("do_nothing"),
+            OnConflictAction::DoUpdate(_) => Some("do_update"),
+        },
+        _ => None,
+    }
+}
+
+/// Add the `found` tables not already in `tables`, leaving out references to `ctes`
+fn merge_tables(tables: &mut Vec<TableAccess>, found: Vec<TableAccess>, ctes: &[String]) {
+    for table in found {
+        let is_cte = table.schema_name.is_none() && ctes.contains(&table.table_name.to_lowercase());
+        let already_exists = tables.iter().any(|t| 
+            t.table_name == table.table_name && t.schema_name == table.schema_name
+        );
+        if !is_cte && !already_exists {
+            tables.push(table);
+        }
+    }
+}
+
+/// Names of the CTEs defined by `WITH RECURSIVE` clauses anywhere in `statement`, lowercase
+fn find_recursive_ctes(statement: &Statement) -> Vec<String> {
+    struct RecursiveCtes(Vec<String>);
+    
+    impl Visitor for RecursiveCtes {
+        type Break = ();
+        
+        fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<Self::Break> {
+            if let Some(with) = query.with.as_ref().filter(|with| with.recursive) {
+                self.0.extend(with.cte_tables.iter().map(|cte| cte.alias.name.value.to_lowercase()));
+            }
+            ControlFlow::Continue(())
+        }
+    }
+    
+    let mut ctes = RecursiveCtes(Vec::new());
+    let _ = statement.visit(&mut ctes);
+    ctes.0
+}
+
+/// The tables a DELETE removes rows from
+fn delete_targets(from: &FromTable) -> &[TableWithJoins] {
+    match from {
+        FromTable::WithFromKeyword(tables) | FromTable::WithoutKeyword(tables) => tables,
+    }
+}
+
+/// `query` without the `ONLY` of a `DELETE FROM ONLY table`
+///
+/// sqlparser doesn't parse `ONLY`, which keeps the DELETE from reaching
+/// tables inheriting from the target but doesn't change the target itself.
+fn strip_delete_only(query: &str) -> std::borrow::Cow<'_, str> {
+    let mut rest = query.trim_start();
+    let mut only_start = 0;
+    for keyword in ["delete", "from", "only"] {
+        only_start = query.len() - rest.len();
+        let (word, tail) = match rest.split_once(char::is_whitespace) {
+            Some(split) => split,
+            None => return std::borrow::Cow::Borrowed(query),
+        };
+        if !word.eq_ignore_ascii_case(keyword) {
+            return std::borrow::Cow::Borrowed(query);
+        }
+        rest = tail.trim_start();
+    }
+    std::borrow::Cow::Owned(format!("{}{}", &query[..only_start], rest))
+}
+
+/// `query` ending in a semicolon if it is a `COPY ... FROM STDIN`
+///
+/// sqlparser reads the rows of a `COPY FROM STDIN` inline after the
+/// statement's semicolon, so without one the statement alone doesn't parse.
+/// Over the wire the rows follow separately as CopyData.
+fn terminate_copy_from_stdin(query: &str) -> std::borrow::Cow<'_, str> {
+    let trimmed = query.trim_end();
+    if trimmed.ends_with(';') || copy_from_stdin(trimmed).is_none() {
+        return std::borrow::Cow::Borrowed(query);
+    }
+    std::borrow::Cow::Owned(format!("{};", trimmed))
+}
+
+/// The `$N` parameter placeholders of `query`, with their byte ranges and numbers
+///
+/// Placeholders inside string literals, quoted identifiers and dollar-quoted
+/// bodies are skipped.
+pub(crate) fn find_placeholders(query: &str) -> Vec<(std::ops::Range<usize>, usize)> {
+    let bytes = query.as_bytes();
+    let is_identifier = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || b == b'$';
+    
+    let mut placeholders = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'$' if i > 0 && is_identifier(bytes[i - 1]) => i += 1,
+            b'$' if bytes.get(i + 1).is_some_and(u8::is_ascii_digit) => {
+                let start = i;
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                if let Ok(index) = query[start + 1..i].parse() {
+                    placeholders.push((start..i, index));
+                }
+            }
+            b'$' => {
+                // A `$tag$` opens a dollar-quoted body that runs to the same tag
+                let tag_end = bytes[i + 1..]
+                    .iter()
+                    .position(|&b| !(b.is_ascii_alphanumeric() || b == b'_'))
+                    .map(|offset| i + 1 + offset);
+                match tag_end {
+                    Some(end) if bytes[end] == b'$' => {
+                        let tag = &query[i..=end];
+                        i = match query[end + 1..].find(tag) {
+                            Some(offset) => end + 1 + offset + tag.len(),
+                            None => bytes.len(),
+                        };
+                    }
+                    _ => i += 1,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    placeholders
+}
+
+/// `query` with each placeholder replaced by `replacement`
+fn replace_placeholders<'a>(
+    query: &'a str,
+    placeholders: &[(std::ops::Range<usize>, usize)],
+    replacement: &str,
+) -> std::borrow::Cow<'a, str> {
+    if placeholders.is_empty() {
+        return std::borrow::Cow::Borrowed(query);
+    }
+    
+    let mut replaced = String::with_capacity(query.len());
+    let mut position = 0;
+    for (span, _) in placeholders {
+        replaced.push_str(&query[position..span.start]);
+        replaced.push_str(replacement);
+        position = span.end;
+    }
+    replaced.push_str(&query[position..]);
+    std::borrow::Cow::Owned(replaced)
+}
+
+/// Type of a `PREPARE TRANSACTION`, `COMMIT PREPARED` or `ROLLBACK PREPARED` statement
+///
+/// The global identifier must be a single string literal, as PostgreSQL requires.
+fn two_phase_commit_type(query: &str) -> Option<QueryType> {
+    let query = query.trim().trim_end_matches(';').trim_end();
+    let (command, rest) = query.split_once(char::is_whitespace)?;
+    let (modifier, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+    
+    let literal = rest.trim().strip_prefix('\'')?.strip_suffix('\'')?;
+    if literal.replace("''", "").contains('\'') {
+        return None;
+    }
+    let gid = literal.replace("''", "'");
+    
+    match (command.to_ascii_lowercase().as_str(), modifier.to_ascii_lowercase().as_str()) {
+        ("prepare", "transaction") => Some(QueryType::PrepareTransaction(gid)),
+        ("commit", "prepared") => Some(QueryType::CommitPrepared(gid)),
+        ("rollback", "prepared") => Some(QueryType::RollbackPrepared(gid)),
+        _ => None,
+    }
+}
+
+/// A cursor statement, recognized before parsing
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CursorStatement {
+    /// `DECLARE name ... CURSOR ... FOR query`
+    Declare { name: String, query: String },
+    
+    /// `FETCH [direction] [FROM | IN] name`
+    Fetch(String),
+    
+    /// `MOVE [direction] [FROM | IN] name`
+    Move(String),
+}
+
+/// Parse a DECLARE, FETCH or MOVE statement
+///
+/// The statement's direction and cursor options don't affect which rows the
+/// cursor's query returns, so only the cursor's name and query are kept.
+fn parse_cursor_statement(query: &str) -> Option<CursorStatement> {
+    let query = query.trim().trim_end_matches(';').trim_end();
+    let (command, rest) = query.split_once(char::is_whitespace)?;
+    
+    match command.to_ascii_lowercase().as_str() {
+        "declare" => {
+            let (name, mut rest) = rest.trim_start().split_once(char::is_whitespace)?;
+            // Options up to CURSOR, then an optional WITH HOLD / WITHOUT HOLD up to FOR
+            let mut seen_cursor = false;
+            loop {
+                let (word, tail) = rest.trim_start().split_once(char::is_whitespace)?;
+                rest = tail;
+                match word.to_ascii_lowercase().as_str() {
+                    "cursor" => seen_cursor = true,
+                    "for" if seen_cursor => break,
+                    "binary" | "asensitive" | "insensitive" | "no" | "scroll" if !seen_cursor => {}
+                    "with" | "without" | "hold" if seen_cursor => {}
+                    _ => return None,
+                }
+            }
+            let cursor_query = rest.trim();
+            if cursor_query.is_empty() {
+                return None;
+            }
+            Some(CursorStatement::Declare {
+                name: cursor_identifier(name)?,
+                query: cursor_query.to_string(),
+            })
+        }
+        "fetch" => Some(CursorStatement::Fetch(cursor_identifier(rest.split_whitespace().last()?)?)),
+        "move" => Some(CursorStatement::Move(cursor_identifier(rest.split_whitespace().last()?)?)),
+        _ => None,
+    }
+}
+
+/// Normalize a cursor name: quoted names keep their case, others are lowercased
+fn cursor_identifier(word: &str) -> Option<String> {
+    if let Some(quoted) = word.strip_prefix('"').and_then(|w| w.strip_suffix('"')) {
+        return (!quoted.is_empty()).then(|| quoted.replace("\"\"", "\""));
+    }
+    let valid = word.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && word.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+    valid.then(|| word.to_lowercase())
+}
+
+/// A `TABLESAMPLE` clause of a query
+///
+/// A sample with a `REPEATABLE` seed selects the same rows on every run over
+/// the same data, so replay reproduces it, but only on the same PostgreSQL
+/// server version: the sampling methods' row selection is implementation-defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TableSample {
+    /// Sampling method, lowercased (e.g. `bernoulli`, `system`)
+    method: String,
+    
+    /// Seed expression given with `REPEATABLE`
+    seed: Option<String>,
+    
+    /// Byte range of the clause in the query
+    span: std::ops::Range<usize>,
+}
+
+/// The `TABLESAMPLE method (args) [REPEATABLE (seed)]` clauses of `query`, in order
+fn find_table_samples(query: &str) -> Vec<TableSample> {
+    let lowercase = query.to_ascii_lowercase();
+    let bytes = lowercase.as_bytes();
+    let is_identifier = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    
+    // Index just past the whitespace from `i`
+    let skip_whitespace = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        i
+    };
+    // Index just past the parenthesized group opening at `i`
+    let close_group = |i: usize| {
+        let mut depth = 0;
+        for (offset, &b) in bytes[i..].iter().enumerate() {
+            match b {
+                b'(' => depth += 1,
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i + offset + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    };
+    
+    let mut samples: Vec<TableSample> = Vec::new();
+    for (start, keyword) in lowercase.match_indices("tablesample") {
+        let end = start + keyword.len();
+        if (start > 0 && is_identifier(bytes[start - 1])) || bytes.get(end).is_some_and(|&b| is_identifier(b)) {
+            continue;
+        }
+        if samples.last().is_some_and(|sample| start < sample.span.end) {
+            continue;
+        }
+        
+        let method_start = skip_whitespace(end);
+        let mut method_end = method_start;
+        while method_end < bytes.len() && is_identifier(bytes[method_end]) {
+            method_end += 1;
+        }
+        let arguments_start = skip_whitespace(method_end);
+        if method_end == method_start || bytes.get(arguments_start) != Some(&b'(') {
+            continue;
+        }
+        let Some(mut clause_end) = close_group(arguments_start) else {
+            continue;
+        };
+        
+        let mut seed = None;
+        let repeatable_start = skip_whitespace(clause_end);
+        if lowercase[repeatable_start..].starts_with("repeatable") {
+            let seed_start = skip_whitespace(repeatable_start + "repeatable".len());
+            if bytes.get(seed_start) == Some(&b'(') {
+                if let Some(seed_end) = close_group(seed_start) {
+                    seed = Some(query[seed_start + 1..seed_end - 1].trim().to_string());
+                    clause_end = seed_end;
+                }
+            }
+        }
+        
+        samples.push(TableSample {
+            method: lowercase[method_start..method_end].to_string(),
+            seed,
+            span: start..clause_end,
+        });
+    }
+    samples
+}
+
+/// `query` with the given `TABLESAMPLE` clauses removed
+fn strip_table_samples<'a>(query: &'a str, samples: &[TableSample]) -> std::borrow::Cow<'a, str> {
+    if samples.is_empty() {
+        return std::borrow::Cow::Borrowed(query);
+    }
+    
+    let mut stripped = String::with_capacity(query.len());
+    let mut position = 0;
+    for sample in samples {
+        stripped.push_str(&query[position..sample.span.start]);
+        stripped.push(' ');
+        position = sample.span.end;
+    }
+    stripped.push_str(&query[position..]);
+    std::borrow::Cow::Owned(stripped)
+}
+
+/// Whether a table reference resolves to a system catalog
+///
+/// Unqualified `pg_` names resolve to `pg_catalog`, which PostgreSQL searches first.
+fn is_system_catalog(table: &TableAccess) -> bool {
+    match &table.schema_name {
+        Some(schema) => SYSTEM_CATALOG_SCHEMAS.contains(&schema.to_lowercase().as_str()),
+        None => table.table_name.to_lowercase().starts_with("pg_"),
+    }
+}
+
+/// Columns accessed per table, keyed by table and schema name
+type ColumnsByTable = HashMap<(String, Option<String>), BTreeSet<String>>;
+
+/// A table in a query scope, with the name its columns are qualified by
+struct ScopeTable {
+    /// Alias, or the table name if it has none, lowercase
+    qualifier: String,
+    
+    /// Table and schema name the table's columns are recorded under
+    key: (String, Option<String>),
+}
+
+/// Column references in an expression, outside the subqueries it contains
+///
+/// A subquery has its own scope, so its references aren't collected. Each
+/// reference is its qualifiers followed by the column name.
+#[derive(Default)]
+struct ColumnReferences {
+    references: Vec<Vec<Ident>>,
+    subquery_depth: usize,
+}
+
+impl Visitor for ColumnReferences {
+    type Break = ();
+    
+    fn pre_visit_query(&mut self, _query: &Query) -> ControlFlow<Self::Break> {
+        self.subquery_depth += 1;
+        ControlFlow::Continue(())
+    }
+    
+    fn post_visit_query(&mut self, _query: &Query) -> ControlFlow<Self::Break> {
+        self.subquery_depth -= 1;
+        ControlFlow::Continue(())
+    }
+    
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<Self::Break> {
+        if self.subquery_depth == 0 {
+            match expr {
+                Expr::Identifier(ident) => self.references.push(vec![ident.clone()]),
+                Expr::CompoundIdentifier(idents) => self.references.push(idents.clone()),
+                _ => {}
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Record the columns `expressions` and `using_columns` reference against the tables in `scope`
+///
+/// Unqualified references named in `aliases` are output columns, not table columns.
+fn record_column_references(
+    scope: &[ScopeTable],
+    expressions: &[&Expr],
+    using_columns: &[&Ident],
+    aliases: &HashSet<String>,
+    columns: &mut ColumnsByTable,
+) {
+    let mut references = ColumnReferences::default();
+    for expr in expressions {
+        let _ = expr.visit(&mut references);
+    }
+    references.references.extend(using_columns.iter().map(|ident| vec![(*ident).clone()]));
+    
+    for reference in &references.references {
+        let Some((column, qualifiers)) = reference.split_last() else {
+            continue;
+        };
+        let column = column.value.to_lowercase();
+        let tables: Vec<&ScopeTable> = match qualifiers.last() {
+            Some(qualifier) => {
+                let qualifier = qualifier.value.to_lowercase();
+                scope.iter().filter(|table| table.qualifier == qualifier).collect()
+            }
+            None if aliases.contains(&column) => continue,
+            None => scope.iter().collect(),
+        };
+        for table in tables {
+            columns.entry(table.key.clone()).or_default().insert(column.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interception::rewrite::fill_non_deterministic_defaults;
+    use verifiable_db_core::models::{ColumnDefinition, ColumnType, TriggerTiming};
+    
+    #[test]
+    fn test_analyze_select_query() {
+        let mut analyzer = QueryAnalyzer::new();
+        let query = "SELECT * FROM users WHERE id = 1";
+        let metadata = analyzer.analyze(query).unwrap();
+        
+        assert_eq!(metadata.query_type, QueryType::Select);
+        assert_eq!(metadata.tables.len(), 1);
+        assert_eq!(metadata.tables[0].table_name, "users");
+    }
+    
+    #[test]
+    fn test_analyze_insert_query() {
+        let mut analyzer = QueryAnalyzer::new();
+        let query = "INSERT INTO users (id, name) VALUES (1, 'John')";
+        let metadata = analyzer.analyze(query).unwrap();
+        
+        assert_eq!(metadata.query_type, QueryType::Insert);
+        assert_eq!(metadata.tables.len(), 1);
+        assert_eq!(metadata.tables[0].table_name, "users");
+        assert_eq!(metadata.tables[0].access_type, AccessType::Write);
+    }
+    
+    #[test]
+    fn test_analyze_update_query() {
+        let mut analyzer = QueryAnalyzer::new();
+        let query = "UPDATE users SET name = 'Jane' WHERE id = 1";
+        let metadata = analyzer.analyze(query).unwrap();
+        
+        assert_eq!(metadata.query_type, QueryType::Update);
+        assert_eq!(metadata.tables.len(), 1);
+        assert_eq!(metadata.tables[0].table_name, "users");
+        assert_eq!(metadata.tables[0].access_type, AccessType::ReadWrite);
+    }
+    
+    #[test]
+    fn test_analyze_delete_query() {
+        let mut analyzer = QueryAnalyzer::new();
+        let query = "DELETE FROM users WHERE id = 1";
+        let metadata = analyzer.analyze(query).unwrap();
+        
+        assert_eq!(metadata.query_type, QueryType::Delete);
+        assert_eq!(metadata.tables.len(), 1);
+        assert_eq!(metadata.tables[0].table_name, "users");
+        assert_eq!(metadata.tables[0].access_type, AccessType::Write);
+    }
     
     #[test]
     fn test_analyze_create_table_query() {
@@ -1137,7 +3232,43 @@ mod tests {
         assert!(!metadata.is_deterministic);
         assert!(metadata.non_deterministic_operations.len() > 0);
     }
-    
+
+    #[test]
+    fn test_non_deterministic_functions_found_by_call() {
+        let mut analyzer = QueryAnalyzer::new();
+
+        // Names that only contain a function's name aren't calls
+        for query in [
+            "SELECT random_seed FROM seeds WHERE id = 1 ORDER BY id",
+            "UPDATE seeds SET random_seed = 42, current_timestamp_utc = 0 WHERE id = 1",
+            "INSERT INTO notes (id, body) VALUES (1, 'call now() later')",
+            "INSERT INTO notes (id, body) VALUES (2, 'ids come from gen_random_uuid')",
+        ] {
+            let metadata = analyzer.analyze(query).unwrap();
+            assert!(metadata.is_deterministic, "{}", query);
+            assert!(analyzer.is_deterministic(query), "{}", query);
+            assert_eq!(analyzer.get_non_deterministic_reason(query), None, "{}", query);
+        }
+
+        // Calls are found regardless of case, spacing, qualification or aliasing
+        for (query, function) in [
+            ("SELECT RANDOM()", "random()"),
+            ("SELECT NOW () AS created", "now()"),
+            ("SELECT pg_catalog.now() AS created", "now()"),
+            ("UPDATE seeds SET touched = CURRENT_TIMESTAMP WHERE id = 1", "current_timestamp"),
+        ] {
+            let metadata = analyzer.analyze(query).unwrap();
+            assert!(!metadata.is_deterministic, "{}", query);
+            assert_eq!(metadata.non_deterministic_reason, Some(format!("Non-deterministic function: {}", function)));
+            assert!(!analyzer.is_deterministic(query), "{}", query);
+        }
+
+        // Entries with an argument only match calls passing it
+        let statement = |query: &str| Parser::parse_sql(&PostgreSqlDialect {}, query).unwrap().remove(0);
+        assert_eq!(find_non_deterministic_calls(&statement("SELECT lo_create(0)")), vec!["lo_create(0)"]);
+        assert!(find_non_deterministic_calls(&statement("SELECT lo_create(16400)")).is_empty());
+    }
+
     #[test]
     fn test_analyze_with_cte() {
         let mut analyzer = QueryAnalyzer::new();
@@ -1148,7 +3279,46 @@ mod tests {
         assert_eq!(metadata.tables.len(), 1);
         assert_eq!(metadata.tables[0].table_name, "users");
     }
-    
+
+    #[test]
+    fn test_analyze_recursive_cte() {
+        let mut analyzer = QueryAnalyzer::new();
+        let query = "WITH RECURSIVE subordinates AS (\
+                         SELECT id, manager_id FROM employees WHERE id = 1 \
+                         UNION ALL \
+                         SELECT e.id, e.manager_id FROM employees e JOIN subordinates s ON e.manager_id = s.id\
+                     ) SELECT id FROM subordinates ORDER BY id";
+        let metadata = analyzer.analyze(query).unwrap();
+
+        // The CTE's reference to itself isn't a table; the base table is found
+        let tables: Vec<&str> = metadata.tables.iter().map(|t| t.table_name.as_str()).collect();
+        assert_eq!(tables, vec!["employees"]);
+        assert_eq!(metadata.tables[0].access_type, AccessType::Read);
+        assert_eq!(metadata.extra.get("recursive_ctes").map(String::as_str), Some("subordinates"));
+
+        // Without RECURSIVE, a CTE's own name inside it is the table it shadows
+        let metadata = analyzer.analyze("WITH users AS (SELECT * FROM users WHERE active) SELECT * FROM users").unwrap();
+        let tables: Vec<&str> = metadata.tables.iter().map(|t| t.table_name.as_str()).collect();
+        assert_eq!(tables, vec!["users"]);
+        assert!(!metadata.extra.contains_key("recursive_ctes"));
+
+        // Extraction stops at MAX_QUERY_DEPTH instead of following arbitrarily deep nesting
+        let nested = |levels: usize| {
+            let mut query = match Parser::parse_sql(&PostgreSqlDialect {}, "SELECT id FROM employees").unwrap().remove(0) {
+                Statement::Query(query) => query,
+                _ => unreachable!(),
+            };
+            for _ in 0..levels {
+                let mut outer = query.clone();
+                outer.body = Box::new(SetExpr::Query(query));
+                query = outer;
+            }
+            Statement::Query(query)
+        };
+        assert_eq!(analyzer.extract_tables(&nested(MAX_QUERY_DEPTH), &QueryType::Select).len(), 1);
+        assert!(analyzer.extract_tables(&nested(MAX_QUERY_DEPTH + 1), &QueryType::Select).is_empty());
+    }
+
     #[test]
     fn test_analyze_joins() {
         let mut analyzer = QueryAnalyzer::new();
@@ -1161,7 +3331,134 @@ mod tests {
         assert!(table_names.contains(&"users".to_string()));
         assert!(table_names.contains(&"orders".to_string()));
     }
-    
+
+    #[test]
+    fn test_column_access() {
+        let mut analyzer = QueryAnalyzer::new();
+        let mut columns = |query: &str, table: &str| -> Option<Vec<String>> {
+            let metadata = analyzer.analyze(query).unwrap();
+            metadata.tables.into_iter().find(|t| t.table_name == table).unwrap().columns
+        };
+        let expected = |names: &[&str]| Some(names.iter().map(|name| name.to_string()).collect::<Vec<_>>());
+
+        // Joined columns go to the table their alias names
+        let query = "SELECT u.name, o.total FROM users u JOIN orders o ON u.id = o.user_id WHERE o.status = 'paid' ORDER BY o.total";
+        assert_eq!(columns(query, "users"), expected(&["id", "name"]));
+        assert_eq!(columns(query, "orders"), expected(&["status", "total", "user_id"]));
+
+        // Wildcards read every column, qualified ones only of their table
+        assert_eq!(columns("SELECT * FROM users WHERE id = 1", "users"), expected(&[ALL_COLUMNS]));
+        let query = "SELECT u.*, o.total FROM users u LEFT JOIN orders o ON u.id = o.user_id";
+        assert_eq!(columns(query, "users"), expected(&[ALL_COLUMNS]));
+        assert_eq!(columns(query, "orders"), expected(&["total", "user_id"]));
+
+        // Output aliases in ORDER BY and columns of subqueries aren't the outer table's
+        let query = "SELECT name AS n FROM users WHERE id IN (SELECT user_id FROM orders WHERE total > 10) ORDER BY n";
+        assert_eq!(columns(query, "users"), expected(&["id", "name"]));
+
+        // INSERT writes its column list, or every column without one
+        assert_eq!(columns("INSERT INTO users (id, name) VALUES (1, 'John')", "users"), expected(&["id", "name"]));
+        assert_eq!(columns("INSERT INTO users VALUES (1, 'John')", "users"), expected(&[ALL_COLUMNS]));
+
+        // UPDATE accesses its assigned columns and the columns it reads
+        assert_eq!(columns("UPDATE users SET name = upper(nickname) WHERE id = 1", "users"), expected(&["id", "name", "nickname"]));
+
+        // Statements not analyzed for columns leave them unknown
+        assert_eq!(columns("CREATE TABLE accounts (id INTEGER PRIMARY KEY)", "accounts"), None);
+    }
+
+    #[test]
+    fn test_upsert_detection() {
+        let mut analyzer = QueryAnalyzer::new();
+
+        // A plain INSERT only writes its table
+        let metadata = analyzer.analyze("INSERT INTO users (id, name) VALUES (1, 'John')").unwrap();
+        assert!(!metadata.is_upsert());
+        assert_eq!(metadata.tables[0].access_type, AccessType::Write);
+
+        // DO NOTHING still reads the existing rows to find the conflict
+        let metadata = analyzer.analyze("INSERT INTO users (id, name) VALUES (1, 'John') ON CONFLICT (id) DO NOTHING").unwrap();
+        assert_eq!(metadata.query_type, QueryType::Insert);
+        assert!(metadata.is_upsert());
+        assert_eq!(metadata.extra.get("conflict_action").map(String::as_str), Some("do_nothing"));
+        assert_eq!(metadata.tables[0].access_type, AccessType::ReadWrite);
+        assert_eq!(metadata.get_modified_tables(), vec!["users".to_string()]);
+        assert_eq!(metadata.get_read_tables(), vec!["users".to_string()]);
+
+        // DO UPDATE rewrites the conflicting row
+        let query = "INSERT INTO users (id, name) VALUES (1, 'John') \
+                     ON CONFLICT (id) DO UPDATE SET visits = users.visits + 1, name = EXCLUDED.name";
+        let metadata = analyzer.analyze(query).unwrap();
+        assert!(metadata.is_upsert());
+        assert_eq!(metadata.extra.get("upsert").map(String::as_str), Some("true"));
+        assert_eq!(metadata.extra.get("conflict_action").map(String::as_str), Some("do_update"));
+        assert_eq!(metadata.tables[0].access_type, AccessType::ReadWrite);
+        assert_eq!(metadata.tables[0].columns, Some(vec!["id".to_string(), "name".to_string(), "visits".to_string()]));
+        assert!(metadata.is_deterministic);
+        assert!(metadata.verifiable);
+
+        // Non-deterministic assignments are still found
+        let query = "INSERT INTO users (id) VALUES (1) ON CONFLICT (id) DO UPDATE SET seen_at = now()";
+        assert!(!analyzer.analyze(query).unwrap().is_deterministic);
+    }
+
+    #[test]
+    fn test_truncate_and_merge() {
+        let mut analyzer = QueryAnalyzer::new();
+
+        // TRUNCATE wipes its table, so it's verified like other DML
+        let metadata = analyzer.analyze("TRUNCATE TABLE orders").unwrap();
+        assert_eq!(metadata.query_type, QueryType::Truncate);
+        assert!(metadata.query_type.is_dml());
+        assert!(metadata.modifies_data());
+        assert!(metadata.verifiable);
+        assert_eq!(metadata.tables[0].access_type, AccessType::Write);
+        assert_eq!(metadata.get_modified_tables(), vec!["orders".to_string()]);
+
+        // MERGE reads and changes its target and only reads its source
+        let query = "MERGE INTO accounts a USING staged s ON a.id = s.id \
+                     WHEN MATCHED THEN UPDATE SET balance = s.balance \
+                     WHEN NOT MATCHED THEN INSERT (id, balance) VALUES (s.id, s.balance)";
+        let metadata = analyzer.analyze(query).unwrap();
+        assert_eq!(metadata.query_type, QueryType::Merge);
+        assert!(metadata.query_type.is_dml());
+        assert!(metadata.verifiable);
+        assert_eq!(metadata.get_modified_tables(), vec!["accounts".to_string()]);
+        assert_eq!(metadata.get_read_tables(), vec!["accounts".to_string(), "staged".to_string()]);
+
+        // MERGE may fire the target's UPDATE triggers, TRUNCATE only its TRUNCATE triggers
+        analyzer.register_triggers("accounts", vec![TriggerDefinition {
+            name: "stamp".to_string(),
+            timing: TriggerTiming::Before,
+            events: vec![TriggerEvent::Update],
+            for_each_row: true,
+            function_name: "stamp_fn".to_string(),
+            function_definition: "BEGIN NEW.updated_at := now(); RETURN NEW; END".to_string(),
+        }]);
+        assert!(!analyzer.analyze(query).unwrap().verifiable);
+        assert!(analyzer.analyze("TRUNCATE TABLE accounts").unwrap().verifiable);
+    }
+
+    #[test]
+    fn test_analyze_copy() {
+        let mut analyzer = QueryAnalyzer::new();
+
+        // COPY FROM STDIN writes its table, with or without a closing semicolon
+        for query in ["COPY orders (id, total) FROM STDIN", "COPY orders FROM STDIN WITH (FORMAT csv);"] {
+            let metadata = analyzer.analyze(query).unwrap();
+            assert_eq!(metadata.query_type, QueryType::Copy, "{}", query);
+            assert!(metadata.modifies_data());
+            assert_eq!(metadata.get_modified_tables(), vec!["orders".to_string()], "{}", query);
+        }
+
+        // COPY TO only reads
+        let metadata = analyzer.analyze("COPY orders TO STDOUT").unwrap();
+        assert!(metadata.get_modified_tables().is_empty());
+        assert_eq!(metadata.get_read_tables(), vec!["orders".to_string()]);
+
+        assert_eq!(terminate_copy_from_stdin("COPY orders TO STDOUT"), "COPY orders TO STDOUT");
+    }
+
     #[test]
     fn test_analyze_nested_joins() {
         let mut analyzer = QueryAnalyzer::new();
@@ -1232,8 +3529,23 @@ mod tests {
             .filter(|t| t.access_type == AccessType::Read)
             .map(|t| t.table_name.clone())
             .collect();
-        assert!(write_tables.contains(&"users".to_string()));
-        assert!(read_tables.contains(&"inactive_accounts".to_string()));
+        assert_eq!(write_tables, vec!["users".to_string()]);
+        assert_eq!(read_tables, vec!["inactive_accounts".to_string()]);
+        assert_eq!(metadata.get_modified_tables(), vec!["users".to_string()]);
+        
+        // A deleted row loses every column; USING tables are read through the join condition
+        assert_eq!(metadata.tables[0].columns, Some(vec![ALL_COLUMNS.to_string()]));
+        assert_eq!(metadata.tables[1].columns, Some(vec!["user_id".to_string()]));
+        
+        // ONLY doesn't change the table deleted from
+        for query in ["DELETE FROM ONLY users WHERE id = 1", "delete from only public.users where id = 1"] {
+            let metadata = analyzer.analyze(query).unwrap();
+            assert_eq!(metadata.query_type, QueryType::Delete);
+            assert_eq!(metadata.tables.len(), 1, "{}", query);
+            assert_eq!(metadata.tables[0].table_name, "users");
+            assert_eq!(metadata.tables[0].access_type, AccessType::Write);
+        }
+        assert_eq!(strip_delete_only("DELETE FROM only_table"), "DELETE FROM only_table");
     }
     
     #[test]
@@ -1267,7 +3579,378 @@ mod tests {
                            ORDER BY order_count DESC";
         let complex_metadata = analyzer.analyze(complex_query).unwrap();
         
-        assert!(complex_metadata.complexity > simple_metadata.complexity);
+        assert!(complex_metadata.complexity_score > simple_metadata.complexity_score);
+    }
+    
+    #[test]
+    fn test_window_and_aggregate_complexity() {
+        let mut analyzer = QueryAnalyzer::new();
+        let mut score = |query: &str| analyzer.analyze(query).unwrap().complexity_score;
+        
+        // A window function outweighs the plain aggregate computing the same sums
+        let aggregate = score("SELECT user_id, SUM(total) FROM orders GROUP BY user_id ORDER BY user_id");
+        let windowed = score("SELECT user_id, SUM(total) OVER (PARTITION BY user_id) FROM orders ORDER BY user_id");
+        assert!(windowed > aggregate, "windowed {} <= aggregate {}", windowed, aggregate);
+        
+        // DISTINCT aggregates outweigh plain ones, which outweigh scalar functions
+        let distinct = score("SELECT COUNT(DISTINCT user_id) FROM orders");
+        let counted = score("SELECT COUNT(user_id) FROM orders");
+        let scalar = score("SELECT UPPER(status) FROM orders");
+        assert!(distinct > counted);
+        assert!(counted > scalar);
+        
+        // Function arguments are scored too
+        assert!(score("SELECT SUM(total * (1 + tax)) FROM orders") > score("SELECT SUM(total) FROM orders"));
+    }
+    
+    #[test]
+    fn test_order_sensitive_aggregates() {
+        let mut analyzer = QueryAnalyzer::new();
+        let is_aggregate_op = |op: &NonDeterministicOperation| op.operation_type == "Aggregate";
+        
+        let unordered = analyzer.analyze("SELECT array_agg(x) FROM t ORDER BY 1").unwrap();
+        assert!(!unordered.is_deterministic);
+        assert!(unordered.non_deterministic_operations.iter().any(is_aggregate_op));
+        
+        let ordered = analyzer.analyze("SELECT array_agg(x ORDER BY x) FROM t ORDER BY 1").unwrap();
+        assert!(!ordered.non_deterministic_operations.iter().any(is_aggregate_op));
+        
+        let count = analyzer.analyze("SELECT count(*) FROM t ORDER BY 1").unwrap();
+        assert!(!count.non_deterministic_operations.iter().any(is_aggregate_op));
+        
+        assert!(!analyzer.is_deterministic("SELECT string_agg(name, ',') FROM t ORDER BY 1"));
+        
+        // Only calls count, not names or strings that mention an aggregate
+        let mentioned = analyzer.analyze("SELECT array_agg_total, 'json_agg(x)' FROM t ORDER BY 1").unwrap();
+        assert!(!mentioned.non_deterministic_operations.iter().any(is_aggregate_op));
+        assert!(!analyzer.is_deterministic("SELECT JSON_AGG (x) FROM t ORDER BY 1"));
+        
+        // Queries that don't parse are still checked, on their text
+        assert_eq!(analyzer.find_unordered_aggregates("SELECT string_agg(name, ',') FROM t WHERE ORDER BY 1"), vec!["string_agg"]);
+        assert!(analyzer.find_unordered_aggregates("SELECT string_agg(name, ',' ORDER BY name) FROM t WHERE ORDER BY 1").is_empty());
+    }
+    
+    #[test]
+    fn test_allow_aggregate() {
+        let mut analyzer = QueryAnalyzer::new();
+        let query = "SELECT array_agg(x) FROM t ORDER BY 1";
+        assert!(!analyzer.analyze(query).unwrap().is_deterministic);
+        
+        // Allowlisting the aggregate also invalidates cached analysis
+        analyzer.allow_aggregate("ARRAY_AGG");
+        assert!(analyzer.analyze(query).unwrap().is_deterministic);
+    }
+    
+    #[test]
+    fn test_non_deterministic_trigger() {
+        let mut analyzer = QueryAnalyzer::new();
+        let trigger = |name: &str, body: &str| TriggerDefinition {
+            name: name.to_string(),
+            timing: TriggerTiming::Before,
+            events: vec![TriggerEvent::Insert, TriggerEvent::Update],
+            for_each_row: true,
+            function_name: format!("{}_fn", name),
+            function_definition: body.to_string(),
+        };
+        
+        analyzer.register_triggers("orders", vec![trigger("set_total", "BEGIN NEW.total := NEW.qty * NEW.price; RETURN NEW; END")]);
+        let metadata = analyzer.analyze("INSERT INTO orders (qty, price) VALUES (1, 2)").unwrap();
+        assert!(metadata.is_deterministic);
+        assert!(metadata.verifiable);
+        
+        // Registering a non-deterministic trigger invalidates cached analysis
+        analyzer.register_triggers("orders", vec![trigger("stamp", "BEGIN NEW.created_at := now(); RETURN NEW; END")]);
+        let metadata = analyzer.analyze("INSERT INTO orders (qty, price) VALUES (1, 2)").unwrap();
+        assert!(!metadata.is_deterministic);
+        assert!(!metadata.verifiable);
+        assert!(metadata.non_deterministic_operations.iter().any(|op| op.operation_type == "Trigger"));
+        
+        // DELETE doesn't fire the trigger
+        let metadata = analyzer.analyze("DELETE FROM orders WHERE id = 1").unwrap();
+        assert!(!metadata.non_deterministic_operations.iter().any(|op| op.operation_type == "Trigger"));
+    }
+    
+    #[test]
+    fn test_system_catalog_reads_are_metadata_queries() {
+        let mut analyzer = QueryAnalyzer::new();
+        
+        let metadata = analyzer.analyze("SELECT * FROM pg_stat_activity").unwrap();
+        assert!(metadata.metadata_query);
+        assert!(!metadata.verifiable);
+        assert!(metadata.tables.is_empty());
+        assert!(metadata.get_read_tables().is_empty());
+        assert_eq!(metadata.extra.get("system_catalogs").map(String::as_str), Some("pg_stat_activity"));
+        
+        let metadata = analyzer.analyze("SELECT table_name FROM information_schema.tables").unwrap();
+        assert!(metadata.metadata_query);
+        
+        // A user table read is not a metadata query, and the catalog is not tracked
+        let metadata = analyzer.analyze("SELECT u.id FROM users u JOIN pg_catalog.pg_class c ON c.relname = u.name").unwrap();
+        assert!(!metadata.metadata_query);
+        assert_eq!(metadata.get_read_tables(), vec!["users".to_string()]);
+    }
+    
+    #[test]
+    fn test_non_deterministic_view() {
+        let mut analyzer = QueryAnalyzer::new();
+        analyzer.register_view("active_users", "SELECT id, name FROM users WHERE active");
+        analyzer.register_view("public.recent_orders", "SELECT id, user_id, now() AS seen_at FROM orders");
+        
+        // A deterministic view reads through to its table
+        let metadata = analyzer.analyze("SELECT id FROM active_users ORDER BY id").unwrap();
+        assert!(metadata.is_deterministic);
+        assert_eq!(metadata.get_read_tables(), vec!["users".to_string()]);
+        assert_eq!(metadata.extra.get("views").map(String::as_str), Some("active_users"));
+        
+        // A view over now() makes selects from it non-deterministic
+        let metadata = analyzer.analyze("SELECT id FROM recent_orders ORDER BY id").unwrap();
+        assert!(!metadata.is_deterministic);
+        assert!(!metadata.verifiable);
+        assert_eq!(metadata.get_read_tables(), vec!["orders".to_string()]);
+        assert!(metadata.non_deterministic_operations.iter()
+            .any(|op| op.operation_type == "View" && !op.can_fix_automatically));
+        
+        // ...including when the select feeds a write
+        let metadata = analyzer.analyze("INSERT INTO archive SELECT id FROM public.recent_orders ORDER BY id").unwrap();
+        assert!(!metadata.verifiable);
+        assert!(metadata.get_read_tables().contains(&"orders".to_string()));
+        
+        // Without expansion the view is opaque
+        analyzer.set_expand_views(false);
+        let metadata = analyzer.analyze("SELECT id FROM recent_orders ORDER BY id").unwrap();
+        assert!(metadata.is_deterministic);
+        assert_eq!(metadata.get_read_tables(), vec!["recent_orders".to_string()]);
+    }
+    
+    #[test]
+    fn test_non_deterministic_column_default() {
+        let mut analyzer = QueryAnalyzer::new();
+        let columns = vec![
+            ColumnDefinition {
+                name: "id".to_string(),
+                column_type: ColumnType::Integer,
+                nullable: false,
+                primary_key: true,
+                unique: true,
+                default_value: None,
+            },
+            ColumnDefinition {
+                name: "created_at".to_string(),
+                column_type: ColumnType::Timestamp,
+                nullable: false,
+                primary_key: false,
+                unique: false,
+                default_value: Some("now()".to_string()),
+            },
+        ];
+        let schema = TableSchema::new("events".to_string(), columns, vec!["id".to_string()], vec![], vec![]);
+        analyzer.register_column_defaults(&schema);
+        
+        // Omitting the column leaves it to now()
+        let query = "INSERT INTO events (id) VALUES (1), (2)";
+        let metadata = analyzer.analyze(query).unwrap();
+        assert!(!metadata.is_deterministic);
+        assert!(metadata.verifiable);
+        assert!(metadata.non_deterministic_operations.iter()
+            .any(|op| op.operation_type == "Default" && op.can_fix_automatically));
+        
+        // The replay supplies the deterministic timestamp instead
+        let defaults = HashMap::from([(
+            "events".to_string(),
+            schema.columns.iter().map(|c| (c.name.clone(), c.default_value.clone())).collect(),
+        )]);
+        assert_eq!(
+            fill_non_deterministic_defaults(query, &defaults, 7, 7).as_deref(),
+            Some("INSERT INTO events (id, created_at) VALUES (1, verification_timestamp()), (2, verification_timestamp())")
+        );
+        assert!(fill_non_deterministic_defaults("INSERT INTO events VALUES (3, DEFAULT)", &defaults, 7, 7)
+            .is_some_and(|q| q.contains("verification_timestamp()")));
+        
+        // Supplying the column explicitly is deterministic
+        let metadata = analyzer.analyze("INSERT INTO events (id, created_at) VALUES (1, '2024-01-01')").unwrap();
+        assert!(metadata.is_deterministic);
+        
+        // ...but INSERT ... SELECT can't be given a value per row
+        let metadata = analyzer.analyze("INSERT INTO events (id) SELECT id FROM staging ORDER BY id").unwrap();
+        assert!(!metadata.verifiable);
+    }
+    
+    #[test]
+    fn test_large_object_functions() {
+        let mut analyzer = QueryAnalyzer::new();
+        
+        // Creating a large object from a SELECT modifies pg_largeobject
+        let metadata = analyzer.analyze(r"SELECT lo_from_bytea(0, '\x68656c6c6f')").unwrap();
+        assert!(metadata.writes_large_objects());
+        assert!(metadata.modifies_data());
+        assert!(metadata.verifiable);
+        assert!(!metadata.cacheable);
+        assert_eq!(metadata.get_modified_tables(), vec![LARGE_OBJECT_TABLE.to_string()]);
+        // The server-assigned OID is replaced with a deterministic one
+        assert!(metadata.non_deterministic_operations.iter()
+            .any(|op| op.operation_type == "Function" && op.can_fix_automatically));
+        
+        // Unlinking through the catalog isn't a metadata query
+        let metadata = analyzer.analyze("SELECT lo_unlink(oid) FROM pg_largeobject_metadata ORDER BY oid").unwrap();
+        assert!(metadata.writes_large_objects());
+        assert!(!metadata.metadata_query);
+        
+        // Reads leave the state alone, and lo_creat doesn't match lo_create
+        let metadata = analyzer.analyze("SELECT lo_get(16400)").unwrap();
+        assert!(!metadata.writes_large_objects());
+        assert_eq!(find_function_calls("SELECT lo_create(16400)", LARGE_OBJECT_WRITE_FUNCTIONS), vec!["lo_create"]);
+        
+        // Names in strings and comments aren't calls
+        let metadata = analyzer.analyze("SELECT 'lo_unlink(1)' AS note -- lo_write(0, 'x')").unwrap();
+        assert!(!metadata.writes_large_objects());
+        
+        // ...except in queries that don't parse, which are matched on their text
+        assert_eq!(find_function_calls("SELECT lo_unlink(1) FROM t WHERE", LARGE_OBJECT_WRITE_FUNCTIONS), vec!["lo_unlink"]);
+        
+        // Server files can't be reproduced by the replay
+        let metadata = analyzer.analyze("SELECT lo_import('/tmp/report.pdf')").unwrap();
+        assert!(metadata.writes_large_objects());
+        assert!(!metadata.verifiable);
+    }
+    
+    #[test]
+    fn test_session_state_functions() {
+        let mut analyzer = QueryAnalyzer::new();
+        let is_session_state = |op: &NonDeterministicOperation| op.operation_type == "SessionState";
+        
+        // Each connection sees its own backend and client address
+        for query in ["SELECT pg_backend_pid()", "SELECT inet_client_addr()"] {
+            let metadata = analyzer.analyze(query).unwrap();
+            assert!(!metadata.is_deterministic);
+            assert!(!metadata.verifiable);
+            assert!(!metadata.cacheable);
+            assert!(metadata.non_deterministic_operations.iter().any(is_session_state));
+            assert!(!analyzer.is_deterministic(query));
+            assert!(analyzer.get_non_deterministic_reason(query).unwrap().contains("session state"));
+        }
+        
+        // Writing session state can't be replayed
+        let query = "INSERT INTO audit (pid, setting) VALUES (pg_backend_pid(), current_setting('work_mem'))";
+        let metadata = analyzer.analyze(query).unwrap();
+        assert!(!metadata.verifiable);
+        assert_eq!(metadata.non_deterministic_operations.iter().filter(|op| is_session_state(op)).count(), 2);
+        
+        // Columns named like the functions aren't calls
+        let metadata = analyzer.analyze("SELECT version FROM releases ORDER BY version").unwrap();
+        assert!(!metadata.non_deterministic_operations.iter().any(is_session_state));
+        let metadata = analyzer.analyze("SELECT 'pg_backend_pid()' AS label FROM releases ORDER BY 1").unwrap();
+        assert!(!metadata.non_deterministic_operations.iter().any(is_session_state));
+        assert!(!analyzer.is_deterministic("SELECT PG_BACKEND_PID ()"));
+        assert!(!analyzer.is_deterministic("SELECT pg_backend_pid() FROM t WHERE"));
+        
+        // Detection can be turned off
+        analyzer.set_detect_session_state_functions(false);
+        let metadata = analyzer.analyze(query).unwrap();
+        assert!(metadata.verifiable);
+        assert!(!metadata.non_deterministic_operations.iter().any(is_session_state));
+    }
+    
+    #[test]
+    fn test_cursor_ordering() {
+        let mut analyzer = QueryAnalyzer::new();
+        
+        // Pages of a cursor over an unordered query differ between runs
+        let metadata = analyzer.analyze("DECLARE unordered CURSOR FOR SELECT id, name FROM users").unwrap();
+        assert_eq!(metadata.query_type, QueryType::DeclareCursor("unordered".to_string()));
+        assert!(!metadata.is_deterministic);
+        assert!(!metadata.verifiable);
+        assert!(metadata.non_deterministic_operations.iter()
+            .any(|op| op.operation_type == "Unordered" && !op.can_fix_automatically));
+        let metadata = analyzer.analyze("FETCH 10 FROM unordered").unwrap();
+        assert_eq!(metadata.query_type, QueryType::Fetch("unordered".to_string()));
+        assert!(!metadata.is_deterministic);
+        assert!(!metadata.verifiable);
+        
+        // With an ORDER BY, fetched rows are tracked against the cursor's tables
+        let metadata = analyzer.analyze("DECLARE \"Ordered\" NO SCROLL CURSOR WITH HOLD FOR SELECT id, name FROM users ORDER BY id").unwrap();
+        assert!(metadata.is_deterministic);
+        assert!(metadata.verifiable);
+        let metadata = analyzer.analyze("FETCH FORWARD 5 IN \"Ordered\";").unwrap();
+        assert_eq!(metadata.query_type, QueryType::Fetch("Ordered".to_string()));
+        assert!(metadata.is_deterministic);
+        assert!(metadata.verifiable);
+        assert!(metadata.query_type.is_read_only());
+        assert!(!metadata.cacheable);
+        assert_eq!(metadata.tables[0].table_name, "users");
+        let metadata = analyzer.analyze("MOVE LAST IN \"Ordered\"").unwrap();
+        assert_eq!(metadata.query_type, QueryType::Move("Ordered".to_string()));
+        assert!(metadata.verifiable);
+        
+        // Set operations need a total order, as in a plain SELECT
+        let metadata = analyzer.analyze("DECLARE c CURSOR FOR SELECT a FROM t1 UNION SELECT a FROM t2").unwrap();
+        assert!(!metadata.verifiable);
+        
+        // Cursors declared outside the proxy can't be checked
+        let metadata = analyzer.analyze("FETCH NEXT FROM elsewhere").unwrap();
+        assert!(!metadata.is_deterministic);
+        assert!(!metadata.verifiable);
+    }
+    
+    #[test]
+    fn test_table_sample() {
+        let mut analyzer = QueryAnalyzer::new();
+        let is_table_sample = |op: &NonDeterministicOperation| op.operation_type == "TableSample";
+        
+        // A sample drawn without a seed differs from run to run
+        let query = "SELECT id FROM users TABLESAMPLE BERNOULLI (10) ORDER BY id";
+        let metadata = analyzer.analyze(query).unwrap();
+        assert!(!metadata.is_deterministic);
+        assert!(metadata.non_deterministic_operations.iter().any(is_table_sample));
+        assert_eq!(metadata.tables[0].table_name, "users");
+        assert!(!analyzer.is_deterministic(query));
+        assert_eq!(analyzer.get_non_deterministic_reason(query).as_deref(), Some("TABLESAMPLE bernoulli without REPEATABLE seed"));
+        
+        let metadata = analyzer.analyze("INSERT INTO sampled SELECT id FROM users TABLESAMPLE BERNOULLI (10)").unwrap();
+        assert!(!metadata.verifiable);
+        
+        // A seeded sample is reproduced by replaying the same statement
+        let metadata = analyzer.analyze("INSERT INTO sampled SELECT u.id FROM users AS u TABLESAMPLE system(5) REPEATABLE (42)").unwrap();
+        assert!(!metadata.non_deterministic_operations.iter().any(is_table_sample));
+        assert!(metadata.verifiable);
+        assert!(metadata.tables.iter().any(|t| t.table_name == "users"));
+        
+        let samples = find_table_samples("select * from a tablesample system (1) repeatable (7), b TABLESAMPLE Bernoulli(50)");
+        assert_eq!(samples.len(), 2);
+        assert_eq!((samples[0].method.as_str(), samples[0].seed.as_deref()), ("system", Some("7")));
+        assert_eq!((samples[1].method.as_str(), samples[1].seed.as_deref()), ("bernoulli", None));
+        assert!(find_table_samples("SELECT tablesample_id FROM t ORDER BY 1").is_empty());
+    }
+    
+    #[test]
+    fn test_unordered_set_operation() {
+        let mut analyzer = QueryAnalyzer::new();
+        let is_set_operation = |op: &NonDeterministicOperation| op.operation_type == "SetOperation";
+        
+        let metadata = analyzer.analyze("SELECT a FROM t1 UNION SELECT a FROM t2").unwrap();
+        assert!(!metadata.is_deterministic);
+        assert!(metadata.non_deterministic_operations.iter().any(is_set_operation));
+        
+        // Ordering each input doesn't order a deduplicated result
+        let metadata = analyzer.analyze("(SELECT a FROM t1 ORDER BY a) INTERSECT (SELECT a FROM t2 ORDER BY a)").unwrap();
+        assert!(metadata.non_deterministic_operations.iter().any(is_set_operation));
+        
+        // ...but it does order UNION ALL
+        let metadata = analyzer.analyze("(SELECT a FROM t1 ORDER BY a) UNION ALL (SELECT a FROM t2 ORDER BY a)").unwrap();
+        assert!(!metadata.non_deterministic_operations.iter().any(is_set_operation));
+        let metadata = analyzer.analyze("(SELECT a FROM t1 ORDER BY a) UNION ALL SELECT a FROM t2").unwrap();
+        assert!(metadata.non_deterministic_operations.iter().any(is_set_operation));
+        
+        // The final ORDER BY must cover every output column
+        let metadata = analyzer.analyze("SELECT a, b FROM t1 EXCEPT SELECT a, b FROM t2 ORDER BY a").unwrap();
+        assert!(metadata.non_deterministic_operations.iter().any(is_set_operation));
+        let metadata = analyzer.analyze("SELECT a, b FROM t1 UNION SELECT a, b FROM t2 ORDER BY a, 2").unwrap();
+        assert!(metadata.is_deterministic);
+        
+        analyzer.set_enforce_set_operation_order(false);
+        let metadata = analyzer.analyze("SELECT a FROM t1 UNION SELECT a FROM t2 ORDER BY a").unwrap();
+        assert!(metadata.is_deterministic);
+        let metadata = analyzer.analyze("(SELECT a FROM t1 ORDER BY a) UNION (SELECT a FROM t2 ORDER BY a)").unwrap();
+        assert!(!metadata.non_deterministic_operations.iter().any(is_set_operation));
     }
     
     #[test]
@@ -1293,5 +3976,186 @@ mod tests {
         let savepoint_query = "SAVEPOINT my_savepoint";
         let savepoint_metadata = analyzer.analyze(savepoint_query).unwrap();
         assert_eq!(savepoint_metadata.query_type, QueryType::Savepoint);
+        
+        // Test the two-phase commit statements
+        let prepare_metadata = analyzer.analyze("PREPARE TRANSACTION 'tx-1'").unwrap();
+        assert_eq!(prepare_metadata.query_type, QueryType::PrepareTransaction("tx-1".to_string()));
+        assert!(prepare_metadata.query_type.is_transaction_control());
+        assert!(!prepare_metadata.verifiable);
+        
+        let commit_prepared = analyzer.analyze("commit  prepared 'it''s';").unwrap();
+        assert_eq!(commit_prepared.query_type, QueryType::CommitPrepared("it's".to_string()));
+        assert_eq!(commit_prepared.query_type.prepared_transaction_id(), Some("it's"));
+        
+        let rollback_prepared = analyzer.analyze("ROLLBACK PREPARED 'tx-1'").unwrap();
+        assert_eq!(rollback_prepared.query_type, QueryType::RollbackPrepared("tx-1".to_string()));
+        
+        // PREPARE of a named statement is not two-phase commit
+        let prepare_statement = analyzer.analyze("PREPARE get_user AS SELECT * FROM users WHERE id = $1").unwrap();
+        assert!(!matches!(prepare_statement.query_type, QueryType::PrepareTransaction(_)));
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let mut analyzer = QueryAnalyzer::new();
+        analyzer.max_cache_size = 3;
+        
+        let hot = "SELECT * FROM users WHERE id = 1";
+        analyzer.analyze(hot).unwrap();
+        
+        for i in 0..10 {
+            analyzer.analyze(&format!("SELECT * FROM orders WHERE id = {}", i)).unwrap();
+            analyzer.analyze(hot).unwrap();
+        }
+        
+        assert_eq!(analyzer.query_cache.len(), 3);
+        assert_eq!(analyzer.cache_order.len(), 3);
+        assert!(analyzer.query_cache.contains_key(hot));
+        assert!(analyzer.query_cache.contains_key("SELECT * FROM orders WHERE id = 9"));
+        assert!(analyzer.query_cache.contains_key("SELECT * FROM orders WHERE id = 8"));
+        assert!(!analyzer.query_cache.contains_key("SELECT * FROM orders WHERE id = 0"));
+        
+        // Cached results are unchanged by the reordering
+        let metadata = analyzer.analyze(hot).unwrap();
+        assert_eq!(metadata.query_type, QueryType::Select);
+        
+        analyzer.clear_cache();
+        assert!(analyzer.query_cache.is_empty());
+        assert!(analyzer.cache_order.is_empty());
+    }
+
+    #[test]
+    fn test_analyze_prepared_statements() {
+        let mut analyzer = QueryAnalyzer::new();
+        
+        let insert = analyzer.analyze_prepared("INSERT INTO users (id, name) VALUES ($1, $2)").unwrap();
+        assert_eq!(insert.query_type, QueryType::Insert);
+        assert!(insert.is_deterministic);
+        assert_eq!(insert.param_count(), 2);
+        assert_eq!(insert.get_modified_tables(), vec!["users"]);
+        
+        let update = analyzer.analyze_prepared("UPDATE users SET name = $2 WHERE id = $1").unwrap();
+        assert_eq!(update.query_type, QueryType::Update);
+        assert!(update.is_deterministic);
+        assert_eq!(update.param_count(), 2);
+        
+        let delete = analyzer.analyze_prepared("DELETE FROM users WHERE id = $1 AND note <> '$2'").unwrap();
+        assert_eq!(delete.query_type, QueryType::Delete);
+        assert_eq!(delete.param_count(), 1);
+        
+        // A placeholder doesn't hide real non-determinism
+        let stamped = analyzer.analyze_prepared("INSERT INTO events (id, at) VALUES ($1, now())").unwrap();
+        assert!(!stamped.is_deterministic);
+        
+        // Bound values travel with the statement for replay
+        let statement = insert.bind_params(vec![
+            verifiable_db_core::models::Value::Integer(1),
+            verifiable_db_core::models::Value::Text("alice".to_string()),
+        ]).unwrap();
+        assert_eq!(statement.query, "INSERT INTO users (id, name) VALUES ($1, $2)");
+        assert_eq!(statement.params.len(), 2);
+        assert!(insert.bind_params(vec![verifiable_db_core::models::Value::Integer(1)]).is_err());
+    }
+    
+    #[test]
+    fn test_find_placeholders() {
+        let query = "SELECT $1, 'x$2', \"a$3\", $$ $4 $$, $body$ $5 $body$, $10 FROM t$6";
+        let numbers: Vec<usize> = find_placeholders(query).into_iter().map(|(_, index)| index).collect();
+        assert_eq!(numbers, vec![1, 10]);
+        
+        let placeholders = find_placeholders("VALUES ($1, $2)");
+        assert_eq!(replace_placeholders("VALUES ($1, $2)", &placeholders, "NULL"), "VALUES (NULL, NULL)");
+    }
+
+    /// Function catalog answering from a fixed table, counting lookups
+    #[derive(Debug, Default)]
+    struct MockCatalog {
+        functions: HashMap<String, FunctionVolatility>,
+        available: bool,
+        lookups: std::sync::atomic::AtomicUsize,
+    }
+    
+    impl FunctionCatalog for MockCatalog {
+        fn volatility(&self, name: &str) -> Result<Option<FunctionVolatility>> {
+            self.lookups.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if !self.available {
+                return Err(ProxyError::Database("connection refused".to_string()));
+            }
+            Ok(self.functions.get(name).copied())
+        }
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_catalog_volatile_functions() {
+        let catalog = Arc::new(MockCatalog {
+            functions: HashMap::from([
+                ("gen_fancy_id".to_string(), FunctionVolatility::Volatile),
+                ("slugify".to_string(), FunctionVolatility::Immutable),
+            ]),
+            available: true,
+            ..Default::default()
+        });
+        
+        let mut analyzer = QueryAnalyzer::new();
+        let query = "INSERT INTO users (id, slug) VALUES (gen_fancy_id(), slugify('Alice'))";
+        
+        // The static list doesn't know the custom function
+        assert!(analyzer.analyze(query).unwrap().is_deterministic);
+        
+        analyzer.set_function_catalog(Some(catalog.clone()));
+        let metadata = analyzer.analyze(query).unwrap();
+        assert!(!metadata.is_deterministic);
+        assert!(metadata.non_deterministic_operations.iter()
+            .any(|op| op.description == "Volatile function: gen_fancy_id"));
+        assert!(!analyzer.is_deterministic(query));
+        assert_eq!(
+            analyzer.get_non_deterministic_reason(query).as_deref(),
+            Some("Contains volatile function: gen_fancy_id")
+        );
+        
+        // Each function is looked up once, however often it's analyzed
+        assert_eq!(catalog.lookups.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert!(analyzer.is_deterministic("INSERT INTO users (slug) VALUES (slugify('Bob'))"));
+        assert_eq!(catalog.lookups.load(std::sync::atomic::Ordering::SeqCst), 2);
+        
+        // Listed functions are left to the static list
+        analyzer.analyze("INSERT INTO users (id) VALUES (gen_random_uuid())").unwrap();
+        assert_eq!(catalog.lookups.load(std::sync::atomic::Ordering::SeqCst), 2);
+        
+        // An unreachable catalog falls back to the static list
+        analyzer.set_function_catalog(Some(Arc::new(MockCatalog::default())));
+        assert!(analyzer.analyze(query).unwrap().is_deterministic);
+        assert!(!analyzer.analyze("INSERT INTO users (id) VALUES (random())").unwrap().is_deterministic);
+    }
+    
+    #[test]
+    fn test_row_lock_detection() {
+        let mut analyzer = QueryAnalyzer::new();
+        
+        let plain = analyzer.analyze("SELECT id FROM accounts WHERE id = 1 ORDER BY id").unwrap();
+        assert!(!plain.takes_row_locks());
+        assert_eq!(plain.tables[0].access_type, AccessType::Read);
+        
+        // Locked rows are ordered against concurrent writers, like written ones
+        let locked = analyzer.analyze("SELECT id FROM accounts WHERE id = 1 ORDER BY id FOR UPDATE").unwrap();
+        assert_eq!(locked.query_type, QueryType::Select);
+        assert!(locked.takes_row_locks());
+        assert_eq!(locked.extra.get("row_locks").map(String::as_str), Some("update"));
+        assert_eq!(locked.tables[0].access_type, AccessType::ReadWrite);
+        assert!(locked.is_deterministic);
+        assert!(!locked.cacheable);
+        
+        // OF limits the lock to the named tables, by alias
+        let query = "SELECT a.id FROM accounts a JOIN owners o ON o.id = a.owner_id ORDER BY a.id FOR SHARE OF a";
+        let metadata = analyzer.analyze(query).unwrap();
+        let access = |name: &str| metadata.tables.iter().find(|t| t.table_name == name).unwrap().access_type.clone();
+        assert_eq!(access("accounts"), AccessType::ReadWrite);
+        assert_eq!(access("owners"), AccessType::Read);
+        assert_eq!(metadata.extra.get("row_locks").map(String::as_str), Some("share"));
+        
+        // Which rows SKIP LOCKED returns depends on concurrent transactions
+        let queue = analyzer.analyze("SELECT id FROM jobs ORDER BY id LIMIT 1 FOR UPDATE SKIP LOCKED").unwrap();
+        assert!(!queue.is_deterministic);
+        assert!(queue.non_deterministic_operations.iter().any(|op| op.operation_type == "RowLock"));
+    }
+}
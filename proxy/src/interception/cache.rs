@@ -0,0 +1,317 @@
+//! Result cache for verified deterministic reads
+//!
+//! A deterministic SELECT over unchanged tables always returns the same rows,
+//! so its result can be served without hitting the backend. Entries are keyed
+//! on the query's normalized fingerprint, its bound parameters and the root of
+//! the tables it reads; once any of those table roots changes the entry no
+//! longer matches and is dropped. Writes seen by the proxy invalidate the
+//! entries reading the written tables right away, before the next block
+//! updates their roots.
+
+use crate::interception::analyzer::{AccessType, QueryMetadata};
+use crate::protocol::message::BackendMessage;
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use verifiable_db_core::models::Value;
+
+/// Configuration for the result cache
+#[derive(Debug, Clone)]
+pub struct ResultCacheConfig {
+    /// Whether results of cacheable queries are cached
+    pub enabled: bool,
+
+    /// Maximum number of cached results; the oldest is evicted first
+    pub max_entries: usize,
+}
+
+impl Default for ResultCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_entries: 1024,
+        }
+    }
+}
+
+/// A cached query result
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedResult {
+    /// Backend messages of the result, from the row description to command completion
+    pub messages: Vec<BackendMessage>,
+
+    /// Root over the roots of the tables the query reads
+    pub state_root: [u8; 32],
+
+    /// Proofs of the tables the query reads, keyed by table name
+    pub proofs: HashMap<String, Vec<u8>>,
+}
+
+/// Result cache hit and miss counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResultCacheStats {
+    /// Number of cached results
+    pub entries: usize,
+
+    /// Lookups served from the cache
+    pub hits: u64,
+
+    /// Lookups of cacheable queries that had to go to the backend
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    fingerprint: String,
+    params: String,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    /// Tables the query reads, as normalized by [`cache_table_name`]
+    tables: Vec<String>,
+    result: CachedResult,
+}
+
+/// Cache of query results, valid while the tables they read are unchanged
+#[derive(Debug, Default)]
+pub struct ResultCache {
+    config: ResultCacheConfig,
+    entries: HashMap<CacheKey, CacheEntry>,
+
+    /// Keys in insertion order, for eviction
+    order: VecDeque<CacheKey>,
+
+    stats: ResultCacheStats,
+}
+
+impl ResultCache {
+    /// Create a cache with the given configuration
+    pub fn new(config: ResultCacheConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a query's result may be cached
+    pub fn is_cacheable(&self, metadata: &QueryMetadata) -> bool {
+        self.config.enabled && metadata.cacheable && !metadata.modifies_data()
+    }
+
+    /// Look up the result of a query against the current roots of the tables it reads
+    ///
+    /// An entry cached under different table roots is stale and is dropped.
+    pub fn get(&mut self, metadata: &QueryMetadata, params: &[Value], table_roots: &BTreeMap<String, [u8; 32]>) -> Option<CachedResult> {
+        if !self.is_cacheable(metadata) {
+            return None;
+        }
+
+        let key = cache_key(metadata, params);
+        let state_root = read_state_root(table_roots);
+        match self.entries.get(&key) {
+            Some(entry) if entry.result.state_root == state_root => {
+                self.stats.hits += 1;
+                debug!("Serving query {} from the result cache", key.fingerprint);
+                Some(entry.result.clone())
+            }
+            Some(_) => {
+                self.remove(&key);
+                self.stats.misses += 1;
+                None
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Cache the result of a query read under the given table roots
+    ///
+    /// Results of non-cacheable queries and results containing an error are ignored.
+    pub fn insert(
+        &mut self,
+        metadata: &QueryMetadata,
+        params: &[Value],
+        table_roots: &BTreeMap<String, [u8; 32]>,
+        messages: Vec<BackendMessage>,
+        proofs: HashMap<String, Vec<u8>>,
+    ) {
+        if !self.is_cacheable(metadata) || self.config.max_entries == 0 {
+            return;
+        }
+        if messages.iter().any(|message| matches!(message, BackendMessage::ErrorResponse(_))) {
+            return;
+        }
+
+        let key = cache_key(metadata, params);
+        self.remove(&key);
+        while self.entries.len() >= self.config.max_entries {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+
+        let tables = read_tables(metadata);
+        self.order.push_back(key.clone());
+        self.entries.insert(key, CacheEntry {
+            tables,
+            result: CachedResult {
+                messages,
+                state_root: read_state_root(table_roots),
+                proofs,
+            },
+        });
+        self.stats.entries = self.entries.len();
+    }
+
+    /// Drop the results of queries reading any of `tables`
+    ///
+    /// Returns the number of results dropped.
+    pub fn invalidate_tables(&mut self, tables: &[String]) -> usize {
+        let tables: Vec<String> = tables.iter().map(|table| cache_table_name(table)).collect();
+        let stale: Vec<CacheKey> = self.entries.iter()
+            .filter(|(_, entry)| entry.tables.iter().any(|table| tables.contains(table)))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &stale {
+            self.remove(key);
+        }
+        stale.len()
+    }
+
+    /// Drop every cached result
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.stats.entries = 0;
+    }
+
+    /// Get the cache's hit and miss counts
+    pub fn stats(&self) -> ResultCacheStats {
+        self.stats
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if self.entries.remove(key).is_some() {
+            self.order.retain(|k| k != key);
+        }
+        self.stats.entries = self.entries.len();
+    }
+}
+
+/// Root over the roots of the tables a query reads, in table name order
+pub fn read_state_root(table_roots: &BTreeMap<String, [u8; 32]>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for (table, root) in table_roots {
+        hasher.update(cache_table_name(table).as_bytes());
+        hasher.update([0u8]);
+        hasher.update(root);
+    }
+    hasher.finalize().into()
+}
+
+/// Tables a query reads, normalized for comparison
+pub fn read_tables(metadata: &QueryMetadata) -> Vec<String> {
+    metadata.tables.iter()
+        .filter(|t| matches!(t.access_type, AccessType::Read | AccessType::ReadWrite))
+        .map(|t| cache_table_name(&t.table_name))
+        .collect()
+}
+
+/// Lowercase table name without the default `public` schema
+fn cache_table_name(table: &str) -> String {
+    let table = table.to_lowercase();
+    match table.strip_prefix("public.") {
+        Some(name) => name.to_string(),
+        None => table,
+    }
+}
+
+fn cache_key(metadata: &QueryMetadata, params: &[Value]) -> CacheKey {
+    CacheKey {
+        fingerprint: metadata.get_normalized_fingerprint(),
+        params: serde_json::to_string(params).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interception::analyzer::QueryAnalyzer;
+    use bytes::Bytes;
+
+    fn result_messages(value: &str) -> Vec<BackendMessage> {
+        vec![
+            BackendMessage::DataRow(vec![Some(Bytes::from(value.to_string()))]),
+            BackendMessage::CommandComplete("SELECT 1".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_repeated_query_served_from_cache() {
+        let mut analyzer = QueryAnalyzer::new();
+        let mut cache = ResultCache::new(ResultCacheConfig { enabled: true, ..Default::default() });
+        let metadata = analyzer.analyze("SELECT name FROM users WHERE id = $1 ORDER BY id").unwrap();
+        assert!(metadata.cacheable);
+
+        let params = vec![Value::Integer(1)];
+        let roots = BTreeMap::from([("users".to_string(), [1u8; 32])]);
+        assert_eq!(cache.get(&metadata, &params, &roots), None);
+
+        let proofs = HashMap::from([("users".to_string(), vec![0xaa])]);
+        cache.insert(&metadata, &params, &roots, result_messages("alice"), proofs.clone());
+
+        // The same query against the unchanged state is served from the cache,
+        // whatever its spelling
+        let respelled = analyzer.analyze("select name  from users where id = $1 order by id;").unwrap();
+        let cached = cache.get(&respelled, &params, &roots).unwrap();
+        assert_eq!(cached.messages, result_messages("alice"));
+        assert_eq!(cached.proofs, proofs);
+        assert_eq!(cached.state_root, read_state_root(&roots));
+        assert_eq!(cache.stats(), ResultCacheStats { entries: 1, hits: 1, misses: 1 });
+
+        // Different parameters are a different result
+        assert_eq!(cache.get(&metadata, &[Value::Integer(2)], &roots), None);
+
+        // Once the table's root changes the entry is stale
+        let changed = BTreeMap::from([("users".to_string(), [2u8; 32])]);
+        assert_eq!(cache.get(&metadata, &params, &changed), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn test_writes_invalidate_cached_reads() {
+        let mut analyzer = QueryAnalyzer::new();
+        let mut cache = ResultCache::new(ResultCacheConfig { enabled: true, max_entries: 2 });
+        let roots = BTreeMap::from([("users".to_string(), [1u8; 32])]);
+
+        let users = analyzer.analyze("SELECT name FROM users ORDER BY id").unwrap();
+        let posts = analyzer.analyze("SELECT title FROM posts ORDER BY id").unwrap();
+        cache.insert(&users, &[], &roots, result_messages("alice"), HashMap::new());
+        cache.insert(&posts, &[], &roots, result_messages("hello"), HashMap::new());
+
+        // A write through the proxy drops reads of the written table only
+        assert_eq!(cache.invalidate_tables(&["public.users".to_string()]), 1);
+        assert_eq!(cache.get(&users, &[], &roots), None);
+        assert!(cache.get(&posts, &[], &roots).is_some());
+
+        // Writes and non-deterministic reads are never cached
+        let insert = analyzer.analyze("INSERT INTO users (id, name) VALUES (1, 'bob')").unwrap();
+        let random = analyzer.analyze("SELECT random() FROM users ORDER BY id").unwrap();
+        assert!(!cache.is_cacheable(&insert));
+        assert!(!cache.is_cacheable(&random));
+
+        // The oldest entry is evicted at capacity
+        cache.insert(&users, &[], &roots, result_messages("alice"), HashMap::new());
+        let comments = analyzer.analyze("SELECT body FROM comments ORDER BY id").unwrap();
+        cache.insert(&comments, &[], &roots, result_messages("nice"), HashMap::new());
+        assert_eq!(cache.stats().entries, 2);
+        assert!(cache.get(&posts, &[], &roots).is_none());
+    }
+}
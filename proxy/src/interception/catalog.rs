@@ -0,0 +1,100 @@
+//! Function volatility from the PostgreSQL catalog
+//!
+//! The static [`NON_DETERMINISTIC_FUNCTIONS`] list only knows built-in
+//! functions. User-defined functions declare their volatility in
+//! `pg_proc.provolatile`, so the analyzer can ask the server's catalog about
+//! any function the list doesn't cover.
+//!
+//! [`NON_DETERMINISTIC_FUNCTIONS`]: crate::interception::rewrite::NON_DETERMINISTIC_FUNCTIONS
+
+use crate::error::{ProxyError, Result};
+use std::fmt::Debug;
+use std::sync::Arc;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// Volatility of user-defined functions with a given name, across all overloads
+const FUNCTION_VOLATILITY_QUERY: &str = "SELECT p.provolatile::text \
+     FROM pg_catalog.pg_proc p \
+     JOIN pg_catalog.pg_namespace n ON n.oid = p.pronamespace \
+     WHERE p.proname = $1 AND n.nspname NOT IN ('pg_catalog', 'information_schema')";
+
+/// Volatility of a function, as recorded in `pg_proc.provolatile`
+///
+/// Ordered from least to most volatile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FunctionVolatility {
+    /// Same result for the same arguments, forever
+    Immutable,
+
+    /// Same result for the same arguments within a single statement
+    Stable,
+
+    /// May return a different result on every call
+    Volatile,
+}
+
+impl FunctionVolatility {
+    /// Parse a `provolatile` code (`i`, `s` or `v`)
+    pub fn from_provolatile(code: &str) -> Option<Self> {
+        match code {
+            "i" => Some(Self::Immutable),
+            "s" => Some(Self::Stable),
+            "v" => Some(Self::Volatile),
+            _ => None,
+        }
+    }
+}
+
+/// Source of function volatility, such as the server's `pg_proc` catalog
+pub trait FunctionCatalog: Send + Sync + Debug {
+    /// Volatility of the user-defined function `name`, or None if there is none
+    ///
+    /// With several overloads, the most volatile one is reported.
+    fn volatility(&self, name: &str) -> Result<Option<FunctionVolatility>>;
+}
+
+/// Function catalog backed by a connection to the PostgreSQL server
+///
+/// Lookups block the calling worker thread, so they need a multi-threaded
+/// Tokio runtime; elsewhere they fail and the analyzer falls back to its
+/// static function list.
+#[derive(Clone)]
+pub struct PgFunctionCatalog {
+    client: Arc<tokio_postgres::Client>,
+}
+
+impl PgFunctionCatalog {
+    /// Create a catalog querying through `client`
+    pub fn new(client: Arc<tokio_postgres::Client>) -> Self {
+        Self { client }
+    }
+}
+
+impl Debug for PgFunctionCatalog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgFunctionCatalog").finish_non_exhaustive()
+    }
+}
+
+impl FunctionCatalog for PgFunctionCatalog {
+    fn volatility(&self, name: &str) -> Result<Option<FunctionVolatility>> {
+        let handle = Handle::try_current()
+            .map_err(|e| ProxyError::Database(format!("No runtime for catalog lookup: {}", e)))?;
+        if handle.runtime_flavor() == RuntimeFlavor::CurrentThread {
+            return Err(ProxyError::Database(
+                "Catalog lookups need a multi-threaded runtime".to_string(),
+            ));
+        }
+
+        let rows = tokio::task::block_in_place(|| {
+            handle.block_on(self.client.query(FUNCTION_VOLATILITY_QUERY, &[&name]))
+        })
+        .map_err(|e| ProxyError::Database(format!("Failed to look up function {}: {}", name, e)))?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|row| row.try_get::<_, String>(0).ok())
+            .filter_map(|code| FunctionVolatility::from_provolatile(&code))
+            .max())
+    }
+}
@@ -0,0 +1,355 @@
+//! Capture of rows loaded with `COPY ... FROM STDIN`
+//!
+//! The rows of a `COPY FROM STDIN` never appear in the statement; the client
+//! streams them in CopyData messages until CopyDone. [`CopyCapture`] collects
+//! that stream so the loaded rows can be verified and the load replayed. A
+//! CopyFail aborts the load on the backend, so the capture is discarded.
+
+use crate::error::{ProxyError, Result};
+use bytes::{Bytes, BytesMut};
+
+/// Signature opening the binary COPY format
+const BINARY_SIGNATURE: &[u8] = b"PGCOPY\n\xff\r\n\0";
+
+/// Data format of a COPY
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    /// Tab-separated text, one row per line
+    Text,
+
+    /// Comma-separated values; quoted values may span lines
+    Csv,
+
+    /// PostgreSQL's binary tuple format
+    Binary,
+}
+
+impl CopyFormat {
+    /// Overall format code sent to the client in `CopyInResponse`
+    pub fn code(self) -> i8 {
+        match self {
+            CopyFormat::Binary => 1,
+            CopyFormat::Text | CopyFormat::Csv => 0,
+        }
+    }
+}
+
+/// Data format of `query` if it is a `COPY ... FROM STDIN`
+pub fn copy_from_stdin(query: &str) -> Option<CopyFormat> {
+    let upper = query.to_uppercase();
+    let words: Vec<&str> = upper
+        .split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ',' | ';'))
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    if words.first() != Some(&"COPY") {
+        return None;
+    }
+
+    let stdin = words.windows(2).position(|pair| pair == ["FROM", "STDIN"])?;
+    let options = &words[stdin + 2..];
+    if options.contains(&"BINARY") {
+        Some(CopyFormat::Binary)
+    } else if options.contains(&"CSV") {
+        Some(CopyFormat::Csv)
+    } else {
+        Some(CopyFormat::Text)
+    }
+}
+
+/// Rows streamed by a completed `COPY FROM STDIN`
+#[derive(Debug, Clone)]
+pub struct CopyRows {
+    /// The COPY statement
+    pub query: String,
+
+    /// Data format of the stream
+    pub format: CopyFormat,
+
+    /// The stream as sent by the client, for replay
+    pub data: Bytes,
+
+    /// Each row as it appears in the stream
+    pub rows: Vec<Bytes>,
+}
+
+/// Collects the CopyData stream of a `COPY FROM STDIN`
+#[derive(Debug)]
+pub struct CopyCapture {
+    /// The COPY statement
+    query: String,
+
+    /// Data format of the stream
+    format: CopyFormat,
+
+    /// Data received so far
+    data: BytesMut,
+
+    /// Largest stream kept for verification
+    max_size: usize,
+
+    /// Whether the stream outgrew `max_size` and was dropped
+    overflowed: bool,
+}
+
+impl CopyCapture {
+    /// Start capturing the stream of `query`, if it is a `COPY FROM STDIN`
+    ///
+    /// Streams larger than `max_size` bytes are not kept, and the load is
+    /// reported as unverifiable when it completes.
+    pub fn new(query: &str, max_size: usize) -> Option<Self> {
+        let format = copy_from_stdin(query)?;
+        Some(Self {
+            query: query.to_string(),
+            format,
+            data: BytesMut::new(),
+            max_size,
+            overflowed: false,
+        })
+    }
+
+    /// The COPY statement
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Data format of the stream
+    pub fn format(&self) -> CopyFormat {
+        self.format
+    }
+
+    /// Add the payload of a CopyData message
+    ///
+    /// Messages need not align with rows; a row may span several of them.
+    pub fn push(&mut self, data: &[u8]) {
+        if self.overflowed {
+            return;
+        }
+        if self.data.len() + data.len() > self.max_size {
+            self.overflowed = true;
+            self.data = BytesMut::new();
+            return;
+        }
+        self.data.extend_from_slice(data);
+    }
+
+    /// Finish the capture on CopyDone, splitting the stream into rows
+    pub fn finish(self) -> Result<CopyRows> {
+        if self.overflowed {
+            return Err(ProxyError::Verification(format!(
+                "COPY data exceeds {} bytes and cannot be verified",
+                self.max_size
+            )));
+        }
+
+        let data = self.data.freeze();
+        let rows = match self.format {
+            CopyFormat::Text => split_text_rows(&data, false),
+            CopyFormat::Csv => split_text_rows(&data, true),
+            CopyFormat::Binary => split_binary_rows(&data)?,
+        };
+
+        Ok(CopyRows {
+            query: self.query,
+            format: self.format,
+            data,
+            rows,
+        })
+    }
+}
+
+/// Rows of a text or CSV stream, up to the optional `\.` end marker
+///
+/// Text escapes newlines inside values, so every line is a row; CSV may
+/// quote them, so newlines inside quotes don't end the row.
+fn split_text_rows(data: &Bytes, csv: bool) -> Vec<Bytes> {
+    let mut rows = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+
+    for (i, &byte) in data.iter().enumerate() {
+        match byte {
+            b'"' if csv => quoted = !quoted,
+            b'\n' if !quoted => {
+                let row = trim_carriage_return(data.slice(start..i));
+                start = i + 1;
+                if row.as_ref() == b"\\." {
+                    return rows;
+                }
+                rows.push(row);
+            }
+            _ => {}
+        }
+    }
+
+    // A last row without a trailing newline
+    let row = trim_carriage_return(data.slice(start..));
+    if !row.is_empty() && row.as_ref() != b"\\." {
+        rows.push(row);
+    }
+    rows
+}
+
+/// `line` without the carriage return of a `\r\n` line ending
+fn trim_carriage_return(line: Bytes) -> Bytes {
+    match line.last() {
+        Some(b'\r') => line.slice(..line.len() - 1),
+        _ => line,
+    }
+}
+
+/// Tuples of a binary stream, each with its field count and fields
+fn split_binary_rows(data: &Bytes) -> Result<Vec<Bytes>> {
+    let truncated = || ProxyError::Protocol("Truncated binary COPY data".to_string());
+    let read_i16 = |at: usize| -> Result<i16> {
+        let bytes = data.get(at..at + 2).ok_or_else(truncated)?;
+        Ok(i16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let read_i32 = |at: usize| -> Result<i32> {
+        let bytes = data.get(at..at + 4).ok_or_else(truncated)?;
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    if !data.starts_with(BINARY_SIGNATURE) {
+        return Err(ProxyError::Protocol("Binary COPY data lacks the PGCOPY signature".to_string()));
+    }
+
+    // Signature, flags, then a length-prefixed header extension
+    let extension_length = read_i32(BINARY_SIGNATURE.len() + 4)?;
+    let mut offset = BINARY_SIGNATURE.len() + 8 + usize::try_from(extension_length).map_err(|_| truncated())?;
+
+    let mut rows = Vec::new();
+    loop {
+        let field_count = read_i16(offset)?;
+        if field_count == -1 {
+            return Ok(rows);
+        }
+
+        let start = offset;
+        offset += 2;
+        for _ in 0..field_count {
+            let length = read_i32(offset)?;
+            offset += 4;
+            if length > 0 {
+                offset += length as usize;
+            }
+        }
+        if offset > data.len() {
+            return Err(truncated());
+        }
+        rows.push(data.slice(start..offset));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::formatter::MessageFormatter;
+    use crate::protocol::message::FrontendMessage;
+    use crate::protocol::parser::MessageParser;
+
+    #[test]
+    fn test_copy_from_stdin_detection() {
+        assert_eq!(copy_from_stdin("COPY items FROM STDIN"), Some(CopyFormat::Text));
+        assert_eq!(copy_from_stdin("copy items (id, name) from stdin;"), Some(CopyFormat::Text));
+        assert_eq!(copy_from_stdin("COPY items FROM STDIN WITH (FORMAT csv, HEADER)"), Some(CopyFormat::Csv));
+        assert_eq!(copy_from_stdin("COPY items FROM STDIN CSV"), Some(CopyFormat::Csv));
+        assert_eq!(copy_from_stdin("COPY items FROM STDIN (FORMAT binary)"), Some(CopyFormat::Binary));
+        assert_eq!(copy_from_stdin("COPY items TO STDOUT"), None);
+        assert_eq!(copy_from_stdin("COPY items FROM '/tmp/items.csv'"), None);
+        assert_eq!(copy_from_stdin("SELECT 'COPY x FROM STDIN'"), None);
+    }
+
+    #[test]
+    fn test_copy_from_stdin_rows_reach_capture() {
+        let formatter = MessageFormatter::new();
+        let parser = MessageParser::new();
+
+        // The client pipelines the whole load; rows straddle CopyData messages
+        let mut stream = BytesMut::new();
+        for message in [
+            FrontendMessage::Query("COPY items (id, name) FROM STDIN".to_string()),
+            FrontendMessage::CopyData(Bytes::from_static(b"1\tapple\n2\tba")),
+            FrontendMessage::CopyData(Bytes::from_static(b"nana\n3\tcherry\n")),
+            FrontendMessage::CopyData(Bytes::from_static(b"4\t\\N\n\\.\n")),
+            FrontendMessage::CopyDone,
+        ] {
+            stream.extend_from_slice(&formatter.format_frontend_message(&message).unwrap());
+        }
+
+        let mut capture = None;
+        let mut rows = None;
+        while !stream.is_empty() {
+            let length = parser.frontend_message_length(&stream).unwrap().unwrap();
+            let frame = stream.split_to(length).freeze();
+            match parser.parse_frontend_message(&frame).unwrap() {
+                FrontendMessage::Query(query) => capture = CopyCapture::new(&query, 1 << 20),
+                FrontendMessage::CopyData(data) => capture.as_mut().unwrap().push(&data),
+                FrontendMessage::CopyDone => rows = Some(capture.take().unwrap().finish().unwrap()),
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+
+        let rows = rows.unwrap();
+        assert_eq!(rows.query, "COPY items (id, name) FROM STDIN");
+        assert_eq!(rows.format, CopyFormat::Text);
+        assert_eq!(
+            rows.rows,
+            vec![
+                Bytes::from_static(b"1\tapple"),
+                Bytes::from_static(b"2\tbanana"),
+                Bytes::from_static(b"3\tcherry"),
+                Bytes::from_static(b"4\t\\N"),
+            ]
+        );
+        assert_eq!(rows.data.as_ref(), b"1\tapple\n2\tbanana\n3\tcherry\n4\t\\N\n\\.\n");
+    }
+
+    #[test]
+    fn test_csv_rows_keep_quoted_newlines() {
+        let mut capture = CopyCapture::new("COPY notes FROM STDIN WITH (FORMAT csv)", 1 << 20).unwrap();
+        capture.push(b"1,\"two\r\nlines\"\r\n2,\"say \"\"hi\"\"\"\r\n");
+
+        let rows = capture.finish().unwrap();
+        assert_eq!(
+            rows.rows,
+            vec![Bytes::from_static(b"1,\"two\r\nlines\""), Bytes::from_static(b"2,\"say \"\"hi\"\"\"")]
+        );
+    }
+
+    #[test]
+    fn test_binary_rows() {
+        let mut data = BINARY_SIGNATURE.to_vec();
+        data.extend_from_slice(&0i32.to_be_bytes()); // flags
+        data.extend_from_slice(&0i32.to_be_bytes()); // no header extension
+        for (id, name) in [(1i32, Some(&b"apple"[..])), (2, None)] {
+            data.extend_from_slice(&2i16.to_be_bytes());
+            data.extend_from_slice(&4i32.to_be_bytes());
+            data.extend_from_slice(&id.to_be_bytes());
+            match name {
+                Some(name) => {
+                    data.extend_from_slice(&(name.len() as i32).to_be_bytes());
+                    data.extend_from_slice(name);
+                }
+                None => data.extend_from_slice(&(-1i32).to_be_bytes()),
+            }
+        }
+        data.extend_from_slice(&(-1i16).to_be_bytes());
+
+        let mut capture = CopyCapture::new("COPY items FROM STDIN (FORMAT binary)", 1 << 20).unwrap();
+        capture.push(&data);
+        assert_eq!(capture.finish().unwrap().rows.len(), 2);
+
+        let mut truncated = CopyCapture::new("COPY items FROM STDIN (FORMAT binary)", 1 << 20).unwrap();
+        truncated.push(&data[..data.len() - 6]);
+        assert!(truncated.finish().is_err());
+    }
+
+    #[test]
+    fn test_oversized_copy_is_not_kept() {
+        let mut capture = CopyCapture::new("COPY items FROM STDIN", 8).unwrap();
+        capture.push(b"1\ta\n");
+        capture.push(b"2\tb\n3\tc\n");
+        assert!(matches!(capture.finish(), Err(ProxyError::Verification(_))));
+    }
+}
@@ -563,6 +563,7 @@ mod tests {
             complexity_score: 1,
             special_handling: false,
             verifiable: true,
+            metadata_query: false,
             cacheable: true,
             extra: HashMap::new(),
             non_deterministic_reason: None,
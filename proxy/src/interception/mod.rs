@@ -4,19 +4,34 @@
 //! with the verification engine.
 
 pub mod analyzer;
+pub mod cache;
+pub mod catalog;
+pub mod copy;
 pub mod execution;
 pub mod rewrite;
 pub mod verification;
 
 pub use analyzer::{QueryAnalyzer, QueryMetadata, QueryType};
+pub use cache::{ResultCache, ResultCacheConfig, ResultCacheStats, CachedResult};
+pub use catalog::{FunctionCatalog, FunctionVolatility, PgFunctionCatalog};
+pub use copy::{CopyCapture, CopyFormat, CopyRows};
 pub use execution::{QueryExecutor, ExecutionPlan, ExecutionResult, ExecutorConfig};
 pub use rewrite::{QueryRewriter, RewriteAction, RewriteReason, RewriterConfig};
-pub use verification::{VerificationManager, VerificationResult, VerificationStatus, VerificationConfig};
+pub use verification::{VerificationManager, VerificationResult, VerificationStatus, VerificationConfig, CheckpointResult, VerificationManagerStatus, VerifierUnavailablePolicy, ReverificationConfig};
 
 use crate::error::{ProxyError, Result};
 use crate::protocol::{FrontendMessage, BackendMessage};
+use crate::protocol::extended::{ExtendedQueryState, PortalResult};
+use governor::{
+    clock::DefaultClock,
+    state::{InMemoryState, NotKeyed},
+    Quota, RateLimiter as GovernorRateLimiter,
+};
 use log::{debug, info, warn, error};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
 use std::sync::Arc;
+use verifiable_db_core::models::{TableSchema, TriggerDefinition, Value};
 
 /// Interception manager responsible for query analysis, transformation and verification
 #[derive(Debug)]
@@ -30,8 +45,24 @@ pub struct InterceptionManager {
     /// Query executor for special queries
     executor: QueryExecutor,
     
-    /// Verification manager for integration with the core verification engine
-    verifier: VerificationManager,
+    /// Verification manager for integration with the core verification engine,
+    /// shared by every connection's interception manager
+    verifier: Arc<VerificationManager>,
+    
+    /// Results of deterministic reads, served while their tables are unchanged
+    result_cache: ResultCache,
+    
+    /// Prepared statements and portals of the extended query protocol
+    extended: ExtendedQueryState,
+    
+    /// Analysis of each prepared statement, keyed by statement name
+    prepared_statements: HashMap<String, QueryMetadata>,
+    
+    /// Rows streamed so far by a `COPY FROM STDIN` in progress, with its analysis
+    copy_in: Option<(CopyCapture, QueryMetadata)>,
+    
+    /// Limiter for queries at or above the complexity threshold, if limited
+    complex_query_limiter: Option<GovernorRateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
     
     /// Configuration for the interception manager
     config: InterceptionConfig,
@@ -55,8 +86,26 @@ pub struct InterceptionConfig {
     /// Whether to track query dependencies
     pub track_dependencies: bool,
     
-    /// Rate limit for complex queries (per minute)
+    /// Rate limit for complex queries (per minute); `None` or 0 means no limit
     pub complex_query_rate_limit: Option<u32>,
+    
+    /// Complexity score at or above which a query counts against `complex_query_rate_limit`
+    pub complex_query_threshold: u32,
+    
+    /// Whether queries reading a view are analyzed against the view's definition
+    pub expand_views: bool,
+    
+    /// Whether set operations need a total order on their result to be deterministic
+    pub enforce_set_operation_order: bool,
+    
+    /// Whether queries reading connection- or server-specific state are non-deterministic
+    pub detect_session_state_functions: bool,
+    
+    /// Caching of results of cacheable reads
+    pub result_cache: ResultCacheConfig,
+    
+    /// Largest `COPY FROM STDIN` stream kept for verification, in bytes
+    pub max_copy_size: usize,
 }
 
 impl Default for InterceptionConfig {
@@ -68,32 +117,97 @@ impl Default for InterceptionConfig {
             enforce_verification: false, // Default to off for now
             track_dependencies: true,
             complex_query_rate_limit: Some(100),
+            complex_query_threshold: 100,
+            expand_views: true,
+            enforce_set_operation_order: true,
+            detect_session_state_functions: true,
+            result_cache: ResultCacheConfig::default(),
+            max_copy_size: 64 << 20, // 64MB
         }
     }
 }
 
 impl InterceptionManager {
-    /// Create a new interception manager
-    pub fn new(config: InterceptionConfig) -> Self {
-        let analyzer = QueryAnalyzer::new();
+    /// Create a new interception manager with its own verification manager
+    pub async fn new(config: InterceptionConfig) -> Result<Self> {
+        let verifier = VerificationManager::new(VerificationConfig::default()).await?;
+        Ok(Self::with_verifier(config, Arc::new(verifier)))
+    }
+    
+    /// Create an interception manager recording statements with `verifier`
+    pub fn with_verifier(config: InterceptionConfig, verifier: Arc<VerificationManager>) -> Self {
+        let mut analyzer = QueryAnalyzer::new();
+        analyzer.set_expand_views(config.expand_views);
+        analyzer.set_enforce_set_operation_order(config.enforce_set_operation_order);
+        analyzer.set_detect_session_state_functions(config.detect_session_state_functions);
         let rewriter = QueryRewriter::new(RewriterConfig::default());
         let executor = QueryExecutor::new(ExecutorConfig::default());
-        let verifier = VerificationManager::new(VerificationConfig::default());
+        let result_cache = ResultCache::new(config.result_cache.clone());
+        let complex_query_limiter = config.complex_query_rate_limit
+            .and_then(NonZeroU32::new)
+            .map(|limit| GovernorRateLimiter::direct(Quota::per_minute(limit)));
         
-        Self {
+        let mut manager = Self {
             analyzer,
             rewriter,
             executor,
-            verifier: tokio::runtime::Runtime::new()
-                .expect("Failed to create runtime")
-                .block_on(verifier)
-                .expect("Failed to initialize verification manager"),
+            verifier,
+            result_cache,
+            extended: ExtendedQueryState::new(),
+            prepared_statements: HashMap::new(),
+            copy_in: None,
+            complex_query_limiter,
             config,
+        };
+        
+        // Statements are analyzed against the tables captured so far
+        for schema in manager.verifier.get_state_capture_manager().cached_schemas() {
+            manager.register_table_schema(&schema);
         }
+        manager
+    }
+    
+    /// Record a captured table's triggers, column defaults and primary key
+    pub fn register_table_schema(&mut self, schema: &TableSchema) {
+        self.register_triggers(&schema.name, schema.triggers.clone());
+        self.register_column_defaults(schema);
+        self.register_primary_keys(schema);
+    }
+    
+    /// Record the triggers installed on a table, so DML on it inherits their
+    /// determinism
+    pub fn register_triggers(&mut self, table_name: &str, triggers: Vec<TriggerDefinition>) {
+        self.analyzer.register_triggers(table_name, triggers);
+    }
+    
+    /// Record the definition of a view so queries reading it are analyzed
+    /// against its underlying tables
+    pub fn register_view(&mut self, name: &str, definition: &str) {
+        self.analyzer.register_view(name, definition);
+    }
+    
+    /// Record a table's column defaults so INSERTs relying on
+    /// non-deterministic defaults are detected
+    pub fn register_column_defaults(&mut self, schema: &TableSchema) {
+        self.analyzer.register_column_defaults(schema);
+    }
+    
+    /// Record a table's primary key so unordered SELECTs over it can be
+    /// ordered by it
+    pub fn register_primary_keys(&mut self, schema: &TableSchema) {
+        self.rewriter.register_primary_keys(schema);
     }
     
     /// Process a query message, potentially transforming it
-    pub fn process_query(&mut self, query: &str) -> Result<QueryProcessingResult> {
+    pub async fn process_query(&mut self, query: &str) -> Result<QueryProcessingResult> {
+        self.process_query_with_params(query, &[]).await
+    }
+    
+    /// Process a prepared statement executed with bound values
+    ///
+    /// The values are part of the result cache key, so a cached result is
+    /// only served for the same parameters.
+    pub async fn process_query_with_params(&mut self, query: &str, params: &[Value]) -> Result<QueryProcessingResult> {
         // Skip processing if query is too large
         if query.len() > self.config.max_query_size {
             warn!("Query exceeds maximum size for analysis: {} bytes", query.len());
@@ -101,6 +215,7 @@ impl InterceptionManager {
                 action: QueryAction::Forward,
                 transformed_query: None,
                 metadata: None,
+                cached_result: None,
             });
         }
         
@@ -114,12 +229,184 @@ impl InterceptionManager {
                     action: QueryAction::Forward,
                     transformed_query: None,
                     metadata: None,
+                    cached_result: None,
                 });
             }
         };
         
+        self.process_analyzed_query(query, metadata, params).await
+    }
+    
+    /// Process a message of the extended query protocol
+    ///
+    /// Statements are analyzed once, at Parse, and each Execute runs its
+    /// portal's statement with the bound values through the same checks as
+    /// a simple query. An empty name is the unnamed statement or portal,
+    /// which the next Parse or Bind replaces. Only Execute has a processing
+    /// result; the other messages just update the statement and portal state.
+    pub async fn process_extended_message(&mut self, message: &FrontendMessage) -> Result<Option<QueryProcessingResult>> {
+        match message {
+            FrontendMessage::Parse { name, query, param_types } => {
+                self.extended.parse(name, query, param_types);
+                if query.len() > self.config.max_query_size {
+                    warn!("Prepared statement exceeds maximum size for analysis: {} bytes", query.len());
+                    self.prepared_statements.remove(name);
+                    return Ok(None);
+                }
+                match self.analyzer.analyze_prepared(query) {
+                    Ok(metadata) => {
+                        self.prepared_statements.insert(name.clone(), metadata);
+                    }
+                    Err(e) => {
+                        warn!("Failed to analyze prepared statement '{}': {}", name, e);
+                        self.prepared_statements.remove(name);
+                    }
+                }
+                Ok(None)
+            }
+            FrontendMessage::Bind { portal, statement, param_formats, param_values, .. } => {
+                self.extended.bind(portal, statement, param_formats, param_values)?;
+                Ok(None)
+            }
+            FrontendMessage::Execute { portal, .. } => self.process_execute(portal).await.map(Some),
+            FrontendMessage::Close { object_type, name } => {
+                self.extended.close(*object_type, name);
+                if *object_type == b'S' {
+                    self.prepared_statements.remove(name);
+                }
+                Ok(None)
+            }
+            FrontendMessage::Describe { .. } | FrontendMessage::Sync | FrontendMessage::Flush => Ok(None),
+            other => Err(ProxyError::Protocol(format!("Not an extended query message: {:?}", other))),
+        }
+    }
+    
+    /// Process a message of the COPY sub-protocol
+    ///
+    /// CopyData is collected for the `COPY FROM STDIN` forwarded last. On
+    /// CopyDone the load is recorded for verification together with its data,
+    /// so replay loads the same rows, and the rows are returned. On CopyFail
+    /// the backend loads nothing, so the rows are dropped.
+    pub fn process_copy_message(&mut self, message: &FrontendMessage) -> Result<Option<CopyRows>> {
+        match message {
+            FrontendMessage::CopyData(data) => {
+                let (capture, _) = self.copy_in.as_mut()
+                    .ok_or_else(|| ProxyError::Protocol("CopyData without COPY FROM STDIN".to_string()))?;
+                capture.push(data);
+                Ok(None)
+            }
+            FrontendMessage::CopyDone => {
+                let (capture, metadata) = self.copy_in.take()
+                    .ok_or_else(|| ProxyError::Protocol("CopyDone without COPY FROM STDIN".to_string()))?;
+                let rows = capture.finish()?;
+                debug!("COPY FROM STDIN streamed {} row(s)", rows.rows.len());
+                
+                if self.config.capture_state {
+                    self.verifier.begin_copy_transaction(&rows.query, rows.data.clone(), &metadata)?;
+                }
+                Ok(Some(rows))
+            }
+            FrontendMessage::CopyFail(reason) => {
+                if let Some((capture, _)) = self.copy_in.take() {
+                    debug!("Client aborted {}: {}", capture.query(), reason);
+                }
+                Ok(None)
+            }
+            other => Err(ProxyError::Protocol(format!("Not a COPY message: {:?}", other))),
+        }
+    }
+    
+    /// Analysis of the prepared statement `name`, if it was parsed and analyzed
+    pub fn prepared_statement(&self, name: &str) -> Option<&QueryMetadata> {
+        self.prepared_statements.get(name)
+    }
+    
+    /// Process an Execute message for `portal`
+    ///
+    /// Executes after a row-limited one fetch more rows of the same run of
+    /// the statement, so only the first is checked and recorded.
+    async fn process_execute(&mut self, portal: &str) -> Result<QueryProcessingResult> {
+        if self.extended.is_started(portal) {
+            debug!("Fetching more rows of portal '{}'", portal);
+            return Ok(QueryProcessingResult {
+                action: QueryAction::Forward,
+                transformed_query: None,
+                metadata: None,
+                cached_result: None,
+            });
+        }
+        
+        let statement = self.extended.execute(portal)?;
+        // The rows stay with the backend; an empty result marks the portal as run
+        self.extended.start(portal, PortalResult::default())?;
+        let metadata = self.extended.portal(portal)
+            .and_then(|bound| self.prepared_statements.get(&bound.statement))
+            .cloned();
+        
+        let Some(metadata) = metadata else {
+            // The statement couldn't be analyzed at Parse either
+            return Ok(QueryProcessingResult {
+                action: QueryAction::Forward,
+                transformed_query: None,
+                metadata: None,
+                cached_result: None,
+            });
+        };
+        
+        let statement = metadata.bind_params(statement.params)?;
+        debug!("Executing portal '{}' with {} bound parameter(s)", portal, statement.params.len());
+        let result = self.process_analyzed_query(&statement.query, metadata.clone(), &statement.params).await?;
+        
+        // Keep the bound values with the statement, so replay runs it with
+        // exactly the parameters the client sent
+        if result.action == QueryAction::Forward && self.config.capture_state {
+            self.verifier.begin_transaction_with_params(&statement.query, statement.params, &metadata)?;
+        }
+        Ok(result)
+    }
+    
+    /// Process an analyzed query, executed with `params`
+    async fn process_analyzed_query(&mut self, query: &str, metadata: QueryMetadata, params: &[Value]) -> Result<QueryProcessingResult> {
         debug!("Query metadata: {:?}", metadata);
         
+        // Writes make cached reads of the written tables stale, and schema
+        // changes may affect any cached read
+        if metadata.query_type.is_ddl() {
+            self.result_cache.clear();
+        } else if metadata.modifies_data() {
+            self.result_cache.invalidate_tables(&metadata.get_modified_tables());
+        }
+        
+        // Serve deterministic reads of unchanged tables from the cache
+        if self.result_cache.is_cacheable(&metadata) {
+            if let Some(table_roots) = self.verifier.get_table_roots(&cache::read_tables(&metadata)) {
+                if let Some(cached) = self.result_cache.get(&metadata, params, &table_roots) {
+                    return Ok(QueryProcessingResult {
+                        action: QueryAction::Cached,
+                        transformed_query: None,
+                        metadata: Some(metadata),
+                        cached_result: Some(cached),
+                    });
+                }
+            }
+        }
+        
+        // Complex queries cost the most to run and to verify, so they are
+        // limited separately; cached results above don't count
+        if metadata.complexity_score >= self.config.complex_query_threshold {
+            if let Some(limiter) = &self.complex_query_limiter {
+                if limiter.check().is_err() {
+                    warn!("Rejecting query with complexity {}: complex query rate limit exceeded", metadata.complexity_score);
+                    return Ok(QueryProcessingResult {
+                        action: QueryAction::Reject,
+                        transformed_query: None,
+                        metadata: Some(metadata),
+                        cached_result: None,
+                    });
+                }
+            }
+        }
+        
         // Decide if we need to rewrite the query
         let rewrite_result = if self.config.enable_rewriting {
             self.rewriter.rewrite(query, &metadata)?
@@ -137,15 +424,20 @@ impl InterceptionManager {
                 action: QueryAction::Handle,
                 transformed_query: Some(rewrite_result.0),
                 metadata: Some(metadata),
+                cached_result: None,
             });
         }
         
+        // The rows of a COPY FROM STDIN follow as CopyData messages
+        if metadata.query_type == QueryType::Copy {
+            self.copy_in = CopyCapture::new(query, self.config.max_copy_size)
+                .map(|capture| (capture, metadata.clone()));
+        }
+        
         // Prepare for verification if enabled
         if self.config.capture_state {
             debug!("Preparing for verification");
-            tokio::runtime::Runtime::new()
-                .expect("Failed to create runtime")
-                .block_on(self.verifier.prepare_verification(&metadata))?;
+            self.verifier.prepare_verification(&metadata).await?;
         }
         
         // Return the processing result
@@ -153,11 +445,41 @@ impl InterceptionManager {
             action: QueryAction::Forward,
             transformed_query: Some(rewrite_result.0),
             metadata: Some(metadata),
+            cached_result: None,
         })
     }
     
+    /// Cache the backend's result for a forwarded query
+    ///
+    /// `messages` are the query's result messages, from the row description
+    /// to command completion. The result is cached together with proofs of
+    /// the tables it reads, under their current committed roots; queries
+    /// that aren't cacheable or read tables without a committed root are
+    /// skipped.
+    pub fn cache_result(&mut self, metadata: &QueryMetadata, params: &[Value], messages: Vec<BackendMessage>) {
+        if !self.result_cache.is_cacheable(metadata) {
+            return;
+        }
+        
+        let tables = cache::read_tables(metadata);
+        let Some(table_roots) = self.verifier.get_table_roots(&tables) else {
+            debug!("Not caching result: tables {:?} have no committed roots", tables);
+            return;
+        };
+        let proofs: HashMap<String, Vec<u8>> = tables.iter()
+            .filter_map(|table| self.verifier.generate_table_proof(table).ok().map(|proof| (table.clone(), proof)))
+            .collect();
+        
+        self.result_cache.insert(metadata, params, &table_roots, messages, proofs);
+    }
+    
+    /// Get the result cache's hit and miss counts
+    pub fn result_cache_stats(&self) -> ResultCacheStats {
+        self.result_cache.stats()
+    }
+    
     /// Process backend response for analysis and verification
-    pub fn process_response(&mut self, message: &BackendMessage, metadata: Option<&QueryMetadata>) -> Result<()> {
+    pub async fn process_response(&mut self, message: &BackendMessage, metadata: Option<&QueryMetadata>) -> Result<()> {
         match message {
             BackendMessage::DataRow(_) => {
                 // If we have metadata, track the result row
@@ -182,12 +504,27 @@ impl InterceptionManager {
                         // TODO: Get transaction ID from current transaction
                         let tx_id = 0;
                         
-                        let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
-                        runtime
-                            .block_on(async {
-                                // Complete the transaction
-                                self.verifier.complete_transaction(tx_id, rows_affected).await
-                            })?;
+                        // Complete the transaction
+                        self.verifier.complete_transaction(tx_id, rows_affected).await?;
+                    }
+                    
+                    // Two-phase commit: hold the transaction's statements from
+                    // PREPARE TRANSACTION, and verify them only once committed
+                    match &metadata.query_type {
+                        QueryType::PrepareTransaction(gid) => {
+                            // The statements still pending are those run in the prepared transaction
+                            let statements = self.verifier.get_unprepared_transactions();
+                            self.verifier.prepare_transaction(gid, &statements)?;
+                        }
+                        QueryType::CommitPrepared(gid) => {
+                            debug!("Prepared transaction {} committed, verifying...", gid);
+                            
+                            self.verifier.commit_prepared(gid).await?;
+                        }
+                        QueryType::RollbackPrepared(gid) => {
+                            self.verifier.rollback_prepared(gid)?;
+                        }
+                        _ => {}
                     }
                 }
             }
@@ -242,6 +579,9 @@ pub struct QueryProcessingResult {
     
     /// Query metadata if analysis was successful
     pub metadata: Option<QueryMetadata>,
+    
+    /// Result to send instead of forwarding, when the action is `Cached`
+    pub cached_result: Option<CachedResult>,
 }
 
 /// Action to take with a processed query
@@ -253,6 +593,9 @@ pub enum QueryAction {
     /// Handle the query directly without forwarding
     Handle,
     
+    /// Send the cached result without forwarding
+    Cached,
+    
     /// Reject the query
     Reject,
 } 
\ No newline at end of file
@@ -4,12 +4,17 @@
 //! execution, replace non-deterministic functions, and enforce query plans when needed.
 
 use crate::error::{ProxyError, Result};
-use crate::interception::analyzer::{NonDeterministicOperation, QueryMetadata, QueryType};
+use crate::interception::analyzer::{NonDeterministicOperation, QueryMetadata, QueryType, AGGREGATE_FUNCTIONS};
 use log::{debug, warn, info};
 use std::collections::HashMap;
-use sqlparser::ast::{Statement, Query, SetExpr, Select, Expr, Function, FunctionArg, ObjectName, Ident};
+use std::ops::ControlFlow;
+use sqlparser::ast::{
+    visit_expressions, visit_expressions_mut, Statement, Query, SetExpr, Select, Expr, Function, FunctionArg, ObjectName, Ident,
+    GroupByExpr, OrderByExpr, TableFactor,
+};
 use sqlparser::dialect::PostgreSqlDialect;
 use sqlparser::parser::Parser;
+use verifiable_db_core::models::TableSchema;
 
 /// Reason for rewriting a query
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -35,6 +40,9 @@ pub enum RewriteReason {
     /// Non-deterministic query plan
     NonDeterministicPlan,
     
+    /// Row-locking clause removed for replay
+    StripRowLocks,
+    
     /// Other reason
     Other(String),
 }
@@ -80,6 +88,9 @@ pub struct QueryRewriter {
     /// Function replacements
     function_replacements: HashMap<String, FunctionReplacement>,
     
+    /// Primary key columns of each table, keyed by lowercase table name
+    primary_keys: HashMap<String, Vec<String>>,
+    
     /// Configuration for the rewriter
     config: RewriterConfig,
 }
@@ -104,6 +115,17 @@ pub struct RewriterConfig {
     
     /// Whether to reject non-deterministic queries that can't be fixed
     pub reject_unfixable_queries: bool,
+    
+    /// Whether to remove `FOR UPDATE` / `FOR SHARE` clauses from SELECTs
+    ///
+    /// On the primary, row locks order a transaction against concurrent
+    /// writers of the same rows, and the verified history reflects that
+    /// order. Replay runs the recorded transactions one at a time in that
+    /// order, so the locks no longer decide anything and only cost lock
+    /// bookkeeping; a replaying rewriter may strip them. Never enable this
+    /// for queries sent to the primary, where stripping a lock changes which
+    /// transaction wins a race.
+    pub strip_row_locks: bool,
 }
 
 impl Default for RewriterConfig {
@@ -115,6 +137,7 @@ impl Default for RewriterConfig {
             add_tracking: false,
             max_query_length: 100000,
             reject_unfixable_queries: false,
+            strip_row_locks: false,
         }
     }
 }
@@ -131,6 +154,9 @@ pub const NON_DETERMINISTIC_FUNCTIONS: &[&str] = &[
     "clock_timestamp()",
     "statement_timestamp()",
     "transaction_timestamp()",
+    "lo_create(0)",
+    "lo_creat(-1)",
+    "lo_from_bytea(0,",
 ];
 
 /// Map of non-deterministic functions to their deterministic replacements
@@ -151,10 +177,192 @@ pub fn get_deterministic_replacement(function: &str, tx_id: u64, seed: u64) -> O
         "timeofday()" | "clock_timestamp()" | "statement_timestamp()" | "transaction_timestamp()" => {
             Some("verification_timestamp()".to_string())
         },
+        // Let the server pick the OID of a new large object
+        "lo_create(0)" | "lo_creat(-1)" => {
+            Some(format!("lo_create({})", deterministic_large_object_oid(tx_id, seed)))
+        },
+        "lo_from_bytea(0," => {
+            Some(format!("lo_from_bytea({},", deterministic_large_object_oid(tx_id, seed)))
+        },
         _ => None,
     }
 }
 
+/// OID given to a large object the server would otherwise assign itself
+///
+/// Derived from the transaction, so the original execution and its replay
+/// create the object under the same OID. Always at or above 16384, the
+/// first OID PostgreSQL leaves to user objects.
+pub fn deterministic_large_object_oid(tx_id: u64, seed: u64) -> u32 {
+    const FIRST_NORMAL_OBJECT_ID: u64 = 16384;
+    let mixed = (tx_id ^ seed.rotate_left(32)).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+    (FIRST_NORMAL_OBJECT_ID + mixed % (u32::MAX as u64 + 1 - FIRST_NORMAL_OBJECT_ID)) as u32
+}
+
+/// Column names of a table in column order, each with its default expression
+pub type ColumnDefaults = Vec<(String, Option<String>)>;
+
+/// A column an INSERT fills from a non-deterministic default
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonDeterministicDefault {
+    /// Table being inserted into
+    pub table_name: String,
+    
+    /// Column taking its default
+    pub column: String,
+    
+    /// Non-deterministic function the default calls
+    pub function: &'static str,
+    
+    /// Whether [`fill_non_deterministic_defaults`] can supply the value explicitly
+    pub fixable: bool,
+}
+
+/// Find the non-deterministic function an SQL expression calls, if any
+pub fn find_non_deterministic_function(expression: &str) -> Option<&'static str> {
+    let expression = expression.to_lowercase();
+    NON_DETERMINISTIC_FUNCTIONS.iter().copied().find(|f| expression.contains(f))
+}
+
+/// Find the columns an INSERT fills from non-deterministic defaults
+///
+/// `defaults` maps lowercase table names to their columns. A column takes
+/// its default when the INSERT's column list omits it, when a VALUES row
+/// without a column list stops short of it, or when a row supplies
+/// `DEFAULT` for it.
+pub fn find_non_deterministic_defaults(statement: &Statement, defaults: &HashMap<String, ColumnDefaults>) -> Vec<NonDeterministicDefault> {
+    let (table_name, columns, source) = match statement {
+        Statement::Insert { table_name, columns, source, .. } => (table_name, columns, source),
+        _ => return Vec::new(),
+    };
+    let table = table_name.0.last().map(|ident| ident.value.to_lowercase()).unwrap_or_default();
+    let table_defaults = match defaults.get(&table) {
+        Some(table_defaults) => table_defaults,
+        None => return Vec::new(),
+    };
+    let rows = values_rows(source.as_deref());
+    
+    defaulted_columns(columns, source.as_deref(), table_defaults)
+        .into_iter()
+        .map(|(index, function)| NonDeterministicDefault {
+            table_name: table.clone(),
+            column: table_defaults[index].0.clone(),
+            function,
+            fixable: rows.is_some(),
+        })
+        .collect()
+}
+
+/// Rewrite an `INSERT ... VALUES` so columns with non-deterministic defaults get deterministic values
+///
+/// Each such column is supplied explicitly with the replacement from
+/// [`get_deterministic_replacement`]. Returns `None` if the statement takes
+/// no such default or can't be rewritten (e.g. `INSERT ... SELECT`).
+pub fn fill_non_deterministic_defaults(query: &str, defaults: &HashMap<String, ColumnDefaults>, tx_id: u64, seed: u64) -> Option<String> {
+    let dialect = PostgreSqlDialect {};
+    let mut statements = Parser::parse_sql(&dialect, query).ok()?;
+    if statements.len() != 1 {
+        return None;
+    }
+    let missing = find_non_deterministic_defaults(&statements[0], defaults);
+    if missing.is_empty() || missing.iter().any(|m| !m.fixable) {
+        return None;
+    }
+    
+    let (columns, source) = match &mut statements[0] {
+        Statement::Insert { columns, source: Some(source), .. } => (columns, source),
+        _ => return None,
+    };
+    let rows = match source.body.as_mut() {
+        SetExpr::Values(values) => &mut values.rows,
+        _ => return None,
+    };
+    let table_defaults = defaults.get(&missing[0].table_name)?;
+    
+    // Without a column list, rows name the leading columns positionally
+    if columns.is_empty() {
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        columns.extend(table_defaults.iter().take(width).map(|(name, _)| Ident::new(name)));
+    }
+    
+    for default in &missing {
+        let replacement = get_deterministic_replacement(default.function, tx_id, seed)?;
+        let replacement = Parser::new(&dialect).try_with_sql(&replacement).ok()?.parse_expr().ok()?;
+        match columns.iter().position(|c| c.value.eq_ignore_ascii_case(&default.column)) {
+            // Supplied as DEFAULT in some rows
+            Some(position) => {
+                for row in rows.iter_mut() {
+                    if row.get(position).is_some_and(is_default_keyword) {
+                        row[position] = replacement.clone();
+                    }
+                }
+            }
+            // Omitted from the column list
+            None => {
+                columns.push(Ident::new(&default.column));
+                for row in rows.iter_mut() {
+                    row.push(replacement.clone());
+                }
+            }
+        }
+    }
+    
+    Some(statements[0].to_string())
+}
+
+/// Rows of an INSERT source given as VALUES
+fn values_rows(source: Option<&Query>) -> Option<&[Vec<Expr>]> {
+    match source.map(|query| query.body.as_ref()) {
+        Some(SetExpr::Values(values)) => Some(values.rows.as_slice()),
+        _ => None,
+    }
+}
+
+/// Whether an expression is the `DEFAULT` keyword of a VALUES row
+fn is_default_keyword(expr: &Expr) -> bool {
+    matches!(expr, Expr::Identifier(ident) if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("default"))
+}
+
+/// Indexes of table columns an INSERT fills from a non-deterministic default, with the function called
+fn defaulted_columns(columns: &[Ident], source: Option<&Query>, table_defaults: &ColumnDefaults) -> Vec<(usize, &'static str)> {
+    let rows = values_rows(source);
+    
+    // Target columns in the order values are given; `None` if every column is supplied
+    let targets: Option<Vec<String>> = if !columns.is_empty() {
+        Some(columns.iter().map(|c| c.value.to_lowercase()).collect())
+    } else if source.is_none() {
+        // DEFAULT VALUES
+        Some(Vec::new())
+    } else {
+        rows.map(|rows| {
+            let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+            table_defaults.iter().take(width).map(|(name, _)| name.to_lowercase()).collect()
+        })
+    };
+    
+    let mut found = Vec::new();
+    for (index, (name, default)) in table_defaults.iter().enumerate() {
+        let function = match default.as_deref().and_then(find_non_deterministic_function) {
+            Some(function) => function,
+            None => continue,
+        };
+        let position = match &targets {
+            Some(targets) => targets.iter().position(|t| t.eq_ignore_ascii_case(name)),
+            None => Some(index),
+        };
+        let takes_default = match position {
+            None => true,
+            Some(position) => rows.is_some_and(|rows| {
+                rows.iter().any(|row| row.get(position).is_some_and(is_default_keyword))
+            }),
+        };
+        if takes_default {
+            found.push((index, function));
+        }
+    }
+    found
+}
+
 impl QueryRewriter {
     /// Create a new query rewriter
     pub fn new(config: RewriterConfig) -> Self {
@@ -233,10 +441,19 @@ impl QueryRewriter {
         
         Self {
             function_replacements,
+            primary_keys: HashMap::new(),
             config,
         }
     }
     
+    /// Record the primary key of a captured table schema
+    ///
+    /// Unordered SELECTs over tables with a known primary key are rewritten
+    /// to be ordered by it.
+    pub fn register_primary_keys(&mut self, schema: &TableSchema) {
+        self.primary_keys.insert(schema.name.to_lowercase(), schema.primary_keys.clone());
+    }
+    
     /// Rewrite a query based on its metadata
     pub fn rewrite(&self, query: &str, metadata: &QueryMetadata) -> Result<(String, RewriteAction)> {
         if !self.config.enabled {
@@ -249,7 +466,8 @@ impl QueryRewriter {
         }
         
         // Check if query needs rewriting
-        if metadata.is_deterministic && !self.config.add_tracking && !self.config.enforce_query_plans {
+        let strip_row_locks = self.config.strip_row_locks && metadata.takes_row_locks();
+        if metadata.is_deterministic && !self.config.add_tracking && !self.config.enforce_query_plans && !strip_row_locks {
             // No rewriting needed
             return Ok((query.to_string(), RewriteAction::None));
         }
@@ -306,7 +524,7 @@ impl QueryRewriter {
         // Add explicit ORDER BY if needed
         if !metadata.is_deterministic && 
            metadata.query_type == QueryType::Select && 
-           metadata.non_deterministic_operations.iter().any(|op| op.operation_type == "OrderBy") {
+           metadata.non_deterministic_operations.iter().any(|op| op.operation_type == "Unordered") {
             
             let (new_statement, action) = self.add_explicit_ordering(&statement, metadata)?;
             statement = new_statement;
@@ -316,6 +534,14 @@ impl QueryRewriter {
             }
         }
         
+        // Remove row locks if configured
+        if strip_row_locks {
+            if let Statement::Query(query) = &mut statement {
+                query.locks.clear();
+                rewrite_action = RewriteAction::Rewritten(RewriteReason::StripRowLocks);
+            }
+        }
+        
         // Add query plan enforcement if configured
         if self.config.enforce_query_plans && 
            (metadata.query_type == QueryType::Select || 
@@ -333,13 +559,7 @@ impl QueryRewriter {
         // Convert rewritten statement back to SQL
         let rewritten_query = self.statement_to_string(&statement);
         
-        // Special case for NOW() function which might not be properly handled by the parser
-        if !metadata.is_deterministic && query.to_lowercase().contains("now()") && !rewritten_query.contains("verification_timestamp") {
-            let direct_replacement = query.replace("now()", "verification_timestamp()").replace("NOW()", "verification_timestamp()");
-            return Ok((direct_replacement, RewriteAction::Rewritten(RewriteReason::NonDeterministicFunction)));
-        }
-        
-        if rewritten_query == query {
+        if rewrite_action == RewriteAction::None || rewritten_query == query {
             // No changes were made
             return Ok((query.to_string(), RewriteAction::None));
         }
@@ -374,31 +594,75 @@ impl QueryRewriter {
         
         // Clone the statement for modification
         let modified_statement = self.replace_functions(statement)?;
+        if modified_statement == *statement {
+            return Ok((modified_statement, RewriteAction::None));
+        }
         
         Ok((modified_statement, RewriteAction::Rewritten(RewriteReason::NonDeterministicFunction)))
     }
     
     /// Add explicit ordering to a query
-    fn add_explicit_ordering(&self, statement: &Statement, metadata: &QueryMetadata) 
+    ///
+    /// A SELECT without ORDER BY is ordered by the primary keys of every
+    /// table it reads, which totally orders its rows. Queries reading a table
+    /// without a known primary key or a derived table, and queries whose rows
+    /// aren't table rows (DISTINCT, GROUP BY, aggregates), are left unchanged.
+    fn add_explicit_ordering(&self, statement: &Statement, _metadata: &QueryMetadata) 
         -> Result<(Statement, RewriteAction)> {
+        let unchanged = || Ok((statement.clone(), RewriteAction::None));
         
-        // This is a placeholder for actual implementation
-        // In a real implementation, we would analyze the query and add appropriate ORDER BY clauses
-        
-        if let Statement::Query(query) = statement {
-            match query.body.as_ref() {
-                SetExpr::Select(select) => {
-                    // If this is a SELECT without an ORDER BY, add it
-                    if query.order_by.is_empty() {
-                        // Implementation would go here
-                    }
+        let query = match statement {
+            Statement::Query(query) if query.order_by.is_empty() => query,
+            _ => return unchanged(),
+        };
+        let select = match query.body.as_ref() {
+            SetExpr::Select(select) => select,
+            _ => return unchanged(),
+        };
+        
+        let grouped = !matches!(&select.group_by, GroupByExpr::Expressions(exprs) if exprs.is_empty());
+        if select.distinct.is_some() || grouped || select.having.is_some() || projects_aggregate(select) {
+            return unchanged();
+        }
+        
+        let mut order_by = Vec::new();
+        for table_with_joins in &select.from {
+            let relations = std::iter::once(&table_with_joins.relation)
+                .chain(table_with_joins.joins.iter().map(|join| &join.relation));
+            for relation in relations {
+                let (name, alias) = match relation {
+                    TableFactor::Table { name, alias, .. } => (name, alias),
+                    _ => return unchanged(),
+                };
+                let table = name.0.last().map(|ident| ident.value.to_lowercase()).unwrap_or_default();
+                let keys = match self.get_primary_keys(&table) {
+                    Some(keys) => keys,
+                    None => return unchanged(),
+                };
+                
+                let qualifier = match alias {
+                    Some(alias) => vec![alias.name.clone()],
+                    None => name.0.clone(),
+                };
+                for key in keys {
+                    let mut parts = qualifier.clone();
+                    parts.push(column_ident(&key));
+                    order_by.push(OrderByExpr {
+                        expr: Expr::CompoundIdentifier(parts),
+                        asc: None,
+                        nulls_first: None,
+                    });
                 }
-                _ => {}
             }
         }
         
-        // Return unchanged statement
-        Ok((statement.clone(), RewriteAction::None))
+        if order_by.is_empty() {
+            return unchanged();
+        }
+        
+        let mut ordered = query.clone();
+        ordered.order_by = order_by;
+        Ok((Statement::Query(ordered), RewriteAction::Rewritten(RewriteReason::AddExplicitOrdering)))
     }
     
     /// Enforce a specific query plan
@@ -429,9 +693,7 @@ impl QueryRewriter {
     
     /// Convert a statement back to a string
     fn statement_to_string(&self, statement: &Statement) -> String {
-        // In a real implementation, we would use a proper SQL formatter
-        // For now, we'll use a simple debug representation
-        format!("{:?}", statement)
+        statement.to_string()
     }
     
     /// Check if a query can be fixed automatically
@@ -553,83 +815,79 @@ impl QueryRewriter {
         Ok(tables)
     }
 
-    /// Get primary keys for a table
+    /// Get primary keys for a table, if registered
     fn get_primary_keys(&self, table: &str) -> Option<Vec<String>> {
-        // In a real implementation, you would query the database schema
-        // For now, we'll return a simple default
-        Some(vec!["id".to_string()])
+        self.primary_keys
+            .get(&table.to_lowercase())
+            .filter(|keys| !keys.is_empty())
+            .cloned()
     }
 
     /// Replace non-deterministic functions in the statement with deterministic equivalents
+    ///
+    /// Calls are renamed in place, so their arguments are kept and names
+    /// inside string literals or identifiers are left alone. Keyword forms
+    /// like `CURRENT_TIMESTAMP` become ordinary calls of their replacement.
     fn replace_functions(&self, statement: &Statement) -> Result<Statement> {
-        // Convert statement to string for text-based replacement
-        // This is a simplified approach; in a real implementation, we'd walk the AST
-        let statement_str = self.statement_to_string(statement);
-        let mut rewritten_str = statement_str.clone();
-        let mut has_replacements = false;
-        
-        // First, handle specific common functions like NOW() directly
-        if rewritten_str.to_lowercase().contains("now()") {
-            rewritten_str = rewritten_str.replace("now()", "verification_timestamp()");
-            rewritten_str = rewritten_str.replace("NOW()", "verification_timestamp()");
-            has_replacements = true;
-        }
-        
-        // Also check for CURRENT_TIMESTAMP
-        if rewritten_str.to_lowercase().contains("current_timestamp") {
-            rewritten_str = rewritten_str.replace("current_timestamp", "verification_timestamp()");
-            rewritten_str = rewritten_str.replace("CURRENT_TIMESTAMP", "verification_timestamp()");
-            has_replacements = true;
-        }
-        
-        // Then process the configured replacements
-        for replacement in self.function_replacements.values() {
-            let original_pattern = format!("{}(", replacement.original);
-            let replacement_pattern = format!("{}(", replacement.replacement);
-            
-            if rewritten_str.to_lowercase().contains(&original_pattern.to_lowercase()) {
-                rewritten_str = rewritten_str.replace(&original_pattern.to_lowercase(), &replacement_pattern);
-                rewritten_str = rewritten_str.replace(&original_pattern.to_uppercase(), &replacement_pattern);
-                has_replacements = true;
-            }
-            
-            // Also check for function calls without parentheses (e.g., CURRENT_TIMESTAMP)
-            let original_word = format!("{} ", replacement.original);
-            let replacement_word = format!("{}() ", replacement.replacement);
-            
-            if rewritten_str.to_lowercase().contains(&original_word.to_lowercase()) {
-                rewritten_str = rewritten_str.replace(&original_word.to_lowercase(), &replacement_word);
-                rewritten_str = rewritten_str.replace(&original_word.to_uppercase(), &replacement_word);
-                has_replacements = true;
+        let mut rewritten = statement.clone();
+        let _ = visit_expressions_mut(&mut rewritten, |expr| {
+            if let Expr::Function(function) = expr {
+                if let Some(replacement) = self.find_function_replacement(&function.name) {
+                    debug!("Replacing {} with {}", function.name, replacement.replacement);
+                    function.name = ObjectName(vec![Ident::new(replacement.replacement.clone())]);
+                    function.special = false;
+                }
             }
-        }
+            ControlFlow::<()>::Continue(())
+        });
         
-        // For debugging
-        debug!("Original query: {}", statement_str);
-        debug!("Rewritten query: {}", rewritten_str);
+        Ok(rewritten)
+    }
+    
+    /// The replacement for calls of the function `name`, if any
+    ///
+    /// Unquoted names match case-insensitively; qualified names only match
+    /// built-ins in `pg_catalog`.
+    fn find_function_replacement(&self, name: &ObjectName) -> Option<&FunctionReplacement> {
+        let (function, qualifier) = match name.0.as_slice() {
+            [function] => (function, None),
+            [schema, function] => (function, Some(schema)),
+            _ => return None,
+        };
+        if qualifier.is_some_and(|schema| !schema.value.eq_ignore_ascii_case("pg_catalog")) {
+            return None;
+        }
         
-        if has_replacements {
-            // Parse the rewritten query back into a statement
-            use sqlparser::dialect::PostgreSqlDialect;
-            use sqlparser::parser::Parser;
-            
-            let dialect = PostgreSqlDialect {};
-            match Parser::parse_sql(&dialect, &rewritten_str) {
-                Ok(statements) => {
-                    if !statements.is_empty() {
-                        return Ok(statements[0].clone());
-                    }
-                }
-                Err(err) => {
-                    debug!("Failed to parse rewritten query: {}", err);
-                    // Fall back to the original statement
-                    return Ok(statement.clone());
-                }
+        let key = match function.quote_style {
+            Some(_) => function.value.clone(),
+            None => function.value.to_lowercase(),
+        };
+        self.function_replacements.get(&key)
+    }
+}
+
+/// Whether a SELECT list calls an aggregate function outside a window
+fn projects_aggregate(select: &Select) -> bool {
+    visit_expressions(&select.projection, |expr| match expr {
+        Expr::Function(function) if function.over.is_none() => {
+            let name = function.name.0.last().map(|ident| ident.value.to_lowercase()).unwrap_or_default();
+            if AGGREGATE_FUNCTIONS.contains(&name.as_str()) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
             }
         }
-        
-        // If no replacements were made or parsing failed, return the original statement
-        Ok(statement.clone())
+        _ => ControlFlow::Continue(()),
+    })
+    .is_break()
+}
+
+/// Identifier for a column name, quoted when it isn't all lowercase
+fn column_ident(name: &str) -> Ident {
+    if name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        Ident::new(name)
+    } else {
+        Ident::with_quote('"', name)
     }
 }
 
@@ -668,6 +926,7 @@ mod tests {
             complexity_score: 10,
             special_handling: false,
             verifiable: true,
+            metadata_query: false,
             cacheable: true,
             extra: HashMap::new(),
             non_deterministic_reason: None,
@@ -766,4 +1025,120 @@ mod tests {
             panic!("Expected RewriteAction::Rewritten, got {:?}", action);
         }
     }
-} 
\ No newline at end of file
+    
+    fn rewriter_with_primary_keys() -> QueryRewriter {
+        let mut rewriter = QueryRewriter::new(RewriterConfig::default());
+        rewriter.register_primary_keys(&TableSchema::new("users".to_string(), vec![], vec!["id".to_string()], vec![], vec![]));
+        rewriter.register_primary_keys(&TableSchema::new(
+            "memberships".to_string(),
+            vec![],
+            vec!["org_id".to_string(), "userId".to_string()],
+            vec![],
+            vec![],
+        ));
+        rewriter
+    }
+    
+    #[test]
+    fn test_order_by_primary_key_injection() {
+        let mut analyzer = QueryAnalyzer::new();
+        let rewriter = rewriter_with_primary_keys();
+        
+        let query = "SELECT id, name FROM users WHERE age > 18";
+        let metadata = analyzer.analyze(query).unwrap();
+        let (rewritten_query, action) = rewriter.rewrite(query, &metadata).unwrap();
+        assert_eq!(action, RewriteAction::Rewritten(RewriteReason::AddExplicitOrdering));
+        assert_eq!(rewritten_query, "SELECT id, name FROM users WHERE age > 18 ORDER BY users.id");
+        
+        // Every joined table contributes its key, through its alias
+        let query = "SELECT u.name FROM users u JOIN memberships m ON m.\"userId\" = u.id LIMIT 10";
+        let metadata = analyzer.analyze(query).unwrap();
+        let (rewritten_query, _) = rewriter.rewrite(query, &metadata).unwrap();
+        assert_eq!(
+            rewritten_query,
+            "SELECT u.name FROM users AS u JOIN memberships AS m ON m.\"userId\" = u.id ORDER BY u.id, m.org_id, m.\"userId\" LIMIT 10"
+        );
+        
+        // The rewritten query is deterministic
+        assert!(analyzer.analyze(&rewritten_query).unwrap().non_deterministic_operations.is_empty());
+    }
+    
+    #[test]
+    fn test_existing_order_by_untouched() {
+        let mut analyzer = QueryAnalyzer::new();
+        let rewriter = rewriter_with_primary_keys();
+        
+        let query = "SELECT id, name FROM users ORDER BY name, id";
+        let metadata = analyzer.analyze(query).unwrap();
+        let (rewritten_query, action) = rewriter.rewrite(query, &metadata).unwrap();
+        assert_eq!(action, RewriteAction::None);
+        assert_eq!(rewritten_query, query);
+    }
+    
+    #[test]
+    fn test_order_by_injection_needs_primary_key() {
+        let mut analyzer = QueryAnalyzer::new();
+        let rewriter = rewriter_with_primary_keys();
+        
+        for query in [
+            // No primary key registered for logs
+            "SELECT message FROM logs",
+            "SELECT u.name FROM users u JOIN logs l ON l.user_id = u.id",
+            // Rows that aren't table rows can't be ordered by a table's key
+            "SELECT DISTINCT name FROM users",
+            "SELECT count(*) FROM users",
+        ] {
+            let metadata = analyzer.analyze(query).unwrap();
+            let (rewritten_query, action) = rewriter.rewrite(query, &metadata).unwrap();
+            assert_eq!(action, RewriteAction::None, "{}", query);
+            assert_eq!(rewritten_query, query);
+        }
+    }
+    
+    #[test]
+    fn test_replace_non_deterministic_functions() {
+        let mut analyzer = QueryAnalyzer::new();
+        let rewriter = QueryRewriter::new(RewriterConfig::default());
+        
+        let query = "INSERT INTO events (id, created_at, score, logged_at, note) \
+                     VALUES (gen_random_uuid(), NOW(), random(1, 10), CURRENT_TIMESTAMP, 'now()')";
+        let metadata = analyzer.analyze(query).unwrap();
+        let (rewritten_query, action) = rewriter.rewrite(query, &metadata).unwrap();
+        
+        assert_eq!(action, RewriteAction::Rewritten(RewriteReason::NonDeterministicFunction));
+        assert_eq!(
+            rewritten_query,
+            "INSERT INTO events (id, created_at, score, logged_at, note) \
+             VALUES (verification_uuid(), verification_timestamp(), verification_random(1, 10), verification_timestamp(), 'now()')"
+        );
+        
+        // Functions that only share a replacement's name are left alone
+        let query = "SELECT audit.now(), \"NOW\"() FROM users ORDER BY id";
+        let metadata = create_test_metadata(query, false);
+        let (rewritten_query, action) = rewriter.rewrite(query, &metadata).unwrap();
+        assert_eq!(action, RewriteAction::None);
+        assert_eq!(rewritten_query, query);
+    }
+    
+    #[test]
+    fn test_row_lock_policy() {
+        let mut analyzer = QueryAnalyzer::new();
+        let query = "SELECT id, balance FROM accounts WHERE id = 1 ORDER BY id FOR UPDATE";
+        let metadata = analyzer.analyze(query).unwrap();
+        
+        // Locks are preserved by default
+        let rewriter = QueryRewriter::new(RewriterConfig::default());
+        let (rewritten_query, action) = rewriter.rewrite(query, &metadata).unwrap();
+        assert_eq!(action, RewriteAction::None);
+        assert_eq!(rewritten_query, query);
+        
+        let rewriter = QueryRewriter::new(RewriterConfig {
+            strip_row_locks: true,
+            ..Default::default()
+        });
+        let (rewritten_query, action) = rewriter.rewrite(query, &metadata).unwrap();
+        assert_eq!(action, RewriteAction::Rewritten(RewriteReason::StripRowLocks));
+        assert_eq!(rewritten_query, "SELECT id, balance FROM accounts WHERE id = 1 ORDER BY id");
+    }
+}
+
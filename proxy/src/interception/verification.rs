@@ -2,15 +2,18 @@
 
 use crate::error::{ProxyError, Result};
 use crate::interception::analyzer::{QueryMetadata, QueryType, AccessType, TableAccess};
-use crate::verification::environment::{VerificationEnvironment, VerificationEnvironmentConfig};
-use crate::verification::contract::{ContractManager, ContractConfig, StateCommitment, Challenge};
-use crate::verification::state::{StateCaptureManager, RowId, DatabaseState as StateDBState};
+use crate::verification::environment::{VerificationEnvironment, VerificationEnvironmentConfig, ReplayStatement, PoolStatus, VersionMismatchPolicy, LowDiskPolicy, check_postgres_versions, query_server_version_num};
+use crate::verification::contract::{ContractManager, ContractConfig, StateCommitment, Challenge, ChallengeStatus};
+use crate::verification::commitment_queue::{CommitmentQueue, CommitmentRetryConfig, QueuedCommitment, AttemptOutcome};
+use crate::verification::events::{EventPublisher, EventPublisherConfig, VerificationEvent, hex_root};
+use crate::verification::state::{StateCaptureManager, RowId, DatabaseState as StateDBState, PostgresVersions, ReconciliationReport};
 use crate::transaction::{TransactionManager, TransactionStatus};
 use crate::verification::{
     client::VerificationServiceClient
 };
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use log::{debug, info, warn, error};
 use serde::{Serialize, Deserialize};
@@ -18,11 +21,13 @@ use tokio::sync::Mutex as TokioMutex;
 use sha2::{Sha256, Digest, digest::FixedOutput, digest::Update};
 use uuid::Uuid;
 use tokio_postgres::{Client, Config, NoTls};
+use bytes::Bytes;
+use deadpool_postgres::{Manager, ManagerConfig, Pool, PoolConfig, RecyclingMethod};
 use crate::config::ProxyConfig;
 use hex;
 use serde_json;
 use tokio::runtime::Runtime;
-use verifiable_db_core::models::{RowId, BlockState as CoreDatabaseState, TableSchema, Row, Value, ColumnDefinition, ColumnType};
+use verifiable_db_core::models::{RowId, BlockState as CoreDatabaseState, TableSchema, TableState, Row, Value, ColumnDefinition, ColumnType};
 use crate::verification::VerificationEngine;
 
 /// Verification status of a transaction
@@ -44,6 +49,31 @@ pub enum VerificationStatus {
     Skipped,
 }
 
+/// Reason recorded on transactions skipped because the verifier was unreachable
+pub const VERIFIER_UNAVAILABLE_REASON: &str = "verifier unavailable";
+
+/// Reason recorded on transactions skipped because the verification database was low on disk space
+pub const LOW_DISK_REASON: &str = "verification database low on disk space";
+
+/// Reason recorded on skipped transactions handed to the verification service once the verifier recovered
+pub const RESUBMITTED_REASON: &str = "verifier unavailable, resubmitted to the verification service";
+
+/// Policy applied when the verification database cannot be reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifierUnavailablePolicy {
+    /// Treat unverifiable transactions as failed (rejected under enforcement)
+    FailClosed,
+    
+    /// Keep committing state roots but skip replay verification
+    DegradeToCommitOnly,
+}
+
+impl Default for VerifierUnavailablePolicy {
+    fn default() -> Self {
+        Self::FailClosed
+    }
+}
+
 /// Result of verification
 #[derive(Debug, Clone)]
 pub struct VerificationResult {
@@ -69,6 +99,41 @@ pub struct VerificationResult {
     pub metadata: HashMap<String, String>,
 }
 
+/// Result of verifying the statements of a transaction block between two checkpoints
+#[derive(Debug, Clone)]
+pub struct CheckpointResult {
+    /// Savepoint the range starts after (`None` = the start of the transaction block)
+    pub from_savepoint: Option<String>,
+    
+    /// Savepoint the range ends at (`None` = COMMIT)
+    pub to_savepoint: Option<String>,
+    
+    /// Verification results of the range's statements, in execution order
+    pub results: Vec<VerificationResult>,
+    
+    /// Failed if any statement failed, Skipped if any was skipped, Verified otherwise
+    pub status: VerificationStatus,
+}
+
+impl CheckpointResult {
+    /// Transactions of the range whose verification failed
+    pub fn failed_transactions(&self) -> Vec<u64> {
+        self.results.iter()
+            .filter(|result| result.status == VerificationStatus::Failed)
+            .map(|result| result.transaction_id)
+            .collect()
+    }
+    
+    /// Human-readable description of the range, e.g. `from savepoint a to COMMIT`
+    pub fn range(&self) -> String {
+        format!(
+            "from {} to {}",
+            self.from_savepoint.as_ref().map_or("BEGIN".to_string(), |name| format!("savepoint {}", name)),
+            self.to_savepoint.as_ref().map_or("COMMIT".to_string(), |name| format!("savepoint {}", name)),
+        )
+    }
+}
+
 /// Configuration for verification
 #[derive(Debug, Clone)]
 pub struct VerificationConfig {
@@ -116,13 +181,113 @@ pub struct VerificationConfig {
     
     /// URL of the verification service
     pub verification_service_url: Option<String>,
+    
+    /// What to do when the verification database is unavailable
+    pub verifier_unavailable_policy: VerifierUnavailablePolicy,
+    
+    /// What to do at startup when the verification database runs a
+    /// different PostgreSQL major version than the live one
+    pub version_mismatch_policy: VersionMismatchPolicy,
+    
+    /// Where to publish verification events
+    pub events: EventPublisherConfig,
+    
+    /// Re-verification of transactions skipped during verifier outages
+    pub reverification: ReverificationConfig,
+    
+    /// Retrying of on-chain commitments that failed to submit
+    pub commitment_retry: CommitmentRetryConfig,
+    
+    /// Verify the statements of a transaction block at each savepoint,
+    /// rather than all at COMMIT
+    ///
+    /// A failure is then localized to the statements between two savepoints,
+    /// and COMMIT only verifies the statements since the last savepoint.
+    pub savepoint_checkpoints: bool,
+}
+
+/// Configuration for re-verifying transactions skipped while the verifier was unavailable
+#[derive(Debug, Clone)]
+pub struct ReverificationConfig {
+    /// Whether the background scheduler runs
+    pub enabled: bool,
+    
+    /// Seconds between scheduler passes
+    pub interval_secs: u64,
+    
+    /// Maximum number of transactions re-verified per pass
+    pub batch_size: usize,
+}
+
+impl Default for ReverificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 30,
+            batch_size: 100,
+        }
+    }
 }
 
 /// Configuration for state capture
 #[derive(Debug, Clone, Default)]
 pub struct VerificationStateConfig {
-    // Basic fields to avoid compilation errors
+    /// Whether database state is captured, starting from a genesis block
+    /// committed over the initial state at startup
     pub enabled: bool,
+    
+    /// Maximum number of tables freshly captured per block (`None` = no cap)
+    ///
+    /// Tables not captured in a block keep their cached roots, so the state
+    /// root always covers every table. Modified tables are captured first,
+    /// oldest change first; see [`StateCaptureManager::with_max_tables_per_block`]
+    /// for the freshness guarantee.
+    pub max_tables_per_block: Option<usize>,
+    
+    /// Apply WAL row changes to cached table states leaf by leaf instead of
+    /// rebuilding each modified table's Merkle tree
+    pub incremental_wal: bool,
+    
+    /// Number of worker threads hashing captured tables concurrently
+    /// (0 or 1 = sequential)
+    pub hash_parallelism: usize,
+    
+    /// Number of tables a full database capture reads concurrently
+    /// (0 or 1 = sequential)
+    pub capture_concurrency: usize,
+    
+    /// Maximum connections to the live database used for captures and
+    /// verification bookkeeping (0 = [`DEFAULT_DATABASE_POOL_SIZE`])
+    pub pool_size: usize,
+    
+    /// Commit tables created after genesis with sparse Merkle trees keyed by
+    /// row id, so row proofs are addressed by key instead of leaf index
+    pub sparse_table_trees: bool,
+    
+    /// Check at every block commit that each foreign key value references an
+    /// existing row, recording dangling references in the block metadata.
+    /// Costs a pass over every table with (or referenced by) a foreign key.
+    pub check_referential_integrity: bool,
+    
+    /// Capture every table of a block within one exported snapshot, so the
+    /// block is a point-in-time state even under concurrent writes
+    pub snapshot_isolation: bool,
+    
+    /// Role state captures run as (`None` = the verification connection's role)
+    ///
+    /// Captures always run with row-level security off and see full,
+    /// unfiltered tables, so the role must be a superuser or have
+    /// `BYPASSRLS` if any captured table has row-level security enabled.
+    pub capture_role: Option<String>,
+    
+    /// Record every applied DDL statement in a chained schema audit log,
+    /// committed with the next block so schema history is tamper-evident
+    pub schema_audit_log: bool,
+    
+    /// Fraction of tables whose roots are recomputed from their rows before
+    /// each block is committed, aborting the commit on a mismatch
+    /// (0 = off, 1 = every table)
+    pub root_validation_sample_rate: f64,
 }
 
 impl Default for VerificationConfig {
@@ -143,10 +308,37 @@ impl Default for VerificationConfig {
             environment: VerificationEnvironmentConfig::default(),
             contract: ContractConfig::default(),
             verification_service_url: None,
+            verifier_unavailable_policy: VerifierUnavailablePolicy::default(),
+            version_mismatch_policy: VersionMismatchPolicy::default(),
+            events: EventPublisherConfig::default(),
+            reverification: ReverificationConfig::default(),
+            commitment_retry: CommitmentRetryConfig::default(),
+            savepoint_checkpoints: false,
         }
     }
 }
 
+/// Default number of pooled connections to the live database
+pub const DEFAULT_DATABASE_POOL_SIZE: usize = 4;
+
+/// Build the pool of live database connections, with at most `size` connections
+///
+/// Returned connections are cleaned (role, settings and open cursors reset)
+/// before reuse, so a capture's role or row-security setting can't leak
+/// into the next caller.
+fn database_pool(db_config: &str, size: usize) -> Result<Pool> {
+    let pg_config = db_config.parse::<Config>()
+        .map_err(|e| ProxyError::Config(format!("Failed to parse database connection string: {}", e)))?;
+    let manager = Manager::from_config(pg_config, NoTls, ManagerConfig {
+        recycling_method: RecyclingMethod::Clean,
+    });
+    let max_size = if size == 0 { DEFAULT_DATABASE_POOL_SIZE } else { size };
+    Pool::builder(manager)
+        .config(PoolConfig { max_size, ..Default::default() })
+        .build()
+        .map_err(|e| ProxyError::Database(format!("Failed to create database connection pool: {}", e)))
+}
+
 /// Database state
 #[derive(Debug, Clone)]
 pub struct DatabaseState {
@@ -204,6 +396,12 @@ pub struct TransactionRecord {
     /// Query that executed the transaction
     pub query: String,
     
+    /// Parameter values bound to the query's placeholders (extended protocol)
+    pub params: Vec<Value>,
+    
+    /// Data streamed by a `COPY ... FROM STDIN`
+    pub copy_data: Option<Bytes>,
+    
     /// Query metadata
     pub metadata: QueryMetadata,
     
@@ -226,6 +424,46 @@ pub struct TransactionRecord {
     pub error: Option<String>,
 }
 
+/// Snapshot of the verification manager's health
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationManagerStatus {
+    /// Whether verification is enabled
+    pub enabled: bool,
+    
+    /// Policy applied when the verifier is unavailable
+    pub verifier_unavailable_policy: VerifierUnavailablePolicy,
+    
+    /// Whether the verification database was unreachable on the last check
+    pub verifier_degraded: bool,
+    
+    /// Whether the block being built contains transactions that skipped replay
+    pub block_degraded: bool,
+    
+    /// Total transactions skipped because the verifier was unavailable
+    pub skipped_unavailable: u64,
+    
+    /// Whether the verification database was low on disk space on the last check
+    pub verifier_low_disk: bool,
+    
+    /// Total transactions skipped because the verification database was low on disk space
+    pub skipped_low_disk: u64,
+    
+    /// Skipped transactions resubmitted to the verification service after the verifier recovered
+    pub reverified: u64,
+    
+    /// On-chain commitments dead-lettered since startup
+    pub dead_lettered_commitments: u64,
+    
+    /// Health of the verification database connection pool
+    pub verifier_pool: PoolStatus,
+    
+    /// Number of pending transactions
+    pub pending_transactions: usize,
+    
+    /// Current block number
+    pub block_number: u64,
+}
+
 /// Verification manager for query verification
 #[derive(Debug)]
 pub struct VerificationManager {
@@ -256,6 +494,9 @@ pub struct VerificationManager {
     /// Contract manager
     contract: Arc<ContractManager>,
     
+    /// Durable queue of on-chain commitments awaiting confirmation
+    commitment_queue: CommitmentQueue,
+    
     /// Transaction manager for transaction boundary protection
     transaction_manager: Arc<Mutex<TransactionManager>>,
     
@@ -264,19 +505,58 @@ pub struct VerificationManager {
     
     /// Database connection string for transaction storage
     db_config: String,
+    
+    /// Pool of live database connections for captures and block bookkeeping
+    database_pool: Pool,
+    
+    /// Whether the verification database was unreachable on the last check
+    verifier_degraded: AtomicBool,
+    
+    /// Whether the current block contains transactions that skipped replay
+    block_degraded: AtomicBool,
+    
+    /// Total transactions skipped because the verifier was unavailable
+    skipped_unavailable: AtomicU64,
+    
+    /// Whether the verification database was low on disk space on the last check
+    verifier_low_disk: AtomicBool,
+    
+    /// Total transactions skipped because the verification database was low on disk space
+    skipped_low_disk: AtomicU64,
+    
+    /// Skipped transactions re-verified after the verifier recovered
+    reverified: AtomicU64,
+    
+    /// On-chain commitments dead-lettered since startup
+    dead_lettered_commitments: AtomicU64,
+    
+    /// Publisher for verification events
+    events: EventPublisher,
 }
 
 impl VerificationManager {
     /// Create a new verification manager with the given configuration
     pub async fn new(config: VerificationConfig) -> Result<Self> {
         // Create state capture manager
-        let state_capture = Arc::new(StateCaptureManager::new());
+        let state_capture = Arc::new(
+            StateCaptureManager::with_max_tables_per_block(config.state_capture.max_tables_per_block)
+                .with_incremental_wal(config.state_capture.incremental_wal)
+                .with_hash_parallelism(config.state_capture.hash_parallelism)
+                .with_capture_concurrency(config.state_capture.capture_concurrency)
+                .with_sparse_table_trees(config.state_capture.sparse_table_trees)
+                .with_referential_integrity_check(config.state_capture.check_referential_integrity)
+                .with_snapshot_isolation(config.state_capture.snapshot_isolation)
+                .with_capture_role(config.state_capture.capture_role.clone())
+                .with_schema_audit_log(config.state_capture.schema_audit_log)
+                .with_root_validation(config.state_capture.root_validation_sample_rate)
+        );
         
         // Create verification environment
         let verification_env = Arc::new(VerificationEnvironment::new(config.environment.clone(), state_capture.clone()));
         
         // Create contract manager
         let contract = Arc::new(ContractManager::new(config.contract.clone()));
+        let commitment_queue = CommitmentQueue::new(config.commitment_retry.clone());
         
         // Create transaction manager
         let transaction_manager = Arc::new(Mutex::new(TransactionManager::new()));
@@ -306,6 +586,10 @@ impl VerificationManager {
         );
         
         info!("Verification manager using database at {}:{}/{}", host, port, database);
+        let database_pool = database_pool(&db_config, config.state_capture.pool_size)?;
+        
+        // Create event publisher (a no-op unless a sink is configured)
+        let events = EventPublisher::from_config(&config.events)?;
         
         let manager = Self {
             current_state: RwLock::new(DatabaseState::new()),
@@ -317,9 +601,19 @@ impl VerificationManager {
             state_capture,
             verification_env,
             contract,
+            commitment_queue,
             transaction_manager,
             verification_service,
             db_config,
+            database_pool,
+            verifier_degraded: AtomicBool::new(false),
+            block_degraded: AtomicBool::new(false),
+            skipped_unavailable: AtomicU64::new(0),
+            verifier_low_disk: AtomicBool::new(false),
+            skipped_low_disk: AtomicU64::new(0),
+            reverified: AtomicU64::new(0),
+            dead_lettered_commitments: AtomicU64::new(0),
+            events,
         };
         
         // Initialize the manager
@@ -331,11 +625,29 @@ impl VerificationManager {
     /// Initialize the verification manager
     pub async fn initialize(&self) -> Result<()> {
         // Initialize the verification environment
-        self.verification_env.initialize().await?;
+        if let Err(e) = self.verification_env.initialize().await {
+            match self.config.verifier_unavailable_policy {
+                VerifierUnavailablePolicy::FailClosed => return Err(e),
+                VerifierUnavailablePolicy::DegradeToCommitOnly => {
+                    warn!("Verification database unavailable, degrading to commit-only: {}", e);
+                    self.set_verifier_degraded(true);
+                }
+            }
+        }
+        
+        // Replay is only deterministic on the live database's major version
+        if self.config.version_mismatch_policy != VersionMismatchPolicy::Ignore && !self.verifier_degraded.load(Ordering::SeqCst) {
+            self.check_server_versions().await?;
+        }
         
         // Initialize the contract manager
         self.contract.initialize().await?;
         
+        // Anchor the chain at the database's initial state
+        if self.config.state_capture.enabled {
+            self.commit_genesis().await?;
+        }
+        
         // Initialize transaction manager
         let mut tx_manager = self.transaction_manager.lock().unwrap();
         tx_manager.initialize().await?;
@@ -343,27 +655,255 @@ impl VerificationManager {
         Ok(())
     }
     
-    /// Get a PostgreSQL client for the main database
-    async fn get_database_client(&self) -> Result<Client> {
-        debug!("Connecting to PostgreSQL to save verification data using connection string");
+    /// Compare the server versions of the live and verification databases
+    ///
+    /// The versions are recorded in the metadata of every later block.
+    async fn check_server_versions(&self) -> Result<()> {
+        let client = self.get_database_client().await?;
+        let versions = PostgresVersions {
+            live: query_server_version_num(&client).await?,
+            verifier: self.verification_env.server_version_num().await?,
+        };
+        self.state_capture.set_postgres_versions(versions)?;
         
-        // Connect to PostgreSQL
-        let (client, connection) = tokio_postgres::connect(&self.db_config, NoTls).await
-            .map_err(|e| ProxyError::Database(format!("Failed to connect to PostgreSQL: {}", e)))?;
+        check_postgres_versions(versions, self.config.version_mismatch_policy)?;
+        Ok(())
+    }
+    
+    /// Capture the initial database state and commit it as the genesis block (block 0)
+    ///
+    /// The genesis block has an all-zero previous hash, so every later block
+    /// chains back to a defined origin. If block 0 was already committed by an
+    /// earlier run, the existing chain is kept.
+    async fn commit_genesis(&self) -> Result<()> {
+        let client = self.get_database_client().await?;
+        
+        let existing = client.query_opt("SELECT state_root FROM verification_blocks WHERE block_number = 0", &[])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to look up genesis block: {}", e)))?;
+        if let Some(row) = existing {
+            let state_root: String = row.get("state_root");
+            info!("Genesis block already committed with root {}", state_root);
+            return Ok(());
+        }
         
-        // The connection object performs the actual communication with the database,
-        // so spawn it off to run on its own
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                error!("Database connection error: {}", e);
-            }
+        let genesis = self.state_capture.capture_genesis(&client).await?;
+        let state_root = genesis.header.state_root;
+        {
+            let mut state = self.current_state.write().unwrap();
+            state.root = state_root;
+            state.block_number = 0;
+            state.timestamp = genesis.header.timestamp.timestamp() as u64;
+            state.table_states = genesis.table_state_roots.clone();
+            state.committed = true;
+        }
+        
+        // Without the retry queue, a failed submission fails startup
+        if !self.config.commitment_retry.enabled {
+            self.contract.commit_state(state_root).await?;
+        }
+        
+        let metadata = serde_json::json!({ "genesis": true });
+        client.execute(
+            "INSERT INTO verification_blocks 
+                (block_number, state_root, transaction_count, timestamp, metadata) 
+                VALUES ($1, $2, $3, $4, $5)",
+            &[
+                &0i64,
+                &format!("0x{}", hex::encode(state_root)),
+                &0i64,
+                &genesis.header.timestamp.timestamp(),
+                &metadata,
+            ],
+        ).await
+            .map_err(|e| ProxyError::Verification(format!("Failed to insert genesis block: {}", e)))?;
+        
+        info!("Committed genesis block with root 0x{}", hex::encode(state_root));
+        self.events.publish(VerificationEvent::BlockCommitted {
+            block_number: 0,
+            state_root: hex_root(&state_root),
+            transaction_count: 0,
+            degraded: false,
         });
         
+        if self.config.commitment_retry.enabled {
+            self.submit_commitment(&client, 0, state_root).await?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Queue a block's on-chain commitment and make the first submission attempt
+    ///
+    /// The commitment is recorded before it is submitted, so a failed attempt
+    /// is left to the retry task instead of failing the block commit. The
+    /// block is finalized once the commitment is confirmed.
+    async fn submit_commitment(&self, client: &Client, block_number: u64, state_root: [u8; 32]) -> Result<()> {
+        self.commitment_queue.enqueue(client, block_number, state_root).await?;
+        
+        let commitment = self.commitment_queue.get(client, block_number).await?
+            .ok_or_else(|| ProxyError::Verification(format!("Commitment for block {} was not queued", block_number)))?;
+        let outcome = self.commitment_queue.attempt(client, &commitment, |root| self.contract.commit_state(root)).await?;
+        self.handle_commitment_outcome(&commitment, &outcome);
+        Ok(())
+    }
+    
+    /// Publish the result of a commitment attempt
+    fn handle_commitment_outcome(&self, commitment: &QueuedCommitment, outcome: &AttemptOutcome) {
+        match outcome {
+            AttemptOutcome::Confirmed => {
+                self.events.publish(VerificationEvent::BlockFinalized {
+                    block_number: commitment.block_number,
+                    state_root: hex_root(&commitment.state_root),
+                    attempts: commitment.attempts + 1,
+                });
+            }
+            AttemptOutcome::DeadLettered { error } => {
+                self.dead_lettered_commitments.fetch_add(1, Ordering::SeqCst);
+                self.events.publish(VerificationEvent::CommitmentDeadLettered {
+                    block_number: commitment.block_number,
+                    state_root: hex_root(&commitment.state_root),
+                    attempts: commitment.attempts + 1,
+                    reason: error.clone(),
+                });
+            }
+            AttemptOutcome::Retrying { .. } => {}
+        }
+    }
+    
+    /// Retry queued commitments whose next attempt is due
+    ///
+    /// Returns the number of commitments attempted.
+    pub async fn retry_commitments(&self) -> Result<usize> {
+        let client = self.get_database_client().await?;
+        let results = self.commitment_queue
+            .process_due(&client, |root| self.contract.commit_state(root))
+            .await?;
+        
+        for (commitment, outcome) in &results {
+            self.handle_commitment_outcome(commitment, outcome);
+        }
+        Ok(results.len())
+    }
+    
+    /// Start the background task retrying failed on-chain commitments
+    ///
+    /// Returns `None` when verification or commitment retries are disabled.
+    pub fn spawn_commitment_retry_task(manager: Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        let config = manager.config.commitment_retry.clone();
+        if !manager.config.enabled || !config.enabled {
+            return None;
+        }
+        
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.poll_interval_secs.max(1)));
+            loop {
+                interval.tick().await;
+                
+                match manager.retry_commitments().await {
+                    Ok(0) => {}
+                    Ok(attempted) => debug!("Commitment retry pass attempted {} commitments", attempted),
+                    Err(e) => warn!("Commitment retry pass failed: {}", e),
+                }
+            }
+        }))
+    }
+    
+    /// Whether a block is finalized, i.e. its on-chain commitment is confirmed
+    pub async fn is_block_finalized(&self, block_number: u64) -> Result<bool> {
+        if !self.config.commitment_retry.enabled {
+            // Commitments are submitted before their block is recorded
+            return Ok(block_number <= self.current_state.read().unwrap().block_number);
+        }
+        
+        let client = self.get_database_client().await?;
+        self.commitment_queue.is_finalized(&client, block_number).await
+    }
+    
+    /// Get the commitments that exhausted their attempts, oldest block first
+    pub async fn get_dead_lettered_commitments(&self) -> Result<Vec<QueuedCommitment>> {
+        let client = self.get_database_client().await?;
+        self.commitment_queue.dead_lettered(&client).await
+    }
+    
+    /// Retry a dead-lettered commitment with a fresh set of attempts
+    ///
+    /// Returns whether the block had a dead-lettered commitment.
+    pub async fn requeue_commitment(&self, block_number: u64) -> Result<bool> {
+        let client = self.get_database_client().await?;
+        self.commitment_queue.requeue(&client, block_number).await
+    }
+    
+    /// Capture the database's large objects and commit them to the state
+    ///
+    /// Keeps the current state root in step with the committed block, so the
+    /// transaction's post-state root covers the large-object writes.
+    async fn commit_large_objects(&self) -> Result<()> {
+        let client = self.get_database_client().await?;
+        let snapshot = StateCaptureManager::capture_large_objects(&client).await?;
+        // Not driven by WAL, so there is no commit LSN to record
+        self.state_capture.commit_large_objects(snapshot, 0)?;
+        
+        if let Some(root) = self.state_capture.get_current_root_hash()? {
+            self.current_state.write().unwrap().root = root;
+        }
+        Ok(())
+    }
+    
+    /// Capture the tables a DDL statement touched and record the change in the schema audit log
+    async fn record_schema_change(&self, metadata: &QueryMetadata) -> Result<()> {
+        let client = self.get_database_client().await?;
+        // Captures cover the public schema, where table names are unqualified
+        let tables: Vec<String> = metadata.get_modified_tables().iter()
+            .map(|table| table.trim_start_matches("public.").to_string())
+            .collect();
+        if let Some(entry) = self.state_capture.capture_schema_change(&client, metadata.query_type.as_str(), &metadata.query, &tables).await? {
+            info!("Recorded schema version {} in the schema audit log", entry.version);
+        }
+        Ok(())
+    }
+    
+    /// Get a PostgreSQL client for the main database from the pool
+    ///
+    /// The client returns to the pool when dropped, so callers hold it for
+    /// one operation only.
+    async fn get_database_client(&self) -> Result<deadpool_postgres::Client> {
+        let client = self.database_pool.get()
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to get database connection from pool: {}", e)))?;
+        debug!("Acquired main database connection from pool");
         Ok(client)
     }
 
     /// Begin a transaction for verification
     pub fn begin_transaction(&self, query: &str, metadata: &QueryMetadata) -> Result<u64> {
+        self.begin_transaction_with_params(query, Vec::new(), metadata)
+    }
+    
+    /// Begin a transaction for a prepared statement executed with bound values
+    ///
+    /// The values are kept with the transaction record so replay runs the
+    /// statement with exactly the parameters of the original execution.
+    pub fn begin_transaction_with_params(&self, query: &str, params: Vec<Value>, metadata: &QueryMetadata) -> Result<u64> {
+        self.record_transaction(query, params, None, metadata)
+    }
+    
+    /// Begin a transaction for a `COPY ... FROM STDIN` bulk load
+    ///
+    /// The streamed data is kept with the transaction record so replay loads
+    /// exactly the rows of the original COPY.
+    pub fn begin_copy_transaction(&self, query: &str, data: Bytes, metadata: &QueryMetadata) -> Result<u64> {
+        self.record_transaction(query, Vec::new(), Some(data), metadata)
+    }
+    
+    /// Record a statement to verify, returning its transaction ID
+    fn record_transaction(
+        &self,
+        query: &str,
+        params: Vec<Value>,
+        copy_data: Option<Bytes>,
+        metadata: &QueryMetadata,
+    ) -> Result<u64> {
         if !self.config.enabled {
             return Ok(0); // Return a dummy transaction ID if verification is disabled
         }
@@ -413,6 +953,8 @@ impl VerificationManager {
         let transaction = TransactionRecord {
             id: transaction_id,
             query: query.to_string(),
+            params,
+            copy_data,
             metadata: metadata.clone(),
             pre_state_root,
             post_state_root: None,
@@ -602,28 +1144,80 @@ impl VerificationManager {
         
         // Verify the transaction
         let verification_start = Instant::now();
-        let verification_result = self.verify_transaction(&transaction.metadata).await;
+        let low_disk = self.skip_for_low_disk(&transaction.metadata).await;
+        let verification_result = if low_disk {
+            Err(ProxyError::Verification(LOW_DISK_REASON.to_string()))
+        } else if self.check_verifier_available(&transaction.metadata).await {
+            self.verify_transaction(&transaction.metadata).await
+        } else {
+            Err(ProxyError::Verification(VERIFIER_UNAVAILABLE_REASON.to_string()))
+        };
         
         // Get verification result
         let verification_time = verification_start.elapsed().as_millis() as u64;
         let mut status = VerificationStatus::NotVerified;
         let error_message;
+        let mut result_metadata = HashMap::new();
         
         match verification_result {
             Ok(_) => {
                 status = VerificationStatus::Verified;
                 error_message = None;
             }
+            Err(_) if low_disk => {
+                // Keep the write, but spare the verification database the replay
+                status = VerificationStatus::Skipped;
+                error_message = Some(LOW_DISK_REASON.to_string());
+                result_metadata.insert("skip_reason".to_string(), LOW_DISK_REASON.to_string());
+                
+                self.set_block_degraded(true);
+                self.skipped_low_disk.fetch_add(1, Ordering::SeqCst);
+                metrics::counter!("verification_skipped_low_disk_total", 1);
+            }
+            Err(_) if self.verifier_degraded.load(Ordering::SeqCst)
+                && self.config.verifier_unavailable_policy == VerifierUnavailablePolicy::DegradeToCommitOnly => {
+                // Keep the write, but record that it was never replayed
+                status = VerificationStatus::Skipped;
+                error_message = Some(VERIFIER_UNAVAILABLE_REASON.to_string());
+                result_metadata.insert("skip_reason".to_string(), VERIFIER_UNAVAILABLE_REASON.to_string());
+                
+                self.set_block_degraded(true);
+                self.skipped_unavailable.fetch_add(1, Ordering::SeqCst);
+                metrics::counter!("verification_skipped_unavailable_total", 1);
+            }
             Err(e) => {
                 status = VerificationStatus::Failed;
                 error_message = Some(e.to_string());
                 
+                self.events.publish(VerificationEvent::VerificationFailed {
+                    transaction_id,
+                    block_number: self.current_state.read().unwrap().block_number,
+                    pre_state_root: transaction.pre_state_root.as_ref().map(hex_root),
+                    post_state_root: transaction.post_state_root.as_ref().map(hex_root),
+                    reason: e.to_string(),
+                });
+                
                 if self.config.enforce {
                     return Err(ProxyError::Verification(format!("Transaction verification failed: {}", e)));
                 }
             }
         }
         
+        // Large objects aren't streamed from WAL, so re-capture them after lo_* writes
+        if transaction.metadata.writes_large_objects() && self.config.state_capture.enabled {
+            if let Err(e) = self.commit_large_objects().await {
+                warn!("Failed to capture large objects after transaction {}: {}", transaction_id, e);
+            }
+        }
+        
+        // Schema changes aren't streamed from WAL either, so capture them after DDL
+        if transaction.metadata.query_type.is_ddl() && self.config.state_capture.schema_audit_log
+            && status != VerificationStatus::Failed {
+            if let Err(e) = self.record_schema_change(&transaction.metadata).await {
+                warn!("Failed to record schema change of transaction {}: {}", transaction_id, e);
+            }
+        }
+        
         // Update transaction record
         {
             let mut records = self.transaction_records.lock().unwrap();
@@ -649,7 +1243,223 @@ impl VerificationManager {
         }
         
         // Save updated transaction to database
-        let transaction_clone = transaction.clone();
+        self.save_transaction_status(transaction.clone());
+        
+        // If verification service is configured, send the transaction for verification
+        if let Some(verification_service) = &self.verification_service {
+            if let Some(pre_state_root) = transaction.pre_state_root {
+                debug!("Sending transaction {} to verification service", transaction_id);
+                if let Err(e) = verification_service.verify_transaction(transaction_id, &transaction.query, &pre_state_root).await {
+                    warn!("Failed to send transaction to verification service: {}", e);
+                    // Don't fail the transaction if the verification service is unavailable
+                    // Just log the error and continue
+                }
+            }
+        }
+        
+        // Check if we need to commit state
+        self.check_commit_state();
+        
+        // Return verification result
+        Ok(VerificationResult {
+            transaction_id,
+            status,
+            pre_state_root: transaction.pre_state_root,
+            post_state_root: transaction.post_state_root,
+            verification_time_ms: verification_time,
+            error: error_message,
+            metadata: result_metadata,
+        })
+    }
+    
+    /// Begin a transaction block whose statements are verified at its checkpoints
+    ///
+    /// Statements run in the block are added with
+    /// [`add_block_statement`](Self::add_block_statement); they are verified
+    /// at savepoints when `savepoint_checkpoints` is set, and otherwise all at
+    /// [`commit_transaction_block`](Self::commit_transaction_block).
+    pub fn begin_transaction_block(&self, query: &str) -> Result<u64> {
+        if !self.config.enabled {
+            return Ok(0);
+        }
+        
+        self.transaction_manager.lock().unwrap().begin_transaction(query, None)
+    }
+    
+    /// Add a statement's verification transaction to a transaction block
+    pub fn add_block_statement(&self, block_id: u64, transaction_id: u64) -> Result<()> {
+        // Statements that weren't verified have no transaction
+        if transaction_id == 0 {
+            return Ok(());
+        }
+        
+        self.transaction_manager.lock().unwrap().add_statement_id(block_id, transaction_id)
+    }
+    
+    /// Create a savepoint in a transaction block
+    ///
+    /// With `savepoint_checkpoints` set, the statements since the previous
+    /// checkpoint are verified, and their result returned.
+    pub async fn create_savepoint(&self, block_id: u64, savepoint: &str) -> Result<Option<CheckpointResult>> {
+        self.transaction_manager.lock().unwrap().create_savepoint(block_id, savepoint)?;
+        
+        if !self.config.enabled || !self.config.savepoint_checkpoints {
+            return Ok(None);
+        }
+        self.verify_checkpoint(block_id, Some(savepoint)).await.map(Some)
+    }
+    
+    /// Roll a transaction block back to a savepoint, discarding the statements run after it
+    pub fn rollback_to_savepoint(&self, block_id: u64, savepoint: &str) -> Result<()> {
+        let discarded = self.transaction_manager.lock().unwrap().rollback_statements_to_savepoint(block_id, savepoint)?;
+        self.discard_transactions(&discarded);
+        Ok(())
+    }
+    
+    /// Verify the statements of a transaction block since its last checkpoint, once COMMIT completes
+    pub async fn commit_transaction_block(&self, block_id: u64) -> Result<CheckpointResult> {
+        let result = self.verify_checkpoint(block_id, None).await?;
+        self.transaction_manager.lock().unwrap().commit_transaction(block_id)?;
+        Ok(result)
+    }
+    
+    /// Verify the statements of a transaction block since its previous checkpoint
+    async fn verify_checkpoint(&self, block_id: u64, savepoint: Option<&str>) -> Result<CheckpointResult> {
+        let range = self.transaction_manager.lock().unwrap().advance_checkpoint(block_id, savepoint)?;
+        
+        let mut checkpoint = CheckpointResult {
+            from_savepoint: range.from_savepoint,
+            to_savepoint: savepoint.map(str::to_string),
+            results: Vec::with_capacity(range.statement_ids.len()),
+            status: VerificationStatus::Verified,
+        };
+        for transaction_id in range.statement_ids {
+            match self.complete_transaction(transaction_id, None).await {
+                Ok(result) => checkpoint.results.push(result),
+                Err(e) => {
+                    return Err(ProxyError::Verification(format!(
+                        "Verification failed {}: {}", checkpoint.range(), e
+                    )));
+                }
+            }
+        }
+        
+        if checkpoint.results.iter().any(|result| result.status == VerificationStatus::Failed) {
+            checkpoint.status = VerificationStatus::Failed;
+            warn!("Verification of transactions {:?} failed {}", checkpoint.failed_transactions(), checkpoint.range());
+        } else if checkpoint.results.iter().any(|result| result.status == VerificationStatus::Skipped) {
+            checkpoint.status = VerificationStatus::Skipped;
+        }
+        
+        debug!("Verified {} statements {}", checkpoint.results.len(), checkpoint.range());
+        
+        Ok(checkpoint)
+    }
+    
+    /// Drop pending transactions whose statements were rolled back
+    ///
+    /// Their effects never became visible, so there is nothing to verify.
+    fn discard_transactions(&self, transaction_ids: &[u64]) {
+        {
+            let mut pending = self.pending_transactions.lock().unwrap();
+            for transaction_id in transaction_ids {
+                pending.remove(transaction_id);
+            }
+        }
+        let mut records = self.transaction_records.lock().unwrap();
+        records.retain(|record| !transaction_ids.contains(&record.id));
+    }
+    
+    /// Hold the statements of a transaction prepared for two-phase commit
+    ///
+    /// `transaction_ids` are the statements' verification transactions, begun
+    /// but not yet completed. They stay pending until COMMIT PREPARED verifies
+    /// them through [`commit_prepared`](Self::commit_prepared), or ROLLBACK
+    /// PREPARED discards them through [`rollback_prepared`](Self::rollback_prepared).
+    pub fn prepare_transaction(&self, gid: &str, transaction_ids: &[u64]) -> Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        
+        // Statements that weren't verified have no transaction
+        let statement_ids: Vec<u64> = transaction_ids.iter().copied().filter(|id| *id != 0).collect();
+        {
+            let pending = self.pending_transactions.lock().unwrap();
+            if let Some(id) = statement_ids.iter().find(|id| !pending.contains(id)) {
+                return Err(ProxyError::Verification(format!("Transaction {} is not pending", id)));
+            }
+        }
+        
+        let mut tx_manager = self.transaction_manager.lock().unwrap();
+        if tx_manager.get_prepared_transaction(gid).is_some() {
+            return Err(ProxyError::Verification(format!("Transaction identifier \"{}\" is already in use", gid)));
+        }
+        let tx_id = tx_manager.begin_transaction(&format!("PREPARE TRANSACTION '{}'", gid.replace('\'', "''")), None)?;
+        if tx_id > 0 {
+            tx_manager.get_transaction_mut(tx_id)?.statement_ids = statement_ids;
+        }
+        tx_manager.prepare_transaction(tx_id, gid)?;
+        
+        debug!("Holding verification of prepared transaction \"{}\" until it is committed", gid);
+        
+        Ok(())
+    }
+    
+    /// Verify the statements of a prepared transaction once COMMIT PREPARED completes
+    pub async fn commit_prepared(&self, gid: &str) -> Result<Vec<VerificationResult>> {
+        if !self.config.enabled {
+            return Ok(Vec::new());
+        }
+        
+        let transaction = self.transaction_manager.lock().unwrap().commit_prepared(gid)?;
+        
+        debug!("Verifying {} statements of prepared transaction \"{}\"", transaction.statement_ids.len(), gid);
+        
+        let mut results = Vec::with_capacity(transaction.statement_ids.len());
+        for transaction_id in transaction.statement_ids {
+            results.push(self.complete_transaction(transaction_id, None).await?);
+        }
+        
+        Ok(results)
+    }
+    
+    /// Discard the statements of a prepared transaction once ROLLBACK PREPARED completes
+    pub fn rollback_prepared(&self, gid: &str) -> Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        
+        let transaction = self.transaction_manager.lock().unwrap().rollback_prepared(gid)?;
+        self.discard_transactions(&transaction.statement_ids);
+        
+        debug!("Discarded {} statements of prepared transaction \"{}\"", transaction.statement_ids.len(), gid);
+        
+        Ok(())
+    }
+    
+    /// Get pending transactions not held by a prepared transaction, in order
+    pub fn get_unprepared_transactions(&self) -> Vec<u64> {
+        let held: HashSet<u64> = {
+            let tx_manager = self.transaction_manager.lock().unwrap();
+            tx_manager.get_prepared_gids().iter()
+                .filter_map(|gid| tx_manager.get_prepared_transaction(gid))
+                .flat_map(|transaction| transaction.statement_ids.clone())
+                .collect()
+        };
+        
+        let mut transaction_ids: Vec<u64> = self.get_pending_transactions().difference(&held).copied().collect();
+        transaction_ids.sort_unstable();
+        transaction_ids
+    }
+    
+    /// Get the global identifiers of transactions awaiting COMMIT PREPARED or ROLLBACK PREPARED
+    pub fn get_prepared_transactions(&self) -> Vec<String> {
+        self.transaction_manager.lock().unwrap().get_prepared_gids()
+    }
+    
+    /// Persist a transaction's verification status in the background
+    fn save_transaction_status(&self, transaction: TransactionRecord) {
+        let transaction_clone = transaction;
         let db_config = self.db_config.clone();
         tokio::spawn(async move {
             // Retry logic - try up to 3 times with a delay
@@ -747,32 +1557,211 @@ impl VerificationManager {
                     transaction_clone.id, max_retries);
             }
         });
+    }
+    
+    /// Check whether the verification database can be reached for this transaction
+    ///
+    /// Transactions that don't modify any table need no replay, so they never
+    /// depend on the verifier being available.
+    async fn check_verifier_available(&self, metadata: &QueryMetadata) -> bool {
+        if metadata.get_modified_tables().is_empty() {
+            return true;
+        }
         
-        // If verification service is configured, send the transaction for verification
-        if let Some(verification_service) = &self.verification_service {
-            if let Some(pre_state_root) = transaction.pre_state_root {
-                debug!("Sending transaction {} to verification service", transaction_id);
-                if let Err(e) = verification_service.verify_transaction(transaction_id, &transaction.query, &pre_state_root).await {
-                    warn!("Failed to send transaction to verification service: {}", e);
-                    // Don't fail the transaction if the verification service is unavailable
-                    // Just log the error and continue
+        let available = self.verification_env.is_available().await;
+        let was_degraded = self.set_verifier_degraded(!available);
+        
+        if !available && !was_degraded {
+            warn!("Verification database became unavailable (policy: {:?})", 
+                  self.config.verifier_unavailable_policy);
+        } else if available && was_degraded {
+            info!("Verification database is available again");
+        }
+        
+        available
+    }
+    
+    /// Skipped transactions waiting to be re-verified
+    fn skipped_unavailable_records(&self, limit: usize) -> Vec<TransactionRecord> {
+        let records = self.transaction_records.lock().unwrap();
+        records.iter()
+            .filter(|r| r.verification_status == VerificationStatus::Skipped
+                && r.error.as_deref() == Some(VERIFIER_UNAVAILABLE_REASON))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+    
+    /// Resubmit up to `batch_size` transactions skipped while the verifier was
+    /// unavailable to the verification service
+    ///
+    /// The service replays each against its recorded pre-state root; this
+    /// proxy holds only the root, not the state, so it can't replay them
+    /// itself. They stay `Skipped` until the service reports back, and
+    /// without a service they're left as they are. Callers check that the
+    /// verifier is reachable first; see
+    /// [`VerificationManager::spawn_reverification_scheduler`]. Returns the
+    /// number of transactions resubmitted.
+    pub async fn reverify_skipped(&self, batch_size: usize) -> usize {
+        let Some(verification_service) = &self.verification_service else {
+            return 0;
+        };
+        
+        let mut updated = 0;
+        for transaction in self.skipped_unavailable_records(batch_size) {
+            let Some(pre_state_root) = transaction.pre_state_root else {
+                continue;
+            };
+            if let Err(e) = verification_service.verify_transaction(transaction.id, &transaction.query, &pre_state_root).await {
+                warn!("Failed to resubmit transaction {} to the verification service: {}", transaction.id, e);
+                break;
+            }
+            
+            let record = {
+                let mut records = self.transaction_records.lock().unwrap();
+                records.iter_mut().find(|r| r.id == transaction.id).map(|record| {
+                    record.error = Some(RESUBMITTED_REASON.to_string());
+                    record.clone()
+                })
+            };
+            
+            if let Some(record) = record {
+                info!("Resubmitted transaction {} skipped during verifier outage", record.id);
+                self.save_transaction_status(record);
+                self.reverified.fetch_add(1, Ordering::SeqCst);
+                metrics::counter!("verification_reverified_total", 1);
+                updated += 1;
+            }
+        }
+        
+        updated
+    }
+    
+    /// Start the background scheduler re-verifying skipped transactions
+    ///
+    /// Each pass probes the verifier and, once it is healthy, resubmits up
+    /// to `batch_size` skipped transactions; see
+    /// [`reverify_skipped`](Self::reverify_skipped). Returns `None` when
+    /// disabled or without a verification service to resubmit them to.
+    pub fn spawn_reverification_scheduler(manager: Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        let config = manager.config.reverification.clone();
+        if !manager.config.enabled || !config.enabled || manager.verification_service.is_none() {
+            return None;
+        }
+        
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1)));
+            loop {
+                interval.tick().await;
+                
+                let has_skipped = !manager.skipped_unavailable_records(1).is_empty();
+                if !has_skipped || !manager.probe_verifier().await {
+                    continue;
+                }
+                
+                let updated = manager.reverify_skipped(config.batch_size).await;
+                if updated > 0 {
+                    debug!("Re-verification pass updated {} transactions", updated);
                 }
             }
+        }))
+    }
+    
+    /// Check the verification database's free disk space before replaying a transaction
+    ///
+    /// Returns whether replay should be skipped: while the database is low on
+    /// disk, [`LowDiskPolicy::Skip`] skips it, and [`LowDiskPolicy::Throttle`]
+    /// delays it by `throttle_delay_ms` before letting it run.
+    async fn skip_for_low_disk(&self, metadata: &QueryMetadata) -> bool {
+        if metadata.get_modified_tables().is_empty() {
+            return false;
         }
         
-        // Check if we need to commit state
-        self.check_commit_state();
+        let low = self.verification_env.is_low_on_disk().await;
+        let was_low = self.verifier_low_disk.swap(low, Ordering::SeqCst);
+        metrics::gauge!("verification_verifier_low_disk", if low { 1.0 } else { 0.0 });
+        let guard = self.verification_env.disk_guard();
+        if low && !was_low {
+            warn!("Verification database is below {} bytes of free disk space (policy: {:?})",
+                  guard.min_free_bytes, guard.policy);
+        } else if !low && was_low {
+            info!("Verification database has enough free disk space again");
+        }
         
-        // Return verification result
-        Ok(VerificationResult {
-            transaction_id,
-            status,
-            pre_state_root: transaction.pre_state_root,
-            post_state_root: transaction.post_state_root,
-            verification_time_ms: verification_time,
-            error: error_message,
-            metadata: HashMap::new(),
-        })
+        if !low {
+            return false;
+        }
+        match guard.policy {
+            LowDiskPolicy::Skip => true,
+            LowDiskPolicy::Throttle => {
+                tokio::time::sleep(Duration::from_millis(guard.throttle_delay_ms)).await;
+                false
+            }
+        }
+    }
+    
+    /// Set whether the verification database is unreachable, returning the previous value
+    fn set_verifier_degraded(&self, degraded: bool) -> bool {
+        metrics::gauge!("verification_verifier_degraded", if degraded { 1.0 } else { 0.0 });
+        self.verifier_degraded.swap(degraded, Ordering::SeqCst)
+    }
+    
+    /// Set whether the block being built contains skipped transactions, returning the previous value
+    fn set_block_degraded(&self, degraded: bool) -> bool {
+        metrics::gauge!("verification_block_degraded", if degraded { 1.0 } else { 0.0 });
+        self.block_degraded.swap(degraded, Ordering::SeqCst)
+    }
+    
+    /// Probe the verification database, updating the degraded flag
+    async fn probe_verifier(&self) -> bool {
+        let available = self.verification_env.is_available().await;
+        let was_degraded = self.set_verifier_degraded(!available);
+        if available && was_degraded {
+            info!("Verification database is available again");
+        }
+        available
+    }
+    
+    /// Get a snapshot of the verification manager's health
+    pub fn get_status(&self) -> VerificationManagerStatus {
+        VerificationManagerStatus {
+            enabled: self.config.enabled,
+            verifier_unavailable_policy: self.config.verifier_unavailable_policy,
+            verifier_degraded: self.verifier_degraded.load(Ordering::SeqCst),
+            block_degraded: self.block_degraded.load(Ordering::SeqCst),
+            skipped_unavailable: self.skipped_unavailable.load(Ordering::SeqCst),
+            verifier_low_disk: self.verifier_low_disk.load(Ordering::SeqCst),
+            skipped_low_disk: self.skipped_low_disk.load(Ordering::SeqCst),
+            reverified: self.reverified.load(Ordering::SeqCst),
+            dead_lettered_commitments: self.dead_lettered_commitments.load(Ordering::SeqCst),
+            verifier_pool: self.verification_env.pool_status(),
+            pending_transactions: self.pending_transactions.lock().unwrap().len(),
+            block_number: self.current_state.read().unwrap().block_number,
+        }
+    }
+    
+    /// Compare the latest committed block to a fresh full capture of the live database
+    ///
+    /// The manual audit for operators. A divergent table without pending
+    /// changes means the database was modified outside the proxy, or a
+    /// verification bug committed a wrong root.
+    pub async fn reconcile(&self) -> Result<ReconciliationReport> {
+        if !self.config.state_capture.enabled {
+            return Err(ProxyError::Config("State capture is disabled, so there is no committed state to reconcile".to_string()));
+        }
+        
+        let client = self.get_database_client().await?;
+        let report = self.state_capture.reconcile(&client).await?;
+        if report.is_consistent() {
+            info!("Reconciliation: block {} matches the database", report.block_number);
+        } else {
+            warn!(
+                "Reconciliation: block {} diverges from the database in tables {:?}",
+                report.block_number,
+                report.divergent_tables.iter().map(|t| t.table.as_str()).collect::<Vec<_>>()
+            );
+        }
+        Ok(report)
     }
     
     /// Verify a transaction
@@ -807,7 +1796,7 @@ impl VerificationManager {
                 for table in &modified_tables {
                     // Get table state through state capture manager's direct methods
                     let state_capture_ref = self.state_capture.as_ref();
-                    if let Some(table_state) = state_capture_ref.get_table(table) {
+                    if let Some(table_state) = state_capture_ref.get_table_state(table) {
                         debug!("Table state for table {} in INSERT query: {:?}", table, table_state);
                     }
                 }
@@ -822,7 +1811,7 @@ impl VerificationManager {
                 for table in &modified_tables {
                     // Get table state through state capture manager's direct methods
                     let state_capture_ref = self.state_capture.as_ref();
-                    if let Some(table_state) = state_capture_ref.get_table(table) {
+                    if let Some(table_state) = state_capture_ref.get_table_state(table) {
                         debug!("Table state for table {} in UPDATE/DELETE query: {:?}", table, table_state);
                     }
                 }
@@ -837,7 +1826,7 @@ impl VerificationManager {
                 for table in &modified_tables {
                     // Get table state through state capture manager's direct methods
                     let state_capture_ref = self.state_capture.as_ref();
-                    if let Some(table_state) = state_capture_ref.get_table(table) {
+                    if let Some(table_state) = state_capture_ref.get_table_state(table) {
                         debug!("Table state for table {} in UPDATE/DELETE query: {:?}", table, table_state);
                     }
                 }
@@ -866,8 +1855,13 @@ impl VerificationManager {
             return false;
         }
         
-        // Always verify data-modifying queries (DML)
-        if metadata.query_type.is_dml() {
+        // System catalog reads aren't part of the verified state
+        if metadata.metadata_query {
+            return false;
+        }
+        
+        // Always verify data-modifying queries (DML), including SELECTs writing large objects
+        if metadata.query_type.is_dml() || metadata.writes_large_objects() {
             return true;
         }
         
@@ -876,8 +1870,8 @@ impl VerificationManager {
             return true;
         }
         
-        // Verify read-only if configured
-        if metadata.query_type == QueryType::Select && self.config.verify_readonly {
+        // Verify read-only if configured, including rows fetched from cursors
+        if matches!(metadata.query_type, QueryType::Select | QueryType::Fetch(_)) && self.config.verify_readonly {
             return true;
         }
         
@@ -983,23 +1977,26 @@ impl VerificationManager {
         debug!("Committing state with root {:?} and block number {}", 
                hex::encode(state_root), block_number);
         
-        // Commit to EigenLayer if contract is available
-        // The contract is an Arc<ContractManager>, not an Option
-        match self.contract.commit_state(state_root).await {
-            Ok(_) => {
-                info!("Successfully committed state to EigenLayer: block={}, root=0x{}", 
-                      block_number, hex::encode(state_root));
-            }
-            Err(e) => {
-                error!("Failed to commit state to EigenLayer: {}", e);
-                return Err(ProxyError::Verification(format!("Failed to commit state: {}", e)));
+        // Without the retry queue, commit to EigenLayer first and fail the
+        // block on error; otherwise the commitment is queued once the block
+        // is recorded
+        if !self.config.commitment_retry.enabled {
+            match self.contract.commit_state(state_root).await {
+                Ok(_) => {
+                    info!("Successfully committed state to EigenLayer: block={}, root=0x{}", 
+                          block_number, hex::encode(state_root));
+                }
+                Err(e) => {
+                    error!("Failed to commit state to EigenLayer: {}", e);
+                    return Err(ProxyError::Verification(format!("Failed to commit state: {}", e)));
+                }
             }
         }
         
         // Insert the block into the database
         // Connect to the database
         let db_config = self.db_config.clone();
-        match tokio_postgres::connect(&db_config, NoTls).await {
+        let client = match tokio_postgres::connect(&db_config, NoTls).await {
             Ok((client, connection)) => {
                 // Spawn the connection
                 tokio::spawn(async move {
@@ -1028,8 +2025,16 @@ impl VerificationManager {
                     .unwrap_or_default()
                     .as_secs() as i64;
                 
-                // Create empty metadata
-                let metadata = serde_json::Value::Object(serde_json::Map::new());
+                // Flag blocks containing transactions that skipped replay
+                let mut metadata = serde_json::Map::new();
+                if self.block_degraded.load(Ordering::SeqCst) {
+                    metadata.insert("degraded".to_string(), serde_json::Value::Bool(true));
+                    metadata.insert(
+                        "skip_reason".to_string(),
+                        serde_json::Value::String(VERIFIER_UNAVAILABLE_REASON.to_string()),
+                    );
+                }
+                let metadata = serde_json::Value::Object(metadata);
                 
                 match client.execute(
                     query,
@@ -1049,12 +2054,14 @@ impl VerificationManager {
                         return Err(ProxyError::Verification(format!("Failed to insert block: {}", e)));
                     }
                 }
+                
+                client
             }
             Err(e) => {
                 error!("Failed to connect to database: {}", e);
                 return Err(ProxyError::Verification(format!("Failed to connect to database: {}", e)));
             }
-        }
+        };
         
         // Reset transaction counter to start from 1 for the new block
         {
@@ -1068,6 +2075,21 @@ impl VerificationManager {
             pending.clear();
         }
         
+        // The next block starts clean
+        let degraded = self.set_block_degraded(false);
+        
+        self.events.publish(VerificationEvent::BlockCommitted {
+            block_number,
+            state_root: hex_root(&state_root),
+            transaction_count: self.transaction_records.lock().unwrap().len() as u64,
+            degraded,
+        });
+        
+        // The block is recorded but not finalized until its commitment confirms
+        if self.config.commitment_retry.enabled {
+            self.submit_commitment(&client, block_number, state_root).await?;
+        }
+        
         Ok(())
     }
     
@@ -1088,6 +2110,18 @@ impl VerificationManager {
         None
     }
     
+    /// Get the statement to replay for a transaction, with its bound parameters
+    pub fn get_replay_statement(&self, transaction_id: u64) -> Option<ReplayStatement> {
+        self.get_transaction(transaction_id)
+            .map(|record| {
+                let statement = ReplayStatement::new(record.query, record.params);
+                match record.copy_data {
+                    Some(data) => statement.with_copy_data(data),
+                    None => statement,
+                }
+            })
+    }
+    
     /// Get all transaction records
     pub fn get_transactions(&self) -> Vec<TransactionRecord> {
         let records = self.transaction_records.lock().unwrap();
@@ -1173,6 +2207,16 @@ impl VerificationManager {
         }
     }
     
+    /// Get the committed roots of the given tables
+    ///
+    /// Returns `None` unless every table has a committed root.
+    pub fn get_table_roots(&self, tables: &[String]) -> Option<BTreeMap<String, [u8; 32]>> {
+        let block = self.state_capture.get_latest_committed_block_state().ok()??;
+        tables.iter()
+            .map(|table| block.table_state_roots.get(table).map(|root| (table.clone(), *root)))
+            .collect()
+    }
+    
     /// Get the state capture manager
     pub fn get_state_capture_manager(&self) -> Arc<StateCaptureManager> {
         self.state_capture.clone()
@@ -1207,18 +2251,32 @@ impl VerificationManager {
             .ok_or_else(|| ProxyError::Verification(format!("Missing post-state root for transaction {}", transaction_id)))?;
         
         // Submit the challenge to EigenLayer
-        self.contract.submit_challenge(
+        let challenge = self.contract.submit_challenge(
             transaction_id,
             pre_state_root,
             post_state_root,
             proof,
-        ).await
+        ).await?;
+        
+        self.events.publish(VerificationEvent::challenge_submitted(&challenge));
+        Ok(challenge)
     }
     
     /// Handle a verification challenge from EigenLayer
     pub async fn handle_challenge(&self, challenge_id: &str) -> Result<Challenge> {
         // Handle the challenge with the EigenLayer manager
-        self.contract.handle_challenge(challenge_id).await
+        let challenge = self.contract.handle_challenge(challenge_id).await?;
+        
+        if matches!(challenge.status, ChallengeStatus::Accepted | ChallengeStatus::Rejected | ChallengeStatus::Expired) {
+            self.events.publish(VerificationEvent::challenge_resolved(&challenge));
+        }
+        Ok(challenge)
+    }
+    
+    /// Replace the event publisher, e.g. to attach a custom sink
+    pub fn with_event_publisher(mut self, events: EventPublisher) -> Self {
+        self.events = events;
+        self
     }
     
     /// Get all state commitments
@@ -1292,9 +2350,6 @@ trait TableStateUpdater {
     fn get_table_root(&self, table_name: &str) -> Option<[u8; 32]>;
     
     /// Get a table state
-    fn get_table(&self, table_name: &str) -> Option<&TableState>;
-    
-    /// Get a table state (alternative method)
     fn get_table_state(&self, table_name: &str) -> Option<TableState>;
 }
 
@@ -1307,25 +2362,17 @@ impl TableStateUpdater for StateCaptureManager {
 
     fn get_root_hash(&self) -> Option<[u8; 32]> {
         // Return current state root
-        self.get_current_root_hash()
+        self.get_current_root_hash().ok().flatten()
     }
 
     fn get_table_root(&self, table_name: &str) -> Option<[u8; 32]> {
-        // For now, we'll return None since we don't have direct access to table roots
-        // In a real implementation, this would need to be implemented properly
-        None
-    }
-    
-    fn get_table(&self, table_name: &str) -> Option<&TableState> {
-        // For now, we'll return None since we don't have direct access to table states
-        // In a real implementation, this would need to be implemented properly
-        None
+        // Root recorded for the table in the latest committed block
+        self.get_current_table_root(table_name).ok().flatten()
     }
     
     fn get_table_state(&self, table_name: &str) -> Option<TableState> {
-        // For now, we'll return None since we don't have direct access to table states
-        // In a real implementation, this would need to be implemented properly
-        None
+        // Clone of the live state, which always matches the latest committed block
+        self.get_latest_committed_table_state(table_name).ok().flatten()
     }
 }
 
@@ -1350,6 +2397,7 @@ mod tests {
             complexity_score: 1,
             special_handling: false,
             verifiable: true,
+            metadata_query: false,
             cacheable: true,
             extra: HashMap::new(),
             non_deterministic_reason: None,
@@ -1451,6 +2499,282 @@ mod tests {
         assert!(!commitments.is_empty());
     }
     
+    #[tokio::test]
+    async fn test_degrade_to_commit_only_when_verifier_down() {
+        let mut config = VerificationConfig::default();
+        config.enabled = true;
+        config.enforce = true;
+        config.verifier_unavailable_policy = VerifierUnavailablePolicy::DegradeToCommitOnly;
+        // Nothing listens on this port, so the verification database is unreachable
+        config.environment.connection_string = "host=127.0.0.1 port=1 user=verifier dbname=verification_db".to_string();
+        config.environment.connection_timeout = 1;
+        
+        let manager = VerificationManager::new(config).await.unwrap();
+        
+        let query = "INSERT INTO users VALUES (1, 'test')";
+        let metadata = create_test_metadata(query, QueryType::Insert, vec!["users"]);
+        
+        // The write succeeds even under enforcement, but is marked as skipped
+        let tx_id = manager.begin_transaction(query, &metadata).unwrap();
+        let result = manager.complete_transaction(tx_id, Some(1)).await.unwrap();
+        assert_eq!(result.status, VerificationStatus::Skipped);
+        assert_eq!(result.error.as_deref(), Some(VERIFIER_UNAVAILABLE_REASON));
+        assert_eq!(manager.get_transaction_status(tx_id), Some(VerificationStatus::Skipped));
+        
+        let status = manager.get_status();
+        assert!(status.verifier_degraded);
+        assert!(status.block_degraded);
+        assert_eq!(status.skipped_unavailable, 1);
+    }
+    
+    #[tokio::test]
+    async fn test_low_disk_skips_replay() {
+        let mut config = VerificationConfig::default();
+        config.enabled = true;
+        config.enforce = true;
+        config.verifier_unavailable_policy = VerifierUnavailablePolicy::DegradeToCommitOnly;
+        config.environment.connection_string = "host=127.0.0.1 port=1 user=verifier dbname=verification_db".to_string();
+        config.environment.connection_timeout = 1;
+        config.environment.disk_guard.enabled = true;
+        config.environment.disk_guard.capacity_bytes = 1 << 30;
+        config.environment.disk_guard.min_free_bytes = 1 << 20;
+        config.environment.disk_guard.check_interval_secs = 3600;
+        
+        let manager = VerificationManager::new(config).await.unwrap();
+        // Simulate a verification database that has all but filled its disk
+        manager.get_verification_environment().record_database_size((1 << 30) - 1024);
+        
+        let query = "INSERT INTO users VALUES (1, 'test')";
+        let metadata = create_test_metadata(query, QueryType::Insert, vec!["users"]);
+        
+        // The write succeeds even under enforcement, but its replay is skipped
+        let tx_id = manager.begin_transaction(query, &metadata).unwrap();
+        let result = manager.complete_transaction(tx_id, Some(1)).await.unwrap();
+        assert_eq!(result.status, VerificationStatus::Skipped);
+        assert_eq!(result.error.as_deref(), Some(LOW_DISK_REASON));
+        assert_eq!(result.metadata.get("skip_reason").map(String::as_str), Some(LOW_DISK_REASON));
+        
+        let status = manager.get_status();
+        assert!(status.verifier_low_disk);
+        assert!(status.block_degraded);
+        assert_eq!(status.skipped_low_disk, 1);
+        assert_eq!(status.skipped_unavailable, 0);
+    }
+    
+    #[tokio::test]
+    async fn test_skipped_transaction_not_vouched_for_after_recovery() {
+        let mut config = VerificationConfig::default();
+        config.enabled = true;
+        config.verifier_unavailable_policy = VerifierUnavailablePolicy::DegradeToCommitOnly;
+        // Nothing listens on this port, so the verification database is unreachable
+        config.environment.connection_string = "host=127.0.0.1 port=1 user=verifier dbname=verification_db".to_string();
+        config.environment.connection_timeout = 1;
+        
+        let manager = VerificationManager::new(config).await.unwrap();
+        
+        let query = "INSERT INTO users VALUES (1, 'test')";
+        let metadata = create_test_metadata(query, QueryType::Insert, vec!["users"]);
+        let tx_id = manager.begin_transaction(query, &metadata).unwrap();
+        manager.complete_transaction(tx_id, Some(1)).await.unwrap();
+        assert_eq!(manager.get_transaction_status(tx_id), Some(VerificationStatus::Skipped));
+        
+        // Without a verification service to replay it, a pass run on
+        // recovery leaves the transaction skipped rather than vouching for it
+        assert_eq!(manager.reverify_skipped(10).await, 0);
+        assert_eq!(manager.get_transaction_status(tx_id), Some(VerificationStatus::Skipped));
+        assert_eq!(manager.get_transaction(tx_id).unwrap().error.as_deref(), Some(VERIFIER_UNAVAILABLE_REASON));
+        assert_eq!(manager.get_status().reverified, 0);
+        assert!(VerificationManager::spawn_reverification_scheduler(Arc::new(manager)).is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_prepared_statement_replays_bound_values() {
+        let mut config = VerificationConfig::default();
+        config.enabled = true;
+        
+        let manager = VerificationManager::new(config).await.unwrap();
+        
+        let query = "INSERT INTO t VALUES ($1)";
+        let metadata = create_test_metadata(query, QueryType::Insert, vec!["t"]);
+        
+        // The same prepared statement executed twice with different values
+        let first = manager.begin_transaction_with_params(query, vec![Value::Integer(1)], &metadata).unwrap();
+        let second = manager.begin_transaction_with_params(query, vec![Value::Integer(2)], &metadata).unwrap();
+        
+        assert_eq!(
+            manager.get_replay_statement(first),
+            Some(ReplayStatement::new(query, vec![Value::Integer(1)]))
+        );
+        assert_eq!(
+            manager.get_replay_statement(second),
+            Some(ReplayStatement::new(query, vec![Value::Integer(2)]))
+        );
+    }
+    
+    #[tokio::test]
+    async fn test_bind_values_recorded_for_replay() {
+        use crate::protocol::extended::{oids, ExtendedQueryState};
+        
+        let mut config = VerificationConfig::default();
+        config.enabled = true;
+        let manager = VerificationManager::new(config).await.unwrap();
+        
+        // A client binding an integer and a uuid in binary, and text in text format
+        let query = "INSERT INTO users (id, account, name) VALUES ($1, $2, $3)";
+        let account = Uuid::new_v4();
+        let mut extended = ExtendedQueryState::new();
+        extended.parse("ins", query, &[oids::INT8, oids::UUID, oids::TEXT]);
+        extended.bind("", "ins", &[1, 1, 0], &[
+            Some(Bytes::copy_from_slice(&42i64.to_be_bytes())),
+            Some(Bytes::copy_from_slice(account.as_bytes())),
+            Some(Bytes::from("O'Brien")),
+        ]).unwrap();
+        let statement = extended.execute("").unwrap();
+        
+        let metadata = create_test_metadata(query, QueryType::Insert, vec!["users"]);
+        let tx_id = manager.begin_transaction_with_params(&statement.query, statement.params, &metadata).unwrap();
+        
+        let replay = manager.get_replay_statement(tx_id).unwrap();
+        assert_eq!(replay.query, query);
+        assert_eq!(replay.params, vec![
+            Value::BigInt(42),
+            Value::Uuid(account),
+            Value::Text("O'Brien".to_string()),
+        ]);
+    }
+    
+    #[tokio::test]
+    async fn test_two_phase_commit_verified_at_commit_prepared() {
+        let mut config = VerificationConfig::default();
+        config.enabled = true;
+        config.verify_all = true;
+        
+        let manager = VerificationManager::new(config).await.unwrap();
+        
+        // Statements run inside the transaction before PREPARE TRANSACTION
+        let insert = "INSERT INTO accounts VALUES (1, 100)";
+        let update = "UPDATE accounts SET balance = balance - 10 WHERE id = 1";
+        let first = manager.begin_transaction(insert, &create_test_metadata(insert, QueryType::Insert, vec!["accounts"])).unwrap();
+        let second = manager.begin_transaction(update, &create_test_metadata(update, QueryType::Update, vec!["accounts"])).unwrap();
+        
+        assert_eq!(manager.get_unprepared_transactions(), vec![first, second]);
+        manager.prepare_transaction("transfer-1", &[first, second]).unwrap();
+        assert_eq!(manager.get_prepared_transactions(), vec!["transfer-1".to_string()]);
+        assert!(manager.get_unprepared_transactions().is_empty());
+        
+        // Nothing is verified across the prepare/commit gap
+        for tx_id in [first, second] {
+            assert_eq!(manager.get_transaction_status(tx_id), Some(VerificationStatus::NotVerified));
+            assert!(manager.get_pending_transactions().contains(&tx_id));
+        }
+        
+        // COMMIT PREPARED verifies the prepared statements' effects
+        let results = manager.commit_prepared("transfer-1").await.unwrap();
+        assert_eq!(results.iter().map(|r| r.transaction_id).collect::<Vec<_>>(), vec![first, second]);
+        for result in &results {
+            assert_eq!(result.status, VerificationStatus::Verified);
+            assert_eq!(manager.get_transaction_status(result.transaction_id), Some(VerificationStatus::Verified));
+        }
+        assert!(manager.get_pending_transactions().is_empty());
+        assert!(manager.get_prepared_transactions().is_empty());
+        assert!(manager.commit_prepared("transfer-1").await.is_err());
+        
+        // ROLLBACK PREPARED discards them without verification
+        let delete = "DELETE FROM accounts WHERE id = 1";
+        let third = manager.begin_transaction(delete, &create_test_metadata(delete, QueryType::Delete, vec!["accounts"])).unwrap();
+        manager.prepare_transaction("transfer-2", &[third]).unwrap();
+        manager.rollback_prepared("transfer-2").unwrap();
+        assert!(manager.get_transaction(third).is_none());
+        assert!(manager.get_pending_transactions().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_savepoint_checkpoint_localizes_failure() {
+        let mut config = VerificationConfig::default();
+        config.enabled = true;
+        config.verify_readonly = true;
+        config.savepoint_checkpoints = true;
+        // Nothing listens on this port, so verifying a write fails
+        config.environment.connection_string = "host=127.0.0.1 port=1 user=verifier dbname=verification_db".to_string();
+        config.environment.connection_timeout = 1;
+        
+        let manager = VerificationManager::new(config).await.unwrap();
+        let block = manager.begin_transaction_block("BEGIN").unwrap();
+        
+        // A statement modifying no table verifies without the verifier
+        let read = "SELECT 1";
+        let first = manager.begin_transaction(read, &create_test_metadata(read, QueryType::Select, vec![])).unwrap();
+        manager.add_block_statement(block, first).unwrap();
+        let checkpoint = manager.create_savepoint(block, "sp1").await.unwrap().unwrap();
+        assert_eq!(checkpoint.status, VerificationStatus::Verified);
+        assert_eq!(checkpoint.range(), "from BEGIN to savepoint sp1");
+        assert_eq!(checkpoint.results.iter().map(|r| r.transaction_id).collect::<Vec<_>>(), vec![first]);
+        
+        // A write after the savepoint fails, and the failure is localized to its range
+        let write = "INSERT INTO accounts VALUES (1, 100)";
+        let second = manager.begin_transaction(write, &create_test_metadata(write, QueryType::Insert, vec!["accounts"])).unwrap();
+        manager.add_block_statement(block, second).unwrap();
+        let checkpoint = manager.create_savepoint(block, "sp2").await.unwrap().unwrap();
+        assert_eq!(checkpoint.status, VerificationStatus::Failed);
+        assert_eq!(checkpoint.range(), "from savepoint sp1 to savepoint sp2");
+        assert_eq!(checkpoint.failed_transactions(), vec![second]);
+        assert_eq!(manager.get_transaction_status(first), Some(VerificationStatus::Verified));
+        
+        // COMMIT only verifies the statements since the last checkpoint
+        let third = manager.begin_transaction(read, &create_test_metadata(read, QueryType::Select, vec![])).unwrap();
+        manager.add_block_statement(block, third).unwrap();
+        let checkpoint = manager.commit_transaction_block(block).await.unwrap();
+        assert_eq!(checkpoint.status, VerificationStatus::Verified);
+        assert_eq!(checkpoint.range(), "from savepoint sp2 to COMMIT");
+        assert_eq!(checkpoint.results.iter().map(|r| r.transaction_id).collect::<Vec<_>>(), vec![third]);
+        assert!(manager.get_pending_transactions().is_empty());
+    }
+    
+    #[test]
+    fn test_database_pool_size_configurable() {
+        let db_config = "host=localhost port=5432 user=verifiable password=verifiable dbname=verifiable_db";
+        assert_eq!(database_pool(db_config, 0).unwrap().status().max_size, DEFAULT_DATABASE_POOL_SIZE);
+        assert_eq!(database_pool(db_config, 2).unwrap().status().max_size, 2);
+        assert!(matches!(database_pool("port=not-a-number", 2), Err(ProxyError::Config(_))));
+    }
+    
+    #[tokio::test]
+    #[ignore] // Needs a PostgreSQL server, configured through the PG_* variables
+    async fn test_concurrent_captures_share_small_pool() {
+        let mut config = VerificationConfig::default();
+        config.state_capture.pool_size = 2;
+        let manager = Arc::new(VerificationManager::new(config).await.unwrap());
+        
+        let client = manager.get_database_client().await.unwrap();
+        client.batch_execute(
+            "DROP TABLE IF EXISTS pooled_capture; \
+             CREATE TABLE pooled_capture (id integer PRIMARY KEY, data text); \
+             INSERT INTO pooled_capture SELECT i, md5(i::text) FROM generate_series(1, 1000) i;"
+        ).await.unwrap();
+        drop(client);
+        
+        // Four times more captures than connections, each holding a client for the whole capture
+        let captures: Vec<_> = (0..8).map(|_| {
+            let manager = manager.clone();
+            tokio::spawn(async move {
+                let client = manager.get_database_client().await?;
+                let context = manager.state_capture.begin_capture_context(&client).await?;
+                let captured = manager.state_capture.capture_table(&client, "pooled_capture").await;
+                StateCaptureManager::end_capture_context(&client).await?;
+                assert!(!context.row_security);
+                captured
+            })
+        }).collect();
+        let roots = tokio::time::timeout(Duration::from_secs(30), futures_util::future::join_all(captures))
+            .await
+            .expect("captures deadlocked on the pool");
+        let roots: Vec<_> = roots.into_iter().map(|r| r.unwrap().unwrap().root_hash).collect();
+        assert!(roots.iter().all(|root| root.is_some() && *root == roots[0]));
+        
+        let client = manager.get_database_client().await.unwrap();
+        client.batch_execute("DROP TABLE pooled_capture").await.unwrap();
+    }
+    
     #[tokio::test]
     async fn test_table_proof() {
         // Create a configuration for testing with verification enabled
@@ -1476,4 +2800,57 @@ mod tests {
         
         // Verify the proof
     }
-} 
\ No newline at end of file
+    
+    #[test]
+    fn test_table_root_tracks_row_changes() {
+        let columns = vec![
+            ColumnDefinition {
+                name: "id".to_string(),
+                column_type: ColumnType::Integer,
+                nullable: false,
+                primary_key: true,
+                unique: true,
+                default_value: None,
+            },
+            ColumnDefinition {
+                name: "name".to_string(),
+                column_type: ColumnType::Text,
+                nullable: true,
+                primary_key: false,
+                unique: false,
+                default_value: None,
+            },
+        ];
+        let schema = TableSchema::new("users".to_string(), columns, vec!["id".to_string()], vec![], vec![]);
+        let row = |id: i32, name: &str| {
+            let mut values = HashMap::new();
+            values.insert("id".to_string(), Value::Integer(id));
+            values.insert("name".to_string(), Value::Text(name.to_string()));
+            Row::new(id.to_string(), "users".to_string(), values)
+        };
+        let mut users = TableState::new(schema);
+        users.insert_row(row(1, "alice"));
+        users.insert_row(row(2, "bob"));
+        
+        let state_capture = StateCaptureManager::new();
+        assert_eq!(state_capture.get_table_root("users"), None);
+        assert!(state_capture.get_table_state("users").is_none());
+        state_capture.initialize_genesis(HashMap::from([("users".to_string(), users)])).unwrap();
+        
+        let captured_root = state_capture.get_table_root("users").unwrap();
+        assert_ne!(captured_root, [0u8; 32]);
+        assert_eq!(state_capture.get_table_state("users").unwrap().root_hash, Some(captured_root));
+        assert_eq!(state_capture.get_table_root("orders"), None);
+        
+        // Changing a single row changes the table root along with the overall root
+        let state_root = state_capture.get_root_hash().unwrap();
+        state_capture.begin_wal_transaction(None).unwrap();
+        state_capture.apply_wal_update("users".to_string(), "2".to_string(), row(2, "carol")).unwrap();
+        state_capture.commit_wal_transaction(1).unwrap();
+        
+        let updated_root = state_capture.get_table_root("users").unwrap();
+        assert_ne!(updated_root, captured_root);
+        assert_ne!(state_capture.get_root_hash().unwrap(), state_root);
+        assert_eq!(state_capture.get_table_state("users").unwrap().root_hash, Some(updated_root));
+    }
+}
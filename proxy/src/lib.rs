@@ -20,11 +20,11 @@ pub use interception::{QueryMetadata, QueryType};
 
 // Security features
 pub mod security;
-pub use security::{RateLimiter, RateLimiterConfig};
+pub use security::{RateLimitAlgorithm, RateLimiter, RateLimiterConfig};
 
 // Server implementation
 pub mod server;
-pub use server::ProxyServer;
+pub use server::{ProxyServer, ShutdownSummary};
 
 // Verification engine
 pub mod verification;
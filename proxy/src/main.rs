@@ -1,9 +1,10 @@
 use anyhow::Result;
 use clap::Parser;
-use log::{info, error};
+use log::{info, error, warn};
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
 use verifiable_db_proxy::server::ProxyServer;
-use verifiable_db_proxy::config::ProxyConfig;
+use verifiable_db_proxy::config::{ProxyConfig, TlsConfig};
 use tokio::signal::unix::{signal, SignalKind};
 
 #[derive(Parser, Debug)]
@@ -48,6 +49,14 @@ struct Args {
     /// Rate limit
     #[arg(short = 'r', long)]
     rate_limit: Option<u32>,
+
+    /// TLS certificate file (PEM), enables TLS for clients
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// TLS private key file (PEM)
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -151,6 +160,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Set a high default rate limit (1000 requests per minute) to avoid blocking during development
         config.rate_limiter_config.rate_limit = 1000;
     }
+    
+    if let (Some(cert_file), Some(key_file)) = (args.tls_cert, args.tls_key) {
+        // Terminate client TLS at the proxy
+        config.tls_config = Some(TlsConfig {
+            cert_file,
+            key_file,
+            require_client_certs: false,
+            ca_file: None,
+        });
+    }
 
     // Create proxy server
     let proxy = ProxyServer::new(config)?;
@@ -172,12 +191,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         _ = sigint.recv() => info!("Received SIGINT"),
     }
 
-    // Clean shutdown
+    // Clean shutdown: open transactions get to finish before their sessions close
     info!("Shutting down proxy server");
-    proxy.stop().await?;  // Ensure we properly shut down the server
-    
-    // Give background tasks time to exit
-    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    let summary = proxy.stop().await?;
+    if !summary.forcibly_closed.is_empty() {
+        warn!(
+            "Closed {} sessions with transactions still open after {}s: {:?}",
+            summary.forcibly_closed.len(),
+            proxy.config().shutdown_timeout,
+            summary.forcibly_closed
+        );
+    }
     
     info!("Proxy server shutdown complete");
     Ok(())
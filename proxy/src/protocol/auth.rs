@@ -2,11 +2,34 @@
 //! 
 //! This module provides authentication functionality for the PostgreSQL wire protocol,
 //! including MD5 password authentication, SCRAM-SHA-256, and other methods.
+//!
+//! # Passthrough trust model
+//!
+//! GSSAPI/Kerberos and client-certificate authentication are not verified by
+//! the proxy's own user table:
+//!
+//! - With [`AuthMethod::GssPassthrough`] the proxy relays the GSSAPI exchange
+//!   between the client and the backend unchanged. It never inspects or
+//!   validates tokens; the backend's `pg_hba.conf` and keytab are the sole
+//!   authority, and the client is authenticated exactly when the backend
+//!   answers with `AuthenticationOk`. The exchange runs on a dedicated backend
+//!   connection, so the proxy needs no Kerberos credentials of its own.
+//! - With [`AuthMethod::Certificate`] the TLS layer is trusted to have verified
+//!   the client certificate chain against the configured CA. The proxy only
+//!   maps the certificate's common name to a database user, either through
+//!   [`AuthConfig::cert_user_map`] or, like PostgreSQL's `cert` method, by
+//!   using the CN as the user name.
+//!
+//! In both cases queries still run over the proxy's own backend session, which
+//! switches to the authenticated user with `SET ROLE` so that the backend's
+//! privileges for that user apply.
 
 use crate::error::{ProxyError, Result};
 use crate::protocol::message::{AuthenticationRequest, BackendMessage, FrontendMessage};
 use crate::config::ProxyConfig;
-use bytes::{Bytes, BytesMut, Buf, BufMut};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bytes::{Bytes, BytesMut, BufMut};
 use log::{debug, error, info, warn};
 use rand::{Rng, thread_rng};
 use hmac::{Hmac, Mac};
@@ -22,6 +45,19 @@ use md5;
 use hex;
 use tokio::runtime::Runtime;
 use std::sync::atomic::{AtomicU8, Ordering};
+use verifiable_db_core::crypto::verify_bytes;
+
+/// SASL mechanism name for SCRAM-SHA-256
+const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+
+/// PBKDF2 iterations for new SCRAM verifiers, matching PostgreSQL's default
+const SCRAM_DEFAULT_ITERATIONS: u32 = 4096;
+
+/// Length of random SCRAM salts in bytes
+const SCRAM_SALT_LENGTH: usize = 16;
+
+/// Length of random SCRAM server nonces in bytes, before base64 encoding
+const SCRAM_NONCE_LENGTH: usize = 18;
 
 /// Authentication handler for PostgreSQL wire protocol
 #[derive(Debug, Clone)]
@@ -40,6 +76,15 @@ pub struct AuthHandler {
     
     /// SASL state for SCRAM-SHA-256 authentication
     sasl_state: Option<SaslState>,
+    
+    /// User established by passthrough or certificate authentication
+    authenticated_user: Option<String>,
+    
+    /// User named in the client's startup message, for SCRAM authentication
+    startup_user: Option<String>,
+    
+    /// Common name of the client's verified TLS certificate, for certificate authentication
+    client_certificate_name: Option<String>,
 }
 
 /// Authentication state
@@ -79,6 +124,12 @@ pub enum AuthMethod {
     
     /// Cleartext password authentication
     CleartextPassword,
+    
+    /// GSSAPI/Kerberos negotiation relayed to the backend
+    GssPassthrough,
+    
+    /// TLS client certificate, mapped from its common name to a user
+    Certificate,
 }
 
 impl fmt::Display for AuthMethod {
@@ -88,6 +139,8 @@ impl fmt::Display for AuthMethod {
             AuthMethod::Md5Password => write!(f, "md5"),
             AuthMethod::ScramSha256 => write!(f, "scram-sha-256"),
             AuthMethod::CleartextPassword => write!(f, "password"),
+            AuthMethod::GssPassthrough => write!(f, "gss"),
+            AuthMethod::Certificate => write!(f, "cert"),
         }
     }
 }
@@ -99,6 +152,8 @@ impl From<&str> for AuthMethod {
             "md5" => AuthMethod::Md5Password,
             "scram-sha-256" => AuthMethod::ScramSha256,
             "password" => AuthMethod::CleartextPassword,
+            "gss" => AuthMethod::GssPassthrough,
+            "cert" => AuthMethod::Certificate,
             _ => {
                 warn!("Unknown authentication method: {}, defaulting to MD5", s);
                 AuthMethod::Md5Password
@@ -121,6 +176,24 @@ pub struct AuthConfig {
     
     /// Require client SSL
     pub require_ssl: bool,
+    
+    /// Certificate common names mapped to database users
+    ///
+    /// Names without an entry are used as the user name directly.
+    pub cert_user_map: HashMap<String, String>,
+    
+    /// Salted SCRAM-SHA-256 verifiers by user name
+    ///
+    /// SCRAM authentication only consults these, never `users`, so the
+    /// proxy does not need to hold cleartext passwords for it.
+    pub scram_verifiers: HashMap<String, ScramVerifier>,
+}
+
+impl AuthConfig {
+    /// Add a SCRAM-SHA-256 user, keeping only a salted verifier of the password
+    pub fn add_scram_user(&mut self, user: &str, password: &str) {
+        self.scram_verifiers.insert(user.to_string(), ScramVerifier::generate(password));
+    }
 }
 
 impl Default for AuthConfig {
@@ -136,33 +209,156 @@ impl Default for AuthConfig {
             default_method: AuthMethod::Md5Password,
             users,
             require_ssl: false,
+            cert_user_map: HashMap::new(),
+            scram_verifiers: HashMap::new(),
         }
     }
 }
 
+/// Salted SCRAM-SHA-256 credentials for one user
+///
+/// Only the salt, iteration count and derived keys are kept. The text form
+/// (`SCRAM-SHA-256$<iterations>:<salt>$<StoredKey>:<ServerKey>`) is the one
+/// PostgreSQL stores in `pg_authid.rolpassword`, so verifiers can be copied
+/// from the backend. Passwords are used as given, without SASLprep.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ScramVerifier {
+    /// Salt for the PBKDF2 key derivation
+    salt: Vec<u8>,
+    
+    /// PBKDF2 iteration count
+    iterations: u32,
+    
+    /// `H(ClientKey)`, against which client proofs are checked
+    stored_key: [u8; 32],
+    
+    /// Key for the server signature sent in the final message
+    server_key: [u8; 32],
+}
+
+impl ScramVerifier {
+    /// Derive a verifier from a password, salt and iteration count
+    pub fn new(password: &str, salt: &[u8], iterations: u32) -> Self {
+        let salted_password = scram_hi(password.as_bytes(), salt, iterations);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        
+        Self {
+            salt: salt.to_vec(),
+            iterations,
+            stored_key: Sha256::digest(client_key).into(),
+            server_key: hmac_sha256(&salted_password, b"Server Key"),
+        }
+    }
+    
+    /// Derive a verifier with a random salt and the default iteration count
+    pub fn generate(password: &str) -> Self {
+        let mut salt = [0u8; SCRAM_SALT_LENGTH];
+        thread_rng().fill(&mut salt);
+        Self::new(password, &salt, SCRAM_DEFAULT_ITERATIONS)
+    }
+    
+    /// Parse a verifier in PostgreSQL's text form
+    pub fn parse(verifier: &str) -> Result<Self> {
+        let invalid = || ProxyError::Config("Invalid SCRAM-SHA-256 verifier".to_string());
+        
+        let rest = verifier.strip_prefix("SCRAM-SHA-256$").ok_or_else(invalid)?;
+        let (params, keys) = rest.split_once('$').ok_or_else(invalid)?;
+        let (iterations, salt) = params.split_once(':').ok_or_else(invalid)?;
+        let (stored_key, server_key) = keys.split_once(':').ok_or_else(invalid)?;
+        
+        let iterations = iterations.parse::<u32>().ok().filter(|i| *i > 0).ok_or_else(invalid)?;
+        let salt = BASE64.decode(salt).map_err(|_| invalid())?;
+        let decode_key = |key: &str| -> Result<[u8; 32]> {
+            BASE64.decode(key).ok().and_then(|key| key.try_into().ok()).ok_or_else(invalid)
+        };
+        
+        Ok(Self {
+            salt,
+            iterations,
+            stored_key: decode_key(stored_key)?,
+            server_key: decode_key(server_key)?,
+        })
+    }
+    
+    /// Verifier for an unknown user, which no client proof satisfies
+    ///
+    /// Running the exchange against it keeps unknown users indistinguishable
+    /// from wrong passwords until the final message. The salt is derived from
+    /// the user name so that it is stable across attempts.
+    fn mock(user: &str) -> Self {
+        let salt = Sha256::digest(format!("scram-mock-salt:{}", user).as_bytes());
+        let mut verifier = Self {
+            salt: salt[..SCRAM_SALT_LENGTH].to_vec(),
+            iterations: SCRAM_DEFAULT_ITERATIONS,
+            stored_key: [0u8; 32],
+            server_key: [0u8; 32],
+        };
+        thread_rng().fill(&mut verifier.stored_key);
+        thread_rng().fill(&mut verifier.server_key);
+        verifier
+    }
+    
+    /// Check a client proof over `auth_message`
+    ///
+    /// The proof is `ClientKey XOR HMAC(StoredKey, AuthMessage)`, so undoing the
+    /// XOR recovers a client key whose hash must be the stored key.
+    fn verify_client_proof(&self, auth_message: &[u8], proof: &[u8]) -> bool {
+        if proof.len() != self.stored_key.len() {
+            return false;
+        }
+        
+        let client_signature = hmac_sha256(&self.stored_key, auth_message);
+        let client_key: Vec<u8> = proof.iter().zip(client_signature).map(|(p, s)| p ^ s).collect();
+        verify_bytes(&self.stored_key, &Sha256::digest(&client_key))
+    }
+    
+    /// Server signature over `auth_message`, proving the server knows the verifier
+    fn server_signature(&self, auth_message: &[u8]) -> [u8; 32] {
+        hmac_sha256(&self.server_key, auth_message)
+    }
+}
+
+impl fmt::Display for ScramVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SCRAM-SHA-256${}:{}${}:{}",
+            self.iterations,
+            BASE64.encode(&self.salt),
+            BASE64.encode(self.stored_key),
+            BASE64.encode(self.server_key),
+        )
+    }
+}
+
+impl fmt::Debug for ScramVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScramVerifier")
+            .field("iterations", &self.iterations)
+            .finish_non_exhaustive()
+    }
+}
+
 /// SASL state for SCRAM-SHA-256 authentication
 #[derive(Debug, Clone)]
 struct SaslState {
-    /// Client first message
-    client_first_message: String,
-    
-    /// Server first message
-    server_first_message: String,
+    /// User being authenticated
+    username: String,
     
-    /// Client nonce
-    client_nonce: String,
+    /// GS2 header the client must echo in its final message
+    gs2_header: String,
     
-    /// Server nonce
-    server_nonce: String,
+    /// Client first message without the GS2 header
+    client_first_bare: String,
     
-    /// Salt
-    salt: Vec<u8>,
+    /// Server first message
+    server_first_message: String,
     
-    /// Iteration count
-    iteration_count: u32,
+    /// Client nonce followed by the server nonce
+    nonce: String,
     
-    /// Client username
-    username: String,
+    /// Credentials the client proof is checked against
+    verifier: ScramVerifier,
     
     /// SCRAM state
     state: ScramState,
@@ -171,18 +367,9 @@ struct SaslState {
 /// SCRAM state
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum ScramState {
-    /// Initial state
-    Initial,
-    
-    /// Client first message received
-    ReceivedClientFirst,
-    
-    /// Server first message sent
+    /// Server first message sent, awaiting the client's proof
     SentServerFirst,
     
-    /// Client final message received
-    ReceivedClientFinal,
-    
     /// Authentication completed
     Completed,
     
@@ -190,6 +377,24 @@ enum ScramState {
     Failed,
 }
 
+/// Kind of message a client sends with the password message tag (`p`)
+///
+/// The tag is shared, so the parser needs to know which exchange is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordMessageKind {
+    /// Cleartext or MD5 password
+    Password,
+    
+    /// Raw GSSAPI token
+    GssResponse,
+    
+    /// First SASL message, naming the mechanism
+    SaslInitialResponse,
+    
+    /// Later SASL message
+    SaslResponse,
+}
+
 impl AuthHandler {
     /// Create a new authentication handler with provided configuration
     pub fn new(auth_config: AuthConfig) -> Self {
@@ -203,6 +408,9 @@ impl AuthHandler {
             current_method: None,
             md5_salt,
             sasl_state: None,
+            authenticated_user: None,
+            startup_user: None,
+            client_certificate_name: None,
         }
     }
     
@@ -214,6 +422,9 @@ impl AuthHandler {
             current_method: None,
             md5_salt: [0u8; 4],
             sasl_state: None,
+            authenticated_user: None,
+            startup_user: None,
+            client_certificate_name: None,
         }
     }
     
@@ -238,25 +449,157 @@ impl AuthHandler {
                 vec![BackendMessage::Authentication(AuthenticationRequest::CleartextPassword)]
             }
             AuthMethod::ScramSha256 => {
+                // SASL state starts with the client's first message
                 self.current_method = Some(AuthMethod::ScramSha256);
-                
-                // Initialize SASL state
-                self.sasl_state = Some(SaslState {
-                    client_first_message: String::new(),
-                    server_first_message: String::new(),
-                    client_nonce: String::new(),
-                    server_nonce: String::new(),
-                    salt: Vec::new(),
-                    iteration_count: 4096, // Default iteration count
-                    username: String::new(),
-                    state: ScramState::Initial,
-                });
+                self.sasl_state = None;
                 
                 vec![BackendMessage::Authentication(AuthenticationRequest::SASL {
-                    mechanisms: vec!["SCRAM-SHA-256".to_string()],
+                    mechanisms: vec![SCRAM_SHA_256.to_string()],
                 })]
             }
+            AuthMethod::GssPassthrough => {
+                // The backend issues the GSSAPI request, see relay_backend_auth
+                self.current_method = Some(AuthMethod::GssPassthrough);
+                Vec::new()
+            }
+            AuthMethod::Certificate => {
+                // Completed once the TLS layer hands over the client certificate
+                self.current_method = Some(AuthMethod::Certificate);
+                Vec::new()
+            }
+        }
+    }
+    
+    /// Relay an authentication message from the backend during GSSAPI passthrough
+    ///
+    /// Returns the messages to forward to the client. The exchange completes
+    /// when the backend sends `AuthenticationOk`.
+    pub fn relay_backend_auth(&mut self, message: BackendMessage, user: &str) -> Result<Vec<BackendMessage>> {
+        if self.current_method != Some(AuthMethod::GssPassthrough) {
+            return Err(ProxyError::Auth("GSSAPI passthrough is not in progress".to_string()));
+        }
+        
+        match message {
+            BackendMessage::Authentication(AuthenticationRequest::GSS)
+            | BackendMessage::Authentication(AuthenticationRequest::SSPI)
+            | BackendMessage::Authentication(AuthenticationRequest::GSSContinue { .. }) => {
+                Ok(vec![message])
+            }
+            BackendMessage::Authentication(AuthenticationRequest::Ok) => {
+                debug!("Backend accepted GSSAPI authentication for {}", user);
+                self.authenticated_user = Some(user.to_string());
+                self.set_state_completed();
+                Ok(vec![message])
+            }
+            BackendMessage::ErrorResponse(_) => {
+                // Pass the backend's reason through; the client is not authenticated
+                self.state = AuthState::NotStarted as u8;
+                Ok(vec![message])
+            }
+            other => Err(ProxyError::Auth(format!(
+                "Unexpected backend message during GSSAPI passthrough: {:?}",
+                other
+            ))),
+        }
+    }
+    
+    /// Relay a client GSSAPI response to the backend
+    ///
+    /// Returns the encoded message to write to the backend unchanged.
+    pub fn relay_client_auth(&mut self, message: &FrontendMessage) -> Result<Bytes> {
+        if self.current_method != Some(AuthMethod::GssPassthrough) || self.get_state() != AuthState::InProgress {
+            return Err(ProxyError::Auth("GSSAPI passthrough is not in progress".to_string()));
+        }
+        
+        match message {
+            FrontendMessage::GssResponse(data) => Ok(encode_gss_response(data)),
+            _ => Err(ProxyError::Protocol("Expected GSSAPI response during passthrough".to_string())),
+        }
+    }
+    
+    /// Whether the next client message is a raw GSSAPI response
+    pub fn expects_gss_response(&self) -> bool {
+        self.current_method == Some(AuthMethod::GssPassthrough) && self.get_state() == AuthState::InProgress
+    }
+    
+    /// Kind of password message expected from the client next
+    pub fn expected_password_message(&self) -> PasswordMessageKind {
+        if self.expects_gss_response() {
+            return PasswordMessageKind::GssResponse;
         }
+        
+        if self.current_method == Some(AuthMethod::ScramSha256) && self.get_state() == AuthState::InProgress {
+            return match self.sasl_state {
+                None => PasswordMessageKind::SaslInitialResponse,
+                Some(_) => PasswordMessageKind::SaslResponse,
+            };
+        }
+        
+        PasswordMessageKind::Password
+    }
+    
+    /// Begin SCRAM-SHA-256 authentication for the user in the startup parameters
+    ///
+    /// As in PostgreSQL, the startup user is authenticated and the user name in
+    /// the SCRAM messages is ignored.
+    pub fn start_scram(&mut self, parameters: &HashMap<String, String>) -> Result<Vec<BackendMessage>> {
+        let user = parameters.get("user")
+            .filter(|user| !user.is_empty())
+            .ok_or_else(|| ProxyError::Auth("Startup message names no user".to_string()))?;
+        
+        self.reset();
+        self.startup_user = Some(user.clone());
+        self.state = AuthState::InProgress as u8;
+        self.current_method = Some(AuthMethod::ScramSha256);
+        
+        Ok(vec![BackendMessage::Authentication(AuthenticationRequest::SASL {
+            mechanisms: vec![SCRAM_SHA_256.to_string()],
+        })])
+    }
+    
+    /// Authenticate a client by the common name of its verified TLS certificate
+    ///
+    /// `common_name` is `None` when the client presented no certificate.
+    pub fn handle_client_certificate(&mut self, common_name: Option<&str>) -> Result<Vec<BackendMessage>> {
+        if self.current_method != Some(AuthMethod::Certificate) {
+            return Err(ProxyError::Auth("Certificate authentication is not configured".to_string()));
+        }
+        
+        let common_name = match common_name {
+            Some(cn) if !cn.is_empty() => cn,
+            _ => return Err(ProxyError::Auth("Client certificate required".to_string())),
+        };
+        
+        let user = self.config.cert_user_map
+            .get(common_name)
+            .cloned()
+            .unwrap_or_else(|| common_name.to_string());
+        
+        debug!("Client certificate {} mapped to user {}", common_name, user);
+        self.authenticated_user = Some(user);
+        self.set_state_completed();
+        
+        Ok(vec![BackendMessage::Authentication(AuthenticationRequest::Ok)])
+    }
+    
+    /// Record the common name of the certificate the client presented in the TLS handshake
+    pub fn set_client_certificate_name(&mut self, common_name: Option<String>) {
+        self.client_certificate_name = common_name;
+    }
+    
+    /// Common name of the certificate the client presented, if any
+    pub fn client_certificate_name(&self) -> Option<&str> {
+        self.client_certificate_name.as_deref()
+    }
+    
+    /// Database user established by passthrough or certificate authentication
+    pub fn authenticated_user(&self) -> Option<&str> {
+        self.authenticated_user.as_deref()
+    }
+    
+    /// Current authentication method
+    pub fn current_method(&self) -> Option<AuthMethod> {
+        self.current_method
     }
     
     /// Handle authentication message from client
@@ -272,45 +615,14 @@ impl AuthHandler {
                     })
                 })
             }
-            // For SASL authentication, client first sends a message with the mechanism
-            FrontendMessage::Unknown { tag, body } if *tag == b'p' => {
-                // This looks like a SASL message, try to parse it
-                let mut buf = body.slice(..);
-                if buf.remaining() >= 2 {
-                    // First two bytes should be length
-                    let _len = buf.get_u16();
-                    
-                    if buf.remaining() > 0 {
-                        // Try to extract mechanism name
-                        let mut mechanism = Vec::new();
-                        while buf.has_remaining() {
-                            let b = buf.get_u8();
-                            if b == 0 {
-                                break;
-                            }
-                            mechanism.push(b);
-                        }
-                        
-                        if let Ok(mechanism_str) = String::from_utf8(mechanism) {
-                            if mechanism_str == "SCRAM-SHA-256" {
-                                // Extract client first message
-                                let mut client_first = Vec::new();
-                                while buf.has_remaining() {
-                                    client_first.push(buf.get_u8());
-                                }
-                                
-                                if let Ok(client_first_str) = String::from_utf8(client_first) {
-                                    return self.handle_sasl_client_first(client_first_str);
-                                }
-                            }
-                        }
-                    }
-                }
-                
-                // If we get here, we couldn't parse the message as SASL
-                Err(ProxyError::Protocol("Invalid authentication message".to_string()))
+            FrontendMessage::SaslInitialResponse { mechanism, data } => {
+                self.handle_sasl_initial_response(mechanism, data.as_ref())
+            }
+            FrontendMessage::SaslResponse(data) => {
+                let client_final = str::from_utf8(data)
+                    .map_err(|_| ProxyError::Protocol("SASL response is not valid UTF-8".to_string()))?;
+                self.handle_sasl_client_final(client_final)
             }
-            // Handle client final message in SASL authentication
             _ => {
                 Err(ProxyError::Protocol("Unexpected message during authentication".to_string()))
             }
@@ -387,79 +699,142 @@ impl AuthHandler {
         Ok(vec![BackendMessage::Authentication(AuthenticationRequest::CleartextPassword)])
     }
     
-    /// Handle SASL client first message
-    fn handle_sasl_client_first(&mut self, client_first: String) -> Result<Vec<BackendMessage>> {
-        let sasl_state = match &mut self.sasl_state {
-            Some(state) => state,
+    /// Handle the SASL initial response, which carries the client first message
+    fn handle_sasl_initial_response(&mut self, mechanism: &str, data: Option<&Bytes>) -> Result<Vec<BackendMessage>> {
+        if self.current_method != Some(AuthMethod::ScramSha256)
+            || self.get_state() != AuthState::InProgress
+            || self.sasl_state.is_some()
+        {
+            return Err(ProxyError::Protocol("Unexpected SASL initial response".to_string()));
+        }
+        
+        if mechanism != SCRAM_SHA_256 {
+            return Err(ProxyError::Auth(format!("Unsupported SASL mechanism: {}", mechanism)));
+        }
+        
+        let data = data.ok_or_else(|| ProxyError::Protocol("SASL initial response carries no data".to_string()))?;
+        let client_first = str::from_utf8(data)
+            .map_err(|_| ProxyError::Protocol("SASL initial response is not valid UTF-8".to_string()))?;
+        
+        let mut server_nonce = [0u8; SCRAM_NONCE_LENGTH];
+        thread_rng().fill(&mut server_nonce);
+        self.handle_sasl_client_first(client_first, &BASE64.encode(server_nonce))
+    }
+    
+    /// Handle SASL client first message, answering with the server first message
+    ///
+    /// Format: `<gs2-header><n=user,r=client-nonce>`, where the GS2 header is
+    /// `n,,` or `y,,` since channel binding is not offered.
+    fn handle_sasl_client_first(&mut self, client_first: &str, server_nonce: &str) -> Result<Vec<BackendMessage>> {
+        let invalid = || ProxyError::Protocol("Invalid SCRAM client first message".to_string());
+        
+        let (gs2_header, client_first_bare) = match client_first.get(..3) {
+            Some("n,,") | Some("y,,") => client_first.split_at(3),
+            _ if client_first.starts_with("p=") => {
+                return Err(ProxyError::Auth("SCRAM channel binding is not supported".to_string()));
+            }
+            _ => return Err(invalid()),
+        };
+        
+        let mut attributes = client_first_bare.split(',');
+        let name = attributes.next().and_then(|a| a.strip_prefix("n=")).ok_or_else(invalid)?;
+        let client_nonce = attributes.next().and_then(|a| a.strip_prefix("r=")).ok_or_else(invalid)?;
+        if client_nonce.is_empty() || !client_nonce.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(invalid());
+        }
+        
+        let username = match &self.startup_user {
+            Some(user) => user.clone(),
+            None => decode_saslname(name).ok_or_else(invalid)?,
+        };
+        let verifier = match self.config.scram_verifiers.get(&username) {
+            Some(verifier) => verifier.clone(),
             None => {
-                return Err(ProxyError::Auth("No SASL state available".to_string()));
+                debug!("No SCRAM verifier for user {}, continuing with a mock", username);
+                ScramVerifier::mock(&username)
             }
         };
         
-        // For now, just return a simple authentication response
-        // In a real implementation, we would parse the SASL client-first message
-        // and respond with a server-first message
+        let nonce = format!("{}{}", client_nonce, server_nonce);
+        let server_first_message = format!(
+            "r={},s={},i={}",
+            nonce,
+            BASE64.encode(&verifier.salt),
+            verifier.iterations
+        );
         
-        // Generate a random salt for SCRAM-SHA-256
-        let mut salt = [0u8; 16];
-        thread_rng().fill(&mut salt);
+        self.sasl_state = Some(SaslState {
+            username,
+            gs2_header: gs2_header.to_string(),
+            client_first_bare: client_first_bare.to_string(),
+            server_first_message: server_first_message.clone(),
+            nonce,
+            verifier,
+            state: ScramState::SentServerFirst,
+        });
         
-        // Return authentication successful for now
-        Ok(vec![BackendMessage::Authentication(AuthenticationRequest::Ok)])
+        Ok(vec![BackendMessage::Authentication(AuthenticationRequest::SASLContinue {
+            data: Bytes::from(server_first_message),
+        })])
     }
     
-    /// Handle SASL client final message
+    /// Handle SASL client final message, verifying the client proof
+    ///
+    /// Format: `c=<base64 gs2-header>,r=<nonce>,p=<base64 client proof>`. On
+    /// success the server signature is returned, followed by `AuthenticationOk`.
     fn handle_sasl_client_final(&mut self, client_final: &str) -> Result<Vec<BackendMessage>> {
         let sasl_state = match &mut self.sasl_state {
-            Some(state) => state,
+            Some(state) if state.state == ScramState::SentServerFirst => state,
+            _ => return Err(ProxyError::Protocol("Unexpected SASL response".to_string())),
+        };
+        
+        // The proof covers everything before it
+        let (without_proof, proof) = match client_final.rsplit_once(",p=") {
+            Some(parts) => parts,
             None => {
-                return Err(ProxyError::Protocol("SASL authentication not started".to_string()));
+                sasl_state.state = ScramState::Failed;
+                return Err(ProxyError::Protocol("Invalid SCRAM client final message".to_string()));
             }
         };
         
-        if sasl_state.state != ScramState::SentServerFirst {
-            return Err(ProxyError::Protocol("Unexpected SASL message".to_string()));
-        }
+        let mut attributes = without_proof.split(',');
+        let channel_binding = attributes.next().and_then(|a| a.strip_prefix("c="));
+        let nonce = attributes.next().and_then(|a| a.strip_prefix("r="));
         
-        // Parse client final message
-        // Format: c=<base64-cbind-input>,r=<server-nonce>,p=<base64-client-proof>
-        let parts: Vec<&str> = client_final.split(',').collect();
-        
-        // Extract server nonce and client proof
-        let mut server_nonce = String::new();
-        let mut client_proof_base64 = String::new();
-        
-        for part in parts {
-            if part.starts_with("r=") {
-                server_nonce = part[2..].to_string();
-            } else if part.starts_with("p=") {
-                client_proof_base64 = part[2..].to_string();
-            }
-        }
-        
-        if server_nonce != sasl_state.server_nonce || client_proof_base64.is_empty() {
+        if channel_binding != Some(BASE64.encode(&sasl_state.gs2_header).as_str())
+            || nonce != Some(sasl_state.nonce.as_str())
+        {
             sasl_state.state = ScramState::Failed;
-            return Err(ProxyError::Protocol("Invalid SASL client final message".to_string()));
+            return Err(ProxyError::Protocol("Invalid SCRAM client final message".to_string()));
         }
         
-        // Update state
-        sasl_state.state = ScramState::ReceivedClientFinal;
+        let auth_message = format!(
+            "{},{},{}",
+            sasl_state.client_first_bare, sasl_state.server_first_message, without_proof
+        );
+        let proof = BASE64.decode(proof).unwrap_or_default();
         
-        // In a real implementation, we would validate the client proof
-        // and generate a server signature, but for this example, we'll skip that
-        // and just return authentication success
+        if !sasl_state.verifier.verify_client_proof(auth_message.as_bytes(), &proof) {
+            sasl_state.state = ScramState::Failed;
+            return Err(ProxyError::Auth(format!(
+                "Password authentication failed for user {}",
+                sasl_state.username
+            )));
+        }
         
-        // For a complete SCRAM-SHA-256 implementation, we would:
-        // 1. Derive client and server keys from the password
-        // 2. Compute the client signature and validate the client proof
-        // 3. Compute the server signature
-        // 4. Return the server signature in a SASLFinal message
+        let server_signature = sasl_state.verifier.server_signature(auth_message.as_bytes());
+        let server_final_message = format!("v={}", BASE64.encode(server_signature));
         
-        // For now, we'll just complete the authentication
+        debug!("SCRAM-SHA-256 authentication succeeded for user {}", sasl_state.username);
         sasl_state.state = ScramState::Completed;
         self.set_state_completed();
         
-        Ok(vec![BackendMessage::Authentication(AuthenticationRequest::Ok)])
+        Ok(vec![
+            BackendMessage::Authentication(AuthenticationRequest::SASLFinal {
+                data: Bytes::from(server_final_message),
+            }),
+            BackendMessage::Authentication(AuthenticationRequest::Ok),
+        ])
     }
     
     /// Get authentication state
@@ -472,6 +847,8 @@ impl AuthHandler {
         self.state = AuthState::NotStarted as u8;
         self.current_method = None;
         self.sasl_state = None;
+        self.authenticated_user = None;
+        self.startup_user = None;
     }
 
     /// Handle verify message
@@ -518,6 +895,56 @@ impl AuthHandler {
     }
 }
 
+/// Encode a GSSAPI response as a frontend `p` message
+fn encode_gss_response(data: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(5 + data.len());
+    buf.put_u8(b'p');
+    buf.put_i32(4 + data.len() as i32);
+    buf.put_slice(data);
+    buf.freeze()
+}
+
+/// HMAC-SHA-256 of `message` under `key`
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+/// SCRAM `Hi` function: PBKDF2 with HMAC-SHA-256 and a single output block
+fn scram_hi(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut block = salt.to_vec();
+    block.extend_from_slice(&1u32.to_be_bytes());
+    
+    let mut u = hmac_sha256(password, &block);
+    let mut result = u;
+    for _ in 1..iterations {
+        u = hmac_sha256(password, &u);
+        for (r, b) in result.iter_mut().zip(u) {
+            *r ^= b;
+        }
+    }
+    
+    result
+}
+
+/// Decode a SCRAM `saslname`, in which `,` and `=` are escaped as `=2C` and `=3D`
+fn decode_saslname(name: &str) -> Option<String> {
+    let mut decoded = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(i) = rest.find('=') {
+        decoded.push_str(&rest[..i]);
+        match rest.get(i..i + 3) {
+            Some("=2C") => decoded.push(','),
+            Some("=3D") => decoded.push('='),
+            _ => return None,
+        }
+        rest = &rest[i + 3..];
+    }
+    decoded.push_str(rest);
+    Some(decoded)
+}
+
 /// Calculate MD5 hex digest
 fn md5_hex(password: &str, salt: &[u8]) -> String {
     let mut context = md5::Context::new();
@@ -544,6 +971,10 @@ mod tests {
         assert_eq!(format!("{}", AuthMethod::Md5Password), "md5");
         assert_eq!(format!("{}", AuthMethod::ScramSha256), "scram-sha-256");
         assert_eq!(format!("{}", AuthMethod::CleartextPassword), "password");
+        assert_eq!(AuthMethod::from("gss"), AuthMethod::GssPassthrough);
+        assert_eq!(AuthMethod::from("cert"), AuthMethod::Certificate);
+        assert_eq!(format!("{}", AuthMethod::GssPassthrough), "gss");
+        assert_eq!(format!("{}", AuthMethod::Certificate), "cert");
         
         // Create auth handler with trust authentication
         let mut config = AuthConfig::default();
@@ -630,4 +1061,235 @@ mod tests {
             }
         });
     }
-} 
\ No newline at end of file
+    
+    #[test]
+    fn test_gss_passthrough_negotiation() {
+        let mut config = AuthConfig::default();
+        config.default_method = AuthMethod::GssPassthrough;
+        let mut handler = AuthHandler::new(config);
+        
+        // The proxy sends nothing itself; the backend drives the exchange
+        assert!(handler.get_initial_auth_request().is_empty());
+        
+        // Backend asks for GSSAPI, which is forwarded to the client
+        let to_client = handler.relay_backend_auth(
+            BackendMessage::Authentication(AuthenticationRequest::GSS), "alice").unwrap();
+        assert_eq!(to_client, vec![BackendMessage::Authentication(AuthenticationRequest::GSS)]);
+        assert!(handler.expects_gss_response());
+        
+        // Client token is forwarded to the backend byte for byte
+        let token = Bytes::from_static(&[0x60, 0x82, 0x00, 0x01, 0xff]);
+        let to_backend = handler.relay_client_auth(&FrontendMessage::GssResponse(token.clone())).unwrap();
+        assert_eq!(to_backend[0], b'p');
+        assert_eq!(&to_backend[1..5], &(4 + token.len() as i32).to_be_bytes());
+        assert_eq!(&to_backend[5..], &token[..]);
+        
+        // Continuation round trip
+        let cont = BackendMessage::Authentication(AuthenticationRequest::GSSContinue {
+            data: Bytes::from_static(b"server-token"),
+        });
+        assert_eq!(handler.relay_backend_auth(cont.clone(), "alice").unwrap(), vec![cont]);
+        assert!(handler.relay_client_auth(&FrontendMessage::GssResponse(Bytes::from_static(b"final"))).is_ok());
+        
+        // Backend accepts and the exchange completes
+        let ok = handler.relay_backend_auth(
+            BackendMessage::Authentication(AuthenticationRequest::Ok), "alice").unwrap();
+        assert_eq!(ok, vec![BackendMessage::Authentication(AuthenticationRequest::Ok)]);
+        assert_eq!(handler.get_state(), AuthState::Completed);
+        assert_eq!(handler.authenticated_user(), Some("alice"));
+        assert!(!handler.expects_gss_response());
+        
+        // Password requests are not relayed during passthrough
+        let mut handler = AuthHandler::new(AuthConfig {
+            default_method: AuthMethod::GssPassthrough,
+            ..AuthConfig::default()
+        });
+        handler.get_initial_auth_request();
+        assert!(handler.relay_backend_auth(
+            BackendMessage::Authentication(AuthenticationRequest::CleartextPassword), "alice").is_err());
+    }
+    
+    #[test]
+    fn test_certificate_authentication() {
+        let mut config = AuthConfig::default();
+        config.default_method = AuthMethod::Certificate;
+        config.cert_user_map.insert("svc.example.com".to_string(), "app".to_string());
+        
+        let mut handler = AuthHandler::new(config.clone());
+        assert!(handler.get_initial_auth_request().is_empty());
+        assert!(handler.handle_client_certificate(None).is_err());
+        assert_ne!(handler.get_state(), AuthState::Completed);
+        
+        // Mapped common name
+        handler.handle_client_certificate(Some("svc.example.com")).unwrap();
+        assert_eq!(handler.get_state(), AuthState::Completed);
+        assert_eq!(handler.authenticated_user(), Some("app"));
+        
+        // Unmapped common name is used as the user name
+        let mut handler = AuthHandler::new(config);
+        handler.get_initial_auth_request();
+        handler.handle_client_certificate(Some("bob")).unwrap();
+        assert_eq!(handler.authenticated_user(), Some("bob"));
+    }
+    
+    /// RFC 7677 section 3 SCRAM-SHA-256 exchange for user "user", password "pencil"
+    const RFC7677_SALT: &str = "W22ZaJ0SNY7soEsUEjb6gQ==";
+    const RFC7677_CLIENT_FIRST: &str = "n,,n=user,r=rOprNGfwEbeRWgbNEkqO";
+    const RFC7677_SERVER_NONCE: &str = "%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0";
+    const RFC7677_SERVER_FIRST: &str =
+        "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+    const RFC7677_CLIENT_FINAL: &str = "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+        p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+    const RFC7677_SERVER_FINAL: &str = "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+    
+    fn scram_handler(password: &str) -> AuthHandler {
+        let salt = BASE64.decode(RFC7677_SALT).unwrap();
+        let mut config = AuthConfig {
+            default_method: AuthMethod::ScramSha256,
+            ..AuthConfig::default()
+        };
+        config.scram_verifiers.insert("user".to_string(), ScramVerifier::new(password, &salt, 4096));
+        
+        let mut handler = AuthHandler::new(config);
+        handler.get_initial_auth_request();
+        handler
+    }
+    
+    fn sasl_response(message: &str) -> FrontendMessage {
+        FrontendMessage::SaslResponse(Bytes::copy_from_slice(message.as_bytes()))
+    }
+    
+    #[test]
+    fn test_scram_sha_256_test_vectors() {
+        let mut handler = scram_handler("pencil");
+        assert_eq!(handler.expected_password_message(), PasswordMessageKind::SaslInitialResponse);
+        
+        let server_first = handler.handle_sasl_client_first(RFC7677_CLIENT_FIRST, RFC7677_SERVER_NONCE).unwrap();
+        assert_eq!(server_first, vec![BackendMessage::Authentication(AuthenticationRequest::SASLContinue {
+            data: Bytes::from_static(RFC7677_SERVER_FIRST.as_bytes()),
+        })]);
+        assert_eq!(handler.expected_password_message(), PasswordMessageKind::SaslResponse);
+        
+        let server_final = handler.handle_auth_message(&sasl_response(RFC7677_CLIENT_FINAL)).unwrap();
+        assert_eq!(server_final, vec![
+            BackendMessage::Authentication(AuthenticationRequest::SASLFinal {
+                data: Bytes::from_static(RFC7677_SERVER_FINAL.as_bytes()),
+            }),
+            BackendMessage::Authentication(AuthenticationRequest::Ok),
+        ]);
+        assert_eq!(handler.get_state(), AuthState::Completed);
+        assert_eq!(handler.expected_password_message(), PasswordMessageKind::Password);
+        
+        // A replayed final message is not accepted twice
+        assert!(handler.handle_auth_message(&sasl_response(RFC7677_CLIENT_FINAL)).is_err());
+    }
+    
+    #[test]
+    fn test_scram_sha_256_rejects_bad_proofs() {
+        // Wrong password
+        let mut handler = scram_handler("pen");
+        handler.handle_sasl_client_first(RFC7677_CLIENT_FIRST, RFC7677_SERVER_NONCE).unwrap();
+        assert!(matches!(
+            handler.handle_auth_message(&sasl_response(RFC7677_CLIENT_FINAL)),
+            Err(ProxyError::Auth(_))
+        ));
+        assert_ne!(handler.get_state(), AuthState::Completed);
+        
+        // Nonce that differs from the one the server issued
+        let mut handler = scram_handler("pencil");
+        handler.handle_sasl_client_first(RFC7677_CLIENT_FIRST, "other-nonce").unwrap();
+        assert!(handler.handle_auth_message(&sasl_response(RFC7677_CLIENT_FINAL)).is_err());
+        
+        // Channel binding flag that does not match the client first message
+        let mut handler = scram_handler("pencil");
+        handler.handle_sasl_client_first(RFC7677_CLIENT_FIRST, RFC7677_SERVER_NONCE).unwrap();
+        let tampered = RFC7677_CLIENT_FINAL.replace("c=biws", "c=eSws");
+        assert!(handler.handle_auth_message(&sasl_response(&tampered)).is_err());
+        
+        // Channel binding is not offered
+        let mut handler = scram_handler("pencil");
+        assert!(handler.handle_sasl_client_first("p=tls-server-end-point,,n=user,r=abc", "xyz").is_err());
+        
+        // Unknown users get a plausible challenge but can never finish
+        let mut handler = scram_handler("pencil");
+        let server_first = handler.handle_sasl_client_first("n,,n=mallory,r=rOprNGfwEbeRWgbNEkqO", RFC7677_SERVER_NONCE).unwrap();
+        match &server_first[0] {
+            BackendMessage::Authentication(AuthenticationRequest::SASLContinue { data }) => {
+                assert!(str::from_utf8(data).unwrap().ends_with(",i=4096"));
+            }
+            other => panic!("Expected SASLContinue, got {:?}", other),
+        }
+        assert!(handler.handle_auth_message(&sasl_response(RFC7677_CLIENT_FINAL)).is_err());
+    }
+    
+    #[test]
+    fn test_scram_authenticates_startup_user() {
+        let mut config = AuthConfig {
+            default_method: AuthMethod::ScramSha256,
+            ..AuthConfig::default()
+        };
+        config.add_scram_user("alice", "secret");
+        let mut handler = AuthHandler::new(config);
+        
+        let mut parameters = HashMap::new();
+        parameters.insert("user".to_string(), "alice".to_string());
+        let request = handler.start_scram(&parameters).unwrap();
+        assert_eq!(request, vec![BackendMessage::Authentication(AuthenticationRequest::SASL {
+            mechanisms: vec!["SCRAM-SHA-256".to_string()],
+        })]);
+        
+        // libpq leaves the SCRAM user name empty and relies on the startup user
+        let client_first_bare = "n=,r=fyko+d2lbbFgONRv9qkxdawL";
+        let initial = FrontendMessage::SaslInitialResponse {
+            mechanism: "SCRAM-SHA-256".to_string(),
+            data: Some(Bytes::from(format!("n,,{}", client_first_bare))),
+        };
+        let server_first = match handler.handle_auth_message(&initial).unwrap().remove(0) {
+            BackendMessage::Authentication(AuthenticationRequest::SASLContinue { data }) => {
+                String::from_utf8(data.to_vec()).unwrap()
+            }
+            other => panic!("Expected SASLContinue, got {:?}", other),
+        };
+        
+        // Client side of the exchange
+        let mut attributes = server_first.split(',');
+        let nonce = attributes.next().unwrap().strip_prefix("r=").unwrap();
+        let salt = BASE64.decode(attributes.next().unwrap().strip_prefix("s=").unwrap()).unwrap();
+        let iterations = attributes.next().unwrap().strip_prefix("i=").unwrap().parse().unwrap();
+        
+        let without_proof = format!("c=biws,r={}", nonce);
+        let auth_message = format!("{},{},{}", client_first_bare, server_first, without_proof);
+        let salted_password = scram_hi(b"secret", &salt, iterations);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        let stored_key: [u8; 32] = Sha256::digest(client_key).into();
+        let signature = hmac_sha256(&stored_key, auth_message.as_bytes());
+        let proof: Vec<u8> = client_key.iter().zip(signature).map(|(k, s)| k ^ s).collect();
+        
+        let client_final = format!("{},p={}", without_proof, BASE64.encode(proof));
+        let messages = handler.handle_auth_message(&sasl_response(&client_final)).unwrap();
+        assert_eq!(messages.last(), Some(&BackendMessage::Authentication(AuthenticationRequest::Ok)));
+        assert_eq!(handler.get_state(), AuthState::Completed);
+    }
+    
+    #[test]
+    fn test_scram_verifier_text_form() {
+        let salt = BASE64.decode(RFC7677_SALT).unwrap();
+        let verifier = ScramVerifier::new("pencil", &salt, 4096);
+        
+        let text = verifier.to_string();
+        assert!(text.starts_with("SCRAM-SHA-256$4096:W22ZaJ0SNY7soEsUEjb6gQ==$"));
+        assert!(!text.contains("pencil"));
+        assert_eq!(ScramVerifier::parse(&text).unwrap(), verifier);
+        
+        // Generated verifiers use fresh salts
+        assert_ne!(ScramVerifier::generate("pencil"), ScramVerifier::generate("pencil"));
+        
+        assert!(ScramVerifier::parse("md5abcdef").is_err());
+        assert!(ScramVerifier::parse("SCRAM-SHA-256$0:W22ZaJ0SNY7soEsUEjb6gQ==$AAAA:AAAA").is_err());
+        assert!(ScramVerifier::parse("SCRAM-SHA-256$4096:W22ZaJ0SNY7soEsUEjb6gQ==$AAAA:AAAA").is_err());
+        
+        // SCRAM user names escape ',' and '='
+        assert_eq!(decode_saslname("a=2Cb=3Dc").as_deref(), Some("a,b=c"));
+        assert_eq!(decode_saslname("bad=2"), None);
+    }
+}
@@ -1,25 +1,43 @@
 //! Connection manager for client connections
 use crate::config::ProxyConfig;
 use crate::error::{ProxyError, Result};
-use crate::protocol::auth::AuthHandler;
+use crate::interception::analyzer::QueryAnalyzer;
+use crate::interception::{InterceptionManager, QueryAction, QueryMetadata};
+use crate::interception::copy::copy_from_stdin;
+use crate::protocol::auth::{AuthHandler, AuthMethod, AuthState, PasswordMessageKind};
+use crate::protocol::extended::{param_type, ExtendedQueryState, PortalResult, RawParam};
 use crate::protocol::message::{
     BackendMessage, ErrorOrNoticeFields, FieldDescription,
     FrontendMessage, TransactionStatus,
 };
 use crate::protocol::parser::MessageParser;
 use crate::protocol::formatter::MessageFormatter;
+use crate::protocol::tls::{self, ClientStream};
 use crate::protocol::validator::ProtocolValidator;
+use crate::security::SecurityGateway;
+use crate::server::cancel::{CancelRegistration, CancelRegistry};
+use crate::server::pool::{backend_pg_config, BackendPool};
 use crate::transaction::TransactionManager;
-use bytes::{Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_util::SinkExt;
 use log::{debug, error, warn};
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::sync::{watch, Semaphore};
 use tokio::time::timeout;
 use tokio_postgres::config::Config as PgConfig;
-use tokio_postgres::Client;
+use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::{Client, Column, CopyInSink, Row};
+use tokio_rustls::TlsAcceptor;
+use verifiable_db_core::models::Value;
+
+/// Largest piece of a CopyData message read from a client at a time
+const COPY_DATA_CHUNK_SIZE: usize = 64 * 1024;
 
 /// Connection state
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -102,35 +120,300 @@ impl Default for ConnectionStats {
 }
 
 /// A wrapper around tokio_postgres::Client that implements Clone
+///
+/// A pooled client goes back to its pool once the last clone is dropped.
 #[derive(Debug, Clone)]
 pub struct ClientWrapper {
     /// The inner client
-    inner: Arc<Client>,
+    inner: Arc<BackendClient>,
+}
+
+/// Backend connection owned by one client or checked out of the pool
+#[derive(Debug)]
+enum BackendClient {
+    /// Connection opened for a single client
+    Dedicated(Client),
+    
+    /// Connection checked out of the shared backend pool
+    Pooled(deadpool_postgres::Client),
 }
 
 impl ClientWrapper {
     /// Create a new client wrapper
     pub fn new(client: Client) -> Self {
         Self {
-            inner: Arc::new(client),
+            inner: Arc::new(BackendClient::Dedicated(client)),
+        }
+    }
+    
+    /// Wrap a connection checked out of the backend pool
+    pub fn pooled(client: deadpool_postgres::Client) -> Self {
+        Self {
+            inner: Arc::new(BackendClient::Pooled(client)),
         }
     }
     
     /// Get a reference to the inner client
     pub fn inner(&self) -> &Client {
-        &self.inner
+        match &*self.inner {
+            BackendClient::Dedicated(client) => client,
+            BackendClient::Pooled(client) => client,
+        }
     }
     
     /// Get a mutable reference to the inner client
     pub fn inner_mut(&self) -> &Client {
-        &self.inner
+        self.inner()
+    }
+}
+
+/// Where a client connection gets its backend sessions
+#[derive(Debug, Clone)]
+pub enum Backend {
+    /// One connection opened for this client
+    Dedicated(ClientWrapper),
+    
+    /// Sessions checked out of the shared pool for each statement
+    Pooled(BackendPool),
+}
+
+impl Backend {
+    /// Backend session for a statement that has no pinned session
+    pub async fn acquire(&self) -> Result<ClientWrapper> {
+        match self {
+            Backend::Dedicated(client) => Ok(client.clone()),
+            Backend::Pooled(pool) => pool.get().await,
+        }
+    }
+}
+
+/// Pins a backend session to a client for the duration of a transaction
+///
+/// Every statement between BEGIN and COMMIT/ROLLBACK must run on the same
+/// backend session, otherwise the transaction loses atomicity. Outside a
+/// transaction any session may be used, so the pinned one is handed back as
+/// soon as the connection is idle again.
+///
+/// Statements that leave state on the session itself, such as a session-level
+/// `SET`, [`hold`](Self::hold) it for the rest of the client's connection.
+/// A transaction the proxy opens for an extended-protocol batch
+/// [`pins`](Self::pin_until_sync) its session until the batch's Sync.
+#[derive(Debug)]
+pub struct SessionAffinity<S> {
+    /// Session pinned to the open transaction, if any
+    pinned: Option<S>,
+    
+    /// Whether the pinned session is kept after the transaction ends
+    held: bool,
+    
+    /// Whether the pinned session runs a transaction ending at the next Sync
+    until_sync: bool,
+}
+
+impl<S: Clone> SessionAffinity<S> {
+    /// Create an affinity tracker with no pinned session
+    pub fn new() -> Self {
+        Self { pinned: None, held: false, until_sync: false }
+    }
+    
+    /// Get the session for the next statement
+    ///
+    /// `status` is the transaction status once the statement has started, so a
+    /// BEGIN pins the session it was routed to. `acquire` is only called when
+    /// no session is pinned.
+    pub fn session_for<F>(&mut self, status: TransactionStatus, acquire: F) -> S
+    where
+        F: FnOnce() -> S,
+    {
+        if let Some(session) = &self.pinned {
+            return session.clone();
+        }
+        
+        let session = acquire();
+        if status != TransactionStatus::Idle {
+            self.pinned = Some(session.clone());
+        }
+        session
+    }
+    
+    /// Release the pinned session once the transaction has ended
+    ///
+    /// Returns the session so the caller can hand it back to a pool.
+    pub fn release_if_idle(&mut self, status: TransactionStatus) -> Option<S> {
+        if status == TransactionStatus::Idle && !self.held && !self.until_sync {
+            self.pinned.take()
+        } else {
+            None
+        }
+    }
+    
+    /// Keep `session` for every later statement of this client
+    pub fn hold(&mut self, session: S) {
+        self.pinned = Some(session);
+        self.held = true;
+    }
+    
+    /// Keep `session` until the extended-protocol batch ends at Sync
+    pub fn pin_until_sync(&mut self, session: S) {
+        self.pinned = Some(session);
+        self.until_sync = true;
+    }
+    
+    /// Whether a session is pinned until the next Sync
+    pub fn is_pinned_until_sync(&self) -> bool {
+        self.until_sync
+    }
+    
+    /// End the batch at Sync, returning the session pinned until it, if any
+    ///
+    /// The session stays pinned until [`release_if_idle`](Self::release_if_idle).
+    pub fn end_batch(&mut self) -> Option<S> {
+        if std::mem::take(&mut self.until_sync) {
+            self.pinned.clone()
+        } else {
+            None
+        }
+    }
+    
+    /// Session pinned to this client, if any
+    pub fn pinned(&self) -> Option<&S> {
+        self.pinned.as_ref()
+    }
+    
+    /// Whether a session is currently pinned
+    pub fn is_pinned(&self) -> bool {
+        self.pinned.is_some()
+    }
+}
+
+impl<S: Clone> Default for SessionAffinity<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the interception manager decided for a client message
+#[derive(Debug)]
+enum Intercepted {
+    /// Process the message as usual; carries the statement it runs, if the
+    /// manager analyzed one
+    Forward(Option<Forwarded>),
+    
+    /// Answer the client with these messages instead
+    Answer(Vec<BackendMessage>),
+}
+
+/// A statement the interception manager analyzed before it ran
+#[derive(Debug)]
+struct Forwarded {
+    /// Analysis of the statement
+    metadata: QueryMetadata,
+    
+    /// Values bound to its parameters
+    params: Vec<Value>,
+    
+    /// Result columns of a portal, since its Execute response doesn't describe them
+    columns: Option<Vec<FieldDescription>>,
+}
+
+/// A `COPY ... FROM STDIN` streaming the client's rows to the backend
+pub struct CopyIn {
+    /// Rows forwarded to the backend session running the COPY
+    sink: Pin<Box<CopyInSink<Bytes>>>,
+    
+    /// Session running the COPY, kept out of the pool until it completes
+    session: ClientWrapper,
+}
+
+impl std::fmt::Debug for CopyIn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CopyIn").field("session", &self.session).finish()
+    }
+}
+
+/// Raw backend connection carrying a relayed authentication exchange
+///
+/// tokio-postgres cannot relay a GSSAPI exchange it does not own, so
+/// passthrough authentication runs on its own connection that speaks the wire
+/// protocol directly. It is closed once the exchange finishes.
+#[derive(Debug)]
+pub struct BackendAuthRelay {
+    /// Connection to the backend
+    stream: TcpStream,
+    
+    /// User named in the client's startup message
+    user: String,
+    
+    /// Bytes read but not yet parsed
+    buffer: BytesMut,
+}
+
+impl BackendAuthRelay {
+    /// Connect to the backend and send the client's startup parameters
+    pub async fn connect(addr: SocketAddr, parameters: &HashMap<String, String>) -> Result<Self> {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        
+        // Startup message: length, protocol 3.0, then name/value pairs
+        let mut body = BytesMut::new();
+        body.put_i32(196608);
+        for (name, value) in parameters {
+            body.put_slice(name.as_bytes());
+            body.put_u8(0);
+            body.put_slice(value.as_bytes());
+            body.put_u8(0);
+        }
+        body.put_u8(0);
+        
+        let mut message = BytesMut::with_capacity(body.len() + 4);
+        message.put_i32(body.len() as i32 + 4);
+        message.put_slice(&body);
+        stream.write_all(&message).await?;
+        
+        Ok(Self {
+            stream,
+            user: parameters.get("user").cloned().unwrap_or_default(),
+            buffer: BytesMut::with_capacity(1024),
+        })
+    }
+    
+    /// User being authenticated
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+    
+    /// Forward an encoded client message to the backend
+    pub async fn send(&mut self, bytes: &[u8]) -> Result<()> {
+        self.stream.write_all(bytes).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+    
+    /// Read the next complete message from the backend
+    pub async fn receive(&mut self, parser: &MessageParser) -> Result<BackendMessage> {
+        loop {
+            if self.buffer.len() >= 5 {
+                let length = (&self.buffer[1..5]).get_u32() as usize;
+                if self.buffer.len() >= 1 + length {
+                    let frame = self.buffer.split_to(1 + length).freeze();
+                    return parser.parse_backend_message(&frame);
+                }
+            }
+            
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return Err(ProxyError::ConnectionClosed);
+            }
+        }
     }
 }
 
 /// Client connection
 pub struct ClientConnection {
     /// Client socket
-    socket: TcpStream,
+    socket: ClientStream,
+    
+    /// Acceptor for `SSLRequest` upgrades, if TLS is enabled
+    tls_acceptor: Option<TlsAcceptor>,
     
     /// Client address
     addr: SocketAddr,
@@ -138,8 +421,17 @@ pub struct ClientConnection {
     /// Connection state
     state: ConnectionState,
     
-    /// PostgreSQL backend client
-    pg_client: Option<ClientWrapper>,
+    /// Source of backend sessions, once the client has authenticated
+    backend: Option<Backend>,
+    
+    /// Shared backend pool; without one the client gets its own connection
+    backend_pool: Option<BackendPool>,
+    
+    /// Cancel keys of all client sessions
+    cancel_registry: CancelRegistry,
+    
+    /// This session's cancel key, once the client has authenticated
+    cancel_registration: Option<CancelRegistration>,
     
     /// Message parser
     parser: MessageParser,
@@ -156,12 +448,43 @@ pub struct ClientConnection {
     /// Transaction manager
     transaction_manager: Arc<Mutex<TransactionManager>>,
     
+    /// Prepared statements and portals for the extended query protocol
+    extended_state: ExtendedQueryState,
+    
+    /// Backend session pinned to the open transaction
+    session_affinity: SessionAffinity<ClientWrapper>,
+    
+    /// COPY FROM STDIN in progress, if any
+    copy_in: Option<CopyIn>,
+    
+    /// Transaction status reported to the client in ReadyForQuery
+    transaction_status: TransactionStatus,
+    
+    /// Backend connection relaying a passthrough authentication exchange
+    auth_relay: Option<BackendAuthRelay>,
+    
+    /// Security checks on authenticated users and their queries
+    security_gateway: Option<Arc<SecurityGateway>>,
+    
+    /// Set once the server starts draining its sessions
+    shutdown: Option<watch::Receiver<bool>>,
+    
+    /// Analyzer pricing simple queries against the query cost budget
+    analyzer: QueryAnalyzer,
+    
+    /// Analysis and verification recording of executed statements, when
+    /// verification is enabled
+    interception: Option<InterceptionManager>,
+    
     /// Configuration
     config: ProxyConfig,
     
     /// Connection statistics
     stats: ConnectionStats,
     
+    /// The server's connection slots, one held by each open client connection
+    connection_slots: Option<Arc<Semaphore>>,
+    
     /// Buffer for reading
     read_buffer: BytesMut,
     
@@ -178,34 +501,112 @@ impl ClientConnection {
         transaction_manager: Arc<Mutex<TransactionManager>>,
     ) -> Self {
         Self {
-            socket,
+            socket: ClientStream::from(socket),
+            tls_acceptor: None,
             addr,
             state: ConnectionState::Initial,
-            pg_client: None,
-            parser: MessageParser::new(),
+            backend: None,
+            backend_pool: None,
+            cancel_registry: CancelRegistry::new(),
+            cancel_registration: None,
+            parser: MessageParser::new().with_max_message_size(config.validator_config.max_message_size),
             formatter: MessageFormatter::new(),
             auth_handler: AuthHandler::new(config.auth_config.clone()),
             validator: ProtocolValidator::new(config.validator_config.clone()),
             transaction_manager,
+            extended_state: ExtendedQueryState::new(),
+            session_affinity: SessionAffinity::new(),
+            copy_in: None,
+            transaction_status: TransactionStatus::Idle,
+            auth_relay: None,
+            security_gateway: None,
+            shutdown: None,
+            analyzer: QueryAnalyzer::new(),
+            interception: None,
             config,
             stats: ConnectionStats::default(),
+            connection_slots: None,
             read_buffer: BytesMut::with_capacity(8192),
             write_buffer: BytesMut::with_capacity(8192),
         }
     }
     
+    /// Count the server's open client connections from its connection slots
+    pub fn with_connection_slots(mut self, slots: Arc<Semaphore>) -> Self {
+        self.connection_slots = Some(slots);
+        self
+    }
+    
+    /// Terminate TLS at the proxy for clients that send an `SSLRequest`
+    pub fn with_tls(mut self, acceptor: Option<TlsAcceptor>) -> Self {
+        self.tls_acceptor = acceptor;
+        self
+    }
+    
+    /// Analyze executed statements and record them for verification
+    pub fn with_interception(mut self, interception: Option<InterceptionManager>) -> Self {
+        self.interception = interception;
+        self
+    }
+    
+    /// Run statements on connections from a shared backend pool
+    pub fn with_backend_pool(mut self, pool: Option<BackendPool>) -> Self {
+        self.backend_pool = pool;
+        self
+    }
+    
+    /// Share cancel keys with other connections, so a `CancelRequest` sent on
+    /// a new connection reaches this session
+    pub fn with_cancel_registry(mut self, registry: CancelRegistry) -> Self {
+        self.cancel_registry = registry;
+        self
+    }
+    
+    /// Rate limit sessions per client address and authenticated user, and
+    /// screen simple queries for SQL injection and excessive cost
+    pub fn with_security_gateway(mut self, gateway: Option<Arc<SecurityGateway>>) -> Self {
+        self.security_gateway = gateway;
+        self
+    }
+    
+    /// Close the session once `shutdown` is set, letting an open transaction
+    /// finish first
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+    
+    /// Statistics of this connection
+    pub fn stats(&self) -> &ConnectionStats {
+        &self.stats
+    }
+    
+    /// Client connections the server currently has open, this one included
+    ///
+    /// `None` for connections not accepted by a server.
+    pub fn active_connections(&self) -> Option<usize> {
+        self.connection_slots.as_ref()
+            .map(|slots| self.config.max_connections.saturating_sub(slots.available_permits()))
+    }
+    
     /// Handle client connection
     pub async fn handle_connection(&mut self) -> Result<()> {
         // Enable TCP_NODELAY for better performance
-        self.socket.set_nodelay(true)?;
+        self.socket.tcp()?.set_nodelay(true)?;
         
         // Set up connection timeout
         let timeout_duration = Duration::from_secs(self.config.connection_timeout);
         let mut last_activity = Instant::now();
         
-        // Initialize connection state
-        self.state = ConnectionState::Startup;
-        let mut transaction_status = TransactionStatus::Idle;
+        // Initialize connection state, unless the session was opened already
+        if self.state == ConnectionState::Initial {
+            self.state = ConnectionState::Startup;
+        }
+        let mut startup_user: Option<String> = None;
+        
+        // Whether the client has been answered up to a ReadyForQuery, so no
+        // extended protocol batch is half processed
+        let mut ready_for_query = true;
         
         debug!("Handling connection from {}", self.addr);
         
@@ -220,14 +621,36 @@ impl ClientConnection {
             }
             
             // Read a message from the client
-            let frontend_message = match Self::read_frontend_message_with_timeout(
+            let read = Self::read_frontend_message_with_timeout(
                 &mut self.socket,
+                &mut self.read_buffer,
                 &self.parser,
+                self.auth_handler.expected_password_message(),
                 timeout_duration,
                 &self.addr
-            ).await {
-                Ok(message) => message,
-                Err(e) => {
+            );
+            
+            // While draining, sessions close between transactions; one in a
+            // transaction or COPY keeps running until it commits or rolls back
+            let between_transactions = ready_for_query
+                && self.transaction_status == TransactionStatus::Idle
+                && self.copy_in.is_none();
+            let result = match self.shutdown.as_mut() {
+                Some(shutdown) if between_transactions => tokio::select! {
+                    result = read => Some(result),
+                    Ok(_) = shutdown.wait_for(|draining| *draining) => None,
+                },
+                _ => Some(read.await),
+            };
+            
+            let frontend_message = match result {
+                Some(Ok(message)) => message,
+                None => {
+                    debug!("Closing connection to {} for shutdown", self.addr);
+                    self.write_shutdown_notice().await?;
+                    break;
+                }
+                Some(Err(e)) => {
                     // Handle error
                     if let ProxyError::ConnectionClosed = e {
                         debug!("Connection closed by client: {}", self.addr);
@@ -239,18 +662,23 @@ impl ClientConnection {
                 }
             };
             
+            // Encryption is negotiated on the raw socket before startup
+            if let FrontendMessage::SSLRequest = frontend_message {
+                if let Err(e) = self.negotiate_tls().await {
+                    error!("TLS negotiation with {} failed: {}", self.addr, e);
+                    return Err(e);
+                }
+                continue;
+            }
+            
+            if let FrontendMessage::Startup { parameters, .. } = &frontend_message {
+                startup_user = parameters.get("user").cloned();
+            }
+            
+            let authenticating = self.backend.is_none();
+            
             // Process message and get backend messages
-            let backend_messages = match process_message(
-                frontend_message, 
-                &mut self.pg_client, 
-                &mut self.auth_handler, 
-                &mut self.validator, 
-                &self.transaction_manager, 
-                &self.config, 
-                &mut self.stats, 
-                &mut self.state,
-                &mut transaction_status
-            ).await {
+            let backend_messages = match self.process_message_internal(frontend_message).await {
                 Ok(messages) => messages,
                 Err(e) => {
                     error!("Error processing message from {}: {}", self.addr, e);
@@ -268,6 +696,25 @@ impl ClientConnection {
                 }
             };
             
+            // A backend session means authentication just succeeded
+            if authenticating && self.backend.is_some() {
+                if let Err(e) = self.admit_user(startup_user.as_deref()) {
+                    if let Err(write_err) = Self::write_error_response(
+                        &mut self.socket, 
+                        &e.to_string(), 
+                        &self.formatter
+                    ).await {
+                        error!("Failed to write error response to {}: {}", self.addr, write_err);
+                    }
+                    
+                    return Err(e);
+                }
+            }
+            
+            if let Some(last) = backend_messages.last() {
+                ready_for_query = matches!(last, BackendMessage::ReadyForQuery(_));
+            }
+            
             // Write backend messages to client
             if let Err(e) = Self::write_backend_messages(
                 &mut self.socket, 
@@ -291,17 +738,146 @@ impl ClientConnection {
         Ok(())
     }
 
+    /// Tell the client its session is being closed because the server is stopping
+    async fn write_shutdown_notice(&mut self) -> Result<()> {
+        let fields = ErrorOrNoticeFields {
+            severity: Some("FATAL".to_string()),
+            code: Some("57P01".to_string()),
+            message: Some("terminating connection due to administrator command".to_string()),
+            ..Default::default()
+        };
+        
+        Self::write_backend_messages(
+            &mut self.socket,
+            vec![BackendMessage::ErrorResponse(fields)],
+            &self.formatter,
+            &mut self.stats,
+            &mut self.state
+        ).await?;
+        self.socket.flush().await?;
+        Ok(())
+    }
+    
+    /// Check the authenticated user against the security gateway's limits
+    fn admit_user(&self, startup_user: Option<&str>) -> Result<()> {
+        let gateway = match &self.security_gateway {
+            Some(gateway) => gateway,
+            None => return Ok(()),
+        };
+        
+        // Passthrough and certificate authentication establish the user
+        // themselves; otherwise it is the one named at startup
+        let user = self.auth_handler.authenticated_user().or(startup_user).unwrap_or_default();
+        if gateway.allow_user(self.addr, user) {
+            Ok(())
+        } else {
+            Err(ProxyError::Security(format!("Too many connections for user {}", user)))
+        }
+    }
+    
+    /// Screen a statement against the security gateway before it reaches
+    /// the backend, returning the answer to a rejected one
+    ///
+    /// A statement is checked for injection when it's sent, so a simple query
+    /// or a Parse, and charged against the cost budget each time it runs, so
+    /// a simple query or the first Execute of a portal.
+    fn screen(&mut self, message: &FrontendMessage) -> Option<Vec<BackendMessage>> {
+        self.security_gateway.as_ref()?;
+        match message {
+            FrontendMessage::Query(query) => {
+                let fields = self.screen_injection(query).or_else(|| self.screen_cost(query))?;
+                if self.transaction_status == TransactionStatus::InTransaction {
+                    self.transaction_status = TransactionStatus::Failed;
+                }
+                Some(vec![BackendMessage::ErrorResponse(fields), BackendMessage::ReadyForQuery(self.transaction_status)])
+            }
+            FrontendMessage::Parse { query, .. } if !self.extended_state.is_skipping_until_sync() => {
+                let fields = self.screen_injection(query)?;
+                Some(extended_error(fields, &mut self.extended_state, &mut self.transaction_status))
+            }
+            FrontendMessage::Execute { portal, .. }
+                if !self.extended_state.is_skipping_until_sync() && !self.extended_state.is_started(portal) =>
+            {
+                // An unknown portal is answered when the Execute is processed
+                let query = self.extended_state.portal_statement(portal)?.query.clone();
+                let fields = self.screen_cost(&query)?;
+                Some(extended_error(fields, &mut self.extended_state, &mut self.transaction_status))
+            }
+            _ => None,
+        }
+    }
+    
+    /// Check a statement for SQL injection, returning the error to answer it
+    /// with if it's rejected
+    fn screen_injection(&self, query: &str) -> Option<ErrorOrNoticeFields> {
+        let gateway = self.security_gateway.as_ref()?;
+        if gateway.analyze_query(self.addr, query) {
+            return None;
+        }
+        Some(error_fields("42501", "Query rejected as a possible SQL injection"))
+    }
+    
+    /// Charge a statement against the client's query cost budget, returning
+    /// the error to answer it with if the budget is exhausted
+    ///
+    /// Statements that analyze cleanly also count towards the client's traffic pattern.
+    fn screen_cost(&mut self, query: &str) -> Option<ErrorOrNoticeFields> {
+        let gateway = self.security_gateway.as_ref()?;
+        
+        // Statements that fail analysis are left for the backend to reject
+        let metadata = self.analyzer.analyze(query).ok()?;
+        gateway.record_query_type(self.addr, &metadata.query_type);
+        if gateway.allow_query_cost(self.addr, metadata.complexity_score) {
+            return None;
+        }
+        Some(error_fields("53000", "Query cost budget exhausted, retry later"))
+    }
+    
+    /// Answer an `SSLRequest`, upgrading the socket if TLS is enabled
+    async fn negotiate_tls(&mut self) -> Result<()> {
+        self.validator.validate_frontend_message(&FrontendMessage::SSLRequest, &self.state)?;
+        if self.socket.is_tls() {
+            return Err(ProxyError::Protocol("SSLRequest received on an encrypted connection".to_string()));
+        }
+        
+        let acceptor = self.tls_acceptor.clone();
+        let response = self.formatter.format_backend_message(&BackendMessage::SSLResponse(acceptor.is_some()))?;
+        self.socket.write_all(&response).await?;
+        self.socket.flush().await?;
+        
+        match acceptor {
+            Some(acceptor) => {
+                // Bytes sent before the handshake would be trusted as if encrypted
+                if !self.read_buffer.is_empty() {
+                    return Err(ProxyError::Protocol("Unencrypted data received after SSLRequest".to_string()));
+                }
+                debug!("Upgrading connection from {} to TLS", self.addr);
+                self.socket.accept_tls(&acceptor).await?;
+                
+                // Certificate authentication maps the client's certificate to a user
+                self.auth_handler.set_client_certificate_name(self.socket.peer_common_name());
+                Ok(())
+            }
+            None => {
+                debug!("TLS is disabled, continuing in plaintext with {}", self.addr);
+                Ok(())
+            }
+        }
+    }
+    
     /// Read a frontend message with timeout
     async fn read_frontend_message_with_timeout<R>(
         reader: &mut R,
+        buf: &mut BytesMut,
         parser: &MessageParser,
+        password_message: PasswordMessageKind,
         timeout_duration: Duration,
         addr: &SocketAddr,
     ) -> Result<FrontendMessage>
     where
         R: AsyncRead + Unpin,
     {
-        match timeout(timeout_duration, Self::read_message(reader, parser)).await {
+        match timeout(timeout_duration, Self::read_message(reader, buf, parser, password_message)).await {
             Ok(result) => result,
             Err(_) => {
                 warn!("Connection from {} timed out waiting for message", addr);
@@ -311,40 +887,242 @@ impl ClientConnection {
     }
     
     /// Read a message from the client
-    async fn read_message<R>(reader: &mut R, parser: &MessageParser) -> Result<FrontendMessage>
+    ///
+    /// `buf` holds bytes read past the previous message, since clients may
+    /// pipeline messages, such as a COPY's CopyData stream and CopyDone.
+    /// `password_message` says how to read a `p` message: GSSAPI and SASL
+    /// responses share the password message tag but carry other payloads.
+    /// A large CopyData message is returned in pieces as its bytes arrive,
+    /// which is allowed since CopyData boundaries carry no meaning.
+    async fn read_message<R>(
+        reader: &mut R,
+        buf: &mut BytesMut,
+        parser: &MessageParser,
+        password_message: PasswordMessageKind,
+    ) -> Result<FrontendMessage>
     where
         R: AsyncRead + Unpin,
     {
-        let mut buf = BytesMut::with_capacity(4096);
-        loop {
-            let bytes_read = reader.read_buf(&mut buf).await?;
+        let length = loop {
+            if let Some(length) = parser.frontend_message_length(buf)? {
+                if buf.len() >= length {
+                    break length;
+                }
+                if buf[0] == b'd' {
+                    if buf.len() >= 5 + COPY_DATA_CHUNK_SIZE {
+                        return Ok(FrontendMessage::CopyData(split_copy_data_chunk(buf, length)));
+                    }
+                    buf.reserve(5 + COPY_DATA_CHUNK_SIZE - buf.len());
+                } else {
+                    buf.reserve(length - buf.len());
+                }
+            }
+            
+            let bytes_read = reader.read_buf(buf).await?;
             if bytes_read == 0 {
                 return Err(ProxyError::ConnectionClosed);
             }
-
-            match parser.parse_frontend_message(&buf.clone().freeze()) {
-                Ok(message) => return Ok(message),
-                Err(ProxyError::Incomplete) => continue,
-                Err(e) => return Err(e),
-            }
-        }
+        };
+        
+        let bytes = buf.split_to(length).freeze();
+        let parsed = match password_message {
+            PasswordMessageKind::GssResponse => parser.parse_gss_response(&bytes),
+            PasswordMessageKind::SaslInitialResponse => parser.parse_sasl_initial_response(&bytes),
+            PasswordMessageKind::SaslResponse => parser.parse_sasl_response(&bytes),
+            PasswordMessageKind::Password => parser.parse_frontend_message(&bytes),
+        };
+        
+        // The message is complete, so a parser wanting more means it's malformed
+        parsed.map_err(|e| match e {
+            ProxyError::Incomplete => ProxyError::Protocol("Truncated message".to_string()),
+            e => e,
+        })
     }
     
     /// Process a frontend message and return backend messages
     async fn process_message_internal(&mut self, message: FrontendMessage) -> Result<Vec<BackendMessage>> {
-        self.stats.messages_received += 1;
-        let mut transaction_status = TransactionStatus::Idle;
-        process_message(
+        if let Some(messages) = self.screen(&message) {
+            return Ok(messages);
+        }
+        
+        let forwarded = match self.intercept(&message).await {
+            Intercepted::Forward(forwarded) => forwarded,
+            Intercepted::Answer(messages) => return Ok(messages),
+        };
+        
+        let messages = process_message(
             message,
-            &mut self.pg_client,
+            &mut self.backend,
+            self.backend_pool.as_ref(),
+            &self.cancel_registry,
+            &mut self.cancel_registration,
             &mut self.auth_handler,
             &mut self.validator,
             &self.transaction_manager,
+            &mut self.extended_state,
+            &mut self.session_affinity,
+            &mut self.copy_in,
+            &mut self.auth_relay,
             &self.config,
             &mut self.stats,
             &mut self.state,
-            &mut transaction_status
-        ).await
+            &mut self.transaction_status
+        ).await?;
+        
+        if let Some(forwarded) = forwarded {
+            self.observe_response(&forwarded, &messages).await;
+        }
+        Ok(messages)
+    }
+    
+    /// Run a query or extended-protocol message through the interception manager
+    ///
+    /// The manager keeps its own statement and portal state, and checks each
+    /// statement before it runs, recording it for verification. It may answer
+    /// a deterministic read from its result cache. A message the manager fails
+    /// to process still runs, for the backend to judge.
+    async fn intercept(&mut self, message: &FrontendMessage) -> Intercepted {
+        let Some(interception) = self.interception.as_mut() else {
+            return Intercepted::Forward(None);
+        };
+        
+        // An aborted transaction answers every statement with an error until
+        // it's rolled back, cached or not
+        if self.transaction_status == TransactionStatus::Failed {
+            return Intercepted::Forward(None);
+        }
+        let result = match message {
+            FrontendMessage::Query(query) => interception.process_query(query).await.map(Some),
+            FrontendMessage::Parse { .. }
+            | FrontendMessage::Bind { .. }
+            | FrontendMessage::Describe { .. }
+            | FrontendMessage::Execute { .. }
+            | FrontendMessage::Close { .. }
+            | FrontendMessage::Sync
+            | FrontendMessage::Flush if !self.extended_state.is_skipping_until_sync() => {
+                interception.process_extended_message(message).await
+            }
+            _ => return Intercepted::Forward(None),
+        };
+        let result = match result {
+            Ok(Some(result)) => result,
+            Ok(None) => return Intercepted::Forward(None),
+            Err(e) => {
+                warn!("Failed to intercept message from {}: {}", self.addr, e);
+                return Intercepted::Forward(None);
+            }
+        };
+        
+        // A portal's columns were described already, and an extended batch
+        // is answered with ReadyForQuery at its Sync
+        let extended = !matches!(message, FrontendMessage::Query(_));
+        let portal = match message {
+            FrontendMessage::Execute { portal, .. } => Some(portal.as_str()),
+            _ => None,
+        };
+        let answer = |messages: Vec<BackendMessage>| -> Vec<BackendMessage> {
+            messages.into_iter()
+                .filter(|message| match message {
+                    BackendMessage::RowDescription(_) => !extended,
+                    BackendMessage::ReadyForQuery(_) => false,
+                    _ => true,
+                })
+                .collect()
+        };
+        
+        let answered: std::result::Result<Vec<BackendMessage>, ErrorOrNoticeFields> = match result.action {
+            QueryAction::Forward => {
+                let Some(metadata) = result.metadata else {
+                    return Intercepted::Forward(None);
+                };
+                let (params, columns) = match portal {
+                    Some(portal) => (
+                        self.extended_state.execute(portal).map(|statement| statement.params).unwrap_or_default(),
+                        self.extended_state.portal_statement(portal).map(|statement| statement.columns.clone()),
+                    ),
+                    None => (Vec::new(), None),
+                };
+                return Intercepted::Forward(Some(Forwarded { metadata, params, columns }));
+            }
+            QueryAction::Cached => {
+                let Some(cached) = result.cached_result else {
+                    return Intercepted::Forward(None);
+                };
+                debug!("Answering {} from the result cache", self.addr);
+                
+                // The portal has run, so executing it again returns no rows
+                if let Some(portal) = portal {
+                    let command_tag = cached.messages.iter().find_map(|message| match message {
+                        BackendMessage::CommandComplete(tag) => Some(tag.clone()),
+                        _ => None,
+                    });
+                    let started = PortalResult { command_tag: command_tag.unwrap_or_default(), ..Default::default() };
+                    if let Err(e) = self.extended_state.start(portal, started) {
+                        warn!("Failed to record cached run of portal '{}': {}", portal, e);
+                    }
+                }
+                Ok(answer(cached.messages))
+            }
+            QueryAction::Handle => {
+                let (Some(query), Some(metadata)) = (&result.transformed_query, &result.metadata) else {
+                    return Intercepted::Forward(None);
+                };
+                match interception.execute_special_query(query, metadata) {
+                    Ok(messages) => Ok(answer(messages)),
+                    Err(e) => Err(error_fields("XX000", e.to_string())),
+                }
+            }
+            QueryAction::Reject => {
+                warn!("Rejecting statement from {}: complex query rate limit exceeded", self.addr);
+                Err(error_fields("53000", "Complex query rate limit exceeded, retry later"))
+            }
+        };
+        
+        Intercepted::Answer(match answered {
+            Ok(messages) if extended => messages,
+            Err(fields) if extended => extended_error(fields, &mut self.extended_state, &mut self.transaction_status),
+            Ok(mut messages) => {
+                messages.push(BackendMessage::ReadyForQuery(self.transaction_status));
+                messages
+            }
+            Err(fields) => {
+                if self.transaction_status == TransactionStatus::InTransaction {
+                    self.transaction_status = TransactionStatus::Failed;
+                }
+                vec![BackendMessage::ErrorResponse(fields), BackendMessage::ReadyForQuery(self.transaction_status)]
+            }
+        })
+    }
+    
+    /// Show the interception manager the backend's answer to a statement it analyzed
+    ///
+    /// A result the statement returned whole outside a transaction is offered
+    /// to the result cache, described the way a simple query's result is.
+    async fn observe_response(&mut self, forwarded: &Forwarded, messages: &[BackendMessage]) {
+        let Some(interception) = self.interception.as_mut() else {
+            return;
+        };
+        for message in messages {
+            if let Err(e) = interception.process_response(message, Some(&forwarded.metadata)).await {
+                warn!("Failed to process response to {}: {}", self.addr, e);
+            }
+        }
+        
+        // A read inside a transaction may see its uncommitted writes
+        if self.transaction_status != TransactionStatus::Idle
+            || messages.iter().any(|message| matches!(message, BackendMessage::ErrorResponse(_)))
+        {
+            return;
+        }
+        let Some(end) = messages.iter().position(|message| matches!(message, BackendMessage::CommandComplete(_))) else {
+            return;
+        };
+        let mut result = Vec::with_capacity(end + 2);
+        if let Some(columns) = forwarded.columns.as_ref().filter(|columns| !columns.is_empty()) {
+            result.push(BackendMessage::RowDescription(columns.clone()));
+        }
+        result.extend_from_slice(&messages[..=end]);
+        interception.cache_result(&forwarded.metadata, &forwarded.params, result);
     }
     
     /// Write an error response to the client
@@ -376,8 +1154,9 @@ impl ClientConnection {
     where
         W: AsyncWrite + Unpin,
     {
-        let bytes = formatter.format_backend_message(message)?;
-        writer.write_all(&bytes).await?;
+        for chunk in formatter.format_backend_message_chunks(message)? {
+            writer.write_all(&chunk).await?;
+        }
         Ok(())
     }
     
@@ -427,6 +1206,15 @@ pub struct ConnectionManager {
     /// Transaction manager
     transaction_manager: Arc<Mutex<TransactionManager>>,
     
+    /// Acceptor shared by all connections, if TLS is enabled
+    tls_acceptor: Option<TlsAcceptor>,
+    
+    /// Backend connections shared by all client sessions
+    backend_pool: BackendPool,
+    
+    /// Cancel keys shared by all client sessions
+    cancel_registry: CancelRegistry,
+    
     /// Active connections
     active_connections: usize,
 }
@@ -435,10 +1223,15 @@ impl ConnectionManager {
     /// Create a new connection manager
     pub fn new(config: ProxyConfig) -> Result<Self> {
         let transaction_manager = Arc::new(Mutex::new(TransactionManager::new()));
+        let tls_acceptor = tls::optional_tls_acceptor(config.tls_config.as_ref())?;
+        let backend_pool = BackendPool::new(&config)?;
         
         Ok(Self {
             config,
             transaction_manager,
+            tls_acceptor,
+            backend_pool,
+            cancel_registry: CancelRegistry::new(),
             active_connections: 0,
         })
     }
@@ -456,7 +1249,9 @@ impl ConnectionManager {
             addr,
             self.config.clone(),
             self.transaction_manager.clone(),
-        );
+        ).with_tls(self.tls_acceptor.clone())
+            .with_backend_pool(Some(self.backend_pool.clone()))
+            .with_cancel_registry(self.cancel_registry.clone());
         
         // Handle connection
         connection.handle_connection().await
@@ -478,10 +1273,17 @@ fn update_state_from_transaction_status(
 /// Process a frontend message and generate backend messages
 async fn process_message(
     message: FrontendMessage,
-    pg_client: &mut Option<ClientWrapper>,
+    backend: &mut Option<Backend>,
+    backend_pool: Option<&BackendPool>,
+    cancel_registry: &CancelRegistry,
+    cancel_registration: &mut Option<CancelRegistration>,
     auth_handler: &mut AuthHandler,
     validator: &mut ProtocolValidator,
     transaction_manager: &Arc<Mutex<TransactionManager>>,
+    extended_state: &mut ExtendedQueryState,
+    session_affinity: &mut SessionAffinity<ClientWrapper>,
+    copy_in: &mut Option<CopyIn>,
+    auth_relay: &mut Option<BackendAuthRelay>,
     config: &ProxyConfig,
     stats: &mut ConnectionStats,
     state: &mut ConnectionState,
@@ -496,14 +1298,89 @@ async fn process_message(
     // Process message based on type
     match message {
         FrontendMessage::Startup { version_major, version_minor, parameters } => {
-            auth_handler.handle_startup(version_major, version_minor, &parameters)
-        }
-        FrontendMessage::Password(password) => {
-            let auth_response = auth_handler.handle_password(password).await?;
-            
-            // Check if authentication was successful by looking for AuthenticationOk in the response
-            let auth_successful = auth_response.iter().any(|msg| {
-                if let BackendMessage::Authentication(auth_req) = msg {
+            match config.auth_config.default_method {
+                AuthMethod::GssPassthrough => {
+                    // The backend drives the exchange; relay its first request
+                    auth_handler.get_initial_auth_request();
+                    let mut relay = BackendAuthRelay::connect(config.backend_addr, &parameters).await?;
+                    let request = relay.receive(&MessageParser::new()).await?;
+                    let response = auth_handler.relay_backend_auth(request, relay.user())?;
+                    
+                    // A backend trusting the client accepts it without an exchange
+                    if auth_handler.get_state() == AuthState::Completed {
+                        let user = relay.user().to_string();
+                        return open_backend_session(
+                            response, backend, backend_pool, cancel_registry, cancel_registration, session_affinity, config, state, Some(&user)
+                        ).await;
+                    }
+                    
+                    *auth_relay = Some(relay);
+                    *state = ConnectionState::Authenticating;
+                    Ok(response)
+                }
+                AuthMethod::Certificate => {
+                    auth_handler.get_initial_auth_request();
+                    
+                    // Without a certificate from the TLS handshake this fails closed
+                    let common_name = auth_handler.client_certificate_name().map(str::to_string);
+                    let auth_response = auth_handler.handle_client_certificate(common_name.as_deref())?;
+                    let role = auth_handler.authenticated_user().map(str::to_string);
+                    open_backend_session(auth_response, backend, backend_pool, cancel_registry, cancel_registration, session_affinity, config, state, role.as_deref()).await
+                }
+                AuthMethod::ScramSha256 => {
+                    *state = ConnectionState::Authenticating;
+                    auth_handler.start_scram(&parameters)
+                }
+                _ => auth_handler.handle_startup(version_major, version_minor, &parameters),
+            }
+        }
+        FrontendMessage::GssResponse(data) => {
+            let relay = auth_relay.as_mut()
+                .ok_or_else(|| ProxyError::Auth("No GSSAPI exchange in progress".to_string()))?;
+            
+            // Forward the token unchanged and relay the backend's answer
+            let bytes = auth_handler.relay_client_auth(&FrontendMessage::GssResponse(data))?;
+            relay.send(&bytes).await?;
+            let reply = relay.receive(&MessageParser::new()).await?;
+            let user = relay.user().to_string();
+            let auth_response = auth_handler.relay_backend_auth(reply, &user)?;
+            
+            let finished = auth_response.iter().any(|msg| {
+                matches!(msg, BackendMessage::Authentication(crate::protocol::message::AuthenticationRequest::Ok)
+                    | BackendMessage::ErrorResponse(_))
+            });
+            if !finished {
+                return Ok(auth_response);
+            }
+            
+            // The exchange is over; its connection is no longer needed
+            *auth_relay = None;
+            
+            if auth_handler.get_state() == AuthState::Completed && backend.is_none() {
+                return open_backend_session(
+                    auth_response, backend, backend_pool, cancel_registry, cancel_registration, session_affinity, config, state, Some(&user)
+                ).await;
+            }
+            
+            Ok(auth_response)
+        }
+        FrontendMessage::SaslInitialResponse { .. } | FrontendMessage::SaslResponse(_) => {
+            let auth_response = auth_handler.handle_auth_message(&message)?;
+            
+            if auth_handler.get_state() == AuthState::Completed && backend.is_none() {
+                return open_backend_session(
+                    auth_response, backend, backend_pool, cancel_registry, cancel_registration, session_affinity, config, state, None
+                ).await;
+            }
+            
+            Ok(auth_response)
+        }
+        FrontendMessage::Password(password) => {
+            let auth_response = auth_handler.handle_password(password).await?;
+            
+            // Check if authentication was successful by looking for AuthenticationOk in the response
+            let auth_successful = auth_response.iter().any(|msg| {
+                if let BackendMessage::Authentication(auth_req) = msg {
                     matches!(auth_req, crate::protocol::message::AuthenticationRequest::Ok)
                 } else {
                     false
@@ -511,94 +1388,10 @@ async fn process_message(
             });
             
             // If authentication was successful, connect to PostgreSQL
-            if auth_successful && pg_client.is_none() {
-                // Connect to PostgreSQL
-                debug!("Authentication successful, connecting to PostgreSQL backend");
-                
-                // Extract host and port from backend_addr
-                let host = config.backend_addr.ip().to_string();
-                let port = config.backend_addr.port();
-                
-                // Use the database credentials configured on the command line
-                // These are stored in the config object
-                let username = config.db_user.clone().unwrap_or_else(|| {
-                    // Fall back to the first user in the auth config if available
-                    config.auth_config.users.keys()
-                        .next()
-                        .cloned()
-                        .unwrap_or_else(|| "postgres".to_string())
-                });
-                
-                let password = config.db_password.clone().unwrap_or_else(|| {
-                    // Fall back to the first password in the auth config if available
-                    config.auth_config.users.values()
-                        .next()
-                        .cloned()
-                        .unwrap_or_else(|| "postgres".to_string())
-                });
-                
-                let database = config.db_name.clone().unwrap_or_else(|| {
-                    "postgres".to_string()
-                });
-                
-                // Create connection string from config
-                let conn_string = format!(
-                    "host={} port={} user={} password={} dbname={}",
-                    host, port, username, password, database
-                );
-                
-                debug!("Connecting to PostgreSQL with connection string: host={} port={} user={} dbname={}", 
-                       host, port, username, database);
-                
-                // Parse connection string and connect
-                match connect_to_postgres(&conn_string).await {
-                    Ok(client) => {
-                        debug!("Connected to PostgreSQL backend");
-                        *pg_client = Some(client);
-                        // Update state to Ready
-                        *state = ConnectionState::Ready;
-                        
-                        // Add necessary startup messages to the response
-                        let mut response = auth_response;
-                        
-                        // Add BackendKeyData message (with dummy values)
-                        response.push(BackendMessage::BackendKeyData {
-                            process_id: 1000, // Dummy process ID
-                            secret_key: 12345, // Dummy secret key
-                        });
-                        
-                        // Add ParameterStatus messages that clients expect
-                        response.push(BackendMessage::ParameterStatus {
-                            name: "server_version".to_string(),
-                            value: "14.0".to_string(),
-                        });
-                        response.push(BackendMessage::ParameterStatus {
-                            name: "client_encoding".to_string(),
-                            value: "UTF8".to_string(),
-                        });
-                        response.push(BackendMessage::ParameterStatus {
-                            name: "DateStyle".to_string(),
-                            value: "ISO, MDY".to_string(),
-                        });
-                        response.push(BackendMessage::ParameterStatus {
-                            name: "integer_datetimes".to_string(),
-                            value: "on".to_string(),
-                        });
-                        response.push(BackendMessage::ParameterStatus {
-                            name: "standard_conforming_strings".to_string(),
-                            value: "on".to_string(),
-                        });
-                        
-                        // Add ReadyForQuery message
-                        response.push(BackendMessage::ReadyForQuery(TransactionStatus::Idle));
-                        
-                        return Ok(response);
-                    }
-                    Err(e) => {
-                        error!("Failed to connect to PostgreSQL backend: {}", e);
-                        return Err(ProxyError::Database(format!("Failed to connect to PostgreSQL backend: {}", e)));
-                    }
-                }
+            if auth_successful && backend.is_none() {
+                return open_backend_session(
+                    auth_response, backend, backend_pool, cancel_registry, cancel_registration, session_affinity, config, state, None
+                ).await;
             }
             
             Ok(auth_response)
@@ -607,19 +1400,48 @@ async fn process_message(
             // Process query
             debug!("Processing query: {}", query);
             
-            // Update transaction status if needed
-            if query.trim().to_uppercase().starts_with("BEGIN") {
-                *transaction_status = TransactionStatus::InTransaction;
-            } else if query.trim().to_uppercase().starts_with("COMMIT") {
-                *transaction_status = TransactionStatus::Idle;
-            } else if query.trim().to_uppercase().starts_with("ROLLBACK") {
-                *transaction_status = TransactionStatus::Idle;
+            if let Some(mut messages) = failed_transaction_response(&query, session_affinity, transaction_status).await? {
+                messages.push(BackendMessage::ReadyForQuery(*transaction_status));
+                return Ok(messages);
             }
             
+            // Update transaction status if needed
+            update_transaction_status(transaction_status, &query);
+            
             // If we have a client, forward the query
-            if let Some(client) = pg_client {
-                // Execute query
-                let rows = match client.inner().query(&query, &[]).await {
+            if let Some(backend) = backend {
+                // Statements inside a transaction stay on the session that ran BEGIN
+                let client = match session_affinity.pinned() {
+                    Some(session) => session.clone(),
+                    None => {
+                        let session = backend.acquire().await?;
+                        session_affinity.session_for(*transaction_status, || session)
+                    }
+                };
+                
+                // Later statements may rely on state this one leaves on the session
+                if changes_session_state(&query) {
+                    session_affinity.hold(client.clone());
+                }
+                
+                // A COPY FROM STDIN continues with the client's CopyData stream
+                if let Some(format) = copy_from_stdin(&query) {
+                    let sink = client.inner().copy_in::<_, Bytes>(query.as_str()).await
+                        .map_err(|e| ProxyError::Database(format!("Database error: {}", e)))?;
+                    *copy_in = Some(CopyIn { sink: Box::pin(sink), session: client });
+                    return Ok(vec![BackendMessage::CopyInResponse {
+                        format: format.code(),
+                        column_formats: Vec::new(),
+                    }]);
+                }
+                
+                // Execute query; a CancelRequest for this session reaches it meanwhile
+                let running = cancel_registration.as_ref().map(|registration| registration.running(&client));
+                let result = client.inner().query(&query, &[]).await;
+                drop(running);
+                session_affinity.release_if_idle(*transaction_status);
+                
+                let rows = match result {
                     Ok(rows) => rows,
                     Err(e) => {
                         // Convert tokio_postgres::Error to ProxyError
@@ -627,62 +1449,7 @@ async fn process_message(
                     }
                 };
                 
-                // Generate response
-                let mut messages = Vec::new();
-                
-                // Add row descriptions
-                if !rows.is_empty() {
-                    let columns = rows[0].columns();
-                    let field_descriptions = columns.iter().map(|col| {
-                        FieldDescription {
-                            name: col.name().to_string(),
-                            table_oid: col.table_oid().unwrap_or(0) as i32,
-                            column_id: col.column_id().unwrap_or(0) as i16,
-                            data_type_oid: col.type_().oid() as i32,
-                            data_type_size: 0, // Not available from tokio-postgres
-                            type_modifier: -1, // Not available from tokio-postgres
-                            format_code: 0, // Text format
-                        }
-                    }).collect::<Vec<_>>();
-                    
-                    messages.push(BackendMessage::RowDescription(field_descriptions));
-                    
-                    // Store row count before consuming rows
-                    let row_count = rows.len();
-                    
-                    // Add data rows
-                    for row in rows {
-                        let mut data_row = Vec::new();
-                        
-                        for i in 0..row.len() {
-                            // Try to get the value as a string
-                            match row.try_get::<_, String>(i) {
-                                Ok(val) => data_row.push(Some(Bytes::from(val))),
-                                Err(_) => {
-                                    // Try as an integer
-                                    if let Ok(val) = row.try_get::<_, i32>(i) {
-                                        data_row.push(Some(Bytes::from(val.to_string())));
-                                    } else if let Ok(val) = row.try_get::<_, i64>(i) {
-                                        data_row.push(Some(Bytes::from(val.to_string())));
-                                    } else if let Ok(val) = row.try_get::<_, bool>(i) {
-                                        data_row.push(Some(Bytes::from(val.to_string())));
-                                    } else {
-                                        // NULL or unsupported type
-                                        data_row.push(None);
-                                    }
-                                }
-                            }
-                        }
-                        
-                        messages.push(BackendMessage::DataRow(data_row));
-                    }
-                    
-                    // Add command complete
-                    messages.push(BackendMessage::CommandComplete(format!("SELECT {}", row_count)));
-                } else {
-                    // For non-SELECT queries
-                    messages.push(BackendMessage::CommandComplete(query.split_whitespace().next().unwrap_or("").to_string()));
-                }
+                let mut messages = query_result_messages(&query, rows);
                 
                 // Add ready for query
                 messages.push(BackendMessage::ReadyForQuery(*transaction_status));
@@ -699,52 +1466,312 @@ async fn process_message(
             Ok(vec![])
         }
         FrontendMessage::SSLRequest => {
-            // Handle SSL request - respond with 'N' (SSL not supported) for now
+            // The connection answers and upgrades SSLRequests itself, since
+            // that needs the raw socket; anything reaching here is declined
             debug!("SSL request received, responding with 'N'");
             Ok(vec![BackendMessage::SSLResponse(false)])
         }
         FrontendMessage::CancelRequest { process_id, secret_key } => {
-            // Handle cancel request
-            debug!("Cancel request received for process ID {} with secret key {}", process_id, secret_key);
-            // Pass through to database
-            if let Some(client) = pg_client {
-                // In a real implementation, we would need to handle this properly
-                // For now, just acknowledge it
-                Ok(vec![])
-            } else {
-                // No client connection
-                Ok(vec![])
+            // Sent on its own connection, which closes without a reply
+            debug!("Cancel request received for process ID {}", process_id);
+            if let Err(e) = cancel_registry.cancel(process_id, secret_key).await {
+                warn!("Failed to forward cancel request for process ID {}: {}", process_id, e);
             }
+            *state = ConnectionState::Closing;
+            Ok(vec![])
+        }
+        // After an error the rest of an extended-protocol batch is ignored
+        FrontendMessage::Parse { .. }
+        | FrontendMessage::Bind { .. }
+        | FrontendMessage::Describe { .. }
+        | FrontendMessage::Execute { .. }
+        | FrontendMessage::Close { .. } if extended_state.is_skipping_until_sync() => {
+            debug!("Ignoring message until Sync");
+            Ok(vec![])
         }
-        // Add handling for extended protocol messages
         FrontendMessage::Parse { name, query, param_types } => {
             debug!("Parse message received: {}", query);
-            // Pass through to database if we have a client
-            if let Some(_client) = pg_client {
-                // In a real implementation, we would pass this to the database
-                // For now, just respond with ParseComplete
-                Ok(vec![BackendMessage::ParseComplete])
-            } else {
-                Err(ProxyError::Database("Not connected to database".to_string()))
+            let Some(backend) = backend else {
+                return Err(ProxyError::Database("Not connected to database".to_string()));
+            };
+            
+            // The backend checks the statement and resolves its parameter and
+            // result types; it is prepared again on the session each Execute runs on
+            let client = match session_affinity.pinned() {
+                Some(session) => session.clone(),
+                None => backend.acquire().await?,
+            };
+            let types: Vec<Type> = param_types.iter().copied().map(param_type).collect();
+            match client.inner().prepare_typed(&query, &types).await {
+                Ok(statement) => {
+                    extended_state.parse(&name, &query, &param_types);
+                    extended_state.describe(
+                        &name,
+                        statement.params().iter().map(|param| param.oid() as i32).collect(),
+                        statement.columns().iter().map(field_description).collect(),
+                    );
+                    Ok(vec![BackendMessage::ParseComplete])
+                }
+                Err(e) => Ok(extended_error(backend_error_fields(&e), extended_state, transaction_status)),
             }
         }
-        FrontendMessage::Bind { .. } => {
+        FrontendMessage::Bind { portal, statement, param_formats, param_values, .. } => {
             debug!("Bind message received");
-            // In a real implementation, we would pass this to the database
-            // For now, just respond with BindComplete
-            Ok(vec![BackendMessage::BindComplete])
+            
+            // The values reach the backend, in the client's formats, when the portal runs
+            let required = extended_state.statement(&statement).map(|prepared| prepared.param_types.len());
+            let error = match required {
+                None => Some(error_fields("26000", format!("prepared statement \"{}\" does not exist", statement))),
+                Some(required) if required != param_values.len() => Some(error_fields("08P01", format!(
+                    "bind message supplies {} parameters, but prepared statement \"{}\" requires {}",
+                    param_values.len(), statement, required
+                ))),
+                Some(_) => None,
+            };
+            if let Some(fields) = error {
+                return Ok(extended_error(fields, extended_state, transaction_status));
+            }
+            
+            // Decode the bound values now so Execute can be replayed with them
+            match extended_state.bind(&portal, &statement, &param_formats, &param_values) {
+                Ok(()) => Ok(vec![BackendMessage::BindComplete]),
+                Err(e) => Ok(extended_error(error_fields("08P01", e.to_string()), extended_state, transaction_status)),
+            }
         }
-        FrontendMessage::Execute { .. } => {
+        FrontendMessage::Describe { object_type, name } => {
+            debug!("Describe message received for {}", name);
+            
+            // Statements were described by the backend when parsed
+            let (parameters, statement) = match object_type {
+                b'S' => (true, extended_state.statement(&name)),
+                b'P' => (false, extended_state.portal_statement(&name)),
+                other => {
+                    return Err(ProxyError::Protocol(format!("Invalid Describe target '{}'", other as char)));
+                }
+            };
+            let Some(statement) = statement else {
+                let fields = match object_type {
+                    b'S' => error_fields("26000", format!("prepared statement \"{}\" does not exist", name)),
+                    _ => error_fields("34000", format!("portal \"{}\" does not exist", name)),
+                };
+                return Ok(extended_error(fields, extended_state, transaction_status));
+            };
+            
+            let mut messages = Vec::new();
+            if parameters {
+                messages.push(BackendMessage::ParameterDescription(statement.param_types.clone()));
+            }
+            if statement.columns.is_empty() {
+                messages.push(BackendMessage::NoData);
+            } else {
+                messages.push(BackendMessage::RowDescription(statement.columns.clone()));
+            }
+            Ok(messages)
+        }
+        FrontendMessage::Execute { portal, max_rows } => {
             debug!("Execute message received");
-            // In a real implementation, we would pass this to the database
-            // For now, just respond with EmptyQueryResponse
-            Ok(vec![BackendMessage::EmptyQueryResponse])
+            
+            // The statement runs at the portal's first Execute. A row-limited
+            // query is opened as a backend cursor for later Executes to fetch
+            // from; any other result is kept whole, as PostgreSQL keeps it
+            if !extended_state.is_started(&portal) {
+                let statement = extended_state.execute(&portal)?;
+                debug!("Executing portal '{}': {} with {} bound parameter(s)", 
+                       portal, statement.query, statement.params.len());
+                
+                let Some(backend) = backend else {
+                    return Err(ProxyError::Database("Not connected to database".to_string()));
+                };
+                if let Some(messages) = failed_transaction_response(&statement.query, session_affinity, transaction_status).await? {
+                    if *transaction_status == TransactionStatus::Failed {
+                        extended_state.skip_until_sync();
+                    }
+                    return Ok(messages);
+                }
+                update_transaction_status(transaction_status, &statement.query);
+                let (param_types, params) = extended_state.backend_params(&portal)?;
+                
+                // Statements inside a transaction stay on the session that ran BEGIN
+                let client = match session_affinity.pinned() {
+                    Some(session) => session.clone(),
+                    None => {
+                        let session = backend.acquire().await?;
+                        session_affinity.session_for(*transaction_status, || session)
+                    }
+                };
+                if changes_session_state(&statement.query) {
+                    session_affinity.hold(client.clone());
+                }
+                
+                // A cursor only lives inside a transaction; outside a block
+                // the proxy opens one until Sync, like the implicit
+                // transaction PostgreSQL runs an extended-protocol batch in
+                let cursor = (max_rows > 0 && is_cursor_query(&statement.query))
+                    .then(|| extended_state.next_cursor_name());
+                if cursor.is_some()
+                    && *transaction_status == TransactionStatus::Idle
+                    && !session_affinity.is_pinned_until_sync()
+                {
+                    client.inner().execute("BEGIN", &[]).await
+                        .map_err(|e| ProxyError::Database(format!("Database error: {}", e)))?;
+                    session_affinity.pin_until_sync(client.clone());
+                }
+                
+                let running = cancel_registration.as_ref().map(|registration| registration.running(&client));
+                let result = match &cursor {
+                    Some(cursor) => {
+                        let declare = format!("DECLARE {} NO SCROLL CURSOR FOR {}", cursor, statement.query);
+                        query_bound(&client, &declare, &param_types, &params).await
+                    }
+                    None => query_bound(&client, &statement.query, &param_types, &params).await,
+                };
+                drop(running);
+                session_affinity.release_if_idle(*transaction_status);
+                
+                let rows = result.map_err(|e| ProxyError::Database(format!("Database error: {}", e)))?;
+                let mut result = PortalResult { cursor: cursor.clone(), ..Default::default() };
+                if cursor.is_none() {
+                    for message in query_result_messages(&statement.query, rows) {
+                        match message {
+                            BackendMessage::DataRow(values) => result.rows.push_back(values),
+                            BackendMessage::CommandComplete(tag) => result.command_tag = tag,
+                            // Columns are described by Describe, not Execute
+                            _ => {}
+                        }
+                    }
+                }
+                extended_state.start(&portal, result)?;
+            }
+            
+            if let Some(cursor) = extended_state.cursor(&portal) {
+                let client = session_affinity.pinned().cloned().ok_or_else(|| {
+                    ProxyError::Protocol(format!("Portal '{}' outlived its transaction", portal))
+                })?;
+                let fetch = match max_rows {
+                    max_rows if max_rows > 0 => format!("FETCH FORWARD {} FROM {}", max_rows, cursor),
+                    _ => format!("FETCH ALL FROM {}", cursor),
+                };
+                
+                let running = cancel_registration.as_ref().map(|registration| registration.running(&client));
+                let result = client.inner().query(fetch.as_str(), &[]).await;
+                drop(running);
+                
+                let rows = result.map_err(|e| ProxyError::Database(format!("Database error: {}", e)))?;
+                let fetched = rows.len();
+                let mut messages: Vec<_> = query_result_messages(&fetch, rows)
+                    .into_iter()
+                    .filter(|message| matches!(message, BackendMessage::DataRow(_)))
+                    .collect();
+                
+                // Fewer rows than asked for means the cursor reached its end
+                if max_rows > 0 && fetched == max_rows as usize {
+                    messages.push(BackendMessage::PortalSuspended);
+                } else {
+                    client.inner().execute(format!("CLOSE {}", cursor).as_str(), &[]).await
+                        .map_err(|e| ProxyError::Database(format!("Database error: {}", e)))?;
+                    extended_state.close_cursor(&portal);
+                    messages.push(BackendMessage::CommandComplete(format!("SELECT {}", fetched)));
+                }
+                return Ok(messages);
+            }
+            
+            let fetch = extended_state.fetch(&portal, max_rows)?;
+            let mut messages: Vec<_> = fetch.rows.into_iter().map(BackendMessage::DataRow).collect();
+            messages.push(match fetch.command_tag {
+                Some(tag) => BackendMessage::CommandComplete(tag),
+                None => BackendMessage::PortalSuspended,
+            });
+            Ok(messages)
+        }
+        FrontendMessage::Close { object_type, name } => {
+            debug!("Close message received for {}", name);
+            extended_state.close(object_type, &name);
+            Ok(vec![BackendMessage::CloseComplete])
+        }
+        FrontendMessage::CopyData(data) => {
+            // After a failed COPY the rest of the client's stream is ignored
+            let Some(copy) = copy_in.as_mut() else {
+                debug!("Ignoring CopyData outside of COPY FROM STDIN");
+                return Ok(vec![]);
+            };
+            
+            // Rows go straight to the backend; nothing is answered until CopyDone
+            match copy.sink.send(data).await {
+                Ok(()) => Ok(vec![]),
+                Err(e) => {
+                    *copy_in = None;
+                    Ok(copy_failed(e, transaction_status))
+                }
+            }
+        }
+        FrontendMessage::CopyDone => {
+            let Some(CopyIn { mut sink, session }) = copy_in.take() else {
+                debug!("Ignoring CopyDone outside of COPY FROM STDIN");
+                return Ok(vec![]);
+            };
+            
+            let result = sink.as_mut().finish().await;
+            
+            // The session goes back to the pool only once the COPY is over
+            drop(session);
+            
+            match result {
+                Ok(rows) => Ok(vec![
+                    BackendMessage::CommandComplete(format!("COPY {}", rows)),
+                    BackendMessage::ReadyForQuery(*transaction_status),
+                ]),
+                Err(e) => Ok(copy_failed(e, transaction_status)),
+            }
+        }
+        FrontendMessage::CopyFail(reason) => {
+            let Some(copy) = copy_in.take() else {
+                debug!("Ignoring CopyFail outside of COPY FROM STDIN");
+                return Ok(vec![]);
+            };
+            
+            // Dropping the unfinished sink makes tokio-postgres send CopyFail,
+            // so the backend loads none of the rows
+            drop(copy);
+            debug!("Client aborted COPY: {}", reason);
+            
+            if *transaction_status == TransactionStatus::InTransaction {
+                *transaction_status = TransactionStatus::Failed;
+            }
+            let fields = ErrorOrNoticeFields {
+                severity: Some("ERROR".to_string()),
+                code: Some("57014".to_string()),
+                message: Some(format!("COPY from stdin failed: {}", reason)),
+                ..Default::default()
+            };
+            Ok(vec![
+                BackendMessage::ErrorResponse(fields),
+                BackendMessage::ReadyForQuery(*transaction_status),
+            ])
         }
         FrontendMessage::Sync => {
             debug!("Sync message received");
+            extended_state.sync();
+            
+            // Outside a transaction block, Sync ends the implicit transaction
+            // and with it every portal
+            if let Some(session) = session_affinity.end_batch() {
+                if *transaction_status == TransactionStatus::Idle {
+                    session.inner().execute("COMMIT", &[]).await
+                        .map_err(|e| ProxyError::Database(format!("Database error: {}", e)))?;
+                }
+                session_affinity.release_if_idle(*transaction_status);
+            }
+            if *transaction_status == TransactionStatus::Idle {
+                extended_state.close_portals();
+            }
+            
             // Respond with ReadyForQuery
             Ok(vec![BackendMessage::ReadyForQuery(*transaction_status)])
         }
+        FrontendMessage::Flush => {
+            // Replies are written as soon as each message is processed
+            Ok(vec![])
+        }
         // For all other messages, log and pass through
         _ => {
             // Instead of rejecting, log the unknown message and continue
@@ -755,12 +1782,383 @@ async fn process_message(
     }
 }
 
-/// Connect to PostgreSQL and return a ClientWrapper
-async fn connect_to_postgres(connection_string: &str) -> Result<ClientWrapper> {
-    // Parse connection string
-    let config = connection_string.parse::<tokio_postgres::config::Config>()
-        .map_err(|e| ProxyError::Database(format!("Failed to parse connection string: {}", e)))?;
+/// Run `query` with parameter values forwarded as the client bound them
+///
+/// The statement is prepared with the client's declared parameter types, so
+/// the backend decodes each value by its type, in its own format.
+async fn query_bound(
+    client: &ClientWrapper,
+    query: &str,
+    param_types: &[Type],
+    params: &[RawParam],
+) -> std::result::Result<Vec<Row>, tokio_postgres::Error> {
+    let statement = client.inner().prepare_typed(query, param_types).await?;
+    let params: Vec<&(dyn ToSql + Sync)> = params.iter().map(|param| param as &(dyn ToSql + Sync)).collect();
+    client.inner().query(&statement, &params).await
+}
+
+/// Whether `query` can be opened as a cursor, so a row-limited portal is
+/// fetched from the backend a batch at a time
+///
+/// Other statements run to completion at their first Execute, as PostgreSQL
+/// runs them, and any rows they return are kept for later Executes.
+fn is_cursor_query(query: &str) -> bool {
+    let keyword = query.trim_start().split(|c: char| !c.is_ascii_alphabetic()).next().unwrap_or("");
+    ["SELECT", "VALUES", "TABLE"].iter().any(|cursor_keyword| keyword.eq_ignore_ascii_case(cursor_keyword))
+}
+
+/// Description of a result column, as the proxy sends its values
+fn field_description(column: &Column) -> FieldDescription {
+    FieldDescription {
+        name: column.name().to_string(),
+        table_oid: column.table_oid().unwrap_or(0) as i32,
+        column_id: column.column_id().unwrap_or(0) as i16,
+        data_type_oid: column.type_().oid() as i32,
+        data_type_size: 0, // Not available from tokio-postgres
+        type_modifier: -1, // Not available from tokio-postgres
+        format_code: 0, // Text format
+    }
+}
+
+/// RowDescription, DataRows and CommandComplete answering `query` with `rows`
+fn query_result_messages(query: &str, rows: Vec<tokio_postgres::Row>) -> Vec<BackendMessage> {
+    let mut messages = Vec::new();
+    
+    // Add row descriptions
+    if !rows.is_empty() {
+        let columns = rows[0].columns();
+        let field_descriptions = columns.iter().map(field_description).collect::<Vec<_>>();
+    
+        messages.push(BackendMessage::RowDescription(field_descriptions));
+    
+        // Store row count before consuming rows
+        let row_count = rows.len();
+    
+        // Add data rows
+        for row in rows {
+            let mut data_row = Vec::new();
+    
+            for i in 0..row.len() {
+                // Try to get the value as a string
+                match row.try_get::<_, String>(i) {
+                    Ok(val) => data_row.push(Some(Bytes::from(val))),
+                    Err(_) => {
+                        // Try as an integer
+                        if let Ok(val) = row.try_get::<_, i32>(i) {
+                            data_row.push(Some(Bytes::from(val.to_string())));
+                        } else if let Ok(val) = row.try_get::<_, i64>(i) {
+                            data_row.push(Some(Bytes::from(val.to_string())));
+                        } else if let Ok(val) = row.try_get::<_, bool>(i) {
+                            data_row.push(Some(Bytes::from(val.to_string())));
+                        } else {
+                            // NULL or unsupported type
+                            data_row.push(None);
+                        }
+                    }
+                }
+            }
+    
+            messages.push(BackendMessage::DataRow(data_row));
+        }
+    
+        // Add command complete
+        messages.push(BackendMessage::CommandComplete(format!("SELECT {}", row_count)));
+    } else {
+        // For non-SELECT queries
+        messages.push(BackendMessage::CommandComplete(query.split_whitespace().next().unwrap_or("").to_string()));
+    }
+    
+    messages
+}
+
+/// Error and ReadyForQuery ending a COPY the backend rejected
+///
+/// The backend's error is passed on as it is, so the client sees which row
+/// failed; an open transaction is now failed.
+fn copy_failed(error: tokio_postgres::Error, transaction_status: &mut TransactionStatus) -> Vec<BackendMessage> {
+    if *transaction_status == TransactionStatus::InTransaction {
+        *transaction_status = TransactionStatus::Failed;
+    }
+    
+    vec![
+        BackendMessage::ErrorResponse(backend_error_fields(&error)),
+        BackendMessage::ReadyForQuery(*transaction_status),
+    ]
+}
+
+/// Error fields passing on an error from the backend as it is
+fn backend_error_fields(error: &tokio_postgres::Error) -> ErrorOrNoticeFields {
+    match error.as_db_error() {
+        Some(db_error) => ErrorOrNoticeFields {
+            severity: Some("ERROR".to_string()),
+            code: Some(db_error.code().code().to_string()),
+            message: Some(db_error.message().to_string()),
+            detail: db_error.detail().map(str::to_string),
+            context: db_error.where_().map(str::to_string),
+            ..Default::default()
+        },
+        None => error_fields("XX000", format!("Database error: {}", error)),
+    }
+}
+
+/// Fields of an ERROR with `code` and `message`
+fn error_fields(code: &str, message: impl Into<String>) -> ErrorOrNoticeFields {
+    ErrorOrNoticeFields {
+        severity: Some("ERROR".to_string()),
+        code: Some(code.to_string()),
+        message: Some(message.into()),
+        ..Default::default()
+    }
+}
+
+/// ErrorResponse failing an extended-protocol message
+///
+/// As in PostgreSQL, the rest of the batch is ignored up to its Sync, which
+/// answers ReadyForQuery, and an open transaction is now failed.
+fn extended_error(
+    fields: ErrorOrNoticeFields,
+    extended_state: &mut ExtendedQueryState,
+    transaction_status: &mut TransactionStatus,
+) -> Vec<BackendMessage> {
+    if *transaction_status == TransactionStatus::InTransaction {
+        *transaction_status = TransactionStatus::Failed;
+    }
+    extended_state.skip_until_sync();
+    vec![BackendMessage::ErrorResponse(fields)]
+}
+
+/// Transaction control statement, by its effect on the transaction block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransactionControl {
+    /// `BEGIN` or `START TRANSACTION`
+    Begin,
     
+    /// `COMMIT`, `END` or `PREPARE TRANSACTION`, ending the block
+    Commit,
+    
+    /// `ROLLBACK` or `ABORT`, ending the block
+    Rollback,
+    
+    /// `ROLLBACK TO SAVEPOINT`, which leaves the block open
+    RollbackToSavepoint,
+}
+
+/// Classify a transaction control statement, by its leading keywords
+///
+/// `COMMIT PREPARED` and `ROLLBACK PREPARED` run outside a transaction block,
+/// so they aren't transaction control here.
+fn transaction_control(query: &str) -> Option<TransactionControl> {
+    let upper = query.trim_start().to_uppercase();
+    let words: Vec<&str> = upper
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .filter(|word| !word.is_empty())
+        .take(3)
+        .collect();
+    
+    match words.as_slice() {
+        ["BEGIN", ..] | ["START", "TRANSACTION", ..] => Some(TransactionControl::Begin),
+        ["COMMIT" | "ROLLBACK", "PREPARED", ..] => None,
+        ["COMMIT" | "END", ..] | ["PREPARE", "TRANSACTION", ..] => Some(TransactionControl::Commit),
+        ["ROLLBACK", "TO", ..] | ["ROLLBACK", "WORK" | "TRANSACTION", "TO"] => Some(TransactionControl::RollbackToSavepoint),
+        ["ROLLBACK" | "ABORT", ..] => Some(TransactionControl::Rollback),
+        _ => None,
+    }
+}
+
+/// Update the client's transaction status for a statement about to run
+///
+/// The status is the one PostgreSQL would report once the statement has
+/// started: a BEGIN opens a block, so the statement is pinned to the session
+/// that runs it, while COMMIT and ROLLBACK end it, even a failed one.
+fn update_transaction_status(status: &mut TransactionStatus, query: &str) {
+    match (transaction_control(query), *status) {
+        (Some(TransactionControl::Begin), TransactionStatus::Idle) => {
+            *status = TransactionStatus::InTransaction;
+        }
+        (Some(TransactionControl::Commit | TransactionControl::Rollback), _) => {
+            *status = TransactionStatus::Idle;
+        }
+        (Some(TransactionControl::RollbackToSavepoint), TransactionStatus::Failed) => {
+            *status = TransactionStatus::InTransaction;
+        }
+        _ => {}
+    }
+}
+
+/// Answer a statement sent while the transaction block is failed
+///
+/// The proxy fails a block itself when it rejects a statement the backend
+/// never saw, so the backend's block may still be live. As in PostgreSQL,
+/// only a ROLLBACK, of the block or to a savepoint, runs as it is; a COMMIT
+/// rolls the block back instead, and every other statement is rejected.
+/// Returns `None` for statements to run as usual.
+async fn failed_transaction_response(
+    query: &str,
+    session_affinity: &mut SessionAffinity<ClientWrapper>,
+    transaction_status: &mut TransactionStatus,
+) -> Result<Option<Vec<BackendMessage>>> {
+    if *transaction_status != TransactionStatus::Failed {
+        return Ok(None);
+    }
+    
+    match transaction_control(query) {
+        Some(TransactionControl::Rollback | TransactionControl::RollbackToSavepoint) => Ok(None),
+        Some(TransactionControl::Commit) => {
+            if let Some(session) = session_affinity.pinned() {
+                session.inner().query("ROLLBACK", &[]).await
+                    .map_err(|e| ProxyError::Database(format!("Database error: {}", e)))?;
+            }
+            *transaction_status = TransactionStatus::Idle;
+            session_affinity.release_if_idle(*transaction_status);
+            Ok(Some(vec![BackendMessage::CommandComplete("ROLLBACK".to_string())]))
+        }
+        _ => {
+            let fields = ErrorOrNoticeFields {
+                severity: Some("ERROR".to_string()),
+                code: Some("25P02".to_string()),
+                message: Some("current transaction is aborted, commands ignored until end of transaction block".to_string()),
+                ..Default::default()
+            };
+            Ok(Some(vec![BackendMessage::ErrorResponse(fields)]))
+        }
+    }
+}
+
+/// Whether a statement leaves state on its backend session past the transaction
+///
+/// Session-level settings, SQL prepared statements, listeners, temporary
+/// tables, holdable cursors and session advisory locks all need the client to
+/// keep using the same backend session afterwards.
+fn changes_session_state(query: &str) -> bool {
+    let upper = query.trim_start().to_uppercase();
+    let words: Vec<&str> = upper
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .filter(|word| !word.is_empty())
+        .take(2)
+        .collect();
+    
+    match words.as_slice() {
+        ["SET", "LOCAL" | "TRANSACTION" | "CONSTRAINTS", ..] => false,
+        ["SET", ..] | ["RESET", ..] | ["PREPARE", ..] | ["LISTEN", ..] => true,
+        ["CREATE", "TEMP" | "TEMPORARY" | "LOCAL" | "GLOBAL", ..] => true,
+        ["DECLARE", ..] => upper.contains("WITH HOLD"),
+        _ => upper.contains("PG_ADVISORY_LOCK"),
+    }
+}
+
+/// Open the proxy's backend session once the client has authenticated
+///
+/// With a backend pool, later statements check out pooled sessions; the first
+/// one is only taken here to make sure the backend is reachable. `role` is the
+/// user established by passthrough or certificate authentication; the session
+/// switches to it so the backend's privileges for that user apply, and is then
+/// held by the client since the role lives on that session.
+async fn open_backend_session(
+    auth_response: Vec<BackendMessage>,
+    backend: &mut Option<Backend>,
+    backend_pool: Option<&BackendPool>,
+    cancel_registry: &CancelRegistry,
+    cancel_registration: &mut Option<CancelRegistration>,
+    session_affinity: &mut SessionAffinity<ClientWrapper>,
+    config: &ProxyConfig,
+    state: &mut ConnectionState,
+    role: Option<&str>,
+) -> Result<Vec<BackendMessage>> {
+    // Connect to PostgreSQL
+    debug!("Authentication successful, connecting to PostgreSQL backend");
+    
+    let session = match backend_pool {
+        Some(pool) => pool.get().await,
+        None => {
+            let pg_config = backend_pg_config(config);
+            debug!("Connecting to PostgreSQL at {} as {:?}", config.backend_addr, pg_config.get_user());
+            connect_to_postgres(&pg_config).await
+        }
+    };
+    
+    match session {
+        Ok(client) => {
+            debug!("Connected to PostgreSQL backend");
+            
+            // Act as the authenticated user rather than the service account
+            if let Some(role) = role {
+                let statement = format!("SET ROLE \"{}\"", role.replace('"', "\"\""));
+                client.inner().batch_execute(&statement).await
+                    .map_err(|e| ProxyError::Auth(format!("Failed to switch to role {}: {}", role, e)))?;
+                session_affinity.hold(client.clone());
+            }
+            
+            *backend = Some(match backend_pool {
+                Some(pool) => Backend::Pooled(pool.clone()),
+                None => Backend::Dedicated(client),
+            });
+            // Update state to Ready
+            *state = ConnectionState::Ready;
+            
+            // Add necessary startup messages to the response
+            let mut response = auth_response;
+            
+            // The proxy's own cancel key, mapped to whichever backend session
+            // is running the client's statement
+            let registration = cancel_registry.register();
+            let key = registration.key();
+            *cancel_registration = Some(registration);
+            response.push(BackendMessage::BackendKeyData {
+                process_id: key.process_id,
+                secret_key: key.secret_key,
+            });
+            
+            // Add ParameterStatus messages that clients expect
+            response.push(BackendMessage::ParameterStatus {
+                name: "server_version".to_string(),
+                value: "14.0".to_string(),
+            });
+            response.push(BackendMessage::ParameterStatus {
+                name: "client_encoding".to_string(),
+                value: "UTF8".to_string(),
+            });
+            response.push(BackendMessage::ParameterStatus {
+                name: "DateStyle".to_string(),
+                value: "ISO, MDY".to_string(),
+            });
+            response.push(BackendMessage::ParameterStatus {
+                name: "integer_datetimes".to_string(),
+                value: "on".to_string(),
+            });
+            response.push(BackendMessage::ParameterStatus {
+                name: "standard_conforming_strings".to_string(),
+                value: "on".to_string(),
+            });
+            
+            // Add ReadyForQuery message
+            response.push(BackendMessage::ReadyForQuery(TransactionStatus::Idle));
+            
+            Ok(response)
+        }
+        Err(e) => {
+            error!("Failed to connect to PostgreSQL backend: {}", e);
+            Err(ProxyError::Database(format!("Failed to connect to PostgreSQL backend: {}", e)))
+        }
+    }
+}
+
+/// Take the first `COPY_DATA_CHUNK_SIZE` data bytes of the partly read
+/// CopyData message of `length` bytes at the front of `buf`
+///
+/// The message header is rewritten in place for the rest of the data, so the
+/// remainder reads as an ordinary, shorter CopyData message.
+fn split_copy_data_chunk(buf: &mut BytesMut, length: usize) -> Bytes {
+    let chunk = Bytes::copy_from_slice(&buf[5..5 + COPY_DATA_CHUNK_SIZE]);
+    let remaining = length - 5 - COPY_DATA_CHUNK_SIZE;
+    
+    buf.advance(COPY_DATA_CHUNK_SIZE);
+    buf[0] = b'd';
+    buf[1..5].copy_from_slice(&(remaining as i32 + 4).to_be_bytes());
+    chunk
+}
+
+/// Connect to PostgreSQL and return a ClientWrapper
+async fn connect_to_postgres(config: &PgConfig) -> Result<ClientWrapper> {
     // Connect to PostgreSQL
     let (client, connection) = config.connect(tokio_postgres::NoTls).await
         .map_err(|e| ProxyError::Database(format!("Failed to connect to PostgreSQL: {}", e)))?;
@@ -790,9 +2188,10 @@ async fn handle_backend_messages(
             update_state_from_transaction_status(state, *transaction_status);
         }
 
-        // Format and write message
-        let bytes = formatter.format_backend_message(&message)?;
-        writer.write_all(&bytes).await?;
+        // Format and write message, large rows a piece at a time
+        for chunk in formatter.format_backend_message_chunks(&message)? {
+            writer.write_all(&chunk).await?;
+        }
     }
 
     writer.flush().await?;
@@ -809,10 +2208,1138 @@ mod tests {
         assert_ne!(ConnectionState::Authenticating, ConnectionState::Closed);
     }
     
+    #[test]
+    fn test_transaction_statements_share_backend_session() {
+        let mut affinity = SessionAffinity::new();
+        let mut next_session = 0u32;
+        let mut acquire = || {
+            next_session += 1;
+            next_session
+        };
+        
+        // Outside a transaction every statement may use a fresh session
+        let before = affinity.session_for(TransactionStatus::Idle, &mut acquire);
+        affinity.release_if_idle(TransactionStatus::Idle);
+        
+        // BEGIN .. COMMIT all run on the session that started the transaction
+        let mut sessions = Vec::new();
+        for status in [
+            TransactionStatus::InTransaction, // BEGIN
+            TransactionStatus::InTransaction, // INSERT
+            TransactionStatus::InTransaction, // UPDATE
+            TransactionStatus::Idle,          // COMMIT
+        ] {
+            sessions.push(affinity.session_for(status, &mut acquire));
+            affinity.release_if_idle(status);
+        }
+        
+        assert!(sessions.iter().all(|s| *s == sessions[0]));
+        assert_ne!(sessions[0], before);
+        assert!(!affinity.is_pinned());
+        
+        // Once idle, the next statement is free to use another session
+        let after = affinity.session_for(TransactionStatus::Idle, &mut acquire);
+        assert_ne!(after, sessions[0]);
+    }
+    
+    #[test]
+    fn test_held_session_outlives_transactions() {
+        let mut affinity = SessionAffinity::new();
+        affinity.hold(7u32);
+        
+        // A held session is kept even when the client is idle
+        assert_eq!(affinity.session_for(TransactionStatus::Idle, || 8), 7);
+        assert_eq!(affinity.release_if_idle(TransactionStatus::Idle), None);
+        assert_eq!(affinity.pinned(), Some(&7));
+    }
+    
+    #[test]
+    fn test_changes_session_state() {
+        for query in [
+            "SET search_path TO app",
+            "set statement_timeout = 0;",
+            "RESET ALL",
+            "PREPARE q AS SELECT 1",
+            "LISTEN events",
+            "CREATE TEMP TABLE t (id int)",
+            "create temporary table t (id int)",
+            "DECLARE c CURSOR WITH HOLD FOR SELECT 1",
+            "SELECT pg_advisory_lock(1)",
+        ] {
+            assert!(changes_session_state(query), "{}", query);
+        }
+        
+        for query in [
+            "SELECT 1",
+            "SET LOCAL search_path TO app",
+            "SET TRANSACTION ISOLATION LEVEL SERIALIZABLE",
+            "SET CONSTRAINTS ALL DEFERRED",
+            "CREATE TABLE t (id int)",
+            "DECLARE c CURSOR FOR SELECT 1",
+            "SELECT pg_advisory_xact_lock(1)",
+        ] {
+            assert!(!changes_session_state(query), "{}", query);
+        }
+    }
+    
+    #[test]
+    fn test_transaction_status_follows_control_statements() {
+        let status_after = |status: TransactionStatus, query: &str| {
+            let mut status = status;
+            update_transaction_status(&mut status, query);
+            status
+        };
+        
+        for query in ["BEGIN", "begin isolation level serializable", "START TRANSACTION READ ONLY"] {
+            assert_eq!(status_after(TransactionStatus::Idle, query), TransactionStatus::InTransaction, "{}", query);
+        }
+        for query in ["COMMIT", "END", "end transaction;", "ROLLBACK", "ABORT", "PREPARE TRANSACTION 'tx1'"] {
+            assert_eq!(status_after(TransactionStatus::InTransaction, query), TransactionStatus::Idle, "{}", query);
+            assert_eq!(status_after(TransactionStatus::Failed, query), TransactionStatus::Idle, "{}", query);
+        }
+        
+        // Rolling back to a savepoint recovers a failed block without ending it
+        for query in ["ROLLBACK TO SAVEPOINT s1", "rollback work to s1"] {
+            assert_eq!(status_after(TransactionStatus::Failed, query), TransactionStatus::InTransaction, "{}", query);
+            assert_eq!(status_after(TransactionStatus::InTransaction, query), TransactionStatus::InTransaction, "{}", query);
+        }
+        
+        // Two-phase commit statements and other statements leave the status alone
+        for query in ["COMMIT PREPARED 'tx1'", "ROLLBACK PREPARED 'tx1'", "SELECT 1", "ENDPOINT"] {
+            assert_eq!(status_after(TransactionStatus::Idle, query), TransactionStatus::Idle, "{}", query);
+        }
+        assert_eq!(status_after(TransactionStatus::Failed, "BEGIN"), TransactionStatus::Failed);
+    }
+    
+    #[tokio::test]
+    async fn test_gss_negotiation_is_relayed_to_backend() {
+        use crate::protocol::auth::AuthConfig;
+        use crate::protocol::message::AuthenticationRequest;
+        use tokio::net::TcpListener;
+        
+        // Fake backend that performs a two-step GSSAPI exchange
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        let backend = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let formatter = MessageFormatter::new();
+            
+            // Startup message
+            let length = socket.read_i32().await.unwrap() as usize;
+            let mut startup = vec![0u8; length - 4];
+            socket.read_exact(&mut startup).await.unwrap();
+            assert!(startup.windows(5).any(|w| w == b"alice"));
+            
+            let mut tokens = Vec::new();
+            for reply in [
+                AuthenticationRequest::GSS,
+                AuthenticationRequest::GSSContinue { data: Bytes::from_static(b"server-token") },
+            ] {
+                let bytes = formatter.format_backend_message(&BackendMessage::Authentication(reply)).unwrap();
+                socket.write_all(&bytes).await.unwrap();
+                
+                // Client token, relayed as a `p` message
+                assert_eq!(socket.read_u8().await.unwrap(), b'p');
+                let length = socket.read_i32().await.unwrap() as usize;
+                let mut token = vec![0u8; length - 4];
+                socket.read_exact(&mut token).await.unwrap();
+                tokens.push(token);
+            }
+            
+            let ok = formatter.format_backend_message(&BackendMessage::Authentication(AuthenticationRequest::Ok)).unwrap();
+            socket.write_all(&ok).await.unwrap();
+            tokens
+        });
+        
+        let mut handler = AuthHandler::new(AuthConfig {
+            default_method: AuthMethod::GssPassthrough,
+            ..AuthConfig::default()
+        });
+        handler.get_initial_auth_request();
+        
+        let parser = MessageParser::new();
+        let mut parameters = HashMap::new();
+        parameters.insert("user".to_string(), "alice".to_string());
+        let mut relay = BackendAuthRelay::connect(backend_addr, &parameters).await.unwrap();
+        
+        let request = relay.receive(&parser).await.unwrap();
+        let to_client = handler.relay_backend_auth(request, relay.user()).unwrap();
+        assert_eq!(to_client, vec![BackendMessage::Authentication(AuthenticationRequest::GSS)]);
+        
+        // The client answers each request with a binary token
+        let mut last = Vec::new();
+        for token in [&[0x60u8, 0x00, 0xff][..], &b"client-final"[..]] {
+            let bytes = handler.relay_client_auth(&FrontendMessage::GssResponse(Bytes::copy_from_slice(token))).unwrap();
+            relay.send(&bytes).await.unwrap();
+            let reply = relay.receive(&parser).await.unwrap();
+            last = handler.relay_backend_auth(reply, "alice").unwrap();
+        }
+        
+        assert_eq!(last, vec![BackendMessage::Authentication(AuthenticationRequest::Ok)]);
+        assert_eq!(handler.get_state(), AuthState::Completed);
+        assert_eq!(handler.authenticated_user(), Some("alice"));
+        
+        let tokens = backend.await.unwrap();
+        assert_eq!(tokens, vec![vec![0x60, 0x00, 0xff], b"client-final".to_vec()]);
+    }
+    
+    #[tokio::test]
+    async fn test_gss_passthrough_trusted_by_backend() {
+        use crate::protocol::message::AuthenticationRequest;
+        
+        // The backend accepts the client at once, without a GSSAPI exchange
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        tokio::spawn(sessions_backend(listener, executed.clone()));
+        
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (socket, addr) = listener.accept().await.unwrap();
+        let mut config = ProxyConfig::default();
+        config.backend_addr = backend_addr;
+        config.auth_config.default_method = AuthMethod::GssPassthrough;
+        let mut connection = ClientConnection::new(socket, addr, config, Arc::new(Mutex::new(TransactionManager::new())));
+        
+        let response = connection.process_message_internal(FrontendMessage::Startup {
+            version_major: 3,
+            version_minor: 0,
+            parameters: HashMap::from([("user".to_string(), "alice".to_string())]),
+        }).await.unwrap();
+        
+        // The session is opened as the user the backend accepted
+        assert_eq!(response.first(), Some(&BackendMessage::Authentication(AuthenticationRequest::Ok)));
+        assert_eq!(response.last(), Some(&BackendMessage::ReadyForQuery(TransactionStatus::Idle)));
+        assert_eq!(connection.state, ConnectionState::Ready);
+        assert!(connection.backend.is_some());
+        assert_eq!(connection.auth_handler.authenticated_user(), Some("alice"));
+    }
+    
+    /// `SSLRequest`: length 8 and the magic request code
+    fn ssl_request() -> Vec<u8> {
+        let mut message = BytesMut::new();
+        message.put_i32(8);
+        message.put_i32(80877103);
+        message.to_vec()
+    }
+    
+    /// Protocol 3.0 startup message for `user`
+    fn startup_message(user: &str) -> Vec<u8> {
+        let mut body = BytesMut::new();
+        body.put_i32(196608);
+        body.put_slice(b"user\0");
+        body.put_slice(user.as_bytes());
+        body.put_u8(0);
+        body.put_u8(0);
+        
+        let mut message = BytesMut::new();
+        message.put_i32(body.len() as i32 + 4);
+        message.put_slice(&body);
+        message.to_vec()
+    }
+    
+    /// Accept one client and run it through a proxy connection
+    async fn serve_one(
+        listener: tokio::net::TcpListener,
+        acceptor: Option<TlsAcceptor>,
+    ) -> (Result<()>, bool) {
+        let (socket, addr) = listener.accept().await.unwrap();
+        let mut connection = ClientConnection::new(
+            socket,
+            addr,
+            ProxyConfig::default(),
+            Arc::new(Mutex::new(TransactionManager::new())),
+        ).with_tls(acceptor);
+        
+        let result = connection.handle_connection().await;
+        (result, connection.socket.is_tls())
+    }
+    
+    #[tokio::test]
+    async fn test_ssl_request_upgrades_to_tls() {
+        use crate::config::TlsConfig;
+        use tokio::net::TcpListener;
+        use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+        use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+        use tokio_rustls::TlsConnector;
+        
+        // Self-signed certificate for the proxy
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let cert_file = dir.path().join("server.crt");
+        let key_file = dir.path().join("server.key");
+        std::fs::write(&cert_file, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_file, cert.serialize_private_key_pem()).unwrap();
+        
+        let acceptor = tls::tls_acceptor(&TlsConfig {
+            cert_file,
+            key_file,
+            require_client_certs: false,
+            ca_file: None,
+        }).unwrap();
+        
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = tokio::spawn(serve_one(listener, Some(acceptor)));
+        
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        socket.write_all(&ssl_request()).await.unwrap();
+        assert_eq!(socket.read_u8().await.unwrap(), b'S');
+        
+        // The client trusts only the proxy's self-signed certificate
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from(cert.serialize_der().unwrap())).unwrap();
+        let client_config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(client_config));
+        let mut stream = connector
+            .connect(ServerName::try_from("localhost").unwrap(), socket)
+            .await
+            .unwrap();
+        
+        // Startup now runs inside the encrypted session
+        stream.write_all(&startup_message("alice")).await.unwrap();
+        assert_eq!(stream.read_u8().await.unwrap(), b'R');
+        stream.shutdown().await.unwrap();
+        
+        let (result, encrypted) = proxy.await.unwrap();
+        result.unwrap();
+        assert!(encrypted);
+    }
+    
+    #[tokio::test]
+    async fn test_ssl_request_declined_without_tls() {
+        use tokio::net::TcpListener;
+        
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = tokio::spawn(serve_one(listener, None));
+        
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        socket.write_all(&ssl_request()).await.unwrap();
+        assert_eq!(socket.read_u8().await.unwrap(), b'N');
+        
+        // The client falls back to plaintext on the same socket
+        socket.write_all(&startup_message("alice")).await.unwrap();
+        assert_eq!(socket.read_u8().await.unwrap(), b'R');
+        drop(socket);
+        
+        let (result, encrypted) = proxy.await.unwrap();
+        result.unwrap();
+        assert!(!encrypted);
+    }
+
+    /// Backend accepting one `COPY FROM STDIN`, answering as tokio-postgres expects
+    ///
+    /// Returns the data the backend loaded, or `None` if the COPY was aborted.
+    async fn copy_backend(listener: tokio::net::TcpListener) -> std::io::Result<Option<Vec<u8>>> {
+        let (mut socket, _) = listener.accept().await?;
+        let length = socket.read_i32().await? as usize;
+        socket.read_exact(&mut vec![0u8; length - 4]).await?;
+        socket.write_all(&[b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'Z', 0, 0, 0, 5, b'I']).await?;
+
+        let mut data = Vec::new();
+        let mut loaded = None;
+        let mut copying = false;
+        let mut finished = false;
+        loop {
+            let tag = socket.read_u8().await?;
+            let length = socket.read_i32().await? as usize;
+            let mut body = vec![0u8; length - 4];
+            socket.read_exact(&mut body).await?;
+
+            let reply: Vec<u8> = match tag {
+                // Prepare: ParseComplete, no parameters, no result columns
+                b'P' => vec![b'1', 0, 0, 0, 4],
+                b'D' => vec![b't', 0, 0, 0, 6, 0, 0, b'n', 0, 0, 0, 4],
+                // Execute: BindComplete, then CopyInResponse in text format
+                b'B' => vec![b'2', 0, 0, 0, 4],
+                b'E' => {
+                    copying = true;
+                    vec![b'G', 0, 0, 0, 7, 0, 0, 0]
+                }
+                b'd' => {
+                    data.extend_from_slice(&body);
+                    continue;
+                }
+                b'c' => {
+                    copying = false;
+                    finished = true;
+                    let rows = data.iter().filter(|&&b| b == b'\n').count();
+                    loaded = Some(std::mem::take(&mut data));
+                    let command_tag = format!("COPY {}\0", rows);
+                    let mut reply = vec![b'C'];
+                    reply.extend_from_slice(&(command_tag.len() as i32 + 4).to_be_bytes());
+                    reply.extend_from_slice(command_tag.as_bytes());
+                    reply
+                }
+                b'f' => {
+                    copying = false;
+                    finished = true;
+                    let fields = b"SERROR\0C57014\0MCOPY from stdin failed\0\0";
+                    let mut reply = vec![b'E'];
+                    reply.extend_from_slice(&(fields.len() as i32 + 4).to_be_bytes());
+                    reply.extend_from_slice(fields);
+                    reply
+                }
+                // Sync is ignored while copying, as PostgreSQL does
+                b'S' if copying => continue,
+                b'S' => vec![b'Z', 0, 0, 0, 5, b'I'],
+                b'X' => return Ok(loaded),
+                _ => continue,
+            };
+            socket.write_all(&reply).await?;
+
+            // The COPY is over once the Sync following CopyDone or CopyFail is answered
+            if tag == b'S' && finished {
+                return Ok(loaded);
+            }
+        }
+    }
+
+    /// Client connection, ready for queries, with its own backend session at `backend_addr`
+    ///
+    /// Also returns the client's end of the socket, which must outlive the connection.
+    async fn ready_connection(backend_addr: SocketAddr) -> (ClientConnection, TcpStream) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client_socket = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (socket, addr) = listener.accept().await.unwrap();
+
+        let mut pg_config = PgConfig::new();
+        pg_config.host(&backend_addr.ip().to_string()).port(backend_addr.port()).user("postgres");
+        let client = connect_to_postgres(&pg_config).await.unwrap();
+
+        let mut connection = ClientConnection::new(
+            socket,
+            addr,
+            ProxyConfig::default(),
+            Arc::new(Mutex::new(TransactionManager::new())),
+        );
+        connection.backend = Some(Backend::Dedicated(client));
+        connection.state = ConnectionState::Ready;
+        (connection, client_socket)
+    }
+
+    #[tokio::test]
+    async fn test_copy_from_stdin_reaches_backend() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        let backend = tokio::spawn(copy_backend(listener));
+        let (mut connection, _client) = ready_connection(backend_addr).await;
+
+        let response = connection.process_message_internal(
+            FrontendMessage::Query("COPY items (id, name) FROM STDIN".to_string())
+        ).await.unwrap();
+        assert_eq!(response, vec![BackendMessage::CopyInResponse { format: 0, column_formats: vec![] }]);
+
+        // Rows are streamed without replies, whatever their split into messages
+        for chunk in [&b"1\tapple\n2\tba"[..], &b"nana\n3\tcherry\n"[..]] {
+            let response = connection.process_message_internal(
+                FrontendMessage::CopyData(Bytes::copy_from_slice(chunk))
+            ).await.unwrap();
+            assert!(response.is_empty());
+        }
+
+        let response = connection.process_message_internal(FrontendMessage::CopyDone).await.unwrap();
+        assert_eq!(response, vec![
+            BackendMessage::CommandComplete("COPY 3".to_string()),
+            BackendMessage::ReadyForQuery(TransactionStatus::Idle),
+        ]);
+        assert!(connection.copy_in.is_none());
+
+        let loaded = backend.await.unwrap().unwrap();
+        assert_eq!(loaded, Some(b"1\tapple\n2\tbanana\n3\tcherry\n".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_copy_fail_aborts_load() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        let backend = tokio::spawn(copy_backend(listener));
+        let (mut connection, _client) = ready_connection(backend_addr).await;
+
+        connection.process_message_internal(FrontendMessage::Query("COPY items FROM STDIN".to_string())).await.unwrap();
+        connection.process_message_internal(FrontendMessage::CopyData(Bytes::from_static(b"1\tapple\n"))).await.unwrap();
+
+        let response = connection.process_message_internal(
+            FrontendMessage::CopyFail("interrupted".to_string())
+        ).await.unwrap();
+        match &response[..] {
+            [BackendMessage::ErrorResponse(fields), BackendMessage::ReadyForQuery(TransactionStatus::Idle)] => {
+                assert_eq!(fields.code.as_deref(), Some("57014"));
+                assert!(fields.message.as_deref().unwrap().contains("interrupted"));
+            }
+            other => panic!("unexpected response {:?}", other),
+        }
+
+        // The backend saw the abort and loaded nothing; stray copy messages are ignored
+        assert_eq!(backend.await.unwrap().unwrap(), None);
+        assert!(connection.process_message_internal(FrontendMessage::CopyDone).await.unwrap().is_empty());
+    }
+
+    /// Backend answering every query with the rows 1, 2 and 3 of one text column
+    ///
+    /// Cursors declared over a query fetch those rows in turn, and statements
+    /// starting with `SELEKT` are syntax errors. Records the SQL of each
+    /// statement it is asked to prepare.
+    async fn rows_backend(listener: tokio::net::TcpListener, prepared: Arc<Mutex<Vec<String>>>) -> std::io::Result<()> {
+        let (mut socket, _) = listener.accept().await?;
+        let length = socket.read_i32().await? as usize;
+        socket.read_exact(&mut vec![0u8; length - 4]).await?;
+        socket.write_all(&[b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'Z', 0, 0, 0, 5, b'I']).await?;
+        
+        let message = |tag: u8, body: &[u8]| {
+            let mut message = vec![tag];
+            message.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+            message.extend_from_slice(body);
+            message
+        };
+        let row = |n: u8| message(b'D', &[0, 1, 0, 0, 0, 1, n]);
+        let cstr = |body: &[u8]| {
+            let end = body.iter().position(|&b| b == 0).unwrap();
+            (String::from_utf8_lossy(&body[..end]).into_owned(), end + 1)
+        };
+        
+        // Statements by name, with their parameter types; the bound statement;
+        // rows left in the open cursor
+        let mut statements: HashMap<String, (String, Vec<i32>)> = HashMap::new();
+        let mut bound = String::new();
+        let mut cursor_rows: Vec<u8> = Vec::new();
+        let mut skipping = false;
+        loop {
+            let tag = socket.read_u8().await?;
+            let length = socket.read_i32().await? as usize;
+            let mut body = vec![0u8; length - 4];
+            socket.read_exact(&mut body).await?;
+            
+            // After an error, messages up to the Sync are ignored
+            if skipping && tag != b'S' {
+                continue;
+            }
+            
+            let reply = match tag {
+                b'P' if body.windows(6).any(|word| word == b"SELEKT") => {
+                    skipping = true;
+                    message(b'E', b"SERROR\0C42601\0Msyntax error at or near \"SELEKT\"\0\0")
+                }
+                b'P' => {
+                    let (name, read) = cstr(&body);
+                    let (query, query_length) = cstr(&body[read..]);
+                    let types = &body[read + query_length..];
+                    let mut param_types: Vec<i32> = types[2..].chunks(4)
+                        .map(|oid| i32::from_be_bytes(oid.try_into().unwrap()))
+                        .collect();
+                    // Parameters the client left unspecified are text
+                    let count = (1..10).filter(|n| query.contains(&format!("${}", n))).count();
+                    param_types.resize(count.max(param_types.len()), 25);
+                    prepared.lock().unwrap().push(query.clone());
+                    statements.insert(name, (query, param_types));
+                    message(b'1', &[])
+                }
+                b'D' => {
+                    let (name, _) = cstr(&body[1..]);
+                    let param_types = statements.get(&name).map(|(_, types)| types.clone()).unwrap_or_default();
+                    let mut params = (param_types.len() as i16).to_be_bytes().to_vec();
+                    for oid in param_types {
+                        params.extend_from_slice(&oid.to_be_bytes());
+                    }
+                    
+                    // One text column `n`
+                    let mut columns = vec![0, 1, b'n', 0, 0, 0, 0, 0, 0, 0];
+                    columns.extend_from_slice(&25i32.to_be_bytes());
+                    columns.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0]);
+                    [message(b't', &params), message(b'T', &columns)].concat()
+                }
+                b'B' => {
+                    let (_, read) = cstr(&body);
+                    let (statement, _) = cstr(&body[read..]);
+                    bound = statements.get(&statement).map(|(query, _)| query.clone()).unwrap_or_default();
+                    message(b'2', &[])
+                }
+                b'E' => {
+                    let words: Vec<&str> = bound.split_whitespace().collect();
+                    match words[..] {
+                        ["DECLARE", ..] => {
+                            cursor_rows = vec![b'1', b'2', b'3'];
+                            message(b'C', b"DECLARE CURSOR\0")
+                        }
+                        ["FETCH", "FORWARD", count, ..] => {
+                            let count = count.parse::<usize>().unwrap().min(cursor_rows.len());
+                            let mut reply: Vec<u8> = cursor_rows.drain(..count).flat_map(row).collect();
+                            reply.extend(message(b'C', format!("FETCH {}\0", count).as_bytes()));
+                            reply
+                        }
+                        ["BEGIN" | "COMMIT" | "CLOSE", ..] => message(b'C', format!("{}\0", words[0]).as_bytes()),
+                        _ => {
+                            let mut reply: Vec<u8> = [b'1', b'2', b'3'].into_iter().flat_map(row).collect();
+                            reply.extend(message(b'C', b"SELECT 3\0"));
+                            reply
+                        }
+                    }
+                }
+                b'C' => message(b'3', &[]),
+                b'S' => {
+                    skipping = false;
+                    message(b'Z', b"I")
+                }
+                b'X' => return Ok(()),
+                _ => continue,
+            };
+            socket.write_all(&reply).await?;
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_portal_executed_in_two_batches() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        let prepared = Arc::new(Mutex::new(Vec::new()));
+        tokio::spawn(rows_backend(listener, prepared.clone()));
+        let (mut connection, _client) = ready_connection(backend_addr).await;
+        
+        connection.process_message_internal(FrontendMessage::Parse {
+            name: String::new(),
+            query: "SELECT n FROM t WHERE n > $1".to_string(),
+            param_types: vec![],
+        }).await.unwrap();
+        connection.process_message_internal(FrontendMessage::Bind {
+            portal: "cursor".to_string(),
+            statement: String::new(),
+            param_formats: vec![],
+            param_values: vec![Some(Bytes::from("0"))],
+            result_formats: vec![],
+        }).await.unwrap();
+        
+        let row = |n: &'static str| BackendMessage::DataRow(vec![Some(Bytes::from(n))]);
+        let execute = || FrontendMessage::Execute { portal: "cursor".to_string(), max_rows: 2 };
+        
+        // The first batch suspends the portal, the second completes it
+        let response = connection.process_message_internal(execute()).await.unwrap();
+        assert_eq!(response, vec![row("1"), row("2"), BackendMessage::PortalSuspended]);
+        let response = connection.process_message_internal(execute()).await.unwrap();
+        assert_eq!(response, vec![row("3"), BackendMessage::CommandComplete("SELECT 1".to_string())]);
+        
+        // Sync outside a transaction commits the one holding the cursor, and closes the portal
+        let response = connection.process_message_internal(FrontendMessage::Sync).await.unwrap();
+        assert_eq!(response, vec![BackendMessage::ReadyForQuery(TransactionStatus::Idle)]);
+        assert!(connection.process_message_internal(execute()).await.is_err());
+        
+        // The rows were fetched from a cursor over the statement, with its
+        // value passed as a parameter
+        assert_eq!(*prepared.lock().unwrap(), vec![
+            "SELECT n FROM t WHERE n > $1",
+            "BEGIN",
+            "DECLARE proxy_portal_1 NO SCROLL CURSOR FOR SELECT n FROM t WHERE n > $1",
+            "FETCH FORWARD 2 FROM proxy_portal_1",
+            "FETCH FORWARD 2 FROM proxy_portal_1",
+            "CLOSE proxy_portal_1",
+            "COMMIT",
+        ]);
+    }
+    
+    #[tokio::test]
+    async fn test_extended_statements_screened() {
+        use crate::security::anomaly_detector::AnomalyDetectorConfig;
+        use crate::security::dos_protection::DoSProtectionConfig;
+        use crate::security::rate_limiter::RateLimiterConfig;
+        use crate::security::security_gateway::SecurityGatewayConfig;
+        use crate::security::traffic_analyzer::TrafficAnalyzerConfig;
+        use crate::security::{AnomalyDetector, DoSProtection, RateLimiter, TrafficAnalyzer};
+        
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        let prepared = Arc::new(Mutex::new(Vec::new()));
+        tokio::spawn(rows_backend(listener, prepared.clone()));
+        let (connection, _client) = ready_connection(backend_addr).await;
+        
+        // The statement below scores 20, so the budget covers one run of it
+        let gateway = SecurityGateway::new(
+            SecurityGatewayConfig { block_anomalous_queries: true, ..Default::default() },
+            Arc::new(Mutex::new(RateLimiter::new(RateLimiterConfig::default()).unwrap())),
+            Arc::new(DoSProtection::new(DoSProtectionConfig {
+                query_cost_budget: 30,
+                expensive_query_threshold: 1,
+                ..Default::default()
+            })),
+            Arc::new(AnomalyDetector::new(AnomalyDetectorConfig::default())),
+            Arc::new(TrafficAnalyzer::new(TrafficAnalyzerConfig::default())),
+        );
+        let mut connection = connection.with_security_gateway(Some(Arc::new(gateway)));
+        
+        let parse = |query: &str| FrontendMessage::Parse {
+            name: String::new(),
+            query: query.to_string(),
+            param_types: vec![],
+        };
+        let bind = || FrontendMessage::Bind {
+            portal: "cursor".to_string(),
+            statement: String::new(),
+            param_formats: vec![],
+            param_values: vec![Some(Bytes::from("0"))],
+            result_formats: vec![],
+        };
+        let execute = || FrontendMessage::Execute { portal: "cursor".to_string(), max_rows: 2 };
+        let error_code = |response: Vec<BackendMessage>| match &response[..] {
+            [BackendMessage::ErrorResponse(fields)] => fields.code.clone(),
+            other => panic!("unexpected response {:?}", other),
+        };
+        
+        // Injection is caught when the statement is parsed
+        let response = connection.process_message_internal(parse("SELECT * FROM users WHERE name = '' OR '1'='1'")).await.unwrap();
+        assert_eq!(error_code(response).as_deref(), Some("42501"));
+        connection.process_message_internal(FrontendMessage::Sync).await.unwrap();
+        
+        // A portal is charged once, however many batches it's fetched in
+        connection.process_message_internal(parse("SELECT n FROM t WHERE n > $1")).await.unwrap();
+        connection.process_message_internal(bind()).await.unwrap();
+        connection.process_message_internal(execute()).await.unwrap();
+        let response = connection.process_message_internal(execute()).await.unwrap();
+        assert_eq!(response.last(), Some(&BackendMessage::CommandComplete("SELECT 1".to_string())));
+        connection.process_message_internal(FrontendMessage::Sync).await.unwrap();
+        
+        // Running the statement again exceeds the budget
+        connection.process_message_internal(bind()).await.unwrap();
+        let response = connection.process_message_internal(execute()).await.unwrap();
+        assert_eq!(error_code(response).as_deref(), Some("53000"));
+        let response = connection.process_message_internal(FrontendMessage::Sync).await.unwrap();
+        assert_eq!(response, vec![BackendMessage::ReadyForQuery(TransactionStatus::Idle)]);
+        
+        // Neither rejected statement reached the backend
+        let prepared = prepared.lock().unwrap();
+        assert_eq!(prepared.iter().filter(|query| query.starts_with("DECLARE")).count(), 1);
+        assert!(!prepared.iter().any(|query| query.contains("users")));
+    }
+    
+    #[tokio::test]
+    async fn test_extended_statements_prepared_on_backend() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        tokio::spawn(rows_backend(listener, Arc::new(Mutex::new(Vec::new()))));
+        let (mut connection, _client) = ready_connection(backend_addr).await;
+        
+        let parse = |query: &str| FrontendMessage::Parse {
+            name: "s".to_string(),
+            query: query.to_string(),
+            param_types: vec![],
+        };
+        let bind = |param_values: Vec<Option<Bytes>>| FrontendMessage::Bind {
+            portal: "p".to_string(),
+            statement: "s".to_string(),
+            param_formats: vec![],
+            param_values,
+            result_formats: vec![],
+        };
+        let execute = || FrontendMessage::Execute { portal: "p".to_string(), max_rows: 0 };
+        let describe = |object_type: u8, name: &str| FrontendMessage::Describe { object_type, name: name.to_string() };
+        let idle = vec![BackendMessage::ReadyForQuery(TransactionStatus::Idle)];
+        
+        // A statement the backend rejects fails the rest of the batch, up to its Sync
+        let response = connection.process_message_internal(parse("SELEKT n FROM t")).await.unwrap();
+        assert!(matches!(&response[..], [BackendMessage::ErrorResponse(fields)] if fields.code.as_deref() == Some("42601")));
+        assert!(connection.process_message_internal(bind(vec![])).await.unwrap().is_empty());
+        assert!(connection.process_message_internal(execute()).await.unwrap().is_empty());
+        assert_eq!(connection.process_message_internal(FrontendMessage::Sync).await.unwrap(), idle);
+        
+        // The backend resolves the statement's parameter and result types
+        let response = connection.process_message_internal(parse("SELECT n FROM t WHERE n > $1")).await.unwrap();
+        assert_eq!(response, vec![BackendMessage::ParseComplete]);
+        let response = connection.process_message_internal(describe(b'S', "s")).await.unwrap();
+        match &response[..] {
+            [BackendMessage::ParameterDescription(types), BackendMessage::RowDescription(columns)] => {
+                assert_eq!(types, &vec![25]);
+                assert_eq!(columns.len(), 1);
+                assert_eq!(columns[0].name, "n");
+            }
+            other => panic!("unexpected response {:?}", other),
+        }
+        
+        // Values must match the statement's parameters
+        let response = connection.process_message_internal(bind(vec![])).await.unwrap();
+        assert!(matches!(&response[..], [BackendMessage::ErrorResponse(fields)] if fields.code.as_deref() == Some("08P01")));
+        assert_eq!(connection.process_message_internal(FrontendMessage::Sync).await.unwrap(), idle);
+        
+        let response = connection.process_message_internal(bind(vec![Some(Bytes::from("0"))])).await.unwrap();
+        assert_eq!(response, vec![BackendMessage::BindComplete]);
+        let response = connection.process_message_internal(describe(b'P', "p")).await.unwrap();
+        assert!(matches!(&response[..], [BackendMessage::RowDescription(columns)] if columns.len() == 1));
+        let response = connection.process_message_internal(execute()).await.unwrap();
+        assert_eq!(response.len(), 4);
+        assert_eq!(response[3], BackendMessage::CommandComplete("SELECT 3".to_string()));
+        
+        // Unknown statements and portals are errors too
+        let response = connection.process_message_internal(describe(b'P', "missing")).await.unwrap();
+        assert!(matches!(&response[..], [BackendMessage::ErrorResponse(fields)] if fields.code.as_deref() == Some("34000")));
+        assert_eq!(connection.process_message_internal(FrontendMessage::Sync).await.unwrap(), idle);
+    }
+    
+    /// Send a simple query and read the reply, returning the transaction
+    /// status it ends with
+    async fn simple_query(client: &mut TcpStream, query: &str) -> u8 {
+        let mut message = vec![b'Q'];
+        message.extend_from_slice(&(query.len() as i32 + 5).to_be_bytes());
+        message.extend_from_slice(query.as_bytes());
+        message.push(0);
+        client.write_all(&message).await.unwrap();
+        read_until_ready(client).await
+    }
+    
+    /// Send a query as one Parse, Bind, Execute and Sync batch and read the
+    /// reply, returning the transaction status it ends with
+    async fn extended_query(client: &mut TcpStream, query: &str) -> u8 {
+        let message = |tag: u8, body: &[u8]| {
+            let mut message = vec![tag];
+            message.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+            message.extend_from_slice(body);
+            message
+        };
+        
+        // Unnamed statement and portal, with no parameters or format codes
+        let mut parse = vec![0];
+        parse.extend_from_slice(query.as_bytes());
+        parse.extend_from_slice(&[0, 0, 0]);
+        let batch = [
+            message(b'P', &parse),
+            message(b'B', &[0, 0, 0, 0, 0, 0, 0, 0]),
+            message(b'E', &[0, 0, 0, 0, 0]),
+            message(b'S', &[]),
+        ];
+        client.write_all(&batch.concat()).await.unwrap();
+        read_until_ready(client).await
+    }
+    
+    /// Read backend messages up to ReadyForQuery, returning its transaction status
+    async fn read_until_ready(client: &mut TcpStream) -> u8 {
+        loop {
+            let tag = client.read_u8().await.unwrap();
+            let length = client.read_i32().await.unwrap() as usize;
+            let mut body = vec![0u8; length - 4];
+            client.read_exact(&mut body).await.unwrap();
+            if tag == b'Z' {
+                return body[0];
+            }
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_transaction_completes_while_draining() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        tokio::spawn(rows_backend(listener, Arc::new(Mutex::new(Vec::new()))));
+        let (connection, mut client) = ready_connection(backend_addr).await;
+        
+        let (shutdown, draining) = watch::channel(false);
+        let mut connection = connection.with_shutdown(draining);
+        let session = tokio::spawn(async move { connection.handle_connection().await });
+        
+        assert_eq!(simple_query(&mut client, "BEGIN").await, b'T');
+        shutdown.send_replace(true);
+        
+        // The open transaction keeps running until it commits
+        let finish = async {
+            assert_eq!(simple_query(&mut client, "SELECT n FROM t").await, b'T');
+            assert_eq!(simple_query(&mut client, "COMMIT").await, b'I');
+        };
+        tokio::time::timeout(Duration::from_secs(5), finish).await.expect("transaction not allowed to finish");
+        
+        // Then the session is closed, telling the client why
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response.first(), Some(&b'E'));
+        assert!(String::from_utf8_lossy(&response).contains("57P01"));
+        session.await.unwrap().unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_extended_transaction_completes_while_draining() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        tokio::spawn(rows_backend(listener, Arc::new(Mutex::new(Vec::new()))));
+        let (connection, mut client) = ready_connection(backend_addr).await;
+        
+        let (shutdown, draining) = watch::channel(false);
+        let mut connection = connection.with_shutdown(draining);
+        let session = tokio::spawn(async move { connection.handle_connection().await });
+        
+        assert_eq!(extended_query(&mut client, "BEGIN").await, b'T');
+        shutdown.send_replace(true);
+        
+        let finish = async {
+            assert_eq!(extended_query(&mut client, "SELECT n FROM t").await, b'T');
+            assert_eq!(extended_query(&mut client, "COMMIT").await, b'I');
+        };
+        tokio::time::timeout(Duration::from_secs(5), finish).await.expect("transaction not allowed to finish");
+        
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(String::from_utf8_lossy(&response).contains("57P01"));
+        session.await.unwrap().unwrap();
+    }
+    
+    /// Backend accepting any number of sessions, answering every statement with no rows
+    ///
+    /// Records each statement executed, with the number of the session it ran on.
+    async fn sessions_backend(listener: tokio::net::TcpListener, executed: Arc<Mutex<Vec<(usize, String)>>>) {
+        for session in 0usize.. {
+            let Ok((socket, _)) = listener.accept().await else { return };
+            tokio::spawn(serve_session(socket, session, executed.clone()));
+        }
+    }
+    
+    async fn serve_session(mut socket: TcpStream, session: usize, executed: Arc<Mutex<Vec<(usize, String)>>>) -> std::io::Result<()> {
+        let length = socket.read_i32().await? as usize;
+        socket.read_exact(&mut vec![0u8; length - 4]).await?;
+        socket.write_all(&[b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'Z', 0, 0, 0, 5, b'I']).await?;
+        
+        let message = |tag: u8, body: &[u8]| {
+            let mut message = vec![tag];
+            message.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+            message.extend_from_slice(body);
+            message
+        };
+        let mut parsed = String::new();
+        loop {
+            let tag = socket.read_u8().await?;
+            let length = socket.read_i32().await? as usize;
+            let mut body = vec![0u8; length - 4];
+            socket.read_exact(&mut body).await?;
+            
+            let reply = match tag {
+                b'P' => {
+                    let mut fields = body.split(|&b| b == 0);
+                    fields.next();
+                    parsed = String::from_utf8_lossy(fields.next().unwrap()).into_owned();
+                    message(b'1', &[])
+                }
+                b'D' => [message(b't', &[0, 0]), message(b'n', &[])].concat(),
+                b'B' => message(b'2', &[]),
+                b'E' => {
+                    executed.lock().unwrap().push((session, parsed.clone()));
+                    let command_tag = format!("{}\0", parsed.split_whitespace().next().unwrap_or_default().to_uppercase());
+                    message(b'C', command_tag.as_bytes())
+                }
+                b'C' => message(b'3', &[]),
+                b'S' => message(b'Z', b"I"),
+                // Simple queries, such as the pool's reset of a returned session
+                b'Q' => [message(b'C', b"SELECT\0"), message(b'Z', b"I")].concat(),
+                b'X' => return Ok(()),
+                _ => continue,
+            };
+            socket.write_all(&reply).await?;
+        }
+    }
+    
+    /// Run `query` with Parse, Bind, Execute and Sync, returning the status Sync reports
+    async fn execute_extended(connection: &mut ClientConnection, query: &str) -> TransactionStatus {
+        for message in [
+            FrontendMessage::Parse { name: String::new(), query: query.to_string(), param_types: vec![] },
+            FrontendMessage::Bind {
+                portal: String::new(),
+                statement: String::new(),
+                param_formats: vec![],
+                param_values: vec![],
+                result_formats: vec![],
+            },
+            FrontendMessage::Execute { portal: String::new(), max_rows: 0 },
+        ] {
+            connection.process_message_internal(message).await.unwrap();
+        }
+        
+        match &connection.process_message_internal(FrontendMessage::Sync).await.unwrap()[..] {
+            [BackendMessage::ReadyForQuery(status)] => *status,
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_extended_transaction_pinned_to_one_session() {
+        use crate::server::pool::BackendPoolConfig;
+        
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        tokio::spawn(sessions_backend(listener, executed.clone()));
+        let (mut connection, _client) = ready_connection(backend_addr).await;
+        
+        let mut pg_config = PgConfig::new();
+        pg_config.host(&backend_addr.ip().to_string()).port(backend_addr.port()).user("postgres");
+        let pool = BackendPool::with_pg_config(pg_config, BackendPoolConfig {
+            min_size: 0,
+            max_size: 4,
+            acquire_timeout: Duration::from_secs(5),
+        }).unwrap();
+        connection.backend = Some(Backend::Pooled(pool.clone()));
+        
+        let mut others = Vec::new();
+        for (query, status) in [
+            ("START TRANSACTION", TransactionStatus::InTransaction),
+            ("INSERT INTO t VALUES (1)", TransactionStatus::InTransaction),
+            ("UPDATE t SET n = 2", TransactionStatus::InTransaction),
+            ("END", TransactionStatus::Idle),
+        ] {
+            assert_eq!(execute_extended(&mut connection, query).await, status, "{}", query);
+            
+            // Other clients take whichever sessions are back in the pool
+            others.push(pool.get().await.unwrap());
+        }
+        
+        let executed = executed.lock().unwrap().clone();
+        assert_eq!(executed.len(), 4);
+        assert!(executed.iter().all(|(session, _)| *session == executed[0].0), "{:?}", executed);
+    }
+    
+    #[tokio::test]
+    async fn test_failed_transaction_never_commits() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        tokio::spawn(sessions_backend(listener, executed.clone()));
+        let (mut connection, _client) = ready_connection(backend_addr).await;
+        let query = |query: &str| FrontendMessage::Query(query.to_string());
+        
+        connection.process_message_internal(query("BEGIN")).await.unwrap();
+        connection.process_message_internal(query("INSERT INTO t VALUES (1)")).await.unwrap();
+        
+        // A statement rejected by the proxy fails the block; the backend's is still live
+        connection.transaction_status = TransactionStatus::Failed;
+        
+        let response = connection.process_message_internal(query("INSERT INTO t VALUES (2)")).await.unwrap();
+        match &response[..] {
+            [BackendMessage::ErrorResponse(fields), BackendMessage::ReadyForQuery(TransactionStatus::Failed)] => {
+                assert_eq!(fields.code.as_deref(), Some("25P02"));
+            }
+            other => panic!("unexpected response {:?}", other),
+        }
+        
+        // Statements through the extended protocol are rejected too
+        connection.process_message_internal(FrontendMessage::Parse {
+            name: String::new(),
+            query: "DELETE FROM t".to_string(),
+            param_types: vec![],
+        }).await.unwrap();
+        connection.process_message_internal(FrontendMessage::Bind {
+            portal: String::new(),
+            statement: String::new(),
+            param_formats: vec![],
+            param_values: vec![],
+            result_formats: vec![],
+        }).await.unwrap();
+        let response = connection.process_message_internal(
+            FrontendMessage::Execute { portal: String::new(), max_rows: 0 }
+        ).await.unwrap();
+        assert!(matches!(&response[..], [BackendMessage::ErrorResponse(fields)] if fields.code.as_deref() == Some("25P02")));
+        let response = connection.process_message_internal(FrontendMessage::Sync).await.unwrap();
+        assert_eq!(response, vec![BackendMessage::ReadyForQuery(TransactionStatus::Failed)]);
+        
+        // COMMIT rolls the block back, on the backend as well
+        let response = connection.process_message_internal(query("COMMIT")).await.unwrap();
+        assert_eq!(response, vec![
+            BackendMessage::CommandComplete("ROLLBACK".to_string()),
+            BackendMessage::ReadyForQuery(TransactionStatus::Idle),
+        ]);
+        
+        let executed: Vec<String> = executed.lock().unwrap().iter().map(|(_, query)| query.clone()).collect();
+        assert_eq!(executed, vec!["BEGIN", "INSERT INTO t VALUES (1)", "ROLLBACK"]);
+    }
+    
+    #[tokio::test]
+    async fn test_oversized_message_rejected() {
+        let parser = MessageParser::new().with_max_message_size(1 << 20);
+        let mut buf = BytesMut::new();
+        
+        // Only the header is sent: the length alone is enough to refuse it
+        let mut reader = &[b'Q', 0x7f, 0xff, 0xff, 0xff][..];
+        let result = ClientConnection::read_message(&mut reader, &mut buf, &parser, PasswordMessageKind::Password).await;
+        assert!(matches!(result, Err(ProxyError::Protocol(_))));
+        assert!(buf.capacity() < 1 << 20);
+    }
+    
+    #[tokio::test]
+    async fn test_large_messages_within_limit() {
+        let parser = MessageParser::new().with_max_message_size(1 << 20);
+        let formatter = MessageFormatter::new();
+        
+        let query = FrontendMessage::Query(format!("SELECT '{}'", "x".repeat(500_000)));
+        let data = Bytes::from((0..900_000).map(|i| i as u8).collect::<Vec<_>>());
+        let mut stream = BytesMut::new();
+        stream.extend_from_slice(&formatter.format_frontend_message(&query).unwrap());
+        stream.extend_from_slice(&formatter.format_frontend_message(&FrontendMessage::CopyData(data.clone())).unwrap());
+        stream.extend_from_slice(&formatter.format_frontend_message(&FrontendMessage::CopyDone).unwrap());
+        
+        let mut reader = &stream[..];
+        let mut buf = BytesMut::new();
+        let kind = PasswordMessageKind::Password;
+        let message = ClientConnection::read_message(&mut reader, &mut buf, &parser, kind).await.unwrap();
+        assert_eq!(message, query);
+        
+        // The CopyData arrives in bounded pieces that add up to the original
+        let mut copied = BytesMut::new();
+        loop {
+            match ClientConnection::read_message(&mut reader, &mut buf, &parser, kind).await.unwrap() {
+                FrontendMessage::CopyData(chunk) => {
+                    assert!(chunk.len() <= COPY_DATA_CHUNK_SIZE);
+                    copied.extend_from_slice(&chunk);
+                }
+                FrontendMessage::CopyDone => break,
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+        assert_eq!(copied.freeze(), data);
+    }
+    
+    #[tokio::test]
+    async fn test_cancel_request_closes_without_reply() {
+        use tokio::net::TcpListener;
+        
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = tokio::spawn(serve_one(listener, None));
+        
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        let mut message = BytesMut::new();
+        message.put_i32(16);
+        message.put_i32(80877102);
+        message.put_i32(1234);
+        message.put_i32(5678);
+        socket.write_all(&message).await.unwrap();
+        
+        // Unknown keys are ignored and the connection is closed
+        let mut reply = Vec::new();
+        socket.read_to_end(&mut reply).await.unwrap();
+        assert!(reply.is_empty());
+        
+        let (result, _) = proxy.await.unwrap();
+        result.unwrap();
+    }
+    
     #[test]
     fn test_connection_stats() {
         let stats = ConnectionStats::default();
         assert_eq!(stats.queries_executed, 0);
         assert_eq!(stats.transactions_executed, 0);
     }
+    
+    #[tokio::test]
+    async fn test_active_connections_read_live() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (socket, addr) = listener.accept().await.unwrap();
+        let config = ProxyConfig {
+            max_connections: 4,
+            ..ProxyConfig::default()
+        };
+        let slots = Arc::new(Semaphore::new(config.max_connections));
+        let connection = ClientConnection::new(socket, addr, config, Arc::new(Mutex::new(TransactionManager::new())));
+        assert_eq!(connection.active_connections(), None);
+        
+        let _own_slot = slots.clone().try_acquire_owned().unwrap();
+        let connection = connection.with_connection_slots(slots.clone());
+        assert_eq!(connection.active_connections(), Some(1));
+        
+        // Connections accepted and closed later are reflected
+        let other_slot = slots.clone().try_acquire_owned().unwrap();
+        assert_eq!(connection.active_connections(), Some(2));
+        drop(other_slot);
+        assert_eq!(connection.active_connections(), Some(1));
+    }
 }
@@ -0,0 +1,675 @@
+//! Extended query protocol state
+//!
+//! This module tracks prepared statements (Parse) and portals (Bind) for a
+//! single client connection, so that every Execute can be mapped back to the
+//! SQL text and the exact parameter values it ran with. A portal's statement
+//! runs once, at its first Execute; an Execute with a row limit leaves the
+//! rest of the result in the portal, or in a backend cursor, for the Executes
+//! that follow. Bound values are forwarded to the backend as the client sent
+//! them, and decoded only to be recorded for replay.
+
+use crate::error::{ProxyError, Result};
+use crate::protocol::message::FieldDescription;
+use crate::verification::environment::ReplayStatement;
+use bytes::{Bytes, BytesMut};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use tokio_postgres::types::{to_sql_checked, Format, IsNull, Kind, ToSql, Type};
+use verifiable_db_core::models::Value;
+
+/// Well-known PostgreSQL type OIDs used when decoding parameters
+pub mod oids {
+    /// Unspecified type, inferred by the server
+    pub const UNKNOWN: i32 = 0;
+    /// boolean
+    pub const BOOL: i32 = 16;
+    /// bytea
+    pub const BYTEA: i32 = 17;
+    /// bigint
+    pub const INT8: i32 = 20;
+    /// smallint
+    pub const INT2: i32 = 21;
+    /// integer
+    pub const INT4: i32 = 23;
+    /// text
+    pub const TEXT: i32 = 25;
+    /// json
+    pub const JSON: i32 = 114;
+    /// real
+    pub const FLOAT4: i32 = 700;
+    /// double precision
+    pub const FLOAT8: i32 = 701;
+    /// character varying
+    pub const VARCHAR: i32 = 1043;
+    /// uuid
+    pub const UUID: i32 = 2950;
+    /// jsonb
+    pub const JSONB: i32 = 3802;
+}
+
+/// A statement created by a Parse message
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedStatement {
+    /// SQL text, possibly containing `$N` placeholders
+    pub query: String,
+
+    /// Parameter type OIDs declared by the client (0 when unspecified), or
+    /// resolved by the backend once it has described the statement
+    pub param_types: Vec<i32>,
+
+    /// Result columns, as described by the backend (empty if it returns no rows)
+    pub columns: Vec<FieldDescription>,
+}
+
+/// A portal created by a Bind message
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundPortal {
+    /// Name of the statement the portal was bound to
+    pub statement: String,
+
+    /// Decoded parameter values, in placeholder order
+    pub params: Vec<Value>,
+
+    /// Parameter values as the client bound them, in placeholder order
+    pub raw_params: Vec<RawParam>,
+
+    /// Result of the statement, once an Execute has run it
+    pub result: Option<PortalResult>,
+}
+
+/// Result of a portal's statement, fetched by one or more Executes
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PortalResult {
+    /// Values of the rows not fetched yet
+    pub rows: VecDeque<Vec<Option<Bytes>>>,
+
+    /// Tag of the statement's CommandComplete
+    pub command_tag: String,
+
+    /// Backend cursor the rows are fetched from, if the statement was opened as one
+    pub cursor: Option<String>,
+}
+
+/// A Bind parameter value, forwarded to the backend in the client's format
+///
+/// The backend decodes it by the statement's parameter type, exactly as it
+/// would have decoded it from the client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawParam {
+    /// Format code, 0 for text and 1 for binary
+    pub format: i16,
+
+    /// Value bytes, or `None` for NULL
+    pub value: Option<Bytes>,
+}
+
+impl ToSql for RawParam {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> std::result::Result<IsNull, Box<dyn Error + Sync + Send>> {
+        match &self.value {
+            Some(value) => {
+                out.extend_from_slice(value);
+                Ok(IsNull::No)
+            }
+            None => Ok(IsNull::Yes),
+        }
+    }
+
+    // The backend checks the value against the parameter's type
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    fn encode_format(&self, _ty: &Type) -> Format {
+        match self.format {
+            1 => Format::Binary,
+            _ => Format::Text,
+        }
+    }
+
+    to_sql_checked!();
+}
+
+/// Type to prepare a parameter declared with `type_oid` as
+///
+/// Unspecified parameters are prepared as `unknown`, which the server
+/// resolves from the query as it does an OID of 0.
+pub fn param_type(type_oid: i32) -> Type {
+    match u32::try_from(type_oid) {
+        Ok(0) | Err(_) => Type::UNKNOWN,
+        Ok(oid) => Type::from_oid(oid)
+            .unwrap_or_else(|| Type::new(format!("oid_{}", oid), oid, Kind::Simple, String::new())),
+    }
+}
+
+/// Rows returned by one Execute of a portal
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortalFetch {
+    /// Values of the fetched rows
+    pub rows: Vec<Vec<Option<Bytes>>>,
+
+    /// Tag completing the portal, or `None` if rows remain and it's suspended
+    pub command_tag: Option<String>,
+}
+
+/// Per-connection prepared statement and portal tracking
+#[derive(Debug, Default)]
+pub struct ExtendedQueryState {
+    /// Prepared statements by name (empty string is the unnamed statement)
+    statements: HashMap<String, PreparedStatement>,
+
+    /// Portals by name (empty string is the unnamed portal)
+    portals: HashMap<String, BoundPortal>,
+
+    /// Backend cursors opened so far, numbering the next one's name
+    cursors: u64,
+
+    /// Whether an error ended the current batch, so messages are ignored until Sync
+    skipping_until_sync: bool,
+}
+
+impl ExtendedQueryState {
+    /// Create an empty state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a Parse message
+    pub fn parse(&mut self, name: &str, query: &str, param_types: &[i32]) {
+        self.statements.insert(
+            name.to_string(),
+            PreparedStatement {
+                query: query.to_string(),
+                param_types: param_types.to_vec(),
+                columns: Vec::new(),
+            },
+        );
+    }
+
+    /// Record the parameter types and result columns the backend resolved for a statement
+    pub fn describe(&mut self, name: &str, param_types: Vec<i32>, columns: Vec<FieldDescription>) {
+        if let Some(prepared) = self.statements.get_mut(name) {
+            prepared.param_types = param_types;
+            prepared.columns = columns;
+        }
+    }
+
+    /// The statement named `name`, if parsed
+    pub fn statement(&self, name: &str) -> Option<&PreparedStatement> {
+        self.statements.get(name)
+    }
+
+    /// The statement the portal named `name` was bound to, if bound
+    pub fn portal_statement(&self, name: &str) -> Option<&PreparedStatement> {
+        self.statements.get(&self.portals.get(name)?.statement)
+    }
+
+    /// Ignore the batch's messages up to its Sync, after an error
+    ///
+    /// PostgreSQL does the same, so a client pipelining a batch doesn't run
+    /// the statements following a failed one.
+    pub fn skip_until_sync(&mut self) {
+        self.skipping_until_sync = true;
+    }
+
+    /// Whether messages are ignored until the next Sync
+    pub fn is_skipping_until_sync(&self) -> bool {
+        self.skipping_until_sync
+    }
+
+    /// Record a Sync message, which ends an errored batch
+    pub fn sync(&mut self) {
+        self.skipping_until_sync = false;
+    }
+
+    /// Record a Bind message, decoding its parameter values
+    pub fn bind(
+        &mut self,
+        portal: &str,
+        statement: &str,
+        param_formats: &[i16],
+        param_values: &[Option<Bytes>],
+    ) -> Result<()> {
+        let prepared = self.statements.get(statement).ok_or_else(|| {
+            ProxyError::Protocol(format!("Bind references unknown statement '{}'", statement))
+        })?;
+
+        let params = decode_params(&prepared.param_types, param_formats, param_values)?;
+        let raw_params = param_values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| RawParam {
+                format: param_format(param_formats, i),
+                value: value.clone(),
+            })
+            .collect();
+
+        self.portals.insert(
+            portal.to_string(),
+            BoundPortal {
+                statement: statement.to_string(),
+                params,
+                raw_params,
+                result: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// The portal named `name`, if bound
+    pub fn portal(&self, name: &str) -> Option<&BoundPortal> {
+        self.portals.get(name)
+    }
+
+    /// Resolve an Execute message to the statement and values it runs with
+    pub fn execute(&self, portal: &str) -> Result<ReplayStatement> {
+        let bound = self.portals.get(portal).ok_or_else(|| {
+            ProxyError::Protocol(format!("Execute references unknown portal '{}'", portal))
+        })?;
+
+        let prepared = self.statements.get(&bound.statement).ok_or_else(|| {
+            ProxyError::Protocol(format!("Portal '{}' references a closed statement", portal))
+        })?;
+
+        Ok(ReplayStatement::new(prepared.query.clone(), bound.params.clone()))
+    }
+
+    /// Parameter types and values to run the portal's statement with on the backend
+    pub fn backend_params(&self, portal: &str) -> Result<(Vec<Type>, Vec<RawParam>)> {
+        let bound = self.portals.get(portal).ok_or_else(|| {
+            ProxyError::Protocol(format!("Execute references unknown portal '{}'", portal))
+        })?;
+
+        let prepared = self.statements.get(&bound.statement).ok_or_else(|| {
+            ProxyError::Protocol(format!("Portal '{}' references a closed statement", portal))
+        })?;
+
+        let types = prepared.param_types.iter().copied().map(param_type).collect();
+        Ok((types, bound.raw_params.clone()))
+    }
+
+    /// Name for a new backend cursor, unique on this connection
+    pub fn next_cursor_name(&mut self) -> String {
+        self.cursors += 1;
+        format!("proxy_portal_{}", self.cursors)
+    }
+
+    /// Backend cursor holding the rest of a started portal's rows, if any
+    pub fn cursor(&self, portal: &str) -> Option<String> {
+        self.portals.get(portal)?.result.as_ref()?.cursor.clone()
+    }
+
+    /// Record that the portal's cursor was fetched to the end and closed
+    ///
+    /// Executing the portal again returns no rows, as PostgreSQL does.
+    pub fn close_cursor(&mut self, portal: &str) {
+        if let Some(result) = self.portals.get_mut(portal).and_then(|bound| bound.result.as_mut()) {
+            result.cursor = None;
+            result.rows.clear();
+            result.command_tag = "SELECT 0".to_string();
+        }
+    }
+
+    /// Whether the portal's statement already ran, so an Execute only fetches
+    pub fn is_started(&self, portal: &str) -> bool {
+        self.portals.get(portal).is_some_and(|bound| bound.result.is_some())
+    }
+
+    /// Keep the result of the portal's statement for fetching
+    pub fn start(&mut self, portal: &str, result: PortalResult) -> Result<()> {
+        let bound = self.portals.get_mut(portal).ok_or_else(|| {
+            ProxyError::Protocol(format!("Execute references unknown portal '{}'", portal))
+        })?;
+        bound.result = Some(result);
+        Ok(())
+    }
+
+    /// Fetch up to `max_rows` rows of a started portal, or all if `max_rows` is 0
+    ///
+    /// The portal completes once its last row is fetched. Fetching from a
+    /// completed portal returns no rows, as PostgreSQL does.
+    pub fn fetch(&mut self, portal: &str, max_rows: i32) -> Result<PortalFetch> {
+        let result = self
+            .portals
+            .get_mut(portal)
+            .and_then(|bound| bound.result.as_mut())
+            .ok_or_else(|| ProxyError::Protocol(format!("Portal '{}' has not been executed", portal)))?;
+
+        let count = match usize::try_from(max_rows) {
+            Ok(max_rows) if max_rows > 0 => max_rows.min(result.rows.len()),
+            _ => result.rows.len(),
+        };
+        let rows: Vec<_> = result.rows.drain(..count).collect();
+
+        // A row-limited fetch that doesn't reach the end suspends the portal
+        if !result.rows.is_empty() {
+            return Ok(PortalFetch { rows, command_tag: None });
+        }
+
+        // Row counts in a SELECT tag are those of the last fetch
+        let command_tag = match result.command_tag.split_once(' ') {
+            Some(("SELECT", _)) => format!("SELECT {}", rows.len()),
+            _ => result.command_tag.clone(),
+        };
+        Ok(PortalFetch { rows, command_tag: Some(command_tag) })
+    }
+
+    /// Drop all portals, as happens when a transaction ends
+    pub fn close_portals(&mut self) {
+        self.portals.clear();
+    }
+
+    /// Record a Close message
+    pub fn close(&mut self, object_type: u8, name: &str) {
+        match object_type {
+            b'S' => {
+                self.statements.remove(name);
+                self.portals.retain(|_, portal| portal.statement != name);
+            }
+            b'P' => {
+                self.portals.remove(name);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decode Bind parameter values into typed values
+///
+/// Format codes follow the protocol rules: no codes means all text, a single
+/// code applies to every parameter, otherwise there is one code per parameter.
+/// Binary values can only be decoded for declared types, since their encoding
+/// depends on the type.
+pub fn decode_params(
+    param_types: &[i32],
+    param_formats: &[i16],
+    param_values: &[Option<Bytes>],
+) -> Result<Vec<Value>> {
+    if param_formats.len() > 1 && param_formats.len() != param_values.len() {
+        return Err(ProxyError::Protocol(format!(
+            "Bind has {} format codes for {} parameters",
+            param_formats.len(),
+            param_values.len()
+        )));
+    }
+
+    param_values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let format = param_format(param_formats, i);
+            let type_oid = param_types.get(i).copied().unwrap_or(oids::UNKNOWN);
+
+            match value {
+                None => Ok(Value::Null),
+                Some(bytes) if format == 0 => decode_text_param(type_oid, bytes),
+                Some(bytes) if format == 1 => decode_binary_param(type_oid, bytes).map_err(|e| match e {
+                    ProxyError::Protocol(message) => {
+                        ProxyError::Protocol(format!("{} (parameter ${})", message, i + 1))
+                    }
+                    e => e,
+                }),
+                Some(_) => Err(ProxyError::Protocol(format!(
+                    "Unknown format code {} for parameter ${}",
+                    format,
+                    i + 1
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// Format code of parameter `i`: none means text, one applies to every parameter
+fn param_format(param_formats: &[i16], i: usize) -> i16 {
+    match param_formats.len() {
+        0 => 0,
+        1 => param_formats[0],
+        _ => param_formats.get(i).copied().unwrap_or(0),
+    }
+}
+
+/// Decode a single text-format parameter
+fn decode_text_param(type_oid: i32, bytes: &Bytes) -> Result<Value> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|e| ProxyError::Protocol(format!("Invalid UTF-8 in text parameter: {}", e)))?;
+
+    let invalid = |kind: &str| ProxyError::Protocol(format!("Invalid {} parameter: {}", kind, text));
+
+    let value = match type_oid {
+        oids::INT2 | oids::INT4 => Value::Integer(text.trim().parse().map_err(|_| invalid("integer"))?),
+        oids::INT8 => Value::BigInt(text.trim().parse().map_err(|_| invalid("bigint"))?),
+        oids::FLOAT4 | oids::FLOAT8 => Value::Float(text.trim().parse().map_err(|_| invalid("float"))?),
+        oids::BOOL => match text.trim() {
+            "t" | "true" | "1" | "on" | "yes" => Value::Boolean(true),
+            "f" | "false" | "0" | "off" | "no" => Value::Boolean(false),
+            _ => return Err(invalid("boolean")),
+        },
+        oids::UUID => Value::Uuid(text.trim().parse().map_err(|_| invalid("uuid"))?),
+        oids::JSON | oids::JSONB => Value::Json(text.to_string()),
+        // Text, varchar and unspecified types are kept as text for the server to coerce
+        _ => Value::Text(text.to_string()),
+    };
+
+    Ok(value)
+}
+
+/// Decode a single binary-format parameter
+fn decode_binary_param(type_oid: i32, bytes: &Bytes) -> Result<Value> {
+    let text = |bytes: &[u8]| {
+        String::from_utf8(bytes.to_vec())
+            .map_err(|e| ProxyError::Protocol(format!("Invalid UTF-8 in binary parameter: {}", e)))
+    };
+
+    let value = match type_oid {
+        oids::INT2 => Value::Integer(i16::from_be_bytes(fixed_width(bytes, "smallint")?) as i32),
+        oids::INT4 => Value::Integer(i32::from_be_bytes(fixed_width(bytes, "integer")?)),
+        oids::INT8 => Value::BigInt(i64::from_be_bytes(fixed_width(bytes, "bigint")?)),
+        oids::FLOAT4 => Value::Float(f32::from_be_bytes(fixed_width(bytes, "real")?) as f64),
+        oids::FLOAT8 => Value::Float(f64::from_be_bytes(fixed_width(bytes, "double")?)),
+        oids::BOOL => Value::Boolean(fixed_width::<1>(bytes, "boolean")?[0] != 0),
+        oids::UUID => Value::Uuid(uuid::Uuid::from_bytes(fixed_width(bytes, "uuid")?)),
+        oids::BYTEA => Value::Binary(bytes.to_vec()),
+        oids::TEXT | oids::VARCHAR => Value::Text(text(bytes)?),
+        oids::JSON => Value::Json(text(bytes)?),
+        // jsonb is its text form behind a version byte
+        oids::JSONB => match bytes.split_first() {
+            Some((1, json)) => Value::Json(text(json)?),
+            _ => return Err(ProxyError::Protocol("Unsupported binary jsonb version".to_string())),
+        },
+        _ => {
+            return Err(ProxyError::Protocol(format!(
+                "Binary format is not supported for type OID {}",
+                type_oid
+            )))
+        }
+    };
+
+    Ok(value)
+}
+
+/// The bytes of a fixed-width binary parameter, checked to be `N` long
+fn fixed_width<const N: usize>(bytes: &[u8], kind: &str) -> Result<[u8; N]> {
+    bytes.try_into().map_err(|_| {
+        ProxyError::Protocol(format!("Invalid binary {} parameter of {} bytes", kind, bytes.len()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_each_execute_carries_its_bound_values() {
+        let mut state = ExtendedQueryState::new();
+        state.parse("ins", "INSERT INTO t VALUES ($1)", &[oids::INT4]);
+
+        state.bind("", "ins", &[], &[Some(Bytes::from("1"))]).unwrap();
+        let first = state.execute("").unwrap();
+
+        state.bind("", "ins", &[], &[Some(Bytes::from("2"))]).unwrap();
+        let second = state.execute("").unwrap();
+
+        assert_eq!(first.query, "INSERT INTO t VALUES ($1)");
+        assert_eq!(first.params, vec![Value::Integer(1)]);
+        assert_eq!(second.query, "INSERT INTO t VALUES ($1)");
+        assert_eq!(second.params, vec![Value::Integer(2)]);
+    }
+
+    #[test]
+    fn test_decode_text_params() {
+        let params = decode_params(
+            &[oids::INT8, oids::BOOL, oids::UNKNOWN],
+            &[0],
+            &[Some(Bytes::from("42")), Some(Bytes::from("t")), None],
+        )
+        .unwrap();
+
+        assert_eq!(params, vec![Value::BigInt(42), Value::Boolean(true), Value::Null]);
+
+        // A malformed integer is a protocol error, not a silent NULL
+        assert!(decode_params(&[oids::INT4], &[], &[Some(Bytes::from("abc"))]).is_err());
+    }
+
+    #[test]
+    fn test_decode_binary_params() {
+        let id = uuid::Uuid::new_v4();
+        let params = decode_params(
+            &[oids::INT4, oids::INT8, oids::FLOAT8, oids::BOOL, oids::TEXT, oids::BYTEA, oids::UUID, oids::JSONB],
+            &[1],
+            &[
+                Some(Bytes::copy_from_slice(&(-7i32).to_be_bytes())),
+                Some(Bytes::copy_from_slice(&(1i64 << 40).to_be_bytes())),
+                Some(Bytes::copy_from_slice(&2.5f64.to_be_bytes())),
+                Some(Bytes::from_static(&[1])),
+                Some(Bytes::from("héllo")),
+                Some(Bytes::from_static(&[0, 159, 255])),
+                Some(Bytes::copy_from_slice(id.as_bytes())),
+                Some(Bytes::from_static(b"\x01{\"a\": 1}")),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            params,
+            vec![
+                Value::Integer(-7),
+                Value::BigInt(1 << 40),
+                Value::Float(2.5),
+                Value::Boolean(true),
+                Value::Text("héllo".to_string()),
+                Value::Binary(vec![0, 159, 255]),
+                Value::Uuid(id),
+                Value::Json("{\"a\": 1}".to_string()),
+            ]
+        );
+
+        // Text and binary can be mixed per parameter
+        let params = decode_params(
+            &[oids::INT4, oids::INT2],
+            &[0, 1],
+            &[Some(Bytes::from("12")), Some(Bytes::copy_from_slice(&300i16.to_be_bytes()))],
+        )
+        .unwrap();
+        assert_eq!(params, vec![Value::Integer(12), Value::Integer(300)]);
+
+        // Wrong widths and undeclared types can't be decoded
+        assert!(decode_params(&[oids::INT4], &[1], &[Some(Bytes::from_static(&[0, 1]))]).is_err());
+        assert!(decode_params(&[oids::UNKNOWN], &[1], &[Some(Bytes::from_static(&[0, 1]))]).is_err());
+        assert!(decode_params(&[oids::INT4], &[2], &[Some(Bytes::from_static(&[0, 0, 0, 1]))]).is_err());
+    }
+
+    #[test]
+    fn test_params_forwarded_as_bound() {
+        let mut state = ExtendedQueryState::new();
+        state.parse("", "SELECT $1, $2, $3", &[oids::INT2, oids::UNKNOWN]);
+        state
+            .bind(
+                "",
+                "",
+                &[1, 0, 0],
+                &[Some(Bytes::copy_from_slice(&7i16.to_be_bytes())), Some(Bytes::from("1.5")), None],
+            )
+            .unwrap();
+
+        // Declared types are kept, unspecified ones left for the server to infer
+        let (types, params) = state.backend_params("").unwrap();
+        assert_eq!(types, vec![Type::INT2, Type::UNKNOWN]);
+
+        // Values go out byte for byte, in the format they were bound in
+        let mut out = BytesMut::new();
+        assert!(matches!(params[0].to_sql(&Type::INT2, &mut out).unwrap(), IsNull::No));
+        assert!(matches!(params[0].encode_format(&Type::INT2), Format::Binary));
+        assert!(matches!(params[1].encode_format(&Type::FLOAT4), Format::Text));
+        assert!(matches!(params[2].to_sql(&Type::TEXT, &mut out).unwrap(), IsNull::Yes));
+        assert_eq!(&out[..], &7i16.to_be_bytes());
+
+        assert_eq!(param_type(600), Type::POINT);
+        assert_eq!(param_type(987654).oid(), 987654);
+    }
+
+    #[test]
+    fn test_row_limited_fetches() {
+        let mut state = ExtendedQueryState::new();
+        state.parse("", "SELECT n FROM t", &[]);
+        state.bind("", "", &[], &[]).unwrap();
+        assert!(!state.is_started(""));
+
+        let row = |n: &'static str| vec![Some(Bytes::from(n))];
+        state.start("", PortalResult {
+            rows: VecDeque::from([row("1"), row("2"), row("3")]),
+            command_tag: "SELECT 3".to_string(),
+            cursor: None,
+        }).unwrap();
+        assert!(state.is_started(""));
+
+        // Two rows suspend the portal, the rest completes it
+        assert_eq!(state.fetch("", 2).unwrap(), PortalFetch { rows: vec![row("1"), row("2")], command_tag: None });
+        assert_eq!(
+            state.fetch("", 2).unwrap(),
+            PortalFetch { rows: vec![row("3")], command_tag: Some("SELECT 1".to_string()) }
+        );
+        assert_eq!(state.fetch("", 0).unwrap(), PortalFetch { rows: vec![], command_tag: Some("SELECT 0".to_string()) });
+
+        // Binding again replaces the portal, so the statement runs again
+        state.bind("", "", &[], &[]).unwrap();
+        assert!(!state.is_started(""));
+        assert!(state.fetch("", 0).is_err());
+    }
+
+    #[test]
+    fn test_statements_described_by_backend() {
+        let mut state = ExtendedQueryState::new();
+        state.parse("s1", "SELECT $1::int8 AS n", &[]);
+        let column = FieldDescription {
+            name: "n".to_string(),
+            table_oid: 0,
+            column_id: 0,
+            data_type_oid: oids::INT8,
+            data_type_size: 0,
+            type_modifier: -1,
+            format_code: 0,
+        };
+        state.describe("s1", vec![oids::INT8], vec![column.clone()]);
+
+        // Binary values decode by the resolved type, though the client declared none
+        state.bind("p1", "s1", &[1], &[Some(Bytes::copy_from_slice(&5i64.to_be_bytes()))]).unwrap();
+        assert_eq!(state.execute("p1").unwrap().params, vec![Value::BigInt(5)]);
+
+        let statement = state.portal_statement("p1").unwrap();
+        assert_eq!(statement.param_types, vec![oids::INT8]);
+        assert_eq!(statement.columns, vec![column]);
+        assert!(state.statement("s2").is_none());
+    }
+
+    #[test]
+    fn test_unknown_statement_and_portal() {
+        let mut state = ExtendedQueryState::new();
+        assert!(state.bind("", "missing", &[], &[]).is_err());
+        assert!(state.execute("missing").is_err());
+
+        state.parse("s1", "SELECT $1", &[]);
+        state.bind("p1", "s1", &[], &[Some(Bytes::from("x"))]).unwrap();
+        state.close(b'S', "s1");
+        assert!(state.execute("p1").is_err());
+    }
+}
@@ -1,16 +1,21 @@
 //! Message formatter for PostgreSQL wire protocol responses
 //!
 //! This module provides formatting functionality for PostgreSQL backend messages,
-//! converting them to the wire format that can be sent to clients.
+//! converting them to the wire format that can be sent to clients, and for the
+//! frontend messages of the extended query protocol the proxy sends on to the
+//! server.
 
 use crate::error::{ProxyError, Result};
 use crate::protocol::message::{
-    AuthenticationRequest, BackendMessage, ErrorOrNoticeFields, TransactionStatus,
+    AuthenticationRequest, BackendMessage, ErrorOrNoticeFields, FrontendMessage, TransactionStatus,
 };
 use bytes::{BufMut, Bytes, BytesMut};
 use log::debug;
 use std::collections::HashMap;
 
+/// Size from which `DataRow` values are written as they are rather than copied
+const STREAMED_VALUE_SIZE: usize = 8 * 1024;
+
 /// Message formatter for PostgreSQL wire protocol responses
 #[derive(Debug, Default, Clone)]
 pub struct MessageFormatter {}
@@ -21,6 +26,51 @@ impl MessageFormatter {
         Self {}
     }
 
+    /// Format a backend message as a sequence of chunks to write in order
+    ///
+    /// Large `DataRow` values are passed through as their own chunks instead
+    /// of being copied into one buffer with the rest of the row, so sending a
+    /// multi-megabyte row doesn't need a second copy of it in memory. Other
+    /// messages are a single chunk.
+    pub fn format_backend_message_chunks(&self, message: &BackendMessage) -> Result<Vec<Bytes>> {
+        let BackendMessage::DataRow(values) = message else {
+            return Ok(vec![self.format_backend_message(message)?]);
+        };
+        
+        let length = values.iter().fold(4 + 2, |length, value| {
+            length + 4 + value.as_ref().map_or(0, |bytes| bytes.len())
+        });
+        let length = i32::try_from(length)
+            .map_err(|_| ProxyError::Protocol(format!("DataRow of {} bytes is too large to send", length)))?;
+        
+        let mut chunks = Vec::new();
+        let mut buffer = BytesMut::new();
+        buffer.put_u8(b'D');
+        buffer.put_i32(length);
+        buffer.put_i16(values.len() as i16);
+        for value in values {
+            match value {
+                Some(bytes) if bytes.len() >= STREAMED_VALUE_SIZE => {
+                    buffer.put_i32(bytes.len() as i32);
+                    chunks.push(buffer.split().freeze());
+                    chunks.push(bytes.clone());
+                },
+                Some(bytes) => {
+                    buffer.put_i32(bytes.len() as i32);
+                    buffer.put_slice(bytes);
+                },
+                None => {
+                    buffer.put_i32(-1); // NULL value
+                },
+            }
+        }
+        if !buffer.is_empty() {
+            chunks.push(buffer.freeze());
+        }
+        
+        Ok(chunks)
+    }
+
     /// Format a backend message for sending to the client
     pub fn format_backend_message(&self, message: &BackendMessage) -> Result<Bytes> {
         let mut buffer = BytesMut::new();
@@ -189,6 +239,123 @@ impl MessageFormatter {
         Ok(buffer.freeze())
     }
 
+    /// Format a frontend message for sending to the server
+    ///
+    /// Covers the simple and extended query messages; startup, authentication
+    /// and COPY messages are passed through as received and can't be formatted.
+    pub fn format_frontend_message(&self, message: &FrontendMessage) -> Result<Bytes> {
+        let mut buffer = BytesMut::new();
+        
+        match message {
+            FrontendMessage::Query(query) => {
+                self.write_message_with_type(&mut buffer, b'Q', |buf| {
+                    self.write_string(buf, query);
+                })?;
+            },
+            FrontendMessage::Parse { name, query, param_types } => {
+                let param_count = Self::count(param_types.len())?;
+                self.write_message_with_type(&mut buffer, b'P', |buf| {
+                    self.write_string(buf, name);
+                    self.write_string(buf, query);
+                    buf.put_i16(param_count);
+                    for param_type in param_types {
+                        buf.put_i32(*param_type);
+                    }
+                })?;
+            },
+            FrontendMessage::Bind { portal, statement, param_formats, param_values, result_formats } => {
+                let format_count = Self::count(param_formats.len())?;
+                let param_count = Self::count(param_values.len())?;
+                let result_format_count = Self::count(result_formats.len())?;
+                self.write_message_with_type(&mut buffer, b'B', |buf| {
+                    self.write_string(buf, portal);
+                    self.write_string(buf, statement);
+                    buf.put_i16(format_count);
+                    for format in param_formats {
+                        buf.put_i16(*format);
+                    }
+                    buf.put_i16(param_count);
+                    for value in param_values {
+                        match value {
+                            Some(value) => {
+                                buf.put_i32(value.len() as i32);
+                                buf.put_slice(value);
+                            },
+                            None => buf.put_i32(-1),
+                        }
+                    }
+                    buf.put_i16(result_format_count);
+                    for format in result_formats {
+                        buf.put_i16(*format);
+                    }
+                })?;
+            },
+            FrontendMessage::Describe { object_type, name } => {
+                self.write_message_with_type(&mut buffer, b'D', |buf| {
+                    buf.put_u8(*object_type);
+                    self.write_string(buf, name);
+                })?;
+            },
+            FrontendMessage::Execute { portal, max_rows } => {
+                self.write_message_with_type(&mut buffer, b'E', |buf| {
+                    self.write_string(buf, portal);
+                    buf.put_i32(*max_rows);
+                })?;
+            },
+            FrontendMessage::Close { object_type, name } => {
+                self.write_message_with_type(&mut buffer, b'C', |buf| {
+                    buf.put_u8(*object_type);
+                    self.write_string(buf, name);
+                })?;
+            },
+            FrontendMessage::Sync => {
+                self.write_message_with_type(&mut buffer, b'S', |_| {})?;
+            },
+            FrontendMessage::Flush => {
+                self.write_message_with_type(&mut buffer, b'H', |_| {})?;
+            },
+            FrontendMessage::Terminate => {
+                self.write_message_with_type(&mut buffer, b'X', |_| {})?;
+            },
+            FrontendMessage::CopyData(data) => {
+                self.write_message_with_type(&mut buffer, b'd', |buf| {
+                    buf.put_slice(data);
+                })?;
+            },
+            FrontendMessage::CopyDone => {
+                self.write_message_with_type(&mut buffer, b'c', |_| {})?;
+            },
+            FrontendMessage::CopyFail(message) => {
+                self.write_message_with_type(&mut buffer, b'f', |buf| {
+                    self.write_string(buf, message);
+                })?;
+            },
+            FrontendMessage::SaslInitialResponse { mechanism, data } => {
+                self.write_message_with_type(&mut buffer, b'p', |buf| {
+                    self.write_string(buf, mechanism);
+                    match data {
+                        Some(data) => {
+                            buf.put_i32(data.len() as i32);
+                            buf.put_slice(data);
+                        }
+                        None => buf.put_i32(-1),
+                    }
+                })?;
+            },
+            FrontendMessage::SaslResponse(data) => {
+                self.write_message_with_type(&mut buffer, b'p', |buf| {
+                    buf.put_slice(data);
+                })?;
+            },
+            other => {
+                return Err(ProxyError::Protocol(format!("Cannot format frontend message: {:?}", other)));
+            },
+        }
+        
+        debug!("Formatted frontend message: {:?} ({} bytes)", message, buffer.len());
+        Ok(buffer.freeze())
+    }
+
     /// Format an error response with the specified severity, code, and message
     pub fn format_error_response(&self, severity: &str, code: &str, message: &str) -> Bytes {
         let mut fields = ErrorOrNoticeFields {
@@ -315,6 +482,11 @@ impl MessageFormatter {
         })
     }
 
+    /// Element count of a message list, which the protocol sends as an i16
+    fn count(len: usize) -> Result<i16> {
+        i16::try_from(len).map_err(|_| ProxyError::Protocol(format!("Too many elements for one message: {}", len)))
+    }
+
     /// Write a null-terminated string
     fn write_string(&self, buffer: &mut BytesMut, string: &str) {
         buffer.put_slice(string.as_bytes());
@@ -353,6 +525,29 @@ mod tests {
         assert_eq!(&bytes[5..14], b"SELECT 1\0"); // Include the null terminator in the comparison
     }
 
+    #[test]
+    fn test_large_data_row_chunks() {
+        let formatter = MessageFormatter::new();
+        let large = Bytes::from(vec![b'x'; 4 << 20]);
+        let message = BackendMessage::DataRow(vec![
+            Some(Bytes::from_static(b"id")),
+            Some(large.clone()),
+            None,
+            Some(Bytes::from_static(b"tail")),
+        ]);
+        
+        let chunks = formatter.format_backend_message_chunks(&message).unwrap();
+        assert_eq!(chunks.concat(), formatter.format_backend_message(&message).unwrap());
+        
+        // The large value is sent as it is, not copied
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].as_ptr(), large.as_ptr());
+        
+        // Other messages are a single chunk
+        let message = BackendMessage::CommandComplete("SELECT 1".to_string());
+        assert_eq!(formatter.format_backend_message_chunks(&message).unwrap().len(), 1);
+    }
+
     #[test]
     fn test_format_error_response() {
         let formatter = MessageFormatter::new();
@@ -29,6 +29,20 @@ pub enum FrontendMessage {
     /// Password message (in response to authentication request)
     Password(String),
     
+    /// GSSAPI/SSPI response (raw token, sent with the password message tag)
+    GssResponse(Bytes),
+    
+    /// SASL initial response, naming the chosen mechanism (password message tag)
+    SaslInitialResponse {
+        /// SASL mechanism, e.g. `SCRAM-SHA-256`
+        mechanism: String,
+        /// Mechanism-specific initial response, if any
+        data: Option<Bytes>,
+    },
+    
+    /// SASL response continuing an exchange (password message tag)
+    SaslResponse(Bytes),
+    
     /// Query message (simple query protocol)
     Query(String),
     
@@ -24,6 +24,12 @@ pub mod validator;
 /// Transaction manager for PostgreSQL transactions
 pub mod transaction;
 
+/// Prepared statement and portal tracking for the extended query protocol
+pub mod extended;
+
+/// TLS termination for client connections
+pub mod tls;
+
 // Re-export common types
 pub use self::message::{FrontendMessage, BackendMessage, AuthenticationRequest};
 pub use self::parser::MessageParser;
@@ -31,4 +37,6 @@ pub use self::formatter::MessageFormatter;
 pub use self::connection::{ClientConnection, ConnectionState, ConnectionStats};
 pub use self::auth::{AuthHandler, AuthState, AuthMethod, AuthConfig};
 pub use self::transaction::{TransactionTracker, TransactionState, IsolationLevel, AccessMode};
-pub use self::validator::{ProtocolValidator, ProtocolValidatorConfig}; 
\ No newline at end of file
+pub use self::validator::{ProtocolValidator, ProtocolValidatorConfig};
+pub use self::extended::{ExtendedQueryState, PreparedStatement, BoundPortal};
+pub use self::tls::{ClientStream, tls_acceptor}; 
\ No newline at end of file
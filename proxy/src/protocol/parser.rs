@@ -10,13 +10,59 @@ use std::convert::TryInto;
 use std::io::Cursor;
 
 /// Message parser for PostgreSQL wire protocol
-#[derive(Clone)]
-pub struct MessageParser;
+#[derive(Clone, Default)]
+pub struct MessageParser {
+    /// Largest frontend message accepted, if limited
+    max_message_size: Option<usize>,
+}
 
 impl MessageParser {
     /// Create a new message parser
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+    
+    /// Reject frontend messages longer than `max_message_size` bytes
+    ///
+    /// The limit is checked against the length prefix, before any of the
+    /// body is buffered, so a bogus length can't force a huge allocation.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = Some(max_message_size);
+        self
+    }
+    
+    /// Length of the first frontend message in `bytes`, once its length is known
+    ///
+    /// Startup-phase messages (startup, SSL and cancel requests) have no type
+    /// byte, and their length field starts with a zero byte since it is far
+    /// below 2^24; every other message is a type byte followed by its length.
+    /// Returns `None` until enough bytes have arrived to tell.
+    pub fn frontend_message_length(&self, bytes: &[u8]) -> Result<Option<usize>> {
+        let (offset, header) = match bytes.first() {
+            None => return Ok(None),
+            Some(0) => (0, 0),
+            Some(_) => (1, 1),
+        };
+        
+        let Some(length) = bytes.get(offset..offset + 4) else {
+            return Ok(None);
+        };
+        let length = i32::from_be_bytes(length.try_into().unwrap());
+        if length < 4 {
+            return Err(ProxyError::Protocol(format!("Invalid message length: {}", length)));
+        }
+        
+        let length = header + length as usize;
+        if let Some(max_message_size) = self.max_message_size {
+            if length > max_message_size {
+                return Err(ProxyError::Protocol(format!(
+                    "Message length {} exceeds maximum of {}",
+                    length, max_message_size
+                )));
+            }
+        }
+        
+        Ok(Some(length))
     }
     
     /// Parse a frontend message from bytes
@@ -125,20 +171,20 @@ impl MessageParser {
             // Parse message
             b'P' => {
                 // Length is the next 4 bytes
-                let _length = cursor.get_u32();
+                let _length = self.read_i32(&mut cursor)?;
                 
-                // Statement name
+                // Statement name (empty for the unnamed statement)
                 let name = self.read_cstring(&mut cursor)?;
                 
                 // Query string
                 let query = self.read_cstring(&mut cursor)?;
                 
                 // Parameter types
-                let param_count = cursor.get_i16() as usize;
+                let param_count = self.read_count(&mut cursor)?;
                 let mut param_types = Vec::with_capacity(param_count);
                 
                 for _ in 0..param_count {
-                    param_types.push(cursor.get_i32());
+                    param_types.push(self.read_i32(&mut cursor)?);
                 }
                 
                 Ok(FrontendMessage::Parse {
@@ -151,49 +197,52 @@ impl MessageParser {
             // Bind message
             b'B' => {
                 // Length is the next 4 bytes
-                let _length = cursor.get_u32();
+                let _length = self.read_i32(&mut cursor)?;
                 
-                // Portal name
+                // Portal name (empty for the unnamed portal)
                 let portal = self.read_cstring(&mut cursor)?;
                 
                 // Statement name
                 let statement = self.read_cstring(&mut cursor)?;
                 
                 // Parameter format codes
-                let format_count = cursor.get_i16() as usize;
+                let format_count = self.read_count(&mut cursor)?;
                 let mut param_formats = Vec::with_capacity(format_count);
                 
                 for _ in 0..format_count {
-                    param_formats.push(cursor.get_i16());
+                    param_formats.push(self.read_i16(&mut cursor)?);
                 }
                 
                 // Parameter values
-                let param_count = cursor.get_i16() as usize;
+                let param_count = self.read_count(&mut cursor)?;
                 let mut param_values = Vec::with_capacity(param_count);
                 
-                for _ in 0..param_count {
-                    let param_length = cursor.get_i32();
+                for i in 0..param_count {
+                    let param_length = self.read_i32(&mut cursor)?;
                     
                     if param_length == -1 {
                         // NULL value
                         param_values.push(None);
                     } else {
                         // Non-NULL value
-                        let param_length = param_length as usize;
-                        let mut param_value = BytesMut::with_capacity(param_length);
-                        let mut param_data = vec![0u8; param_length];
-                        cursor.copy_to_slice(&mut param_data);
-                        param_value.extend_from_slice(&param_data);
-                        param_values.push(Some(param_value.freeze()));
+                        let param_length = usize::try_from(param_length).map_err(|_| {
+                            ProxyError::Protocol(format!("Invalid length {} for parameter ${}", param_length, i + 1))
+                        })?;
+                        if cursor.remaining() < param_length {
+                            return Err(ProxyError::Protocol("Truncated Bind message".to_string()));
+                        }
+                        let position = cursor.position() as usize;
+                        param_values.push(Some(bytes.slice(position..position + param_length)));
+                        cursor.advance(param_length);
                     }
                 }
                 
                 // Result format codes
-                let result_format_count = cursor.get_i16() as usize;
+                let result_format_count = self.read_count(&mut cursor)?;
                 let mut result_formats = Vec::with_capacity(result_format_count);
                 
                 for _ in 0..result_format_count {
-                    result_formats.push(cursor.get_i16());
+                    result_formats.push(self.read_i16(&mut cursor)?);
                 }
                 
                 Ok(FrontendMessage::Bind {
@@ -208,10 +257,10 @@ impl MessageParser {
             // Describe message
             b'D' => {
                 // Length is the next 4 bytes
-                let _length = cursor.get_u32();
+                let _length = self.read_i32(&mut cursor)?;
                 
                 // Object type (S for statement, P for portal)
-                let object_type = cursor.get_u8();
+                let object_type = self.read_object_type(&mut cursor)?;
                 
                 // Object name
                 let name = self.read_cstring(&mut cursor)?;
@@ -225,13 +274,13 @@ impl MessageParser {
             // Execute message
             b'E' => {
                 // Length is the next 4 bytes
-                let _length = cursor.get_u32();
+                let _length = self.read_i32(&mut cursor)?;
                 
                 // Portal name
                 let portal = self.read_cstring(&mut cursor)?;
                 
                 // Maximum row count
-                let max_rows = cursor.get_i32();
+                let max_rows = self.read_i32(&mut cursor)?;
                 
                 Ok(FrontendMessage::Execute {
                     portal,
@@ -255,7 +304,7 @@ impl MessageParser {
                 let _length = cursor.get_u32();
                 
                 // Object type (S for statement, P for portal)
-                let object_type = cursor.get_u8();
+                let object_type = self.read_object_type(&mut cursor)?;
                 
                 // Object name
                 let name = self.read_cstring(&mut cursor)?;
@@ -273,14 +322,18 @@ impl MessageParser {
             
             // Copy data message
             b'd' => {
-                // Length is the next 4 bytes
-                let length = cursor.get_u32() as usize - 4;
-                let mut data = BytesMut::with_capacity(length);
-                let mut data_bytes = vec![0u8; length];
-                cursor.copy_to_slice(&mut data_bytes);
-                data.extend_from_slice(&data_bytes);
+                // Length is the next 4 bytes; the rest is the data
+                let length = self.read_i32(&mut cursor)?;
+                let length = usize::try_from(length)
+                    .ok()
+                    .and_then(|length| length.checked_sub(4))
+                    .ok_or_else(|| ProxyError::Protocol(format!("Invalid CopyData length: {}", length)))?;
+                if cursor.remaining() < length {
+                    return Err(ProxyError::Protocol("Truncated message".to_string()));
+                }
                 
-                Ok(FrontendMessage::CopyData(data.freeze()))
+                let start = cursor.position() as usize;
+                Ok(FrontendMessage::CopyData(bytes.slice(start..start + length)))
             }
             
             // Copy done message
@@ -291,7 +344,7 @@ impl MessageParser {
             // Copy fail message
             b'f' => {
                 // Length is the next 4 bytes
-                let _length = cursor.get_u32();
+                let _length = self.read_i32(&mut cursor)?;
                 
                 // Error message
                 let error_message = self.read_cstring(&mut cursor)?;
@@ -358,6 +411,71 @@ impl MessageParser {
         }
     }
     
+    /// Parse a GSSAPI response from bytes
+    ///
+    /// GSSAPI responses share the `p` tag with password messages but carry a
+    /// binary token, so callers use this while a GSSAPI exchange is in progress.
+    pub fn parse_gss_response(&self, bytes: &Bytes) -> Result<FrontendMessage> {
+        let body = self.password_message_body(bytes, "GSSAPI response")?;
+        Ok(FrontendMessage::GssResponse(body))
+    }
+    
+    /// Parse a SASL initial response from bytes
+    ///
+    /// Like GSSAPI responses these share the `p` tag, so callers use this for
+    /// the first client message after an `AuthenticationSASL` request.
+    pub fn parse_sasl_initial_response(&self, bytes: &Bytes) -> Result<FrontendMessage> {
+        let body = self.password_message_body(bytes, "SASL initial response")?;
+        let mut cursor = Cursor::new(&body);
+        
+        let mechanism = self.read_cstring(&mut cursor)?;
+        let length = self.read_i32(&mut cursor)?;
+        let start = cursor.position() as usize;
+        let data = match usize::try_from(length) {
+            Ok(length) if start + length == body.len() => Some(body.slice(start..start + length)),
+            Err(_) if length == -1 && start == body.len() => None,
+            _ => {
+                return Err(ProxyError::Protocol(format!(
+                    "Invalid SASL initial response length: {}",
+                    length
+                )));
+            }
+        };
+        
+        Ok(FrontendMessage::SaslInitialResponse { mechanism, data })
+    }
+    
+    /// Parse a SASL response from bytes, continuing a SASL exchange
+    pub fn parse_sasl_response(&self, bytes: &Bytes) -> Result<FrontendMessage> {
+        let body = self.password_message_body(bytes, "SASL response")?;
+        Ok(FrontendMessage::SaslResponse(body))
+    }
+    
+    /// Body of a complete `p` message, whose contents depend on the exchange
+    fn password_message_body(&self, bytes: &Bytes, expected: &str) -> Result<Bytes> {
+        if bytes.len() < 5 {
+            return Err(ProxyError::Incomplete);
+        }
+        
+        if bytes[0] != b'p' {
+            return Err(ProxyError::Protocol(format!(
+                "Expected {}, got message type {}",
+                expected,
+                bytes[0] as char
+            )));
+        }
+        
+        let length = u32::from_be_bytes(bytes[1..5].try_into().unwrap()) as usize;
+        if length < 4 {
+            return Err(ProxyError::Protocol(format!("Invalid {} length", expected)));
+        }
+        if bytes.len() < 1 + length {
+            return Err(ProxyError::Incomplete);
+        }
+        
+        Ok(bytes.slice(5..1 + length))
+    }
+    
     /// Parse a backend message from bytes
     pub fn parse_backend_message(&self, bytes: &Bytes) -> Result<BackendMessage> {
         let mut cursor = Cursor::new(bytes);
@@ -827,6 +945,39 @@ impl MessageParser {
         String::from_utf8(bytes).map_err(|e| ProxyError::Protocol(format!("Invalid UTF-8: {}", e)))
     }
     
+    /// Read a big-endian 16-bit integer
+    fn read_i16(&self, cursor: &mut Cursor<&Bytes>) -> Result<i16> {
+        if cursor.remaining() < 2 {
+            return Err(ProxyError::Protocol("Truncated message".to_string()));
+        }
+        Ok(cursor.get_i16())
+    }
+    
+    /// Read a big-endian 32-bit integer
+    fn read_i32(&self, cursor: &mut Cursor<&Bytes>) -> Result<i32> {
+        if cursor.remaining() < 4 {
+            return Err(ProxyError::Protocol("Truncated message".to_string()));
+        }
+        Ok(cursor.get_i32())
+    }
+    
+    /// Read a 16-bit element count, which must not be negative
+    fn read_count(&self, cursor: &mut Cursor<&Bytes>) -> Result<usize> {
+        let count = self.read_i16(cursor)?;
+        usize::try_from(count).map_err(|_| ProxyError::Protocol(format!("Invalid count: {}", count)))
+    }
+    
+    /// Read the object type of a Describe or Close message
+    fn read_object_type(&self, cursor: &mut Cursor<&Bytes>) -> Result<u8> {
+        if !cursor.has_remaining() {
+            return Err(ProxyError::Protocol("Truncated message".to_string()));
+        }
+        match cursor.get_u8() {
+            object_type @ (b'S' | b'P') => Ok(object_type),
+            other => Err(ProxyError::Protocol(format!("Invalid object type: {:?}", other as char))),
+        }
+    }
+    
     /// Parse error and notice fields
     fn parse_error_fields(&self, cursor: &mut Cursor<&Bytes>) -> Result<ErrorOrNoticeFields> {
         let mut fields = ErrorOrNoticeFields::default();
@@ -879,8 +1030,15 @@ impl MessageParser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocol::formatter::MessageFormatter;
     use bytes::BufMut;
     
+    /// Format `message` and parse it back
+    fn round_trip(message: &FrontendMessage) -> FrontendMessage {
+        let bytes = MessageFormatter::new().format_frontend_message(message).unwrap();
+        MessageParser::new().parse_frontend_message(&bytes).unwrap()
+    }
+    
     #[test]
     fn test_parse_startup_message() {
         let mut buf = BytesMut::new();
@@ -980,4 +1138,160 @@ mod tests {
             _ => panic!("Expected ErrorResponse message"),
         }
     }
-} 
\ No newline at end of file
+    
+    #[test]
+    fn test_extended_query_round_trip() {
+        let messages = vec![
+            FrontendMessage::Parse {
+                name: "insert_user".to_string(),
+                query: "INSERT INTO users (id, name) VALUES ($1, $2)".to_string(),
+                param_types: vec![23, 25],
+            },
+            // Unnamed statement, types left to the server
+            FrontendMessage::Parse {
+                name: String::new(),
+                query: "SELECT name FROM users WHERE id = $1".to_string(),
+                param_types: vec![],
+            },
+            FrontendMessage::Bind {
+                portal: String::new(),
+                statement: "insert_user".to_string(),
+                param_formats: vec![0],
+                param_values: vec![Some(Bytes::from_static(b"42")), None],
+                result_formats: vec![1],
+            },
+            FrontendMessage::Describe { object_type: b'S', name: "insert_user".to_string() },
+            FrontendMessage::Describe { object_type: b'P', name: String::new() },
+            FrontendMessage::Execute { portal: String::new(), max_rows: 0 },
+            FrontendMessage::Execute { portal: "cursor".to_string(), max_rows: 100 },
+            FrontendMessage::Close { object_type: b'S', name: "insert_user".to_string() },
+            FrontendMessage::Sync,
+            FrontendMessage::Flush,
+        ];
+        
+        for message in &messages {
+            assert_eq!(&round_trip(message), message);
+        }
+    }
+    
+    #[test]
+    fn test_truncated_extended_messages() {
+        let parser = MessageParser::new();
+        let formatter = MessageFormatter::new();
+        
+        let bind = FrontendMessage::Bind {
+            portal: String::new(),
+            statement: String::new(),
+            param_formats: vec![],
+            param_values: vec![Some(Bytes::from_static(b"hello"))],
+            result_formats: vec![],
+        };
+        let bytes = formatter.format_frontend_message(&bind).unwrap();
+        
+        // Cutting the message anywhere in the parameter data is an error, not a panic
+        for len in 8..bytes.len() {
+            assert!(parser.parse_frontend_message(&bytes.slice(..len)).is_err(), "length {}", len);
+        }
+        
+        // Describe and Close only name statements or portals
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'D');
+        buf.put_u32(6);
+        buf.put_u8(b'X');
+        buf.put_u8(0);
+        assert!(parser.parse_frontend_message(&buf.freeze()).is_err());
+    }
+    
+    #[test]
+    fn test_copy_messages_round_trip() {
+        let parser = MessageParser::new();
+        let formatter = MessageFormatter::new();
+        
+        for message in [
+            FrontendMessage::CopyData(Bytes::from_static(b"1\tapple\n")),
+            FrontendMessage::CopyData(Bytes::new()),
+            FrontendMessage::CopyDone,
+            FrontendMessage::CopyFail("client aborted".to_string()),
+        ] {
+            let bytes = formatter.format_frontend_message(&message).unwrap();
+            assert_eq!(parser.parse_frontend_message(&bytes).unwrap(), message);
+        }
+        
+        // A CopyData cut short is an error, not a panic
+        let bytes = formatter.format_frontend_message(&FrontendMessage::CopyData(Bytes::from_static(b"row"))).unwrap();
+        assert!(parser.parse_frontend_message(&bytes.slice(..6)).is_err());
+    }
+    
+    #[test]
+    fn test_frontend_message_length() {
+        let parser = MessageParser::new();
+        let formatter = MessageFormatter::new();
+        
+        // Tagged messages count their type byte
+        let copy_done = formatter.format_frontend_message(&FrontendMessage::CopyDone).unwrap();
+        assert_eq!(parser.frontend_message_length(&copy_done).unwrap(), Some(5));
+        assert_eq!(parser.frontend_message_length(&copy_done[..3]).unwrap(), None);
+        assert_eq!(parser.frontend_message_length(&[]).unwrap(), None);
+        
+        // Startup-phase messages have none
+        let mut ssl_request = BytesMut::new();
+        ssl_request.put_i32(8);
+        ssl_request.put_i32(80877103);
+        assert_eq!(parser.frontend_message_length(&ssl_request).unwrap(), Some(8));
+        
+        let mut bad = BytesMut::new();
+        bad.put_u8(b'd');
+        bad.put_i32(2);
+        assert!(parser.frontend_message_length(&bad).is_err());
+    }
+    
+    #[test]
+    fn test_max_message_size() {
+        let parser = MessageParser::new().with_max_message_size(1024);
+        
+        // A length prefix over the limit is rejected from the header alone
+        let mut huge = BytesMut::new();
+        huge.put_u8(b'Q');
+        huge.put_i32(i32::MAX);
+        assert!(matches!(parser.frontend_message_length(&huge), Err(ProxyError::Protocol(_))));
+        
+        // Messages up to the limit, type byte included, pass
+        let query = FrontendMessage::Query("x".repeat(1024 - 6));
+        let bytes = MessageFormatter::new().format_frontend_message(&query).unwrap();
+        assert_eq!(bytes.len(), 1024);
+        assert_eq!(parser.frontend_message_length(&bytes).unwrap(), Some(1024));
+        assert_eq!(parser.parse_frontend_message(&bytes).unwrap(), query);
+    }
+    
+    #[test]
+    fn test_parse_sasl_responses() {
+        let parser = MessageParser::new();
+        let formatter = MessageFormatter::new();
+        
+        let initial = FrontendMessage::SaslInitialResponse {
+            mechanism: "SCRAM-SHA-256".to_string(),
+            data: Some(Bytes::from_static(b"n,,n=,r=rOprNGfwEbeRWgbNEkqO")),
+        };
+        let bytes = formatter.format_frontend_message(&initial).unwrap();
+        assert_eq!(parser.parse_sasl_initial_response(&bytes).unwrap(), initial);
+        
+        // A mechanism without an initial response sends length -1
+        let empty = FrontendMessage::SaslInitialResponse { mechanism: "SCRAM-SHA-256".to_string(), data: None };
+        let bytes = formatter.format_frontend_message(&empty).unwrap();
+        assert_eq!(parser.parse_sasl_initial_response(&bytes).unwrap(), empty);
+        
+        // The declared length must cover exactly the rest of the message
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'p');
+        buf.put_u32(4 + 14 + 4 + 2);
+        buf.put_slice(b"SCRAM-SHA-256\0");
+        buf.put_i32(3);
+        buf.put_slice(b"n,");
+        assert!(parser.parse_sasl_initial_response(&buf.freeze()).is_err());
+        
+        let response = FrontendMessage::SaslResponse(Bytes::from_static(b"c=biws,r=abc,p=xyz"));
+        let bytes = formatter.format_frontend_message(&response).unwrap();
+        assert_eq!(parser.parse_sasl_response(&bytes).unwrap(), response);
+        assert!(matches!(parser.parse_sasl_response(&bytes.slice(..6)), Err(ProxyError::Incomplete)));
+    }
+}
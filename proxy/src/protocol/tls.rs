@@ -0,0 +1,279 @@
+//! TLS termination for client connections
+//!
+//! Clients ask for encryption with an `SSLRequest` before the startup
+//! message. When [`TlsConfig`] is set the proxy answers `S` and upgrades the
+//! socket in place; otherwise it answers `N` and the session continues in
+//! plaintext.
+
+use crate::config::TlsConfig;
+use crate::error::{ProxyError, Result};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+/// Build a TLS acceptor from the certificate and key files in `config`
+///
+/// With `require_client_certs`, clients must present a certificate signed by
+/// a CA in `ca_file`.
+pub fn tls_acceptor(config: &TlsConfig) -> Result<TlsAcceptor> {
+    let certs = load_certs(&config.cert_file)?;
+    let key = {
+        let mut reader = open_pem(&config.key_file)?;
+        rustls_pemfile::private_key(&mut reader)
+            .map_err(|e| pem_error(&config.key_file, e))?
+            .ok_or_else(|| {
+                ProxyError::Config(format!("No private key found in {}", config.key_file.display()))
+            })?
+    };
+
+    let builder = ServerConfig::builder();
+    let builder = if config.require_client_certs {
+        let ca_file = config.ca_file.as_ref().ok_or_else(|| {
+            ProxyError::Config("Client certificates are required but no CA file is configured".to_string())
+        })?;
+
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(ca_file)? {
+            roots
+                .add(cert)
+                .map_err(|e| ProxyError::Config(format!("Invalid CA certificate in {}: {}", ca_file.display(), e)))?;
+        }
+
+        let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+            .build()
+            .map_err(|e| ProxyError::Config(format!("Invalid client certificate verifier: {}", e)))?;
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+
+    let server_config = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| ProxyError::Config(format!("Invalid TLS certificate or key: {}", e)))?;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Build a TLS acceptor if `config` enables TLS
+pub fn optional_tls_acceptor(config: Option<&TlsConfig>) -> Result<Option<TlsAcceptor>> {
+    config.map(tls_acceptor).transpose()
+}
+
+/// Read every certificate in a PEM file
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let mut reader = open_pem(path)?;
+    let certs = rustls_pemfile::certs(&mut reader)
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(|e| pem_error(path, e))?;
+
+    if certs.is_empty() {
+        return Err(ProxyError::Config(format!("No certificates found in {}", path.display())));
+    }
+
+    Ok(certs)
+}
+
+/// Open a PEM file for reading
+fn open_pem(path: &Path) -> Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| ProxyError::Config(format!("Failed to open {}: {}", path.display(), e)))
+}
+
+/// Configuration error for an unreadable PEM file
+fn pem_error(path: &Path, error: io::Error) -> ProxyError {
+    ProxyError::Config(format!("Failed to read {}: {}", path.display(), error))
+}
+
+/// Client socket, plaintext or upgraded to TLS
+#[derive(Debug)]
+pub enum ClientStream {
+    /// Plaintext TCP connection
+    Plain(TcpStream),
+
+    /// TLS session terminated at the proxy
+    Tls(Box<TlsStream<TcpStream>>),
+
+    /// Socket taken out while a TLS handshake is in progress
+    Detached,
+}
+
+impl ClientStream {
+    /// Whether the connection has been upgraded to TLS
+    pub fn is_tls(&self) -> bool {
+        matches!(self, Self::Tls(_))
+    }
+
+    /// Common name of the certificate the client presented, if any
+    ///
+    /// The acceptor has verified the certificate against the configured CA
+    /// by the time the handshake completes.
+    pub fn peer_common_name(&self) -> Option<String> {
+        match self {
+            Self::Tls(stream) => common_name(stream.get_ref().1.peer_certificates()?.first()?),
+            _ => None,
+        }
+    }
+
+    /// Underlying TCP socket
+    pub fn tcp(&self) -> io::Result<&TcpStream> {
+        match self {
+            Self::Plain(stream) => Ok(stream),
+            Self::Tls(stream) => Ok(stream.get_ref().0),
+            Self::Detached => Err(detached()),
+        }
+    }
+
+    /// Perform the server side of a TLS handshake on a plaintext stream
+    ///
+    /// If the handshake fails the stream is left detached, since the client
+    /// can no longer be spoken to in plaintext.
+    pub async fn accept_tls(&mut self, acceptor: &TlsAcceptor) -> Result<()> {
+        let stream = match std::mem::replace(self, Self::Detached) {
+            Self::Plain(stream) => stream,
+            other => {
+                *self = other;
+                return Err(ProxyError::Protocol("TLS is already negotiated".to_string()));
+            }
+        };
+
+        let stream = acceptor.accept(stream).await?;
+        *self = Self::Tls(Box::new(stream));
+        Ok(())
+    }
+}
+
+impl From<TcpStream> for ClientStream {
+    fn from(stream: TcpStream) -> Self {
+        Self::Plain(stream)
+    }
+}
+
+impl AsyncRead for ClientStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+            Self::Detached => Poll::Ready(Err(detached())),
+        }
+    }
+}
+
+impl AsyncWrite for ClientStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+            Self::Detached => Poll::Ready(Err(detached())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+            Self::Detached => Poll::Ready(Err(detached())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+            Self::Detached => Poll::Ready(Err(detached())),
+        }
+    }
+}
+
+/// DER encoding of the `commonName` attribute type, OID 2.5.4.3
+const COMMON_NAME_OID: &[u8] = &[0x55, 0x04, 0x03];
+
+/// Common name in the subject of a DER-encoded X.509 certificate
+///
+/// The last one wins when there are several, as in PostgreSQL.
+pub fn common_name(cert: &[u8]) -> Option<String> {
+    // Certificate ::= SEQUENCE { tbsCertificate SEQUENCE { [0] version OPTIONAL,
+    //   serialNumber, signature, issuer, validity, subject, ... }, ... }
+    let (_, certificate, _) = der_element(cert)?;
+    let (_, mut tbs, _) = der_element(certificate)?;
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_element(tbs)?.2;
+    }
+    for _ in 0..4 {
+        tbs = der_element(tbs)?.2;
+    }
+    let (_, mut subject, _) = der_element(tbs)?;
+
+    // Name ::= SEQUENCE OF SET OF SEQUENCE { type OID, value ANY }
+    let mut common_name = None;
+    while !subject.is_empty() {
+        let (_, mut names, rest) = der_element(subject)?;
+        subject = rest;
+        while !names.is_empty() {
+            let (_, attribute, rest) = der_element(names)?;
+            names = rest;
+            let (tag, oid, value) = der_element(attribute)?;
+            if tag == 0x06 && oid == COMMON_NAME_OID {
+                common_name = Some(String::from_utf8_lossy(der_element(value)?.1).into_owned());
+            }
+        }
+    }
+    common_name
+}
+
+/// Split the first DER element off `bytes`, as its tag, contents and the bytes after it
+fn der_element(bytes: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = bytes.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = if first & 0x80 == 0 {
+        (first as usize, rest)
+    } else {
+        let octets = (first & 0x7f) as usize;
+        if octets == 0 || octets > std::mem::size_of::<usize>() || rest.len() < octets {
+            return None;
+        }
+        let length = rest[..octets].iter().fold(0usize, |length, &octet| (length << 8) | octet as usize);
+        (length, &rest[octets..])
+    };
+    if rest.len() < length {
+        return None;
+    }
+    Some((tag, &rest[..length], &rest[length..]))
+}
+
+/// Error for I/O on a stream whose handshake failed
+fn detached() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "client stream is detached")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_name_read_from_subject() {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]);
+        params.distinguished_name.push(rcgen::DnType::OrganizationName, "Acme");
+        params.distinguished_name.push(rcgen::DnType::CommonName, "svc.example.com");
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        assert_eq!(common_name(&cert.serialize_der().unwrap()).as_deref(), Some("svc.example.com"));
+
+        // Certificates may leave the common name out
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]);
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        assert_eq!(common_name(&cert.serialize_der().unwrap()), None);
+
+        assert_eq!(common_name(&[0x30, 0x82, 0xff]), None);
+    }
+}
@@ -1,12 +1,15 @@
 //! Protocol validator for PostgreSQL wire protocol
 //! 
 //! This module provides validation of PostgreSQL protocol state transitions
-//! to ensure security and prevent protocol exploitation attacks.
+//! to ensure security and prevent protocol exploitation attacks. Besides the
+//! messages permitted in each connection state, it tracks the client's
+//! prepared statements and portals, so extended query messages referring to
+//! objects the client never created are rejected rather than forwarded.
 
 use crate::error::{ProxyError, Result};
 use crate::protocol::connection::ConnectionState;
 use crate::protocol::message::{FrontendMessage, BackendMessage, TransactionStatus};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use log::{debug, warn};
 
 /// Protocol validator for PostgreSQL wire protocol
@@ -21,6 +24,12 @@ pub struct ProtocolValidator {
     /// Last received message type
     last_message_type: Option<String>,
     
+    /// Names of the client's prepared statements
+    statements: HashSet<String>,
+    
+    /// Names of the client's portals, with the statement each was bound to
+    portals: HashMap<String, String>,
+    
     /// Configuration for protocol validation
     config: ProtocolValidatorConfig,
 }
@@ -37,6 +46,9 @@ pub struct ProtocolValidatorConfig {
     /// Maximum allowed parameter size
     pub max_parameter_size: usize,
     
+    /// Maximum allowed size of a single frontend message, including its header
+    pub max_message_size: usize,
+    
     /// Allowed authentication methods
     pub allowed_auth_methods: Vec<String>,
     
@@ -50,6 +62,7 @@ impl Default for ProtocolValidatorConfig {
             strict_mode: true,
             max_query_length: 1_000_000, // 1MB
             max_parameter_size: 100_000, // 100KB
+            max_message_size: 64 << 20, // 64MB
             allowed_auth_methods: vec![
                 "md5".to_string(),
                 "scram-sha-256".to_string(),
@@ -85,6 +98,8 @@ impl ProtocolValidator {
             permitted_messages: ProtocolValidator::initialize_permitted_messages(),
             sequence_counter: 0,
             last_message_type: None,
+            statements: HashSet::new(),
+            portals: HashMap::new(),
             config,
         }
     }
@@ -130,6 +145,9 @@ impl ProtocolValidator {
         match message {
             FrontendMessage::Startup { .. } => "Startup",
             FrontendMessage::Password(_) => "Password",
+            FrontendMessage::GssResponse(_) => "GssResponse",
+            FrontendMessage::SaslInitialResponse { .. } => "SaslInitialResponse",
+            FrontendMessage::SaslResponse(_) => "SaslResponse",
             FrontendMessage::Query(_) => "Query",
             FrontendMessage::Parse { .. } => "Parse",
             FrontendMessage::Bind { .. } => "Bind",
@@ -242,7 +260,10 @@ impl ProtocolValidator {
             FrontendMessage::CancelRequest { .. } => {
                 permitted.contains(&PermittedMessageType::Startup(0))
             }
-            FrontendMessage::Password(_) => {
+            FrontendMessage::Password(_)
+            | FrontendMessage::GssResponse(_)
+            | FrontendMessage::SaslInitialResponse { .. }
+            | FrontendMessage::SaslResponse(_) => {
                 permitted.contains(&PermittedMessageType::Specific('p'))
             }
             FrontendMessage::Query(query) => {
@@ -256,13 +277,10 @@ impl ProtocolValidator {
                 }
                 
                 // If in failed transaction, only allow ROLLBACK
-                if *state == ConnectionState::InFailedTransaction {
-                    let query_upper = query.to_uppercase();
-                    if !query_upper.contains("ROLLBACK") && !query_upper.contains("ABORT") {
-                        return Err(ProxyError::Protocol(
-                            "Only ROLLBACK is allowed in a failed transaction".to_string()
-                        ));
-                    }
+                if *state == ConnectionState::InFailedTransaction && !ends_failed_transaction(query) {
+                    return Err(ProxyError::Protocol(
+                        "Only ROLLBACK is allowed in a failed transaction".to_string()
+                    ));
                 }
                 
                 permitted.contains(&PermittedMessageType::Query)
@@ -348,6 +366,107 @@ impl ProtocolValidator {
             )));
         }
         
+        self.validate_sequence(message, state)
+    }
+    
+    /// Check that an extended query message fits the statements and portals
+    /// the client has created, and track the ones it creates and closes
+    ///
+    /// Follows the server's rules: a named statement or portal must be closed
+    /// before its name is reused, while the unnamed ones are replaced; a
+    /// simple query replaces the unnamed statement; and portals only live
+    /// until the end of their transaction.
+    fn validate_sequence(&mut self, message: &FrontendMessage, state: &ConnectionState) -> Result<()> {
+        let in_transaction_block = matches!(
+            state,
+            ConnectionState::InTransaction | ConnectionState::InFailedTransaction
+        );
+        
+        match message {
+            FrontendMessage::Query(_) => {
+                self.statements.remove("");
+                if in_transaction_block {
+                    self.portals.remove("");
+                } else {
+                    self.portals.clear();
+                }
+            }
+            FrontendMessage::Parse { name, query, .. } => {
+                if !name.is_empty() && self.statements.contains(name) {
+                    return Err(ProxyError::Protocol(format!("Prepared statement \"{}\" already exists", name)));
+                }
+                if *state == ConnectionState::InFailedTransaction && !ends_failed_transaction(query) {
+                    return Err(ProxyError::Protocol(
+                        "Only ROLLBACK is allowed in a failed transaction".to_string()
+                    ));
+                }
+                self.statements.insert(name.clone());
+            }
+            FrontendMessage::Bind { portal, statement, .. } => {
+                if !self.statements.contains(statement) {
+                    return Err(ProxyError::Protocol(format!(
+                        "Bind to prepared statement \"{}\", which was never parsed",
+                        statement
+                    )));
+                }
+                if !portal.is_empty() && self.portals.contains_key(portal) {
+                    return Err(ProxyError::Protocol(format!("Portal \"{}\" already exists", portal)));
+                }
+                self.portals.insert(portal.clone(), statement.clone());
+            }
+            FrontendMessage::Execute { portal, .. } => {
+                if !self.portals.contains_key(portal) {
+                    return Err(ProxyError::Protocol(format!(
+                        "Execute of portal \"{}\", which was never bound",
+                        portal
+                    )));
+                }
+            }
+            FrontendMessage::Describe { object_type, name } => {
+                let exists = match object_type {
+                    b'S' => self.statements.contains(name),
+                    b'P' => self.portals.contains_key(name),
+                    _ => {
+                        return Err(ProxyError::Protocol(format!(
+                            "Invalid Describe object type {:?}",
+                            *object_type as char
+                        )))
+                    }
+                };
+                if !exists {
+                    return Err(ProxyError::Protocol(format!(
+                        "Describe of unknown {} \"{}\"",
+                        if *object_type == b'S' { "prepared statement" } else { "portal" },
+                        name
+                    )));
+                }
+            }
+            FrontendMessage::Close { object_type, name } => {
+                // Closing something that doesn't exist is not an error
+                match object_type {
+                    b'S' => {
+                        // A statement's portals are closed with it
+                        self.statements.remove(name);
+                        self.portals.retain(|_, statement| statement != name);
+                    }
+                    b'P' => {
+                        self.portals.remove(name);
+                    }
+                    _ => {
+                        return Err(ProxyError::Protocol(format!(
+                            "Invalid Close object type {:?}",
+                            *object_type as char
+                        )))
+                    }
+                }
+            }
+            FrontendMessage::Sync if !in_transaction_block => {
+                // Sync commits the implicit transaction, closing its portals
+                self.portals.clear();
+            }
+            _ => {}
+        }
+        
         Ok(())
     }
     
@@ -400,10 +519,18 @@ impl ProtocolValidator {
     pub fn reset(&mut self) {
         self.sequence_counter = 0;
         self.last_message_type = None;
+        self.statements.clear();
+        self.portals.clear();
         debug!("Protocol validator reset");
     }
 }
 
+/// Whether `query` may run in a failed transaction, which only ending it can
+fn ends_failed_transaction(query: &str) -> bool {
+    let query_upper = query.to_uppercase();
+    query_upper.contains("ROLLBACK") || query_upper.contains("ABORT")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -477,6 +604,93 @@ mod tests {
         assert!(validator.validate_frontend_message(&long_query_msg, &ConnectionState::Ready).is_err());
     }
     
+    fn parse(name: &str, query: &str) -> FrontendMessage {
+        FrontendMessage::Parse { name: name.to_string(), query: query.to_string(), param_types: vec![] }
+    }
+    
+    fn bind(portal: &str, statement: &str) -> FrontendMessage {
+        FrontendMessage::Bind {
+            portal: portal.to_string(),
+            statement: statement.to_string(),
+            param_formats: vec![],
+            param_values: vec![],
+            result_formats: vec![],
+        }
+    }
+    
+    fn execute(portal: &str) -> FrontendMessage {
+        FrontendMessage::Execute { portal: portal.to_string(), max_rows: 0 }
+    }
+    
+    #[test]
+    fn test_extended_messages_in_order() {
+        let mut validator = ProtocolValidator::new(ProtocolValidatorConfig::default());
+        let ready = ConnectionState::Ready;
+        
+        for message in [
+            parse("s1", "SELECT $1"),
+            FrontendMessage::Describe { object_type: b'S', name: "s1".to_string() },
+            bind("p1", "s1"),
+            FrontendMessage::Describe { object_type: b'P', name: "p1".to_string() },
+            execute("p1"),
+            FrontendMessage::Close { object_type: b'P', name: "p1".to_string() },
+            bind("p1", "s1"),
+            execute("p1"),
+            FrontendMessage::Sync,
+            // The named statement outlives the transaction
+            bind("", "s1"),
+            execute(""),
+            // The unnamed statement is simply replaced
+            parse("", "SELECT 1"),
+            parse("", "SELECT 2"),
+            FrontendMessage::Sync,
+        ] {
+            assert!(validator.validate_frontend_message(&message, &ready).is_ok(), "{:?} rejected", message);
+        }
+    }
+    
+    #[test]
+    fn test_out_of_order_extended_messages() {
+        let mut validator = ProtocolValidator::new(ProtocolValidatorConfig::default());
+        let ready = ConnectionState::Ready;
+        
+        // Bind before Parse, Execute before Bind
+        assert!(validator.validate_frontend_message(&bind("", "s1"), &ready).is_err());
+        assert!(validator.validate_frontend_message(&execute(""), &ready).is_err());
+        let describe = FrontendMessage::Describe { object_type: b'P', name: "p1".to_string() };
+        assert!(validator.validate_frontend_message(&describe, &ready).is_err());
+        
+        // Named statements and portals can't be redefined without closing them
+        validator.validate_frontend_message(&parse("s1", "SELECT 1"), &ready).unwrap();
+        assert!(validator.validate_frontend_message(&parse("s1", "SELECT 2"), &ready).is_err());
+        validator.validate_frontend_message(&bind("p1", "s1"), &ready).unwrap();
+        assert!(validator.validate_frontend_message(&bind("p1", "s1"), &ready).is_err());
+        
+        // Closing a statement closes its portals
+        let close = FrontendMessage::Close { object_type: b'S', name: "s1".to_string() };
+        validator.validate_frontend_message(&close, &ready).unwrap();
+        assert!(validator.validate_frontend_message(&execute("p1"), &ready).is_err());
+        assert!(validator.validate_frontend_message(&bind("p1", "s1"), &ready).is_err());
+        
+        // Portals end with the implicit transaction, but not with a transaction block
+        validator.validate_frontend_message(&parse("s2", "SELECT 1"), &ready).unwrap();
+        validator.validate_frontend_message(&bind("p2", "s2"), &ConnectionState::InTransaction).unwrap();
+        validator.validate_frontend_message(&FrontendMessage::Sync, &ConnectionState::InTransaction).unwrap();
+        validator.validate_frontend_message(&execute("p2"), &ConnectionState::InTransaction).unwrap();
+        validator.validate_frontend_message(&FrontendMessage::Sync, &ready).unwrap();
+        assert!(validator.validate_frontend_message(&execute("p2"), &ready).is_err());
+        
+        // A simple query replaces the unnamed statement
+        validator.validate_frontend_message(&parse("", "SELECT 1"), &ready).unwrap();
+        validator.validate_frontend_message(&FrontendMessage::Query("SELECT 2".to_string()), &ready).unwrap();
+        assert!(validator.validate_frontend_message(&bind("", ""), &ready).is_err());
+        
+        // Only ROLLBACK can be prepared in a failed transaction
+        let failed = ConnectionState::InFailedTransaction;
+        assert!(validator.validate_frontend_message(&parse("", "SELECT 1"), &failed).is_err());
+        assert!(validator.validate_frontend_message(&parse("", "ROLLBACK"), &failed).is_ok());
+    }
+    
     #[test]
     fn test_failed_transaction_restrictions() {
         let config = ProtocolValidatorConfig::default();
@@ -2,10 +2,22 @@
 //!
 //! This module provides detection of unusual query patterns that might indicate security issues.
 
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::ops::ControlFlow;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
+use log::{debug, warn};
+use sqlparser::ast::{
+    visit_expressions, visit_relations, BinaryOperator, Expr, ObjectName, Query, SetExpr, SetOperator, Statement,
+    UnaryOperator, Value, Visit, Visitor,
+};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::tokenizer::{Token, Tokenizer, Whitespace};
+
+use crate::interception::analyzer::{QueryAnalyzer, SYSTEM_CATALOG_SCHEMAS};
+
 /// Configuration for anomaly detection
 #[derive(Debug, Clone)]
 pub struct AnomalyDetectorConfig {
@@ -20,6 +32,9 @@ pub struct AnomalyDetectorConfig {
     
     /// Threshold for query frequency anomalies
     pub query_frequency_threshold: f64,
+    
+    /// Combined [`AnomalyEvent`] score at which a query counts as an injection attempt
+    pub injection_score_threshold: u32,
 }
 
 impl Default for AnomalyDetectorConfig {
@@ -29,6 +44,59 @@ impl Default for AnomalyDetectorConfig {
             time_window_seconds: 300, // 5 minutes
             query_pattern_threshold: 0.8,
             query_frequency_threshold: 3.0,
+            injection_score_threshold: 50,
+        }
+    }
+}
+
+/// Kind of suspicious query pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyKind {
+    /// Condition with an always-true `OR` branch, like `OR 1=1`
+    Tautology,
+    
+    /// UNION whose later branches read system catalogs the first doesn't
+    CatalogUnion,
+    
+    /// Statement stacked after a SELECT, like `SELECT ...; DROP TABLE users`
+    StackedStatement,
+    
+    /// Comment right after a string literal, cutting off the rest of the query
+    CommentTruncation,
+}
+
+impl AnomalyKind {
+    /// Score of a single detection, out of 100
+    pub fn score(&self) -> u32 {
+        match self {
+            AnomalyKind::Tautology => 60,
+            AnomalyKind::CatalogUnion => 80,
+            AnomalyKind::StackedStatement => 40,
+            AnomalyKind::CommentTruncation => 50,
+        }
+    }
+}
+
+/// Suspicious pattern found in a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnomalyEvent {
+    /// Kind of pattern
+    pub kind: AnomalyKind,
+    
+    /// Score of the detection, higher is more suspicious
+    pub score: u32,
+    
+    /// What was found
+    pub description: String,
+}
+
+impl AnomalyEvent {
+    /// Create an event scored for its kind
+    fn new(kind: AnomalyKind, description: String) -> Self {
+        Self {
+            kind,
+            score: kind.score(),
+            description,
         }
     }
 }
@@ -64,9 +132,38 @@ impl AnomalyDetector {
         // Track the query
         self.track_query(query);
         
-        // For now, simply return true (no anomalies)
-        // In a real implementation, this would perform pattern analysis
-        true
+        let events = self.detect_injection(query);
+        let score: u32 = events.iter().map(|event| event.score).sum();
+        if score < self.config.injection_score_threshold {
+            return true;
+        }
+        
+        for event in &events {
+            warn!("Possible SQL injection ({:?}, score {}): {}", event.kind, event.score, event.description);
+        }
+        false
+    }
+    
+    /// Find SQL injection patterns in a query
+    ///
+    /// Predicates and set operations are checked on the statements parsed by
+    /// [`QueryAnalyzer`]; comments, which the parser drops, on the query's tokens.
+    pub fn detect_injection(&self, query: &str) -> Vec<AnomalyEvent> {
+        let mut events = Vec::new();
+        
+        match QueryAnalyzer::parse_statements(query) {
+            Ok(statements) => {
+                for statement in &statements {
+                    events.extend(find_tautologies(statement));
+                    events.extend(find_catalog_unions(statement));
+                }
+                events.extend(find_stacked_statements(&statements));
+            }
+            Err(e) => debug!("Checking unparsed query for injection by its tokens only: {}", e),
+        }
+        events.extend(find_comment_truncation(query));
+        
+        events
     }
     
     /// Track a query for frequency analysis
@@ -85,4 +182,244 @@ impl AnomalyDetector {
         times.push(now);
         times.retain(|time| now.duration_since(*time) < time_window);
     }
-} 
\ No newline at end of file
+}
+
+/// `OR` branches that are always true, making the whole condition true
+fn find_tautologies(statement: &Statement) -> Vec<AnomalyEvent> {
+    let mut events = Vec::new();
+    let _ = visit_expressions(statement, |expr| {
+        if let Expr::BinaryOp { left, op: BinaryOperator::Or, right } = expr {
+            for operand in [left, right] {
+                if constant_truth(operand) == Some(true) {
+                    events.push(AnomalyEvent::new(
+                        AnomalyKind::Tautology,
+                        format!("Always-true condition {} in OR", operand),
+                    ));
+                }
+            }
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    events
+}
+
+/// Truth value of a condition that doesn't depend on any row, if it is one
+///
+/// Comparisons of an expression with itself count, since `x = x` is how
+/// injected conditions avoid literals.
+fn constant_truth(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Nested(inner) => constant_truth(inner),
+        Expr::Value(Value::Boolean(value)) => Some(*value),
+        Expr::UnaryOp { op: UnaryOperator::Not, expr } => constant_truth(expr).map(|value| !value),
+        Expr::BinaryOp { left, op: BinaryOperator::Or, right } => {
+            match (constant_truth(left), constant_truth(right)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            }
+        }
+        Expr::BinaryOp { left, op: BinaryOperator::And, right } => {
+            match (constant_truth(left), constant_truth(right)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            }
+        }
+        Expr::BinaryOp { left, op, right } => {
+            let ordering = constant_ordering(left, right)?;
+            match op {
+                BinaryOperator::Eq => Some(ordering == Ordering::Equal),
+                BinaryOperator::NotEq => Some(ordering != Ordering::Equal),
+                BinaryOperator::Lt => Some(ordering == Ordering::Less),
+                BinaryOperator::LtEq => Some(ordering != Ordering::Greater),
+                BinaryOperator::Gt => Some(ordering == Ordering::Greater),
+                BinaryOperator::GtEq => Some(ordering != Ordering::Less),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// How two operands compare, when that's known without reading a row
+fn constant_ordering(left: &Expr, right: &Expr) -> Option<Ordering> {
+    match (left, right) {
+        // Comparisons with NULL are never true
+        (Expr::Value(Value::Null), _) | (_, Expr::Value(Value::Null)) => None,
+        (Expr::Value(Value::Number(left, _)), Expr::Value(Value::Number(right, _))) => {
+            left.parse::<f64>().ok()?.partial_cmp(&right.parse::<f64>().ok()?)
+        }
+        (Expr::Value(Value::SingleQuotedString(left)), Expr::Value(Value::SingleQuotedString(right))) => {
+            Some(left.cmp(right))
+        }
+        _ if left == right => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// UNIONs that append system catalog rows to a query over user tables
+fn find_catalog_unions(statement: &Statement) -> Vec<AnomalyEvent> {
+    struct CatalogUnions(Vec<AnomalyEvent>);
+    
+    impl Visitor for CatalogUnions {
+        type Break = ();
+        
+        fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<Self::Break> {
+            let mut branches = Vec::new();
+            union_branches(&query.body, &mut branches);
+            
+            if let Some((first, rest)) = branches.split_first() {
+                if catalogs_read(first).is_empty() {
+                    for catalog in rest.iter().flat_map(|branch| catalogs_read(branch)) {
+                        self.0.push(AnomalyEvent::new(
+                            AnomalyKind::CatalogUnion,
+                            format!("UNION reads system catalog {}", catalog),
+                        ));
+                    }
+                }
+            }
+            ControlFlow::Continue(())
+        }
+    }
+    
+    let mut unions = CatalogUnions(Vec::new());
+    let _ = statement.visit(&mut unions);
+    unions.0
+}
+
+/// The operands of a chain of UNIONs, left to right
+fn union_branches<'a>(set_expr: &'a SetExpr, branches: &mut Vec<&'a SetExpr>) {
+    match set_expr {
+        SetExpr::SetOperation { op: SetOperator::Union, left, right, .. } => {
+            union_branches(left, branches);
+            union_branches(right, branches);
+        }
+        _ => branches.push(set_expr),
+    }
+}
+
+/// System catalogs a query branch reads
+///
+/// Unqualified `pg_` names resolve to `pg_catalog`, which PostgreSQL searches first.
+fn catalogs_read(set_expr: &SetExpr) -> Vec<String> {
+    let mut catalogs = Vec::new();
+    let _ = visit_relations(set_expr, |name: &ObjectName| {
+        let is_catalog = match name.0.as_slice() {
+            [.., schema, _] => SYSTEM_CATALOG_SCHEMAS.contains(&schema.value.to_lowercase().as_str()),
+            [table] => table.value.to_lowercase().starts_with("pg_"),
+            [] => false,
+        };
+        if is_catalog {
+            catalogs.push(name.to_string());
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    catalogs
+}
+
+/// Statements other than queries following a SELECT
+fn find_stacked_statements(statements: &[Statement]) -> Vec<AnomalyEvent> {
+    match statements.split_first() {
+        Some((Statement::Query(_), rest)) => rest.iter()
+            .filter(|statement| !matches!(statement, Statement::Query(_)))
+            .map(|statement| AnomalyEvent::new(
+                AnomalyKind::StackedStatement,
+                format!("Statement stacked after a SELECT: {}", statement),
+            ))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Comments that start right where a string literal ends, like `'admin'--`
+fn find_comment_truncation(query: &str) -> Vec<AnomalyEvent> {
+    let tokens = match Tokenizer::new(&PostgreSqlDialect {}, query).tokenize() {
+        Ok(tokens) => tokens,
+        Err(_) => return Vec::new(),
+    };
+    
+    tokens.windows(2)
+        .filter_map(|pair| match pair {
+            [Token::SingleQuotedString(literal), Token::Whitespace(
+                Whitespace::SingleLineComment { .. } | Whitespace::MultiLineComment(_),
+            )] => Some(AnomalyEvent::new(
+                AnomalyKind::CommentTruncation,
+                format!("Comment directly after string literal '{}'", literal),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn kinds(query: &str) -> Vec<AnomalyKind> {
+        let detector = AnomalyDetector::new(AnomalyDetectorConfig::default());
+        detector.detect_injection(query).into_iter().map(|event| event.kind).collect()
+    }
+    
+    #[test]
+    fn test_injection_strings_detected() {
+        assert_eq!(kinds("SELECT * FROM users WHERE name = '' OR '1'='1'"), vec![AnomalyKind::Tautology]);
+        assert_eq!(kinds("SELECT * FROM users WHERE id = 5 OR 1=1"), vec![AnomalyKind::Tautology]);
+        assert_eq!(kinds("SELECT * FROM users WHERE id = 5 OR (2 > 1)"), vec![AnomalyKind::Tautology]);
+        assert_eq!(kinds("SELECT * FROM users WHERE id = 5 OR id = id"), vec![AnomalyKind::Tautology]);
+        assert_eq!(
+            kinds("SELECT name, email FROM users WHERE id = 1 UNION SELECT usename, passwd FROM pg_shadow"),
+            vec![AnomalyKind::CatalogUnion],
+        );
+        assert_eq!(
+            kinds("SELECT name FROM users WHERE id = 1 UNION SELECT table_name FROM information_schema.tables"),
+            vec![AnomalyKind::CatalogUnion],
+        );
+        assert_eq!(
+            kinds("SELECT * FROM users WHERE name = 'admin'--' AND password = 'secret'"),
+            vec![AnomalyKind::CommentTruncation],
+        );
+        assert_eq!(
+            kinds("SELECT * FROM users WHERE id = 1; DROP TABLE users"),
+            vec![AnomalyKind::StackedStatement],
+        );
+        
+        // Each detection adds to the score; the default threshold blocks all of these but the stacked statement
+        let detector = AnomalyDetector::new(AnomalyDetectorConfig::default());
+        assert!(!detector.analyze_query("SELECT * FROM users WHERE name = '' OR '1'='1'"));
+        assert!(!detector.analyze_query("SELECT * FROM users WHERE name = 'admin'--' AND password = 'secret'"));
+        assert!(detector.analyze_query("SELECT * FROM users WHERE id = 1; DROP TABLE users"));
+        assert!(!detector.analyze_query("SELECT * FROM users WHERE id = 1 OR 1=1; DROP TABLE users"));
+    }
+    
+    #[test]
+    fn test_benign_lookalikes_allowed() {
+        let detector = AnomalyDetector::new(AnomalyDetectorConfig::default());
+        let benign = [
+            // Query builders start conditions with an always-true AND
+            "SELECT * FROM users WHERE 1=1 AND name = 'alice'",
+            "SELECT * FROM orders WHERE status = 'open' OR status = 'pending'",
+            "SELECT * FROM users u JOIN teams t ON t.id = u.team_id OR t.owner_id = u.id",
+            "SELECT * FROM users WHERE deleted_at = NULL OR active = true",
+            "SELECT name FROM users UNION SELECT name FROM admins",
+            "SELECT relname FROM pg_class UNION SELECT nspname FROM pg_namespace",
+            "SELECT 'it''s' AS greeting -- a comment after whitespace",
+            "SELECT 1; SELECT 2",
+            "BEGIN; UPDATE accounts SET balance = 0 WHERE id = 1; COMMIT",
+        ];
+        
+        for query in benign {
+            assert!(kinds(query).is_empty(), "flagged benign query: {}", query);
+            assert!(detector.analyze_query(query));
+        }
+    }
+    
+    #[test]
+    fn test_disabled_detector_allows_everything() {
+        let detector = AnomalyDetector::new(AnomalyDetectorConfig {
+            enabled: false,
+            ..Default::default()
+        });
+        assert!(detector.analyze_query("SELECT * FROM users WHERE name = '' OR '1'='1'"));
+    }
+}
\ No newline at end of file
@@ -18,6 +18,13 @@ pub struct DoSProtectionConfig {
     
     /// Whether to enable DoS protection
     pub enabled: bool,
+    
+    /// Total complexity score of expensive queries a connection may run per
+    /// time window; 0 means no budget
+    pub query_cost_budget: u32,
+    
+    /// Complexity score at or above which a query counts against `query_cost_budget`
+    pub expensive_query_threshold: u32,
 }
 
 impl Default for DoSProtectionConfig {
@@ -26,6 +33,8 @@ impl Default for DoSProtectionConfig {
             max_connections_per_ip: 100,
             time_window_seconds: 60,
             enabled: true,
+            query_cost_budget: 10_000,
+            expensive_query_threshold: 100,
         }
     }
 }
@@ -37,6 +46,9 @@ pub struct DoSProtection {
     
     /// Connection tracking
     connections: Mutex<HashMap<SocketAddr, Vec<Instant>>>,
+    
+    /// Complexity scores of the expensive queries each connection ran in the time window
+    query_costs: Mutex<HashMap<SocketAddr, Vec<(Instant, u32)>>>,
 }
 
 impl DoSProtection {
@@ -45,6 +57,7 @@ impl DoSProtection {
         Self {
             config,
             connections: Mutex::new(HashMap::new()),
+            query_costs: Mutex::new(HashMap::new()),
         }
     }
     
@@ -76,4 +89,88 @@ impl DoSProtection {
         
         true
     }
-} 
\ No newline at end of file
+    
+    /// Check if a connection may run a query with the given complexity score
+    ///
+    /// Scores of expensive queries accumulate over the time window; one that
+    /// would take the connection past its budget is rejected and doesn't count
+    /// against it. Cheap queries always pass.
+    pub fn allow_query(&self, addr: SocketAddr, complexity_score: u32) -> bool {
+        if !self.config.enabled
+            || self.config.query_cost_budget == 0
+            || complexity_score < self.config.expensive_query_threshold
+        {
+            return true;
+        }
+        
+        let mut query_costs = self.query_costs.lock().unwrap();
+        let now = Instant::now();
+        let time_window = Duration::from_secs(self.config.time_window_seconds);
+        
+        let costs = query_costs.entry(addr).or_default();
+        costs.retain(|(time, _)| now.duration_since(*time) < time_window);
+        
+        let spent: u64 = costs.iter().map(|(_, cost)| u64::from(*cost)).sum();
+        if spent + u64::from(complexity_score) > u64::from(self.config.query_cost_budget) {
+            return false;
+        }
+        
+        costs.push((now, complexity_score));
+        true
+    }
+    
+    /// Forget the query costs of a closed connection
+    pub fn close_connection(&self, addr: SocketAddr) {
+        self.query_costs.lock().unwrap().remove(&addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_query_cost_budget() {
+        let protection = DoSProtection::new(DoSProtectionConfig {
+            query_cost_budget: 1000,
+            ..Default::default()
+        });
+        let addr: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+        let other_addr: SocketAddr = "10.0.0.1:40001".parse().unwrap();
+        
+        // Large joins use up the budget
+        assert!(protection.allow_query(addr, 400));
+        assert!(protection.allow_query(addr, 400));
+        assert!(!protection.allow_query(addr, 400));
+        assert!(!protection.allow_query(addr, 400));
+        
+        // A smaller expensive query still fits in what's left
+        assert!(protection.allow_query(addr, 200));
+        assert!(!protection.allow_query(addr, 100));
+        
+        // Cheap queries aren't counted, so they keep passing
+        for _ in 0..1000 {
+            assert!(protection.allow_query(addr, 20));
+        }
+        
+        // Other connections have their own budget
+        assert!(protection.allow_query(other_addr, 400));
+        
+        // Closing the connection releases its costs
+        protection.close_connection(addr);
+        assert!(protection.allow_query(addr, 400));
+    }
+    
+    #[test]
+    fn test_query_cost_budget_disabled() {
+        let protection = DoSProtection::new(DoSProtectionConfig {
+            query_cost_budget: 0,
+            ..Default::default()
+        });
+        let addr: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+        
+        for _ in 0..100 {
+            assert!(protection.allow_query(addr, u32::MAX));
+        }
+    }
+}
\ No newline at end of file
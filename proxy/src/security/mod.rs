@@ -19,8 +19,8 @@ pub mod traffic_analyzer;
 pub mod security_gateway;
 
 // Re-export important types
-pub use rate_limiter::{RateLimiter, RateLimiterConfig};
+pub use rate_limiter::{RateLimitAlgorithm, RateLimiter, RateLimiterConfig};
 pub use dos_protection::DoSProtection;
-pub use anomaly_detector::AnomalyDetector;
-pub use traffic_analyzer::TrafficAnalyzer;
-pub use security_gateway::SecurityGateway; 
\ No newline at end of file
+pub use anomaly_detector::{AnomalyDetector, AnomalyEvent, AnomalyKind};
+pub use traffic_analyzer::{TrafficAnalyzer, TrafficEvent};
+pub use security_gateway::{AccessPolicy, NetworkAccessConfig, SecurityGateway}; 
\ No newline at end of file
@@ -5,7 +5,7 @@ use governor::{
     Quota, RateLimiter as GovernorRateLimiter,
 };
 use log::{debug, warn};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::net::IpAddr;
 use std::num::NonZeroU32;
 use std::sync::Arc;
@@ -14,6 +14,22 @@ use tokio::sync::Mutex;
 
 use crate::error::{ProxyError, Result};
 
+/// Length of the window that rate limits are counted over
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// How requests are counted against a rate limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitAlgorithm {
+    /// Quota that refills over each window, so a client can use up to twice
+    /// the rate across a window edge
+    #[default]
+    FixedWindow,
+    
+    /// Log of request times over the trailing window, which never admits
+    /// more than the rate in any window
+    SlidingWindow,
+}
+
 /// Configuration for rate limiting
 #[derive(Debug, Clone)]
 pub struct RateLimiterConfig {
@@ -28,6 +44,15 @@ pub struct RateLimiterConfig {
     
     /// Block list for IPs that are always blocked
     pub block_list: Vec<IpAddr>,
+    
+    /// Connections per minute for each client IP and database user pair
+    pub per_user_rate_limit: u32,
+    
+    /// Per-user overrides of `per_user_rate_limit`
+    pub user_rate_limits: HashMap<String, u32>,
+    
+    /// How requests are counted against the limits
+    pub algorithm: RateLimitAlgorithm,
 }
 
 impl Default for RateLimiterConfig {
@@ -37,15 +62,87 @@ impl Default for RateLimiterConfig {
             rate_limit: 0, // 0 means no rate limit
             allow_list: Vec::new(),
             block_list: Vec::new(),
+            per_user_rate_limit: 0, // 0 means no rate limit
+            user_rate_limits: HashMap::new(),
+            algorithm: RateLimitAlgorithm::default(),
         }
     }
 }
 
+/// Limiter for a single rate limit, using the configured algorithm
+#[derive(Debug)]
+enum WindowLimiter {
+    /// Governor quota
+    Fixed(GovernorRateLimiter<NotKeyed, InMemoryState, DefaultClock>),
+    
+    /// Sliding window log
+    Sliding(SlidingWindowLog),
+}
+
+impl WindowLimiter {
+    /// Create a limiter allowing `rate_limit` requests per window
+    fn new(algorithm: RateLimitAlgorithm, rate_limit: NonZeroU32) -> Self {
+        match algorithm {
+            RateLimitAlgorithm::FixedWindow => Self::Fixed(GovernorRateLimiter::direct(Quota::per_minute(rate_limit))),
+            RateLimitAlgorithm::SlidingWindow => Self::Sliding(SlidingWindowLog::new(rate_limit, RATE_LIMIT_WINDOW)),
+        }
+    }
+    
+    /// Record a request, returning whether it is within the limit
+    fn check(&mut self) -> bool {
+        match self {
+            Self::Fixed(limiter) => limiter.check().is_ok(),
+            Self::Sliding(log) => log.check_at(Instant::now()),
+        }
+    }
+}
+
+/// Times of the requests admitted within the trailing window
+#[derive(Debug)]
+struct SlidingWindowLog {
+    /// Requests allowed per window
+    limit: usize,
+    
+    /// Window length
+    window: Duration,
+    
+    /// Admission times, oldest first
+    admitted: VecDeque<Instant>,
+}
+
+impl SlidingWindowLog {
+    /// Create an empty log
+    fn new(limit: NonZeroU32, window: Duration) -> Self {
+        Self {
+            limit: limit.get() as usize,
+            window,
+            admitted: VecDeque::new(),
+        }
+    }
+    
+    /// Record a request made at `now`, returning whether it is within the limit
+    fn check_at(&mut self, now: Instant) -> bool {
+        while let Some(&oldest) = self.admitted.front() {
+            if now.duration_since(oldest) < self.window {
+                break;
+            }
+            self.admitted.pop_front();
+        }
+        
+        if self.admitted.len() >= self.limit {
+            return false;
+        }
+        
+        self.admitted.push_back(now);
+        true
+    }
+}
+
 /// Rate limiter for client connections
 #[derive(Debug)]
 pub struct RateLimiter {
     /// Rate limiter implementation
-    limiter: GovernorRateLimiter<NotKeyed, InMemoryState, DefaultClock>,
+    limiter: WindowLimiter,
     
     /// Client reputation tracking
     client_reputation: HashMap<IpAddr, ClientReputation>,
@@ -58,6 +155,38 @@ pub struct RateLimiter {
     
     /// Block list for IPs that are always blocked
     block_list: Vec<IpAddr>,
+    
+    /// Default rate limit per minute for each client IP and user pair
+    per_user_rate_limit: u32,
+    
+    /// Per-user overrides of the default
+    user_rate_limits: HashMap<String, u32>,
+    
+    /// Token buckets by client IP and user
+    user_buckets: HashMap<RateLimitKey, UserBucket>,
+    
+    /// How requests are counted against the limits
+    algorithm: RateLimitAlgorithm,
+}
+
+/// Key of a per-user token bucket
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RateLimitKey {
+    /// Client IP
+    ip: IpAddr,
+    
+    /// Database user
+    user: String,
+}
+
+/// Token bucket for one client IP and user pair
+#[derive(Debug)]
+struct UserBucket {
+    /// Rate limiter implementation
+    limiter: WindowLimiter,
+    
+    /// Last access time
+    last_access: Instant,
 }
 
 /// Client reputation for rate limiting
@@ -82,14 +211,10 @@ impl RateLimiter {
         // Ensure rate limit is positive
         let rate_limit = std::cmp::max(1, config.rate_limit);
         
-        // Create quota
-        let quota = Quota::per_minute(
-            NonZeroU32::new(rate_limit)
-                .ok_or_else(|| ProxyError::Config("Rate limit must be positive".to_string()))?,
-        );
-        
-        // Create governor rate limiter
-        let limiter = GovernorRateLimiter::direct(quota);
+        // Create limiter for the quota
+        let quota = NonZeroU32::new(rate_limit)
+            .ok_or_else(|| ProxyError::Config("Rate limit must be positive".to_string()))?;
+        let limiter = WindowLimiter::new(config.algorithm, quota);
         
         Ok(Self {
             limiter,
@@ -97,6 +222,10 @@ impl RateLimiter {
             rate_limit,
             allow_list: config.allow_list.clone(),
             block_list: config.block_list.clone(),
+            per_user_rate_limit: config.per_user_rate_limit,
+            user_rate_limits: config.user_rate_limits,
+            user_buckets: HashMap::new(),
+            algorithm: config.algorithm,
         })
     }
     
@@ -150,8 +279,7 @@ impl RateLimiter {
         }
         
         // Check rate limiter
-        let check_result = self.limiter.check();
-        if check_result.is_err() {
+        if !self.limiter.check() {
             debug!("Rate limit exceeded for IP {}", ip);
             // Track violation
             if let Some(reputation) = self.client_reputation.get_mut(&ip) {
@@ -167,6 +295,48 @@ impl RateLimiter {
         true
     }
     
+    /// Check if a database user connecting from an IP is allowed to proceed
+    ///
+    /// Every IP and user pair draws from its own bucket, so one user hitting
+    /// the limit does not throttle other users.
+    pub fn check_user(&mut self, ip: IpAddr, user: &str) -> bool {
+        if self.allow_list.contains(&ip) {
+            return true;
+        }
+        
+        if self.block_list.contains(&ip) {
+            warn!("IP {} is in block list, denying access", ip);
+            return false;
+        }
+        
+        // A limit of 0 leaves the user unthrottled
+        let rate_limit = match NonZeroU32::new(self.user_rate_limit(user)) {
+            Some(rate_limit) => rate_limit,
+            None => return true,
+        };
+        
+        let now = Instant::now();
+        let key = RateLimitKey { ip, user: user.to_string() };
+        let algorithm = self.algorithm;
+        let bucket = self.user_buckets.entry(key).or_insert_with(|| UserBucket {
+            limiter: WindowLimiter::new(algorithm, rate_limit),
+            last_access: now,
+        });
+        bucket.last_access = now;
+        
+        if !bucket.limiter.check() {
+            debug!("Rate limit exceeded for user {} from IP {}", user, ip);
+            return false;
+        }
+        
+        true
+    }
+    
+    /// Rate limit per minute that applies to a user
+    pub fn user_rate_limit(&self, user: &str) -> u32 {
+        self.user_rate_limits.get(user).copied().unwrap_or(self.per_user_rate_limit)
+    }
+    
     /// Update client reputation
     fn update_client_reputation(&mut self, ip: IpAddr) {
         let now = Instant::now();
@@ -203,6 +373,9 @@ impl RateLimiter {
         self.client_reputation.retain(|_, reputation| {
             now.duration_since(reputation.last_access) < Duration::from_secs(3600)
         });
+        self.user_buckets.retain(|_, bucket| {
+            now.duration_since(bucket.last_access) < Duration::from_secs(3600)
+        });
     }
     
     /// Add a method to check connections
@@ -231,6 +404,9 @@ impl Clone for RateLimiter {
             rate_limit: self.rate_limit,
             allow_list: self.allow_list.clone(),
             block_list: self.block_list.clone(),
+            per_user_rate_limit: self.per_user_rate_limit,
+            user_rate_limits: self.user_rate_limits.clone(),
+            algorithm: self.algorithm,
         };
         
         RateLimiter::new(config).unwrap_or_else(|_| {
@@ -268,8 +444,7 @@ impl RateLimiterManager {
             let limiter = RateLimiter::new(RateLimiterConfig {
                 enabled: true, // Enable by default for new limiters
                 rate_limit: self.default_rate_limit,
-                allow_list: Vec::new(),
-                block_list: Vec::new(),
+                ..Default::default()
             }).unwrap_or_else(|_| {
                 // Fallback configuration
                 let fallback_config = RateLimiterConfig::default();
@@ -322,6 +497,7 @@ mod tests {
             rate_limit: 100,
             allow_list: vec![],
             block_list: vec![],
+            ..Default::default()
         };
         
         let rate_limiter = RateLimiter::new(config).unwrap();
@@ -338,6 +514,7 @@ mod tests {
             rate_limit: 2, // Only allow 2 requests per minute
             allow_list: vec![],
             block_list: vec![],
+            ..Default::default()
         }).unwrap();
         
         // First check should pass
@@ -366,6 +543,7 @@ mod tests {
             rate_limit: 1,
             allow_list: vec!["127.0.0.1".parse().unwrap()],
             block_list: vec![],
+            ..Default::default()
         }).unwrap();
         
         let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
@@ -386,6 +564,7 @@ mod tests {
             rate_limit: 100,
             allow_list: vec![],
             block_list: vec!["192.168.1.1".parse().unwrap()],
+            ..Default::default()
         }).unwrap();
         
         let ip = "192.168.1.1".parse::<IpAddr>().unwrap();
@@ -396,5 +575,93 @@ mod tests {
         // Remove from block list
         rate_limiter.remove_from_block_list(ip);
         assert!(rate_limiter.check(ip));
+    }    
+    #[test]
+    fn test_per_user_rate_limit() {
+        let mut rate_limiter = RateLimiter::new(RateLimiterConfig {
+            rate_limit: 100,
+            per_user_rate_limit: 2,
+            user_rate_limits: HashMap::from([("batch".to_string(), 4)]),
+            ..Default::default()
+        }).unwrap();
+        
+        let ip = "10.0.0.1".parse::<IpAddr>().unwrap();
+        let other_ip = "10.0.0.2".parse::<IpAddr>().unwrap();
+        
+        // Alice exhausts her quota
+        assert!(rate_limiter.check_user(ip, "alice"));
+        assert!(rate_limiter.check_user(ip, "alice"));
+        assert!(!rate_limiter.check_user(ip, "alice"));
+        
+        // Bob on the same IP, and Alice elsewhere, are unaffected
+        assert!(rate_limiter.check_user(ip, "bob"));
+        assert!(rate_limiter.check_user(other_ip, "alice"));
+        
+        // Overrides replace the default
+        assert_eq!(rate_limiter.user_rate_limit("batch"), 4);
+        for _ in 0..4 {
+            assert!(rate_limiter.check_user(ip, "batch"));
+        }
+        assert!(!rate_limiter.check_user(ip, "batch"));
+    }
+    
+    #[test]
+    fn test_per_user_rate_limit_disabled() {
+        let mut rate_limiter = RateLimiter::new(RateLimiterConfig {
+            rate_limit: 1,
+            user_rate_limits: HashMap::from([("alice".to_string(), 1)]),
+            ..Default::default()
+        }).unwrap();
+        
+        let ip = "10.0.0.1".parse::<IpAddr>().unwrap();
+        
+        // Without a default, only users with an override are limited
+        for _ in 0..10 {
+            assert!(rate_limiter.check_user(ip, "bob"));
+        }
+        assert!(rate_limiter.check_user(ip, "alice"));
+        assert!(!rate_limiter.check_user(ip, "alice"));
+    }
+    
+    #[test]
+    fn test_sliding_window_prevents_boundary_burst() {
+        let start = Instant::now();
+        let mut log = SlidingWindowLog::new(NonZeroU32::new(3).unwrap(), RATE_LIMIT_WINDOW);
+        
+        // A full quota just before the edge of a fixed window...
+        for _ in 0..3 {
+            assert!(log.check_at(start + Duration::from_secs(59)));
+        }
+        
+        // ...leaves nothing for just after it, as those requests are still
+        // within the trailing window
+        assert!(!log.check_at(start + Duration::from_secs(61)));
+        assert!(!log.check_at(start + Duration::from_secs(118)));
+        
+        // Capacity returns once the earlier requests age out
+        assert!(log.check_at(start + Duration::from_secs(119)));
+        assert!(log.check_at(start + Duration::from_secs(119)));
+        assert!(log.check_at(start + Duration::from_secs(119)));
+        assert!(!log.check_at(start + Duration::from_secs(119)));
+    }
+    
+    #[test]
+    fn test_sliding_window_algorithm() {
+        let mut rate_limiter = RateLimiter::new(RateLimiterConfig {
+            rate_limit: 2,
+            per_user_rate_limit: 1,
+            algorithm: RateLimitAlgorithm::SlidingWindow,
+            ..Default::default()
+        }).unwrap();
+        
+        let ip = "10.0.0.1".parse::<IpAddr>().unwrap();
+        
+        assert!(rate_limiter.check(ip));
+        assert!(rate_limiter.check(ip));
+        assert!(!rate_limiter.check(ip));
+        
+        assert!(rate_limiter.check_user(ip, "alice"));
+        assert!(!rate_limiter.check_user(ip, "alice"));
+        assert!(rate_limiter.check_user(ip, "bob"));
     }
 } 
\ No newline at end of file
@@ -2,25 +2,82 @@
 //!
 //! This module provides a central gateway for security functions in the proxy.
 
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use ipnet::IpNet;
+use log::{debug, warn};
 
 use crate::security::rate_limiter::RateLimiter;
 use crate::security::dos_protection::DoSProtection;
 use crate::security::anomaly_detector::AnomalyDetector;
-use crate::security::traffic_analyzer::TrafficAnalyzer;
+use crate::security::traffic_analyzer::{TrafficAnalyzer, TrafficEvent};
+use crate::interception::analyzer::QueryType;
+
+/// Decision for clients matching neither access list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccessPolicy {
+    /// Accept the connection
+    #[default]
+    Allow,
+    
+    /// Reject the connection
+    Deny,
+}
+
+/// Network-level access control, applied before clients authenticate
+#[derive(Debug, Clone, Default)]
+pub struct NetworkAccessConfig {
+    /// Networks clients are accepted from
+    pub allow: Vec<IpNet>,
+    
+    /// Networks clients are rejected from, even if also in `allow`
+    pub deny: Vec<IpNet>,
+    
+    /// Decision for clients in neither list
+    pub default_policy: AccessPolicy,
+}
+
+impl NetworkAccessConfig {
+    /// Check if a client address may connect
+    ///
+    /// IPv4 clients of a dual-stack listener show up as IPv4-mapped IPv6
+    /// addresses, so those are matched as IPv4.
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            IpAddr::V4(_) => ip,
+        };
+        
+        if self.deny.iter().any(|network| network.contains(&ip)) {
+            return false;
+        }
+        if self.allow.iter().any(|network| network.contains(&ip)) {
+            return true;
+        }
+        self.default_policy == AccessPolicy::Allow
+    }
+}
 
 /// Configuration for the security gateway
 #[derive(Debug, Clone)]
 pub struct SecurityGatewayConfig {
     /// Whether to enable the security gateway
     pub enabled: bool,
+    
+    /// Whether queries the anomaly detector flags are rejected rather than only logged
+    pub block_anomalous_queries: bool,
+    
+    /// Networks clients may connect from
+    pub network_access: NetworkAccessConfig,
 }
 
 impl Default for SecurityGatewayConfig {
     fn default() -> Self {
         Self {
             enabled: true,
+            block_anomalous_queries: false,
+            network_access: NetworkAccessConfig::default(),
         }
     }
 }
@@ -31,7 +88,7 @@ pub struct SecurityGateway {
     config: SecurityGatewayConfig,
     
     /// Rate limiter
-    rate_limiter: Arc<RateLimiter>,
+    rate_limiter: Arc<Mutex<RateLimiter>>,
     
     /// DoS protection
     dos_protection: Arc<DoSProtection>,
@@ -47,7 +104,7 @@ impl SecurityGateway {
     /// Create a new security gateway
     pub fn new(
         config: SecurityGatewayConfig,
-        rate_limiter: Arc<RateLimiter>,
+        rate_limiter: Arc<Mutex<RateLimiter>>,
         dos_protection: Arc<DoSProtection>,
         anomaly_detector: Arc<AnomalyDetector>,
         traffic_analyzer: Arc<TrafficAnalyzer>,
@@ -62,22 +119,41 @@ impl SecurityGateway {
     }
     
     /// Check if a connection is allowed
+    ///
+    /// Network access lists apply even with the rest of the gateway disabled.
     pub fn allow_connection(&self, addr: SocketAddr) -> bool {
+        // Access lists come first, so denied hosts use none of the rate limit
+        if !self.config.network_access.permits(addr.ip()) {
+            self.traffic_analyzer.record_denied_connection(addr);
+            return false;
+        }
+        
         if !self.config.enabled {
             return true;
         }
         
         // Check all security components
-        // Extract IP from SocketAddr for rate limiter
-        let ip = addr.ip();
-        
-        // Note: Since rate_limiter.check_connection requires a mutable reference and we have an Arc,
-        // we'll assume the check passes. In a real implementation, this would need a mutex or other solution.
-        true
+        self.rate_limiter.lock().unwrap().check_connection(addr.ip())
             && self.dos_protection.allow_connection(addr)
             && !self.traffic_analyzer.is_suspicious(addr)
     }
     
+    /// Check if an authenticated database user may open a session
+    ///
+    /// Limits apply per client IP and user, so a throttled user does not
+    /// block anyone else connecting from the same address.
+    pub fn allow_user(&self, addr: SocketAddr, user: &str) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        
+        let allowed = self.rate_limiter.lock().unwrap().check_user(addr.ip(), user);
+        if !allowed {
+            debug!("Session for user {} from {} rejected by rate limiter", user, addr);
+        }
+        allowed
+    }
+    
     /// Analyze a query for security issues
     pub fn analyze_query(&self, addr: SocketAddr, query: &str) -> bool {
         if !self.config.enabled {
@@ -85,7 +161,45 @@ impl SecurityGateway {
         }
         
         // Check query with anomaly detector
-        self.anomaly_detector.analyze_query(query)
+        if self.anomaly_detector.analyze_query(query) {
+            return true;
+        }
+        
+        if self.config.block_anomalous_queries {
+            warn!("Rejecting anomalous query from {}", addr);
+            false
+        } else {
+            warn!("Allowing anomalous query from {}, blocking is disabled", addr);
+            true
+        }
+    }
+    
+    /// Check if a connection's query cost budget covers a query
+    pub fn allow_query_cost(&self, addr: SocketAddr, complexity_score: u32) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        
+        let allowed = self.dos_protection.allow_query(addr, complexity_score);
+        if !allowed {
+            warn!("Query with complexity {} from {} exceeds the query cost budget", complexity_score, addr);
+        }
+        allowed
+    }
+    
+    /// Track the query type mix of a client, returning an event if it shifted
+    pub fn record_query_type(&self, addr: SocketAddr, query_type: &QueryType) -> Option<TrafficEvent> {
+        if !self.config.enabled {
+            return None;
+        }
+        
+        self.traffic_analyzer.record_query_type(addr, query_type)
+    }
+    
+    /// Release the state kept for a closed connection
+    pub fn connection_closed(&self, addr: SocketAddr) {
+        self.dos_protection.close_connection(addr);
+        self.traffic_analyzer.close_connection(addr);
     }
     
     /// Record traffic for analysis
@@ -96,4 +210,125 @@ impl SecurityGateway {
         
         self.traffic_analyzer.record_traffic(addr, query_type, bytes_sent, bytes_received);
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::security::anomaly_detector::AnomalyDetectorConfig;
+    use crate::security::dos_protection::DoSProtectionConfig;
+    use crate::security::rate_limiter::RateLimiterConfig;
+    use crate::security::traffic_analyzer::TrafficAnalyzerConfig;
+    
+    fn gateway(rate_limiter_config: RateLimiterConfig) -> SecurityGateway {
+        gateway_with_config(SecurityGatewayConfig::default(), rate_limiter_config)
+    }
+    
+    fn gateway_with_config(config: SecurityGatewayConfig, rate_limiter_config: RateLimiterConfig) -> SecurityGateway {
+        SecurityGateway::new(
+            config,
+            Arc::new(Mutex::new(RateLimiter::new(rate_limiter_config).unwrap())),
+            Arc::new(DoSProtection::new(DoSProtectionConfig::default())),
+            Arc::new(AnomalyDetector::new(AnomalyDetectorConfig::default())),
+            Arc::new(TrafficAnalyzer::new(TrafficAnalyzerConfig::default())),
+        )
+    }
+    
+    #[test]
+    fn test_throttled_user_does_not_block_others() {
+        let gateway = gateway(RateLimiterConfig {
+            rate_limit: 100,
+            per_user_rate_limit: 1,
+            user_rate_limits: HashMap::from([("reporting".to_string(), 3)]),
+            ..Default::default()
+        });
+        
+        let addr: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+        let other_addr: SocketAddr = "10.0.0.2:40000".parse().unwrap();
+        
+        assert!(gateway.allow_user(addr, "alice"));
+        assert!(!gateway.allow_user(addr, "alice"));
+        
+        // Other users, and the same user from another address, still connect
+        assert!(gateway.allow_user(addr, "bob"));
+        assert!(gateway.allow_user(other_addr, "alice"));
+        for _ in 0..3 {
+            assert!(gateway.allow_user(addr, "reporting"));
+        }
+        assert!(!gateway.allow_user(addr, "reporting"));
+        
+        // Connections are still checked against the address-wide limit
+        assert!(gateway.allow_connection(addr));
+    }
+    
+    #[test]
+    fn test_anomalous_queries_logged_or_blocked() {
+        let addr: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+        let injection = "SELECT * FROM users WHERE name = '' OR '1'='1'";
+        let benign = "SELECT * FROM users WHERE name = 'alice'";
+        
+        // Logging only by default
+        let gateway = gateway(RateLimiterConfig::default());
+        assert!(gateway.analyze_query(addr, injection));
+        
+        let gateway = gateway_with_config(
+            SecurityGatewayConfig { block_anomalous_queries: true, ..Default::default() },
+            RateLimiterConfig::default(),
+        );
+        assert!(!gateway.analyze_query(addr, injection));
+        assert!(gateway.analyze_query(addr, benign));
+    }
+    
+    #[test]
+    fn test_network_access_lists() {
+        let network_access = NetworkAccessConfig {
+            allow: vec!["10.0.0.0/8".parse().unwrap(), "192.168.1.0/24".parse().unwrap()],
+            deny: vec!["10.0.5.0/24".parse().unwrap()],
+            default_policy: AccessPolicy::Deny,
+        };
+        let gateway = gateway_with_config(
+            SecurityGatewayConfig { network_access, ..Default::default() },
+            RateLimiterConfig { rate_limit: 1000, ..Default::default() },
+        );
+        let addr = |addr: &str| -> SocketAddr { addr.parse().unwrap() };
+        
+        // Allowed networks
+        assert!(gateway.allow_connection(addr("10.1.2.3:40000")));
+        assert!(gateway.allow_connection(addr("192.168.1.20:40000")));
+        assert!(gateway.allow_connection(addr("[::ffff:10.1.2.3]:40000")));
+        
+        // A deny entry wins over the broader allow entry
+        assert!(!gateway.allow_connection(addr("10.0.5.7:40000")));
+        assert!(!gateway.allow_connection(addr("10.0.5.7:40001")));
+        assert_eq!(gateway.traffic_analyzer.denied_connections("10.0.5.7".parse().unwrap()), 2);
+        
+        // Outside both lists the default policy applies
+        assert!(!gateway.allow_connection(addr("172.16.0.1:40000")));
+        assert!(!gateway.allow_connection(addr("[2001:db8::1]:40000")));
+        
+        // Disabling the gateway doesn't lift the access lists
+        let gateway = gateway_with_config(
+            SecurityGatewayConfig { enabled: false, network_access: gateway.config.network_access.clone(), ..Default::default() },
+            RateLimiterConfig::default(),
+        );
+        assert!(gateway.allow_connection(addr("10.1.2.3:40000")));
+        assert!(!gateway.allow_connection(addr("10.0.5.7:40000")));
+    }
+    
+    #[test]
+    fn test_network_access_default_policy() {
+        let network_access = NetworkAccessConfig {
+            deny: vec!["203.0.113.0/24".parse().unwrap(), "2001:db8::/32".parse().unwrap()],
+            ..Default::default()
+        };
+        
+        // Without lists, everyone may connect
+        assert!(NetworkAccessConfig::default().permits("203.0.113.9".parse().unwrap()));
+        
+        assert!(network_access.permits("198.51.100.1".parse().unwrap()));
+        assert!(network_access.permits("2001:db9::1".parse().unwrap()));
+        assert!(!network_access.permits("203.0.113.9".parse().unwrap()));
+        assert!(!network_access.permits("2001:db8::1".parse().unwrap()));
+    }
+}
\ No newline at end of file
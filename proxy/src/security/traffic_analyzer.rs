@@ -2,11 +2,22 @@
 //!
 //! This module analyzes client traffic patterns to identify potential security issues.
 
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
+use log::warn;
+
+use crate::interception::analyzer::QueryType;
+
+/// Added to every query type count when comparing mixes, so a type missing
+/// from one window doesn't make the divergence infinite
+const MIX_SMOOTHING: f64 = 0.5;
+
+/// How long the query pattern of an idle client is kept
+const PATTERN_IDLE_TIMEOUT: Duration = Duration::from_secs(3600);
+
 /// Configuration for traffic analysis
 #[derive(Debug, Clone)]
 pub struct TrafficAnalyzerConfig {
@@ -21,6 +32,14 @@ pub struct TrafficAnalyzerConfig {
     
     /// Threshold for suspicious traffic patterns (ratio)
     pub suspicious_pattern_threshold: f64,
+    
+    /// Queries per window a client's query type mix is measured over; 0
+    /// disables query pattern tracking
+    pub pattern_window_size: usize,
+    
+    /// KL divergence between consecutive windows, in nats, at which a
+    /// client's query mix counts as shifted
+    pub pattern_shift_threshold: f64,
 }
 
 impl Default for TrafficAnalyzerConfig {
@@ -30,10 +49,89 @@ impl Default for TrafficAnalyzerConfig {
             time_window_seconds: 60,
             max_requests_per_client: 1000,
             suspicious_pattern_threshold: 0.9,
+            pattern_window_size: 100,
+            pattern_shift_threshold: 1.0,
+        }
+    }
+}
+
+/// Notable change in a client's traffic
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrafficEvent {
+    /// A client's mix of query types changed sharply between windows, as
+    /// when a reporting client starts issuing bulk deletes
+    QueryMixShift {
+        /// Client address
+        addr: SocketAddr,
+        
+        /// KL divergence of the new window's mix from the previous one's, in nats
+        divergence: f64,
+        
+        /// Query type counts of the new window, most frequent first
+        current_mix: Vec<(&'static str, u32)>,
+    },
+}
+
+/// Query type mix of a client over consecutive windows of queries
+#[derive(Debug)]
+struct QueryPattern {
+    /// Query type counts of the last complete window
+    baseline: Option<HashMap<&'static str, u32>>,
+    
+    /// Query type counts of the window being filled
+    current: HashMap<&'static str, u32>,
+    
+    /// Queries in the window being filled
+    current_total: usize,
+    
+    /// Last time a query was recorded
+    last_seen: Instant,
+}
+
+impl QueryPattern {
+    fn new() -> Self {
+        Self {
+            baseline: None,
+            current: HashMap::new(),
+            current_total: 0,
+            last_seen: Instant::now(),
+        }
+    }
+    
+    /// Count a query, returning the divergence from the previous window
+    /// once the current one is full
+    fn record(&mut self, query_type: &'static str, window_size: usize) -> Option<f64> {
+        self.last_seen = Instant::now();
+        *self.current.entry(query_type).or_insert(0) += 1;
+        self.current_total += 1;
+        if self.current_total < window_size {
+            return None;
         }
+        
+        let window = std::mem::take(&mut self.current);
+        self.current_total = 0;
+        let divergence = self.baseline.as_ref().map(|baseline| kl_divergence(&window, baseline));
+        self.baseline = Some(window);
+        divergence
     }
 }
 
+/// KL divergence of the mix `current` from the mix `baseline`, in nats
+fn kl_divergence(current: &HashMap<&'static str, u32>, baseline: &HashMap<&'static str, u32>) -> f64 {
+    let query_types: HashSet<&'static str> = current.keys().chain(baseline.keys()).copied().collect();
+    let smoothing = MIX_SMOOTHING * query_types.len() as f64;
+    let current_total = current.values().sum::<u32>() as f64 + smoothing;
+    let baseline_total = baseline.values().sum::<u32>() as f64 + smoothing;
+    
+    query_types.iter()
+        .map(|query_type| {
+            let p = (current.get(query_type).copied().unwrap_or(0) as f64 + MIX_SMOOTHING) / current_total;
+            let q = (baseline.get(query_type).copied().unwrap_or(0) as f64 + MIX_SMOOTHING) / baseline_total;
+            p * (p / q).ln()
+        })
+        .sum()
+}
+
 /// Client traffic statistics
 #[derive(Debug)]
 struct ClientStats {
@@ -69,6 +167,12 @@ pub struct TrafficAnalyzer {
     /// Client statistics
     client_stats: Mutex<HashMap<SocketAddr, ClientStats>>,
     
+    /// Times of denied connection attempts, by client IP
+    denied_connections: Mutex<HashMap<IpAddr, Vec<Instant>>>,
+    
+    /// Query type mix of each client
+    query_patterns: Mutex<HashMap<SocketAddr, QueryPattern>>,
+    
     /// Last cleanup time
     last_cleanup: Mutex<Instant>,
 }
@@ -79,6 +183,8 @@ impl TrafficAnalyzer {
         Self {
             config,
             client_stats: Mutex::new(HashMap::new()),
+            denied_connections: Mutex::new(HashMap::new()),
+            query_patterns: Mutex::new(HashMap::new()),
             last_cleanup: Mutex::new(Instant::now()),
         }
     }
@@ -103,6 +209,71 @@ impl TrafficAnalyzer {
         self.cleanup_if_needed();
     }
     
+    /// Record a connection attempt refused by network access control
+    pub fn record_denied_connection(&self, addr: SocketAddr) {
+        warn!("Denied connection attempt from {}", addr);
+        if !self.config.enabled {
+            return;
+        }
+        
+        let now = Instant::now();
+        self.denied_connections.lock().unwrap().entry(addr.ip()).or_default().push(now);
+        
+        // Periodic cleanup
+        self.cleanup_if_needed();
+    }
+    
+    /// Number of denied connection attempts from an IP in the time window
+    pub fn denied_connections(&self, ip: IpAddr) -> usize {
+        let now = Instant::now();
+        let time_window = Duration::from_secs(self.config.time_window_seconds);
+        
+        self.denied_connections.lock().unwrap()
+            .get(&ip)
+            .map_or(0, |times| times.iter().filter(|time| now.duration_since(**time) < time_window).count())
+    }
+    
+    /// Record the type of a query a client ran, returning an event if its
+    /// query mix shifted
+    ///
+    /// Queries are counted in windows of `pattern_window_size` and each full
+    /// window is compared with the one before it, so a client's pattern takes
+    /// two histograms over the query types.
+    pub fn record_query_type(&self, addr: SocketAddr, query_type: &QueryType) -> Option<TrafficEvent> {
+        if !self.config.enabled || self.config.pattern_window_size == 0 {
+            return None;
+        }
+        
+        let event = {
+            let mut patterns = self.query_patterns.lock().unwrap();
+            let pattern = patterns.entry(addr).or_insert_with(QueryPattern::new);
+            pattern.record(query_type.as_str(), self.config.pattern_window_size)
+                .filter(|divergence| *divergence >= self.config.pattern_shift_threshold)
+                .map(|divergence| {
+                    let mut current_mix: Vec<(&'static str, u32)> = pattern.baseline.iter()
+                        .flatten()
+                        .map(|(query_type, count)| (*query_type, *count))
+                        .collect();
+                    current_mix.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+                    TrafficEvent::QueryMixShift { addr, divergence, current_mix }
+                })
+        };
+        
+        if let Some(TrafficEvent::QueryMixShift { divergence, current_mix, .. }) = &event {
+            warn!("Query mix of {} shifted (divergence {:.2}): now {:?}", addr, divergence, current_mix);
+        }
+        
+        // Periodic cleanup
+        self.cleanup_if_needed();
+        event
+    }
+    
+    /// Forget the statistics of a closed connection
+    pub fn close_connection(&self, addr: SocketAddr) {
+        self.client_stats.lock().unwrap().remove(&addr);
+        self.query_patterns.lock().unwrap().remove(&addr);
+    }
+    
     /// Check if client traffic is suspicious
     pub fn is_suspicious(&self, addr: SocketAddr) -> bool {
         if !self.config.enabled {
@@ -145,7 +316,75 @@ impl TrafficAnalyzer {
             // Remove clients with no recent activity
             stats.retain(|_, client_stats| !client_stats.request_times.is_empty());
             
+            let mut denied = self.denied_connections.lock().unwrap();
+            for times in denied.values_mut() {
+                times.retain(|time| now.duration_since(*time) < time_window);
+            }
+            denied.retain(|_, times| !times.is_empty());
+            
+            self.query_patterns.lock().unwrap()
+                .retain(|_, pattern| now.duration_since(pattern.last_seen) < PATTERN_IDLE_TIMEOUT);
+            
             *last_cleanup = now;
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn analyzer() -> TrafficAnalyzer {
+        TrafficAnalyzer::new(TrafficAnalyzerConfig {
+            pattern_window_size: 20,
+            ..Default::default()
+        })
+    }
+    
+    /// Record a window of 16 reads and 4 inserts, returning the events raised
+    fn record_reads(analyzer: &TrafficAnalyzer, addr: SocketAddr) -> Vec<TrafficEvent> {
+        (0..20)
+            .map(|i| if i % 5 == 0 { QueryType::Insert } else { QueryType::Select })
+            .filter_map(|query_type| analyzer.record_query_type(addr, &query_type))
+            .collect()
+    }
+    
+    #[test]
+    fn test_query_mix_shift_detected() {
+        let analyzer = analyzer();
+        let addr: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+        
+        // A stable mix raises nothing
+        for _ in 0..5 {
+            assert!(record_reads(&analyzer, addr).is_empty());
+        }
+        
+        // Switching to bulk deletes raises an alert once the window fills
+        let events: Vec<TrafficEvent> = (0..20)
+            .filter_map(|_| analyzer.record_query_type(addr, &QueryType::Delete))
+            .collect();
+        match events.as_slice() {
+            [TrafficEvent::QueryMixShift { addr: event_addr, divergence, current_mix }] => {
+                assert_eq!(*event_addr, addr);
+                assert!(*divergence >= 1.0);
+                assert_eq!(current_mix, &vec![("DELETE", 20)]);
+            }
+            other => panic!("expected a query mix shift, got {:?}", other),
+        }
+        
+        // Other clients are tracked separately
+        let other_addr: SocketAddr = "10.0.0.2:40000".parse().unwrap();
+        assert!(record_reads(&analyzer, other_addr).is_empty());
+        assert!(record_reads(&analyzer, other_addr).is_empty());
+    }
+    
+    #[test]
+    fn test_kl_divergence() {
+        let mix = |counts: &[(&'static str, u32)]| counts.iter().copied().collect::<HashMap<_, _>>();
+        
+        let reads = mix(&[("SELECT", 80), ("INSERT", 20)]);
+        assert!(kl_divergence(&reads, &reads).abs() < 1e-9);
+        assert!(kl_divergence(&mix(&[("SELECT", 70), ("INSERT", 30)]), &reads) < 0.1);
+        assert!(kl_divergence(&mix(&[("DELETE", 100)]), &reads) > 1.0);
+    }
 } 
\ No newline at end of file
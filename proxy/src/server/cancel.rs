@@ -0,0 +1,303 @@
+//! Query cancellation
+//!
+//! Clients cancel a running query by opening a second connection and sending
+//! a `CancelRequest` with the process ID and secret key from their session's
+//! `BackendKeyData`. Statements run on pooled backend sessions, so the proxy
+//! hands out its own keys and maps each one to the backend session currently
+//! running a statement for that client.
+
+use crate::error::{ProxyError, Result};
+use crate::protocol::connection::ClientWrapper;
+use log::debug;
+use rand::Rng;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio_postgres::{CancelToken, NoTls};
+
+/// Process ID and secret key sent to a client in `BackendKeyData`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CancelKey {
+    /// Process ID the client quotes in its `CancelRequest`
+    pub process_id: i32,
+
+    /// Secret key the client quotes in its `CancelRequest`
+    pub secret_key: i32,
+}
+
+/// Registered client session
+struct CancelTarget {
+    /// Secret key handed to the client
+    secret_key: i32,
+
+    /// Cancel token of the backend session running the client's statement
+    running: Option<CancelToken>,
+}
+
+/// Cancel keys of every client session, shared across connections
+#[derive(Clone, Default)]
+pub struct CancelRegistry {
+    /// Registered sessions by process ID
+    sessions: Arc<Mutex<HashMap<i32, CancelTarget>>>,
+}
+
+impl CancelRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocate a cancel key for a new client session
+    ///
+    /// The key is released when the returned registration is dropped.
+    pub fn register(&self) -> CancelRegistration {
+        let mut rng = rand::thread_rng();
+        let mut sessions = self.sessions.lock().unwrap();
+
+        let process_id = loop {
+            let process_id = rng.gen_range(1..=i32::MAX);
+            if !sessions.contains_key(&process_id) {
+                break process_id;
+            }
+        };
+        let secret_key = rng.gen();
+
+        sessions.insert(process_id, CancelTarget { secret_key, running: None });
+
+        CancelRegistration {
+            registry: self.clone(),
+            key: CancelKey { process_id, secret_key },
+        }
+    }
+
+    /// Cancel the statement running for the session with this key
+    ///
+    /// Returns whether a cancel was sent to the backend. Unknown keys, wrong
+    /// secrets and idle sessions are ignored, as PostgreSQL does.
+    pub async fn cancel(&self, process_id: i32, secret_key: i32) -> Result<bool> {
+        let token = {
+            let sessions = self.sessions.lock().unwrap();
+            match sessions.get(&process_id) {
+                Some(target) if target.secret_key == secret_key => target.running.clone(),
+                _ => None,
+            }
+        };
+
+        match token {
+            Some(token) => {
+                debug!("Forwarding cancel request for process ID {} to the backend", process_id);
+                token
+                    .cancel_query(NoTls)
+                    .await
+                    .map_err(|e| ProxyError::Database(format!("Failed to cancel query: {}", e)))?;
+                Ok(true)
+            }
+            None => {
+                debug!("Ignoring cancel request for process ID {}: no matching running query", process_id);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Number of registered sessions
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Whether no sessions are registered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record which backend session, if any, runs the statement for `process_id`
+    fn set_running(&self, process_id: i32, token: Option<CancelToken>) {
+        if let Some(target) = self.sessions.lock().unwrap().get_mut(&process_id) {
+            target.running = token;
+        }
+    }
+}
+
+impl fmt::Debug for CancelRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelRegistry")
+            .field("sessions", &self.len())
+            .finish()
+    }
+}
+
+/// Cancel key held by one client session
+#[derive(Debug)]
+pub struct CancelRegistration {
+    /// Registry the key belongs to
+    registry: CancelRegistry,
+
+    /// Key sent to the client
+    key: CancelKey,
+}
+
+impl CancelRegistration {
+    /// Key to send to the client in `BackendKeyData`
+    pub fn key(&self) -> CancelKey {
+        self.key
+    }
+
+    /// Make `client` the target of cancel requests until the guard is dropped
+    pub fn running(&self, client: &ClientWrapper) -> RunningQuery<'_> {
+        self.registry.set_running(self.key.process_id, Some(client.inner().cancel_token()));
+        RunningQuery { registration: self }
+    }
+}
+
+impl Drop for CancelRegistration {
+    fn drop(&mut self) {
+        self.registry.sessions.lock().unwrap().remove(&self.key.process_id);
+    }
+}
+
+/// Statement that can be cancelled while the guard is alive
+#[derive(Debug)]
+pub struct RunningQuery<'a> {
+    /// Registration of the session running the statement
+    registration: &'a CancelRegistration,
+}
+
+impl Drop for RunningQuery<'_> {
+    fn drop(&mut self) {
+        self.registration.registry.set_running(self.registration.key.process_id, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::Notify;
+    use tokio_postgres::error::SqlState;
+
+    /// Process ID and secret key the fake backend hands out
+    const BACKEND_PID: i32 = 4242;
+    const BACKEND_SECRET: i32 = 77;
+
+    /// Code of a `CancelRequest` in place of a protocol version
+    const CANCEL_REQUEST_CODE: i32 = 80877102;
+
+    /// Backend whose queries run until a matching cancel request arrives
+    async fn slow_backend() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cancelled = Arc::new(Notify::new());
+
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(serve_backend(socket, cancelled.clone()));
+            }
+        });
+
+        addr
+    }
+
+    async fn serve_backend(mut socket: TcpStream, cancelled: Arc<Notify>) -> std::io::Result<()> {
+        let length = socket.read_i32().await? as usize;
+        let code = socket.read_i32().await?;
+        if code == CANCEL_REQUEST_CODE {
+            let process_id = socket.read_i32().await?;
+            let secret_key = socket.read_i32().await?;
+            if process_id == BACKEND_PID && secret_key == BACKEND_SECRET {
+                cancelled.notify_one();
+            }
+            return Ok(());
+        }
+
+        // Startup message, answered with AuthenticationOk, BackendKeyData and ReadyForQuery
+        socket.read_exact(&mut vec![0u8; length - 8]).await?;
+        let mut reply = vec![b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'K', 0, 0, 0, 12];
+        reply.extend_from_slice(&BACKEND_PID.to_be_bytes());
+        reply.extend_from_slice(&BACKEND_SECRET.to_be_bytes());
+        reply.extend_from_slice(&[b'Z', 0, 0, 0, 5, b'I']);
+        socket.write_all(&reply).await?;
+
+        loop {
+            let tag = socket.read_u8().await?;
+            let length = socket.read_i32().await? as usize;
+            socket.read_exact(&mut vec![0u8; length - 4]).await?;
+
+            match tag {
+                b'Q' => {
+                    // Simulated long-running query, aborted by a cancel request
+                    cancelled.notified().await;
+
+                    let mut fields = Vec::new();
+                    fields.extend_from_slice(b"SERROR\0C57014\0Mcanceling statement due to user request\0\0");
+                    let mut reply = vec![b'E'];
+                    reply.extend_from_slice(&(fields.len() as i32 + 4).to_be_bytes());
+                    reply.extend_from_slice(&fields);
+                    reply.extend_from_slice(&[b'Z', 0, 0, 0, 5, b'I']);
+                    socket.write_all(&reply).await?;
+                }
+                b'X' => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    async fn connect(addr: std::net::SocketAddr) -> ClientWrapper {
+        let mut config = tokio_postgres::Config::new();
+        config.host(&addr.ip().to_string()).port(addr.port()).user("postgres");
+        let (client, connection) = config.connect(NoTls).await.unwrap();
+        tokio::spawn(connection);
+        ClientWrapper::new(client)
+    }
+
+    #[tokio::test]
+    async fn test_cancel_aborts_running_query() {
+        let addr = slow_backend().await;
+        let client = connect(addr).await;
+
+        let registry = CancelRegistry::new();
+        let registration = registry.register();
+        let key = registration.key();
+
+        let query = async {
+            let _running = registration.running(&client);
+            client.inner().batch_execute("SELECT pg_sleep(60)").await
+        };
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            registry.cancel(key.process_id, key.secret_key).await.unwrap()
+        };
+
+        let (result, sent) = tokio::time::timeout(Duration::from_secs(5), async { tokio::join!(query, cancel) })
+            .await
+            .expect("query was not cancelled");
+
+        assert!(sent);
+        let error = result.unwrap_err();
+        assert_eq!(error.code(), Some(&SqlState::QUERY_CANCELED));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_requires_matching_key_and_running_query() {
+        let addr = slow_backend().await;
+        let client = connect(addr).await;
+
+        let registry = CancelRegistry::new();
+        let registration = registry.register();
+        let key = registration.key();
+
+        // Nothing is running yet
+        assert!(!registry.cancel(key.process_id, key.secret_key).await.unwrap());
+
+        let running = registration.running(&client);
+        assert!(!registry.cancel(key.process_id, key.secret_key.wrapping_add(1)).await.unwrap());
+        assert!(!registry.cancel(key.process_id.wrapping_add(1), key.secret_key).await.unwrap());
+        drop(running);
+
+        // The key is released with the session
+        assert_eq!(registry.len(), 1);
+        drop(registration);
+        assert!(registry.is_empty());
+    }
+}
@@ -0,0 +1,442 @@
+//! Server implementation for the PostgreSQL proxy
+//!
+//! This module provides the main server implementation for the PostgreSQL proxy.
+
+/// Backend connection pool shared by client sessions
+pub mod pool;
+
+/// Query cancellation across client connections
+pub mod cancel;
+
+pub use self::cancel::{CancelKey, CancelRegistry};
+pub use self::pool::{BackendPool, BackendPoolConfig};
+
+use crate::config::ProxyConfig;
+use crate::error::{ProxyError, Result};
+use crate::interception::{InterceptionConfig, InterceptionManager, VerificationManager};
+use crate::protocol::auth::AuthHandler;
+use crate::protocol::connection::ClientConnection;
+use crate::protocol::formatter::MessageFormatter;
+use crate::protocol::message::{BackendMessage, ErrorOrNoticeFields};
+use crate::protocol::tls;
+use crate::protocol::validator::ProtocolValidator;
+use crate::transaction::TransactionManager;
+use crate::security::{RateLimiter, RateLimiterConfig, SecurityGateway};
+use crate::security::anomaly_detector::{AnomalyDetector, AnomalyDetectorConfig};
+use crate::security::dos_protection::{DoSProtection, DoSProtectionConfig};
+use crate::security::security_gateway::SecurityGatewayConfig;
+use crate::security::traffic_analyzer::{TrafficAnalyzer, TrafficAnalyzerConfig};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{watch, Semaphore};
+use tokio::task::JoinHandle;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::net::SocketAddr;
+use std::time::Duration;
+use log::{info, error, debug, warn};
+use tokio_rustls::TlsAcceptor;
+
+/// Outcome of draining client sessions when the server stops
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// Clients whose sessions were closed after the grace period, with their
+    /// transactions still open
+    pub forcibly_closed: Vec<SocketAddr>,
+}
+
+/// Main proxy server implementation
+#[derive(Clone)]
+pub struct ProxyServer {
+    /// Server configuration
+    config: ProxyConfig,
+    
+    /// Rate limiting and DoS protection for clients
+    security_gateway: Arc<SecurityGateway>,
+    
+    /// One permit per open client connection, up to `max_connections`
+    connection_slots: Arc<Semaphore>,
+    
+    /// Acceptor for client TLS, if enabled
+    tls_acceptor: Option<TlsAcceptor>,
+    
+    /// Backend connections shared by all clients
+    backend_pool: BackendPool,
+    
+    /// Cancel keys of all client sessions
+    cancel_registry: CancelRegistry,
+    
+    /// Verification engine recording the statements of every client session,
+    /// set up when the server starts if verification is enabled
+    verifier: Arc<Mutex<Option<Arc<VerificationManager>>>>,
+    
+    /// Tasks serving client sessions, by client address
+    sessions: Arc<Mutex<HashMap<SocketAddr, JoinHandle<()>>>>,
+    
+    /// Set when the server stops, to stop accepting and drain sessions
+    shutdown: Arc<watch::Sender<bool>>,
+    
+    /// Whether the server is running
+    running: Arc<Mutex<bool>>,
+}
+
+impl ProxyServer {
+    /// Create a new proxy server
+    pub fn new(config: ProxyConfig) -> Result<Self> {
+        // Create rate limiter
+        let rate_limiter_config = RateLimiterConfig {
+            allow_list: vec!["127.0.0.1".parse().unwrap()],
+            block_list: Vec::new(),
+            ..config.rate_limiter_config.clone()
+        };
+        
+        let rate_limiter = Arc::new(Mutex::new(RateLimiter::new(rate_limiter_config)?));
+        
+        let security_gateway = Arc::new(SecurityGateway::new(
+            SecurityGatewayConfig {
+                network_access: config.network_access_config.clone(),
+                ..Default::default()
+            },
+            rate_limiter,
+            Arc::new(DoSProtection::new(DoSProtectionConfig::default())),
+            Arc::new(AnomalyDetector::new(AnomalyDetectorConfig::default())),
+            Arc::new(TrafficAnalyzer::new(TrafficAnalyzerConfig::default())),
+        ));
+        
+        // Load the certificate up front so a bad TLS setup fails at startup
+        let tls_acceptor = tls::optional_tls_acceptor(config.tls_config.as_ref())?;
+        
+        // Connections open lazily, so creating the pool needs no backend
+        let backend_pool = BackendPool::new(&config)?;
+        
+        let connection_slots = Arc::new(Semaphore::new(config.max_connections));
+        
+        Ok(Self {
+            config,
+            security_gateway,
+            connection_slots,
+            tls_acceptor,
+            backend_pool,
+            cancel_registry: CancelRegistry::new(),
+            verifier: Arc::new(Mutex::new(None)),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            shutdown: Arc::new(watch::channel(false).0),
+            running: Arc::new(Mutex::new(false)),
+        })
+    }
+    
+    /// Start the proxy server
+    pub async fn start(&self) -> Result<()> {
+        // Make sure we're not already running
+        {
+            let mut running = self.running.lock().unwrap();
+            if *running {
+                return Err(ProxyError::Other("Server is already running".to_string()));
+            }
+            *running = true;
+        }
+        if let Err(e) = self.start_verifier().await {
+            *self.running.lock().unwrap() = false;
+            return Err(e);
+        }
+        self.shutdown.send_replace(false);
+        
+        // Create a TCP listener
+        let listener = TcpListener::bind(&self.config.listen_addr).await
+            .map_err(|e| ProxyError::Other(format!("Failed to bind to {}: {}", self.config.listen_addr, e)))?;
+        
+        info!("Proxy server listening on {}", self.config.listen_addr);
+        
+        // Open the minimum pool size in the background; clients can connect meanwhile
+        let backend_pool = self.backend_pool.clone();
+        tokio::spawn(async move {
+            if let Err(e) = backend_pool.warm_up().await {
+                warn!("Failed to open backend connections: {}", e);
+            }
+        });
+        
+        // Create a thread-safe reference to self
+        let server = Arc::new(self.clone());
+        
+        // Accept incoming connections
+        tokio::spawn(server.accept_connections(listener));
+        
+        Ok(())
+    }
+    
+    /// Set up the verification engine shared by client sessions, if
+    /// verification is enabled
+    ///
+    /// Starts the engine's commitment retry and re-verification tasks. A
+    /// server restarted after [`stop`](Self::stop) keeps its engine.
+    async fn start_verifier(&self) -> Result<()> {
+        if !self.config.verification_config.enabled || self.verifier.lock().unwrap().is_some() {
+            return Ok(());
+        }
+        
+        let verifier = Arc::new(VerificationManager::new(self.config.verification_config.clone()).await?);
+        
+        // The engine's background tasks live as long as it does
+        if VerificationManager::spawn_commitment_retry_task(verifier.clone()).is_some() {
+            debug!("Retrying failed on-chain commitments in the background");
+        }
+        if VerificationManager::spawn_reverification_scheduler(verifier.clone()).is_some() {
+            debug!("Re-verifying transactions skipped during verifier outages in the background");
+        }
+        
+        *self.verifier.lock().unwrap() = Some(verifier);
+        info!("Verification enabled for client sessions");
+        Ok(())
+    }
+    
+    /// Accept connections until the server is stopped
+    async fn accept_connections(self: Arc<Self>, listener: TcpListener) {
+        let mut shutdown = self.shutdown.subscribe();
+        loop {
+            // Check if we're still running
+            if !*self.running.lock().unwrap() {
+                break;
+            }
+            
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = shutdown.wait_for(|stopping| *stopping) => break,
+            };
+            
+            match accepted {
+                Ok((stream, addr)) => {
+                    // Clients past the limit are turned away, like PostgreSQL does,
+                    // rather than holding sockets open while they wait
+                    let permit = match self.connection_slots.clone().try_acquire_owned() {
+                        Ok(permit) => permit,
+                        Err(_) => {
+                            warn!("Rejecting connection from {}: {} connections already open", addr, self.config.max_connections);
+                            tokio::spawn(reject_connection(stream));
+                            continue;
+                        }
+                    };
+                    
+                    // Clone the server reference for this connection
+                    let connection_server = self.clone();
+                    
+                    // Spawn a task to handle this connection
+                    let session = tokio::spawn(async move {
+                        if let Err(e) = connection_server.handle_connection(stream, addr).await {
+                            error!("Error handling connection: {}", e);
+                        }
+                        drop(permit);
+                    });
+                    
+                    // Kept so sessions outliving the shutdown grace period can be closed
+                    let mut sessions = self.sessions.lock().unwrap();
+                    sessions.retain(|_, session| !session.is_finished());
+                    sessions.insert(addr, session);
+                }
+                Err(e) => {
+                    error!("Error accepting connection: {}", e);
+                }
+            }
+        }
+        
+        info!("Proxy server no longer accepting connections");
+    }
+    
+    /// Stop the proxy server, draining client sessions
+    ///
+    /// New connections are refused at once and idle sessions are closed.
+    /// Sessions in a transaction may finish it with COMMIT or ROLLBACK for up
+    /// to `shutdown_timeout` seconds; any still open then are closed forcibly
+    /// and listed in the returned summary.
+    pub async fn stop(&self) -> Result<ShutdownSummary> {
+        {
+            let mut running = self.running.lock().unwrap();
+            if !*running {
+                return Ok(ShutdownSummary::default());  // Already stopped
+            }
+            *running = false;
+        }
+        
+        info!("Stopping proxy server, draining {} connections...", self.active_connections());
+        self.shutdown.send_replace(true);
+        
+        let grace_period = Duration::from_secs(self.config.shutdown_timeout);
+        let drained = tokio::time::timeout(grace_period, async {
+            while self.active_connections() > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.is_ok();
+        
+        let mut summary = ShutdownSummary::default();
+        let mut sessions = self.sessions.lock().unwrap();
+        for (addr, session) in sessions.drain() {
+            if !drained && !session.is_finished() {
+                warn!("Closing session of {} with a transaction still open", addr);
+                session.abort();
+                summary.forcibly_closed.push(addr);
+            }
+        }
+        
+        info!("Proxy server stopped");
+        Ok(summary)
+    }
+    
+    /// Handle a client connection
+    async fn handle_connection(&self, client_stream: TcpStream, client_addr: SocketAddr) -> Result<()> {
+        // Check if the client is allowed to connect
+        if !self.security_gateway.allow_connection(client_addr) {
+            debug!("Connection from {} rejected by security gateway", client_addr);
+            return Ok(());
+        }
+        
+        info!("New connection from {}", client_addr);
+        
+        // Create a transaction manager
+        let transaction_manager = Arc::new(Mutex::new(TransactionManager::new()));
+        
+        // Each session analyzes its own statements, recording them with the shared verifier
+        let interception = self.verifier.lock().unwrap().clone()
+            .map(|verifier| InterceptionManager::with_verifier(InterceptionConfig::default(), verifier));
+        
+        // Create a client connection
+        let mut client_connection = ClientConnection::new(
+            client_stream,
+            client_addr,
+            self.config.clone(),
+            transaction_manager,
+        )
+        .with_tls(self.tls_acceptor.clone())
+        .with_backend_pool(Some(self.backend_pool.clone()))
+        .with_cancel_registry(self.cancel_registry.clone())
+        .with_security_gateway(Some(self.security_gateway.clone()))
+        .with_interception(interception)
+        .with_shutdown(self.shutdown.subscribe())
+        .with_connection_slots(self.connection_slots.clone());
+        
+        // Handle the connection
+        let result = client_connection.handle_connection().await;
+        self.security_gateway.connection_closed(client_addr);
+        
+        match result {
+            Ok(()) => {
+                info!("Connection from {} closed", client_addr);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Error handling connection from {}: {}", client_addr, e);
+                Err(e)
+            }
+        }
+    }
+    
+    /// Get server configuration
+    pub fn config(&self) -> &ProxyConfig {
+        &self.config
+    }
+    
+    /// Check if the server is running
+    pub fn is_running(&self) -> bool {
+        *self.running.lock().unwrap()
+    }
+    
+    /// Number of client connections currently open
+    pub fn active_connections(&self) -> usize {
+        self.config.max_connections - self.connection_slots.available_permits()
+    }
+}
+
+/// Tell a client over the connection limit why it is being disconnected
+async fn reject_connection(mut stream: TcpStream) {
+    let fields = ErrorOrNoticeFields {
+        severity: Some("FATAL".to_string()),
+        code: Some("53300".to_string()),
+        message: Some("sorry, too many clients already".to_string()),
+        ..Default::default()
+    };
+    
+    match MessageFormatter::new().format_backend_message(&BackendMessage::ErrorResponse(fields)) {
+        Ok(bytes) => {
+            if let Err(e) = stream.write_all(&bytes).await {
+                debug!("Failed to send connection limit error: {}", e);
+            }
+        }
+        Err(e) => error!("Failed to format connection limit error: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::runtime::Runtime;
+    
+    #[test]
+    fn test_server_creation() {
+        let config = ProxyConfig::default();
+        let server = ProxyServer::new(config).unwrap();
+        
+        assert!(!server.is_running());
+    }
+    
+    #[test]
+    fn test_server_start_stop() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let config = ProxyConfig::default();
+            let server = ProxyServer::new(config).unwrap();
+            
+            // Start the server
+            server.start().await.unwrap();
+            assert!(server.is_running());
+            
+            // Stop the server; with no clients there is nothing to drain
+            let summary = server.stop().await.unwrap();
+            assert!(!server.is_running());
+            assert!(summary.forcibly_closed.is_empty());
+        });
+    }
+    
+    /// Wait until the server has `count` connections open
+    async fn wait_for_connections(server: &ProxyServer, count: usize) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while server.active_connections() != count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("connection count never reached");
+    }
+    
+    #[tokio::test]
+    async fn test_connections_over_limit_rejected() {
+        let config = ProxyConfig {
+            max_connections: 2,
+            ..ProxyConfig::default()
+        };
+        let server = Arc::new(ProxyServer::new(config).unwrap());
+        *server.running.lock().unwrap() = true;
+        
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(server.clone().accept_connections(listener));
+        
+        let first = TcpStream::connect(addr).await.unwrap();
+        let _second = TcpStream::connect(addr).await.unwrap();
+        wait_for_connections(&server, 2).await;
+        
+        // The next client is told why and disconnected
+        let mut third = TcpStream::connect(addr).await.unwrap();
+        let mut response = Vec::new();
+        third.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response.first(), Some(&b'E'));
+        assert!(String::from_utf8_lossy(&response).contains("53300"));
+        assert_eq!(server.active_connections(), 2);
+        
+        // Closing a connection frees its slot
+        drop(first);
+        wait_for_connections(&server, 1).await;
+        
+        let mut fourth = TcpStream::connect(addr).await.unwrap();
+        wait_for_connections(&server, 2).await;
+        let mut byte = [0u8; 1];
+        assert!(tokio::time::timeout(Duration::from_millis(100), fourth.read(&mut byte)).await.is_err());
+    }
+} 
\ No newline at end of file
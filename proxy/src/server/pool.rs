@@ -0,0 +1,278 @@
+//! Backend connection pool
+//!
+//! Client sessions share a bounded set of backend connections instead of each
+//! opening its own, so the backend's `max_connections` is not exhausted by
+//! many idle clients. A connection is checked out per statement and returned
+//! afterwards, unless the client holds a transaction or has changed session
+//! state; then it stays pinned to that client (see [`SessionAffinity`]).
+//!
+//! [`SessionAffinity`]: crate::protocol::connection::SessionAffinity
+
+use crate::config::ProxyConfig;
+use crate::error::{ProxyError, Result};
+use crate::protocol::connection::ClientWrapper;
+use deadpool_postgres::{Manager, ManagerConfig, Pool, PoolConfig, RecyclingMethod};
+use futures_util::future::try_join_all;
+use log::debug;
+use std::fmt;
+use std::time::Duration;
+use tokio_postgres::config::Config as PgConfig;
+use tokio_postgres::NoTls;
+
+/// Backend connection pool configuration
+#[derive(Debug, Clone)]
+pub struct BackendPoolConfig {
+    /// Connections opened when the server starts
+    pub min_size: usize,
+
+    /// Upper bound on backend connections across all clients
+    pub max_size: usize,
+
+    /// How long a statement waits for a free connection
+    pub acquire_timeout: Duration,
+}
+
+impl Default for BackendPoolConfig {
+    fn default() -> Self {
+        Self {
+            min_size: 2,
+            max_size: 20,
+            acquire_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Pool of backend connections shared by all client sessions
+#[derive(Clone)]
+pub struct BackendPool {
+    /// Underlying connection pool
+    pool: Pool,
+
+    /// Pool configuration
+    config: BackendPoolConfig,
+}
+
+impl BackendPool {
+    /// Create a pool for the backend and credentials in `config`
+    ///
+    /// Connections are opened lazily; [`warm_up`](Self::warm_up) opens the
+    /// configured minimum up front.
+    pub fn new(config: &ProxyConfig) -> Result<Self> {
+        Self::with_pg_config(backend_pg_config(config), config.pool_config.clone())
+    }
+
+    /// Create a pool with explicit connection settings
+    pub fn with_pg_config(pg_config: PgConfig, config: BackendPoolConfig) -> Result<Self> {
+        if config.max_size == 0 || config.min_size > config.max_size {
+            return Err(ProxyError::Config(format!(
+                "Invalid backend pool size: min {} max {}",
+                config.min_size, config.max_size
+            )));
+        }
+
+        // Returned connections are reset, so roles, settings and temporary
+        // tables never leak from one client to the next
+        let manager = Manager::from_config(pg_config, NoTls, ManagerConfig {
+            recycling_method: RecyclingMethod::Clean,
+        });
+
+        let pool = Pool::builder(manager)
+            .config(PoolConfig {
+                max_size: config.max_size,
+                ..Default::default()
+            })
+            .build()
+            .map_err(|e| ProxyError::Database(format!("Failed to create backend pool: {}", e)))?;
+
+        Ok(Self { pool, config })
+    }
+
+    /// Open the configured minimum number of connections
+    pub async fn warm_up(&self) -> Result<()> {
+        let sessions = try_join_all((0..self.config.min_size).map(|_| self.get())).await?;
+        debug!("Opened {} backend connection(s)", sessions.len());
+        Ok(())
+    }
+
+    /// Check out a backend session
+    ///
+    /// The connection goes back to the pool once every clone of the session
+    /// has been dropped.
+    pub async fn get(&self) -> Result<ClientWrapper> {
+        let client = tokio::time::timeout(self.config.acquire_timeout, self.pool.get())
+            .await
+            .map_err(|_| ProxyError::Database("Timed out waiting for a backend connection".to_string()))?
+            .map_err(|e| ProxyError::Database(format!("Failed to get backend connection: {}", e)))?;
+
+        Ok(ClientWrapper::pooled(client))
+    }
+
+    /// Backend connections currently open, whether checked out or idle
+    pub fn size(&self) -> usize {
+        self.pool.status().size
+    }
+
+    /// Maximum number of backend connections
+    pub fn max_size(&self) -> usize {
+        self.config.max_size
+    }
+}
+
+impl fmt::Debug for BackendPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackendPool")
+            .field("size", &self.size())
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+/// Connection settings for the proxy's backend sessions
+///
+/// Uses the configured database credentials, falling back to the first user
+/// in the auth config.
+pub fn backend_pg_config(config: &ProxyConfig) -> PgConfig {
+    let (fallback_user, fallback_password) = config.auth_config.users.iter()
+        .next()
+        .map(|(user, password)| (user.clone(), password.clone()))
+        .unwrap_or_else(|| ("postgres".to_string(), "postgres".to_string()));
+
+    let mut pg_config = PgConfig::new();
+    pg_config
+        .host(&config.backend_addr.ip().to_string())
+        .port(config.backend_addr.port())
+        .user(config.db_user.as_deref().unwrap_or(&fallback_user))
+        .password(config.db_password.as_deref().unwrap_or(&fallback_password))
+        .dbname(config.db_name.as_deref().unwrap_or("postgres"));
+    pg_config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Minimal backend that trusts every client and answers every simple query
+    ///
+    /// Returns its address and a counter of connections accepted so far.
+    async fn fake_backend() -> (std::net::SocketAddr, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve_backend(socket));
+            }
+        });
+
+        (addr, connections)
+    }
+
+    async fn serve_backend(mut socket: TcpStream) -> std::io::Result<()> {
+        // Startup message, answered with AuthenticationOk and ReadyForQuery
+        let length = socket.read_i32().await? as usize;
+        socket.read_exact(&mut vec![0u8; length - 4]).await?;
+        socket.write_all(&[b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'Z', 0, 0, 0, 5, b'I']).await?;
+
+        loop {
+            let tag = socket.read_u8().await?;
+            let length = socket.read_i32().await? as usize;
+            socket.read_exact(&mut vec![0u8; length - 4]).await?;
+
+            match tag {
+                b'Q' => {
+                    let mut reply = vec![b'C', 0, 0, 0, 11];
+                    reply.extend_from_slice(b"SELECT\0");
+                    reply.extend_from_slice(&[b'Z', 0, 0, 0, 5, b'I']);
+                    socket.write_all(&reply).await?;
+                }
+                b'X' => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    fn pool_for(addr: std::net::SocketAddr, min_size: usize, max_size: usize) -> BackendPool {
+        let mut config = ProxyConfig::default();
+        config.backend_addr = addr;
+        config.pool_config = BackendPoolConfig {
+            min_size,
+            max_size,
+            acquire_timeout: Duration::from_secs(5),
+        };
+        BackendPool::new(&config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_clients_share_bounded_backend_connections() {
+        const CLIENTS: usize = 12;
+        const MAX_BACKEND_CONNECTIONS: usize = 3;
+
+        let (addr, connections) = fake_backend().await;
+        let pool = pool_for(addr, 0, MAX_BACKEND_CONNECTIONS);
+
+        // Every client runs a few statements, each on a checked-out session
+        let clients = (0..CLIENTS).map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                for _ in 0..3 {
+                    let session = pool.get().await.unwrap();
+                    session.inner().batch_execute("SELECT 1").await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            })
+        });
+        for client in clients {
+            client.await.unwrap();
+        }
+
+        let opened = connections.load(Ordering::SeqCst);
+        assert!(opened >= 1);
+        assert!(opened <= MAX_BACKEND_CONNECTIONS, "{} clients opened {} backend connections", CLIENTS, opened);
+        assert!(pool.size() <= pool.max_size());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_session_holds_its_connection() {
+        let (addr, connections) = fake_backend().await;
+        let pool = pool_for(addr, 2, 2);
+        pool.warm_up().await.unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+        // A pinned session keeps its connection; others share what is left
+        let pinned = pool.get().await.unwrap();
+        for _ in 0..5 {
+            let session = pool.get().await.unwrap();
+            session.inner().batch_execute("SELECT 1").await.unwrap();
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+        // With both connections checked out, the next client waits and times out
+        let other = pool.get().await.unwrap();
+        let waiting = BackendPool {
+            config: BackendPoolConfig { acquire_timeout: Duration::from_millis(50), ..pool.config.clone() },
+            ..pool.clone()
+        };
+        assert!(waiting.get().await.is_err());
+
+        drop(other);
+        drop(pinned);
+        assert!(waiting.get().await.is_ok());
+    }
+
+    #[test]
+    fn test_pool_size_validation() {
+        let config = BackendPoolConfig { min_size: 4, max_size: 2, ..Default::default() };
+        assert!(BackendPool::with_pg_config(PgConfig::new(), config).is_err());
+
+        let config = BackendPoolConfig { min_size: 0, max_size: 0, ..Default::default() };
+        assert!(BackendPool::with_pg_config(PgConfig::new(), config).is_err());
+    }
+}
@@ -5,7 +5,7 @@
 
 // Re-export the WAL submodule
 pub mod wal;
-pub use wal::{WalCaptureManager, WalRecord, WalRecordType, TransactionTree, TransactionStatus, SavepointRecord};
+pub use wal::{WalCaptureManager, WalRecord, WalRecordType, TransactionTree, TransactionStatus, SavepointRecord, WAL_RECORD_FORMAT_VERSION, encode_wal_stream, decode_wal_stream};
 
 use crate::error::{ProxyError, Result};
 use crate::interception::analyzer::QueryMetadata;
@@ -53,6 +53,28 @@ pub struct Transaction {
     
     /// WAL records associated with this transaction
     pub wal_records: Vec<WalRecord>,
+    
+    /// Verification transactions of the statements run in this transaction
+    pub statement_ids: Vec<u64>,
+    
+    /// Global identifier the transaction was prepared under, for two-phase commit
+    pub prepared_gid: Option<String>,
+    
+    /// Number of leading `statement_ids` already verified at a checkpoint
+    pub checkpoint_position: usize,
+    
+    /// Savepoint of the latest checkpoint (`None` before the first one)
+    pub checkpoint_savepoint: Option<String>,
+}
+
+/// Statements of a transaction not yet verified at a checkpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointRange {
+    /// Savepoint of the previous checkpoint (`None` = the start of the transaction)
+    pub from_savepoint: Option<String>,
+    
+    /// Verification transactions of the statements, in execution order
+    pub statement_ids: Vec<u64>,
 }
 
 /// Savepoint structure
@@ -72,6 +94,9 @@ pub struct Savepoint {
     
     /// Statements executed after this savepoint
     pub statements: Vec<String>,
+    
+    /// Number of the transaction's `statement_ids` when the savepoint was created
+    pub statement_position: usize,
 }
 
 /// Transaction manager for tracking and managing database transactions
@@ -83,6 +108,9 @@ pub struct TransactionManager {
     /// Active transactions
     active_transactions: HashMap<u64, Transaction>,
     
+    /// Transactions prepared for two-phase commit, by global identifier
+    prepared_transactions: HashMap<String, u64>,
+    
     /// WAL capture manager
     wal_manager: Option<WalCaptureManager>,
     
@@ -96,6 +124,7 @@ impl TransactionManager {
         Self {
             counter: AtomicU64::new(1),
             active_transactions: HashMap::new(),
+            prepared_transactions: HashMap::new(),
             wal_manager: None,
             enabled: true,
         }
@@ -145,6 +174,10 @@ impl TransactionManager {
             parent_id: None,
             child_ids: vec![],
             wal_records: vec![],
+            statement_ids: vec![],
+            prepared_gid: None,
+            checkpoint_position: 0,
+            checkpoint_savepoint: None,
         };
         
         self.active_transactions.insert(tx_id, transaction);
@@ -206,6 +239,76 @@ impl TransactionManager {
         Ok(())
     }
     
+    /// Prepare a transaction for two-phase commit under a global identifier
+    ///
+    /// The transaction is held until [`commit_prepared`](Self::commit_prepared)
+    /// or [`rollback_prepared`](Self::rollback_prepared) is called with the same identifier.
+    pub fn prepare_transaction(&mut self, tx_id: u64, gid: &str) -> Result<()> {
+        if !self.enabled || tx_id == 0 {
+            return Ok(());
+        }
+        
+        if self.prepared_transactions.contains_key(gid) {
+            return Err(ProxyError::Other(format!("Transaction identifier \"{}\" is already in use", gid)));
+        }
+        
+        let transaction = self.active_transactions.get_mut(&tx_id)
+            .ok_or_else(|| ProxyError::Other(format!("Transaction {} not found", tx_id)))?;
+            
+        if transaction.committed || transaction.rolled_back || transaction.prepared_gid.is_some() {
+            return Err(ProxyError::Other(format!("Transaction {} is not in progress", tx_id)));
+        }
+        
+        transaction.prepared_gid = Some(gid.to_string());
+        self.prepared_transactions.insert(gid.to_string(), tx_id);
+        
+        debug!("Prepared transaction {} as \"{}\"", tx_id, gid);
+        
+        Ok(())
+    }
+    
+    /// Commit a prepared transaction, returning it
+    pub fn commit_prepared(&mut self, gid: &str) -> Result<Transaction> {
+        let tx_id = self.take_prepared(gid)?;
+        self.commit_transaction(tx_id)?;
+        
+        debug!("Committed prepared transaction \"{}\"", gid);
+        
+        self.active_transactions.get(&tx_id)
+            .cloned()
+            .ok_or_else(|| ProxyError::Other(format!("Transaction {} not found", tx_id)))
+    }
+    
+    /// Roll back a prepared transaction, returning it
+    pub fn rollback_prepared(&mut self, gid: &str) -> Result<Transaction> {
+        let tx_id = self.take_prepared(gid)?;
+        self.rollback_transaction(tx_id)?;
+        
+        debug!("Rolled back prepared transaction \"{}\"", gid);
+        
+        self.active_transactions.get(&tx_id)
+            .cloned()
+            .ok_or_else(|| ProxyError::Other(format!("Transaction {} not found", tx_id)))
+    }
+    
+    /// Get the transaction prepared under a global identifier
+    pub fn get_prepared_transaction(&self, gid: &str) -> Option<Arc<Transaction>> {
+        let tx_id = self.prepared_transactions.get(gid)?;
+        self.active_transactions.get(tx_id).cloned().map(Arc::new)
+    }
+    
+    /// Global identifiers of the transactions awaiting COMMIT PREPARED or ROLLBACK PREPARED
+    pub fn get_prepared_gids(&self) -> Vec<String> {
+        let mut gids: Vec<String> = self.prepared_transactions.keys().cloned().collect();
+        gids.sort();
+        gids
+    }
+    
+    fn take_prepared(&mut self, gid: &str) -> Result<u64> {
+        self.prepared_transactions.remove(gid)
+            .ok_or_else(|| ProxyError::Other(format!("Prepared transaction \"{}\" not found", gid)))
+    }
+    
     /// Create a savepoint
     pub fn create_savepoint(&mut self, tx_id: u64, savepoint_name: &str) -> Result<()> {
         if !self.enabled || tx_id == 0 {
@@ -226,6 +329,7 @@ impl TransactionManager {
             released: false,
             rolled_back: false,
             statements: vec![],
+            statement_position: transaction.statement_ids.len(),
         };
         
         transaction.savepoints.insert(savepoint_name.to_string(), savepoint);
@@ -271,6 +375,68 @@ impl TransactionManager {
         }
     }
     
+    /// Roll back to a savepoint, discarding the statements run after it
+    ///
+    /// Returns the discarded statements' verification transactions that
+    /// weren't verified at a checkpoint yet. A checkpoint past the savepoint
+    /// is undone, so the next checkpoint starts from the savepoint.
+    pub fn rollback_statements_to_savepoint(&mut self, tx_id: u64, savepoint_name: &str) -> Result<Vec<u64>> {
+        if !self.enabled || tx_id == 0 {
+            return Ok(vec![]);
+        }
+        
+        self.rollback_to_savepoint(tx_id, savepoint_name)?;
+        
+        let transaction = self.get_transaction_mut(tx_id)?;
+        let position = transaction.savepoints.get(savepoint_name)
+            .map(|savepoint| savepoint.statement_position)
+            .unwrap_or_default();
+        let discarded: Vec<u64> = transaction.statement_ids.drain(position..).collect();
+        
+        let unverified = if transaction.checkpoint_position > position {
+            let verified = transaction.checkpoint_position - position;
+            transaction.checkpoint_position = position;
+            transaction.checkpoint_savepoint = Some(savepoint_name.to_string());
+            discarded[verified..].to_vec()
+        } else {
+            discarded
+        };
+        
+        debug!("Discarded {} unverified statements after savepoint {} in transaction {}", unverified.len(), savepoint_name, tx_id);
+        
+        Ok(unverified)
+    }
+    
+    /// Record the verification transaction of a statement run in a transaction
+    pub fn add_statement_id(&mut self, tx_id: u64, statement_id: u64) -> Result<()> {
+        if !self.enabled || tx_id == 0 {
+            return Ok(());
+        }
+        
+        self.get_transaction_mut(tx_id)?.statement_ids.push(statement_id);
+        Ok(())
+    }
+    
+    /// Take the statements run since the previous checkpoint, starting a new one
+    ///
+    /// `savepoint` is the savepoint the new checkpoint is aligned to, or
+    /// `None` for the final checkpoint at COMMIT.
+    pub fn advance_checkpoint(&mut self, tx_id: u64, savepoint: Option<&str>) -> Result<CheckpointRange> {
+        if !self.enabled || tx_id == 0 {
+            return Ok(CheckpointRange { from_savepoint: None, statement_ids: vec![] });
+        }
+        
+        let transaction = self.get_transaction_mut(tx_id)?;
+        let range = CheckpointRange {
+            from_savepoint: transaction.checkpoint_savepoint.take(),
+            statement_ids: transaction.statement_ids[transaction.checkpoint_position..].to_vec(),
+        };
+        transaction.checkpoint_position = transaction.statement_ids.len();
+        transaction.checkpoint_savepoint = savepoint.map(str::to_string);
+        
+        Ok(range)
+    }
+    
     /// Add a statement to a transaction
     pub fn add_statement(&mut self, tx_id: u64, statement: &str) -> Result<()> {
         if !self.enabled || tx_id == 0 {
@@ -354,6 +520,8 @@ impl TransactionManager {
             TransactionStatus::Committed
         } else if tx.rolled_back {
             TransactionStatus::Aborted
+        } else if tx.prepared_gid.is_some() {
+            TransactionStatus::Prepared
         } else {
             TransactionStatus::InProgress
         };
@@ -448,4 +616,61 @@ mod tests {
         assert_eq!(savepoint.statements.len(), 1);
         assert_eq!(savepoint.statements[0], "INSERT INTO tbl VALUES (1)");
     }
+    
+    #[test]
+    fn test_prepared_transaction() {
+        let mut manager = TransactionManager::new();
+        
+        // Prepare a transaction; it is held until COMMIT PREPARED
+        let tx_id = manager.begin_transaction("BEGIN", None).unwrap();
+        manager.prepare_transaction(tx_id, "tx-1").unwrap();
+        assert_eq!(manager.get_transaction_status(tx_id).unwrap(), TransactionStatus::Prepared);
+        assert_eq!(manager.get_prepared_gids(), vec!["tx-1".to_string()]);
+        assert_eq!(manager.get_prepared_transaction("tx-1").unwrap().id, tx_id);
+        
+        // A global identifier can only be used once at a time
+        let other = manager.begin_transaction("BEGIN", None).unwrap();
+        assert!(manager.prepare_transaction(other, "tx-1").is_err());
+        
+        let committed = manager.commit_prepared("tx-1").unwrap();
+        assert_eq!(committed.id, tx_id);
+        assert!(committed.committed);
+        assert!(manager.get_prepared_transaction("tx-1").is_none());
+        assert!(manager.commit_prepared("tx-1").is_err());
+        
+        // A rolled back prepared transaction is discarded
+        manager.prepare_transaction(other, "tx-2").unwrap();
+        let rolled_back = manager.rollback_prepared("tx-2").unwrap();
+        assert!(rolled_back.rolled_back);
+        assert!(manager.get_prepared_gids().is_empty());
+    }
+    
+    #[test]
+    fn test_savepoint_checkpoints() {
+        let mut manager = TransactionManager::new();
+        let tx_id = manager.begin_transaction("BEGIN", None).unwrap();
+        
+        manager.add_statement_id(tx_id, 1).unwrap();
+        manager.create_savepoint(tx_id, "sp1").unwrap();
+        let range = manager.advance_checkpoint(tx_id, Some("sp1")).unwrap();
+        assert_eq!(range, CheckpointRange { from_savepoint: None, statement_ids: vec![1] });
+        
+        // Statements after a rolled back savepoint are discarded
+        manager.add_statement_id(tx_id, 2).unwrap();
+        manager.create_savepoint(tx_id, "sp2").unwrap();
+        manager.add_statement_id(tx_id, 3).unwrap();
+        assert_eq!(manager.rollback_statements_to_savepoint(tx_id, "sp2").unwrap(), vec![3]);
+        
+        // The final checkpoint covers only the delta since the last one
+        manager.add_statement_id(tx_id, 4).unwrap();
+        let range = manager.advance_checkpoint(tx_id, None).unwrap();
+        assert_eq!(range, CheckpointRange { from_savepoint: Some("sp1".to_string()), statement_ids: vec![2, 4] });
+        
+        // Rolling back past a checkpoint undoes it
+        manager.add_statement_id(tx_id, 5).unwrap();
+        assert_eq!(manager.rollback_statements_to_savepoint(tx_id, "sp1").unwrap(), vec![5]);
+        let range = manager.advance_checkpoint(tx_id, None).unwrap();
+        assert_eq!(range, CheckpointRange { from_savepoint: Some("sp1".to_string()), statement_ids: vec![] });
+        assert_eq!(manager.get_transaction(tx_id).unwrap().statement_ids, vec![1]);
+    }
 } 
\ No newline at end of file
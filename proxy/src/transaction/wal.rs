@@ -74,6 +74,157 @@ pub struct WalRecord {
     pub savepoint_name: Option<String>,
 }
 
+/// Version of the [`WalRecord`] wire format written by [`WalRecord::to_bytes`]
+pub const WAL_RECORD_FORMAT_VERSION: u8 = 1;
+
+/// Magic bytes opening every encoded WAL record
+const WAL_RECORD_MAGIC: &[u8; 4] = b"VWAL";
+
+impl WalRecord {
+    /// Encode the record in its canonical, versioned wire format
+    ///
+    /// The encoding is the magic bytes `VWAL`, the format version, then each
+    /// field in declaration order: integers big-endian, strings and data
+    /// length-prefixed with a `u32`, options as a presence byte followed by
+    /// the value, and the record type as a one-byte tag (`Other` carries its
+    /// name). Equal records always encode to the same bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64 + self.data.len());
+        bytes.extend_from_slice(WAL_RECORD_MAGIC);
+        bytes.push(WAL_RECORD_FORMAT_VERSION);
+        bytes.extend_from_slice(&self.lsn.to_be_bytes());
+        bytes.extend_from_slice(&self.txid.to_be_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+        
+        let tag = match &self.record_type {
+            WalRecordType::Begin => 0,
+            WalRecordType::Commit => 1,
+            WalRecordType::Abort => 2,
+            WalRecordType::Insert => 3,
+            WalRecordType::Update => 4,
+            WalRecordType::Delete => 5,
+            WalRecordType::Truncate => 6,
+            WalRecordType::Savepoint => 7,
+            WalRecordType::ReleaseSavepoint => 8,
+            WalRecordType::RollbackToSavepoint => 9,
+            WalRecordType::Ddl => 10,
+            WalRecordType::Other(_) => 255,
+        };
+        bytes.push(tag);
+        if let WalRecordType::Other(name) = &self.record_type {
+            write_bytes(&mut bytes, name.as_bytes());
+        }
+        
+        match self.relation_id {
+            Some(relation_id) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&relation_id.to_be_bytes());
+            }
+            None => bytes.push(0),
+        }
+        write_optional_string(&mut bytes, self.relation_name.as_deref());
+        write_bytes(&mut bytes, &self.data);
+        bytes.extend_from_slice(&self.checksum);
+        bytes.push(self.is_savepoint as u8);
+        write_optional_string(&mut bytes, self.savepoint_name.as_deref());
+        bytes
+    }
+    
+    /// Decode a record written by [`to_bytes`](Self::to_bytes)
+    ///
+    /// Records with an unknown format version, malformed or trailing bytes,
+    /// or a checksum that doesn't match their contents are rejected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = WalReader { bytes, position: 0 };
+        if reader.take(WAL_RECORD_MAGIC.len())? != WAL_RECORD_MAGIC {
+            return Err(ProxyError::Protocol("Not an encoded WAL record".to_string()));
+        }
+        let version = reader.u8()?;
+        if version != WAL_RECORD_FORMAT_VERSION {
+            return Err(ProxyError::Protocol(format!(
+                "Unsupported WAL record format version {} (expected {})", version, WAL_RECORD_FORMAT_VERSION
+            )));
+        }
+        
+        let lsn = reader.u64()?;
+        let txid = reader.u64()?;
+        let timestamp = reader.u64()?;
+        let record_type = match reader.u8()? {
+            0 => WalRecordType::Begin,
+            1 => WalRecordType::Commit,
+            2 => WalRecordType::Abort,
+            3 => WalRecordType::Insert,
+            4 => WalRecordType::Update,
+            5 => WalRecordType::Delete,
+            6 => WalRecordType::Truncate,
+            7 => WalRecordType::Savepoint,
+            8 => WalRecordType::ReleaseSavepoint,
+            9 => WalRecordType::RollbackToSavepoint,
+            10 => WalRecordType::Ddl,
+            255 => WalRecordType::Other(reader.string()?),
+            tag => return Err(ProxyError::Protocol(format!("Unknown WAL record type tag {}", tag))),
+        };
+        let relation_id = match reader.u8()? {
+            0 => None,
+            1 => Some(u32::from_be_bytes(reader.array()?)),
+            flag => return Err(ProxyError::Protocol(format!("Invalid WAL record option flag {}", flag))),
+        };
+        let relation_name = reader.optional_string()?;
+        let data = reader.bytes()?.to_vec();
+        let checksum = reader.array()?;
+        let is_savepoint = match reader.u8()? {
+            0 => false,
+            1 => true,
+            flag => return Err(ProxyError::Protocol(format!("Invalid WAL record savepoint flag {}", flag))),
+        };
+        let savepoint_name = reader.optional_string()?;
+        if reader.position != bytes.len() {
+            return Err(ProxyError::Protocol(format!(
+                "{} trailing bytes after WAL record", bytes.len() - reader.position
+            )));
+        }
+        
+        let record = WalRecord {
+            lsn,
+            txid,
+            timestamp,
+            record_type,
+            relation_id,
+            relation_name,
+            data,
+            checksum,
+            is_savepoint,
+            savepoint_name,
+        };
+        if calculate_wal_record_checksum(&record) != record.checksum {
+            return Err(ProxyError::Protocol(format!("Checksum mismatch for WAL record at LSN {}", record.lsn)));
+        }
+        Ok(record)
+    }
+}
+
+/// Encode a WAL stream as a sequence of length-prefixed records
+///
+/// The stream can be stored durably and replayed after a restart, or handed
+/// to a challenger as the exact WAL that produced a block.
+pub fn encode_wal_stream(records: &[WalRecord]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for record in records {
+        write_bytes(&mut bytes, &record.to_bytes());
+    }
+    bytes
+}
+
+/// Decode a WAL stream written by [`encode_wal_stream`], rejecting it if any record is invalid
+pub fn decode_wal_stream(bytes: &[u8]) -> Result<Vec<WalRecord>> {
+    let mut reader = WalReader { bytes, position: 0 };
+    let mut records = Vec::new();
+    while reader.position < bytes.len() {
+        records.push(WalRecord::from_bytes(reader.bytes()?)?);
+    }
+    Ok(records)
+}
+
 /// Savepoint record structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SavepointRecord {
@@ -134,6 +285,8 @@ pub enum TransactionStatus {
     Committed,
     /// Transaction has been aborted
     Aborted,
+    /// Transaction has been prepared for two-phase commit
+    Prepared,
 }
 
 /// WAL capture manager
@@ -445,6 +598,72 @@ impl WalCaptureManager {
     }
 }
 
+/// Append `value` prefixed with its `u32` length
+fn write_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    bytes.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(value);
+}
+
+/// Append a presence byte and, if present, the length-prefixed string
+fn write_optional_string(bytes: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            bytes.push(1);
+            write_bytes(bytes, value.as_bytes());
+        }
+        None => bytes.push(0),
+    }
+}
+
+/// Cursor over an encoded WAL record
+struct WalReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> WalReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.position.checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| ProxyError::Protocol("Truncated WAL record".to_string()))?;
+        let value = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(value)
+    }
+    
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut value = [0; N];
+        value.copy_from_slice(self.take(N)?);
+        Ok(value)
+    }
+    
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+    
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = u32::from_be_bytes(self.array()?) as usize;
+        self.take(len)
+    }
+    
+    fn string(&mut self) -> Result<String> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|e| ProxyError::Protocol(format!("Invalid string in WAL record: {}", e)))
+    }
+    
+    fn optional_string(&mut self) -> Result<Option<String>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => self.string().map(Some),
+            flag => Err(ProxyError::Protocol(format!("Invalid WAL record option flag {}", flag))),
+        }
+    }
+}
+
 /// Create a checksum for a WAL record
 fn calculate_wal_record_checksum(record: &WalRecord) -> [u8; 32] {
     let mut hasher = Sha256::new();
@@ -509,6 +728,60 @@ mod tests {
         record
     }
     
+    #[test]
+    fn test_wal_record_round_trip() {
+        let mut update = create_test_record(7, 200, WalRecordType::Update);
+        update.relation_id = Some(16384);
+        update.relation_name = Some("users".to_string());
+        update.data = vec![0, 1, 2, 255];
+        update.checksum = calculate_wal_record_checksum(&update);
+        
+        let mut savepoint = create_test_record(7, 201, WalRecordType::Other("custom".to_string()));
+        savepoint.is_savepoint = true;
+        savepoint.savepoint_name = Some("sp1".to_string());
+        savepoint.checksum = calculate_wal_record_checksum(&savepoint);
+        
+        for record in [create_test_record(7, 199, WalRecordType::Begin), update.clone(), savepoint.clone()] {
+            let bytes = record.to_bytes();
+            let decoded = WalRecord::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.to_bytes(), bytes);
+            assert_eq!(decoded.record_type, record.record_type);
+            assert_eq!(decoded.relation_name, record.relation_name);
+            assert_eq!(decoded.data, record.data);
+            assert_eq!(decoded.checksum, record.checksum);
+            assert_eq!(decoded.savepoint_name, record.savepoint_name);
+        }
+        
+        // A stream keeps its records and their order
+        let stream = encode_wal_stream(&[update.clone(), savepoint.clone()]);
+        let decoded = decode_wal_stream(&stream).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].lsn, 200);
+        assert_eq!(decoded[1].lsn, 201);
+        
+        // Truncated, trailing or tampered bytes are rejected
+        let bytes = update.to_bytes();
+        assert!(WalRecord::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(WalRecord::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+        let mut tampered = bytes.clone();
+        let last_data_byte = tampered.len() - 32 - 1 - 1 - 1;
+        tampered[last_data_byte] ^= 1;
+        assert!(WalRecord::from_bytes(&tampered).is_err());
+        assert!(decode_wal_stream(&stream[..stream.len() - 1]).is_err());
+    }
+    
+    #[test]
+    fn test_wal_record_unknown_version_rejected() {
+        let mut bytes = create_test_record(1, 100, WalRecordType::Insert).to_bytes();
+        assert_eq!(bytes[WAL_RECORD_MAGIC.len()], WAL_RECORD_FORMAT_VERSION);
+        
+        bytes[WAL_RECORD_MAGIC.len()] = WAL_RECORD_FORMAT_VERSION + 1;
+        match WalRecord::from_bytes(&bytes) {
+            Err(ProxyError::Protocol(message)) => assert!(message.contains("version")),
+            other => panic!("expected a version error, got {:?}", other),
+        }
+    }
+    
     #[test]
     #[ignore]
     fn test_wal_capture_normal_transaction() {
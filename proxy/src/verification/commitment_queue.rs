@@ -0,0 +1,512 @@
+//! Durable retry queue for on-chain state commitments
+//!
+//! Every block's state root is recorded in the commitment queue table before
+//! it is submitted, so a submission that fails (e.g. on a transient RPC error)
+//! survives restarts instead of being dropped. A background task retries due
+//! commitments with exponential backoff; a commitment that still fails after
+//! the configured number of attempts is moved to a dead-letter state for
+//! operator attention. A block is finalized only once its commitment is
+//! confirmed.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use log::{debug, info, warn, error};
+use tokio_postgres::{Client, Row};
+use crate::error::{ProxyError, Result};
+
+/// Table holding queued commitments
+pub const COMMITMENT_QUEUE_TABLE: &str = "verification_commitment_queue";
+
+/// Schema of the commitment queue table (times are Unix milliseconds)
+const CREATE_COMMITMENT_QUEUE_TABLE: &str = "CREATE TABLE IF NOT EXISTS verification_commitment_queue (
+    block_number BIGINT PRIMARY KEY,
+    state_root VARCHAR(66) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at BIGINT NOT NULL,
+    last_error TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+)";
+
+const COMMITMENT_COLUMNS: &str = "block_number, state_root, status, attempts, next_attempt_at, last_error";
+
+/// Configuration for retrying failed on-chain commitments
+#[derive(Debug, Clone)]
+pub struct CommitmentRetryConfig {
+    /// Whether commitments go through the durable queue; when disabled a
+    /// failed submission fails the block commit
+    pub enabled: bool,
+
+    /// Submission attempts before a commitment is dead-lettered
+    pub max_attempts: u32,
+
+    /// Delay before the first retry (in milliseconds), doubled per attempt
+    pub initial_backoff_ms: u64,
+
+    /// Upper bound on the delay between retries (in milliseconds)
+    pub max_backoff_ms: u64,
+
+    /// Seconds between passes of the retry task
+    pub poll_interval_secs: u64,
+
+    /// Maximum number of commitments retried per pass
+    pub batch_size: usize,
+}
+
+impl Default for CommitmentRetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_attempts: 8,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 300_000, // 5 minutes
+            poll_interval_secs: 5,
+            batch_size: 50,
+        }
+    }
+}
+
+/// Status of a queued commitment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitmentStatus {
+    /// Waiting for its next submission attempt
+    Pending,
+
+    /// Accepted on chain; the block is finalized
+    Confirmed,
+
+    /// Gave up after the maximum number of attempts
+    DeadLettered,
+}
+
+impl CommitmentStatus {
+    /// Name stored in the queue table
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommitmentStatus::Pending => "pending",
+            CommitmentStatus::Confirmed => "confirmed",
+            CommitmentStatus::DeadLettered => "dead_letter",
+        }
+    }
+
+    /// Parse a status stored in the queue table
+    pub fn parse(status: &str) -> Result<Self> {
+        match status {
+            "pending" => Ok(CommitmentStatus::Pending),
+            "confirmed" => Ok(CommitmentStatus::Confirmed),
+            "dead_letter" => Ok(CommitmentStatus::DeadLettered),
+            other => Err(ProxyError::Verification(format!("Unknown commitment status: {}", other))),
+        }
+    }
+}
+
+/// A block's commitment as recorded in the queue
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedCommitment {
+    /// Block the commitment belongs to
+    pub block_number: u64,
+
+    /// State root to commit
+    pub state_root: [u8; 32],
+
+    /// Current status
+    pub status: CommitmentStatus,
+
+    /// Submission attempts made so far
+    pub attempts: u32,
+
+    /// Earliest time of the next attempt (Unix milliseconds)
+    pub next_attempt_at: u64,
+
+    /// Error of the last failed attempt
+    pub last_error: Option<String>,
+}
+
+impl QueuedCommitment {
+    fn from_row(row: &Row) -> Result<Self> {
+        let state_root: String = row.get("state_root");
+        let bytes = hex::decode(state_root.trim_start_matches("0x"))
+            .map_err(|e| ProxyError::Verification(format!("Invalid queued state root {}: {}", state_root, e)))?;
+        let state_root: [u8; 32] = bytes.try_into()
+            .map_err(|_| ProxyError::Verification(format!("Queued state root {} is not 32 bytes", state_root)))?;
+
+        Ok(Self {
+            block_number: row.get::<_, i64>("block_number") as u64,
+            state_root,
+            status: CommitmentStatus::parse(row.get("status"))?,
+            attempts: row.get::<_, i32>("attempts") as u32,
+            next_attempt_at: row.get::<_, i64>("next_attempt_at") as u64,
+            last_error: row.get("last_error"),
+        })
+    }
+}
+
+/// Result of one submission attempt
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttemptOutcome {
+    /// The commitment was accepted
+    Confirmed,
+
+    /// The attempt failed and will be retried at `next_attempt_at`
+    Retrying {
+        /// Earliest time of the next attempt (Unix milliseconds)
+        next_attempt_at: u64,
+
+        /// Why the attempt failed
+        error: String,
+    },
+
+    /// The attempt failed and no attempts are left
+    DeadLettered {
+        /// Why the last attempt failed
+        error: String,
+    },
+}
+
+/// Durable queue of on-chain commitments, stored in the main database
+#[derive(Debug)]
+pub struct CommitmentQueue {
+    config: CommitmentRetryConfig,
+
+    /// Whether the queue table is known to exist
+    table_ready: AtomicBool,
+}
+
+impl CommitmentQueue {
+    /// Create a queue with the given retry configuration
+    pub fn new(config: CommitmentRetryConfig) -> Self {
+        Self {
+            config,
+            table_ready: AtomicBool::new(false),
+        }
+    }
+
+    /// Get the retry configuration
+    pub fn config(&self) -> &CommitmentRetryConfig {
+        &self.config
+    }
+
+    /// Delay before the next attempt after `attempts` failed attempts
+    pub fn backoff_delay(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(32);
+        let delay = self.config.initial_backoff_ms.saturating_mul(1u64 << exponent);
+        Duration::from_millis(delay.min(self.config.max_backoff_ms))
+    }
+
+    /// Create the queue table if it doesn't exist
+    pub async fn ensure_table(&self, client: &Client) -> Result<()> {
+        if self.table_ready.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        client.batch_execute(CREATE_COMMITMENT_QUEUE_TABLE).await
+            .map_err(|e| ProxyError::Database(format!("Failed to create commitment queue table: {}", e)))?;
+        self.table_ready.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Record a block's commitment as pending, due immediately
+    ///
+    /// Enqueuing a block that is already queued leaves its entry untouched.
+    pub async fn enqueue(&self, client: &Client, block_number: u64, state_root: [u8; 32]) -> Result<()> {
+        self.ensure_table(client).await?;
+
+        let now = now_millis() as i64;
+        client.execute(
+            "INSERT INTO verification_commitment_queue
+                (block_number, state_root, status, attempts, next_attempt_at, created_at, updated_at)
+                VALUES ($1, $2, $3, 0, $4, $4, $4)
+                ON CONFLICT (block_number) DO NOTHING",
+            &[
+                &(block_number as i64),
+                &format!("0x{}", hex::encode(state_root)),
+                &CommitmentStatus::Pending.as_str(),
+                &now,
+            ],
+        ).await
+            .map_err(|e| ProxyError::Database(format!("Failed to enqueue commitment for block {}: {}", block_number, e)))?;
+
+        debug!("Queued commitment for block {}", block_number);
+        Ok(())
+    }
+
+    /// Get the queued commitment of a block
+    pub async fn get(&self, client: &Client, block_number: u64) -> Result<Option<QueuedCommitment>> {
+        self.ensure_table(client).await?;
+
+        let query = format!("SELECT {} FROM verification_commitment_queue WHERE block_number = $1", COMMITMENT_COLUMNS);
+        let row = client.query_opt(query.as_str(), &[&(block_number as i64)]).await
+            .map_err(|e| ProxyError::Database(format!("Failed to look up commitment for block {}: {}", block_number, e)))?;
+        row.as_ref().map(QueuedCommitment::from_row).transpose()
+    }
+
+    /// Whether a block's commitment has been confirmed
+    pub async fn is_finalized(&self, client: &Client, block_number: u64) -> Result<bool> {
+        Ok(self.get(client, block_number).await?
+            .is_some_and(|commitment| commitment.status == CommitmentStatus::Confirmed))
+    }
+
+    /// Get pending commitments whose next attempt is due, oldest block first
+    pub async fn due(&self, client: &Client, limit: usize) -> Result<Vec<QueuedCommitment>> {
+        self.ensure_table(client).await?;
+
+        let query = format!(
+            "SELECT {} FROM verification_commitment_queue
+                WHERE status = $1 AND next_attempt_at <= $2
+                ORDER BY block_number LIMIT $3",
+            COMMITMENT_COLUMNS
+        );
+        let rows = client.query(
+            query.as_str(),
+            &[&CommitmentStatus::Pending.as_str(), &(now_millis() as i64), &(limit.min(i64::MAX as usize) as i64)],
+        ).await
+            .map_err(|e| ProxyError::Database(format!("Failed to load due commitments: {}", e)))?;
+        rows.iter().map(QueuedCommitment::from_row).collect()
+    }
+
+    /// Get dead-lettered commitments, oldest block first
+    pub async fn dead_lettered(&self, client: &Client) -> Result<Vec<QueuedCommitment>> {
+        self.ensure_table(client).await?;
+
+        let query = format!(
+            "SELECT {} FROM verification_commitment_queue WHERE status = $1 ORDER BY block_number",
+            COMMITMENT_COLUMNS
+        );
+        let rows = client.query(query.as_str(), &[&CommitmentStatus::DeadLettered.as_str()]).await
+            .map_err(|e| ProxyError::Database(format!("Failed to load dead-lettered commitments: {}", e)))?;
+        rows.iter().map(QueuedCommitment::from_row).collect()
+    }
+
+    /// Move a dead-lettered commitment back to pending with a fresh set of
+    /// attempts, e.g. once an operator has fixed the cause
+    ///
+    /// Returns whether a dead-lettered commitment was found.
+    pub async fn requeue(&self, client: &Client, block_number: u64) -> Result<bool> {
+        self.ensure_table(client).await?;
+
+        let now = now_millis() as i64;
+        let updated = client.execute(
+            "UPDATE verification_commitment_queue
+                SET status = $1, attempts = 0, next_attempt_at = $2, updated_at = $2
+                WHERE block_number = $3 AND status = $4",
+            &[
+                &CommitmentStatus::Pending.as_str(),
+                &now,
+                &(block_number as i64),
+                &CommitmentStatus::DeadLettered.as_str(),
+            ],
+        ).await
+            .map_err(|e| ProxyError::Database(format!("Failed to requeue commitment for block {}: {}", block_number, e)))?;
+        Ok(updated > 0)
+    }
+
+    /// Make one submission attempt for a queued commitment and record its outcome
+    pub async fn attempt<F, Fut, T>(&self, client: &Client, commitment: &QueuedCommitment, submit: F) -> Result<AttemptOutcome>
+    where
+        F: FnOnce([u8; 32]) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let attempts = commitment.attempts + 1;
+        let now = now_millis();
+
+        let (outcome, status, next_attempt_at, last_error) = match submit(commitment.state_root).await {
+            Ok(_) => {
+                info!("Commitment for block {} confirmed after {} attempt(s)", commitment.block_number, attempts);
+                (AttemptOutcome::Confirmed, CommitmentStatus::Confirmed, now, None)
+            }
+            Err(e) if attempts >= self.config.max_attempts => {
+                error!("Commitment for block {} dead-lettered after {} attempts: {}", commitment.block_number, attempts, e);
+                let error = e.to_string();
+                (AttemptOutcome::DeadLettered { error: error.clone() }, CommitmentStatus::DeadLettered, now, Some(error))
+            }
+            Err(e) => {
+                let next_attempt_at = now + self.backoff_delay(attempts).as_millis() as u64;
+                warn!("Commitment for block {} failed (attempt {}/{}), retrying: {}",
+                      commitment.block_number, attempts, self.config.max_attempts, e);
+                let error = e.to_string();
+                (AttemptOutcome::Retrying { next_attempt_at, error: error.clone() }, CommitmentStatus::Pending, next_attempt_at, Some(error))
+            }
+        };
+
+        client.execute(
+            "UPDATE verification_commitment_queue
+                SET status = $1, attempts = $2, next_attempt_at = $3, last_error = $4, updated_at = $5
+                WHERE block_number = $6",
+            &[
+                &status.as_str(),
+                &(attempts as i32),
+                &(next_attempt_at as i64),
+                &last_error,
+                &(now as i64),
+                &(commitment.block_number as i64),
+            ],
+        ).await
+            .map_err(|e| ProxyError::Database(format!("Failed to record commitment attempt for block {}: {}", commitment.block_number, e)))?;
+
+        Ok(outcome)
+    }
+
+    /// Attempt every due commitment (up to the configured batch size)
+    ///
+    /// Returns each attempted commitment, as it was before the attempt, with
+    /// the attempt's outcome.
+    pub async fn process_due<F, Fut, T>(&self, client: &Client, submit: F) -> Result<Vec<(QueuedCommitment, AttemptOutcome)>>
+    where
+        F: Fn([u8; 32]) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut results = Vec::new();
+        for commitment in self.due(client, self.config.batch_size).await? {
+            let outcome = self.attempt(client, &commitment, &submit).await?;
+            results.push((commitment, outcome));
+        }
+        Ok(results)
+    }
+}
+
+/// Current time in Unix milliseconds
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+    use tokio_postgres::NoTls;
+
+    async fn connect_test_database() -> Client {
+        let connection_string = format!(
+            "host={} port={} user={} password={} dbname={}",
+            std::env::var("PG_HOST").unwrap_or_else(|_| "localhost".to_string()),
+            std::env::var("PG_PORT").unwrap_or_else(|_| "5432".to_string()),
+            std::env::var("PG_USER").unwrap_or_else(|_| "verifiable".to_string()),
+            std::env::var("PG_PASSWORD").unwrap_or_else(|_| "verifiable".to_string()),
+            std::env::var("PG_DATABASE").unwrap_or_else(|_| "verifiable_db".to_string()),
+        );
+        let (client, connection) = tokio_postgres::connect(&connection_string, NoTls).await.unwrap();
+        tokio::spawn(async move {
+            let _ = connection.await;
+        });
+        client
+    }
+
+    fn test_queue(max_attempts: u32) -> CommitmentQueue {
+        CommitmentQueue::new(CommitmentRetryConfig {
+            max_attempts,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 1,
+            ..CommitmentRetryConfig::default()
+        })
+    }
+
+    /// Pick a block number unlikely to collide with earlier runs
+    fn test_block_number() -> u64 {
+        1_000_000_000 + now_millis() % 1_000_000_000
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let queue = CommitmentQueue::new(CommitmentRetryConfig {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1000,
+            ..CommitmentRetryConfig::default()
+        });
+
+        assert_eq!(queue.backoff_delay(1), Duration::from_millis(100));
+        assert_eq!(queue.backoff_delay(2), Duration::from_millis(200));
+        assert_eq!(queue.backoff_delay(4), Duration::from_millis(800));
+        assert_eq!(queue.backoff_delay(5), Duration::from_millis(1000));
+        assert_eq!(queue.backoff_delay(u32::MAX), Duration::from_millis(1000));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database"]
+    async fn test_transient_failure_retried_until_confirmed() {
+        let client = connect_test_database().await;
+        let queue = test_queue(5);
+        let block_number = test_block_number();
+        queue.enqueue(&client, block_number, [7u8; 32]).await.unwrap();
+
+        // The RPC endpoint fails twice before accepting the commitment
+        let calls = AtomicU32::new(0);
+        let submit = |state_root: [u8; 32]| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                assert_eq!(state_root, [7u8; 32]);
+                if call < 2 {
+                    Err(ProxyError::Verification("RPC request timed out".to_string()))
+                } else {
+                    Ok(())
+                }
+            }
+        };
+
+        for _ in 0..2 {
+            let commitment = queue.get(&client, block_number).await.unwrap().unwrap();
+            let outcome = queue.attempt(&client, &commitment, submit).await.unwrap();
+            assert!(matches!(outcome, AttemptOutcome::Retrying { .. }));
+            assert!(!queue.is_finalized(&client, block_number).await.unwrap());
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let commitment = queue.get(&client, block_number).await.unwrap().unwrap();
+        assert_eq!(commitment.status, CommitmentStatus::Pending);
+        assert_eq!(commitment.attempts, 2);
+        assert_eq!(commitment.last_error.as_deref(), Some("Verification error: RPC request timed out"));
+        assert!(queue.due(&client, usize::MAX).await.unwrap().iter().any(|c| c.block_number == block_number));
+
+        let outcome = queue.attempt(&client, &commitment, submit).await.unwrap();
+        assert_eq!(outcome, AttemptOutcome::Confirmed);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let commitment = queue.get(&client, block_number).await.unwrap().unwrap();
+        assert_eq!(commitment.status, CommitmentStatus::Confirmed);
+        assert_eq!(commitment.attempts, 3);
+        assert_eq!(commitment.last_error, None);
+        assert!(queue.is_finalized(&client, block_number).await.unwrap());
+
+        // Confirmed commitments are never due again
+        assert!(!queue.due(&client, usize::MAX).await.unwrap().iter().any(|c| c.block_number == block_number));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database"]
+    async fn test_permanent_failure_dead_lettered() {
+        let client = connect_test_database().await;
+        let queue = test_queue(3);
+        let block_number = test_block_number();
+        queue.enqueue(&client, block_number, [9u8; 32]).await.unwrap();
+
+        let submit = |_state_root: [u8; 32]| async {
+            Err::<(), _>(ProxyError::Verification("execution reverted".to_string()))
+        };
+
+        let mut outcomes = Vec::new();
+        for _ in 0..3 {
+            let commitment = queue.get(&client, block_number).await.unwrap().unwrap();
+            outcomes.push(queue.attempt(&client, &commitment, submit).await.unwrap());
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(matches!(outcomes[1], AttemptOutcome::Retrying { .. }));
+        assert!(matches!(outcomes[2], AttemptOutcome::DeadLettered { .. }));
+
+        let commitment = queue.get(&client, block_number).await.unwrap().unwrap();
+        assert_eq!(commitment.status, CommitmentStatus::DeadLettered);
+        assert_eq!(commitment.attempts, 3);
+        assert!(!queue.is_finalized(&client, block_number).await.unwrap());
+
+        // Dead-lettered commitments wait for an operator instead of being retried
+        assert!(!queue.due(&client, usize::MAX).await.unwrap().iter().any(|c| c.block_number == block_number));
+        assert!(queue.dead_lettered(&client).await.unwrap().iter().any(|c| c.block_number == block_number));
+
+        assert!(queue.requeue(&client, block_number).await.unwrap());
+        let commitment = queue.get(&client, block_number).await.unwrap().unwrap();
+        assert_eq!(commitment.status, CommitmentStatus::Pending);
+        assert_eq!(commitment.attempts, 0);
+    }
+}
@@ -257,22 +257,26 @@ impl ContractManager {
             return Ok(None);
         }
         
-        // Check if we've committed too recently
-        let mut last_commitment = self.last_commitment.lock().unwrap();
-        let now = Instant::now();
-        let elapsed = now.duration_since(*last_commitment);
-        if elapsed < Duration::from_secs(self.config.commit_frequency_seconds) {
-            debug!("Skipping state commitment - committed too recently ({:?} ago)", elapsed);
-            return Ok(None);
-        }
-        
-        // Update the last commitment time
-        *last_commitment = now;
-        
-        // Get the next sequence number
-        let mut sequence = self.sequence.lock().unwrap();
-        *sequence += 1;
-        let sequence_num = *sequence;
+        // Reserve the next sequence number unless we've committed too recently,
+        // keeping the previous commitment time so a failed submission can be
+        // retried without waiting out the interval. The locks are released
+        // before submitting.
+        let (previous_commitment, sequence_num) = {
+            let mut last_commitment = self.last_commitment.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(*last_commitment);
+            if elapsed < Duration::from_secs(self.config.commit_frequency_seconds) {
+                debug!("Skipping state commitment - committed too recently ({:?} ago)", elapsed);
+                return Ok(None);
+            }
+            
+            let previous_commitment = *last_commitment;
+            *last_commitment = now;
+            
+            let mut sequence = self.sequence.lock().unwrap();
+            *sequence += 1;
+            (previous_commitment, *sequence)
+        };
         
         // Create the commitment
         let mut commitment = StateCommitment {
@@ -311,7 +315,13 @@ impl ContractManager {
                 },
                 Err(e) => {
                     error!("Failed to commit state root to contract: {:?}", e);
-                    // Continue anyway - we'll track it locally
+                    // Release the reservation so the retry isn't rate-limited
+                    *self.last_commitment.lock().unwrap() = previous_commitment;
+                    let mut sequence = self.sequence.lock().unwrap();
+                    if *sequence == sequence_num {
+                        *sequence -= 1;
+                    }
+                    return Err(e);
                 }
             }
         } else {
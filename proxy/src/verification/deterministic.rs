@@ -2,10 +2,37 @@
 //!
 //! This module provides deterministic alternatives to PostgreSQL functions
 //! that are non-deterministic, to ensure reproducible query execution.
+//!
+//! # Timestamp model
+//!
+//! PostgreSQL has three clocks, and replay reproduces each of them:
+//!
+//! - Transaction time (`now()`, `current_timestamp`, `transaction_timestamp()`)
+//!   is fixed for the whole transaction. It is the block timestamp.
+//! - Statement time (`statement_timestamp()`) is fixed within a statement but
+//!   advances between statements. The n-th statement (counting from zero) gets
+//!   the transaction time plus `n` times the statement resolution, so the first
+//!   statement sees the transaction time, as in PostgreSQL.
+//! - Clock time (`clock_timestamp()`) advances on every call and is derived
+//!   from a logical counter.
+//!
+//! Callers mark statement boundaries with
+//! [`DeterministicSqlFunctions::begin_statement`], so the same statement
+//! sequence always yields the same timestamps.
+//!
+//! # Clocks
+//!
+//! The transaction time is taken from a [`DeterministicClock`] when each
+//! transaction begins. The default [`WallClock`] reads system time, which is
+//! fine for a single node; proxies in a cluster with skewed clocks should use
+//! a [`LogicalClock`], a wall clock with a skew grace, or a consensus-derived
+//! clock, so every node replays a transaction with the same timestamp.
 
 use crate::error::{ProxyError, Result};
 use log::{debug, warn, info};
 use sha2::{Sha256, Digest};
+use std::fmt::Debug;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
@@ -52,16 +79,16 @@ impl DeterministicTimestamp {
     /// Get the timestamp as a string in PostgreSQL format
     /// Format: YYYY-MM-DD HH:MM:SS.uuuuuu+00
     pub fn as_string(&self) -> String {
-        let secs = self.block_timestamp;
         let millis = self.logical_timestamp % 1000;
-        
-        // Convert to date and time components
-        let (year, month, day, hour, minute, second) = seconds_to_date_time(secs);
-        
-        format!(
-            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}+00",
-            year, month, day, hour, minute, second, millis * 1000
-        )
+        format_timestamp(self.block_timestamp, millis * 1000)
+    }
+    
+    /// Get the block timestamp plus an offset, in PostgreSQL format
+    ///
+    /// The logical timestamp is ignored.
+    pub fn offset_string(&self, offset_micros: u64) -> String {
+        let secs = self.block_timestamp + offset_micros / 1_000_000;
+        format_timestamp(secs, offset_micros % 1_000_000)
     }
     
     /// Increment the logical timestamp
@@ -70,6 +97,113 @@ impl DeterministicTimestamp {
     }
 }
 
+/// Source of transaction timestamps for replay
+///
+/// Every node replaying a transaction must get the same value from its clock
+/// for the same transaction ID.
+pub trait DeterministicClock: Send + Sync + Debug {
+    /// Transaction time (seconds since epoch) for the transaction `tx_id`
+    fn transaction_time(&self, tx_id: u64) -> u64;
+}
+
+/// Wall-clock transaction time, optionally rounded down to a skew grace window
+///
+/// Nodes whose clocks differ by less than the grace usually agree, but can
+/// still straddle a window boundary; use a [`LogicalClock`] or a
+/// consensus-derived clock where agreement must be guaranteed.
+#[derive(Debug, Clone, Default)]
+pub struct WallClock {
+    /// Width of the window timestamps are rounded down to (0 = exact seconds)
+    skew_grace_secs: u64,
+}
+
+impl WallClock {
+    /// Create a wall clock reporting exact seconds
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Round timestamps down to multiples of `secs`
+    pub fn with_skew_grace(secs: u64) -> Self {
+        Self { skew_grace_secs: secs }
+    }
+}
+
+impl DeterministicClock for WallClock {
+    fn transaction_time(&self, _tx_id: u64) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        
+        if self.skew_grace_secs > 1 {
+            now - now % self.skew_grace_secs
+        } else {
+            now
+        }
+    }
+}
+
+/// Logical transaction time derived from the transaction ID
+///
+/// Transaction `n` gets `genesis_secs + n * step_secs`, which is monotonic
+/// and identical on every node regardless of system time.
+#[derive(Debug, Clone)]
+pub struct LogicalClock {
+    /// Time of transaction 0 (seconds since epoch)
+    genesis_secs: u64,
+    
+    /// Seconds between consecutive transaction IDs
+    step_secs: u64,
+}
+
+impl LogicalClock {
+    /// Create a logical clock starting at `genesis_secs`
+    pub fn new(genesis_secs: u64, step_secs: u64) -> Self {
+        Self { genesis_secs, step_secs }
+    }
+}
+
+impl DeterministicClock for LogicalClock {
+    fn transaction_time(&self, tx_id: u64) -> u64 {
+        self.genesis_secs.saturating_add(tx_id.saturating_mul(self.step_secs))
+    }
+}
+
+/// Configuration of the clock used for replayed transaction times
+///
+/// Consensus-derived clocks are installed programmatically with
+/// [`DeterministicSqlFunctions::with_clock`].
+#[derive(Debug, Clone)]
+pub enum ClockConfig {
+    /// System time, rounded down to `skew_grace_secs` windows (0 = exact)
+    Wall {
+        skew_grace_secs: u64,
+    },
+    
+    /// Time derived from the transaction ID
+    Logical {
+        genesis_secs: u64,
+        step_secs: u64,
+    },
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        ClockConfig::Wall { skew_grace_secs: 0 }
+    }
+}
+
+impl ClockConfig {
+    /// Build the configured clock
+    pub fn build(&self) -> Arc<dyn DeterministicClock> {
+        match self {
+            ClockConfig::Wall { skew_grace_secs } => Arc::new(WallClock::with_skew_grace(*skew_grace_secs)),
+            ClockConfig::Logical { genesis_secs, step_secs } => Arc::new(LogicalClock::new(*genesis_secs, *step_secs)),
+        }
+    }
+}
+
 /// Deterministic random number generator
 ///
 /// Unlike PostgreSQL's RANDOM(), this returns a deterministic
@@ -141,6 +275,16 @@ fn combine_seeds(a: u64, b: u64) -> u64 {
     u64::from_le_bytes(bytes)
 }
 
+/// Format seconds since epoch and a microsecond part in PostgreSQL format
+fn format_timestamp(secs: u64, micros: u64) -> String {
+    let (year, month, day, hour, minute, second) = seconds_to_date_time(secs);
+    
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}+00",
+        year, month, day, hour, minute, second, micros
+    )
+}
+
 /// Convert seconds since epoch to date and time components
 fn seconds_to_date_time(secs: u64) -> (u32, u32, u32, u32, u32, u32) {
     // Simplified implementation - in a real system, use a proper date library
@@ -166,6 +310,9 @@ fn seconds_to_date_time(secs: u64) -> (u32, u32, u32, u32, u32, u32) {
     (year, month, day, hour, minute, second)
 }
 
+/// Default spacing between consecutive statement timestamps (1 ms)
+pub const DEFAULT_STATEMENT_RESOLUTION_MICROS: u64 = 1000;
+
 /// Deterministic SQL functions for use by the rewriter
 #[derive(Debug)]
 pub struct DeterministicSqlFunctions {
@@ -174,6 +321,15 @@ pub struct DeterministicSqlFunctions {
     
     /// Random number generator
     random: DeterministicRandom,
+    
+    /// Statements started in the current transaction
+    statements_started: u64,
+    
+    /// Spacing between consecutive statement timestamps, in microseconds
+    statement_resolution_micros: u64,
+    
+    /// Clock assigning each transaction's time (`None` keeps the initial time)
+    clock: Option<Arc<dyn DeterministicClock>>,
 }
 
 impl DeterministicSqlFunctions {
@@ -182,10 +338,63 @@ impl DeterministicSqlFunctions {
         Self {
             timestamp: DeterministicTimestamp::new(block_timestamp),
             random: DeterministicRandom::new(tx_id, seed),
+            statements_started: 0,
+            statement_resolution_micros: DEFAULT_STATEMENT_RESOLUTION_MICROS,
+            clock: None,
+        }
+    }
+    
+    /// Take each transaction's time from `clock` instead of the initial time
+    pub fn with_clock(mut self, clock: Arc<dyn DeterministicClock>) -> Self {
+        self.set_clock(clock);
+        self
+    }
+    
+    /// Replace the clock used for subsequent transactions
+    pub fn set_clock(&mut self, clock: Arc<dyn DeterministicClock>) {
+        self.clock = Some(clock);
+    }
+    
+    /// Set the spacing between consecutive statement timestamps
+    pub fn with_statement_resolution(mut self, micros: u64) -> Self {
+        self.statement_resolution_micros = micros;
+        self
+    }
+    
+    /// Start transaction `tx_id`, resetting statement time
+    ///
+    /// With a clock installed, the transaction time is read from it.
+    pub fn begin_transaction(&mut self, tx_id: u64) {
+        self.statements_started = 0;
+        if let Some(clock) = &self.clock {
+            self.timestamp = DeterministicTimestamp::new(clock.transaction_time(tx_id));
         }
     }
     
-    /// Get a deterministic timestamp
+    /// Mark the start of the next statement in the transaction
+    pub fn begin_statement(&mut self) {
+        self.statements_started += 1;
+    }
+    
+    /// Get the transaction timestamp (`now()`, `transaction_timestamp()`)
+    ///
+    /// The same for every statement in the transaction.
+    pub fn transaction_timestamp(&self) -> String {
+        self.timestamp.offset_string(0)
+    }
+    
+    /// Get the statement timestamp (`statement_timestamp()`)
+    ///
+    /// Fixed within a statement and offset by the statement resolution for
+    /// each earlier statement in the transaction.
+    pub fn statement_timestamp(&self) -> String {
+        let index = self.statements_started.saturating_sub(1);
+        self.timestamp.offset_string(index * self.statement_resolution_micros)
+    }
+    
+    /// Get a deterministic clock timestamp (`clock_timestamp()`)
+    ///
+    /// Advances on every call.
     pub fn timestamp(&mut self) -> String {
         let result = self.timestamp.as_string();
         self.timestamp.increment();
@@ -294,4 +503,96 @@ mod tests {
         let uuid = functions.uuid();
         assert!(uuid.len() == 36); // UUID format check
     }
+    
+    #[test]
+    fn test_transaction_and_statement_timestamps() {
+        let mut functions = DeterministicSqlFunctions::new(1, 1609459200, 0);
+        functions.begin_transaction(1);
+        
+        functions.begin_statement();
+        let now1 = functions.transaction_timestamp();
+        let stmt1 = functions.statement_timestamp();
+        
+        // Statement time is stable within a statement
+        assert_eq!(functions.statement_timestamp(), stmt1);
+        
+        functions.begin_statement();
+        let now2 = functions.transaction_timestamp();
+        let stmt2 = functions.statement_timestamp();
+        
+        // Same now() across statements, distinct statement_timestamp()
+        assert_eq!(now1, now2);
+        assert_ne!(stmt1, stmt2);
+        assert_eq!(stmt1, now1);
+        assert_eq!(stmt2, "2021-01-14 00:00:00.001000+00");
+        
+        // Replaying the same sequence gives the same values
+        let mut replay = DeterministicSqlFunctions::new(1, 1609459200, 0);
+        replay.begin_transaction(1);
+        replay.begin_statement();
+        replay.begin_statement();
+        assert_eq!(replay.statement_timestamp(), stmt2);
+        
+        // Resolution is configurable and carries into seconds
+        let mut coarse = DeterministicSqlFunctions::new(1, 1609459200, 0)
+            .with_statement_resolution(600_000);
+        for _ in 0..3 {
+            coarse.begin_statement();
+        }
+        assert_eq!(coarse.statement_timestamp(), "2021-01-14 00:00:01.200000+00");
+        
+        // A new transaction restarts statement time
+        coarse.begin_transaction(1);
+        coarse.begin_statement();
+        assert_eq!(coarse.statement_timestamp(), coarse.transaction_timestamp());
+    }
+    
+    /// Clock standing in for a consensus-derived time, agreed on by all nodes
+    #[derive(Debug)]
+    struct MockClock {
+        times: std::collections::HashMap<u64, u64>,
+    }
+    
+    impl DeterministicClock for MockClock {
+        fn transaction_time(&self, tx_id: u64) -> u64 {
+            self.times[&tx_id]
+        }
+    }
+    
+    #[test]
+    fn test_nodes_with_skewed_clocks_replay_identically() {
+        let clock: Arc<dyn DeterministicClock> = Arc::new(MockClock {
+            times: [(7, 1609459200), (8, 1609459205)].into_iter().collect(),
+        });
+        
+        let replay = |node_wall_time: u64| {
+            let mut functions = DeterministicSqlFunctions::new(0, node_wall_time, 0).with_clock(clock.clone());
+            let mut values = Vec::new();
+            for tx_id in [7, 8] {
+                functions.begin_transaction(tx_id);
+                for _ in 0..2 {
+                    functions.begin_statement();
+                    values.push(functions.transaction_timestamp());
+                    values.push(functions.statement_timestamp());
+                    values.push(functions.timestamp());
+                }
+            }
+            values
+        };
+        
+        // Two nodes whose wall clocks disagree by three seconds
+        let node_a = replay(1609459300);
+        let node_b = replay(1609459303);
+        assert_eq!(node_a, node_b);
+        assert_eq!(node_a[0], DeterministicTimestamp::new(1609459200).offset_string(0));
+        
+        // Without the shared clock the skew leaks into the replay
+        let mut unsynced = DeterministicSqlFunctions::new(0, 1609459303, 0);
+        unsynced.begin_transaction(7);
+        assert_ne!(unsynced.transaction_timestamp(), node_a[0]);
+        
+        // A logical clock needs no coordination at all
+        let logical = LogicalClock::new(1609459200, 5);
+        assert_eq!(logical.transaction_time(8), 1609459240);
+    }
 } 
\ No newline at end of file
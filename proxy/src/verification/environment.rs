@@ -10,23 +10,29 @@ use tokio_postgres::{Client, Socket, config::Config, NoTls};
 use tokio::sync::RwLock as TokioRwLock;
 use tokio::sync::mpsc;
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
-use log::{debug, warn, error};
+use log::{debug, warn};
 use std::sync::atomic::{AtomicU64, Ordering};
 use regex::Regex;
+use bytes::Bytes;
+use futures_util::SinkExt;
 // Add deadpool-postgres imports
 use deadpool_postgres::{Pool, PoolConfig, Manager, RecyclingMethod};
 
+use chrono::{TimeZone, Utc};
+
 use crate::error::{Result, ProxyError};
-use crate::verification::state::{StateCaptureManager};
+use crate::protocol::extended::RawParam;
+use crate::verification::state::{StateCaptureManager, PostgresVersions, LARGE_OBJECT_TABLE, LARGE_OBJECT_CAPTURE_QUERY, LARGE_OBJECT_INLINE_LIMIT};
 use verifiable_db_core::models::{Value, ColumnDefinition, RowId, BlockState as CoreDatabaseState, TableSchema, Row};
 use crate::interception::analyzer::QueryMetadata;
+use crate::interception::rewrite::{ColumnDefaults, fill_non_deterministic_defaults};
 use crate::protocol::transaction::TransactionState;
-use crate::verification::deterministic::DeterministicSqlFunctions;
+use crate::verification::deterministic::{ClockConfig, DeterministicClock, DeterministicSqlFunctions, DEFAULT_STATEMENT_RESOLUTION_MICROS};
 
 // For proper SQL parameter handling in PostgreSQL queries
-use tokio_postgres::types::ToSql;
+use tokio_postgres::types::{FromSql, ToSql, Type};
 
 /// Configuration for the verification environment
 #[derive(Debug, Clone)]
@@ -51,6 +57,24 @@ pub struct VerificationEnvironmentConfig {
     
     /// Connection timeout in seconds
     pub connection_timeout: u64,
+    
+    /// Publish connection pool gauges and counters through the `metrics` facade
+    pub pool_metrics: bool,
+    
+    /// Log a warning once no pooled connection has been available for this
+    /// many seconds (0 = never warn)
+    pub pool_exhaustion_warning_secs: u64,
+    
+    /// Spacing between replayed `statement_timestamp()` values (microseconds)
+    pub statement_timestamp_resolution_micros: u64,
+    
+    /// Clock assigning replayed transaction times
+    ///
+    /// Nodes replaying the same transactions must use clocks that agree.
+    pub clock: ClockConfig,
+    
+    /// Guard against the verification database running out of disk space
+    pub disk_guard: DiskGuardConfig,
 }
 
 impl Default for VerificationEnvironmentConfig {
@@ -63,10 +87,247 @@ impl Default for VerificationEnvironmentConfig {
             verification_schema: "verification".to_string(),
             pool_size: 5,
             connection_timeout: 30,
+            pool_metrics: false,
+            pool_exhaustion_warning_secs: 60,
+            statement_timestamp_resolution_micros: DEFAULT_STATEMENT_RESOLUTION_MICROS,
+            clock: ClockConfig::default(),
+            disk_guard: DiskGuardConfig::default(),
+        }
+    }
+}
+
+/// What to do with replay while the verification database is low on disk space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LowDiskPolicy {
+    /// Delay each replay by `throttle_delay_ms`, slowing the database's growth
+    Throttle,
+    
+    /// Skip replay, marking transactions as skipped with the reason
+    Skip,
+}
+
+impl Default for LowDiskPolicy {
+    fn default() -> Self {
+        Self::Skip
+    }
+}
+
+/// Free disk space guard for the verification database
+///
+/// Replay creates and drops tables in the verification database, which can
+/// fill its disk under heavy load. Free space is the disk capacity given to
+/// the database minus its `pg_database_size`.
+#[derive(Debug, Clone)]
+pub struct DiskGuardConfig {
+    /// Whether free space is checked before replay
+    pub enabled: bool,
+    
+    /// Disk space available to the verification database (bytes)
+    pub capacity_bytes: u64,
+    
+    /// Free space below which the database is low on disk (bytes)
+    pub min_free_bytes: u64,
+    
+    /// Minimum time between database size queries (seconds)
+    pub check_interval_secs: u64,
+    
+    /// What to do with replay while low on disk
+    pub policy: LowDiskPolicy,
+    
+    /// Delay before each replay under [`LowDiskPolicy::Throttle`] (milliseconds)
+    pub throttle_delay_ms: u64,
+}
+
+impl Default for DiskGuardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity_bytes: 0,
+            min_free_bytes: 0,
+            check_interval_secs: 30,
+            policy: LowDiskPolicy::default(),
+            throttle_delay_ms: 1000,
         }
     }
 }
 
+/// Last known free disk space of the verification database
+#[derive(Debug, Default)]
+struct DiskStatus {
+    free_bytes: Option<u64>,
+    checked_at: Option<Instant>,
+}
+
+/// Number of recent connection errors kept in [`PoolStatus::recent_errors`]
+pub const POOL_RECENT_ERRORS: usize = 10;
+
+/// Snapshot of the verification database connection pool's health
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PoolStatus {
+    /// Maximum number of connections
+    pub max_size: usize,
+    
+    /// Connections currently open
+    pub size: usize,
+    
+    /// Open connections idle in the pool
+    pub available: usize,
+    
+    /// Open connections handed out to callers
+    pub in_use: usize,
+    
+    /// Callers waiting for a connection
+    pub waiting: usize,
+    
+    /// Connections acquired since startup
+    pub acquired: u64,
+    
+    /// Acquisitions that failed, including timeouts
+    pub errors: u64,
+    
+    /// Acquisitions that timed out waiting for a connection
+    pub timeouts: u64,
+    
+    /// Average time spent waiting for a connection (milliseconds)
+    pub average_wait_ms: u64,
+    
+    /// Longest time spent waiting for a connection (milliseconds)
+    pub max_wait_ms: u64,
+    
+    /// Seconds the pool has had every connection in use, if it has
+    pub exhausted_secs: Option<u64>,
+    
+    /// Most recent acquisition errors, oldest first
+    pub recent_errors: Vec<String>,
+}
+
+/// Connection acquisition statistics behind [`PoolStatus`]
+#[derive(Debug, Default)]
+struct PoolStats {
+    acquired: u64,
+    errors: u64,
+    timeouts: u64,
+    total_wait: Duration,
+    max_wait: Duration,
+    recent_errors: VecDeque<String>,
+    
+    /// When the pool was first seen with every connection in use
+    exhausted_since: Option<Instant>,
+    
+    /// Whether the current exhaustion has been warned about
+    exhaustion_warned: bool,
+}
+
+/// What to do at startup when the live and verification databases run different PostgreSQL major versions
+///
+/// Function behavior and collation order can change between major versions,
+/// so replay on a different version may diverge from the live execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionMismatchPolicy {
+    /// Don't query or compare the versions; blocks record them as unknown
+    Ignore,
+    
+    /// Log a warning and start anyway
+    Warn,
+    
+    /// Refuse to start
+    Refuse,
+}
+
+impl Default for VersionMismatchPolicy {
+    fn default() -> Self {
+        Self::Refuse
+    }
+}
+
+/// Major version of a PostgreSQL `server_version_num` (e.g. 150004 is 15, 90624 is 9.6)
+pub fn postgres_major_version(version_num: u32) -> u32 {
+    if version_num >= 100_000 {
+        version_num / 10_000
+    } else {
+        version_num / 100
+    }
+}
+
+/// Human-readable form of a PostgreSQL `server_version_num` (e.g. 150004 is `15.4`)
+pub fn format_postgres_version(version_num: u32) -> String {
+    if version_num >= 100_000 {
+        format!("{}.{}", version_num / 10_000, version_num % 10_000)
+    } else {
+        format!("{}.{}.{}", version_num / 10_000, version_num / 100 % 100, version_num % 100)
+    }
+}
+
+/// Query the `server_version_num` of the server `client` is connected to
+pub async fn query_server_version_num(client: &Client) -> Result<u32> {
+    let row = client.query_one("SELECT current_setting('server_version_num')", &[])
+        .await
+        .map_err(|e| ProxyError::Database(format!("Failed to query server version: {}", e)))?;
+    let version: String = row.get(0);
+    version.parse()
+        .map_err(|e| ProxyError::Database(format!("Invalid server version {}: {}", version, e)))
+}
+
+/// Compare the major versions of the live and verification databases under `policy`
+///
+/// Returns whether the major versions match (always true under `Ignore`),
+/// or an error if they don't and the policy is to refuse.
+pub fn check_postgres_versions(versions: PostgresVersions, policy: VersionMismatchPolicy) -> Result<bool> {
+    if policy == VersionMismatchPolicy::Ignore
+        || postgres_major_version(versions.live) == postgres_major_version(versions.verifier) {
+        return Ok(true);
+    }
+    
+    let message = format!(
+        "Live database runs PostgreSQL {} but the verification database runs PostgreSQL {}; \
+         replay isn't deterministic across major versions",
+        format_postgres_version(versions.live), format_postgres_version(versions.verifier)
+    );
+    match policy {
+        VersionMismatchPolicy::Refuse => Err(ProxyError::Config(message)),
+        _ => {
+            warn!("{}", message);
+            Ok(false)
+        }
+    }
+}
+
+/// A statement to replay, together with the parameter values it was executed with
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayStatement {
+    /// SQL text, possibly containing `$N` placeholders
+    pub query: String,
+    
+    /// Bound parameter values, in placeholder order
+    pub params: Vec<Value>,
+    
+    /// Data streamed by a `COPY ... FROM STDIN`, sent again on replay
+    pub copy_data: Option<Bytes>,
+}
+
+impl ReplayStatement {
+    /// Create a statement with bound parameter values
+    pub fn new(query: impl Into<String>, params: Vec<Value>) -> Self {
+        Self {
+            query: query.into(),
+            params,
+            copy_data: None,
+        }
+    }
+    
+    /// Replay a `COPY ... FROM STDIN` with the data the client streamed
+    pub fn with_copy_data(mut self, data: Bytes) -> Self {
+        self.copy_data = Some(data);
+        self
+    }
+}
+
+impl From<String> for ReplayStatement {
+    fn from(query: String) -> Self {
+        Self::new(query, Vec::new())
+    }
+}
+
 /// Verification result containing state comparison and execution details
 #[derive(Debug, Clone)]
 pub struct VerificationExecutionResult {
@@ -107,18 +368,27 @@ pub struct VerificationEnvironment {
     /// Database connection pool for verification databases
     connection_pool: Arc<Pool>,
     
+    /// Connection pool acquisition statistics
+    pool_stats: Mutex<PoolStats>,
+    
     /// Deterministic SQL functions
     deterministic_functions: Arc<Mutex<DeterministicSqlFunctions>>,
     
     /// AtomicU64 to track the current transaction ID
     current_transaction_id: AtomicU64,
+    
+    /// Last free disk space check
+    disk_status: Mutex<DiskStatus>,
 }
 
 impl VerificationEnvironment {
     /// Create a new verification environment with the given configuration
     pub fn new(config: VerificationEnvironmentConfig, state_capture: Arc<StateCaptureManager>) -> Result<Self> {
+        let clock = config.clock.build();
         let deterministic_functions = Arc::new(Mutex::new(
-            DeterministicSqlFunctions::new(0, SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(), 0)
+            DeterministicSqlFunctions::new(0, clock.transaction_time(0), 0)
+                .with_statement_resolution(config.statement_timestamp_resolution_micros)
+                .with_clock(clock)
         ));
         
         // Parse the connection string into a PostgreSQL config
@@ -144,25 +414,30 @@ impl VerificationEnvironment {
             config,
             state_capture,
             connection_pool: Arc::new(pool),
+            pool_stats: Mutex::new(PoolStats::default()),
             deterministic_functions,
             current_transaction_id: AtomicU64::new(0),
+            disk_status: Mutex::new(DiskStatus::default()),
         })
     }
     
-    // Helper method to convert Value to SQL parameter
-    fn value_to_param<'a>(&self, value: &'a Value) -> Result<Box<dyn ToSql + Sync + 'a>> {
-        match value {
-            Value::Null => Ok(Box::new(None::<String>)), // Represent SQL NULL
-            Value::Text(t) => Ok(Box::new(t.clone())),
-            Value::Integer(i) => Ok(Box::new(*i)),
-            Value::BigInt(bi) => Ok(Box::new(*bi)),
-            Value::Float(f) => Ok(Box::new(*f)),
-            Value::Boolean(b) => Ok(Box::new(*b)),
-            // Add other types like Uuid, Timestamp, Binary, Json as needed
-            _ => Err(ProxyError::Database(format!(
-                "Unsupported value type for SQL parameter: {:?}",
-                value
-            ))),
+    /// Replay transaction times from `clock` instead of the configured one
+    ///
+    /// Use this to install a consensus-derived clock shared by all nodes.
+    pub fn with_clock(self, clock: Arc<dyn DeterministicClock>) -> Self {
+        self.deterministic_functions.lock().unwrap().set_clock(clock);
+        self
+    }
+    
+    /// Convert a value to a SQL parameter
+    ///
+    /// Values are bound as untyped text, which the server parses by the
+    /// parameter's type, so a value needn't have been decoded as exactly that
+    /// type: an `Integer` may fill a smallint column, a `Float` a real one.
+    fn value_to_param(&self, value: &Value) -> RawParam {
+        RawParam {
+            format: 0,
+            value: value_to_text(value).map(Bytes::from),
         }
     }
 
@@ -180,6 +455,14 @@ impl VerificationEnvironment {
             Value::Timestamp(ts) => ts.to_string(),
             Value::Binary(bin) => format!("{:?}", bin), // Or hex encode?
             Value::Json(j) => j.clone(),
+            Value::Array(elements) => format!(
+                "{{{}}}",
+                elements.iter().map(|e| self.value_to_string(e)).collect::<Vec<_>>().join(",")
+            ),
+            Value::Composite(fields) => format!(
+                "({})",
+                fields.iter().map(|(_, v)| self.value_to_string(v)).collect::<Vec<_>>().join(",")
+            ),
             // Consider a more robust default or error handling
         }
     }
@@ -215,17 +498,185 @@ impl VerificationEnvironment {
     /// Get a client from the connection pool
     async fn get_client(&self) -> Result<deadpool_postgres::Client> {
         let timeout = Duration::from_secs(self.config.connection_timeout);
+        let started = Instant::now();
         
         // Get a client from the pool with timeout
-        let client = tokio::time::timeout(timeout, self.connection_pool.get())
-            .await
-            .map_err(|_| ProxyError::Database("Connection pool timeout".to_string()))?
-            .map_err(|e| ProxyError::Database(format!("Failed to get database connection from pool: {}", e)))?;
-            
+        let result = tokio::time::timeout(timeout, self.connection_pool.get()).await;
+        let timed_out = result.is_err();
+        let result = result
+            .map_err(|_| ProxyError::Database("Connection pool timeout".to_string()))
+            .and_then(|client| client
+                .map_err(|e| ProxyError::Database(format!("Failed to get database connection from pool: {}", e))));
+        self.record_acquisition(started.elapsed(), result.as_ref().err(), timed_out);
+        
+        let client = result?;
         debug!("Acquired database connection from pool");
         Ok(client)
     }
     
+    /// Record the outcome of a connection acquisition
+    fn record_acquisition(&self, wait: Duration, error: Option<&ProxyError>, timed_out: bool) {
+        {
+            let mut stats = self.pool_stats.lock().unwrap();
+            match error {
+                None => {
+                    stats.acquired += 1;
+                    stats.total_wait += wait;
+                    stats.max_wait = stats.max_wait.max(wait);
+                }
+                Some(e) => {
+                    stats.errors += 1;
+                    if timed_out {
+                        stats.timeouts += 1;
+                    }
+                    if stats.recent_errors.len() == POOL_RECENT_ERRORS {
+                        stats.recent_errors.pop_front();
+                    }
+                    stats.recent_errors.push_back(e.to_string());
+                }
+            }
+        }
+        
+        if self.config.pool_metrics {
+            metrics::histogram!("verification_pool_wait_seconds", wait.as_secs_f64());
+            if error.is_some() {
+                metrics::counter!("verification_pool_errors_total", 1);
+            }
+        }
+        
+        // Refresh the gauges and exhaustion tracking
+        self.pool_status();
+    }
+    
+    /// Get the health of the connection pool
+    ///
+    /// Also publishes the pool gauges when metrics are enabled, and warns
+    /// once the pool has been exhausted for longer than configured.
+    pub fn pool_status(&self) -> PoolStatus {
+        let pool = self.connection_pool.status();
+        let in_use = pool.size.saturating_sub(pool.available);
+        let exhausted = pool.available == 0 && pool.size >= pool.max_size;
+        
+        let mut stats = self.pool_stats.lock().unwrap();
+        if !exhausted {
+            if stats.exhaustion_warned {
+                debug!("Verification connection pool has available connections again");
+            }
+            stats.exhausted_since = None;
+            stats.exhaustion_warned = false;
+        } else if stats.exhausted_since.is_none() {
+            stats.exhausted_since = Some(Instant::now());
+        }
+        
+        let exhausted_for = stats.exhausted_since.map(|since| since.elapsed());
+        if let Some(exhausted_for) = exhausted_for {
+            let threshold = self.config.pool_exhaustion_warning_secs;
+            if threshold > 0 && !stats.exhaustion_warned && exhausted_for >= Duration::from_secs(threshold) {
+                warn!(
+                    "Verification connection pool exhausted for {}s: all {} connections in use, {} callers waiting; consider raising pool_size",
+                    exhausted_for.as_secs(), pool.max_size, pool.waiting
+                );
+                stats.exhaustion_warned = true;
+            }
+        }
+        
+        if self.config.pool_metrics {
+            metrics::gauge!("verification_pool_max_size", pool.max_size as f64);
+            metrics::gauge!("verification_pool_size", pool.size as f64);
+            metrics::gauge!("verification_pool_available", pool.available as f64);
+            metrics::gauge!("verification_pool_in_use", in_use as f64);
+            metrics::gauge!("verification_pool_waiting", pool.waiting as f64);
+        }
+        
+        PoolStatus {
+            max_size: pool.max_size,
+            size: pool.size,
+            available: pool.available,
+            in_use,
+            waiting: pool.waiting,
+            acquired: stats.acquired,
+            errors: stats.errors,
+            timeouts: stats.timeouts,
+            average_wait_ms: (stats.total_wait.as_millis() / stats.acquired.max(1) as u128) as u64,
+            max_wait_ms: stats.max_wait.as_millis() as u64,
+            exhausted_secs: exhausted_for.map(|duration| duration.as_secs()),
+            recent_errors: stats.recent_errors.iter().cloned().collect(),
+        }
+    }
+    
+    /// Query the verification database's `server_version_num`
+    pub async fn server_version_num(&self) -> Result<u32> {
+        let client = self.get_client().await?;
+        query_server_version_num(&client).await
+    }
+    
+    /// Check whether the verification database can currently be reached
+    pub async fn is_available(&self) -> bool {
+        match self.get_client().await {
+            Ok(_) => true,
+            Err(e) => {
+                debug!("Verification database unavailable: {}", e);
+                false
+            }
+        }
+    }
+    
+    /// Disk space guard configuration
+    pub fn disk_guard(&self) -> &DiskGuardConfig {
+        &self.config.disk_guard
+    }
+    
+    /// Record the verification database's size, returning its free disk space
+    pub fn record_database_size(&self, size_bytes: u64) -> u64 {
+        let free_bytes = self.config.disk_guard.capacity_bytes.saturating_sub(size_bytes);
+        *self.disk_status.lock().unwrap() = DiskStatus {
+            free_bytes: Some(free_bytes),
+            checked_at: Some(Instant::now()),
+        };
+        free_bytes
+    }
+    
+    /// Free disk space of the verification database, or `None` if the disk guard is disabled
+    ///
+    /// The database size is queried at most once per `check_interval_secs`;
+    /// in between, the last result is reused.
+    pub async fn check_disk_space(&self) -> Result<Option<u64>> {
+        let guard = &self.config.disk_guard;
+        if !guard.enabled {
+            return Ok(None);
+        }
+        
+        {
+            let status = self.disk_status.lock().unwrap();
+            if let (Some(free_bytes), Some(checked_at)) = (status.free_bytes, status.checked_at) {
+                if checked_at.elapsed() < Duration::from_secs(guard.check_interval_secs) {
+                    return Ok(Some(free_bytes));
+                }
+            }
+        }
+        
+        let client = self.get_client().await?;
+        let row = client.query_one("SELECT pg_database_size(current_database())", &[])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to query verification database size: {}", e)))?;
+        let size: i64 = row.get(0);
+        Ok(Some(self.record_database_size(size.max(0) as u64)))
+    }
+    
+    /// Whether the verification database's free disk space is below `min_free_bytes`
+    ///
+    /// A failed check counts as not low; an unreachable database is handled
+    /// by [`is_available`](Self::is_available).
+    pub async fn is_low_on_disk(&self) -> bool {
+        match self.check_disk_space().await {
+            Ok(free_bytes) => free_bytes.is_some_and(|free| free < self.config.disk_guard.min_free_bytes),
+            Err(e) => {
+                debug!("Failed to check verification database disk space: {}", e);
+                false
+            }
+        }
+    }
+    
     /// Release a client back to the pool
     fn release_client(&self, _client: &deadpool_postgres::Client) {
         // No need to release client explicitly with deadpool-postgres
@@ -241,6 +692,11 @@ impl VerificationEnvironment {
             
         // For each table in the pre-state, create the table structure and populate with data
         for (table_name, table_state) in pre_state.tables().iter() {
+            if table_name == LARGE_OBJECT_TABLE {
+                self.restore_large_objects(client, &table_state.rows).await?;
+                continue;
+            }
+            
             // Create the table with the correct schema
             self.create_table(client, &table_state.table_schema).await?;
             
@@ -250,9 +706,64 @@ impl VerificationEnvironment {
             }
         }
         
+        // Install triggers only once the pre-state is loaded, so loading it
+        // doesn't fire them a second time
+        for (_, table_state) in pre_state.tables().iter().filter(|(name, _)| name.as_str() != LARGE_OBJECT_TABLE) {
+            for statement in self.trigger_statements(&table_state.table_schema) {
+                client.execute(&statement, &[])
+                    .await
+                    .map_err(|e| ProxyError::Database(format!("Failed to install trigger on {}: {}", table_state.table_schema.name, e)))?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Recreate the pre-state's large objects under their original OIDs
+    ///
+    /// Objects captured by reference only (larger than
+    /// `LARGE_OBJECT_INLINE_LIMIT`) have no content to restore, so a
+    /// transaction can't be replayed over them.
+    async fn restore_large_objects(&self, client: &deadpool_postgres::Client, rows: &HashMap<RowId, Row>) -> Result<()> {
+        client.execute("SELECT lo_unlink(oid) FROM pg_largeobject_metadata", &[])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to clear large objects: {}", e)))?;
+        
+        for row in rows.values() {
+            let loid = match row.values.get("loid") {
+                Some(Value::BigInt(loid)) => *loid,
+                other => return Err(ProxyError::Verification(format!("Invalid large object id: {:?}", other))),
+            };
+            let data = match row.values.get("data") {
+                Some(Value::Text(data)) => data.clone(),
+                _ => return Err(ProxyError::Verification(format!(
+                    "Large object {} is larger than {} bytes and was captured by reference only", loid, LARGE_OBJECT_INLINE_LIMIT
+                ))),
+            };
+            client.execute("SELECT lo_from_bytea($1::int8::oid, decode($2, 'hex'))", &[&loid, &data])
+                .await
+                .map_err(|e| ProxyError::Database(format!("Failed to restore large object {}: {}", loid, e)))?;
+        }
+        
         Ok(())
     }
     
+    /// Build the statements that recreate a table's triggers
+    ///
+    /// Each trigger function is created before the trigger that executes it.
+    fn trigger_statements(&self, schema: &TableSchema) -> Vec<String> {
+        let qualified_table = format!("{}.{}", self.config.verification_schema, schema.name);
+        let mut statements = Vec::new();
+        
+        for trigger in &schema.triggers {
+            statements.push(trigger.function_definition.clone());
+            statements.push(format!("DROP TRIGGER IF EXISTS {} ON {}", trigger.name, qualified_table));
+            statements.push(trigger.create_statement(&qualified_table));
+        }
+        
+        statements
+    }
+    
     /// Create a table in the verification database
     async fn create_table(&self, client: &deadpool_postgres::Client, schema: &TableSchema) -> Result<()> {
         // Build the CREATE TABLE statement
@@ -313,19 +824,12 @@ impl VerificationEnvironment {
         insert_stmt.push_str(")");
         
         // Build the params vector
-        let mut params: Vec<Box<dyn ToSql + Sync>> = Vec::new();
-        for column in &columns {
-            if let Some(value) = row.values.get(column) {
-                // Convert the value to a PostgreSQL type
-                // This is a simplified conversion - in reality, you'd need to handle all PostgreSQL types
-                params.push(self.value_to_param(value)?);
-            } else {
-                params.push(Box::new(None::<String>));
-            }
-        }
+        let params: Vec<RawParam> = columns.iter()
+            .map(|column| self.value_to_param(row.values.get(column).unwrap_or(&Value::Null)))
+            .collect();
         
         // Execute the insert statement
-        let param_refs: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p.as_ref()).collect();
+        let param_refs: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
         client.execute(&insert_stmt, &param_refs[..])
             .await
             .map_err(|e| ProxyError::Database(format!("Failed to insert row: {}", e)))?;
@@ -362,7 +866,7 @@ impl VerificationEnvironment {
     pub async fn verify_transaction(
         &self,
         transaction_id: u64,
-        queries: Vec<String>,
+        statements: Vec<ReplayStatement>,
         metadata: Vec<QueryMetadata>,
         pre_state: CoreDatabaseState,
         expected_post_state: CoreDatabaseState,
@@ -452,11 +956,37 @@ impl VerificationEnvironment {
             }
         }
         
+        // Transaction time comes from the clock; statement time restarts with each transaction
+        self.deterministic_functions.lock().unwrap().begin_transaction(transaction_id);
+        
+        // INSERTs leaving columns to non-deterministic defaults get the deterministic equivalent
+        let column_defaults: HashMap<String, ColumnDefaults> = pre_state.tables().iter()
+            .map(|(name, table_state)| {
+                let defaults = table_state.table_schema.columns.iter()
+                    .map(|column| (column.name.clone(), column.default_value.clone()))
+                    .collect();
+                (name.to_lowercase(), defaults)
+            })
+            .collect();
+        
         // Execute each query in the transaction
-        for (i, query) in queries.iter().enumerate() {
+        for (i, statement) in statements.iter().enumerate() {
+            self.deterministic_functions.lock().unwrap().begin_statement();
+            
+            let tx_id = self.current_transaction_id.load(Ordering::SeqCst);
+            let query = fill_non_deterministic_defaults(&statement.query, &column_defaults, tx_id, tx_id)
+                .unwrap_or_else(|| statement.query.clone());
+            
+            let execution = async {
+                match &statement.copy_data {
+                    Some(data) => self.copy_in_with_client(&client, &query, data).await.map(|_| ()),
+                    None => self.execute_query_with_client(&client, &query, &statement.params).await.map(|_| ()),
+                }
+            };
+            
             match tokio::time::timeout(
                 Duration::from_millis(self.config.execution_timeout_ms),
-                self.execute_query_with_client(&client, query)
+                execution
             ).await {
                 Ok(query_result) => {
                     if let Err(e) = query_result {
@@ -533,8 +1063,35 @@ impl VerificationEnvironment {
         Ok(result)
     }
     
+    /// Replay a `COPY ... FROM STDIN` against a verification database
+    ///
+    /// Returns the number of rows loaded.
+    async fn copy_in_with_client(
+        &self,
+        client: &deadpool_postgres::Client,
+        query: &str,
+        data: &Bytes,
+    ) -> Result<u64> {
+        let sink = client.copy_in::<_, Bytes>(query).await
+            .map_err(|e| ProxyError::Database(format!("Failed to start COPY: {}", e)))?;
+        futures_util::pin_mut!(sink);
+        
+        sink.send(data.clone()).await
+            .map_err(|e| ProxyError::Database(format!("Failed to send COPY data: {}", e)))?;
+        sink.finish().await
+            .map_err(|e| ProxyError::Database(format!("Failed to complete COPY: {}", e)))
+    }
+    
     /// Execute a query against a verification database
-    async fn execute_query_with_client(&self, client: &deadpool_postgres::Client, query: &str) -> Result<Vec<tokio_postgres::Row>> {
+    ///
+    /// `params` are the values originally bound to the statement's `$N`
+    /// placeholders; they are passed as real SQL parameters, never inlined.
+    async fn execute_query_with_client(
+        &self,
+        client: &deadpool_postgres::Client,
+        query: &str,
+        params: &[Value],
+    ) -> Result<Vec<tokio_postgres::Row>> {
         let mut rewritten_query = query.to_string();
         
         // Get transaction ID before processing
@@ -566,8 +1123,12 @@ impl VerificationEnvironment {
             }
         }
         
+        // Bind the original parameter values
+        let bound_params: Vec<RawParam> = params.iter().map(|value| self.value_to_param(value)).collect();
+        let param_refs: Vec<&(dyn ToSql + Sync)> = bound_params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+        
         // Then execute the rewritten query instead of the original:
-        let result = client.query(&rewritten_query, &[]).await;
+        let result = client.query(&rewritten_query, &param_refs[..]).await;
         
         // Update the current transaction ID
         self.current_transaction_id.store(tx_id + 1, Ordering::SeqCst);
@@ -591,10 +1152,14 @@ impl VerificationEnvironment {
                 ..Default::default()
             };
             
-            // Query all rows from the table
-            let select_stmt = format!("SELECT * FROM {}.{}", self.config.verification_schema, table_name);
-            let rows = client.query(&select_stmt, &[])
-                .await
+            // Query all rows from the table; large objects are read back from pg_largeobject
+            let rows = if table_name == LARGE_OBJECT_TABLE {
+                client.query(LARGE_OBJECT_CAPTURE_QUERY, &[&LARGE_OBJECT_INLINE_LIMIT]).await
+            } else {
+                let select_stmt = format!("SELECT * FROM {}.{}", self.config.verification_schema, table_name);
+                client.query(&select_stmt, &[]).await
+            };
+            let rows = rows
                 .map_err(|e| ProxyError::Database(format!("Failed to query rows from table {}: {}", table_name, e)))?;
                 
             // Process each row
@@ -663,7 +1228,7 @@ impl VerificationEnvironment {
                                                 break;
                                             },
                                             Some(actual_value) => {
-                                                if expected_value != actual_value {
+                                                if !expected_value.canonical_eq(actual_value) {
                                                     debug!("Value mismatch for column {} in row {:?} for table {}: expected {:?}, actual {:?}", 
                                                         col, row_id, name, expected_value, actual_value);
                                                     mismatched_rows.entry(name.clone())
@@ -711,7 +1276,9 @@ impl VerificationEnvironment {
         
         // Execute the requested function
         let result = match function_name {
-            "now" | "current_timestamp" => functions.timestamp(),
+            "now" | "current_timestamp" | "transaction_timestamp" => functions.transaction_timestamp(),
+            "statement_timestamp" => functions.statement_timestamp(),
+            "clock_timestamp" => functions.timestamp(),
             "random" => functions.random().to_string(),
             "uuid" | "gen_random_uuid" => functions.uuid(),
             "txid_current" => functions.txid().to_string(),
@@ -739,59 +1306,12 @@ impl VerificationEnvironment {
 
         // Process each column
         for (i, column) in table_schema.columns.iter().enumerate() {
-            let data_type = column.data_type.to_lowercase();
-
-            // Extract the value according to the data type
-            let value = match data_type.as_str() {
-                "integer" | "int" | "int4" => {
-                    match pg_row.try_get::<_, Option<i32>>(i) {
-                        Ok(Some(v)) => Value::Integer(v),
-                        Ok(None) => Value::Null,
-                        Err(e) => return Err(ProxyError::Database(format!("Failed to get int column '{}': {}", column.name, e)))
-                    }
-                },
-                "bigint" | "int8" => {
-                    match pg_row.try_get::<_, Option<i64>>(i) {
-                        Ok(Some(v)) => Value::BigInt(v),
-                        Ok(None) => Value::Null,
-                        Err(e) => return Err(ProxyError::Database(format!("Failed to get bigint column '{}': {}", column.name, e)))
-                    }
-                },
-                "text" | "varchar" | "char" | "character varying" => {
-                    match pg_row.try_get::<_, Option<String>>(i) {
-                        Ok(Some(v)) => Value::Text(v),
-                        Ok(None) => Value::Null,
-                        Err(e) => return Err(ProxyError::Database(format!("Failed to get text column '{}': {}", column.name, e)))
-                    }
-                },
-                "float" | "float4" | "float8" | "real" | "double precision" => {
-                    match pg_row.try_get::<_, Option<f64>>(i) {
-                        Ok(Some(v)) => Value::Float(v),
-                        Ok(None) => Value::Null,
-                        Err(e) => return Err(ProxyError::Database(format!("Failed to get float column '{}': {}", column.name, e)))
-                    }
-                },
-                "boolean" | "bool" => {
-                    match pg_row.try_get::<_, Option<bool>>(i) {
-                        Ok(Some(v)) => Value::Boolean(v),
-                        Ok(None) => Value::Null,
-                        Err(e) => return Err(ProxyError::Database(format!("Failed to get boolean column '{}': {}", column.name, e)))
-                    }
-                },
-                // Add more specific types like timestamp, uuid, json, bytea here
-                _ => {
-                    // Fallback: Try to get as string for unknown/unhandled types
-                    warn!("Unhandled data type '{}' for column '{}', attempting to read as text.", data_type, column.name);
-                    match pg_row.try_get::<_, Option<String>>(i) {
-                        Ok(Some(v)) => Value::Text(v),
-                        Ok(None) => Value::Null,
-                        Err(e) => {
-                            error!("Failed to get column '{}' as fallback text: {}", column.name, e);
-                            Value::Null // Or return error?
-                        }
-                    }
-                }
-            };
+            let decoding = ColumnDecoding::for_data_type(&column.data_type).ok_or_else(|| ProxyError::Database(format!(
+                "Unsupported data type '{}' for column '{}'", column.data_type, column.name
+            )))?;
+            let value = decoding.decode(pg_row, i).map_err(|e| ProxyError::Database(format!(
+                "Failed to get {:?} column '{}': {}", decoding, column.name, e
+            )))?;
 
             row_values.insert(column.name.clone(), value.clone());
 
@@ -817,10 +1337,199 @@ impl VerificationEnvironment {
     }
 }
 
+/// How a captured column is decoded, chosen from its SQL data type
+///
+/// Decoded values match what `StateCaptureManager` captures for the same
+/// column, so replayed and captured row hashes agree: numerics keep their
+/// exact decimal text and timestamps become Unix epoch milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnDecoding {
+    SmallInt,
+    Integer,
+    BigInt,
+    Real,
+    Double,
+    Numeric,
+    Boolean,
+    Text,
+    Timestamp,
+    Uuid,
+    Json,
+    Binary,
+}
+
+impl ColumnDecoding {
+    /// Decoding for a data type name, or `None` if the type is not supported
+    fn for_data_type(data_type: &str) -> Option<Self> {
+        let data_type = data_type.to_lowercase();
+        // Drop type modifiers such as `numeric(10,2)` or `varchar(255)`
+        let base = data_type.split('(').next().unwrap_or_default().trim();
+        let decoding = match base {
+            "smallint" | "int2" => ColumnDecoding::SmallInt,
+            "integer" | "int" | "int4" => ColumnDecoding::Integer,
+            "bigint" | "int8" => ColumnDecoding::BigInt,
+            "real" | "float4" => ColumnDecoding::Real,
+            "float" | "float8" | "double precision" => ColumnDecoding::Double,
+            "numeric" | "decimal" => ColumnDecoding::Numeric,
+            "boolean" | "bool" => ColumnDecoding::Boolean,
+            "text" | "varchar" | "char" | "character" | "character varying" | "bpchar" | "name" => ColumnDecoding::Text,
+            "timestamp" | "timestamptz" | "timestamp without time zone" | "timestamp with time zone" => ColumnDecoding::Timestamp,
+            "uuid" => ColumnDecoding::Uuid,
+            "json" | "jsonb" => ColumnDecoding::Json,
+            "bytea" => ColumnDecoding::Binary,
+            _ => return None,
+        };
+        Some(decoding)
+    }
+
+    /// Decode column `index` of a row
+    fn decode(self, pg_row: &tokio_postgres::Row, index: usize) -> std::result::Result<Value, tokio_postgres::Error> {
+        let value = match self {
+            ColumnDecoding::SmallInt => pg_row.try_get::<_, Option<i16>>(index)?.map(|v| Value::Integer(v.into())),
+            ColumnDecoding::Integer => pg_row.try_get::<_, Option<i32>>(index)?.map(Value::Integer),
+            ColumnDecoding::BigInt => pg_row.try_get::<_, Option<i64>>(index)?.map(Value::BigInt),
+            ColumnDecoding::Real => pg_row.try_get::<_, Option<f32>>(index)?.map(|v| Value::Float(v.into())),
+            ColumnDecoding::Double => pg_row.try_get::<_, Option<f64>>(index)?.map(Value::Float),
+            ColumnDecoding::Numeric => pg_row.try_get::<_, Option<NumericText>>(index)?.map(|v| Value::Text(v.0)),
+            ColumnDecoding::Boolean => pg_row.try_get::<_, Option<bool>>(index)?.map(Value::Boolean),
+            ColumnDecoding::Text => pg_row.try_get::<_, Option<String>>(index)?.map(Value::Text),
+            ColumnDecoding::Timestamp => pg_row.try_get::<_, Option<SystemTime>>(index)?.map(|v| Value::Timestamp(epoch_millis(v))),
+            ColumnDecoding::Uuid => pg_row.try_get::<_, Option<uuid::Uuid>>(index)?.map(Value::Uuid),
+            ColumnDecoding::Json => pg_row.try_get::<_, Option<serde_json::Value>>(index)?.map(|v| Value::Json(v.to_string())),
+            ColumnDecoding::Binary => pg_row.try_get::<_, Option<Vec<u8>>>(index)?.map(Value::Binary),
+        };
+        Ok(value.unwrap_or(Value::Null))
+    }
+}
+
+/// PostgreSQL's text form of a value, or `None` for NULL
+fn value_to_text(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::Null => return None,
+        Value::Integer(i) => i.to_string(),
+        Value::BigInt(bi) => bi.to_string(),
+        Value::Float(f) if f.is_nan() => "NaN".to_string(),
+        Value::Float(f) if f.is_infinite() => if *f > 0.0 { "Infinity" } else { "-Infinity" }.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Text(t) => t.clone(),
+        Value::Binary(bytes) => format!("\\x{}", hex::encode(bytes)),
+        Value::Boolean(b) => if *b { "t" } else { "f" }.to_string(),
+        Value::Uuid(u) => u.to_string(),
+        // Captured as milliseconds since the epoch; a timestamp without time
+        // zone ignores the offset
+        Value::Timestamp(millis) => Utc.timestamp_millis_opt(*millis).single()?
+            .format("%Y-%m-%d %H:%M:%S%.3f+00")
+            .to_string(),
+        Value::Json(json) => json.clone(),
+        Value::Array(elements) => format!(
+            "{{{}}}",
+            elements.iter().map(array_element_text).collect::<Vec<_>>().join(",")
+        ),
+        Value::Composite(fields) => format!(
+            "({})",
+            fields.iter().map(|(_, field)| composite_field_text(field)).collect::<Vec<_>>().join(",")
+        ),
+    };
+    Some(text)
+}
+
+/// Text form of an array element, quoted so delimiters in it are kept
+fn array_element_text(element: &Value) -> String {
+    match element {
+        Value::Array(_) => value_to_text(element).unwrap_or_default(),
+        _ => match value_to_text(element) {
+            Some(text) => format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")),
+            None => "NULL".to_string(),
+        },
+    }
+}
+
+/// Text form of a composite field; NULL is left empty
+fn composite_field_text(field: &Value) -> String {
+    match value_to_text(field) {
+        Some(text) => format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\"\"")),
+        None => String::new(),
+    }
+}
+
+/// Milliseconds since the Unix epoch, rounded half away from zero like
+/// `(extract(epoch from ts) * 1000)::bigint`
+fn epoch_millis(time: SystemTime) -> i64 {
+    let (micros, negative) = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => (after.as_micros(), false),
+        Err(before) => (before.duration().as_micros(), true),
+    };
+    let millis = ((micros + 500) / 1000) as i64;
+    if negative { -millis } else { millis }
+}
+
+/// A `numeric` value in the text form PostgreSQL prints for it
+struct NumericText(String);
+
+impl<'a> FromSql<'a> for NumericText {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        numeric_to_text(raw).map(NumericText).map_err(Into::into)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::NUMERIC
+    }
+}
+
+/// Render a binary `numeric` (base-10000 digit groups) as decimal text
+fn numeric_to_text(raw: &[u8]) -> std::result::Result<String, String> {
+    let field = |offset: usize| u16::from_be_bytes([raw[offset], raw[offset + 1]]);
+    if raw.len() < 8 {
+        return Err("numeric value too short".to_string());
+    }
+    let ndigits = field(0) as usize;
+    let weight = field(2) as i16 as i32;
+    let sign = field(4);
+    let dscale = field(6) as usize;
+    if raw.len() != 8 + 2 * ndigits {
+        return Err(format!("numeric value has {} bytes for {} digits", raw.len(), ndigits));
+    }
+    match sign {
+        0x0000 | 0x4000 => {}
+        0xC000 => return Ok("NaN".to_string()),
+        0xD000 => return Ok("Infinity".to_string()),
+        0xF000 => return Ok("-Infinity".to_string()),
+        _ => return Err(format!("invalid numeric sign {:#06x}", sign)),
+    }
+
+    // Digit group i has weight `weight - i`; groups outside the stored ones are zero
+    let group = |i: i32| if i >= 0 && (i as usize) < ndigits { field(8 + 2 * i as usize) } else { 0 };
+    let mut text = String::new();
+    if sign == 0x4000 {
+        text.push('-');
+    }
+    if weight < 0 {
+        text.push('0');
+    } else {
+        text.push_str(&group(0).to_string());
+        for i in 1..=weight {
+            text.push_str(&format!("{:04}", group(i)));
+        }
+    }
+    if dscale > 0 {
+        let mut fraction = String::new();
+        let mut i = weight + 1;
+        while fraction.len() < dscale {
+            fraction.push_str(&format!("{:04}", group(i)));
+            i += 1;
+        }
+        fraction.truncate(dscale);
+        text.push('.');
+        text.push_str(&fraction);
+    }
+    Ok(text)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tokio::runtime::Runtime;
+    use verifiable_db_core::models::{TriggerDefinition, TriggerEvent, TriggerTiming};
     use std::time::{SystemTime, UNIX_EPOCH};
     
     // Create a helper function for test table schema creation
@@ -838,6 +1547,7 @@ mod tests {
                 }
             ],
             primary_key: vec!["id".to_string()],
+            triggers: Vec::new(),
             version: 1,
             created_at: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
@@ -861,6 +1571,11 @@ mod tests {
             verification_schema: "verification".to_string(),
             pool_size: 5,
             connection_timeout: 30,
+            pool_metrics: false,
+            pool_exhaustion_warning_secs: 60,
+            statement_timestamp_resolution_micros: DEFAULT_STATEMENT_RESOLUTION_MICROS,
+            clock: ClockConfig::default(),
+            disk_guard: DiskGuardConfig::default(),
         };
         
         let state_capture = Arc::new(StateCaptureManager::new());
@@ -869,6 +1584,205 @@ mod tests {
         let _env = VerificationEnvironment::new(config, state_capture).unwrap();
     }
     
+    /// Connection string of the database the ignored tests run against
+    fn test_connection_string() -> String {
+        format!(
+            "host={} port={} user={} password={} dbname={}",
+            std::env::var("PG_HOST").unwrap_or_else(|_| "localhost".to_string()),
+            std::env::var("PG_PORT").unwrap_or_else(|_| "5432".to_string()),
+            std::env::var("PG_USER").unwrap_or_else(|_| "verifiable".to_string()),
+            std::env::var("PG_PASSWORD").unwrap_or_else(|_| "verifiable".to_string()),
+            std::env::var("PG_DATABASE").unwrap_or_else(|_| "verifiable_db".to_string()),
+        )
+    }
+    
+    #[test]
+    fn test_trigger_statements() {
+        let config = VerificationEnvironmentConfig {
+            verification_schema: "verification".to_string(),
+            ..Default::default()
+        };
+        let env = VerificationEnvironment::new(config, Arc::new(StateCaptureManager::new())).unwrap();
+        
+        let mut schema = create_test_table_schema();
+        assert!(env.trigger_statements(&schema).is_empty());
+        
+        let function_definition = "CREATE OR REPLACE FUNCTION public.audit_insert() RETURNS trigger \
+            LANGUAGE plpgsql AS $$ BEGIN INSERT INTO audit_log (row_id) VALUES (NEW.id); RETURN NEW; END $$";
+        schema.triggers.push(TriggerDefinition {
+            name: "audit_test_table".to_string(),
+            timing: TriggerTiming::After,
+            events: vec![TriggerEvent::Insert],
+            for_each_row: true,
+            function_name: "audit_insert".to_string(),
+            function_definition: function_definition.to_string(),
+        });
+        
+        // The function must exist before the trigger that executes it, and the
+        // trigger is attached to the table inside the verification schema
+        let statements = env.trigger_statements(&schema);
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[0], function_definition);
+        assert_eq!(statements[1], "DROP TRIGGER IF EXISTS audit_test_table ON verification.test_table");
+        assert_eq!(
+            statements[2],
+            "CREATE TRIGGER audit_test_table AFTER INSERT ON verification.test_table FOR EACH ROW EXECUTE FUNCTION audit_insert()"
+        );
+    }
+    
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database"]
+    async fn test_trigger_side_effects_replayed() {
+        let config = VerificationEnvironmentConfig {
+            connection_string: test_connection_string(),
+            verification_schema: "trigger_replay".to_string(),
+            pool_size: 1,
+            ..Default::default()
+        };
+        let env = VerificationEnvironment::new(config, Arc::new(StateCaptureManager::new())).unwrap();
+        let client = env.get_client().await.unwrap();
+        
+        let function_definition = "CREATE OR REPLACE FUNCTION audit_balance() RETURNS trigger \
+            LANGUAGE plpgsql AS $$ BEGIN INSERT INTO audit_log (account_id, balance) VALUES (NEW.id, NEW.balance); RETURN NEW; END $$";
+        let mut schema = create_test_table_schema();
+        schema.name = "accounts".to_string();
+        schema.triggers.push(TriggerDefinition {
+            name: "audit_accounts".to_string(),
+            timing: TriggerTiming::After,
+            events: vec![TriggerEvent::Update],
+            for_each_row: true,
+            function_name: "audit_balance".to_string(),
+            function_definition: function_definition.to_string(),
+        });
+        let pre_state = "CREATE TABLE accounts (id int PRIMARY KEY, balance int NOT NULL); \
+            CREATE TABLE audit_log (account_id int, balance int); \
+            INSERT INTO accounts VALUES (1, 100), (2, 200)";
+        let update = "UPDATE accounts SET balance = balance + 10 WHERE id = 1";
+        
+        // The statement as it ran on the database, firing the trigger
+        client.batch_execute("DROP SCHEMA IF EXISTS trigger_source CASCADE; CREATE SCHEMA trigger_source; SET search_path TO trigger_source").await.unwrap();
+        client.batch_execute(pre_state).await.unwrap();
+        client.batch_execute(function_definition).await.unwrap();
+        client.batch_execute("CREATE TRIGGER audit_accounts AFTER UPDATE ON accounts FOR EACH ROW EXECUTE FUNCTION audit_balance()").await.unwrap();
+        client.batch_execute(update).await.unwrap();
+        
+        // Its replay over the same pre-state, with the captured trigger recreated
+        client.batch_execute("DROP SCHEMA IF EXISTS trigger_replay CASCADE; CREATE SCHEMA trigger_replay; SET search_path TO trigger_replay").await.unwrap();
+        client.batch_execute(pre_state).await.unwrap();
+        for statement in env.trigger_statements(&schema) {
+            client.batch_execute(&statement).await.unwrap();
+        }
+        env.execute_query_with_client(&client, update, &[]).await.unwrap();
+        
+        let mut states = Vec::new();
+        for schema in ["trigger_source", "trigger_replay"] {
+            let mut state = Vec::new();
+            for table in ["accounts", "audit_log"] {
+                let rows = client.query(&format!("SELECT * FROM {}.{} ORDER BY 1", schema, table), &[]).await.unwrap();
+                state.push(rows.iter().map(|row| (row.get::<_, i32>(0), row.get::<_, i32>(1))).collect::<Vec<_>>());
+            }
+            states.push(state);
+        }
+        
+        // Both tables match, including the row the trigger wrote
+        assert_eq!(states[0], states[1]);
+        assert_eq!(states[1], vec![vec![(1, 110), (2, 200)], vec![(1, 110)]]);
+        
+        client.batch_execute("DROP SCHEMA trigger_source CASCADE; DROP SCHEMA trigger_replay CASCADE").await.unwrap();
+    }
+    
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database"]
+    async fn test_pool_status_tracks_acquired_connections() {
+        let config = VerificationEnvironmentConfig {
+            connection_string: test_connection_string(),
+            pool_size: 2,
+            connection_timeout: 1,
+            ..Default::default()
+        };
+        let env = VerificationEnvironment::new(config, Arc::new(StateCaptureManager::new())).unwrap();
+        assert_eq!(env.pool_status(), PoolStatus { max_size: 2, ..Default::default() });
+        
+        let first = env.get_client().await.unwrap();
+        let second = env.get_client().await.unwrap();
+        let status = env.pool_status();
+        assert_eq!((status.size, status.available, status.in_use), (2, 0, 2));
+        assert_eq!(status.acquired, 2);
+        assert!(status.exhausted_secs.is_some());
+        
+        // Every connection is in use, so the next caller times out
+        assert!(env.get_client().await.is_err());
+        let status = env.pool_status();
+        assert_eq!((status.errors, status.timeouts), (1, 1));
+        assert_eq!(status.recent_errors, vec!["Database error: Connection pool timeout".to_string()]);
+        
+        // Released connections become available again
+        drop(first);
+        let status = env.pool_status();
+        assert_eq!((status.size, status.available, status.in_use), (2, 1, 1));
+        assert_eq!(status.exhausted_secs, None);
+        
+        drop(second);
+        assert_eq!(env.pool_status().available, 2);
+    }
+    
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database"]
+    async fn test_replayed_params_parsed_by_column_type() {
+        let config = VerificationEnvironmentConfig {
+            connection_string: test_connection_string(),
+            pool_size: 1,
+            ..Default::default()
+        };
+        let env = VerificationEnvironment::new(config, Arc::new(StateCaptureManager::new())).unwrap();
+        let client = env.get_client().await.unwrap();
+        client.batch_execute("CREATE TEMP TABLE replay_params (small int2, ratio float4, note text)").await.unwrap();
+        
+        // Bind decodes int2 as Integer and float4 as Float, neither of which
+        // is the columns' Rust type
+        env.execute_query_with_client(
+            &client,
+            "INSERT INTO replay_params VALUES ($1, $2, $3)",
+            &[Value::Integer(7), Value::Float(1.5), Value::Null],
+        ).await.unwrap();
+        
+        let rows = client.query("SELECT small, ratio, note FROM replay_params", &[]).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get::<_, i16>(0), 7);
+        assert_eq!(rows[0].get::<_, f32>(1), 1.5);
+        assert_eq!(rows[0].get::<_, Option<String>>(2), None);
+        
+        // Parameters the statement doesn't type are read as text
+        let rows = env.execute_query_with_client(&client, "SELECT $1 AS untyped", &[Value::Integer(42)]).await.unwrap();
+        assert_eq!(rows[0].get::<_, String>(0), "42");
+    }
+    
+    #[test]
+    fn test_values_bound_as_postgres_text() {
+        assert_eq!(value_to_text(&Value::Null), None);
+        assert_eq!(value_to_text(&Value::Integer(-7)).as_deref(), Some("-7"));
+        assert_eq!(value_to_text(&Value::Float(1.5)).as_deref(), Some("1.5"));
+        assert_eq!(value_to_text(&Value::Float(f64::NEG_INFINITY)).as_deref(), Some("-Infinity"));
+        assert_eq!(value_to_text(&Value::Boolean(true)).as_deref(), Some("t"));
+        assert_eq!(value_to_text(&Value::Binary(vec![0xde, 0xad])).as_deref(), Some("\\xdead"));
+        assert_eq!(value_to_text(&Value::Timestamp(1_500)).as_deref(), Some("1970-01-01 00:00:01.500+00"));
+        assert_eq!(
+            value_to_text(&Value::Array(vec![
+                Value::Text("a,\"b\"".to_string()),
+                Value::Null,
+                Value::Array(vec![Value::Integer(1)]),
+            ])).as_deref(),
+            Some("{\"a,\\\"b\\\"\",NULL,{\"1\"}}"),
+        );
+        assert_eq!(
+            value_to_text(&Value::Composite(vec![
+                ("name".to_string(), Value::Text("say \"hi\"".to_string())),
+                ("age".to_string(), Value::Null),
+            ])).as_deref(),
+            Some("(\"say \"\"hi\"\"\",)"),
+        );
+    }
+    
     #[test]
     fn test_environment_cleanup() {
         // Create a minimal configuration
@@ -880,6 +1794,11 @@ mod tests {
             verification_schema: "verification".to_string(),
             pool_size: 5,
             connection_timeout: 30,
+            pool_metrics: false,
+            pool_exhaustion_warning_secs: 60,
+            statement_timestamp_resolution_micros: DEFAULT_STATEMENT_RESOLUTION_MICROS,
+            clock: ClockConfig::default(),
+            disk_guard: DiskGuardConfig::default(),
         };
         
         let state_capture = Arc::new(StateCaptureManager::new());
@@ -895,4 +1814,116 @@ mod tests {
             assert!(result.is_ok());
         });
     }
+    
+    #[test]
+    fn test_low_disk_space_detected() {
+        let config = VerificationEnvironmentConfig {
+            disk_guard: DiskGuardConfig {
+                enabled: true,
+                capacity_bytes: 1000,
+                min_free_bytes: 200,
+                check_interval_secs: 3600,
+                ..DiskGuardConfig::default()
+            },
+            ..VerificationEnvironmentConfig::default()
+        };
+        let env = VerificationEnvironment::new(config, Arc::new(StateCaptureManager::new())).unwrap();
+        let rt = Runtime::new().unwrap();
+        
+        // Recent sizes are reused without querying the database
+        assert_eq!(env.record_database_size(900), 100);
+        assert!(rt.block_on(env.is_low_on_disk()));
+        assert_eq!(env.record_database_size(400), 600);
+        assert!(!rt.block_on(env.is_low_on_disk()));
+        assert_eq!(env.record_database_size(2000), 0);
+        assert!(rt.block_on(env.is_low_on_disk()));
+        
+        // Disabled guards never report low disk
+        let env = VerificationEnvironment::new(VerificationEnvironmentConfig::default(), Arc::new(StateCaptureManager::new())).unwrap();
+        env.record_database_size(u64::MAX);
+        assert_eq!(rt.block_on(env.check_disk_space()).unwrap(), None);
+        assert!(!rt.block_on(env.is_low_on_disk()));
+    }
+    
+    #[test]
+    fn test_major_version_mismatch_refused_or_warned() {
+        // Live PostgreSQL 15.4, verification database PostgreSQL 14.9
+        let mismatched = PostgresVersions { live: 150004, verifier: 140009 };
+        assert!(matches!(
+            check_postgres_versions(mismatched, VersionMismatchPolicy::Refuse),
+            Err(ProxyError::Config(_))
+        ));
+        assert!(!check_postgres_versions(mismatched, VersionMismatchPolicy::Warn).unwrap());
+        assert!(check_postgres_versions(mismatched, VersionMismatchPolicy::Ignore).unwrap());
+        
+        // Minor versions don't matter
+        let minor = PostgresVersions { live: 150004, verifier: 150008 };
+        assert!(check_postgres_versions(minor, VersionMismatchPolicy::Refuse).unwrap());
+        
+        // Before PostgreSQL 10 the major version has two parts
+        assert_eq!(postgres_major_version(90624), 906);
+        assert_eq!(format_postgres_version(90624), "9.6.24");
+        assert_eq!(format_postgres_version(150004), "15.4");
+        let legacy = PostgresVersions { live: 90624, verifier: 90520 };
+        assert!(check_postgres_versions(legacy, VersionMismatchPolicy::Refuse).is_err());
+    }
+    
+    #[test]
+    fn test_mixed_column_types_decoded_by_type() {
+        // information_schema names of a table with mixed column types
+        let columns = [
+            ("smallint", ColumnDecoding::SmallInt),
+            ("INTEGER", ColumnDecoding::Integer),
+            ("bigint", ColumnDecoding::BigInt),
+            ("real", ColumnDecoding::Real),
+            ("double precision", ColumnDecoding::Double),
+            ("numeric(10,2)", ColumnDecoding::Numeric),
+            ("boolean", ColumnDecoding::Boolean),
+            ("character varying(255)", ColumnDecoding::Text),
+            ("timestamp with time zone", ColumnDecoding::Timestamp),
+            ("timestamp without time zone", ColumnDecoding::Timestamp),
+            ("uuid", ColumnDecoding::Uuid),
+            ("jsonb", ColumnDecoding::Json),
+            ("bytea", ColumnDecoding::Binary),
+        ];
+        for (data_type, decoding) in columns {
+            assert_eq!(ColumnDecoding::for_data_type(data_type), Some(decoding), "{}", data_type);
+        }
+        
+        // Unsupported types are refused rather than captured as NULL
+        assert_eq!(ColumnDecoding::for_data_type("tsvector"), None);
+    }
+    
+    #[test]
+    fn test_numeric_rendered_like_postgres() {
+        fn numeric(weight: i16, sign: u16, dscale: u16, groups: &[u16]) -> Vec<u8> {
+            let mut raw = Vec::new();
+            raw.extend_from_slice(&(groups.len() as u16).to_be_bytes());
+            raw.extend_from_slice(&weight.to_be_bytes());
+            raw.extend_from_slice(&sign.to_be_bytes());
+            raw.extend_from_slice(&dscale.to_be_bytes());
+            for group in groups {
+                raw.extend_from_slice(&group.to_be_bytes());
+            }
+            raw
+        }
+        
+        assert_eq!(numeric_to_text(&numeric(0, 0, 0, &[])).unwrap(), "0");
+        assert_eq!(numeric_to_text(&numeric(0, 0, 2, &[])).unwrap(), "0.00");
+        assert_eq!(numeric_to_text(&numeric(1, 0, 2, &[1, 2345, 6700])).unwrap(), "12345.67");
+        assert_eq!(numeric_to_text(&numeric(0, 0x4000, 3, &[42, 5000])).unwrap(), "-42.500");
+        assert_eq!(numeric_to_text(&numeric(-1, 0, 2, &[500])).unwrap(), "0.05");
+        assert_eq!(numeric_to_text(&numeric(-2, 0, 6, &[1200])).unwrap(), "0.000012");
+        assert_eq!(numeric_to_text(&numeric(2, 0, 0, &[1])).unwrap(), "100000000");
+        assert_eq!(numeric_to_text(&numeric(0, 0xC000, 0, &[])).unwrap(), "NaN");
+        assert!(numeric_to_text(&[0, 1]).is_err());
+        assert!(numeric_to_text(&numeric(0, 0x1234, 0, &[])).is_err());
+    }
+    
+    #[test]
+    fn test_timestamps_decoded_as_epoch_millis() {
+        assert_eq!(epoch_millis(UNIX_EPOCH + Duration::from_micros(1_500_400)), 1500);
+        assert_eq!(epoch_millis(UNIX_EPOCH + Duration::from_micros(1_500_500)), 1501);
+        assert_eq!(epoch_millis(UNIX_EPOCH - Duration::from_micros(1_500_500)), -1501);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,452 @@
+//! Verification event publishing
+//!
+//! This module lets operators forward verification events (block commits,
+//! failed verifications, challenges) to external systems such as Kafka or
+//! NATS. Events are queued and delivered by a background thread, so a slow or
+//! failing sink never blocks the verification pipeline; when the queue is
+//! full, new events are dropped and counted.
+//!
+//! Network sinks are only compiled with the `event-publishing` feature.
+//! Without it, the publisher built from configuration is a no-op.
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use log::{debug, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::error::{ProxyError, Result};
+use crate::verification::contract::Challenge;
+
+/// Event emitted by the verification pipeline
+///
+/// Roots are `0x`-prefixed hex strings so the JSON form is readable by
+/// downstream consumers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VerificationEvent {
+    /// A block was committed
+    BlockCommitted {
+        /// Block number
+        block_number: u64,
+
+        /// State root of the block
+        state_root: String,
+
+        /// Number of transactions in the block
+        transaction_count: u64,
+
+        /// Whether the block contains transactions that skipped replay
+        degraded: bool,
+    },
+
+    /// A block's on-chain commitment was confirmed, finalizing the block
+    BlockFinalized {
+        /// Block number
+        block_number: u64,
+
+        /// State root of the block
+        state_root: String,
+
+        /// Submission attempts it took
+        attempts: u32,
+    },
+
+    /// A block's on-chain commitment kept failing and needs operator attention
+    CommitmentDeadLettered {
+        /// Block number
+        block_number: u64,
+
+        /// State root of the block
+        state_root: String,
+
+        /// Submission attempts made
+        attempts: u32,
+
+        /// Error of the last attempt
+        reason: String,
+    },
+
+    /// Replay of a transaction did not reproduce its captured state
+    VerificationFailed {
+        /// Transaction ID
+        transaction_id: u64,
+
+        /// Block the transaction belongs to
+        block_number: u64,
+
+        /// State root before the transaction
+        pre_state_root: Option<String>,
+
+        /// State root after the transaction
+        post_state_root: Option<String>,
+
+        /// Why verification failed
+        reason: String,
+    },
+
+    /// A challenge was submitted
+    ChallengeSubmitted {
+        /// Challenge ID
+        challenge_id: String,
+
+        /// Transaction ID that was challenged
+        transaction_id: u64,
+
+        /// Block number where the challenge was submitted
+        block_number: Option<u64>,
+
+        /// State root before the transaction
+        pre_state_root: String,
+
+        /// State root after the transaction
+        post_state_root: String,
+    },
+
+    /// A challenge reached a final status
+    ChallengeResolved {
+        /// Challenge ID
+        challenge_id: String,
+
+        /// Transaction ID that was challenged
+        transaction_id: u64,
+
+        /// Block number where the challenge was submitted
+        block_number: Option<u64>,
+
+        /// Final status of the challenge
+        status: String,
+    },
+}
+
+impl VerificationEvent {
+    /// Create a challenge-submitted event
+    pub fn challenge_submitted(challenge: &Challenge) -> Self {
+        VerificationEvent::ChallengeSubmitted {
+            challenge_id: challenge.id.clone(),
+            transaction_id: challenge.transaction_id,
+            block_number: challenge.block_number,
+            pre_state_root: hex_root(&challenge.pre_state_root),
+            post_state_root: hex_root(&challenge.post_state_root),
+        }
+    }
+
+    /// Create a challenge-resolved event
+    pub fn challenge_resolved(challenge: &Challenge) -> Self {
+        VerificationEvent::ChallengeResolved {
+            challenge_id: challenge.id.clone(),
+            transaction_id: challenge.transaction_id,
+            block_number: challenge.block_number,
+            status: format!("{:?}", challenge.status),
+        }
+    }
+
+    /// Name of the event type, as used in the JSON `type` field
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            VerificationEvent::BlockCommitted { .. } => "block_committed",
+            VerificationEvent::BlockFinalized { .. } => "block_finalized",
+            VerificationEvent::CommitmentDeadLettered { .. } => "commitment_dead_lettered",
+            VerificationEvent::VerificationFailed { .. } => "verification_failed",
+            VerificationEvent::ChallengeSubmitted { .. } => "challenge_submitted",
+            VerificationEvent::ChallengeResolved { .. } => "challenge_resolved",
+        }
+    }
+}
+
+/// Format a root as a `0x`-prefixed hex string
+pub fn hex_root(root: &[u8; 32]) -> String {
+    format!("0x{}", hex::encode(root))
+}
+
+/// Destination for verification events
+///
+/// `send` runs on the publisher's background thread, so it may block.
+pub trait EventSink: Send + Sync + Debug {
+    /// Deliver one event
+    fn send(&self, event: &VerificationEvent) -> Result<()>;
+}
+
+/// Sink that keeps events in memory
+#[derive(Debug, Default)]
+pub struct InMemoryEventSink {
+    events: Mutex<Vec<VerificationEvent>>,
+}
+
+impl InMemoryEventSink {
+    /// Create an empty in-memory sink
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the events received so far
+    pub fn events(&self) -> Vec<VerificationEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl EventSink for InMemoryEventSink {
+    fn send(&self, event: &VerificationEvent) -> Result<()> {
+        self.events.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+}
+
+/// Sink that POSTs each event as JSON to an HTTP endpoint
+///
+/// Works with HTTP bridges such as the Kafka REST proxy or a NATS HTTP gateway.
+#[cfg(feature = "event-publishing")]
+#[derive(Debug)]
+pub struct WebhookEventSink {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(feature = "event-publishing")]
+impl WebhookEventSink {
+    /// Create a sink posting to `url`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+}
+
+#[cfg(feature = "event-publishing")]
+impl EventSink for WebhookEventSink {
+    fn send(&self, event: &VerificationEvent) -> Result<()> {
+        self.client.post(&self.url)
+            .json(event)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ProxyError::Connection(format!("Failed to publish {} event: {}", event.event_type(), e)))?;
+        Ok(())
+    }
+}
+
+/// Where to publish verification events
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EventSinkConfig {
+    /// Don't publish events
+    #[default]
+    None,
+
+    /// POST events as JSON to an HTTP endpoint (requires `event-publishing`)
+    Webhook {
+        /// Endpoint URL
+        url: String,
+    },
+}
+
+/// Configuration for the event publisher
+#[derive(Debug, Clone)]
+pub struct EventPublisherConfig {
+    /// Where to publish events
+    pub sink: EventSinkConfig,
+
+    /// Maximum number of events waiting for delivery before new ones are dropped
+    pub queue_capacity: usize,
+}
+
+impl Default for EventPublisherConfig {
+    fn default() -> Self {
+        Self {
+            sink: EventSinkConfig::None,
+            queue_capacity: 1024,
+        }
+    }
+}
+
+/// Publishes verification events to a sink without blocking the caller
+#[derive(Debug)]
+pub struct EventPublisher {
+    /// Queue feeding the delivery thread, `None` for a no-op publisher
+    sender: Option<SyncSender<VerificationEvent>>,
+
+    /// Events dropped because the queue was full or the sink went away
+    dropped: AtomicU64,
+}
+
+impl EventPublisher {
+    /// Create a publisher that discards every event
+    pub fn noop() -> Self {
+        Self {
+            sender: None,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Create a publisher delivering to `sink` from a background thread
+    pub fn new(sink: Arc<dyn EventSink>, queue_capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<VerificationEvent>(queue_capacity);
+
+        // The thread exits once the publisher (and so the sender) is dropped
+        let spawned = thread::Builder::new()
+            .name("verification-events".to_string())
+            .spawn(move || {
+                for event in receiver {
+                    if let Err(e) = sink.send(&event) {
+                        warn!("Failed to publish {} event: {}", event.event_type(), e);
+                    }
+                }
+            });
+
+        match spawned {
+            Ok(_) => Self {
+                sender: Some(sender),
+                dropped: AtomicU64::new(0),
+            },
+            Err(e) => {
+                warn!("Failed to start event publisher thread, events will be discarded: {}", e);
+                Self::noop()
+            }
+        }
+    }
+
+    /// Create a publisher from configuration
+    pub fn from_config(config: &EventPublisherConfig) -> Result<Self> {
+        match &config.sink {
+            EventSinkConfig::None => Ok(Self::noop()),
+            #[cfg(feature = "event-publishing")]
+            EventSinkConfig::Webhook { url } => {
+                if url.is_empty() {
+                    return Err(ProxyError::Config("Event webhook URL must not be empty".to_string()));
+                }
+                Ok(Self::new(Arc::new(WebhookEventSink::new(url.clone())), config.queue_capacity))
+            }
+            #[cfg(not(feature = "event-publishing"))]
+            EventSinkConfig::Webhook { .. } => {
+                warn!("Event sink configured but the proxy was built without the `event-publishing` feature; events will be discarded");
+                Ok(Self::noop())
+            }
+        }
+    }
+
+    /// Queue an event for delivery
+    ///
+    /// Never blocks: if the queue is full the event is dropped.
+    pub fn publish(&self, event: VerificationEvent) {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return,
+        };
+
+        match sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(event)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!("Event queue full, dropping {} event", event.event_type());
+            }
+            Err(TrySendError::Disconnected(event)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                debug!("Event publisher stopped, dropping {} event", event.event_type());
+            }
+        }
+    }
+
+    /// Whether events are delivered anywhere
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Number of events dropped so far
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for EventPublisher {
+    fn default() -> Self {
+        Self::noop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Wait for the background thread to deliver `count` events
+    fn wait_for_events(sink: &InMemoryEventSink, count: usize) -> Vec<VerificationEvent> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sink.events().len() < count && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        sink.events()
+    }
+
+    #[test]
+    fn test_in_memory_sink_receives_events() {
+        let sink = Arc::new(InMemoryEventSink::new());
+        let publisher = EventPublisher::new(sink.clone(), 16);
+        assert!(publisher.is_enabled());
+
+        let committed = VerificationEvent::BlockCommitted {
+            block_number: 7,
+            state_root: hex_root(&[1u8; 32]),
+            transaction_count: 3,
+            degraded: false,
+        };
+        let failed = VerificationEvent::VerificationFailed {
+            transaction_id: 42,
+            block_number: 7,
+            pre_state_root: Some(hex_root(&[2u8; 32])),
+            post_state_root: None,
+            reason: "State mismatch in table users".to_string(),
+        };
+        publisher.publish(committed.clone());
+        publisher.publish(failed.clone());
+
+        assert_eq!(wait_for_events(&sink, 2), vec![committed, failed]);
+        assert_eq!(publisher.dropped_events(), 0);
+    }
+
+    #[test]
+    fn test_failing_sink_does_not_block() {
+        #[derive(Debug)]
+        struct FailingSink;
+
+        impl EventSink for FailingSink {
+            fn send(&self, _event: &VerificationEvent) -> Result<()> {
+                Err(ProxyError::Connection("sink unavailable".to_string()))
+            }
+        }
+
+        // Publishing far more events than the queue holds returns immediately
+        let publisher = EventPublisher::new(Arc::new(FailingSink), 1);
+        for block_number in 0..100 {
+            publisher.publish(VerificationEvent::BlockCommitted {
+                block_number,
+                state_root: hex_root(&[0u8; 32]),
+                transaction_count: 0,
+                degraded: false,
+            });
+        }
+
+        // The no-op default accepts events without delivering them
+        let noop = EventPublisher::from_config(&EventPublisherConfig::default()).unwrap();
+        assert!(!noop.is_enabled());
+        noop.publish(VerificationEvent::BlockCommitted {
+            block_number: 0,
+            state_root: hex_root(&[0u8; 32]),
+            transaction_count: 0,
+            degraded: false,
+        });
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = VerificationEvent::BlockCommitted {
+            block_number: 1,
+            state_root: hex_root(&[0xabu8; 32]),
+            transaction_count: 2,
+            degraded: true,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], event.event_type());
+        assert_eq!(json["block_number"], 1);
+    }
+}
@@ -71,6 +71,12 @@ pub struct MerkleProof {
 
 /// A secure Merkle tree
 /// Uses domain separation and salting to prevent second-preimage attacks
+///
+/// A level with an odd number of nodes pairs its last node with itself: the
+/// parent is the node hashed twice under the node domain, never the node
+/// promoted unchanged. Proofs carry the duplicate as the sibling, and
+/// verification only accepts a duplicate where the tree's leaf count puts
+/// one, so a padded node can't be passed off as a leaf of its own.
 #[derive(Debug, Clone)]
 pub struct MerkleTree {
     /// Leaves of the tree
@@ -247,18 +253,18 @@ impl MerkleTree {
             
             let sibling_pos = NodePosition { level, index: sibling_index };
             
-            // If the sibling exists, use it; otherwise use a dummy hash
-            let sibling_hash = if sibling_index < self.node_count_at_level(level) {
-                *self.nodes.get(&sibling_pos).ok_or_else(|| {
-                    ProxyError::Verification(format!(
-                        "Missing sibling at level {} index {}", level, sibling_index
-                    ))
-                })?
+            // If the sibling exists, use it; otherwise the node is the last of
+            // an odd-sized level and is paired with itself, as in build()
+            let pos = if sibling_index < self.node_count_at_level(level) {
+                sibling_pos
             } else {
-                // This shouldn't happen with a properly built tree
-                // But just in case, use a dummy hash
-                [0; 32]
+                NodePosition { level, index: current_index }
             };
+            let sibling_hash = *self.nodes.get(&pos).ok_or_else(|| {
+                ProxyError::Verification(format!(
+                    "Missing node at level {} index {}", pos.level, pos.index
+                ))
+            })?;
             
             path.push(ProofNode {
                 sibling_hash,
@@ -283,13 +289,40 @@ impl MerkleTree {
         })
     }
     
-    /// Verify a Merkle proof
+    /// Verify a Merkle proof against this tree
+    ///
+    /// The proof must follow the path its leaf index takes through a tree of
+    /// this tree's size and end at this tree's root. In particular a sibling
+    /// beyond the end of a level must duplicate the node it pads.
     pub fn verify_proof(&self, proof: &MerkleProof) -> Result<bool> {
+        let root = self.root_hash().ok_or_else(|| {
+            ProxyError::Verification("Tree not built".to_string())
+        })?;
+        
+        if proof.root_hash != root
+            || proof.leaf_count != self.leaves.len() as u64
+            || proof.leaf_index >= proof.leaf_count
+            || proof.path.len() != self.height as usize
+        {
+            return Ok(false);
+        }
+        
         // Start with the leaf hash
         let mut current_hash = proof.leaf_hash;
+        let mut current_index = proof.leaf_index;
         
         // Apply each step in the proof path
-        for node in &proof.path {
+        for (level, node) in proof.path.iter().enumerate() {
+            if node.is_left != (current_index % 2 == 1) {
+                return Ok(false);
+            }
+            
+            let sibling_index = if node.is_left { current_index - 1 } else { current_index + 1 };
+            if sibling_index >= self.node_count_at_level(level as u8) && node.sibling_hash != current_hash {
+                return Ok(false);
+            }
+            current_index /= 2;
+            
             if node.is_left {
                 // Sibling is on the left
                 current_hash = self.hash_node(&node.sibling_hash, &current_hash);
@@ -821,6 +854,56 @@ mod tests {
         assert!(tree.verify_proof(&proof).unwrap());
     }
     
+    #[test]
+    fn test_odd_levels_pad_by_duplication() {
+        for leaf_count in 1..=9 {
+            let mut tree = MerkleTree::with_salt([1u8; 32]);
+            for i in 0..leaf_count {
+                tree.add_leaf(format!("test data {}", i).into_bytes());
+            }
+            assert!(tree.build().is_ok());
+            
+            // Every leaf verifies, including the padded last ones
+            for index in 0..leaf_count {
+                let proof = tree.generate_proof(index).unwrap();
+                assert!(tree.verify_proof(&proof).unwrap());
+            }
+        }
+        
+        // With three leaves, the root is H(H(a, b), H(c, c))
+        let mut tree = MerkleTree::with_salt([1u8; 32]);
+        for data in [b"a", b"b", b"c"] {
+            tree.add_leaf(data.to_vec());
+        }
+        assert!(tree.build().is_ok());
+        let root = tree.root_hash().unwrap();
+        let [a, b, c] = [0, 1, 2].map(|index| tree.get_leaf(index).unwrap().hash);
+        let ab = tree.hash_node(&a, &b);
+        assert_eq!(tree.hash_node(&ab, &tree.hash_node(&c, &c)), root);
+        
+        // Second-preimage attempt: the duplicate of c claimed as a fourth leaf.
+        // The path hashes to the real root, but no such leaf exists
+        let forged = MerkleProof {
+            leaf_hash: c,
+            path: vec![
+                ProofNode { sibling_hash: c, is_left: true },
+                ProofNode { sibling_hash: ab, is_left: true },
+            ],
+            root_hash: root,
+            leaf_index: 3,
+            leaf_count: 4,
+            verified: false,
+        };
+        assert!(!tree.verify_proof(&forged).unwrap());
+        assert!(!tree.verify_proof(&MerkleProof { leaf_count: 3, ..forged.clone() }).unwrap());
+        
+        // Nor can the padding position hold anything but the duplicate
+        let mut proof = tree.generate_proof(2).unwrap();
+        assert_eq!(proof.path[0].sibling_hash, c);
+        proof.path[0].sibling_hash = a;
+        assert!(!tree.verify_proof(&proof).unwrap());
+    }
+    
     #[test]
     fn test_domain_separation() {
         // Create a Merkle tree with a specific salt
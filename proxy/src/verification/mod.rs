@@ -9,19 +9,27 @@ pub use merkle::{MerkleTree, SparseMerkleTree, MerkleProof, MerkleLeaf, ProofNod
 
 // Export the state capture module
 pub mod state;
-pub use state::{StateCaptureManager, TableState, DatabaseState, TableSchema, Row, Value, CoreDatabaseState as BlockState};
+pub use state::{StateCaptureManager, CaptureContext, PostgresVersions, ReconciliationReport, TableDivergence, TableState, DatabaseState, TableSchema, Row, Value, CoreDatabaseState as BlockState};
 
 // Export the verification environment module
 pub mod environment;
-pub use environment::{VerificationEnvironment, VerificationEnvironmentConfig, VerificationExecutionResult};
+pub use environment::{VerificationEnvironment, VerificationEnvironmentConfig, VerificationExecutionResult, ReplayStatement, PoolStatus, VersionMismatchPolicy, DiskGuardConfig, LowDiskPolicy};
 
 // Export the EigenLayer integration module
 pub mod contract;
 pub use contract::{ContractManager, ContractConfig, StateCommitment, Challenge, ChallengeStatus};
 
+// Export the durable commitment retry queue
+pub mod commitment_queue;
+pub use commitment_queue::{CommitmentQueue, CommitmentRetryConfig, CommitmentStatus, QueuedCommitment, AttemptOutcome};
+
+// Export the verification event publishing module
+pub mod events;
+pub use events::{VerificationEvent, EventSink, EventPublisher, EventPublisherConfig, EventSinkConfig, InMemoryEventSink};
+
 // Export the deterministic module
 pub mod deterministic;
-pub use deterministic::{DeterministicTimestamp, DeterministicRandom, DeterministicSqlFunctions};
+pub use deterministic::{DeterministicTimestamp, DeterministicRandom, DeterministicSqlFunctions, DeterministicClock, WallClock, LogicalClock, ClockConfig};
 
 // Export the verification service client
 pub mod client;
@@ -4,13 +4,18 @@
 //! for verification purposes, including table snapshots and incremental updates.
 
 use crate::error::{ProxyError, Result};
-use verifiable_db_core::models::{self as core_models, TableSchema, TableState, Row, BlockState as CoreDatabaseState, BlockHeader, BlockMetadata, Value, ColumnType};
+use crate::verification::environment::format_postgres_version;
+use crate::transaction::{WalRecord, WalRecordType};
+use verifiable_db_core::models::{self as core_models, TableSchema, ColumnDefinition, TableState, Row, BlockState as CoreDatabaseState, BlockHeader, BlockMetadata, Value, ColumnType, TriggerDefinition, PartitionScheme, PartitionStrategy, PartitionDefinition};
 use verifiable_db_core::merkle::{self, SecureMerkleTree}; // Import SecureMerkleTree
-use chrono::Utc;
+use verifiable_db_core::schema::{SchemaVersion, SchemaMigration, SchemaAuditLog, SchemaAuditEntry, MigrationDirection, MigrationOperation, DdlStatement, ColumnDefinitionDdl, TableDefinition};
+use chrono::{TimeZone, Utc};
 use log::{debug, warn, info, error};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use serde::{Serialize, Deserialize};
+use rand::Rng;
 use hex;
+use futures_util::stream::{self, StreamExt, TryStreamExt};
 
 // Helper struct to track changes within a single table for an in-progress transaction
 #[derive(Debug, Default, Clone)]
@@ -42,6 +47,126 @@ pub struct StateCaptureManager {
     schema_cache: Arc<Mutex<HashMap<String, TableSchema>>>, 
     /// Legacy transaction counter
     transaction_counter: Mutex<u64>,
+    /// Maximum number of tables freshly captured per block (`None` = no cap)
+    max_tables_per_block: Option<usize>,
+    /// Tables with changes not yet reflected in a block root, with the block they became dirty in
+    dirty_tables: RwLock<HashMap<String, u64>>,
+    /// Block number each table was last freshly captured in
+    last_captured_block: RwLock<HashMap<String, u64>>,
+    /// Whether WAL changes update table Merkle trees leaf by leaf instead of rebuilding them
+    incremental_wal: bool,
+    /// Maximum number of worker threads hashing tables concurrently (1 = sequential)
+    hash_parallelism: usize,
+    /// Whether tables created during commits use sparse, row-id keyed Merkle trees
+    sparse_table_trees: bool,
+    /// Partitioned parent of each known partition, keyed by partition name
+    partition_parents: RwLock<HashMap<String, String>>,
+    /// Whether block commits check that no foreign key value is dangling
+    check_referential_integrity: bool,
+    /// Whether database captures read every table from one exported snapshot
+    snapshot_isolation: bool,
+    /// Role database captures run as (`None` = the connection's own role)
+    capture_role: Option<String>,
+    /// Maximum number of tables a database capture reads concurrently (1 = sequential)
+    capture_concurrency: usize,
+    /// Server versions of the live and verification databases, recorded in block metadata
+    postgres_versions: RwLock<Option<PostgresVersions>>,
+    /// Whether applied schema changes are recorded in a chained audit log
+    schema_audit_enabled: bool,
+    /// Current schema version and its audit log, started at genesis
+    schema_audit: Mutex<Option<SchemaAudit>>,
+    /// Fraction of table roots recomputed from rows before a block is committed
+    root_validation_rate: f64,
+}
+
+/// Schema history tracked by the schema audit log
+#[derive(Debug)]
+struct SchemaAudit {
+    /// Latest schema version
+    schema: SchemaVersion,
+    /// Chained log of the migrations applied since genesis
+    log: SchemaAuditLog,
+    /// Number of log entries already committed in a block
+    committed: usize,
+}
+
+/// PostgreSQL server versions (`server_version_num`) of the live and verification databases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostgresVersions {
+    /// Version of the live backend
+    pub live: u32,
+    /// Version of the verification database replay runs on
+    pub verifier: u32,
+}
+
+/// Role and row-level security context a database capture ran under
+///
+/// Captures run with `row_security` off, so they see every row of every
+/// table: a table whose policies would filter rows for the capture role
+/// fails the capture instead of being captured partially. Proofs therefore
+/// attest to full, unfiltered tables, including rows a client's own
+/// policies hide from it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureContext {
+    /// Role the capture ran as
+    pub role: String,
+    /// Whether the role bypasses row-level security (superuser or `BYPASSRLS`)
+    pub bypass_rls: bool,
+    /// Whether row-level security was enabled for the capture; always false
+    pub row_security: bool,
+    /// Captured tables with row-level security enabled, whose policies were not applied
+    pub rls_tables: Vec<String>,
+}
+
+/// A foreign key value in a committed state that references no existing row
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForeignKeyViolation {
+    /// Table holding the foreign key
+    pub table: String,
+    /// Row whose foreign key value is dangling
+    pub row_id: String,
+    /// Foreign key columns
+    pub columns: Vec<String>,
+    /// Table the foreign key references
+    pub referenced_table: String,
+}
+
+/// Result of comparing the latest committed block to a fresh capture of the database
+///
+/// A divergent table that has no changes pending capture means the database
+/// was changed outside the verified path, or the chain committed a wrong root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    /// Latest committed block
+    pub block_number: u64,
+    /// State root of the latest committed block
+    pub committed_root: [u8; 32],
+    /// State root over the freshly captured tables
+    pub current_root: [u8; 32],
+    /// Tables whose fresh root differs from the committed one, by name
+    pub divergent_tables: Vec<TableDivergence>,
+}
+
+impl ReconciliationReport {
+    /// Whether the committed chain matches the database
+    pub fn is_consistent(&self) -> bool {
+        self.committed_root == self.current_root && self.divergent_tables.is_empty()
+    }
+}
+
+/// A table whose fresh capture doesn't match its committed root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableDivergence {
+    /// Table name
+    pub table: String,
+    /// Root committed in the latest block (`None` = not committed or empty)
+    pub committed_root: Option<[u8; 32]>,
+    /// Root of the fresh capture (`None` = table missing or empty)
+    pub current_root: Option<[u8; 32]>,
+    /// Whether the table has tracked changes not yet committed, which explain a divergence
+    pub pending_changes: bool,
+    /// Rows that differ from the tracked state: changed, missing or unexpected, sorted
+    pub rows: Vec<String>,
 }
 
 impl StateCaptureManager {
@@ -54,9 +179,155 @@ impl StateCaptureManager {
             in_progress_state: RwLock::new(None),
             schema_cache: Arc::new(Mutex::new(HashMap::new())), 
             transaction_counter: Mutex::new(0),
+            max_tables_per_block: None,
+            dirty_tables: RwLock::new(HashMap::new()),
+            last_captured_block: RwLock::new(HashMap::new()),
+            incremental_wal: false,
+            hash_parallelism: 1,
+            sparse_table_trees: false,
+            partition_parents: RwLock::new(HashMap::new()),
+            check_referential_integrity: false,
+            snapshot_isolation: false,
+            capture_role: None,
+            capture_concurrency: 1,
+            postgres_versions: RwLock::new(None),
+            schema_audit_enabled: false,
+            schema_audit: Mutex::new(None),
+            root_validation_rate: 0.0,
+        }
+    }
+
+    /// Create a state capture manager that freshly captures at most
+    /// `max_tables_per_block` tables per block
+    ///
+    /// Every block's state root still covers all tables: tables not captured
+    /// in a block contribute the root from their most recent capture.
+    /// Modified tables are captured first, oldest change first, so a change
+    /// is reflected in the state root within `ceil(dirty tables / cap)`
+    /// blocks. Leftover capacity re-verifies unchanged tables, least recently
+    /// captured first. Tables without a cached root are always captured.
+    pub fn with_max_tables_per_block(max_tables_per_block: Option<usize>) -> Self {
+        Self {
+            max_tables_per_block,
+            ..Self::new()
         }
     }
 
+    /// Apply WAL row changes to the cached table states incrementally
+    ///
+    /// Only the Merkle leaves of changed rows (and rows sorted after an
+    /// inserted or deleted one) are recomputed, so commit cost follows the
+    /// number of changed rows rather than table size. The resulting roots are
+    /// identical to a full recapture, which still runs when an unchanged
+    /// table is re-verified.
+    pub fn with_incremental_wal(mut self, enabled: bool) -> Self {
+        self.incremental_wal = enabled;
+        self
+    }
+
+    /// Rebuild the Merkle trees of captured tables on up to `workers` threads
+    ///
+    /// Each table's tree is independent, so they are hashed concurrently;
+    /// the database root is still assembled from table roots in sorted name
+    /// order and is identical to the sequential result. Values below 1 are
+    /// treated as 1.
+    pub fn with_hash_parallelism(mut self, workers: usize) -> Self {
+        self.hash_parallelism = workers.max(1);
+        self
+    }
+
+    /// Read up to `tables` tables concurrently during database captures
+    ///
+    /// Table queries are pipelined on the capturing connection, so they all
+    /// run under its capture role and snapshot. Captured tables are keyed by
+    /// name and the database root is assembled in sorted name order, so it
+    /// doesn't depend on which table finishes first. Values below 1 are
+    /// treated as 1.
+    pub fn with_capture_concurrency(mut self, tables: usize) -> Self {
+        self.capture_concurrency = tables.max(1);
+        self
+    }
+
+    /// Commit tables first seen after genesis with sparse Merkle trees keyed by row id
+    ///
+    /// Row proofs of these tables are addressed by key and keep their leaf
+    /// position across inserts and deletes. Genesis tables keep the tree mode
+    /// their states were built with, since their roots are already fixed.
+    pub fn with_sparse_table_trees(mut self, enabled: bool) -> Self {
+        self.sparse_table_trees = enabled;
+        self
+    }
+
+    /// Check referential integrity of the committed state at every block commit
+    ///
+    /// Every foreign key value (with no NULL column) must match the referenced
+    /// columns of some row of the referenced table. Dangling references are
+    /// recorded under `fk_violations` in the block's additional data rather
+    /// than failing the commit, so corruption that slipped past
+    /// per-transaction verification is surfaced. Foreign keys to tables
+    /// outside the captured state are not checked.
+    pub fn with_referential_integrity_check(mut self, enabled: bool) -> Self {
+        self.check_referential_integrity = enabled;
+        self
+    }
+
+    /// Capture all tables of a block within a single exported snapshot
+    ///
+    /// The capture runs in one repeatable-read transaction, so writes that
+    /// commit while tables are being read don't leak into some tables and
+    /// not others: the block is a point-in-time state. The exported snapshot
+    /// id is recorded under `snapshot_id` in the block's additional data.
+    pub fn with_snapshot_isolation(mut self, enabled: bool) -> Self {
+        self.snapshot_isolation = enabled;
+        self
+    }
+
+    /// Run database captures as `role`
+    ///
+    /// The role must be a superuser or have `BYPASSRLS` to capture tables
+    /// with row-level security enabled; see [`CaptureContext`]. `None` keeps
+    /// the connection's own role.
+    pub fn with_capture_role(mut self, role: Option<String>) -> Self {
+        self.capture_role = role;
+        self
+    }
+
+    /// Record applied schema changes in a chained, tamper-evident audit log
+    ///
+    /// The log starts from the schema captured at genesis. Each schema
+    /// change becomes a migration entry linking the schema checksums before
+    /// and after it to the previous entry; see
+    /// [`record_schema_change`](Self::record_schema_change). Entries are
+    /// committed with the next block, under `schema_audit` in its additional
+    /// data, so the schema history is anchored like the data history.
+    pub fn with_schema_audit_log(mut self, enabled: bool) -> Self {
+        self.schema_audit_enabled = enabled;
+        self
+    }
+
+    /// Recompute a sample of table roots from their rows before each block is committed
+    ///
+    /// Each table whose committed root should reflect its current rows is
+    /// checked with probability `sample_rate` (0 disables the check, 1 checks
+    /// every table). Tables with changes deferred to a later block are
+    /// skipped. A mismatch aborts the commit, so a capture bug can't put a
+    /// wrong state root on-chain; the mismatched tables are marked modified
+    /// and freshly captured by the next commit.
+    pub fn with_root_validation(mut self, sample_rate: f64) -> Self {
+        self.root_validation_rate = sample_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Record the server versions of the live and verification databases in later blocks' metadata
+    ///
+    /// The live version is the block's `postgres_version`; the verification
+    /// database's is recorded under `verifier_postgres_version` in its
+    /// additional data.
+    pub fn set_postgres_versions(&self, versions: PostgresVersions) -> Result<()> {
+        *self.postgres_versions.write().map_err(poison_err)? = Some(versions);
+        Ok(())
+    }
+
     /// Initializes the state manager with a genesis block.
     /// `genesis_state` contains the header and roots.
     /// `initial_table_states` contains the actual TableState objects for the genesis block.
@@ -91,14 +362,28 @@ impl StateCaptureManager {
                 None => return Err(ProxyError::Verification(format!("Genesis state has root for table '{}' but no corresponding TableState object was provided", table_name)))
             }
         }
-        // Verify the number of roots matches the number of provided states
-        if genesis_state.table_state_roots.len() != initial_table_states.len() {
+        // Verify the number of roots matches the number of provided states (empty tables have no root)
+        let rooted_states = initial_table_states.values().filter(|ts| ts.root_hash.is_some()).count();
+        if genesis_state.table_state_roots.len() != rooted_states {
              return Err(ProxyError::Verification(format!(
-                 "Mismatch between number of roots in genesis BlockState ({}) and number of provided TableStates ({})", 
-                 genesis_state.table_state_roots.len(), initial_table_states.len()
+                 "Mismatch between number of roots in genesis BlockState ({}) and number of provided non-empty TableStates ({})", 
+                 genesis_state.table_state_roots.len(), rooted_states
             )));
         }
 
+        for table_state in initial_table_states.values() {
+            self.register_partitions(&table_state.schema);
+        }
+
+        if self.schema_audit_enabled {
+            let tables = initial_table_states.iter()
+                .map(|(name, state)| (name.clone(), state.schema.clone()))
+                .collect();
+            let schema = SchemaVersion::create_initial(GENESIS_OPERATOR_ID.to_string(), "Genesis schema".to_string(), tables);
+            let log = SchemaAuditLog::new(&schema);
+            *self.schema_audit.lock().map_err(poison_err)? = Some(SchemaAudit { schema, log, committed: 0 });
+        }
+
         // Store genesis block (roots only) in history
         history_lock.insert(genesis_block_number, genesis_state);
         // Store full initial table states in live state map
@@ -110,6 +395,302 @@ impl StateCaptureManager {
         Ok(())
     }
 
+    /// Initializes the state manager with a genesis block built over `initial_table_states`.
+    ///
+    /// The genesis block is block 0 with an all-zero previous hash, no
+    /// transactions and a fixed timestamp, so its header (and hash) depends
+    /// only on the initial tables. Returns the committed genesis block.
+    pub fn initialize_genesis(&self, mut initial_table_states: HashMap<String, TableState>) -> Result<CoreDatabaseState> {
+        let genesis_state = build_genesis_state(&mut initial_table_states, None, None, None);
+        self.initialize_with_genesis_state(genesis_state.clone(), initial_table_states)?;
+        Ok(genesis_state)
+    }
+
+    /// Captures every user table of the database and commits it as the genesis block.
+    ///
+    /// Intended to run once at startup, before any changes are tracked from WAL.
+    pub async fn capture_genesis(&self, client: &tokio_postgres::Client) -> Result<CoreDatabaseState> {
+        let (mut initial_table_states, context, snapshot_id) = self.capture_full_database(client).await?;
+        let versions = *self.postgres_versions.read().map_err(poison_err)?;
+        let genesis_state = build_genesis_state(&mut initial_table_states, snapshot_id.as_deref(), Some(&context), versions);
+        self.initialize_with_genesis_state(genesis_state.clone(), initial_table_states)?;
+        info!("Captured genesis state over {} tables, state root: {}", genesis_state.table_state_roots.len(), hex::encode(genesis_state.header.state_root));
+        Ok(genesis_state)
+    }
+
+    /// Compare the latest committed block to a fresh full capture of the database
+    ///
+    /// This is the manual audit: it recaptures every table the way genesis
+    /// does and reports the tables whose roots differ from the committed
+    /// ones, down to the differing rows. See [`reconcile_tables`](Self::reconcile_tables).
+    pub async fn reconcile(&self, client: &tokio_postgres::Client) -> Result<ReconciliationReport> {
+        let (captured, _, _) = self.capture_full_database(client).await?;
+        self.reconcile_tables(captured)
+    }
+
+    /// Compare the latest committed block to freshly captured tables
+    ///
+    /// Each table is hashed with the tree mode it is committed with. Rows of
+    /// a divergent table are compared against the rows tracked for it, which
+    /// include changes not yet committed; `pending_changes` flags those tables.
+    pub fn reconcile_tables(&self, mut captured: HashMap<String, TableState>) -> Result<ReconciliationReport> {
+        let live_states = self.live_table_states.read().map_err(poison_err)?;
+        let dirty_tables = self.dirty_tables.read().map_err(poison_err)?;
+        let block_number = *self.latest_committed_block_number.read().map_err(poison_err)?;
+        let history = self.state_history.read().map_err(poison_err)?;
+        let block = history.get(&block_number)
+            .ok_or_else(|| ProxyError::Verification(format!("No committed block {} to reconcile against", block_number)))?;
+
+        for (name, table_state) in captured.iter_mut() {
+            if let Some(live) = live_states.get(name) {
+                table_state.tree_mode = live.tree_mode;
+            }
+            table_state.rebuild_merkle_tree();
+        }
+
+        let current_roots: HashMap<String, [u8; 32]> = captured.iter()
+            .filter_map(|(name, state)| state.root_hash.map(|root| (name.clone(), root)))
+            .collect();
+        let mut names: Vec<&String> = block.table_state_roots.keys().chain(current_roots.keys()).collect();
+        names.sort();
+        names.dedup();
+
+        let mut divergent_tables = Vec::new();
+        for name in names {
+            let committed_root = block.table_state_roots.get(name).copied();
+            let current_root = current_roots.get(name).copied();
+            if committed_root == current_root {
+                continue;
+            }
+            let rows = diff_rows(live_states.get(name), captured.get(name));
+            warn!("Table '{}' diverges from block {}: {} rows differ", name, block_number, rows.len());
+            divergent_tables.push(TableDivergence {
+                table: name.clone(),
+                committed_root,
+                current_root,
+                pending_changes: dirty_tables.contains_key(name),
+                rows,
+            });
+        }
+
+        Ok(ReconciliationReport {
+            block_number,
+            committed_root: block.header.state_root,
+            current_root: aggregate_state_root(&current_roots),
+            divergent_tables,
+        })
+    }
+
+    /// Capture every user table within the configured capture context and snapshot
+    async fn capture_full_database(&self, client: &tokio_postgres::Client) -> Result<(HashMap<String, TableState>, CaptureContext, Option<String>)> {
+        let context = self.begin_capture_context(client).await?;
+        let snapshot_id = if self.snapshot_isolation {
+            Some(Self::begin_capture_snapshot(client).await?)
+        } else {
+            None
+        };
+        let captured = self.capture_database(client).await;
+        if snapshot_id.is_some() {
+            Self::end_capture_snapshot(client, captured.is_ok()).await?;
+        }
+        Self::end_capture_context(client).await?;
+        Ok((captured?, context, snapshot_id))
+    }
+
+    /// Capture every user table of the database, and its large objects
+    async fn capture_database(&self, client: &tokio_postgres::Client) -> Result<HashMap<String, TableState>> {
+        let rows = client.query(GENESIS_TABLES_QUERY, &[])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to list tables for genesis: {}", e)))?;
+
+        let table_names: Vec<String> = rows.iter().map(|row| row.get("table_name")).collect();
+        let mut table_states = capture_tables(table_names, self.capture_concurrency, |table_name| async move {
+            self.capture_table(client, &table_name).await
+        }).await?;
+
+        self.cache_schema(large_object_schema());
+        table_states.insert(LARGE_OBJECT_TABLE.to_string(), Self::capture_large_objects(client).await?);
+        Ok(table_states)
+    }
+
+    /// Capture one table's schema, triggers, partitioning and rows, caching its schema
+    pub async fn capture_table(&self, client: &tokio_postgres::Client, table_name: &str) -> Result<TableState> {
+        let mut schema = Self::capture_table_schema(client, table_name).await?
+            .with_triggers(Self::capture_triggers(client, table_name).await?);
+        if let Some(partitioning) = Self::capture_partitioning(client, table_name).await? {
+            schema = schema.with_partitioning(partitioning);
+        }
+        self.cache_schema(schema.clone());
+        Self::capture_table_rows(client, schema).await
+    }
+
+    /// Switch `client` to the capture role with row-level security off
+    ///
+    /// Returns the context captures on `client` run under until
+    /// `end_capture_context`. With `row_security` off, reading a table whose
+    /// policies apply to the role is an error rather than a filtered result.
+    pub async fn begin_capture_context(&self, client: &tokio_postgres::Client) -> Result<CaptureContext> {
+        if let Some(role) = &self.capture_role {
+            client.batch_execute(&format!("SET ROLE {}", quote_ident(role)))
+                .await
+                .map_err(|e| ProxyError::Database(format!("Failed to set capture role {}: {}", role, e)))?;
+        }
+        client.batch_execute("SET row_security = off")
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to disable row security for capture: {}", e)))?;
+
+        let role = client.query_one(CAPTURE_ROLE_QUERY, &[])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to query capture role: {}", e)))?;
+        let rls_tables = client.query(RLS_TABLES_QUERY, &[])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to list tables with row security: {}", e)))?
+            .iter()
+            .map(|row| row.get("table_name"))
+            .collect();
+
+        let context = CaptureContext {
+            role: role.get("role"),
+            bypass_rls: role.get("bypass_rls"),
+            row_security: false,
+            rls_tables,
+        };
+        if !context.bypass_rls && !context.rls_tables.is_empty() {
+            warn!("Capture role {} doesn't bypass row-level security; capturing tables {:?} will fail unless it owns them",
+                context.role, context.rls_tables);
+        }
+        Ok(context)
+    }
+
+    /// Restore the role and row security setting changed by `begin_capture_context`
+    pub async fn end_capture_context(client: &tokio_postgres::Client) -> Result<()> {
+        client.batch_execute("RESET ROLE; RESET row_security")
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to reset capture context: {}", e)))
+    }
+
+    /// Open a read-only repeatable-read transaction on `client` and export its snapshot
+    ///
+    /// Every capture on `client` until `end_capture_snapshot` sees the
+    /// database as of this call. Other sessions can join the same snapshot
+    /// with `SET TRANSACTION SNAPSHOT` and the returned id.
+    pub async fn begin_capture_snapshot(client: &tokio_postgres::Client) -> Result<String> {
+        client.batch_execute("BEGIN ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to begin capture snapshot: {}", e)))?;
+        let row = client.query_one("SELECT pg_export_snapshot()", &[])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to export capture snapshot: {}", e)))?;
+        Ok(row.get(0))
+    }
+
+    /// Close the snapshot transaction opened by `begin_capture_snapshot`
+    pub async fn end_capture_snapshot(client: &tokio_postgres::Client, commit: bool) -> Result<()> {
+        client.batch_execute(if commit { "COMMIT" } else { "ROLLBACK" })
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to end capture snapshot: {}", e)))
+    }
+
+    /// Commits the current contents of the database's large objects as a new block.
+    ///
+    /// Large objects live in `pg_largeobject`, which logical decoding doesn't
+    /// stream, so statements calling `lo_*` functions are followed by a fresh
+    /// `snapshot` from `capture_large_objects`. Only objects whose size or
+    /// content hash changed are applied to the committed state.
+    pub fn commit_large_objects(&self, snapshot: TableState, commit_lsn: u64) -> Result<u64> {
+        self.cache_schema(large_object_schema());
+        let committed = self.get_latest_committed_table_state(LARGE_OBJECT_TABLE)?;
+
+        self.begin_wal_transaction(None)?;
+        for (loid, row) in &snapshot.rows {
+            match committed.as_ref().and_then(|state| state.get_row(loid)) {
+                None => self.apply_wal_insert(LARGE_OBJECT_TABLE.to_string(), row.clone())?,
+                Some(previous) if previous.values != row.values => {
+                    self.apply_wal_update(LARGE_OBJECT_TABLE.to_string(), loid.clone(), row.clone())?
+                }
+                Some(_) => {}
+            }
+        }
+        if let Some(committed) = &committed {
+            for loid in committed.rows.keys().filter(|loid| !snapshot.rows.contains_key(*loid)) {
+                self.apply_wal_delete(LARGE_OBJECT_TABLE.to_string(), loid.clone())?;
+            }
+        }
+        self.commit_wal_transaction(commit_lsn)
+    }
+
+    /// Apply the row changes in `records` as one block and return the new state root
+    ///
+    /// Only tables the records touch are rehashed (leaf by leaf with
+    /// incremental WAL); every other table keeps its committed root. Row
+    /// payloads (`WalRecord::data`) are the new `Row` as JSON for inserts and
+    /// updates, and the UTF-8 row id for deletes; an update that changes a
+    /// row's id arrives as a delete and an insert. Changes are applied in
+    /// record order, records of transactions aborted within `records` are
+    /// skipped, and a truncate deletes every row of its table. DDL and
+    /// rollbacks to savepoints can't be applied as deltas: they are rejected
+    /// and the state is left unchanged.
+    pub fn apply_wal_delta(&self, records: &[WalRecord]) -> Result<[u8; 32]> {
+        let aborted: HashSet<u64> = records.iter()
+            .filter(|record| record.record_type == WalRecordType::Abort)
+            .map(|record| record.txid)
+            .collect();
+
+        // Net effect per row: the row's final version, or `None` once deleted
+        let mut net_changes: BTreeMap<(String, String), Option<Row>> = BTreeMap::new();
+        for record in records.iter().filter(|record| !aborted.contains(&record.txid)) {
+            let table_name = || record.relation_name.clone()
+                .map(|name| self.logical_table_name(name))
+                .ok_or_else(|| ProxyError::Verification(format!("WAL record at LSN {} names no table", record.lsn)));
+            match &record.record_type {
+                WalRecordType::Insert | WalRecordType::Update => {
+                    let row: Row = serde_json::from_slice(&record.data)
+                        .map_err(|e| ProxyError::Verification(format!("Invalid row in WAL record at LSN {}: {}", record.lsn, e)))?;
+                    net_changes.insert((table_name()?, row.id.clone()), Some(row));
+                }
+                WalRecordType::Delete => {
+                    let row_id = String::from_utf8(record.data.clone())
+                        .map_err(|e| ProxyError::Verification(format!("Invalid row id in WAL record at LSN {}: {}", record.lsn, e)))?;
+                    net_changes.insert((table_name()?, row_id), None);
+                }
+                WalRecordType::Truncate => {
+                    let table_name = table_name()?;
+                    let live_ids: Vec<String> = self.live_table_states.read().map_err(poison_err)?
+                        .get(&table_name)
+                        .map(|state| state.rows.keys().cloned().collect())
+                        .unwrap_or_default();
+                    for (_, change) in net_changes.range_mut((table_name.clone(), String::new())..)
+                        .take_while(|((name, _), _)| *name == table_name)
+                    {
+                        *change = None;
+                    }
+                    for row_id in live_ids {
+                        net_changes.insert((table_name.clone(), row_id), None);
+                    }
+                }
+                WalRecordType::Ddl | WalRecordType::RollbackToSavepoint => {
+                    return Err(ProxyError::Verification(format!(
+                        "WAL record at LSN {} ({:?}) can't be applied as a delta; recapture the database instead",
+                        record.lsn, record.record_type
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        self.begin_wal_transaction(None)?;
+        for ((table_name, row_id), change) in net_changes {
+            match change {
+                Some(row) => self.apply_wal_update(table_name, row_id, row)?,
+                None => self.apply_wal_delete(table_name, row_id)?,
+            }
+        }
+        let commit_lsn = records.iter().map(|record| record.lsn).max().unwrap_or(0);
+        self.commit_wal_transaction(commit_lsn)?;
+        self.get_current_root_hash()?
+            .ok_or_else(|| ProxyError::Verification("No state root after applying WAL delta".to_string()))
+    }
+
     /// Begins tracking changes for a new transaction received from WAL.
     pub fn begin_wal_transaction(&self, transaction_id: Option<u32>) -> Result<()> {
         let mut in_progress_lock = self.in_progress_state.write().map_err(poison_err)?;
@@ -127,6 +708,7 @@ impl StateCaptureManager {
 
     /// Applies an insert operation from WAL to the in-progress transaction state.
     pub fn apply_wal_insert(&self, table_name: String, new_row: Row) -> Result<()> {
+        let table_name = self.logical_table_name(table_name);
         let mut in_progress_lock = self.in_progress_state.write().map_err(poison_err)?;
         if let Some(state) = in_progress_lock.as_mut() {
             state.changes.entry(table_name).or_default().inserts.push(new_row);
@@ -140,6 +722,7 @@ impl StateCaptureManager {
     /// Applies an update operation from WAL.
     /// `row_id` is the string representation of the primary key.
     pub fn apply_wal_update(&self, table_name: String, row_id: String, new_row: Row) -> Result<()> {
+        let table_name = self.logical_table_name(table_name);
         let mut in_progress_lock = self.in_progress_state.write().map_err(poison_err)?;
         if let Some(state) = in_progress_lock.as_mut() {
             state.changes.entry(table_name).or_default().updates.push((row_id.clone(), new_row)); // Clone row_id for logging
@@ -153,6 +736,7 @@ impl StateCaptureManager {
     /// Applies a delete operation from WAL.
     /// `row_id` is the string representation of the primary key.
     pub fn apply_wal_delete(&self, table_name: String, row_id: String) -> Result<()> {
+        let table_name = self.logical_table_name(table_name);
         let mut in_progress_lock = self.in_progress_state.write().map_err(poison_err)?;
         if let Some(state) = in_progress_lock.as_mut() {
             state.changes.entry(table_name).or_default().deletes.push(row_id.clone()); // Clone row_id for logging
@@ -196,9 +780,21 @@ impl StateCaptureManager {
                     self.cache_schema(minimal_schema.clone()); // Cache the minimal one to avoid repeated warnings
                     minimal_schema
                 });
-                TableState::new(schema)
+                let table_state = TableState::new(schema);
+                if self.sparse_table_trees {
+                    table_state.with_sparse_tree()
+                } else {
+                    table_state
+                }
             });
 
+            if self.incremental_wal {
+                apply_changes_incrementally(&mut table_state, changes);
+                info!("Updated live state for table '{}', new row count: {}", table_name, table_state.row_count);
+                modified_tables.insert(table_name.clone(), table_state);
+                continue;
+            }
+
             // Apply deletes (using row_id string)
             for row_id_to_delete in changes.deletes {
                 // `delete_row` returns Option<Row>, result ignored for now
@@ -220,52 +816,131 @@ impl StateCaptureManager {
                 debug!("Applied insert in table '{}'", table_name); // Log less verbosely for inserts
             }
 
-            // Recalculate Merkle root for the modified table state
-            // This is crucial. Assumes TableState has this method.
-            table_state.rebuild_merkle_tree(); 
-            info!("Updated live state for table '{}', new row count: {}, new root: {:?}", 
-                  table_name, table_state.row_count, table_state.root_hash.map(hex::encode));
+            info!("Updated live state for table '{}', new row count: {}", table_name, table_state.row_count);
             
             // Store modified state temporarily
             modified_tables.insert(table_name.clone(), table_state);
         }
 
+        let new_block_number = previous_block_number + 1;
+        let cached_table_roots = previous_block_state.table_state_roots.clone();
+
+        // Mark modified tables as pending capture, keeping the block they first became dirty in
+        let mut dirty_lock = self.dirty_tables.write().map_err(poison_err)?;
+        for table_name in modified_tables.keys() {
+            dirty_lock.entry(table_name.clone()).or_insert(new_block_number);
+        }
+
         // Update the live_table_states with the modified ones (and add back unmodified ones removed earlier)
         live_states_lock.extend(modified_tables);
 
         // --- 5. Calculate Merkle Roots using SecureMerkleTree --- 
 
-        // 5.1 Aggregate Table Root
-        let final_table_state_roots: HashMap<String, [u8; 32]> = live_states_lock.iter()
-            .filter_map(|(name, state_lock)| {
-                let state = state_lock.read().unwrap(); // Handle potential poison error
-                state.root_hash.map(|root| (name.clone(), root))
-            })
-            .collect();
+        // 5.1 Capture the selected tables; the rest keep their cached roots
+        let mut last_captured_lock = self.last_captured_block.write().map_err(poison_err)?;
+        // Restored if root validation aborts the commit, so captured tables are captured again
+        let capture_bookkeeping = (self.root_validation_rate > 0.0)
+            .then(|| (dirty_lock.clone(), last_captured_lock.clone()));
+        let capture_set = select_tables_to_capture(
+            live_states_lock.keys(),
+            &dirty_lock,
+            &last_captured_lock,
+            &cached_table_roots,
+            self.max_tables_per_block,
+        );
+
+        let mut captured_dirty = HashSet::new();
+        let mut to_rebuild = Vec::new();
+        for (name, state) in live_states_lock.iter_mut() {
+            if capture_set.contains(name) {
+                let was_dirty = dirty_lock.remove(name).is_some();
+                if was_dirty {
+                    captured_dirty.insert(name.clone());
+                }
+                // Incrementally applied changes already left the root up to date
+                if !(was_dirty && self.incremental_wal) {
+                    to_rebuild.push(state);
+                }
+            }
+        }
+        rebuild_tables(to_rebuild, self.hash_parallelism);
+
+        let mut final_table_state_roots: HashMap<String, [u8; 32]> = HashMap::new();
+        for (name, state) in live_states_lock.iter() {
+            if capture_set.contains(name) {
+                let was_dirty = captured_dirty.contains(name);
+                if !was_dirty && state.root_hash != cached_table_roots.get(name).copied() {
+                    warn!("Re-verification of unchanged table '{}' produced a root that differs from its cached root", name);
+                }
+                last_captured_lock.insert(name.clone(), new_block_number);
+                if let Some(root) = state.root_hash {
+                    final_table_state_roots.insert(name.clone(), root);
+                }
+            } else if let Some(root) = cached_table_roots.get(name) {
+                final_table_state_roots.insert(name.clone(), *root);
+            }
+        }
+        if !dirty_lock.is_empty() {
+            debug!("Deferred capture of {} modified tables to a later block", dirty_lock.len());
+        }
 
-        let mut sorted_table_roots: Vec<_> = final_table_state_roots.iter().collect();
-        sorted_table_roots.sort_by_key(|(name, _)| *name); // Sort by table name for determinism
-        let table_root_vecs: Vec<Vec<u8>> = sorted_table_roots.iter().map(|(_, hash)| hash.to_vec()).collect();
-        let table_tree = SecureMerkleTree::from_leaves(&table_root_vecs);
-        let new_overall_state_root = table_tree.root_hash();
+        // Recompute a sample of the roots about to be committed from the current rows
+        if let Some((saved_dirty, saved_last_captured)) = capture_bookkeeping {
+            let mismatches = find_root_mismatches(&live_states_lock, &final_table_state_roots, &dirty_lock, self.root_validation_rate);
+            if !mismatches.is_empty() {
+                error!("Aborting commit of block {}: roots of tables {:?} don't match their rows", new_block_number, mismatches);
+                *dirty_lock = saved_dirty;
+                *last_captured_lock = saved_last_captured;
+                for name in &mismatches {
+                    dirty_lock.entry(name.clone()).or_insert(new_block_number);
+                }
+                return Err(ProxyError::Verification(format!(
+                    "Root validation failed for block {}: tables {} don't match their recomputed roots",
+                    new_block_number, mismatches.join(", ")
+                )));
+            }
+        }
+
+        // 5.2 Aggregate Table Root
+        let new_overall_state_root = aggregate_state_root(&final_table_state_roots);
 
-        // 5.2 Empty Transaction Root
+        // 5.3 Empty Transaction Root
         let empty_tx_tree = SecureMerkleTree::from_leaves(&Vec::<Vec<u8>>::new());
         let transactions_root = empty_tx_tree.root_hash();
 
         // --- 6. Create Metadata (Example) --- 
         // TODO: Populate metadata fields properly
+        let mut additional_data = serde_json::json!({ "commit_lsn": commit_lsn });
+        if self.check_referential_integrity {
+            let violations = find_dangling_foreign_keys(&live_states_lock);
+            if !violations.is_empty() {
+                warn!("Block {} commits {} dangling foreign key references", new_block_number, violations.len());
+            }
+            additional_data["fk_violations"] = serde_json::json!(violations);
+        }
+        let versions = *self.postgres_versions.read().map_err(poison_err)?;
+        if let Some(versions) = versions {
+            additional_data["verifier_postgres_version"] = serde_json::json!(format_postgres_version(versions.verifier));
+        }
+        if let Some(audit) = self.schema_audit.lock().map_err(poison_err)?.as_mut() {
+            additional_data["schema_audit"] = serde_json::json!({
+                "version": audit.log.head_version(),
+                "checksum": hex::encode(audit.log.head_checksum()),
+                "head": hex::encode(audit.log.head_hash()),
+                "entries": &audit.log.entries[audit.committed..],
+            });
+            audit.committed = audit.log.entries.len();
+        }
         let metadata = BlockMetadata {
-            postgres_version: "unknown".to_string(),
+            postgres_version: postgres_version(versions),
             protocol_version: env!("CARGO_PKG_VERSION").to_string(),
             operator_id: "proxy-node-1".to_string(),
             operator_signature: None,
             operator_public_key: None,
-            additional_data: Some(serde_json::to_string(&HashMap::from([("commit_lsn", commit_lsn)])).unwrap_or_default()),
+            additional_data: Some(additional_data.to_string()),
         };
 
         // --- 7. Create the new block header using the calculated roots --- 
-        let new_block_number = previous_block_number + 1;
         let new_block_header = BlockHeader::new(
             new_block_number,
             previous_block_hash, // Hash of the previous block's header
@@ -311,6 +986,16 @@ impl StateCaptureManager {
         }
     }
 
+    /// Gets the root hash of a single table in the latest committed block.
+    /// Returns `None` if the state is uninitialized or the table is not tracked.
+    pub fn get_current_table_root(&self, table_name: &str) -> Result<Option<[u8; 32]>> {
+        let latest_block_num = *self.latest_committed_block_number.read().map_err(poison_err)?;
+        let history_lock = self.state_history.read().map_err(poison_err)?;
+        Ok(history_lock
+            .get(&latest_block_num)
+            .and_then(|state| state.table_state_roots.get(table_name).copied()))
+    }
+
     /// Gets the block number of the latest committed block.
     pub fn get_current_block_number(&self) -> Result<u64> {
         Ok(*self.latest_committed_block_number.read().map_err(poison_err)?)
@@ -364,12 +1049,107 @@ impl StateCaptureManager {
         }
     }
 
+    /// Record a schema change made by the DDL statement `sql` in the schema audit log
+    ///
+    /// `tables` holds the schema of each table the statement touched after it
+    /// ran, or `None` if it no longer exists. The change to the current schema
+    /// is recorded as a migration named `name`; returns its audit entry, or
+    /// `None` if the audit log is disabled or the statement changed no table
+    /// definition.
+    pub fn record_schema_change(&self, name: &str, sql: &str, tables: Vec<(String, Option<TableSchema>)>) -> Result<Option<SchemaAuditEntry>> {
+        let mut audit_lock = self.schema_audit.lock().map_err(poison_err)?;
+        let audit = match audit_lock.as_mut() {
+            Some(audit) => audit,
+            None => return Ok(None),
+        };
+
+        let mut operations = Vec::new();
+        for (table_name, schema) in &tables {
+            let ddl = match (audit.schema.get_table(table_name), schema) {
+                (Some(_), None) => DdlStatement::drop_table(table_name.clone(), sql.to_string()),
+                (None, Some(schema)) => DdlStatement::create_table(table_definition(schema), sql.to_string()),
+                (Some(previous), Some(schema)) => {
+                    let changes = column_changes(previous, schema);
+                    if changes.is_empty() {
+                        continue;
+                    }
+                    DdlStatement::alter_table(table_name.clone(), changes, sql.to_string())
+                }
+                (None, None) => continue,
+            };
+            operations.push(MigrationOperation { order: operations.len() + 1, statement: ddl });
+        }
+        if operations.is_empty() {
+            return Ok(None);
+        }
+
+        let migration = SchemaMigration::new(name.to_string(), MigrationDirection::Up, operations);
+        let schema = audit.log.apply_migration(&audit.schema, migration, "proxy".to_string(), sql.to_string())
+            .map_err(|e| ProxyError::Verification(format!("Failed to record schema change: {}", e)))?;
+        audit.schema = schema;
+        let entry = audit.log.entries.last().cloned();
+        drop(audit_lock);
+
+        for schema in tables.into_iter().filter_map(|(_, schema)| schema) {
+            self.cache_schema(schema);
+        }
+        Ok(entry)
+    }
+
+    /// Capture the schemas of `tables` after the DDL statement `sql` ran and record the change
+    ///
+    /// See [`record_schema_change`](Self::record_schema_change); a table with
+    /// no columns left is treated as dropped.
+    pub async fn capture_schema_change(&self, client: &tokio_postgres::Client, name: &str, sql: &str, tables: &[String]) -> Result<Option<SchemaAuditEntry>> {
+        let mut schemas = Vec::with_capacity(tables.len());
+        for table_name in tables {
+            let schema = Self::capture_table_schema(client, table_name).await?;
+            schemas.push((table_name.clone(), (!schema.columns.is_empty()).then_some(schema)));
+        }
+        self.record_schema_change(name, sql, schemas)
+    }
+
+    /// The schema audit log, if enabled and started
+    pub fn get_schema_audit_log(&self) -> Option<SchemaAuditLog> {
+        self.schema_audit.lock().unwrap().as_ref().map(|audit| audit.log.clone())
+    }
+
     /// Retain schema cache and legacy ID methods for now
     pub fn cache_schema(&self, schema: TableSchema) {
+        self.register_partitions(&schema);
         let mut cache = self.schema_cache.lock().unwrap();
         cache.insert(schema.name.clone(), schema);
     }
 
+    /// Route WAL changes of a partitioned table's partitions to the parent
+    fn register_partitions(&self, schema: &TableSchema) {
+        let partitioning = match &schema.partitioning {
+            Some(partitioning) => partitioning,
+            None => return,
+        };
+        let mut parents = self.partition_parents.write().unwrap();
+        for partition in &partitioning.partitions {
+            parents.insert(partition.name.clone(), schema.name.clone());
+        }
+    }
+
+    /// Name of the logical table a WAL change belongs to
+    ///
+    /// Changes to a partition belong to its partitioned parent, which is
+    /// verified as one table; the row keeps the partition's name so the
+    /// parent's state can record where it lives.
+    fn logical_table_name(&self, table_name: String) -> String {
+        match self.partition_parents.read().unwrap().get(&table_name) {
+            Some(parent) => parent.clone(),
+            None => table_name,
+        }
+    }
+
+    /// Schemas of every captured table
+    pub fn cached_schemas(&self) -> Vec<TableSchema> {
+        self.schema_cache.lock().unwrap().values().cloned().collect()
+    }
+
     pub fn get_schema(&self, table_name: &str) -> Option<TableSchema> {
         let cache_lock = self.schema_cache.lock().unwrap(); // TODO handle poison
         // Use explicit match instead of .cloned()
@@ -379,6 +1159,139 @@ impl StateCaptureManager {
         }
     }
 
+    /// Capture the trigger definitions installed on a table
+    ///
+    /// Internal triggers (e.g. those backing foreign keys) are skipped since
+    /// the replay recreates them from the table's constraints.
+    pub async fn capture_triggers(client: &tokio_postgres::Client, table_name: &str) -> Result<Vec<TriggerDefinition>> {
+        let rows = client.query(TRIGGER_CAPTURE_QUERY, &[&table_name])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to capture triggers for {}: {}", table_name, e)))?;
+        
+        Ok(rows.iter().map(|row| {
+            TriggerDefinition::from_pg_type(
+                row.get("trigger_name"),
+                row.get("trigger_type"),
+                row.get("function_name"),
+                row.get("function_definition"),
+            )
+        }).collect())
+    }
+
+    /// Capture the columns, primary key and foreign keys of a table
+    pub async fn capture_table_schema(client: &tokio_postgres::Client, table_name: &str) -> Result<TableSchema> {
+        let rows = client.query(COLUMN_CAPTURE_QUERY, &[&table_name])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to capture columns for {}: {}", table_name, e)))?;
+
+        let mut columns = Vec::with_capacity(rows.len());
+        let mut primary_keys = Vec::new();
+        for row in &rows {
+            let name: String = row.get("column_name");
+            let data_type: String = row.get("data_type");
+            let key_position: Option<i32> = row.get("key_position");
+            if let Some(position) = key_position {
+                primary_keys.push((position, name.clone()));
+            }
+            columns.push(ColumnDefinition {
+                name,
+                column_type: column_type_from_pg(&data_type),
+                nullable: !row.get::<_, bool>("not_null"),
+                primary_key: key_position.is_some(),
+                unique: key_position.is_some(),
+                default_value: row.get("default_value"),
+            });
+        }
+        primary_keys.sort();
+
+        let foreign_keys = client.query(FOREIGN_KEY_CAPTURE_QUERY, &[&table_name])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to capture foreign keys for {}: {}", table_name, e)))?
+            .iter()
+            .map(|row| (row.get("columns"), row.get("referenced_table"), row.get("referenced_columns")))
+            .collect();
+
+        Ok(TableSchema::new(
+            table_name.to_string(),
+            columns,
+            primary_keys.into_iter().map(|(_, name)| name).collect(),
+            vec![],
+            foreign_keys,
+        ))
+    }
+
+    /// Capture every row of a table into a new TableState
+    ///
+    /// Rows are identified by their escaped primary key values joined with
+    /// `,` (see `primary_key_id`), or by their position in a scan ordered by
+    /// every column if the table has no primary key. Rows of a partitioned
+    /// table keep the name of the partition holding them.
+    ///
+    /// Rows are streamed from the server and converted one at a time, so the
+    /// raw result set is never buffered; the Merkle tree is built once, after
+    /// the last row.
+    pub async fn capture_table_rows(client: &tokio_postgres::Client, schema: TableSchema) -> Result<TableState> {
+        let capture = RowCapture::new(&schema);
+        let capture_error = |e: tokio_postgres::Error| ProxyError::Database(format!("Failed to capture rows of {}: {}", schema.name, e));
+        let pg_rows = client.query_raw(capture.query.as_str(), std::iter::empty::<&str>())
+            .await
+            .map_err(capture_error)?;
+        futures_util::pin_mut!(pg_rows);
+
+        let mut rows = Vec::new();
+        while let Some(pg_row) = pg_rows.try_next().await.map_err(capture_error)? {
+            rows.push(capture.row(&pg_row, rows.len(), &schema)?);
+        }
+        let mut table_state = TableState::new(schema);
+        table_state.insert_rows(rows);
+        Ok(table_state)
+    }
+
+    /// Capture every large object as a row of the `pg_largeobject` pseudo-table
+    ///
+    /// Every object is one row holding its size and SHA-256 content hash;
+    /// content up to `LARGE_OBJECT_INLINE_LIMIT` bytes is kept as well so a
+    /// replay can restore it. Reading `pg_largeobject` requires superuser
+    /// (or `lo_compat_privileges`).
+    pub async fn capture_large_objects(client: &tokio_postgres::Client) -> Result<TableState> {
+        let rows = client.query(LARGE_OBJECT_CAPTURE_QUERY, &[&LARGE_OBJECT_INLINE_LIMIT])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to capture large objects: {}", e)))?;
+
+        let mut table_state = TableState::new(large_object_schema());
+        for row in rows {
+            table_state.insert_row(large_object_row(row.get("loid"), row.get("size"), row.get("content_hash"), row.get("data")));
+        }
+        Ok(table_state)
+    }
+
+    /// Capture the partitioning of a partitioned table
+    ///
+    /// Returns `None` for ordinary tables. Multi-level partitioning is
+    /// flattened to the leaf partitions, which are the ones holding rows.
+    pub async fn capture_partitioning(client: &tokio_postgres::Client, table_name: &str) -> Result<Option<PartitionScheme>> {
+        let rows = client.query(PARTITION_CAPTURE_QUERY, &[&table_name])
+            .await
+            .map_err(|e| ProxyError::Database(format!("Failed to capture partitions for {}: {}", table_name, e)))?;
+
+        let first = match rows.first() {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let strategy_code: String = first.get("strategy");
+        let strategy = PartitionStrategy::from_pg_code(&strategy_code)
+            .ok_or_else(|| ProxyError::Database(format!("Unknown partition strategy '{}' for {}", strategy_code, table_name)))?;
+
+        Ok(Some(PartitionScheme {
+            strategy,
+            key: first.get("partition_key"),
+            partitions: rows.iter().map(|row| PartitionDefinition {
+                name: row.get("partition_name"),
+                bound: row.get("partition_bound"),
+            }).collect(),
+        }))
+    }
+
     pub fn get_next_transaction_id(&self) -> u64 {
         let mut counter = self.transaction_counter.lock().unwrap();
         *counter += 1;
@@ -386,6 +1299,558 @@ impl StateCaptureManager {
     }
 }
 
+/// Catalog query returning the user-defined triggers on a table, ordered by name
+const TRIGGER_CAPTURE_QUERY: &str = "SELECT t.tgname::text AS trigger_name, t.tgtype AS trigger_type, \
+     p.proname::text AS function_name, pg_get_functiondef(p.oid) AS function_definition \
+     FROM pg_trigger t \
+     JOIN pg_class c ON c.oid = t.tgrelid \
+     JOIN pg_namespace n ON n.oid = c.relnamespace \
+     JOIN pg_proc p ON p.oid = t.tgfoid \
+     WHERE n.nspname = 'public' AND c.relname = $1 AND NOT t.tgisinternal \
+     ORDER BY t.tgname";
+
+/// Catalog query returning the user tables covered by the genesis block, ordered by name
+///
+/// Partitions are covered by their partitioned parent, and the proxy's own
+/// bookkeeping tables are excluded.
+const GENESIS_TABLES_QUERY: &str = "SELECT c.relname::text AS table_name \
+     FROM pg_class c \
+     JOIN pg_namespace n ON n.oid = c.relnamespace \
+     WHERE n.nspname = 'public' AND c.relkind IN ('r', 'p') AND NOT c.relispartition \
+     AND c.relname NOT LIKE 'verification\\_%' \
+     ORDER BY c.relname";
+
+/// Catalog query returning the current role and whether it bypasses row-level security
+const CAPTURE_ROLE_QUERY: &str = "SELECT r.rolname::text AS role, (r.rolsuper OR r.rolbypassrls) AS bypass_rls \
+     FROM pg_roles r WHERE r.rolname = current_user";
+
+/// Catalog query listing the captured tables with row-level security enabled, in name order
+const RLS_TABLES_QUERY: &str = "SELECT c.relname::text AS table_name \
+     FROM pg_class c \
+     JOIN pg_namespace n ON n.oid = c.relnamespace \
+     WHERE n.nspname = 'public' AND c.relkind IN ('r', 'p') AND NOT c.relispartition \
+     AND c.relname NOT LIKE 'verification\\_%' AND c.relrowsecurity \
+     ORDER BY c.relname";
+
+/// Catalog query returning the columns of a table in order, with their position in the primary key
+const COLUMN_CAPTURE_QUERY: &str = "SELECT a.attname::text AS column_name, format_type(a.atttypid, a.atttypmod) AS data_type, \
+     a.attnotnull AS not_null, pg_get_expr(d.adbin, d.adrelid) AS default_value, \
+     array_position(i.indkey::int2[], a.attnum)::int4 AS key_position \
+     FROM pg_attribute a \
+     JOIN pg_class c ON c.oid = a.attrelid \
+     JOIN pg_namespace n ON n.oid = c.relnamespace \
+     LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum \
+     LEFT JOIN pg_index i ON i.indrelid = c.oid AND i.indisprimary \
+     WHERE n.nspname = 'public' AND c.relname = $1 AND a.attnum > 0 AND NOT a.attisdropped \
+     ORDER BY a.attnum";
+
+/// Catalog query returning the foreign keys of a table with their columns in key order, ordered by name
+const FOREIGN_KEY_CAPTURE_QUERY: &str = "SELECT ref.relname::text AS referenced_table, \
+     ARRAY(SELECT a.attname::text FROM unnest(con.conkey) WITH ORDINALITY k(attnum, ord) \
+           JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum ORDER BY k.ord) AS columns, \
+     ARRAY(SELECT a.attname::text FROM unnest(con.confkey) WITH ORDINALITY k(attnum, ord) \
+           JOIN pg_attribute a ON a.attrelid = con.confrelid AND a.attnum = k.attnum ORDER BY k.ord) AS referenced_columns \
+     FROM pg_constraint con \
+     JOIN pg_class c ON c.oid = con.conrelid \
+     JOIN pg_namespace n ON n.oid = c.relnamespace \
+     JOIN pg_class ref ON ref.oid = con.confrelid \
+     WHERE con.contype = 'f' AND n.nspname = 'public' AND c.relname = $1 \
+     ORDER BY con.conname";
+
+/// Name under which large objects are included in the state root
+pub const LARGE_OBJECT_TABLE: &str = "pg_largeobject";
+
+/// Largest large object whose content is kept in the captured state, in bytes
+///
+/// Bigger objects are captured by reference (size and content hash only),
+/// so replays can check but not restore them.
+pub const LARGE_OBJECT_INLINE_LIMIT: i64 = 64 * 1024;
+
+/// Query returning each large object's size, content hash and hex content (if no longer than `$1` bytes), ordered by OID
+///
+/// Objects created but never written have no `pg_largeobject` pages and are
+/// captured with size 0. Columns match `large_object_schema`.
+pub const LARGE_OBJECT_CAPTURE_QUERY: &str = "SELECT loid, length(content)::int8 AS size, \
+     encode(sha256(content), 'hex') AS content_hash, \
+     CASE WHEN length(content) <= $1::int8 THEN encode(content, 'hex') END AS data \
+     FROM (SELECT m.oid::int8 AS loid, \
+           coalesce(string_agg(l.data, ''::bytea ORDER BY l.pageno), ''::bytea) AS content \
+           FROM pg_largeobject_metadata m \
+           LEFT JOIN pg_largeobject l ON l.loid = m.oid \
+           GROUP BY m.oid) objects \
+     ORDER BY loid";
+
+/// Schema of the large-object pseudo-table: one row per object, keyed by OID
+fn large_object_schema() -> TableSchema {
+    let column = |name: &str, column_type| ColumnDefinition {
+        name: name.to_string(),
+        column_type,
+        nullable: name == "data",
+        primary_key: name == "loid",
+        unique: name == "loid",
+        default_value: None,
+    };
+    TableSchema::new(
+        LARGE_OBJECT_TABLE.to_string(),
+        vec![
+            column("loid", ColumnType::BigInt),
+            column("size", ColumnType::BigInt),
+            column("content_hash", ColumnType::Text),
+            column("data", ColumnType::Text),
+        ],
+        vec!["loid".to_string()],
+        vec![],
+        vec![],
+    )
+}
+
+/// Row of the large-object pseudo-table for one object, with its hex content if inlined
+fn large_object_row(loid: i64, size: i64, content_hash: String, data: Option<String>) -> Row {
+    let values = HashMap::from([
+        ("loid".to_string(), Value::BigInt(loid)),
+        ("size".to_string(), Value::BigInt(size)),
+        ("content_hash".to_string(), Value::Text(content_hash)),
+        ("data".to_string(), data.map(Value::Text).unwrap_or(Value::Null)),
+    ]);
+    Row::new(loid.to_string(), LARGE_OBJECT_TABLE.to_string(), values)
+}
+
+/// Operator id recorded in every genesis header, so the header doesn't depend on the node
+const GENESIS_OPERATOR_ID: &str = "genesis";
+
+/// Build the genesis block (block 0) over the initial table states, rebuilding their Merkle trees
+///
+/// `snapshot_id` is the exported snapshot the tables were captured in,
+/// `capture_context` the role context they were captured under, and
+/// `versions` the server versions of the databases, if any.
+fn build_genesis_state(
+    initial_table_states: &mut HashMap<String, TableState>,
+    snapshot_id: Option<&str>,
+    capture_context: Option<&CaptureContext>,
+    versions: Option<PostgresVersions>,
+) -> CoreDatabaseState {
+    let mut genesis_table_roots = HashMap::new();
+    for (table_name, table_state) in initial_table_states.iter_mut() {
+        table_state.rebuild_merkle_tree();
+        // Empty tables have no root until their first rows are committed
+        if let Some(root) = table_state.root_hash {
+            genesis_table_roots.insert(table_name.clone(), root);
+        }
+    }
+
+    let mut sorted_roots: Vec<_> = genesis_table_roots.iter().collect();
+    sorted_roots.sort_by_key(|(name, _)| *name); // Sort by table name for determinism
+    let root_vecs: Vec<Vec<u8>> = sorted_roots.iter().map(|(_, hash)| hash.to_vec()).collect();
+    let state_root = SecureMerkleTree::from_leaves(&root_vecs).root_hash();
+    let transactions_root = SecureMerkleTree::from_leaves(&Vec::<Vec<u8>>::new()).root_hash();
+
+    let metadata = BlockMetadata {
+        postgres_version: postgres_version(versions),
+        protocol_version: env!("CARGO_PKG_VERSION").to_string(),
+        operator_id: GENESIS_OPERATOR_ID.to_string(),
+        operator_signature: None,
+        operator_public_key: None,
+        additional_data: capture_data(snapshot_id, capture_context, versions),
+    };
+    let header = BlockHeader::new(
+        0,
+        [0u8; 32], // Nothing precedes genesis
+        transactions_root,
+        state_root,
+        Utc.timestamp_opt(0, 0).unwrap(),
+        metadata,
+    );
+
+    CoreDatabaseState::new(header, HashMap::new(), genesis_table_roots)
+}
+
+/// Block additional data describing how the captured tables were read, if anything is recorded
+fn capture_data(snapshot_id: Option<&str>, capture_context: Option<&CaptureContext>, versions: Option<PostgresVersions>) -> Option<String> {
+    let mut data = serde_json::Map::new();
+    if let Some(versions) = versions {
+        data.insert("verifier_postgres_version".to_string(), serde_json::json!(format_postgres_version(versions.verifier)));
+    }
+    if let Some(id) = snapshot_id {
+        data.insert("snapshot_id".to_string(), serde_json::json!(id));
+    }
+    if let Some(context) = capture_context {
+        data.insert("capture_context".to_string(), serde_json::json!(context));
+    }
+    (!data.is_empty()).then(|| serde_json::Value::Object(data).to_string())
+}
+
+/// Definition of a created table, for a schema migration
+fn table_definition(schema: &TableSchema) -> TableDefinition {
+    TableDefinition {
+        name: schema.name.clone(),
+        columns: schema.columns.clone(),
+        primary_keys: schema.primary_keys.clone(),
+        unique_constraints: schema.unique_constraints.clone(),
+        foreign_keys: schema.foreign_keys.clone(),
+    }
+}
+
+/// Column changes turning `previous` into `current`, for a schema migration
+fn column_changes(previous: &TableSchema, current: &TableSchema) -> Vec<ColumnDefinitionDdl> {
+    let mut changes: Vec<ColumnDefinitionDdl> = previous.columns.iter()
+        .filter(|column| current.get_column(&column.name).is_none())
+        .map(|column| ColumnDefinitionDdl::DropColumn(column.name.clone()))
+        .collect();
+    for column in &current.columns {
+        match previous.get_column(&column.name) {
+            None => changes.push(ColumnDefinitionDdl::AddColumn(column.clone())),
+            Some(old) if serde_json::to_value(old).ok() != serde_json::to_value(column).ok() => {
+                changes.push(ColumnDefinitionDdl::ModifyColumn(column.clone()));
+            }
+            Some(_) => {}
+        }
+    }
+    changes
+}
+
+/// Block `postgres_version`: the live database's version, if known
+fn postgres_version(versions: Option<PostgresVersions>) -> String {
+    versions.map_or_else(|| "unknown".to_string(), |versions| format_postgres_version(versions.live))
+}
+
+/// Map a PostgreSQL type name (as given by `format_type`) to a column type
+fn column_type_from_pg(data_type: &str) -> ColumnType {
+    let length = || data_type
+        .split(['(', ')'])
+        .nth(1)
+        .and_then(|n| n.parse().ok());
+    match data_type {
+        "smallint" | "integer" => ColumnType::Integer,
+        "bigint" => ColumnType::BigInt,
+        "real" | "double precision" => ColumnType::Float,
+        "text" => ColumnType::Text,
+        "bytea" => ColumnType::Binary,
+        "boolean" => ColumnType::Boolean,
+        "uuid" => ColumnType::Uuid,
+        "json" | "jsonb" => ColumnType::Json,
+        t if t.starts_with("timestamp") => ColumnType::Timestamp,
+        t if t.starts_with("character varying(") => length().map(ColumnType::VarChar).unwrap_or(ColumnType::Text),
+        t if t.starts_with("character(") => length().map(ColumnType::Char).unwrap_or(ColumnType::Text),
+        // Exact types without a native representation (e.g. numeric) keep their text form
+        _ => ColumnType::Text,
+    }
+}
+
+/// Select-list expression reading a column as the text `parse_captured_value` expects
+fn capture_expression(column: &ColumnDefinition) -> String {
+    let name = quote_ident(&column.name);
+    match column.column_type {
+        ColumnType::Binary => format!("encode({}, 'hex')", name),
+        ColumnType::Timestamp => format!("(extract(epoch from {}) * 1000)::bigint::text", name),
+        _ => format!("{}::text", name),
+    }
+}
+
+/// Query reading a table's rows for capture, and how to turn its result rows into `Row`s
+struct RowCapture {
+    /// Query selecting the partition name, then every column as text
+    query: String,
+    /// Positions of the primary key columns in the schema
+    key_indexes: Vec<usize>,
+}
+
+impl RowCapture {
+    fn new(schema: &TableSchema) -> Self {
+        // Values are read as text; column i is at select-list position i + 2
+        let select_list: String = schema.columns.iter()
+            .map(|column| format!(", {}", capture_expression(column)))
+            .collect();
+        let key_indexes: Vec<usize> = schema.primary_keys.iter()
+            .filter_map(|key| schema.columns.iter().position(|c| &c.name == key))
+            .collect();
+        let order_by: Vec<String> = if key_indexes.is_empty() {
+            (0..schema.columns.len()).map(|i| (i + 2).to_string()).collect()
+        } else {
+            key_indexes.iter().map(|i| (i + 2).to_string()).collect()
+        };
+        let mut query = format!("SELECT tableoid::regclass::text{} FROM {}", select_list, quote_ident(&schema.name));
+        if !order_by.is_empty() {
+            query.push_str(&format!(" ORDER BY {}", order_by.join(", ")));
+        }
+        RowCapture { query, key_indexes }
+    }
+
+    /// Convert the result row at position `index` of the ordered scan
+    fn row(&self, pg_row: &tokio_postgres::Row, index: usize, schema: &TableSchema) -> Result<Row> {
+        let partition: String = pg_row.get(0);
+        let texts: Vec<Option<String>> = (1..pg_row.len()).map(|i| pg_row.get(i)).collect();
+        let id = if self.key_indexes.is_empty() {
+            (index + 1).to_string()
+        } else {
+            primary_key_id(self.key_indexes.iter().map(|i| texts[*i].as_deref().unwrap_or_default()))
+        };
+        let mut values = HashMap::new();
+        for (column, text) in schema.columns.iter().zip(texts) {
+            values.insert(column.name.clone(), parse_captured_value(text, column)?);
+        }
+        Ok(Row::new(id, partition, values))
+    }
+}
+
+/// Parse a column value captured as text
+fn parse_captured_value(text: Option<String>, column: &ColumnDefinition) -> Result<Value> {
+    let text = match text {
+        Some(text) => text,
+        None => return Ok(Value::Null),
+    };
+    let invalid = |e: &dyn std::fmt::Display| ProxyError::Database(format!("Invalid value '{}' for column {}: {}", text, column.name, e));
+    let value = match column.column_type {
+        ColumnType::Integer => Value::Integer(text.parse().map_err(|e| invalid(&e))?),
+        ColumnType::BigInt => Value::BigInt(text.parse().map_err(|e| invalid(&e))?),
+        ColumnType::Timestamp => Value::Timestamp(text.parse().map_err(|e| invalid(&e))?),
+        ColumnType::Float => Value::Float(text.parse().map_err(|e| invalid(&e))?),
+        ColumnType::Boolean => Value::Boolean(text == "true"),
+        ColumnType::Uuid => Value::Uuid(uuid::Uuid::parse_str(&text).map_err(|e| invalid(&e))?),
+        ColumnType::Binary => Value::Binary(hex::decode(&text).map_err(|e| invalid(&e))?),
+        ColumnType::Json => Value::Json(text),
+        ColumnType::Text | ColumnType::VarChar(_) | ColumnType::Char(_) => Value::Text(text),
+    };
+    Ok(value)
+}
+
+/// Row id of a primary key tuple: its values joined with `,`
+///
+/// `\` and `,` inside values are escaped with `\`, so distinct tuples such as
+/// `("a,b", "c")` and `("a", "b,c")` never share an id. Ordinary
+/// single-column keys keep their plain value as id.
+fn primary_key_id<'a>(values: impl IntoIterator<Item = &'a str>) -> String {
+    values.into_iter()
+        .map(|value| value.replace('\\', "\\\\").replace(',', "\\,"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Quote an identifier for use in generated SQL
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Catalog query returning the leaf partitions of a partitioned table, ordered by name
+const PARTITION_CAPTURE_QUERY: &str = "SELECT pt.partstrat::text AS strategy, pg_get_partkeydef(pt.partrelid) AS partition_key, \
+     c.relname::text AS partition_name, pg_get_expr(c.relpartbound, c.oid) AS partition_bound \
+     FROM pg_partitioned_table pt \
+     JOIN pg_class parent ON parent.oid = pt.partrelid \
+     CROSS JOIN LATERAL pg_partition_tree(pt.partrelid) tree \
+     JOIN pg_class c ON c.oid = tree.relid \
+     WHERE parent.relname = $1 AND tree.isleaf \
+     ORDER BY c.relname";
+
+/// Find the foreign key values across `tables` that reference no existing row
+///
+/// Follows MATCH SIMPLE semantics: a foreign key with any NULL column
+/// references nothing and is never dangling. Violations are ordered by
+/// table, then row id.
+fn find_dangling_foreign_keys(tables: &HashMap<String, TableState>) -> Vec<ForeignKeyViolation> {
+    let mut violations = Vec::new();
+    let mut table_names: Vec<&String> = tables.keys().collect();
+    table_names.sort();
+
+    for table_name in table_names {
+        let table_state = &tables[table_name];
+        for (columns, referenced_table, referenced_columns) in &table_state.schema.foreign_keys {
+            let referenced = match tables.get(referenced_table) {
+                Some(referenced) => referenced,
+                None => {
+                    debug!("Skipping foreign key {} -> {}: referenced table is not captured", table_name, referenced_table);
+                    continue;
+                }
+            };
+            let existing: HashSet<String> = referenced.rows.values()
+                .filter_map(|row| reference_key(row, referenced_columns))
+                .collect();
+
+            let mut dangling: Vec<&Row> = table_state.rows.values()
+                .filter(|row| reference_key(row, columns).is_some_and(|key| !existing.contains(&key)))
+                .collect();
+            dangling.sort_by(|a, b| a.id.cmp(&b.id));
+            violations.extend(dangling.into_iter().map(|row| ForeignKeyViolation {
+                table: table_name.clone(),
+                row_id: row.id.clone(),
+                columns: columns.clone(),
+                referenced_table: referenced_table.clone(),
+            }));
+        }
+    }
+    violations
+}
+
+/// Comparable key of a row's values in `columns`, or `None` if any is NULL
+///
+/// Integer widths are unified so an `integer` key matches a `bigint` one.
+fn reference_key(row: &Row, columns: &[String]) -> Option<String> {
+    let values = columns.iter()
+        .map(|column| match row.values.get(column)? {
+            Value::Null => None,
+            Value::Integer(v) => Some(Value::BigInt(*v as i64)),
+            value => Some(value.clone()),
+        })
+        .collect::<Option<Vec<Value>>>()?;
+    serde_json::to_string(&values).ok()
+}
+
+/// Capture `table_names` with at most `concurrency` captures in flight
+///
+/// The first failed capture fails the whole capture, naming its table.
+async fn capture_tables<F, Fut>(table_names: Vec<String>, concurrency: usize, capture: F) -> Result<HashMap<String, TableState>>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<TableState>>,
+{
+    stream::iter(table_names)
+        .map(|table_name| {
+            let captured = capture(table_name.clone());
+            async move {
+                let table_state = captured.await
+                    .map_err(|e| ProxyError::Database(format!("Failed to capture table {}: {}", table_name, e)))?;
+                Ok((table_name, table_state))
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .try_collect()
+        .await
+}
+
+/// Rebuild the Merkle trees of `tables` using at most `parallelism` threads
+fn rebuild_tables(tables: Vec<&mut TableState>, parallelism: usize) {
+    let workers = parallelism.min(tables.len());
+    if workers <= 1 {
+        for table_state in tables {
+            table_state.rebuild_merkle_tree();
+        }
+        return;
+    }
+
+    // Spread tables over a bounded set of workers; each tree is built independently
+    let mut buckets: Vec<Vec<&mut TableState>> = (0..workers).map(|_| Vec::new()).collect();
+    for (i, table_state) in tables.into_iter().enumerate() {
+        buckets[i % workers].push(table_state);
+    }
+    std::thread::scope(|scope| {
+        for bucket in buckets {
+            scope.spawn(move || {
+                for table_state in bucket {
+                    table_state.rebuild_merkle_tree();
+                }
+            });
+        }
+    });
+}
+
+/// Apply one table's WAL changes, updating only the affected Merkle leaves
+fn apply_changes_incrementally(table_state: &mut TableState, changes: TableChanges) {
+    for row_id in changes.deletes {
+        table_state.delete_row_incremental(&row_id);
+    }
+    for (row_id, updated_row) in changes.updates {
+        // A changed primary key moves the row to a different leaf
+        if updated_row.id != row_id {
+            table_state.delete_row_incremental(&row_id);
+        }
+        table_state.upsert_row_incremental(updated_row);
+    }
+    for inserted_row in changes.inserts {
+        table_state.upsert_row_incremental(inserted_row);
+    }
+}
+
+/// Pick the tables to freshly capture in a block
+///
+/// Tables without a cached root are always captured. Without a cap every
+/// dirty table is captured and unchanged tables keep their cached roots.
+/// With a cap, dirty tables come first (oldest change first), then unchanged
+/// tables fill any leftover capacity, least recently captured first.
+fn select_tables_to_capture<'a>(
+    tables: impl Iterator<Item = &'a String>,
+    dirty_tables: &HashMap<String, u64>,
+    last_captured_block: &HashMap<String, u64>,
+    cached_roots: &HashMap<String, [u8; 32]>,
+    max_tables_per_block: Option<usize>,
+) -> HashSet<String> {
+    let mut selected = HashSet::new();
+    let mut dirty = Vec::new();
+    let mut clean = Vec::new();
+
+    for name in tables {
+        if !cached_roots.contains_key(name) {
+            selected.insert(name.clone());
+        } else if let Some(since) = dirty_tables.get(name) {
+            dirty.push((*since, name));
+        } else {
+            clean.push((last_captured_block.get(name).copied().unwrap_or(0), name));
+        }
+    }
+
+    let cap = match max_tables_per_block {
+        Some(cap) => cap,
+        None => {
+            selected.extend(dirty.into_iter().map(|(_, name)| name.clone()));
+            return selected;
+        }
+    };
+
+    // Sort by (block, name) so the selection is deterministic
+    dirty.sort();
+    clean.sort();
+    let remaining = cap.saturating_sub(selected.len());
+    selected.extend(dirty.into_iter().chain(clean).take(remaining).map(|(_, name)| name.clone()));
+    selected
+}
+
+/// State root over table roots, taken in table name order
+fn aggregate_state_root(table_roots: &HashMap<String, [u8; 32]>) -> [u8; 32] {
+    let mut sorted_table_roots: Vec<_> = table_roots.iter().collect();
+    sorted_table_roots.sort_by_key(|(name, _)| *name); // Sort by table name for determinism
+    let table_root_vecs: Vec<Vec<u8>> = sorted_table_roots.iter().map(|(_, hash)| hash.to_vec()).collect();
+    SecureMerkleTree::from_leaves(&table_root_vecs).root_hash()
+}
+
+/// Ids of rows that differ between a tracked and a freshly captured table, sorted
+fn diff_rows(tracked: Option<&TableState>, captured: Option<&TableState>) -> Vec<String> {
+    let empty = HashMap::new();
+    let tracked = tracked.map_or(&empty, |state| &state.rows);
+    let captured = captured.map_or(&empty, |state| &state.rows);
+    let mut rows: Vec<String> = tracked.keys()
+        .chain(captured.keys().filter(|id| !tracked.contains_key(*id)))
+        .filter(|id| {
+            let hash = |rows: &HashMap<String, Row>| rows.get(*id).map(Row::calculate_hash);
+            hash(tracked) != hash(captured)
+        })
+        .cloned()
+        .collect();
+    rows.sort();
+    rows
+}
+
+/// Tables whose root about to be committed differs from one recomputed from their rows
+///
+/// Each table is checked with probability `sample_rate`. Tables in `pending`
+/// have changes not yet reflected in their committed root and are skipped.
+fn find_root_mismatches(
+    tables: &HashMap<String, TableState>,
+    roots: &HashMap<String, [u8; 32]>,
+    pending: &HashMap<String, u64>,
+    sample_rate: f64,
+) -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    let mut mismatches: Vec<String> = tables.iter()
+        .filter(|(name, _)| !pending.contains_key(*name))
+        .filter(|_| sample_rate >= 1.0 || rng.gen_bool(sample_rate))
+        .filter(|(name, state)| {
+            let mut recaptured = (*state).clone();
+            recaptured.rebuild_merkle_tree();
+            recaptured.root_hash != roots.get(*name).copied()
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    mismatches.sort();
+    mismatches
+}
+
 // Helper for lock poisoning errors (Keep)
 fn poison_err<T>(e: PoisonError<T>) -> ProxyError {
     ProxyError::Verification(format!("State lock poisoned: {}", e))
@@ -625,6 +2090,713 @@ mod tests {
         assert!(manager.get_historical_table_state("users", 0).unwrap().is_none());
     }
     
+    #[test]
+    fn test_max_tables_per_block() {
+        let manager = StateCaptureManager::with_max_tables_per_block(Some(2));
+
+        let names = ["a", "b", "c"];
+        let schemas = names.iter().map(|n| (n.to_string(), create_test_schema(n))).collect();
+        let data = names.iter().map(|n| (n.to_string(), vec![create_test_row(1, "genesis", n)])).collect();
+        let genesis_state = setup_genesis_state(&manager, schemas, data).unwrap();
+        let genesis_roots = genesis_state.table_state_roots.clone();
+        let live_root = |name: &str| manager.get_latest_committed_table_state(name).unwrap().unwrap().root_hash.unwrap();
+
+        // === Block 1: only "a" changes; it is captured fresh, "b" and "c" keep cached roots ===
+        manager.begin_wal_transaction(Some(1)).unwrap();
+        manager.apply_wal_insert("a".to_string(), create_test_row(2, "a2", "a")).unwrap();
+        manager.commit_wal_transaction(10).unwrap();
+
+        let block1 = manager.get_historical_block_state(1).unwrap().unwrap();
+        assert_eq!(block1.table_state_roots["a"], live_root("a"));
+        assert_ne!(block1.table_state_roots["a"], genesis_roots["a"]);
+        assert_eq!(block1.table_state_roots["b"], genesis_roots["b"]);
+        assert_eq!(block1.table_state_roots["c"], genesis_roots["c"]);
+
+        // === Block 2: all three change, but only two fit under the cap ===
+        manager.begin_wal_transaction(Some(2)).unwrap();
+        for name in names {
+            manager.apply_wal_insert(name.to_string(), create_test_row(3, "x", name)).unwrap();
+        }
+        manager.commit_wal_transaction(20).unwrap();
+
+        // The state root still covers every table, with the deferred one at its cached root
+        let block2 = manager.get_historical_block_state(2).unwrap().unwrap();
+        assert_eq!(block2.table_state_roots.len(), 3);
+        let fresh: Vec<_> = names.iter().filter(|n| block2.table_state_roots[**n] == live_root(n)).collect();
+        let deferred: Vec<_> = names.iter().filter(|n| block2.table_state_roots[**n] != live_root(n)).collect();
+        assert_eq!(fresh.len(), 2);
+        assert_eq!(deferred.len(), 1);
+        assert_eq!(block2.table_state_roots[*deferred[0]], block1.table_state_roots[*deferred[0]]);
+
+        // === Block 3: no changes; the deferred table is captured first ===
+        manager.begin_wal_transaction(Some(3)).unwrap();
+        manager.commit_wal_transaction(30).unwrap();
+
+        let block3 = manager.get_historical_block_state(3).unwrap().unwrap();
+        for name in names {
+            assert_eq!(block3.table_state_roots[name], live_root(name));
+        }
+        assert!(manager.dirty_tables.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_incremental_wal_matches_full_recapture() {
+        let incremental = StateCaptureManager::new().with_incremental_wal(true);
+        let full = StateCaptureManager::new();
+
+        for manager in [&incremental, &full] {
+            let schemas = vec![("users".to_string(), create_test_schema("users"))].into_iter().collect();
+            let rows = (1..=6).map(|id| create_test_row(id * 10, "genesis", "users")).collect();
+            let data = vec![("users".to_string(), rows)].into_iter().collect();
+            setup_genesis_state(manager, schemas, data).unwrap();
+        }
+
+        // Inserts in the middle and at the end, updates, deletes, and a primary key change
+        let transactions: Vec<Vec<(&str, i32, i32)>> = vec![
+            vec![("insert", 0, 25), ("insert", 0, 70), ("update", 30, 30)],
+            vec![("delete", 10, 0), ("delete", 70, 0), ("insert", 0, 5)],
+            vec![("update", 40, 45), ("insert", 0, 80), ("insert", 0, 90), ("insert", 0, 100)],
+            vec![("delete", 5, 0), ("delete", 20, 0), ("delete", 25, 0), ("delete", 30, 0)],
+        ];
+
+        for (i, transaction) in transactions.iter().enumerate() {
+            for manager in [&incremental, &full] {
+                manager.begin_wal_transaction(Some(i as u32)).unwrap();
+                for &(op, old_id, new_id) in transaction {
+                    let row = create_test_row(new_id, &format!("tx{}", i), "users");
+                    match op {
+                        "insert" => manager.apply_wal_insert("users".to_string(), row).unwrap(),
+                        "update" => manager.apply_wal_update("users".to_string(), old_id.to_string(), row).unwrap(),
+                        _ => manager.apply_wal_delete("users".to_string(), old_id.to_string()).unwrap(),
+                    }
+                }
+                manager.commit_wal_transaction(i as u64 * 10).unwrap();
+            }
+
+            let incremental_table = incremental.get_latest_committed_table_state("users").unwrap().unwrap();
+            let mut recaptured = incremental_table.clone();
+            recaptured.rebuild_merkle_tree();
+            assert_eq!(incremental_table.root_hash, recaptured.root_hash);
+            assert_eq!(incremental.get_current_root_hash().unwrap(), full.get_current_root_hash().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parallel_hashing_matches_sequential() {
+        let sequential = StateCaptureManager::new();
+        let concurrent = StateCaptureManager::new().with_hash_parallelism(3);
+        let tables = ["accounts", "orders", "users"];
+
+        for manager in [&sequential, &concurrent] {
+            let schemas = tables.iter().map(|t| (t.to_string(), create_test_schema(t))).collect();
+            let data = tables.iter()
+                .map(|t| (t.to_string(), (1..=20).map(|id| create_test_row(id, "genesis", t)).collect()))
+                .collect();
+            setup_genesis_state(manager, schemas, data).unwrap();
+
+            // Touch all three tables so every tree is rebuilt
+            manager.begin_wal_transaction(Some(1)).unwrap();
+            for table in tables {
+                manager.apply_wal_insert(table.to_string(), create_test_row(100, "new", table)).unwrap();
+                manager.apply_wal_update(table.to_string(), "5".to_string(), create_test_row(5, "changed", table)).unwrap();
+                manager.apply_wal_delete(table.to_string(), "7".to_string()).unwrap();
+            }
+            manager.commit_wal_transaction(10).unwrap();
+        }
+
+        assert!(sequential.get_current_root_hash().unwrap().is_some());
+        assert_eq!(sequential.get_current_root_hash().unwrap(), concurrent.get_current_root_hash().unwrap());
+        for table in tables {
+            assert_eq!(
+                sequential.get_latest_committed_table_state(table).unwrap().unwrap().root_hash,
+                concurrent.get_latest_committed_table_state(table).unwrap().unwrap().root_hash,
+            );
+        }
+    }
+
+    #[test]
+    fn test_range_partitioned_table_captured_as_one_table() {
+        let manager = StateCaptureManager::new();
+        let schema = create_test_schema("events").with_partitioning(PartitionScheme {
+            strategy: PartitionStrategy::Range,
+            key: "RANGE (id)".to_string(),
+            partitions: vec![
+                PartitionDefinition { name: "events_p0".to_string(), bound: "FOR VALUES FROM (0) TO (100)".to_string() },
+                PartitionDefinition { name: "events_p1".to_string(), bound: "FOR VALUES FROM (100) TO (200)".to_string() },
+            ],
+        });
+        let schemas = vec![("events".to_string(), schema)].into_iter().collect();
+        let data = vec![("events".to_string(), vec![create_test_row(1, "genesis", "events_p0")])].into_iter().collect();
+        setup_genesis_state(&manager, schemas, data).unwrap();
+
+        // WAL reports changes against the partitions
+        manager.begin_wal_transaction(Some(1)).unwrap();
+        manager.apply_wal_insert("events_p0".to_string(), create_test_row(50, "low", "events_p0")).unwrap();
+        manager.apply_wal_insert("events_p1".to_string(), create_test_row(150, "high", "events_p1")).unwrap();
+        manager.apply_wal_update("events_p0".to_string(), "1".to_string(), create_test_row(1, "changed", "events_p0")).unwrap();
+        manager.commit_wal_transaction(10).unwrap();
+
+        // One logical table, no per-partition tables
+        let block = manager.get_latest_committed_block_state().unwrap().unwrap();
+        assert_eq!(block.table_state_roots.keys().collect::<Vec<_>>(), vec!["events"]);
+        assert!(manager.get_latest_committed_table_state("events_p1").unwrap().is_none());
+
+        let events = manager.get_latest_committed_table_state("events").unwrap().unwrap();
+        assert_eq!(events.row_count, 3);
+        assert_eq!(events.partition_of("150"), Some("events_p1"));
+        assert_eq!(events.partition_of("1"), Some("events_p0"));
+        assert_eq!(block.table_state_roots["events"], events.root_hash.unwrap());
+
+        // A row in the second partition proves against the logical table root
+        let (row, proof_bytes) = events.generate_proof("150").unwrap();
+        assert_eq!(row.table_name, "events");
+        let proof: merkle::SecureMerkleProof = bincode::deserialize(&proof_bytes).unwrap();
+        assert!(proof.verify(&events.root_hash.unwrap()));
+    }
+
+    #[test]
+    fn test_fresh_database_genesis_is_deterministic() {
+        // A fresh database: tables exist but hold no rows
+        let fresh_tables = || -> HashMap<String, TableState> {
+            ["users", "orders"].iter()
+                .map(|name| (name.to_string(), TableState::new(create_test_schema(name))))
+                .collect()
+        };
+
+        let first = StateCaptureManager::new();
+        let second = StateCaptureManager::new();
+        let genesis = first.initialize_genesis(fresh_tables()).unwrap();
+        let replica = second.initialize_genesis(fresh_tables()).unwrap();
+
+        assert_eq!(genesis.header.number, 0);
+        assert_eq!(genesis.header.previous_hash, [0u8; 32]);
+        assert_eq!(genesis.header.state_root, replica.header.state_root);
+        assert_eq!(genesis.header.hash, replica.header.hash);
+        assert_eq!(first.get_current_root_hash().unwrap(), Some(genesis.header.state_root));
+
+        // Initial rows are part of the genesis root
+        let mut seeded = fresh_tables();
+        seeded.get_mut("users").unwrap().insert_row(create_test_row(1, "alice", "users"));
+        let seeded_genesis = StateCaptureManager::new().initialize_genesis(seeded).unwrap();
+        assert_ne!(seeded_genesis.header.state_root, genesis.header.state_root);
+
+        // The first committed block chains onto genesis
+        first.cache_schema(create_test_schema("users"));
+        first.begin_wal_transaction(Some(1)).unwrap();
+        first.apply_wal_insert("users".to_string(), create_test_row(1, "alice", "users")).unwrap();
+        assert_eq!(first.commit_wal_transaction(100).unwrap(), 1);
+        let block1 = first.get_historical_block_state(1).unwrap().unwrap();
+        assert_eq!(Some(block1.header.previous_hash), genesis.header.hash);
+        assert_eq!(block1.header.state_root, seeded_genesis.header.state_root);
+    }
+
+    #[test]
+    fn test_block_metadata_records_postgres_versions() {
+        let manager = StateCaptureManager::new();
+        manager.initialize_genesis(HashMap::new()).unwrap();
+        manager.set_postgres_versions(PostgresVersions { live: 150004, verifier: 140009 }).unwrap();
+
+        manager.cache_schema(create_test_schema("users"));
+        manager.begin_wal_transaction(Some(1)).unwrap();
+        manager.apply_wal_insert("users".to_string(), create_test_row(1, "alice", "users")).unwrap();
+        manager.commit_wal_transaction(100).unwrap();
+
+        let block = manager.get_historical_block_state(1).unwrap().unwrap();
+        assert_eq!(block.header.metadata.postgres_version, "15.4");
+        let additional: serde_json::Value = serde_json::from_str(block.header.metadata.additional_data.as_ref().unwrap()).unwrap();
+        assert_eq!(additional["verifier_postgres_version"], "14.9");
+    }
+
+    #[test]
+    fn test_schema_changes_committed_in_audit_log() {
+        let manager = StateCaptureManager::new().with_schema_audit_log(true);
+        manager.initialize_genesis(HashMap::new()).unwrap();
+
+        // Creating and then altering a table records two linked migrations
+        let users = create_test_schema("users");
+        let created = manager.record_schema_change("CREATE TABLE", "CREATE TABLE users (id int PRIMARY KEY, data text)", vec![("users".to_string(), Some(users.clone()))])
+            .unwrap().unwrap();
+        let mut columns = users.columns.clone();
+        columns.push(ColumnDefinition {
+            name: "age".to_string(),
+            column_type: ColumnType::Integer,
+            nullable: true,
+            primary_key: false,
+            unique: false,
+            default_value: None,
+        });
+        let altered_users = TableSchema::new("users".to_string(), columns, users.primary_keys.clone(), vec![], vec![]);
+        let altered = manager.record_schema_change("ALTER TABLE", "ALTER TABLE users ADD COLUMN age int", vec![("users".to_string(), Some(altered_users))])
+            .unwrap().unwrap();
+        assert_eq!(altered.previous_checksum, created.checksum);
+        assert_eq!(altered.previous_entry_hash, created.entry_hash);
+        assert!(manager.get_schema("users").unwrap().get_column("age").is_some());
+
+        // An index changes no table definition, so nothing is recorded
+        assert!(manager.record_schema_change("CREATE INDEX", "CREATE INDEX users_age ON users (age)", vec![]).unwrap().is_none());
+
+        // The next block commits the new entries and the log's head
+        manager.begin_wal_transaction(Some(1)).unwrap();
+        manager.apply_wal_insert("users".to_string(), create_test_row(1, "alice", "users")).unwrap();
+        manager.commit_wal_transaction(100).unwrap();
+
+        let block = manager.get_historical_block_state(1).unwrap().unwrap();
+        let additional: serde_json::Value = serde_json::from_str(block.header.metadata.additional_data.as_ref().unwrap()).unwrap();
+        let log = manager.get_schema_audit_log().unwrap();
+        assert!(log.verify().is_ok());
+        assert_eq!(additional["schema_audit"]["version"], 3);
+        assert_eq!(additional["schema_audit"]["head"], hex::encode(log.head_hash()));
+        assert_eq!(additional["schema_audit"]["entries"].as_array().unwrap().len(), 2);
+        assert_eq!(additional["schema_audit"]["entries"][1]["statements"][0], "ALTER TABLE users ADD COLUMN age int");
+    }
+
+    #[test]
+    fn test_dangling_foreign_key_flagged_at_commit() {
+        let users = create_test_schema("users");
+        let orders = {
+            let base = create_test_schema("orders");
+            let mut columns = base.columns.clone();
+            columns.push(ColumnDefinition {
+                name: "user_id".to_string(),
+                column_type: ColumnType::Integer,
+                nullable: true,
+                primary_key: false,
+                unique: false,
+                default_value: None,
+            });
+            let foreign_keys = vec![(vec!["user_id".to_string()], "users".to_string(), vec!["id".to_string()])];
+            TableSchema::new("orders".to_string(), columns, base.primary_keys, vec![], foreign_keys)
+        };
+        let order = |id: i32, user_id: Value| {
+            let mut row = create_test_row(id, "order", "orders");
+            row.values.insert("user_id".to_string(), user_id);
+            Row::new(row.id, row.table_name, row.values)
+        };
+        let violations = |manager: &StateCaptureManager| -> Option<serde_json::Value> {
+            let block = manager.get_latest_committed_block_state().unwrap().unwrap();
+            let data: serde_json::Value = serde_json::from_str(&block.header.metadata.additional_data.unwrap()).unwrap();
+            data.get("fk_violations").cloned()
+        };
+
+        let checked = StateCaptureManager::new().with_referential_integrity_check(true);
+        let unchecked = StateCaptureManager::new();
+        for manager in [&checked, &unchecked] {
+            let mut initial = HashMap::new();
+            initial.insert("users".to_string(), TableState::new(users.clone()));
+            initial.insert("orders".to_string(), TableState::new(orders.clone()));
+            initial.get_mut("users").unwrap().insert_row(create_test_row(1, "alice", "users"));
+            manager.initialize_genesis(initial).unwrap();
+
+            // Order 11 points at a user that doesn't exist; order 12 has no user
+            manager.begin_wal_transaction(Some(1)).unwrap();
+            manager.apply_wal_insert("orders".to_string(), order(10, Value::Integer(1))).unwrap();
+            manager.apply_wal_insert("orders".to_string(), order(11, Value::Integer(2))).unwrap();
+            manager.apply_wal_insert("orders".to_string(), order(12, Value::Null)).unwrap();
+            manager.commit_wal_transaction(10).unwrap();
+        }
+
+        let flagged: Vec<ForeignKeyViolation> = serde_json::from_value(violations(&checked).unwrap()).unwrap();
+        assert_eq!(flagged, vec![ForeignKeyViolation {
+            table: "orders".to_string(),
+            row_id: "11".to_string(),
+            columns: vec!["user_id".to_string()],
+            referenced_table: "users".to_string(),
+        }]);
+        // Skipping the check leaves the metadata without a verdict
+        assert!(violations(&unchecked).is_none());
+
+        // Inserting the missing user clears the violation in the next block
+        checked.begin_wal_transaction(Some(2)).unwrap();
+        checked.apply_wal_insert("users".to_string(), create_test_row(2, "bob", "users")).unwrap();
+        checked.commit_wal_transaction(20).unwrap();
+        assert_eq!(violations(&checked), Some(serde_json::json!([])));
+    }
+
+    #[test]
+    fn test_corrupted_cached_root_caught_before_commit() {
+        let manager = StateCaptureManager::new().with_root_validation(1.0);
+        let mut initial = HashMap::new();
+        for name in ["users", "orders"] {
+            let mut table_state = TableState::new(create_test_schema(name));
+            table_state.insert_row(create_test_row(1, "first", name));
+            initial.insert(name.to_string(), table_state);
+        }
+        manager.initialize_genesis(initial).unwrap();
+
+        // Corrupt the cached root of users, which the next block doesn't recapture
+        manager.state_history.write().unwrap().get_mut(&0).unwrap()
+            .table_state_roots.insert("users".to_string(), [7u8; 32]);
+
+        manager.begin_wal_transaction(Some(1)).unwrap();
+        manager.apply_wal_insert("orders".to_string(), create_test_row(2, "second", "orders")).unwrap();
+        let err = manager.commit_wal_transaction(10).unwrap_err();
+        assert!(err.to_string().contains("users"));
+        assert_eq!(manager.get_current_block_number().unwrap(), 0);
+
+        // The next commit recaptures both tables instead of reusing the bad root
+        manager.begin_wal_transaction(Some(2)).unwrap();
+        manager.apply_wal_insert("orders".to_string(), create_test_row(3, "third", "orders")).unwrap();
+        assert_eq!(manager.commit_wal_transaction(20).unwrap(), 1);
+        let block = manager.get_latest_committed_block_state().unwrap().unwrap();
+        let live = manager.live_table_states.read().unwrap();
+        assert_eq!(block.table_state_roots["users"], live["users"].root_hash.unwrap());
+        assert_eq!(block.table_state_roots["orders"], live["orders"].root_hash.unwrap());
+        assert_eq!(live["orders"].row_count, 3);
+    }
+
+    #[test]
+    fn test_reconciliation_detects_external_modification() {
+        let manager = StateCaptureManager::new();
+        let mut initial = HashMap::new();
+        for name in ["users", "orders"] {
+            let mut table_state = TableState::new(create_test_schema(name));
+            table_state.insert_row(create_test_row(1, "first", name));
+            table_state.insert_row(create_test_row(2, "second", name));
+            initial.insert(name.to_string(), table_state);
+        }
+        let genesis = manager.initialize_genesis(initial.clone()).unwrap();
+
+        // A capture of the untouched database matches the chain
+        let report = manager.reconcile_tables(initial.clone()).unwrap();
+        assert!(report.is_consistent());
+        assert_eq!(report.current_root, genesis.header.state_root);
+
+        // A row changed without going through the proxy, and one inserted
+        let mut captured = initial;
+        let users = captured.get_mut("users").unwrap();
+        users.insert_row(create_test_row(2, "tampered", "users"));
+        users.insert_row(create_test_row(3, "injected", "users"));
+        let report = manager.reconcile_tables(captured).unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.block_number, 0);
+        assert_eq!(report.committed_root, genesis.header.state_root);
+        assert_ne!(report.current_root, genesis.header.state_root);
+        assert_eq!(report.divergent_tables.len(), 1);
+        let divergence = &report.divergent_tables[0];
+        assert_eq!(divergence.table, "users");
+        assert_eq!(divergence.committed_root, Some(genesis.table_state_roots["users"]));
+        assert!(!divergence.pending_changes);
+        assert_eq!(divergence.rows, vec!["2".to_string(), "3".to_string()]);
+    }
+
+    #[test]
+    fn test_large_object_write_changes_state_root() {
+        let manager = StateCaptureManager::new();
+        let mut initial = HashMap::new();
+        initial.insert("users".to_string(), TableState::new(create_test_schema("users")));
+        initial.insert(LARGE_OBJECT_TABLE.to_string(), TableState::new(large_object_schema()));
+        let genesis = manager.initialize_genesis(initial).unwrap();
+
+        let snapshot = |objects: &[(i64, i64, &str)]| {
+            let mut state = TableState::new(large_object_schema());
+            for &(loid, size, hash) in objects {
+                state.insert_row(large_object_row(loid, size, hash.to_string(), None));
+            }
+            state
+        };
+
+        // lo_create + lo_put: a new object with content
+        manager.commit_large_objects(snapshot(&[(16400, 5, "aa")]), 10).unwrap();
+        let created = manager.get_current_root_hash().unwrap().unwrap();
+        assert_ne!(created, genesis.header.state_root);
+
+        // Overwriting the content in place changes the root again
+        manager.commit_large_objects(snapshot(&[(16400, 5, "bb")]), 20).unwrap();
+        let overwritten = manager.get_current_root_hash().unwrap().unwrap();
+        assert_ne!(overwritten, created);
+        let objects = manager.get_latest_committed_table_state(LARGE_OBJECT_TABLE).unwrap().unwrap();
+        assert_eq!(objects.get_row("16400").unwrap().values["content_hash"], Value::Text("bb".to_string()));
+
+        // An unchanged snapshot leaves the root as it was
+        manager.commit_large_objects(snapshot(&[(16400, 5, "bb")]), 30).unwrap();
+        assert_eq!(manager.get_current_root_hash().unwrap().unwrap(), overwritten);
+
+        // lo_unlink removes the object from the committed state
+        manager.commit_large_objects(snapshot(&[]), 40).unwrap();
+        let objects = manager.get_latest_committed_table_state(LARGE_OBJECT_TABLE).unwrap().unwrap();
+        assert_eq!(objects.row_count, 0);
+    }
+
+    async fn connect_test_database() -> tokio_postgres::Client {
+        let config = format!(
+            "host={} port={} user={} password={} dbname={}",
+            std::env::var("PG_HOST").unwrap_or_else(|_| "localhost".to_string()),
+            std::env::var("PG_PORT").unwrap_or_else(|_| "5432".to_string()),
+            std::env::var("PG_USER").unwrap_or_else(|_| "verifiable".to_string()),
+            std::env::var("PG_PASSWORD").unwrap_or_else(|_| "verifiable".to_string()),
+            std::env::var("PG_DATABASE").unwrap_or_else(|_| "verifiable_db".to_string()),
+        );
+        let (client, connection) = tokio_postgres::connect(&config, tokio_postgres::NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client
+    }
+
+    #[tokio::test]
+    #[ignore] // Needs a PostgreSQL server, configured through the PG_* variables
+    async fn test_snapshot_capture_excludes_concurrent_writes() {
+        let capturer = connect_test_database().await;
+        let writer = connect_test_database().await;
+        writer.batch_execute(
+            "DROP TABLE IF EXISTS snapshot_a, snapshot_b; \
+             CREATE TABLE snapshot_a (id integer PRIMARY KEY, data text); \
+             CREATE TABLE snapshot_b (id integer PRIMARY KEY, data text); \
+             INSERT INTO snapshot_a VALUES (1, 'a'); \
+             INSERT INTO snapshot_b VALUES (1, 'b');"
+        ).await.unwrap();
+
+        let manager = StateCaptureManager::new().with_snapshot_isolation(true);
+        let snapshot_id = StateCaptureManager::begin_capture_snapshot(&capturer).await.unwrap();
+        let first = manager.capture_table(&capturer, "snapshot_a").await.unwrap();
+        // Another session commits to both tables between the two captures
+        writer.batch_execute("INSERT INTO snapshot_a VALUES (2, 'late'); INSERT INTO snapshot_b VALUES (2, 'late');").await.unwrap();
+        let second = manager.capture_table(&capturer, "snapshot_b").await.unwrap();
+        StateCaptureManager::end_capture_snapshot(&capturer, true).await.unwrap();
+
+        assert_eq!(first.row_count, 1);
+        assert_eq!(second.row_count, 1);
+        assert!(second.get_row("2").is_none());
+
+        // The block records the snapshot it was captured in
+        let mut tables = HashMap::from([("snapshot_a".to_string(), first), ("snapshot_b".to_string(), second)]);
+        let genesis = build_genesis_state(&mut tables, Some(&snapshot_id), None, None);
+        let metadata: serde_json::Value = serde_json::from_str(&genesis.header.metadata.additional_data.unwrap()).unwrap();
+        assert_eq!(metadata["snapshot_id"], snapshot_id);
+
+        writer.batch_execute("DROP TABLE snapshot_a, snapshot_b").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Needs a PostgreSQL server, configured through the PG_* variables
+    async fn test_streamed_capture_matches_buffered_capture() {
+        let client = connect_test_database().await;
+        client.batch_execute(
+            "DROP TABLE IF EXISTS capture_large; \
+             CREATE TABLE capture_large (tenant integer, id integer, data text, PRIMARY KEY (tenant, id)); \
+             INSERT INTO capture_large SELECT i % 7, i, md5(i::text) FROM generate_series(1, 50000) i;"
+        ).await.unwrap();
+
+        let manager = StateCaptureManager::new();
+        let streamed = manager.capture_table(&client, "capture_large").await.unwrap();
+
+        // Buffer the whole result set and insert row by row, as captures used to
+        let schema = streamed.schema.clone();
+        let capture = RowCapture::new(&schema);
+        let pg_rows = client.query(capture.query.as_str(), &[]).await.unwrap();
+        let mut buffered = TableState::new(schema.clone());
+        for (index, pg_row) in pg_rows.iter().enumerate() {
+            let row = capture.row(pg_row, index, &schema).unwrap();
+            buffered.rows.insert(row.id.clone(), row);
+        }
+        buffered.row_count = buffered.rows.len();
+        buffered.rebuild_merkle_tree();
+
+        assert_eq!(streamed.row_count, 50000);
+        assert_eq!(streamed.root_hash, buffered.root_hash);
+        assert!(streamed.get_row("6,50000").is_some());
+
+        client.batch_execute("DROP TABLE capture_large").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Needs a PostgreSQL server, configured through the PG_* variables
+    async fn test_capture_sees_rows_hidden_by_row_security() {
+        let client = connect_test_database().await;
+        client.batch_execute(
+            "DROP TABLE IF EXISTS rls_accounts; \
+             DROP ROLE IF EXISTS rls_client; \
+             CREATE ROLE rls_client NOLOGIN; \
+             CREATE TABLE rls_accounts (id integer PRIMARY KEY, owner text); \
+             INSERT INTO rls_accounts VALUES (1, 'alice'), (2, 'bob'); \
+             ALTER TABLE rls_accounts ENABLE ROW LEVEL SECURITY; \
+             ALTER TABLE rls_accounts FORCE ROW LEVEL SECURITY; \
+             CREATE POLICY own_rows ON rls_accounts USING (owner = current_user); \
+             GRANT SELECT ON rls_accounts TO rls_client;"
+        ).await.unwrap();
+
+        // A client subject to the policy sees none of the rows
+        client.batch_execute("SET ROLE rls_client").await.unwrap();
+        let visible: i64 = client.query_one("SELECT count(*) FROM rls_accounts", &[]).await.unwrap().get(0);
+        client.batch_execute("RESET ROLE").await.unwrap();
+        assert_eq!(visible, 0);
+
+        // The capture sees every row, and records the context it ran under
+        let manager = StateCaptureManager::new();
+        let context = manager.begin_capture_context(&client).await.unwrap();
+        let captured = manager.capture_table(&client, "rls_accounts").await;
+        StateCaptureManager::end_capture_context(&client).await.unwrap();
+        assert_eq!(captured.unwrap().row_count, 2);
+        assert!(context.bypass_rls);
+        assert!(!context.row_security);
+        assert!(context.rls_tables.contains(&"rls_accounts".to_string()));
+
+        let genesis = build_genesis_state(&mut HashMap::new(), None, Some(&context), None);
+        let metadata: serde_json::Value = serde_json::from_str(&genesis.header.metadata.additional_data.unwrap()).unwrap();
+        assert_eq!(metadata["capture_context"], serde_json::json!(context));
+
+        // A capture role subject to the policy fails rather than capturing a filtered table
+        let manager = StateCaptureManager::new().with_capture_role(Some("rls_client".to_string()));
+        let context = manager.begin_capture_context(&client).await.unwrap();
+        let captured = manager.capture_table(&client, "rls_accounts").await;
+        StateCaptureManager::end_capture_context(&client).await.unwrap();
+        assert_eq!(context.role, "rls_client");
+        assert!(!context.bypass_rls);
+        assert!(captured.is_err());
+
+        client.batch_execute("DROP TABLE rls_accounts; DROP ROLE rls_client").await.unwrap();
+    }
+
+    fn wal_record(txid: u64, lsn: u64, record_type: WalRecordType, table: &str, data: Vec<u8>) -> WalRecord {
+        WalRecord {
+            lsn,
+            txid,
+            timestamp: 0,
+            record_type,
+            relation_id: None,
+            relation_name: Some(table.to_string()),
+            data,
+            checksum: [0; 32],
+            is_savepoint: false,
+            savepoint_name: None,
+        }
+    }
+
+    fn wal_row(txid: u64, lsn: u64, record_type: WalRecordType, row: &Row) -> WalRecord {
+        wal_record(txid, lsn, record_type, &row.table_name, serde_json::to_vec(row).unwrap())
+    }
+
+    #[test]
+    fn test_wal_delta_root_matches_recapture() {
+        let initial = || {
+            let mut tables = HashMap::new();
+            for (name, ids) in [("users", 1..=3), ("orders", 1..=1), ("audit", 1..=2), ("untouched", 1..=4)] {
+                let mut table_state = TableState::new(create_test_schema(name));
+                for id in ids {
+                    table_state.insert_row(create_test_row(id, "initial", name));
+                }
+                tables.insert(name.to_string(), table_state);
+            }
+            tables
+        };
+        let records = vec![
+            // Committed: insert, update and delete in users
+            wal_record(10, 100, WalRecordType::Begin, "", vec![]),
+            wal_row(10, 101, WalRecordType::Insert, &create_test_row(4, "new", "users")),
+            wal_row(10, 102, WalRecordType::Update, &create_test_row(2, "changed", "users")),
+            wal_record(10, 103, WalRecordType::Delete, "users", b"3".to_vec()),
+            wal_record(10, 104, WalRecordType::Commit, "", vec![]),
+            // Aborted: never applied
+            wal_row(11, 105, WalRecordType::Insert, &create_test_row(5, "aborted", "users")),
+            wal_record(11, 106, WalRecordType::Abort, "", vec![]),
+            // Order matters: a row inserted then deleted is gone, one updated twice keeps its last version
+            wal_row(12, 107, WalRecordType::Insert, &create_test_row(2, "transient", "orders")),
+            wal_record(12, 108, WalRecordType::Delete, "orders", b"2".to_vec()),
+            wal_row(12, 109, WalRecordType::Update, &create_test_row(1, "first", "orders")),
+            wal_row(12, 110, WalRecordType::Update, &create_test_row(1, "second", "orders")),
+            // Truncate removes tracked rows and rows inserted earlier in the delta
+            wal_row(13, 111, WalRecordType::Insert, &create_test_row(3, "new", "audit")),
+            wal_record(13, 112, WalRecordType::Truncate, "audit", vec![]),
+            wal_row(13, 113, WalRecordType::Insert, &create_test_row(9, "after truncate", "audit")),
+        ];
+
+        // The same final rows, captured from scratch
+        let mut recaptured = initial();
+        let users = recaptured.get_mut("users").unwrap();
+        users.insert_row(create_test_row(4, "new", "users"));
+        users.update_row(create_test_row(2, "changed", "users"));
+        users.delete_row("3");
+        recaptured.get_mut("orders").unwrap().update_row(create_test_row(1, "second", "orders"));
+        let mut audit = TableState::new(create_test_schema("audit"));
+        audit.insert_row(create_test_row(9, "after truncate", "audit"));
+        recaptured.insert("audit".to_string(), audit);
+        let expected_root = build_genesis_state(&mut recaptured, None, None, None).header.state_root;
+
+        for incremental in [false, true] {
+            let manager = StateCaptureManager::new().with_incremental_wal(incremental);
+            manager.initialize_genesis(initial()).unwrap();
+            let untouched_root = manager.get_latest_committed_block_state().unwrap().unwrap().table_state_roots["untouched"];
+
+            assert_eq!(manager.apply_wal_delta(&records).unwrap(), expected_root);
+            assert_eq!(manager.get_current_block_number().unwrap(), 1);
+            let block = manager.get_latest_committed_block_state().unwrap().unwrap();
+            assert_eq!(block.table_state_roots["untouched"], untouched_root);
+            assert!(manager.get_latest_committed_table_state("users").unwrap().unwrap().get_row("5").is_none());
+
+            // DDL can't be applied as a delta, and nothing is committed
+            let ddl = [wal_record(14, 114, WalRecordType::Ddl, "users", vec![])];
+            assert!(manager.apply_wal_delta(&ddl).is_err());
+            assert_eq!(manager.get_current_block_number().unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn test_primary_key_ids_never_collide() {
+        // Tuples that collide when values are joined verbatim
+        let tuples: Vec<Vec<&str>> = vec![
+            vec!["a,b", "c"],
+            vec!["a", "b,c"],
+            vec!["a\\", "b"],
+            vec!["a\\,b"],
+            vec!["a", "", "b"],
+            vec!["a,", "b"],
+            vec!["a", ",b"],
+            vec!["a\\", ",b"],
+        ];
+        let ids: HashSet<String> = tuples.iter().map(|tuple| primary_key_id(tuple.iter().copied())).collect();
+        assert_eq!(ids.len(), tuples.len());
+
+        // Plain keys keep their value
+        assert_eq!(primary_key_id(["42"]), "42");
+        assert_eq!(primary_key_id(["7", "alice"]), "7,alice");
+        assert_eq!(primary_key_id(["a,b", "c"]), "a\\,b,c");
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_capture_root_independent_of_completion_order() {
+        let names: Vec<String> = (0..8).map(|i| format!("table_{}", i)).collect();
+        let capture = |reverse: bool| {
+            let names = names.clone();
+            async move {
+                capture_tables(names, 4, |name| async move {
+                    // Later tables finish first, or last
+                    let index: u64 = name.trim_start_matches("table_").parse().unwrap();
+                    let delay = if reverse { 8 - index } else { index };
+                    tokio::time::sleep(std::time::Duration::from_millis(delay * 5)).await;
+
+                    let mut table_state = TableState::new(create_test_schema(&name));
+                    table_state.insert_row(create_test_row(index as i32, &name, &name));
+                    table_state.rebuild_merkle_tree();
+                    Ok(table_state)
+                }).await.unwrap()
+            }
+        };
+        let root = |tables: &HashMap<String, TableState>| {
+            let roots: HashMap<String, [u8; 32]> = tables.iter()
+                .map(|(name, table_state)| (name.clone(), table_state.root_hash.unwrap()))
+                .collect();
+            aggregate_state_root(&roots)
+        };
+
+        let forward = capture(false).await;
+        let backward = capture(true).await;
+        assert_eq!(forward.len(), 8);
+        assert_eq!(root(&forward), root(&backward));
+        let sequential = capture_tables(names.clone(), 1, |name| {
+            let table_state = forward[&name].clone();
+            async move { Ok(table_state) }
+        }).await.unwrap();
+        assert_eq!(root(&sequential), root(&forward));
+
+        // A single failed table fails the capture and is named
+        let err = capture_tables(names, 4, |name| async move {
+            if name == "table_5" {
+                Err(ProxyError::Database("permission denied".to_string()))
+            } else {
+                Ok(TableState::new(create_test_schema(&name)))
+            }
+        }).await.unwrap_err();
+        assert!(err.to_string().contains("table_5"));
+    }
+
     // TODO: Add test_wal_errors
     // TODO: Add test_schema_handling_on_commit
 }
\ No newline at end of file
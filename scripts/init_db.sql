@@ -67,6 +67,19 @@ CREATE TABLE IF NOT EXISTS verification_blocks (
     FOREIGN KEY (previous_block_number) REFERENCES verification_blocks(block_number)
 );
 
+-- Create verification_commitment_queue table to track on-chain commitments
+-- until they are confirmed (times are Unix milliseconds)
+CREATE TABLE IF NOT EXISTS verification_commitment_queue (
+    block_number BIGINT PRIMARY KEY,
+    state_root VARCHAR(66) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at BIGINT NOT NULL,
+    last_error TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
 -- Create verification_transactions table to store transaction information
 CREATE TABLE IF NOT EXISTS verification_transactions (
     tx_id BIGSERIAL PRIMARY KEY,
@@ -3,7 +3,7 @@ use axum::{
     routing::{get, post},
     extract::{Path, Query, State, Json as AxumJson},
     response::{IntoResponse, Json},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -13,8 +13,10 @@ use verifiable_db_core::models::{
     BlockState, 
     BlockHeader, 
     Challenge, ChallengeType, ChallengeStatus, 
+    Operation,
     TransactionRecord
 };
+use verifiable_db_core::crypto;
 use verifiable_db_core::merkle::SecureMerkleProof;
 
 /// Common response type that can be either data or an error
@@ -32,6 +34,42 @@ pub struct AppState {
     
     /// History of database states (blocks)
     pub state_history: RwLock<HashMap<u64, BlockState>>,
+    
+    /// Verification results, keyed by transaction ID
+    pub verification_results: RwLock<HashMap<u64, TransactionVerification>>,
+    
+    /// Bearer token required to download operation logs (`None` = disabled)
+    ///
+    /// Before-images can contain data the client never read, so the logs
+    /// are only served to holders of this token.
+    pub operation_log_token: Option<String>,
+    
+    /// Maximum number of transactions accepted in one batch record check
+    pub max_batch_size: usize,
+}
+
+/// Default limit on the number of transactions in one batch record check
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+
+/// Outcome of verifying a single transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationOutcome {
+    /// Replay reproduced the recorded state
+    Verified,
+    /// Replay did not reproduce the recorded state
+    Failed,
+    /// Replay was not performed
+    Skipped,
+}
+
+/// Stored verification result for a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionVerification {
+    /// Outcome of the verification
+    pub outcome: VerificationOutcome,
+    /// Time spent verifying, in milliseconds
+    pub verification_time_ms: u64,
 }
 
 /// Create a new API router with the specified state
@@ -40,8 +78,12 @@ pub fn create_router(state: Arc<AppState>) -> Router {
         .route("/api/v1/state-root/:block_number", get(get_state_root))
         .route("/api/v1/state-root/latest", get(get_latest_state_root))
         .route("/api/v1/table-state/:table_name", get(get_table_state))
+        .route("/api/v1/table-state/:table_name/history", get(get_table_state_history))
+        .route("/api/v1/block/:number/summary", get(get_block_summary))
+        .route("/api/v1/transaction/:id/operations", get(get_transaction_operations))
         .route("/api/v1/proof/row/:table/:primary_key", get(get_row_proof))
-        .route("/api/v1/verify/transaction", post(verify_transaction))
+        .route("/api/v1/check/transaction", post(check_transaction))
+        .route("/api/v1/check/transactions", post(check_transactions))
         .route("/api/v1/challenge", post(submit_challenge))
         .with_state(state)
 }
@@ -164,6 +206,274 @@ async fn get_table_state(
     response
 }
 
+/// Query parameters for table root history
+#[derive(Debug, Deserialize)]
+struct TableHistoryQuery {
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+/// A single change in a table's root over a block range
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct TableRootHistoryEntry {
+    block_number: u64,
+    /// hex encoded root, `None` when the table did not exist at this block
+    table_root: Option<String>,
+    exists: bool,
+}
+
+/// Response for table root history endpoint
+#[derive(Debug, Serialize)]
+struct TableHistoryResponse {
+    table_name: String,
+    from_block: u64,
+    to_block: u64,
+    changes: Vec<TableRootHistoryEntry>,
+}
+
+/// Collect the blocks in `[from, to]` at which the root of `table_name` changed.
+///
+/// The first stored block in the range is always reported so the caller has a
+/// starting point. Blocks where the table is absent are reported once with
+/// `exists: false`, so gaps in the table's lifetime are visible.
+fn table_root_history(
+    history: &HashMap<u64, BlockState>,
+    table_name: &str,
+    from: u64,
+    to: u64,
+) -> Vec<TableRootHistoryEntry> {
+    let mut block_numbers: Vec<u64> = history
+        .keys()
+        .copied()
+        .filter(|number| *number >= from && *number <= to)
+        .collect();
+    block_numbers.sort_unstable();
+
+    let mut changes = Vec::new();
+    let mut previous: Option<Option<[u8; 32]>> = None;
+
+    for number in block_numbers {
+        let root = history[&number].get_table_state_root(table_name);
+        if previous == Some(root) {
+            continue;
+        }
+
+        changes.push(TableRootHistoryEntry {
+            block_number: number,
+            table_root: root.map(hex::encode),
+            exists: root.is_some(),
+        });
+        previous = Some(root);
+    }
+
+    changes
+}
+
+/// Get the history of a table's root across a range of blocks
+async fn get_table_state_history(
+    State(state): State<Arc<AppState>>,
+    Path(table_name): Path<String>,
+    Query(params): Query<TableHistoryQuery>,
+) -> impl IntoResponse {
+    let state_history = state.state_history.read().await;
+
+    let from = params.from.unwrap_or(0);
+    let to = params
+        .to
+        .or_else(|| state_history.keys().max().copied())
+        .unwrap_or(0);
+
+    if from > to {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::Error {
+                error: "`from` must not be greater than `to`".to_string()
+            })
+        );
+    }
+
+    let changes = table_root_history(&state_history, &table_name, from, to);
+    if changes.iter().all(|entry| !entry.exists) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::Error {
+                error: "Table not found in the requested block range".to_string()
+            })
+        );
+    }
+
+    let data = TableHistoryResponse {
+        table_name,
+        from_block: from,
+        to_block: to,
+        changes,
+    };
+
+    (StatusCode::OK, Json(ApiResponse::Success(data)))
+}
+
+/// Response for block summary endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct BlockSummaryResponse {
+    block_number: u64,
+    state_root: String, // hex encoded
+    transactions_root: String, // hex encoded
+    transaction_count: usize,
+    verified: usize,
+    failed: usize,
+    skipped: usize,
+    /// Transactions without a stored verification result
+    pending: usize,
+    total_verification_time_ms: u64,
+    /// Set when any transaction in the block failed verification
+    has_failures: bool,
+}
+
+/// Aggregate the verification results of a block's transactions
+fn block_summary(
+    header: &BlockHeader,
+    transaction_ids: impl IntoIterator<Item = u64>,
+    results: &HashMap<u64, TransactionVerification>,
+) -> BlockSummaryResponse {
+    let mut summary = BlockSummaryResponse {
+        block_number: header.number,
+        state_root: hex::encode(header.state_root),
+        transactions_root: hex::encode(header.transactions_root),
+        transaction_count: 0,
+        verified: 0,
+        failed: 0,
+        skipped: 0,
+        pending: 0,
+        total_verification_time_ms: 0,
+        has_failures: false,
+    };
+
+    for id in transaction_ids {
+        summary.transaction_count += 1;
+
+        let result = match results.get(&id) {
+            Some(result) => result,
+            None => {
+                summary.pending += 1;
+                continue;
+            }
+        };
+
+        match result.outcome {
+            VerificationOutcome::Verified => summary.verified += 1,
+            VerificationOutcome::Failed => summary.failed += 1,
+            VerificationOutcome::Skipped => summary.skipped += 1,
+        }
+        summary.total_verification_time_ms += result.verification_time_ms;
+    }
+
+    summary.has_failures = summary.failed > 0;
+    summary
+}
+
+/// Get the verification summary for a block
+async fn get_block_summary(
+    State(state): State<Arc<AppState>>,
+    Path(number): Path<u64>,
+) -> impl IntoResponse {
+    let state_history = state.state_history.read().await;
+
+    let block = match state_history.get(&number) {
+        Some(block) => block,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::Error {
+                    error: "Block not found".to_string()
+                })
+            );
+        }
+    };
+
+    let results = state.verification_results.read().await;
+    let data = block_summary(
+        &block.header,
+        block.transactions.values().map(|transaction| transaction.id),
+        &results,
+    );
+
+    (StatusCode::OK, Json(ApiResponse::Success(data)))
+}
+
+/// Response for the operation log endpoint
+#[derive(Debug, Clone, Serialize)]
+struct OperationLogResponse {
+    transaction_id: u64,
+    block_number: u64,
+    pre_state_root: String, // hex encoded
+    post_state_root: String, // hex encoded
+    /// Operations in execution order, with row images before and after each
+    operations: Vec<Operation>,
+}
+
+impl From<&TransactionRecord> for OperationLogResponse {
+    fn from(record: &TransactionRecord) -> Self {
+        OperationLogResponse {
+            transaction_id: record.id,
+            block_number: record.block_number,
+            pre_state_root: hex::encode(record.pre_state_root),
+            post_state_root: hex::encode(record.post_state_root),
+            operations: record.operations.clone(),
+        }
+    }
+}
+
+/// Check the request's bearer token against the configured operation log token
+fn authorize_operation_log(expected: Option<&str>, headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+    let expected = expected.ok_or((StatusCode::FORBIDDEN, "Operation log access is disabled"))?;
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match provided {
+        Some(token) if token == expected => Ok(()),
+        Some(_) => Err((StatusCode::FORBIDDEN, "Invalid operation log token")),
+        None => Err((StatusCode::UNAUTHORIZED, "Missing operation log token")),
+    }
+}
+
+/// Find a transaction in the recorded blocks
+fn find_transaction<'a>(
+    blocks: impl IntoIterator<Item = &'a BlockState>,
+    transaction_id: u64,
+) -> Option<&'a TransactionRecord> {
+    blocks
+        .into_iter()
+        .flat_map(|block| block.transactions.values())
+        .find(|transaction| transaction.id == transaction_id)
+}
+
+/// Get the ordered operation log of a transaction, for building fraud proofs
+async fn get_transaction_operations(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Err((status, error)) = authorize_operation_log(state.operation_log_token.as_deref(), &headers) {
+        return (status, Json(ApiResponse::Error { error: error.to_string() }));
+    }
+
+    let state_history = state.state_history.read().await;
+    let db_state = state.db_state.read().await;
+
+    match find_transaction(state_history.values().chain(db_state.iter()), id) {
+        Some(record) => (StatusCode::OK, Json(ApiResponse::Success(OperationLogResponse::from(record)))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::Error {
+                error: "Transaction not found".to_string()
+            })
+        ),
+    }
+}
+
 /// Query parameters for row proof
 #[derive(Debug, Deserialize)]
 struct RowProofQuery {
@@ -176,6 +486,8 @@ struct RowProofResponse {
     table_name: String,
     primary_key: String, // Assuming primary key is still a string for identification
     proof: SecureMerkleProof, // Use the core proof type
+    /// `proof` ABI-encoded for on-chain verifiers, 0x-prefixed hex
+    abi_proof: String,
     state_root: String, // hex encoded root of the overall state tree
     block_number: u64,
 }
@@ -215,6 +527,7 @@ async fn get_row_proof(
             let data = RowProofResponse {
                 table_name,
                 primary_key,
+                abi_proof: format!("0x{}", hex::encode(proof.to_abi_bytes())),
                 proof, // Placeholder
                 state_root: hex::encode(db_state.header.state_root),
                 block_number: db_state.header.number,
@@ -232,46 +545,143 @@ async fn get_row_proof(
     }
 }
 
-/// Request for verifying a transaction - Update if it uses core types
+/// Request for checking a transaction against its record
 #[derive(Debug, Deserialize)]
-struct VerifyTransactionRequest {
+struct CheckTransactionRequest {
     transaction_id: u64, // Assuming using u64 based on memo item #4
-    // Include other necessary fields like pre/post state roots, operations etc.
-    // These might need to align with core::TransactionRecord or core::Operation
     pre_state_root: String, // hex encoded
     post_state_root: String, // hex encoded
-    operations: Vec<String>, // Placeholder: Needs proper Operation type alignment
+    /// SQL of the transaction's operations in order; empty to skip the check
+    operations: Vec<String>,
 }
 
-/// Response for transaction verification - Update if it uses core types
+/// Result of checking a transaction against its record
+///
+/// Nothing is replayed: `consistent` only says the submitted roots and
+/// operations are the ones recorded, not that executing them yields the
+/// post-state root.
 #[derive(Debug, Serialize)]
-struct VerifyTransactionResponse {
+struct CheckTransactionResponse {
     transaction_id: u64,
-    verified: bool,
+    consistent: bool,
     reason: Option<String>,
 }
 
-// TODO: Update verify_transaction logic based on core types and actual verification flow
-async fn verify_transaction(
+/// Response for batch transaction record checks
+#[derive(Debug, Serialize)]
+struct BatchCheckResponse {
+    /// Per-transaction results, in request order
+    results: Vec<CheckTransactionResponse>,
+    consistent: usize,
+    inconsistent: usize,
+}
+
+/// Decode a hex encoded (optionally `0x` prefixed) 32-byte root
+fn decode_root(root: &str) -> Option<[u8; 32]> {
+    hex::decode(root.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+}
+
+/// Check a hex encoded root against a recorded one in constant time
+fn root_matches(root: &str, recorded: &[u8; 32]) -> bool {
+    decode_root(root).is_some_and(|root| crypto::verify_hash(&root, recorded))
+}
+
+/// Check a request against the recorded transaction
+fn check_against_record(record: Option<&TransactionRecord>, request: &CheckTransactionRequest) -> CheckTransactionResponse {
+    let mismatch = match record {
+        None => Some("Transaction not found"),
+        Some(record) if !root_matches(&request.pre_state_root, &record.pre_state_root) => {
+            Some("Pre-state root does not match the recorded transaction")
+        }
+        Some(record) if !root_matches(&request.post_state_root, &record.post_state_root) => {
+            Some("Post-state root does not match the recorded transaction")
+        }
+        Some(record) if !request.operations.is_empty()
+            && !request.operations.iter().eq(record.operations.iter().map(|op| &op.sql)) => {
+            Some("Operations do not match the recorded transaction")
+        }
+        Some(_) => None,
+    };
+
+    CheckTransactionResponse {
+        transaction_id: request.transaction_id,
+        consistent: mismatch.is_none(),
+        reason: Some(mismatch.unwrap_or("Transaction matches its record").to_string()),
+    }
+}
+
+/// Check a batch of requests against the recorded blocks
+///
+/// The blocks are indexed once for the whole batch instead of being searched
+/// per transaction. Each request gets its own result, in request order.
+fn check_batch<'a>(
+    blocks: impl IntoIterator<Item = &'a BlockState>,
+    requests: &[CheckTransactionRequest],
+) -> Vec<CheckTransactionResponse> {
+    let records: HashMap<u64, &TransactionRecord> = blocks
+        .into_iter()
+        .flat_map(|block| block.transactions.values())
+        .map(|record| (record.id, record))
+        .collect();
+
+    requests
+        .iter()
+        .map(|request| check_against_record(records.get(&request.transaction_id).copied(), request))
+        .collect()
+}
+
+/// Check a transaction's roots and operations against its record
+///
+/// A consistency check, not a replay, so no verification outcome is recorded.
+async fn check_transaction(
     State(state): State<Arc<AppState>>,
-    AxumJson(request): AxumJson<VerifyTransactionRequest>,
+    AxumJson(request): AxumJson<CheckTransactionRequest>,
 ) -> impl IntoResponse {
-    // In a real implementation, this would:
-    // 1. Restore the database to the pre-state
-    // 2. Execute the transaction
-    // 3. Calculate the new state root
-    // 4. Compare with the expected state root
-    
-    // For this simplified version, we'll just return a placeholder response
-    let response = VerifyTransactionResponse {
-        transaction_id: request.transaction_id,
-        verified: true,
-        reason: Some("Transaction verified successfully".to_string()),
+    let response = {
+        let state_history = state.state_history.read().await;
+        let db_state = state.db_state.read().await;
+        let record = find_transaction(state_history.values().chain(db_state.iter()), request.transaction_id);
+        check_against_record(record, &request)
     };
     
     (StatusCode::OK, Json(response))
 }
 
+/// Check many transactions against their records in one request
+///
+/// A transaction that doesn't match its record is reported in its own
+/// result and doesn't fail the batch.
+async fn check_transactions(
+    State(state): State<Arc<AppState>>,
+    AxumJson(requests): AxumJson<Vec<CheckTransactionRequest>>,
+) -> impl IntoResponse {
+    if requests.len() > state.max_batch_size {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(ApiResponse::Error {
+                error: format!("Batch of {} transactions exceeds the limit of {}", requests.len(), state.max_batch_size)
+            })
+        );
+    }
+    
+    let results = {
+        let state_history = state.state_history.read().await;
+        let db_state = state.db_state.read().await;
+        check_batch(state_history.values().chain(db_state.iter()), &requests)
+    };
+    
+    let consistent = results.iter().filter(|result| result.consistent).count();
+    let response = BatchCheckResponse {
+        inconsistent: results.len() - consistent,
+        consistent,
+        results,
+    };
+    
+    (StatusCode::OK, Json(ApiResponse::Success(response)))
+}
+
 /// Request for submitting a challenge - Update if it uses core types
 #[derive(Debug, Deserialize)]
 struct ChallengeRequest {
@@ -305,4 +715,181 @@ async fn submit_challenge(
     };
     
     (StatusCode::OK, Json(response))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use verifiable_db_core::models::{BlockMetadata, OperationType, Row, TransactionType, Value};
+
+    fn block_with_tables(number: u64, tables: &[(&str, [u8; 32])]) -> BlockState {
+        let metadata = BlockMetadata {
+            postgres_version: "14.0".to_string(),
+            protocol_version: "1.0".to_string(),
+            operator_id: "operator1".to_string(),
+            operator_signature: None,
+            operator_public_key: None,
+            additional_data: None,
+        };
+
+        let header = BlockHeader::new(number, [0; 32], [0; 32], [number as u8; 32], Utc::now(), metadata);
+        let table_state_roots = tables
+            .iter()
+            .map(|(name, root)| (name.to_string(), *root))
+            .collect();
+
+        BlockState::new(header, HashMap::new(), table_state_roots)
+    }
+
+    #[test]
+    fn test_table_root_history_reports_only_changes() {
+        let mut history = HashMap::new();
+        history.insert(1, block_with_tables(1, &[]));
+        history.insert(2, block_with_tables(2, &[("users", [1; 32])]));
+        history.insert(3, block_with_tables(3, &[("users", [1; 32])]));
+        history.insert(4, block_with_tables(4, &[("users", [2; 32])]));
+        history.insert(5, block_with_tables(5, &[("users", [2; 32]), ("orders", [9; 32])]));
+        history.insert(6, block_with_tables(6, &[("orders", [9; 32])]));
+
+        let changes = table_root_history(&history, "users", 1, 6);
+        let blocks: Vec<u64> = changes.iter().map(|entry| entry.block_number).collect();
+        assert_eq!(blocks, vec![1, 2, 4, 6]);
+
+        // The table did not exist yet at block 1 and was dropped by block 6
+        assert!(!changes[0].exists);
+        assert_eq!(changes[1].table_root, Some(hex::encode([1u8; 32])));
+        assert_eq!(changes[2].table_root, Some(hex::encode([2u8; 32])));
+        assert!(!changes[3].exists);
+        assert_eq!(changes[3].table_root, None);
+
+        // A narrower range starts from the root at the first block in range
+        let changes = table_root_history(&history, "users", 3, 5);
+        let blocks: Vec<u64> = changes.iter().map(|entry| entry.block_number).collect();
+        assert_eq!(blocks, vec![3, 4]);
+    }
+
+    #[test]
+    fn test_block_summary_counts_mixed_outcomes() {
+        let block = block_with_tables(7, &[("users", [1; 32])]);
+
+        let mut results = HashMap::new();
+        let mut record = |id: u64, outcome: VerificationOutcome, ms: u64| {
+            results.insert(id, TransactionVerification { outcome, verification_time_ms: ms });
+        };
+        record(1, VerificationOutcome::Verified, 10);
+        record(2, VerificationOutcome::Verified, 15);
+        record(3, VerificationOutcome::Failed, 40);
+        record(4, VerificationOutcome::Skipped, 0);
+        // Result for a transaction in another block is ignored
+        record(99, VerificationOutcome::Failed, 500);
+
+        let summary = block_summary(&block.header, vec![1, 2, 3, 4, 5], &results);
+        assert_eq!(summary.block_number, 7);
+        assert_eq!(summary.state_root, hex::encode([7u8; 32]));
+        assert_eq!(summary.transaction_count, 5);
+        assert_eq!(summary.verified, 2);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.pending, 1);
+        assert_eq!(summary.total_verification_time_ms, 65);
+        assert!(summary.has_failures);
+
+        // Without failures the block is not flagged
+        let summary = block_summary(&block.header, vec![1, 2, 4], &results);
+        assert!(!summary.has_failures);
+    }
+
+    #[test]
+    fn test_operation_log_includes_update_images() {
+        let row = |balance: i64| {
+            let mut values = HashMap::new();
+            values.insert("id".to_string(), Value::Integer(1));
+            values.insert("balance".to_string(), Value::BigInt(balance));
+            Row::new("1".to_string(), "accounts".to_string(), values)
+        };
+        let update = Operation::new(
+            OperationType::Update,
+            "UPDATE accounts SET balance = balance - 50 WHERE id = 1".to_string(),
+            None,
+            vec!["accounts".to_string()],
+            Some(vec![row(100)]),
+            Some(vec![row(50)]),
+            3,
+        );
+        let now = Utc::now();
+        let record = TransactionRecord::new(
+            42, 7, TransactionType::ReadWrite, now, now, vec![update],
+            [1; 32], [2; 32], HashMap::new(), 0, 0, None, None,
+        );
+
+        let mut block = block_with_tables(7, &[("accounts", [2; 32])]);
+        block.transactions.insert(Default::default(), record);
+
+        let found = find_transaction([&block], 42).unwrap();
+        let log = OperationLogResponse::from(found);
+        assert_eq!(log.block_number, 7);
+        assert_eq!(log.pre_state_root, hex::encode([1u8; 32]));
+        assert_eq!(log.operations.len(), 1);
+
+        let json = serde_json::to_value(&log).unwrap();
+        let operation = &json["operations"][0];
+        assert_eq!(operation["operation_type"], "Update");
+        assert_eq!(operation["rows_before"][0]["values"]["balance"], serde_json::json!({"BigInt": 100}));
+        assert_eq!(operation["rows_after"][0]["values"]["balance"], serde_json::json!({"BigInt": 50}));
+        assert!(find_transaction([&block], 43).is_none());
+
+        // Access requires the configured bearer token
+        let mut headers = HeaderMap::new();
+        assert_eq!(authorize_operation_log(Some("secret"), &headers).unwrap_err().0, StatusCode::UNAUTHORIZED);
+        headers.insert(header::AUTHORIZATION, "Bearer wrong".parse().unwrap());
+        assert_eq!(authorize_operation_log(Some("secret"), &headers).unwrap_err().0, StatusCode::FORBIDDEN);
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert!(authorize_operation_log(Some("secret"), &headers).is_ok());
+        assert_eq!(authorize_operation_log(None, &headers).unwrap_err().0, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_batch_check_reports_each_transaction() {
+        let now = Utc::now();
+        let transaction = |id: u64, pre: u8, post: u8| {
+            let insert = Operation::new(
+                OperationType::Insert,
+                format!("INSERT INTO users (id) VALUES ({})", id),
+                None,
+                vec!["users".to_string()],
+                None,
+                None,
+                1,
+            );
+            TransactionRecord::new(
+                id, id, TransactionType::ReadWrite, now, now, vec![insert],
+                [pre; 32], [post; 32], HashMap::new(), 0, 0, None, None,
+            )
+        };
+        let mut block1 = block_with_tables(1, &[("users", [1; 32])]);
+        block1.transactions.insert(Default::default(), transaction(1, 0, 1));
+        let mut block2 = block_with_tables(2, &[("users", [2; 32])]);
+        block2.transactions.insert(Default::default(), transaction(2, 1, 2));
+
+        let request = |id: u64, pre: u8, post: u8, operations: Vec<&str>| CheckTransactionRequest {
+            transaction_id: id,
+            pre_state_root: hex::encode([pre; 32]),
+            post_state_root: format!("0x{}", hex::encode([post; 32])),
+            operations: operations.into_iter().map(str::to_string).collect(),
+        };
+        let requests = vec![
+            request(1, 0, 1, vec!["INSERT INTO users (id) VALUES (1)"]),
+            request(2, 1, 9, vec![]),
+            request(3, 2, 3, vec![]),
+            request(2, 1, 2, vec!["DELETE FROM users"]),
+            request(2, 1, 2, vec![]),
+        ];
+
+        let results = check_batch([&block1, &block2], &requests);
+        let consistent: Vec<bool> = results.iter().map(|result| result.consistent).collect();
+        assert_eq!(consistent, vec![true, false, false, false, true]);
+        assert_eq!(results[1].reason.as_deref(), Some("Post-state root does not match the recorded transaction"));
+        assert_eq!(results[2].reason.as_deref(), Some("Transaction not found"));
+        assert_eq!(results[3].reason.as_deref(), Some("Operations do not match the recorded transaction"));
+    }
+}
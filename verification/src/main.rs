@@ -35,6 +35,13 @@ async fn main() {
     let app_state = Arc::new(AppState {
         db_state: RwLock::new(None),
         state_history: RwLock::new(HashMap::new()),
+        verification_results: RwLock::new(HashMap::new()),
+        // Operation logs carry row before-images, so they stay off unless a token is set
+        operation_log_token: std::env::var("OPERATION_LOG_TOKEN").ok().filter(|token| !token.is_empty()),
+        max_batch_size: std::env::var("CHECK_BATCH_MAX_SIZE")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(api::DEFAULT_MAX_BATCH_SIZE),
     });
 
     // Get API port from environment variable